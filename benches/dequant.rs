@@ -0,0 +1,35 @@
+//! Q4_K / Q6_K block dequantization throughput (`src/ops/quant/quant_k_handler.rs`), timed by
+//! [`dequantize_benchmark`] so the numbers match `bench_compare dequant`.
+//!
+//! ```text
+//! cargo bench --bench dequant
+//! cargo run --release --bin bench_compare -- dequant   # same kernels, MB/s of f32 output
+//! ```
+
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use inference_engine_rust::bench_metrics::dequantize_benchmark;
+use inference_engine_rust::core::tensor::TensorType;
+
+/// One 4096-wide row repeated 16 times: 64K weights, small enough to stay cache-resident.
+const ELEMENTS: usize = 4096 * 16;
+
+fn bench_dequant(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dequant");
+    // Report bytes of f32 output, matching `bench_compare dequant`.
+    group.throughput(Throughput::Bytes((ELEMENTS * 4) as u64));
+    for (name, dtype) in [("q4_k", TensorType::Q4K), ("q6_k", TensorType::Q6K)] {
+        group.bench_with_input(BenchmarkId::new(name, ELEMENTS), &ELEMENTS, |bench, _| {
+            // Only the decode loop is timed; the synthetic input is built outside it.
+            bench.iter_custom(|iters| {
+                let m = dequantize_benchmark(dtype, ELEMENTS, iters as usize).unwrap();
+                Duration::from_secs_f64(m.elapsed_ms / 1e3)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dequant);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+//! Specialized vs generic small-vector kernels (`src/ops/specialized.rs`).
+//!
+//! ```text
+//! cargo bench --bench kernels
+//! ```
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use inference_engine_rust::bench_metrics::synthetic_data;
+use inference_engine_rust::ops::specialized::{
+    HEAD_DIMS, HIDDEN_DIMS, axpy, axpy_generic, dot, dot_generic, sum_squares, sum_squares_generic,
+};
+
+fn bench_head_dim(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dot");
+    for n in HEAD_DIMS {
+        let (a, b) = (synthetic_data(n), synthetic_data(n + 1)[1..].to_vec());
+        group.bench_with_input(BenchmarkId::new("specialized", n), &n, |bench, _| {
+            bench.iter(|| dot(black_box(&a), black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("generic", n), &n, |bench, _| {
+            bench.iter(|| dot_generic(black_box(&a), black_box(&b)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("axpy");
+    for n in HEAD_DIMS {
+        let x = synthetic_data(n);
+        let mut y = vec![0.0f32; n];
+        group.bench_with_input(BenchmarkId::new("specialized", n), &n, |bench, _| {
+            bench.iter(|| axpy(black_box(0.5), black_box(&x), &mut y))
+        });
+        group.bench_with_input(BenchmarkId::new("generic", n), &n, |bench, _| {
+            bench.iter(|| axpy_generic(black_box(0.5), black_box(&x), &mut y))
+        });
+    }
+    group.finish();
+}
+
+fn bench_hidden_dim(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sum_squares");
+    for n in HIDDEN_DIMS {
+        let x = synthetic_data(n);
+        group.bench_with_input(BenchmarkId::new("specialized", n), &n, |bench, _| {
+            bench.iter(|| sum_squares(black_box(&x)))
+        });
+        group.bench_with_input(BenchmarkId::new("generic", n), &n, |bench, _| {
+            bench.iter(|| sum_squares_generic(black_box(&x)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_head_dim, bench_hidden_dim);
+criterion_main!(benches);
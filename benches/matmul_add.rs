@@ -0,0 +1,67 @@
+//! Fused `matmul_add` vs `matmul` followed by `residual_add` (`src/ops/matmul.rs`), for a
+//! 4096-wide decode-step projection back into the residual stream.
+//!
+//! ```text
+//! cargo bench --bench matmul_add
+//! ```
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use inference_engine_rust::bench_metrics::{synthetic_blocks, synthetic_data};
+use inference_engine_rust::core::tensor::{Tensor, TensorType};
+use inference_engine_rust::ops::matmul::{matmul, matmul_add};
+use inference_engine_rust::ops::quant::quant_k_handler::{Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE};
+use inference_engine_rust::ops::residual_add::residual_add;
+
+use common::f32_tensor;
+
+/// Hidden width of a 7B model; the weight is `[K, K]`.
+const K: usize = 4096;
+const BLOCK_ELEMENTS: usize = 256;
+
+/// A `[K, K]` weight of synthetic `dtype` blocks.
+fn blocks(dtype: TensorType, block_bytes: usize) -> Tensor {
+    let bytes = synthetic_blocks(dtype, K * K / BLOCK_ELEMENTS, block_bytes);
+    Tensor::from_bytes(dtype, bytes, vec![K, K]).unwrap()
+}
+
+fn bench_matmul_add(c: &mut Criterion) {
+    let weights = [
+        ("f32", f32_tensor(&synthetic_data(K * K), vec![K, K])),
+        ("q4_k", blocks(TensorType::Q4K, Q4K_BLOCK_SIZE)),
+        ("q6_k", blocks(TensorType::Q6K, Q6K_BLOCK_SIZE)),
+    ];
+    let input = f32_tensor(&synthetic_data(K), vec![1, K]);
+    let residual = synthetic_data(K + 1)[1..].to_vec();
+
+    let mut group = c.benchmark_group("matmul_add");
+    group.sample_size(20);
+    for (name, weight) in &weights {
+        let mut output = f32_tensor(&vec![0.0; K], vec![1, K]);
+        group.bench_with_input(BenchmarkId::new("fused", name), name, |bench, _| {
+            bench.iter(|| {
+                matmul_add(black_box(&input), weight, black_box(&residual), &mut output).unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("separate", name), name, |bench, _| {
+            bench.iter(|| {
+                let mut projected = f32_tensor(&vec![0.0; K], vec![1, K]);
+                matmul(black_box(&input), weight, &mut projected).unwrap();
+                let mut sum = vec![0.0f32; K];
+                residual_add(
+                    black_box(&residual),
+                    projected.as_f32_slice().unwrap(),
+                    &mut sum,
+                )
+                .unwrap();
+                sum
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_matmul_add);
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+//! Cost of entering a worker pool for one decode-shape matmul (`src/engine/thread_pool.rs`):
+//! building a fresh rayon pool per call (what every new session used to do), installing the
+//! process-wide [`shared_pool`], and running on the caller's (global) pool.
+//!
+//! ```text
+//! cargo bench --bench thread_pool
+//! ```
+//!
+//! `attention_heads` sizes `ATTENTION_PARALLEL_MIN_OPS` (`src/layers/attention.rs`): one decode
+//! query's heads (8 query / 2 KV heads of 64) over a growing cache, each head through
+//! [`attend_online`], run inline and split across the shared pool the way the layer splits them.
+//! Work is `q_dim * keys * 2` multiply-adds, so the 32K threshold sits at 32 cached keys.
+//!
+//! Measured on one core (x86_64, release), inline vs split: 8 keys 9.1 µs vs 25.8 µs, 32 keys
+//! 34.5 µs vs 48.2 µs, 128 keys 138 µs vs 150 µs, 512 keys 547 µs vs 563 µs. With one core the
+//! split never wins; it adds a fixed hand-off of 12-17 µs, so this machine cannot place the
+//! crossover. At the threshold an inline step is ~35 µs, so the split pays off once extra cores
+//! speed the heads up by more than ~1.4x, which two cores should already manage; 32K was left
+//! unchanged. Rerun on a multi-core target before changing it.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use inference_engine_rust::bench_metrics::synthetic_data;
+use inference_engine_rust::engine::thread_pool::{ThreadAffinity, shared_pool};
+use inference_engine_rust::layers::attention::{KVCache, attend_online};
+use inference_engine_rust::ops::matmul::matmul;
+use rayon::prelude::*;
+
+use common::f32_tensor;
+
+/// Hidden width of a 1B-class model; the weight is `[K, K]`.
+const K: usize = 2048;
+const THREADS: usize = 4;
+
+fn bench_thread_pool(c: &mut Criterion) {
+    let weight = f32_tensor(&synthetic_data(K * K), vec![K, K]);
+    let input = f32_tensor(&synthetic_data(K), vec![1, K]);
+    let mut output = f32_tensor(&vec![0.0; K], vec![1, K]);
+
+    let mut group = c.benchmark_group("thread_pool");
+    group.sample_size(20);
+    group.bench_function("fresh_pool_per_call", |bench| {
+        bench.iter(|| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(THREADS)
+                .build()
+                .unwrap();
+            pool.install(|| matmul(black_box(&input), &weight, &mut output))
+                .unwrap()
+        })
+    });
+    let shared = shared_pool(Some(THREADS), ThreadAffinity::None).unwrap();
+    group.bench_function("shared_pool", |bench| {
+        bench.iter(|| {
+            shared
+                .install(|| matmul(black_box(&input), &weight, &mut output))
+                .unwrap()
+        })
+    });
+    group.bench_function("global_pool", |bench| {
+        bench.iter(|| matmul(black_box(&input), &weight, &mut output).unwrap())
+    });
+    group.finish();
+}
+
+const HEADS: usize = 8;
+const KV_HEADS: usize = 2;
+const HEAD_DIM: usize = 64;
+
+/// One decode query's attention over the first `keys` rows of `cache`, head by head.
+fn attend_heads(cache: &KVCache, q: &[f32], keys: usize, out: &mut [f32], split: bool) {
+    let scale = 1.0 / (HEAD_DIM as f32).sqrt();
+    let per_head = |(head, out): (usize, &mut [f32])| {
+        let kv_head = head / (HEADS / KV_HEADS);
+        let q = &q[head * HEAD_DIM..(head + 1) * HEAD_DIM];
+        out.fill(0.0);
+        attend_online(
+            0..keys,
+            None,
+            out,
+            |j| Ok(cache.k_row(j, kv_head)?.dot(q) * scale),
+            |j| Ok(cache.v_row(j, kv_head)?),
+        )
+    };
+    if split {
+        out.par_chunks_mut(HEAD_DIM)
+            .enumerate()
+            .try_for_each(per_head)
+            .unwrap();
+    } else {
+        out.chunks_mut(HEAD_DIM)
+            .enumerate()
+            .try_for_each(per_head)
+            .unwrap();
+    }
+}
+
+fn bench_attention_heads(c: &mut Criterion) {
+    let q = synthetic_data(HEADS * HEAD_DIM);
+    let mut out = vec![0.0f32; HEADS * HEAD_DIM];
+    let shared = shared_pool(Some(THREADS), ThreadAffinity::None).unwrap();
+
+    let mut group = c.benchmark_group("attention_heads");
+    group.sample_size(20);
+    for keys in [8, 32, 128, 512] {
+        let mut cache = KVCache::new(keys, KV_HEADS, HEAD_DIM);
+        let row = synthetic_data(KV_HEADS * HEAD_DIM);
+        for _ in 0..keys {
+            cache.append_kv(&row, &row).unwrap();
+        }
+        group.bench_with_input(BenchmarkId::new("inline", keys), &keys, |bench, &keys| {
+            bench.iter(|| attend_heads(&cache, black_box(&q), keys, &mut out, false))
+        });
+        group.bench_with_input(BenchmarkId::new("split", keys), &keys, |bench, &keys| {
+            bench.iter(|| {
+                shared.install(|| attend_heads(&cache, black_box(&q), keys, &mut out, true))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_thread_pool, bench_attention_heads);
+criterion_main!(benches);
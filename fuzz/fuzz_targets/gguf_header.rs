@@ -0,0 +1,14 @@
+//! GGUF header + KV metadata + tensor table parsing on arbitrary bytes: any input must give `Ok`
+//! or an error, never a panic, an abort on allocation, or a stack overflow.
+//!
+//! `cargo +nightly fuzz run gguf_header`. Minimized crashers go in `tests/data/gguf_crashers/`,
+//! which `tests/gguf_limits.rs` replays on every `cargo test`.
+
+#![no_main]
+
+use inference_engine_rust::model_loader::file_loader::parse_header_bytes;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_header_bytes(data);
+});
@@ -0,0 +1,10 @@
+//! `ops::math` from the main crate, compiled without `std`: `cargo build` proves the kernels'
+//! core only needs `core` and `alloc`, and `cargo test` runs its unit tests on the same code
+//! paths (the test harness itself needs `std`, so tests link it).
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[path = "../../src/ops/math/mod.rs"]
+pub mod math;
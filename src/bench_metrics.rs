@@ -10,18 +10,24 @@
 //! - **Decode throughput** — after a warm prefill, times only the greedy decode loop (`n` new tokens).
 
 use std::path::Path;
-use std::time::Instant;
 
 use serde::Serialize;
 
 use crate::EngineError;
+use crate::core::tensor::TensorType;
+use crate::core::time::Instant;
 use crate::engine::embed::prefill_from_tokens_loaded;
 use crate::engine::session::InferenceSession;
 use crate::layers::attention::kv_caches_for_config;
 use crate::loaded_model::LoadedModel;
 use crate::model_config::{ModelConfig, TokenizerPromptConfig};
 use crate::model_loader::file_loader::read_file;
+use crate::model_loader::gguf_types::LoadOptions;
 use crate::model_weights::ModelWeightNames;
+use crate::ops::quant::quant_k_handler::{
+    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, dequantize_q4k_block,
+    dequantize_q6k_block, dequantize_q8_0_block,
+};
 use crate::tokenizer::Tokenizer;
 
 /// Default prompt (matches `tests/generate_smoke.rs`).
@@ -242,10 +248,19 @@ pub fn run_cold_start(
     let config_and_resolve_ms = ms(t0.elapsed());
 
     let t0 = Instant::now();
-    names.load_all(&mut gguf, model_path.as_str())?;
+    let load_options = LoadOptions::default();
+    let load_stats = names.load_all_with(&mut gguf, model_path.as_str(), &load_options)?;
     let tensor_load_ms = ms(t0.elapsed());
 
-    let model = LoadedModel::from_loaded_parts(model_path, gguf, config, names, tok_prompt);
+    let model = LoadedModel::from_loaded_parts(
+        model_path,
+        gguf,
+        config,
+        names,
+        tok_prompt,
+        load_options,
+        load_stats,
+    );
 
     let t0 = Instant::now();
     let prefill_in = prefill_from_tokens_loaded(model.gguf(), model.config(), &prompt_ids)?;
@@ -311,6 +326,116 @@ pub fn run_all(
     })
 }
 
+/// Weights in one 4096x4096 projection (a 7B-class `attn_q.weight`).
+pub const DEFAULT_DEQUANT_BENCH_ELEMENTS: usize = 4096 * 4096;
+
+/// Weights per Q4_K / Q6_K superblock.
+const K_BLOCK_ELEMENTS: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DequantThroughputMetrics {
+    /// `Q4K`, `Q6K` or `Q8_0`.
+    pub dtype: String,
+    /// Elements dequantized per iteration (rounded up to whole blocks).
+    pub elements: usize,
+    pub iterations: usize,
+    /// Packed input bytes read per iteration.
+    pub input_bytes: usize,
+    /// Wall time for all iterations (buffers allocated and filled beforehand).
+    pub elapsed_ms: f64,
+    pub elements_per_sec: f64,
+    /// f32 output produced, in MB (1e6 bytes) per second.
+    pub output_mb_per_sec: f64,
+}
+
+/// Time block dequantization of a synthetic `dtype` tensor with `elements` weights, `iterations`
+/// times into one reused f32 buffer. Quant bytes are pseudo-random with fixed, finite fp16 scales,
+/// so every block takes the same path as real weights (no zero / NaN shortcuts).
+pub fn dequantize_benchmark(
+    dtype: TensorType,
+    elements: usize,
+    iterations: usize,
+) -> Result<DequantThroughputMetrics, EngineError> {
+    type BlockDecoder = fn(&[u8], &mut [f32]) -> Result<(), EngineError>;
+    let (block_elems, block_bytes, decode): (usize, usize, BlockDecoder) = match dtype {
+        TensorType::Q4K => (K_BLOCK_ELEMENTS, Q4K_BLOCK_SIZE, dequantize_q4k_block),
+        TensorType::Q6K => (K_BLOCK_ELEMENTS, Q6K_BLOCK_SIZE, dequantize_q6k_block),
+        TensorType::Q8_0 => (Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, dequantize_q8_0_block),
+        TensorType::F32 => {
+            return Err(EngineError::Model(
+                "dequantize_benchmark: F32 has nothing to dequantize".into(),
+            ));
+        }
+    };
+    if elements == 0 || iterations == 0 {
+        return Err(EngineError::Model(
+            "dequantize_benchmark: elements and iterations must be non-zero".into(),
+        ));
+    }
+    let n_blocks = elements.div_ceil(block_elems);
+    let input = synthetic_blocks(dtype, n_blocks, block_bytes);
+    let mut out = vec![0.0f32; n_blocks * block_elems];
+
+    let t0 = Instant::now();
+    for _ in 0..iterations {
+        for (block, dst) in input
+            .chunks_exact(block_bytes)
+            .zip(out.chunks_exact_mut(block_elems))
+        {
+            decode(std::hint::black_box(block), dst)?;
+        }
+        std::hint::black_box(&mut out);
+    }
+    let secs = t0.elapsed().as_secs_f64().max(f64::MIN_POSITIVE);
+
+    let total = (out.len() * iterations) as f64;
+    Ok(DequantThroughputMetrics {
+        dtype: format!("{dtype:?}"),
+        elements: out.len(),
+        iterations,
+        input_bytes: input.len(),
+        elapsed_ms: secs * 1e3,
+        elements_per_sec: total / secs,
+        output_mb_per_sec: total * std::mem::size_of::<f32>() as f64 / secs / 1e6,
+    })
+}
+
+/// `n_blocks` packed blocks of LCG bytes with the fp16 super-scales overwritten by small values,
+/// for benchmarks that need realistic quantized weights (see [`dequantize_benchmark`]).
+pub fn synthetic_blocks(dtype: TensorType, n_blocks: usize, block_bytes: usize) -> Vec<u8> {
+    const D: [u8; 2] = 0x2000u16.to_le_bytes(); // 2^-7
+    const DMIN: [u8; 2] = 0x1c00u16.to_le_bytes(); // 2^-8
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut bytes: Vec<u8> = (0..n_blocks * block_bytes)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect();
+    for block in bytes.chunks_exact_mut(block_bytes) {
+        match dtype {
+            TensorType::Q4K => {
+                block[0..2].copy_from_slice(&D);
+                block[2..4].copy_from_slice(&DMIN);
+            }
+            TensorType::Q6K => block[208..210].copy_from_slice(&D),
+            TensorType::Q8_0 => block[0..2].copy_from_slice(&D),
+            TensorType::F32 => {}
+        }
+    }
+    bytes
+}
+
+/// `len` values in `[-1, 1]` in a fixed, non-monotonic order, for benchmark activations and F32
+/// weights.
+pub fn synthetic_data(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| ((i * 37 % 101) as f32 - 50.0) / 50.0)
+        .collect()
+}
+
 /// Reference timings from `llama-completion --perf` using **CPU-fair** defaults:
 /// no layer offload (`-ngl 0`), no device offload (`--device none`), no sneaking matmuls to the GPU
 /// (`--no-op-offload`), single thread (`-t 1`), no empty warmup (`--no-warmup`), one generated token (`-n 1`).
@@ -439,3 +564,33 @@ common_perf_print: prompt eval time =    1609.65 ms /     6 tokens (  268.28 ms
         assert_eq!(p.prompt_tokens, 6);
     }
 }
+
+#[cfg(test)]
+mod dequant_bench_tests {
+    use super::*;
+
+    #[test]
+    fn reports_positive_rates_for_whole_blocks() {
+        for dtype in [TensorType::Q4K, TensorType::Q6K, TensorType::Q8_0] {
+            let m = dequantize_benchmark(dtype, 1000, 2).unwrap();
+            assert_eq!(m.elements % 32, 0, "{dtype:?}");
+            assert!(m.elements >= 1000);
+            assert!(m.elements_per_sec.is_finite() && m.elements_per_sec > 0.0);
+            let mb = m.elements_per_sec * 4.0 / 1e6;
+            assert!((m.output_mb_per_sec - mb).abs() <= 1e-9 * mb);
+        }
+        assert!(dequantize_benchmark(TensorType::F32, 1000, 1).is_err());
+        assert!(dequantize_benchmark(TensorType::Q4K, 0, 1).is_err());
+    }
+
+    #[test]
+    fn synthetic_blocks_dequantize_to_finite_values() {
+        let bytes = synthetic_blocks(TensorType::Q6K, 2, Q6K_BLOCK_SIZE);
+        let mut out = [0.0f32; 256];
+        for block in bytes.chunks_exact(Q6K_BLOCK_SIZE) {
+            dequantize_q6k_block(block, &mut out).unwrap();
+            assert!(out.iter().all(|v| v.is_finite()));
+            assert!(out.iter().any(|&v| v != 0.0));
+        }
+    }
+}
@@ -7,6 +7,7 @@
 //! cargo run --release --bin bench_compare -- decode-throughput -n 128
 //! cargo run --release --bin bench_compare -- all --json
 //! cargo run --release --bin bench_compare -- interactive-ttft --compare-llama
+//! cargo run --release --bin bench_compare -- compare --model-b model/.../Q5_K_M.gguf --prompts prompts.txt
 //! ```
 //!
 //! Map results to llama-bench: **pp** = prompt tokens / prefill wall time; **tg** = decode tok/s.
@@ -17,10 +18,16 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use inference_engine_rust::EngineError;
 use inference_engine_rust::bench_metrics::{
-    ColdStartMetrics, DEFAULT_BENCH_PROMPT, DecodeThroughputMetrics, EngineBench,
-    InteractiveTtftMetrics, LlamaCompletionTtftRef, run_all, run_cold_start,
+    ColdStartMetrics, DEFAULT_BENCH_PROMPT, DEFAULT_DEQUANT_BENCH_ELEMENTS,
+    DecodeThroughputMetrics, DequantThroughputMetrics, EngineBench, InteractiveTtftMetrics,
+    LlamaCompletionTtftRef, dequantize_benchmark, run_all, run_cold_start,
     run_llama_completion_ttft_ref,
 };
+use inference_engine_rust::compare::compare_model_files;
+use inference_engine_rust::core::tensor::TensorType;
+use inference_engine_rust::model_config::TokenizerPromptConfig;
+use inference_engine_rust::model_loader::file_loader::read_file;
+use inference_engine_rust::tokenizer::Tokenizer;
 
 #[derive(Parser, Debug)]
 #[command(name = "bench_compare")]
@@ -84,6 +91,29 @@ enum Commands {
         #[arg(short = 'n', long, default_value_t = 128)]
         decode_tokens: usize,
     },
+    /// Load `--model` and `--model-b` side by side; report greedy agreement, first-token KL,
+    /// perplexity, speed and memory (see `inference_engine_rust::compare`)
+    Compare {
+        /// Second GGUF (same vocabulary as `--model`)
+        #[arg(long)]
+        model_b: PathBuf,
+        /// One prompt per non-empty line; defaults to `--prompt`
+        #[arg(long)]
+        prompts: Option<PathBuf>,
+        /// Greedy steps per prompt
+        #[arg(short = 'n', long, default_value_t = 32)]
+        new_tokens: usize,
+    },
+    /// Q4_K and Q6_K block dequantization throughput on a synthetic tensor (no model needed;
+    /// `--model` / `--tokenizer` are ignored)
+    Dequant {
+        /// Weights per tensor (default: one 4096x4096 projection)
+        #[arg(short = 'n', long, default_value_t = DEFAULT_DEQUANT_BENCH_ELEMENTS)]
+        elements: usize,
+        /// Passes over the tensor per run
+        #[arg(long, default_value_t = 4)]
+        iterations: usize,
+    },
 }
 
 fn finite_pos_ms(x: f64, name: &str) -> Result<(), EngineError> {
@@ -216,6 +246,31 @@ fn print_decode_human(m: &DecodeThroughputMetrics) {
     println!("  prefill_tokens_per_s: {pps:.3}  (rough pp analog)");
 }
 
+fn print_dequant_human(runs: &[DequantThroughputMetrics]) {
+    let mut eps: Vec<f64> = runs.iter().map(|m| m.elements_per_sec).collect();
+    let mut mbps: Vec<f64> = runs.iter().map(|m| m.output_mb_per_sec).collect();
+    println!(
+        "  {:<5} median {:>8.1} Melem/s  {:>9.1} MB/s f32 out  ({} runs)",
+        runs[0].dtype,
+        median(&mut eps) / 1e6,
+        median(&mut mbps),
+        runs.len()
+    );
+}
+
+fn summarize_dequant(runs: &[DequantThroughputMetrics]) -> serde_json::Value {
+    let mut eps: Vec<f64> = runs.iter().map(|m| m.elements_per_sec).collect();
+    let mut mbps: Vec<f64> = runs.iter().map(|m| m.output_mb_per_sec).collect();
+    serde_json::json!({
+        "elements_per_sec": {
+            "median": median(&mut eps),
+            "min": eps.iter().copied().fold(f64::INFINITY, f64::min),
+            "max": eps.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        },
+        "output_mb_per_sec": { "median": median(&mut mbps) },
+    })
+}
+
 fn median(values: &mut [f64]) -> f64 {
     values.sort_by(|a, b| a.total_cmp(b));
     let len = values.len();
@@ -298,6 +353,38 @@ fn main() -> Result<(), EngineError> {
     }
 
     match cli.command {
+        Commands::Compare {
+            model_b,
+            prompts,
+            new_tokens,
+        } => {
+            let texts = match prompts {
+                Some(path) => std::fs::read_to_string(&path)?
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                None => vec![cli.prompt.clone()],
+            };
+            let mut tokenizer = Tokenizer::load_from_file(&cli.tokenizer)?;
+            let model_a = cli
+                .model
+                .to_str()
+                .ok_or_else(|| EngineError::Model("model path is not valid UTF-8".into()))?;
+            // Metadata only: BOS/EOS handling for encoding; tensors load in `compare_model_files`.
+            let prompt_cfg = TokenizerPromptConfig::from_gguf(&read_file(model_a)?)?;
+            let prompt_ids = texts
+                .iter()
+                .map(|t| tokenizer.encode_with_prompt_config(t, &prompt_cfg))
+                .collect::<Result<Vec<_>, _>>()?;
+            let report = compare_model_files(&cli.model, &model_b, &prompt_ids, new_tokens)?;
+            if cli.json {
+                println!("{}", report.to_json()?);
+            } else {
+                println!("=== compare ({} prompts) ===", prompt_ids.len());
+                println!("{report}");
+            }
+        }
         Commands::All { decode_tokens } => {
             let mut runs = Vec::with_capacity(cli.runs);
             for _ in 0..cli.runs {
@@ -463,6 +550,42 @@ fn main() -> Result<(), EngineError> {
                 println!("  decode: {}", summary);
             }
         }
+        Commands::Dequant {
+            elements,
+            iterations,
+        } => {
+            let mut suites = Vec::new();
+            for dtype in [TensorType::Q4K, TensorType::Q6K] {
+                let mut runs = Vec::with_capacity(cli.runs);
+                for _ in 0..cli.runs {
+                    let m = dequantize_benchmark(dtype, elements, iterations)?;
+                    finite_pos_ms(m.elapsed_ms, "dequant.elapsed_ms")?;
+                    runs.push(m);
+                }
+                suites.push(runs);
+            }
+            if cli.json {
+                let per_dtype: Vec<_> = suites
+                    .iter()
+                    .map(|runs| {
+                        serde_json::json!({
+                            "dtype": runs[0].dtype,
+                            "runs": runs,
+                            "summary": summarize_dequant(runs),
+                        })
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    serde_json::json!({ "suite": "dequant", "results": per_dtype })
+                );
+            } else {
+                println!("=== dequant ({elements} elements x {iterations} iterations) ===");
+                for runs in &suites {
+                    print_dequant_human(runs);
+                }
+            }
+        }
     }
 
     Ok(())
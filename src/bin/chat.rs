@@ -16,6 +16,7 @@
 
 use std::io::{BufRead, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 use clap::Parser;
 use inference_engine_rust::EngineError;
@@ -23,9 +24,11 @@ use inference_engine_rust::chat_prompt::{
     ChatMessage, ChatPromptStyle, gemma4_e2b_assistant_visible,
     gemma4_e2b_decode_has_structure_marker,
 };
-use inference_engine_rust::engine::generation::greedy_next_token;
+use inference_engine_rust::engine::generation::{StopTokens, greedy_next_token};
 use inference_engine_rust::engine::session::InferenceSession;
 use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::logging::{self, Verbosity};
+use inference_engine_rust::model_config::vocab_token_id;
 use inference_engine_rust::tokenizer::Tokenizer;
 
 #[derive(Parser, Debug)]
@@ -46,19 +49,37 @@ struct Args {
     #[arg(long, default_value_t = 256)]
     max_reply_tokens: usize,
 
-    /// Stop if model emits this token id (overrides GGUF eos when set)
+    /// Stop if model emits this token id (overrides the GGUF terminators and turn end when set)
     #[arg(long)]
     stop_token: Option<u32>,
 
     /// Print the assistant reply only after the full decode (no token-by-token streaming)
     #[arg(long)]
     no_stream: bool,
+
+    /// Log errors only (overrides RUST_LOG and --verbose)
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Log load milestones (-v) or per-tensor progress too (-vv); overrides RUST_LOG
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
-fn main() -> Result<(), EngineError> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+/// Errors print as one `error: ...` line (their `Display`), not as a `Debug` dump.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
+fn run() -> Result<(), EngineError> {
     let args = Args::parse();
+    logging::init(Verbosity::from_flags(args.quiet, args.verbose));
     let style = ChatPromptStyle::parse(&args.style).ok_or_else(|| {
         EngineError::Model(format!(
             "unknown --style {:?}: use gemma4-e2b | mistral-instruct",
@@ -82,11 +103,21 @@ fn main() -> Result<(), EngineError> {
     let mut tokenizer = Tokenizer::load_from_file(&args.tokenizer)?;
     let tok_prompt = model.tokenizer_prompt();
 
-    let stop_id = args.stop_token.unwrap_or(tok_prompt.eos_token_id);
+    let stops = match args.stop_token {
+        Some(id) => StopTokens::new([id]),
+        None => StopTokens::new(
+            tok_prompt.terminator_ids().chain(
+                style
+                    .turn_end_piece()
+                    .and_then(|piece| vocab_token_id(model.gguf(), piece)),
+            ),
+        ),
+    };
 
     eprintln!(
-        "Chat ({:?}). Commands: /quit /exit. EOS stop id: {}",
-        style, stop_id
+        "Chat ({:?}). Commands: /quit /exit. Stop ids: {:?}",
+        style,
+        stops.ids()
     );
     eprintln!("— — —");
 
@@ -104,7 +135,7 @@ fn main() -> Result<(), EngineError> {
             break;
         }
         let line = line.trim_end_matches(['\n', '\r']);
-        if line.is_empty() {
+        if line.trim().is_empty() {
             continue;
         }
         if line == "/quit" || line == "/exit" {
@@ -118,7 +149,7 @@ fn main() -> Result<(), EngineError> {
             .render_conversation(&history)
             .map_err(|e| EngineError::Model(e.to_string()))?;
 
-        let prompt_ids = tokenizer.encode_with_prompt_config(&prompt_text, &tok_prompt)?;
+        let prompt_ids = tokenizer.encode_with_prompt_config(&prompt_text, tok_prompt)?;
 
         eprintln!(
             "… running (prefill {} prompt tokens, up to {} new); CPU can take a while …",
@@ -141,7 +172,7 @@ fn main() -> Result<(), EngineError> {
 
         for _ in 0..args.max_reply_tokens {
             let next_id = greedy_next_token(&session, &state)?;
-            if next_id == stop_id {
+            if stops.contains(next_id) {
                 break;
             }
             generated.push(next_id);
@@ -143,6 +143,16 @@ pub enum ChatPromptStyle {
 }
 
 impl ChatPromptStyle {
+    /// Vocabulary piece that closes an assistant turn, for styles whose turn end is not
+    /// (only) the model's EOS.
+    pub fn turn_end_piece(self) -> Option<&'static str> {
+        match self {
+            Self::Raw => None,
+            Self::MistralInstruct => Some("</s>"),
+            Self::Gemma4E2b => Some("<turn|>"),
+        }
+    }
+
     pub fn parse(s: &str) -> Option<Self> {
         match s.trim().to_ascii_lowercase().as_str() {
             "" | "raw" | "none" => Some(Self::Raw),
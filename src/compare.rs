@@ -0,0 +1,452 @@
+//! Side-by-side evaluation of two GGUF files (e.g. Q4_K_M vs Q5_K_M of one model).
+//!
+//! Both models stay resident for the whole run, so with `mem-profile` the reported load deltas
+//! show what each quantization costs while the other is also in memory.
+//!
+//! Per prompt, each model prefills the prompt (perplexity over its own next-token predictions),
+//! then both decode greedily in lockstep. Model A's token is fed to **both** sessions, so every
+//! step compares the two argmaxes on the same history instead of letting one early divergence
+//! poison the rest of the continuation.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::EngineError;
+use crate::core::time::Instant;
+use crate::engine::sampling::{log_sum_exp, sample_greedy};
+use crate::engine::session::InferenceSession;
+use crate::loaded_model::LoadedModel;
+use crate::mem_profile::memory_stats;
+use crate::model_loader::gguf_types::LoadOptions;
+
+/// Speed, memory and perplexity of one side of a comparison.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelRunReport {
+    pub model_path: String,
+    pub load_ms: f64,
+    /// Live heap growth while loading; `None` without the `mem-profile` feature.
+    pub load_bytes: Option<usize>,
+    pub prefill_tokens_per_sec: f64,
+    pub decode_tokens_per_sec: f64,
+    /// `exp(mean NLL)` of each prompt token given its prefix; `None` if no prompt has 2+ tokens.
+    pub perplexity: Option<f64>,
+}
+
+/// Agreement between the two models on one prompt.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptComparison {
+    pub prompt_tokens: usize,
+    /// Steps where both greedy choices matched, out of `steps`.
+    pub agreed_steps: usize,
+    pub steps: usize,
+    /// `KL(P_a || P_b)` of the next-token distributions right after the prompt, in nats.
+    pub first_token_kl: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonReport {
+    pub a: ModelRunReport,
+    pub b: ModelRunReport,
+    pub prompts: Vec<PromptComparison>,
+    /// Matched greedy steps over all prompts / total steps (1.0 when nothing was decoded).
+    pub token_agreement: f64,
+    pub mean_first_token_kl: f64,
+    /// `b.perplexity - a.perplexity`, when both are defined.
+    pub perplexity_delta: Option<f64>,
+}
+
+impl ComparisonReport {
+    pub fn to_json(&self) -> Result<String, EngineError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| EngineError::Model(format!("comparison report to JSON: {e}")))
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Display>(v: Option<T>) -> String {
+            v.map_or_else(|| "-".to_string(), |v| v.to_string())
+        }
+        let ppl = |r: &ModelRunReport| opt(r.perplexity.map(|p| format!("{p:.4}")));
+        let mib =
+            |r: &ModelRunReport| opt(r.load_bytes.map(|b| format!("{:.1}", b as f64 / 1048576.0)));
+        writeln!(f, "{:<22} {:>14} {:>14}", "", "A", "B")?;
+        writeln!(
+            f,
+            "{:<22} {:>14.1} {:>14.1}",
+            "load_ms", self.a.load_ms, self.b.load_ms
+        )?;
+        writeln!(
+            f,
+            "{:<22} {:>14} {:>14}",
+            "load_mib",
+            mib(&self.a),
+            mib(&self.b)
+        )?;
+        writeln!(
+            f,
+            "{:<22} {:>14.2} {:>14.2}",
+            "prefill_tok_per_s", self.a.prefill_tokens_per_sec, self.b.prefill_tokens_per_sec
+        )?;
+        writeln!(
+            f,
+            "{:<22} {:>14.2} {:>14.2}",
+            "decode_tok_per_s", self.a.decode_tokens_per_sec, self.b.decode_tokens_per_sec
+        )?;
+        writeln!(
+            f,
+            "{:<22} {:>14} {:>14}",
+            "perplexity",
+            ppl(&self.a),
+            ppl(&self.b)
+        )?;
+        writeln!(f)?;
+        writeln!(f, "A: {}", self.a.model_path)?;
+        writeln!(f, "B: {}", self.b.model_path)?;
+        writeln!(f, "token_agreement:     {:.4}", self.token_agreement)?;
+        writeln!(f, "mean_first_token_kl: {:.6}", self.mean_first_token_kl)?;
+        write!(
+            f,
+            "perplexity_delta:    {}",
+            opt(self.perplexity_delta.map(|d| format!("{d:+.4}")))
+        )
+    }
+}
+
+/// `KL(softmax(p) || softmax(q))` in nats, e.g. for the quantized model's next-token
+/// distribution `q` against an F32 reference `p`. Computed in f64 from log-probabilities. Tokens
+/// `p` gives zero probability (`-inf` logits, e.g. masked ones) contribute nothing; one `q` rules
+/// out while `p` does not makes the divergence infinite. A NaN logit gives NaN.
+pub fn kl_divergence(p: &[f32], q: &[f32]) -> Result<f32, EngineError> {
+    if p.len() != q.len() || p.is_empty() {
+        return Err(EngineError::Model(format!(
+            "kl_divergence: vocab sizes {} and {} must match and be non-empty",
+            p.len(),
+            q.len()
+        )));
+    }
+    let (zp, zq) = (log_sum_exp(p), log_sum_exp(q));
+    let kl: f64 = p
+        .iter()
+        .zip(q)
+        .map(|(&a, &b)| (a as f64 - zp, b as f64 - zq))
+        .filter(|&(a, _)| a != f64::NEG_INFINITY)
+        .map(|(a, b)| a.exp() * (a - b))
+        .sum();
+    // Rounding can leave identical distributions a hair below zero; `max` would also hide NaN.
+    Ok(if kl < 0.0 { 0.0 } else { kl as f32 })
+}
+
+/// Load both files and run [`compare_models`]; load time and memory are measured here.
+pub fn compare_model_files(
+    path_a: impl AsRef<Path>,
+    path_b: impl AsRef<Path>,
+    prompts: &[Vec<u32>],
+    new_tokens: usize,
+) -> Result<ComparisonReport, EngineError> {
+    let (model_a, load_a) = timed_load(path_a.as_ref())?;
+    let (model_b, load_b) = timed_load(path_b.as_ref())?;
+    let mut report = compare_models(&model_a, &model_b, prompts, new_tokens)?;
+    (report.a.load_ms, report.a.load_bytes) = load_a;
+    (report.b.load_ms, report.b.load_bytes) = load_b;
+    Ok(report)
+}
+
+fn timed_load(path: &Path) -> Result<(LoadedModel, (f64, Option<usize>)), EngineError> {
+    let before = memory_stats();
+    let t = Instant::now();
+    let model = LoadedModel::load(path)?;
+    let load_ms = t.elapsed().as_secs_f64() * 1e3;
+    let bytes = before
+        .zip(memory_stats())
+        .map(|(b, a)| a.current_bytes.saturating_sub(b.current_bytes));
+    Ok((model, (load_ms, bytes)))
+}
+
+/// Compare two already-loaded models on token-id `prompts`, decoding `new_tokens` per prompt.
+///
+/// The models must share a vocabulary; `load_ms`/`load_bytes` are left at zero/`None`.
+pub fn compare_models(
+    a: &LoadedModel,
+    b: &LoadedModel,
+    prompts: &[Vec<u32>],
+    new_tokens: usize,
+) -> Result<ComparisonReport, EngineError> {
+    if a.config().vocab_size != b.config().vocab_size {
+        return Err(EngineError::Model(format!(
+            "compare_models: vocab sizes differ ({} vs {})",
+            a.config().vocab_size,
+            b.config().vocab_size
+        )));
+    }
+    if prompts.is_empty() || prompts.iter().any(|p| p.is_empty()) {
+        return Err(EngineError::Model(
+            "compare_models: need at least one non-empty prompt".into(),
+        ));
+    }
+
+    let mut sa = InferenceSession::new(a)?;
+    let mut sb = InferenceSession::new(b)?;
+    let (mut acc_a, mut acc_b) = (SideTotals::default(), SideTotals::default());
+    let mut results = Vec::with_capacity(prompts.len());
+
+    for prompt in prompts {
+        sa.reset();
+        sb.reset();
+        let mut la = acc_a.prefill(&mut sa, prompt)?;
+        let mut lb = acc_b.prefill(&mut sb, prompt)?;
+        let first_token_kl = f64::from(kl_divergence(&la, &lb)?);
+
+        let mut agreed_steps = 0;
+        for step in 0..new_tokens {
+            let ta = sample_greedy(&la)?;
+            let tb = sample_greedy(&lb)?;
+            agreed_steps += usize::from(ta == tb);
+            if step + 1 == new_tokens {
+                break;
+            }
+            la = acc_a.decode(&mut sa, ta)?;
+            lb = acc_b.decode(&mut sb, ta)?;
+        }
+        results.push(PromptComparison {
+            prompt_tokens: prompt.len(),
+            agreed_steps,
+            steps: new_tokens,
+            first_token_kl,
+        });
+    }
+
+    let steps: usize = results.iter().map(|r| r.steps).sum();
+    let agreed: usize = results.iter().map(|r| r.agreed_steps).sum();
+    let token_agreement = if steps == 0 {
+        1.0
+    } else {
+        agreed as f64 / steps as f64
+    };
+    let mean_first_token_kl =
+        results.iter().map(|r| r.first_token_kl).sum::<f64>() / results.len() as f64;
+    let (a, b) = (acc_a.report(a), acc_b.report(b));
+    let perplexity_delta = a.perplexity.zip(b.perplexity).map(|(pa, pb)| pb - pa);
+    Ok(ComparisonReport {
+        a,
+        b,
+        prompts: results,
+        token_agreement,
+        mean_first_token_kl,
+        perplexity_delta,
+    })
+}
+
+/// Logits of one model with its weights as stored (fused quantized matmuls) against the same
+/// model with every weight dequantized to F32 ([`LoadOptions::force_f32_weights`]), over every
+/// prompt position. What remains is the error of the quantized kernels themselves (e.g. the
+/// activation quantization of the Q8 dot products), not of the stored weights.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrecisionReport {
+    pub model_path: String,
+    /// Prompt positions compared (one next-token distribution each).
+    pub positions: usize,
+    /// Weights the F32 load converted; 0 means the file had nothing to dequantize.
+    pub dequantized_tensors: usize,
+    pub max_abs_diff: f32,
+    pub mean_abs_diff: f64,
+    /// `KL(P_f32 || P_quantized)` in nats, averaged and worst over positions.
+    pub mean_kl: f64,
+    pub max_kl: f64,
+    /// Positions where both greedy choices match.
+    pub argmax_agreement: usize,
+}
+
+impl fmt::Display for PrecisionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.model_path)?;
+        writeln!(f, "dequantized tensors: {}", self.dequantized_tensors)?;
+        writeln!(f, "max_abs_diff:        {:.6}", self.max_abs_diff)?;
+        writeln!(f, "mean_abs_diff:       {:.6}", self.mean_abs_diff)?;
+        writeln!(f, "mean_kl:             {:.6}", self.mean_kl)?;
+        writeln!(f, "max_kl:              {:.6}", self.max_kl)?;
+        write!(
+            f,
+            "argmax_agreement:    {}/{}",
+            self.argmax_agreement, self.positions
+        )
+    }
+}
+
+/// Load `path` as stored and with [`LoadOptions::force_f32_weights`], then run
+/// [`compare_weight_precision_models`] on `prompt`.
+pub fn compare_weight_precision(
+    path: impl AsRef<Path>,
+    prompt: &[u32],
+) -> Result<PrecisionReport, EngineError> {
+    let path = path.as_ref();
+    let quantized = LoadedModel::load(path)?;
+    let reference = LoadedModel::load_with(
+        path,
+        &LoadOptions {
+            force_f32_weights: true,
+            ..LoadOptions::default()
+        },
+    )?;
+    compare_weight_precision_models(&quantized, &reference, prompt)
+}
+
+/// Prefill `prompt` on both models and compare the next-token logits at every position.
+/// `reference` is the F32 load; the tensors it converted
+/// ([`LoadStats::dtype_overrides`](crate::model_loader::gguf_types::LoadStats::dtype_overrides))
+/// are counted as [`PrecisionReport::dequantized_tensors`].
+pub fn compare_weight_precision_models(
+    quantized: &LoadedModel,
+    reference: &LoadedModel,
+    prompt: &[u32],
+) -> Result<PrecisionReport, EngineError> {
+    if prompt.is_empty() {
+        return Err(EngineError::Model(
+            "compare_weight_precision: need a non-empty prompt".into(),
+        ));
+    }
+    let mut sq = InferenceSession::new(quantized)?;
+    let mut sr = InferenceSession::new(reference)?;
+    let (state_q, state_r) = (sq.prefill(prompt)?, sr.prefill(prompt)?);
+
+    let mut max_abs_diff = 0.0f32;
+    let (mut abs_sum, mut compared) = (0.0f64, 0usize);
+    let (mut kl_sum, mut max_kl) = (0.0f64, 0.0f64);
+    let mut argmax_agreement = 0;
+    for pos in 0..prompt.len() {
+        let lq = sq.next_token_logits(&state_q.rows(pos, pos + 1)?)?;
+        let lr = sr.next_token_logits(&state_r.rows(pos, pos + 1)?)?;
+        for (q, r) in lq.iter().zip(&lr) {
+            let d = (q - r).abs();
+            max_abs_diff = max_abs_diff.max(d);
+            abs_sum += d as f64;
+        }
+        compared += lq.len();
+        let kl = f64::from(kl_divergence(&lr, &lq)?);
+        kl_sum += kl;
+        max_kl = max_kl.max(kl);
+        argmax_agreement += usize::from(sample_greedy(&lq)? == sample_greedy(&lr)?);
+    }
+    Ok(PrecisionReport {
+        model_path: quantized.model_path().to_string(),
+        positions: prompt.len(),
+        dequantized_tensors: reference.load_stats().dtype_overrides.len(),
+        max_abs_diff,
+        mean_abs_diff: abs_sum / compared.max(1) as f64,
+        mean_kl: kl_sum / prompt.len() as f64,
+        max_kl,
+        argmax_agreement,
+    })
+}
+
+/// Running totals for one model across all prompts.
+#[derive(Default)]
+struct SideTotals {
+    nll: f64,
+    scored_tokens: usize,
+    prefill_tokens: usize,
+    prefill_secs: f64,
+    decode_tokens: usize,
+    decode_secs: f64,
+}
+
+impl SideTotals {
+    /// Prefill `prompt`, score every prompt token after the first, return last-token logits.
+    fn prefill(
+        &mut self,
+        session: &mut InferenceSession<'_>,
+        prompt: &[u32],
+    ) -> Result<Vec<f32>, EngineError> {
+        let t = Instant::now();
+        let state = session.prefill(prompt)?;
+        let last = session.logits_last_token(&state)?;
+        self.prefill_secs += t.elapsed().as_secs_f64();
+        self.prefill_tokens += prompt.len();
+
+        for (pos, &target) in prompt.iter().enumerate().skip(1) {
+            let logits = session.logits_last_token(&state.rows(pos - 1, pos)?)?;
+            let target_logit = logits.get(target as usize).copied().ok_or_else(|| {
+                EngineError::Model(format!(
+                    "compare_models: prompt token {target} out of vocab"
+                ))
+            })?;
+            self.nll -= target_logit as f64 - log_sum_exp(&logits);
+            self.scored_tokens += 1;
+        }
+        Ok(last)
+    }
+
+    fn decode(
+        &mut self,
+        session: &mut InferenceSession<'_>,
+        token: u32,
+    ) -> Result<Vec<f32>, EngineError> {
+        let t = Instant::now();
+        let state = session.decode_token(token)?;
+        let logits = session.logits_last_token(&state)?;
+        self.decode_secs += t.elapsed().as_secs_f64();
+        self.decode_tokens += 1;
+        Ok(logits)
+    }
+
+    fn report(&self, model: &LoadedModel) -> ModelRunReport {
+        let rate = |n: usize, secs: f64| n as f64 / secs.max(f64::EPSILON);
+        ModelRunReport {
+            model_path: model.model_path().to_string(),
+            load_ms: 0.0,
+            load_bytes: None,
+            prefill_tokens_per_sec: rate(self.prefill_tokens, self.prefill_secs),
+            decode_tokens_per_sec: rate(self.decode_tokens, self.decode_secs),
+            perplexity: (self.scored_tokens > 0)
+                .then(|| (self.nll / self.scored_tokens as f64).exp()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kl_is_zero_for_identical_and_positive_otherwise() {
+        let p = [1.0f32, 2.0, 3.0];
+        assert!(kl_divergence(&p, &p).unwrap() < 1e-7);
+        // Shifting all logits leaves the distribution unchanged.
+        assert!(kl_divergence(&p, &[11.0, 12.0, 13.0]).unwrap() < 1e-7);
+        assert!(kl_divergence(&p, &[3.0, 2.0, 1.0]).unwrap() > 0.1);
+    }
+
+    #[test]
+    fn kl_divergence_is_stable_for_large_and_masked_logits() {
+        let p = [1000.0f32, 999.0, -1000.0];
+        assert!(kl_divergence(&p, &p).unwrap() < 1e-6);
+        let kl = kl_divergence(&p, &[999.0, 1000.0, -1000.0]).unwrap();
+        assert!(kl > 0.1 && kl.is_finite(), "{kl}");
+
+        // Masked in p: no contribution. Masked in q only: q rules out what p allows.
+        let masked = [2.0f32, f32::NEG_INFINITY, 1.0];
+        assert!(
+            kl_divergence(&masked, &[2.0, 5.0, 1.0])
+                .unwrap()
+                .is_finite()
+        );
+        assert!(kl_divergence(&masked, &masked).unwrap() < 1e-6);
+        assert_eq!(
+            kl_divergence(&[2.0, 5.0, 1.0], &masked).unwrap(),
+            f32::INFINITY
+        );
+        assert!(
+            kl_divergence(&[1.0, f32::NAN], &[1.0, 2.0])
+                .unwrap()
+                .is_nan()
+        );
+    }
+
+    #[test]
+    fn kl_divergence_rejects_mismatched_or_empty_vocabs() {
+        assert!(kl_divergence(&[1.0, 2.0], &[1.0]).is_err());
+        assert!(kl_divergence(&[], &[]).is_err());
+    }
+}
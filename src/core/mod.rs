@@ -1 +1,3 @@
+pub mod stats;
 pub mod tensor;
+pub mod time;
@@ -0,0 +1,298 @@
+//! Weight statistics for checking dequantization against reference tools.
+//!
+//! [`Tensor::stats`](crate::core::tensor::Tensor::stats) streams a tensor one block at a time:
+//! min, max, mean and standard deviation of the dequantized values, a [`HISTOGRAM_BINS`]-bin
+//! histogram over `[min, max]`, and the same summary of the block scales (and Q4_K mins) read
+//! straight from the packed bytes. [`StatsReport`] collects them per tensor name, prints a table,
+//! round-trips through JSON, and [`StatsReport::diff`] compares against a report produced
+//! elsewhere (e.g. by a script over `gguf-py` output) with the same layout.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+
+/// Bins in [`Histogram::counts`].
+pub const HISTOGRAM_BINS: usize = 16;
+
+/// Summary of a set of values. `stddev` is the population standard deviation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ValueStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl ValueStats {
+    /// `(field, |self - other|)` for the largest of the min / max / mean / stddev differences.
+    fn max_deviation(&self, other: &ValueStats) -> (&'static str, f64) {
+        [
+            ("min", self.min - other.min),
+            ("max", self.max - other.max),
+            ("mean", self.mean - other.mean),
+            ("stddev", self.stddev - other.stddev),
+        ]
+        .into_iter()
+        .map(|(field, d)| (field, d.abs()))
+        .fold(
+            ("min", 0.0),
+            |worst, d| if d.1 > worst.1 { d } else { worst },
+        )
+    }
+}
+
+/// Streaming mean and variance (Welford), so no pass needs the values twice in memory.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Accumulator {
+    count: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+}
+
+impl Accumulator {
+    pub(crate) fn push(&mut self, x: f64) {
+        self.count += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// `None` if nothing was pushed.
+    pub(crate) fn finish(&self) -> Option<ValueStats> {
+        (self.count > 0).then(|| ValueStats {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            stddev: (self.m2 / self.count as f64).sqrt(),
+        })
+    }
+}
+
+/// Counts of values in [`HISTOGRAM_BINS`] equal-width bins over `[min, max]`; the last bin is
+/// closed on the right. Everything lands in bin 0 when `min == max`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub(crate) fn new(min: f64, max: f64) -> Self {
+        Self {
+            min,
+            max,
+            counts: vec![0; HISTOGRAM_BINS],
+        }
+    }
+
+    pub(crate) fn push(&mut self, x: f64) {
+        let width = self.max - self.min;
+        let bin = if width > 0.0 {
+            (((x - self.min) / width) * HISTOGRAM_BINS as f64) as usize
+        } else {
+            0
+        };
+        self.counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+}
+
+/// Statistics of one tensor, from [`Tensor::stats`](crate::core::tensor::Tensor::stats).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TensorStats {
+    /// Storage type, as [`TensorType`](crate::core::tensor::TensorType)'s `Debug` name.
+    pub dtype: String,
+    /// Dequantized values.
+    pub values: ValueStats,
+    #[serde(default)]
+    pub histogram: Histogram,
+    /// Effective per-block (per-sub-block for K-quants) scales, `d * scale`; `None` for F32.
+    #[serde(default)]
+    pub scales: Option<ValueStats>,
+    /// Effective Q4_K sub-block mins, `dmin * min`; `None` for other types.
+    #[serde(default)]
+    pub mins: Option<ValueStats>,
+}
+
+/// [`TensorStats`] by tensor name, from
+/// [`GGUFData::stats_report`](crate::model_loader::gguf_types::GGUFData::stats_report) or
+/// [`Self::from_json`].
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct StatsReport {
+    pub tensors: BTreeMap<String, TensorStats>,
+}
+
+impl StatsReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("stats report serializes")
+    }
+
+    /// Parse a report written by [`Self::to_json`] or by an external tool using the same fields.
+    /// `histogram`, `scales` and `mins` may be omitted.
+    pub fn from_json(json: &str) -> Result<Self, EngineError> {
+        serde_json::from_str(json)
+            .map_err(|e| EngineError::Model(format!("invalid stats report JSON: {e}")))
+    }
+
+    /// Compare with `reference`, matching tensors by name.
+    pub fn diff(&self, reference: &StatsReport) -> StatsDiff {
+        let mut tensors = Vec::new();
+        let mut missing_in_reference = Vec::new();
+        for (name, ours) in &self.tensors {
+            let Some(theirs) = reference.tensors.get(name) else {
+                missing_in_reference.push(name.clone());
+                continue;
+            };
+            let mut worst = ours.values.max_deviation(&theirs.values);
+            for (label, a, b) in [
+                ("scales", &ours.scales, &theirs.scales),
+                ("mins", &ours.mins, &theirs.mins),
+            ] {
+                if let (Some(a), Some(b)) = (a, b) {
+                    let d = a.max_deviation(b).1;
+                    if d > worst.1 {
+                        worst = (label, d);
+                    }
+                }
+            }
+            tensors.push(TensorDeviation {
+                name: name.clone(),
+                max_deviation: worst.1,
+                field: worst.0,
+            });
+        }
+        tensors.sort_by(|a, b| b.max_deviation.total_cmp(&a.max_deviation));
+        let missing_here = reference
+            .tensors
+            .keys()
+            .filter(|name| !self.tensors.contains_key(*name))
+            .cloned()
+            .collect();
+        StatsDiff {
+            tensors,
+            missing_in_reference,
+            missing_here,
+        }
+    }
+}
+
+/// One row per tensor: `name  dtype  count  min  max  mean  stddev  scale mean`.
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:<5} {:>10} {:>11} {:>11} {:>11} {:>11} {:>11}",
+            "tensor", "dtype", "count", "min", "max", "mean", "stddev", "scale mean"
+        )?;
+        for (name, s) in &self.tensors {
+            let v = &s.values;
+            let scale = s.scales.map_or("-".into(), |sc| format!("{:.4e}", sc.mean));
+            write!(
+                f,
+                "\n{name:<32} {:<5} {:>10} {:>11.4e} {:>11.4e} {:>11.4e} {:>11.4e} {scale:>11}",
+                s.dtype, v.count, v.min, v.max, v.mean, v.stddev
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Largest statistic difference for one tensor in a [`StatsDiff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorDeviation {
+    pub name: String,
+    pub max_deviation: f64,
+    /// Which statistic differed most: `min`, `max`, `mean`, `stddev` of the values, or
+    /// `scales` / `mins` when a scale summary did.
+    pub field: &'static str,
+}
+
+/// Result of [`StatsReport::diff`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatsDiff {
+    /// Tensors in both reports, largest deviation first.
+    pub tensors: Vec<TensorDeviation>,
+    pub missing_in_reference: Vec<String>,
+    pub missing_here: Vec<String>,
+}
+
+impl StatsDiff {
+    /// Largest deviation over all matched tensors (0 if none matched).
+    pub fn max_deviation(&self) -> f64 {
+        self.tensors.first().map_or(0.0, |t| t.max_deviation)
+    }
+}
+
+impl fmt::Display for StatsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<32} {:>13}  field", "tensor", "max deviation")?;
+        for t in &self.tensors {
+            write!(
+                f,
+                "\n{:<32} {:>13.4e}  {}",
+                t.name, t.max_deviation, t.field
+            )?;
+        }
+        for name in &self.missing_in_reference {
+            write!(f, "\n{name:<32} not in reference")?;
+        }
+        for name in &self.missing_here {
+            write!(f, "\n{name:<32} only in reference")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulator_matches_the_two_pass_formulas() {
+        let xs = [1.0, -2.0, 4.5, 0.25, 3.0];
+        let mut acc = Accumulator::default();
+        xs.iter().for_each(|&x| acc.push(x));
+        let stats = acc.finish().unwrap();
+        let mean = xs.iter().sum::<f64>() / 5.0;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 5.0;
+        assert_eq!((stats.count, stats.min, stats.max), (5, -2.0, 4.5));
+        assert!((stats.mean - mean).abs() < 1e-12);
+        assert!((stats.stddev - var.sqrt()).abs() < 1e-12);
+        assert!(Accumulator::default().finish().is_none());
+    }
+
+    #[test]
+    fn histogram_edges_fall_in_the_end_bins() {
+        let mut h = Histogram::new(-1.0, 1.0);
+        for x in [-1.0, -0.99, 0.0, 0.99, 1.0] {
+            h.push(x);
+        }
+        assert_eq!(h.counts[0], 2);
+        assert_eq!(h.counts[HISTOGRAM_BINS / 2], 1);
+        assert_eq!(h.counts[HISTOGRAM_BINS - 1], 2);
+        let mut flat = Histogram::new(3.0, 3.0);
+        flat.push(3.0);
+        assert_eq!(flat.counts[0], 1);
+    }
+}
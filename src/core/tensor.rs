@@ -1,39 +1,166 @@
+use std::fmt;
+use std::ops::{Deref, Range};
 use std::sync::Arc;
 
+use serde::{Deserialize, Serialize};
+
 use crate::EngineError;
+use crate::core::stats::{Accumulator, Histogram, TensorStats};
+use crate::model_loader::storage::Mapping;
+use crate::ops::math::matmul::COLUMN_TILE;
+use crate::ops::quant::block_iterator::BlockIter;
+use crate::ops::quant::quant_k_handler::{
+    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, dequantize_q4k_block,
+    dequantize_q6k_block, dequantize_q8_0_block, quantize_q8_0_block,
+};
+
+/// Weights per Q4_K / Q6_K superblock.
+const K_BLOCK_ELEMENTS: usize = 256;
+
+type BlockDecoder = fn(&[u8], &mut [f32]) -> Result<(), EngineError>;
 
-#[derive(Debug)]
+/// `Debug` and `Display` print a summary (dtype, dims, element count and the first
+/// [`PREVIEW_VALUES`] values), never the whole buffer.
 pub struct Tensor {
     dtype: TensorType,
-    buffer: Arc<Vec<u8>>,
+    buffer: TensorBuffer,
     dimensions: Vec<usize>,
     stride: Vec<usize>,
+    layout: WeightLayout,
+}
+
+/// Order of a quantized 2-D weight's blocks in its buffer. Only matmul reads a non-ggml layout
+/// directly; everything that walks elements in storage order (dequantizing, slicing, dtype
+/// conversion) sees the ggml order regardless.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum WeightLayout {
+    /// As in the GGUF file: every output column's blocks are one run, columns one after another.
+    #[default]
+    Ggml,
+    /// Output columns in tiles of [`COLUMN_TILE`]: block `b` of each of a tile's columns side by
+    /// side, then block `b + 1`, so decode multiplies each input segment against the whole tile
+    /// while it is in cache. Blocks are moved whole, so scales stay with their quants.
+    ColumnTiles,
+}
+
+/// The bytes behind a [`Tensor`]: owned, or a range of a read-only [`Mapping`] whose pages are
+/// shared with every process mapping the same file.
+#[derive(Debug, Clone)]
+enum TensorBuffer {
+    Owned(Arc<Vec<u8>>),
+    Mapped(Arc<Mapping>, Range<usize>),
+}
+
+impl Deref for TensorBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            Self::Mapped(map, range) => &map[range.clone()],
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TensorType {
     /// Unquantized float32 tensors (used for layer normalization weights)
     F32,
-    /// 4-bit quantization, unpacked to u8 (values 0-15)
+    /// Q4_K: 256-weight superblocks of 4-bit codes `0..=15`, `w = d·sc·q − dmin·m` per 32-weight
+    /// sub-block. The only type with per-block mins.
     Q4K,
-    /// 6-bit quantization, unpacked to u8 (values 0-63)
+    /// Q6_K: 256-weight superblocks of 6-bit codes re-centred by 32, `w = d·sc·(q − 32)` per
+    /// 16-weight sub-block with int8 `sc`. No mins.
     Q6K,
-    /// Q8_0: blocks of 32 int8 values with one fp16 scale per block (ggml `block_q8_0`).
+    /// Q8_0: blocks of 32 int8 values with one fp16 scale per block (ggml `block_q8_0`),
+    /// `w = d·q`. No mins.
     Q8_0,
 }
 
+impl TensorType {
+    /// Whether dequantizing subtracts a stored per-block min (`scale·q − min`). Formats without
+    /// one are symmetric (`scale·q` over signed codes); see
+    /// [`crate::ops::quant::block_iterator`] for the per-type layout.
+    pub fn has_block_mins(self) -> bool {
+        matches!(self, TensorType::Q4K)
+    }
+}
+
 impl Tensor {
     /// Create a new Tensor that owns a raw byte buffer.
     pub(crate) fn new(dtype: TensorType, buffer: Arc<Vec<u8>>, dimensions: Vec<usize>) -> Self {
+        Self::with_buffer(dtype, TensorBuffer::Owned(buffer), dimensions)
+    }
+
+    fn with_buffer(dtype: TensorType, buffer: TensorBuffer, dimensions: Vec<usize>) -> Self {
         let stride = compute_row_major_stride(&dimensions);
         Self {
             dtype,
             buffer,
             dimensions,
             stride,
+            layout: WeightLayout::Ggml,
         }
     }
 
+    /// A tensor over `range` of a read-only mapping, without copying. Fails if the range is out
+    /// of bounds or too short for `dimensions`, or if F32 data is not 4-byte aligned.
+    pub(crate) fn mapped(
+        dtype: TensorType,
+        map: Arc<Mapping>,
+        range: Range<usize>,
+        dimensions: Vec<usize>,
+    ) -> Result<Self, EngineError> {
+        let needed = byte_len(dtype, dimensions.iter().product());
+        let bytes = map.get(range.clone()).ok_or_else(|| {
+            EngineError::Tensor(format!(
+                "mapped range {range:?} outside a {}-byte file",
+                map.len()
+            ))
+        })?;
+        if bytes.len() < needed {
+            return Err(EngineError::Tensor(format!(
+                "{dtype:?} mapping has {} bytes, need {needed} for dims {dimensions:?}",
+                bytes.len()
+            )));
+        }
+        if dtype == TensorType::F32 && bytes.as_ptr().align_offset(4) != 0 {
+            return Err(EngineError::Tensor(
+                "mapped F32 data not 4-byte aligned".into(),
+            ));
+        }
+        Ok(Self::with_buffer(
+            dtype,
+            TensorBuffer::Mapped(map, range),
+            dimensions,
+        ))
+    }
+
+    /// Whether the bytes live in a shared read-only mapping rather than this process's heap.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.buffer, TensorBuffer::Mapped(..))
+    }
+
+    /// A tensor over `buffer` holding `dtype` data (F32 little-endian, or packed ggml blocks),
+    /// for callers outside the loader such as benchmarks. Fails if `buffer` is too short for
+    /// `dimensions`.
+    pub fn from_bytes(
+        dtype: TensorType,
+        buffer: Vec<u8>,
+        dimensions: Vec<usize>,
+    ) -> Result<Self, EngineError> {
+        let needed = byte_len(dtype, dimensions.iter().product());
+        if buffer.len() < needed {
+            return Err(EngineError::Tensor(format!(
+                "{dtype:?} buffer has {} bytes, need {needed} for dims {dimensions:?}",
+                buffer.len()
+            )));
+        }
+        Ok(Self::new(dtype, Arc::new(buffer), dimensions))
+    }
+
     /// Read a single F32 value from the buffer (little-endian).
     pub fn f32_at(&self, index: usize) -> Result<f32, EngineError> {
         if self.dtype != TensorType::F32 {
@@ -59,19 +186,35 @@ impl Tensor {
             return Err(EngineError::Tensor("dtype is not F32".into()));
         }
         // SAFETY: `words` is only returned if there is no unaligned prefix/suffix.
-        let (prefix, words, suffix) = unsafe { self.buffer.as_slice().align_to::<f32>() };
+        let (prefix, words, suffix) = unsafe { self.buffer.align_to::<f32>() };
         if !prefix.is_empty() || !suffix.is_empty() {
             return Err(EngineError::Tensor("buffer not aligned for F32".into()));
         }
         Ok(words)
     }
 
+    /// Borrow a 0-D (scalar) or 1-D F32 tensor as a flat vector, e.g. norm weights.
+    ///
+    /// Errors for multi-dimensional tensors so callers don't silently flatten a matrix.
+    pub fn as_vector(&self) -> Result<&[f32], EngineError> {
+        if self.dimensions.len() > 1 {
+            return Err(EngineError::Tensor(format!(
+                "expected a 1-D tensor, got dims {:?}",
+                self.dimensions
+            )));
+        }
+        self.as_f32_slice()
+    }
+
     /// Return a contiguous mutable F32 slice for row-major tensors.
     pub fn as_f32_slice_mut(&mut self) -> Result<&mut [f32], EngineError> {
         if self.dtype != TensorType::F32 {
             return Err(EngineError::Tensor("dtype is not F32".into()));
         }
-        let buffer = Arc::get_mut(&mut self.buffer)
+        let TensorBuffer::Owned(buffer) = &mut self.buffer else {
+            return Err(EngineError::Tensor("buffer is a read-only mapping".into()));
+        };
+        let buffer = Arc::get_mut(buffer)
             .ok_or_else(|| EngineError::Tensor("buffer is shared (Arc)".into()))?;
         let (prefix, words, suffix) = unsafe { buffer.as_mut_slice().align_to_mut::<f32>() };
         if !prefix.is_empty() || !suffix.is_empty() {
@@ -95,6 +238,355 @@ impl Tensor {
     pub fn dtype(&self) -> TensorType {
         self.dtype
     }
+
+    /// Block order of the buffer; [`WeightLayout::Ggml`] unless set by [`Self::with_layout`].
+    pub fn layout(&self) -> WeightLayout {
+        self.layout
+    }
+
+    /// Whether [`Self::with_layout`] can store this tensor as `layout`: always for
+    /// [`WeightLayout::Ggml`]; for [`WeightLayout::ColumnTiles`], a quantized 2-D weight whose
+    /// columns are whole blocks and whose column count is a multiple of [`COLUMN_TILE`].
+    pub fn supports_layout(&self, layout: WeightLayout) -> bool {
+        match layout {
+            WeightLayout::Ggml => true,
+            WeightLayout::ColumnTiles => self.column_tiles().is_some(),
+        }
+    }
+
+    /// Blocks per column, bytes per block and column count, if the tensor can be column-tiled.
+    fn column_tiles(&self) -> Option<(usize, usize, usize)> {
+        let &[k, n] = self.dimensions.as_slice() else {
+            return None;
+        };
+        let (block_elems, block_bytes, _) = self.block_layout().ok()??;
+        (k > 0 && k % block_elems == 0 && n > 0 && n % COLUMN_TILE == 0).then_some((
+            k / block_elems,
+            block_bytes,
+            n,
+        ))
+    }
+
+    /// The same weight with its blocks in `layout` order. Reordering copies the buffer (a mapped
+    /// tensor becomes owned); asking for the current layout shares it. Fails where
+    /// [`Self::supports_layout`] is false.
+    pub fn with_layout(&self, layout: WeightLayout) -> Result<Tensor, EngineError> {
+        if layout == self.layout {
+            let mut same =
+                Tensor::with_buffer(self.dtype, self.buffer.clone(), self.dimensions.clone());
+            same.layout = layout;
+            return Ok(same);
+        }
+        let Some((blocks_per_col, block_bytes, n)) = self.column_tiles() else {
+            return Err(EngineError::Tensor(format!(
+                "{:?} {:?} cannot be stored as {layout:?}: needs a quantized [K, N] weight with \
+                 whole-block columns and N a multiple of {COLUMN_TILE}",
+                self.dtype, self.dimensions
+            )));
+        };
+        let mut bytes = vec![0u8; n * blocks_per_col * block_bytes];
+        for col in 0..n {
+            for b in 0..blocks_per_col {
+                // Block `b` of column `col`, in ggml order and in tile order.
+                let ggml = (col * blocks_per_col + b) * block_bytes;
+                let tiled = ((col / COLUMN_TILE * blocks_per_col + b) * COLUMN_TILE
+                    + col % COLUMN_TILE)
+                    * block_bytes;
+                let (src, dst) = match layout {
+                    WeightLayout::ColumnTiles => (ggml, tiled),
+                    WeightLayout::Ggml => (tiled, ggml),
+                };
+                bytes[dst..dst + block_bytes].copy_from_slice(&self.buffer[src..src + block_bytes]);
+            }
+        }
+        let mut out = Tensor::new(self.dtype, Arc::new(bytes), self.dimensions.clone());
+        out.layout = layout;
+        Ok(out)
+    }
+
+    /// Number of logical elements (product of dims; 1 for a scalar).
+    pub fn element_count(&self) -> usize {
+        self.dimensions.iter().product()
+    }
+
+    /// Dequantize every element to F32, in storage order (ggml: `dims[0]` varies fastest).
+    pub fn dequantize_to_f32(&self) -> Result<Vec<f32>, EngineError> {
+        self.dequantize_range(0..self.element_count())
+    }
+
+    /// Rows `start..end` of the outermost dimension (PyTorch dim 0; ggml's last, `dims[n - 1]`)
+    /// as a new F32 tensor, e.g. one head's or group's slice of a projection. Only the blocks
+    /// covering those rows are dequantized. The range must be non-empty (`start < end`): a
+    /// tensor with a zero dimension is not a weight any caller could use.
+    pub fn slice_dim0(&self, start: usize, end: usize) -> Result<Tensor, EngineError> {
+        let Some((&rows, inner)) = self.dimensions.split_last() else {
+            return Err(EngineError::Tensor("slice_dim0: scalar tensor".into()));
+        };
+        if start >= end || end > rows {
+            return Err(EngineError::Tensor(format!(
+                "slice_dim0: rows {start}..{end} empty or out of bounds for {rows} rows (dims {:?})",
+                self.dimensions
+            )));
+        }
+        let row_elems: usize = inner.iter().product();
+        let values = self.dequantize_range(start * row_elems..end * row_elems)?;
+        let mut dimensions = inner.to_vec();
+        dimensions.push(end - start);
+        let buffer = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        Ok(Tensor::new(TensorType::F32, Arc::new(buffer), dimensions))
+    }
+
+    /// Elements `range` (in storage order) as F32, decoding only the blocks they touch.
+    fn dequantize_range(&self, range: std::ops::Range<usize>) -> Result<Vec<f32>, EngineError> {
+        if self.layout != WeightLayout::Ggml {
+            return self
+                .with_layout(WeightLayout::Ggml)?
+                .dequantize_range(range);
+        }
+        let Some((block_elems, block_bytes, decode)) = self.block_layout()? else {
+            return Ok(self.as_f32_slice()?[range].to_vec());
+        };
+        let (first, last) = (range.start / block_elems, range.end.div_ceil(block_elems));
+        let mut out = vec![0.0f32; (last - first) * block_elems];
+        for (block, dst) in self.buffer[first * block_bytes..last * block_bytes]
+            .chunks_exact(block_bytes)
+            .zip(out.chunks_exact_mut(block_elems))
+        {
+            decode(block, dst)?;
+        }
+        let offset = first * block_elems;
+        out.truncate(range.end - offset);
+        out.drain(..range.start - offset);
+        Ok(out)
+    }
+
+    /// Elements and bytes per block and the block decoder, after checking the buffer holds every
+    /// block; `None` for F32.
+    fn block_layout(&self) -> Result<Option<(usize, usize, BlockDecoder)>, EngineError> {
+        let (block_elems, block_bytes, decode): (usize, usize, BlockDecoder) = match self.dtype {
+            TensorType::F32 => return Ok(None),
+            TensorType::Q4K => (K_BLOCK_ELEMENTS, Q4K_BLOCK_SIZE, dequantize_q4k_block),
+            TensorType::Q6K => (K_BLOCK_ELEMENTS, Q6K_BLOCK_SIZE, dequantize_q6k_block),
+            TensorType::Q8_0 => (Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, dequantize_q8_0_block),
+        };
+        let n = self.element_count();
+        let n_blocks = n.div_ceil(block_elems);
+        if self.buffer.len() < n_blocks * block_bytes {
+            return Err(EngineError::Tensor(format!(
+                "{:?} buffer has {} bytes, need {} for {n} elements",
+                self.dtype,
+                self.buffer.len(),
+                n_blocks * block_bytes
+            )));
+        }
+        Ok(Some((block_elems, block_bytes, decode)))
+    }
+
+    /// Call `f` on the dequantized values in storage order, one block (or, for F32, one chunk of
+    /// the buffer) at a time.
+    fn for_each_block(&self, mut f: impl FnMut(&[f32])) -> Result<(), EngineError> {
+        let n = self.element_count();
+        let Some((block_elems, block_bytes, decode)) = self.block_layout()? else {
+            let words = self.as_f32_slice()?;
+            if words.len() < n {
+                return Err(EngineError::Tensor(format!(
+                    "F32 buffer has {} elements, need {n}",
+                    words.len()
+                )));
+            }
+            words[..n].chunks(4096).for_each(f);
+            return Ok(());
+        };
+        let mut block = vec![0.0f32; block_elems];
+        for (i, raw) in self.buffer.chunks_exact(block_bytes).enumerate() {
+            let start = i * block_elems;
+            if start >= n {
+                break;
+            }
+            decode(raw, &mut block)?;
+            f(&block[..block_elems.min(n - start)]);
+        }
+        Ok(())
+    }
+
+    /// Statistics of the dequantized values and of the block scales, decoded one block at a
+    /// time: no f32 copy of the tensor is made. Two passes, since the histogram needs the range.
+    pub fn stats(&self) -> Result<TensorStats, EngineError> {
+        let mut acc = Accumulator::default();
+        self.for_each_block(|block| block.iter().for_each(|&x| acc.push(f64::from(x))))?;
+        let values = acc
+            .finish()
+            .ok_or_else(|| EngineError::Tensor("stats: tensor has no elements".into()))?;
+        let mut histogram = Histogram::new(values.min, values.max);
+        self.for_each_block(|block| block.iter().for_each(|&x| histogram.push(f64::from(x))))?;
+
+        let (mut scales, mut mins) = (Accumulator::default(), Accumulator::default());
+        if self.dtype != TensorType::F32 {
+            for block in BlockIter::new(self)? {
+                for sub in &block.sub_blocks {
+                    scales.push(sub.scale);
+                    if self.dtype.has_block_mins() {
+                        mins.push(sub.min);
+                    }
+                }
+            }
+        }
+        Ok(TensorStats {
+            dtype: format!("{:?}", self.dtype),
+            values,
+            histogram,
+            scales: scales.finish(),
+            mins: mins.finish(),
+        })
+    }
+
+    /// The same values stored as `dtype`: F32 dequantizes, Q8_0 (re)quantizes every 32
+    /// consecutive elements into one block, so the element count must be a multiple of 32.
+    /// Converting to the current dtype shares the buffer. K-quant targets are not supported.
+    pub fn to_dtype(&self, dtype: TensorType) -> Result<Tensor, EngineError> {
+        if dtype == self.dtype {
+            return self.with_layout(self.layout);
+        }
+        let values = self.dequantize_to_f32()?;
+        let buffer = match dtype {
+            TensorType::F32 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            TensorType::Q8_0 => {
+                if values.len() % Q8_0_BLOCK_ELEMENTS != 0 {
+                    return Err(EngineError::Tensor(format!(
+                        "cannot store {} elements as Q8_0: not a multiple of {Q8_0_BLOCK_ELEMENTS}",
+                        values.len()
+                    )));
+                }
+                let mut bytes = vec![0u8; values.len() / Q8_0_BLOCK_ELEMENTS * Q8_0_BLOCK_SIZE];
+                for (src, dst) in values
+                    .chunks_exact(Q8_0_BLOCK_ELEMENTS)
+                    .zip(bytes.chunks_exact_mut(Q8_0_BLOCK_SIZE))
+                {
+                    quantize_q8_0_block(src, dst)?;
+                }
+                bytes
+            }
+            TensorType::Q4K | TensorType::Q6K => {
+                return Err(EngineError::Tensor(format!(
+                    "converting {:?} to {dtype:?} is not supported",
+                    self.dtype
+                )));
+            }
+        };
+        Ok(Tensor::new(
+            dtype,
+            Arc::new(buffer),
+            self.dimensions.clone(),
+        ))
+    }
+
+    /// Dequantize a 2-D ggml tensor `[ne0, ne1]` into an `Array2` of shape `(ne1, ne0)`, so each
+    /// contiguous ggml row becomes an ndarray row. For a weight `[K, N]` that is the familiar
+    /// PyTorch `(out_features, in_features)` view: `arr[[col, kk]] == W(kk, col)`.
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray2(&self) -> Result<ndarray::Array2<f32>, EngineError> {
+        let &[ne0, ne1] = self.dimensions.as_slice() else {
+            return Err(EngineError::Tensor(format!(
+                "to_ndarray2: expected a 2-D tensor, got dims {:?}",
+                self.dimensions
+            )));
+        };
+        ndarray::Array2::from_shape_vec((ne1, ne0), self.dequantize_to_f32()?)
+            .map_err(|e| EngineError::Tensor(format!("to_ndarray2: {e}")))
+    }
+}
+
+/// Leading values shown by a tensor's `Debug` and `Display` summaries.
+pub const PREVIEW_VALUES: usize = 4;
+
+impl Tensor {
+    /// [`fmt::Display`] summary headed by `name`; tensors do not carry their own (see
+    /// [`crate::model_loader::gguf_types::GGUFData::tensor_name`]).
+    pub fn named<'a>(&'a self, name: &'a str) -> NamedTensor<'a> {
+        NamedTensor { name, tensor: self }
+    }
+
+    fn preview(&self) -> Preview<'_> {
+        Preview(self)
+    }
+}
+
+/// The first [`PREVIEW_VALUES`] elements as F32, `...` if there are more.
+struct Preview<'a>(&'a Tensor);
+
+impl fmt::Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.0.element_count();
+        let Ok(values) = self.0.dequantize_range(0..n.min(PREVIEW_VALUES)) else {
+            return f.write_str("[<unreadable>]");
+        };
+        f.write_str("[")?;
+        for (i, v) in values.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{v}")?;
+        }
+        if n > PREVIEW_VALUES {
+            f.write_str(", ...")?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl fmt::Debug for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tensor")
+            .field("dtype", &self.dtype)
+            .field("dims", &self.dimensions)
+            .field("elements", &self.element_count())
+            .field("bytes", &self.buffer.len())
+            .field("mapped", &self.is_mapped())
+            .field("head", &self.preview())
+            .finish()
+    }
+}
+
+/// `F32 [4096, 32000] (131072000 elements): [0.01, -0.2, 0.5, 0.03, ...]`
+impl fmt::Display for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} ({} elements): {}",
+            self.dtype,
+            self.dimensions,
+            self.element_count(),
+            self.preview()
+        )
+    }
+}
+
+/// [`Tensor::named`]: `name: ` followed by the tensor's [`fmt::Display`] summary.
+pub struct NamedTensor<'a> {
+    name: &'a str,
+    tensor: &'a Tensor,
+}
+
+impl fmt::Display for NamedTensor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.tensor)
+    }
+}
+
+/// Bytes needed to hold `n` elements of `dtype`, counting whole quantization blocks.
+fn byte_len(dtype: TensorType, n: usize) -> usize {
+    match dtype {
+        TensorType::F32 => n * 4,
+        TensorType::Q4K => n.div_ceil(K_BLOCK_ELEMENTS) * Q4K_BLOCK_SIZE,
+        TensorType::Q6K => n.div_ceil(K_BLOCK_ELEMENTS) * Q6K_BLOCK_SIZE,
+        TensorType::Q8_0 => n.div_ceil(Q8_0_BLOCK_ELEMENTS) * Q8_0_BLOCK_SIZE,
+    }
 }
 
 fn compute_row_major_stride(dimensions: &[usize]) -> Vec<usize> {
@@ -110,3 +602,239 @@ fn compute_row_major_stride(dimensions: &[usize]) -> Vec<usize> {
     }
     stride
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::quant::utils::f32_to_f16;
+    use crate::test_utils::f32_tensor;
+
+    #[test]
+    fn debug_and_display_summarize_without_dumping_the_buffer() {
+        let values: Vec<f32> = (0..4096 * 64).map(|i| i as f32).collect();
+        let t = f32_tensor(&values, vec![4096, 64]);
+        let debug = format!("{t:?}");
+        assert!(debug.len() < 200, "{debug}");
+        assert!(
+            debug.contains("[4096, 64]") && debug.contains("262144"),
+            "{debug}"
+        );
+        assert!(debug.contains("[0, 1, 2, 3, ...]"), "{debug}");
+        assert_eq!(
+            t.named("blk.0.attn_q.weight").to_string(),
+            "blk.0.attn_q.weight: F32 [4096, 64] (262144 elements): [0, 1, 2, 3, ...]"
+        );
+
+        let mut block = vec![0x00, 0x38];
+        block.extend([2u8; 32]);
+        let q = Tensor::new(TensorType::Q8_0, Arc::new(block), vec![32]);
+        assert!(q.to_string().ends_with("[1, 1, 1, 1, ...]"), "{q}");
+        let short = f32_tensor(&[0.5, -1.0], vec![2]);
+        assert_eq!(short.to_string(), "F32 [2] (2 elements): [0.5, -1]");
+    }
+
+    #[test]
+    fn dequantize_q8_0_block_scales_int8() {
+        // d = 0.5 (f16 0x3800), q = 0, 1, .., 31
+        let mut block = vec![0x00, 0x38];
+        block.extend((0..32).map(|i| i as u8));
+        let t = Tensor::new(TensorType::Q8_0, Arc::new(block), vec![32]);
+        let values = t.dequantize_to_f32().unwrap();
+        assert_eq!(values.len(), 32);
+        assert_eq!(values[3], 1.5);
+        assert_eq!(values[31], 15.5);
+    }
+
+    #[test]
+    fn q8_0_stats_match_hand_computed_values() {
+        // Block 0: d = 0.5, q = 2 -> 32 x 1.0. Block 1: d = 0.25, q = -4 x 16 then 4 x 16.
+        let mut bytes = vec![0x00, 0x38];
+        bytes.extend([2u8; 32]);
+        bytes.extend([0x00, 0x34]);
+        bytes.extend([(-4i8) as u8; 16]);
+        bytes.extend([4u8; 16]);
+        let t = Tensor::new(TensorType::Q8_0, Arc::new(bytes), vec![64]);
+        let stats = t.stats().unwrap();
+
+        assert_eq!(stats.dtype, "Q8_0");
+        let v = stats.values;
+        assert_eq!((v.count, v.min, v.max), (64, -1.0, 1.0));
+        // 48 ones and 16 minus ones: mean 0.5, E[x^2] = 1, variance 0.75.
+        assert!((v.mean - 0.5).abs() < 1e-12);
+        assert!((v.stddev - 0.75f64.sqrt()).abs() < 1e-12);
+        assert_eq!(stats.histogram.counts[0], 16);
+        assert_eq!(
+            stats.histogram.counts[crate::core::stats::HISTOGRAM_BINS - 1],
+            48
+        );
+
+        let scales = stats.scales.unwrap();
+        assert_eq!((scales.count, scales.min, scales.max), (2, 0.25, 0.5));
+        assert!((scales.mean - 0.375).abs() < 1e-12);
+        assert!((scales.stddev - 0.125).abs() < 1e-12);
+        assert!(stats.mins.is_none());
+    }
+
+    #[test]
+    fn streamed_q4k_stats_match_the_full_dequant() {
+        // Two superblocks for 300 elements, so the second is only partly used.
+        let mut bytes = Vec::new();
+        for b in 0..2u8 {
+            bytes.extend(f32_to_f16(0.01 * f32::from(b + 1)).to_le_bytes());
+            bytes.extend(f32_to_f16(0.003).to_le_bytes());
+            bytes.extend((0..12u8).map(|i| i.wrapping_mul(37).wrapping_add(b)));
+            bytes.extend((0..128u8).map(|i| i.wrapping_mul(91).wrapping_add(13 * b)));
+        }
+        let t = Tensor::new(TensorType::Q4K, Arc::new(bytes), vec![300]);
+        let stats = t.stats().unwrap();
+
+        let all = t.dequantize_to_f32().unwrap();
+        let mut acc = Accumulator::default();
+        all.iter().for_each(|&x| acc.push(f64::from(x)));
+        let full = acc.finish().unwrap();
+        assert_eq!(stats.values.count, 300);
+        assert_eq!((stats.values.min, stats.values.max), (full.min, full.max));
+        assert!((stats.values.mean - full.mean).abs() < 1e-9);
+        assert!((stats.values.stddev - full.stddev).abs() < 1e-9);
+        assert_eq!(stats.histogram.counts.iter().sum::<u64>(), 300);
+        assert_eq!(stats.scales.unwrap().count, 16);
+        assert_eq!(stats.mins.unwrap().count, 16);
+
+        let f32_stats = f32_tensor(&all, vec![300]).stats().unwrap();
+        assert_eq!(f32_stats.values, stats.values);
+        assert!(f32_stats.scales.is_none());
+    }
+
+    #[test]
+    fn dequantize_f32_copies_elements() {
+        let t = f32_tensor(&[1.0, -2.0, 3.5, 4.0], vec![2, 2]);
+        assert_eq!(t.element_count(), 4);
+        assert_eq!(t.dequantize_to_f32().unwrap(), vec![1.0, -2.0, 3.5, 4.0]);
+    }
+
+    #[test]
+    fn dequantize_rejects_short_buffer() {
+        let t = Tensor::new(TensorType::Q8_0, Arc::new(vec![0u8; 10]), vec![32]);
+        assert!(t.dequantize_to_f32().is_err());
+    }
+
+    #[test]
+    fn stats_rejects_short_f32_buffer() {
+        let t = Tensor::new(TensorType::F32, Arc::new(vec![0u8; 8]), vec![4]);
+        let err = t.stats().unwrap_err();
+        assert!(matches!(err, EngineError::Tensor(_)));
+        assert!(err.to_string().contains("need 4"), "{err}");
+    }
+
+    #[test]
+    fn from_bytes_checks_the_buffer_covers_the_dims() {
+        let t = Tensor::from_bytes(TensorType::Q8_0, vec![0u8; Q8_0_BLOCK_SIZE], vec![32, 1]);
+        assert_eq!(t.unwrap().element_count(), 32);
+        assert!(
+            Tensor::from_bytes(TensorType::Q4K, vec![0u8; Q4K_BLOCK_SIZE - 1], vec![256]).is_err()
+        );
+        assert!(Tensor::from_bytes(TensorType::F32, vec![0u8; 12], vec![2, 2]).is_err());
+    }
+
+    #[test]
+    fn slice_dim0_matches_the_full_dequant() {
+        // Six rows of 32: the Q8_0 copy has one block per row.
+        let values: Vec<f32> = (0..6 * 32).map(|i| (i as f32 - 90.0) / 16.0).collect();
+        let f32 = f32_tensor(&values, vec![32, 6]);
+        for t in [&f32, &f32.to_dtype(TensorType::Q8_0).unwrap()] {
+            let full = t.dequantize_to_f32().unwrap();
+            let rows = t.slice_dim0(2, 4).unwrap();
+            assert_eq!(rows.dtype(), TensorType::F32);
+            assert_eq!(rows.dimensions(), &[32, 2]);
+            assert_eq!(rows.as_f32_slice().unwrap(), &full[2 * 32..4 * 32]);
+            assert!(t.slice_dim0(3, 3).is_err());
+            assert!(t.slice_dim0(4, 7).is_err());
+            assert!(t.slice_dim0(4, 2).is_err());
+        }
+        // Rows that start and end inside a block.
+        let odd = f32_tensor(&values, vec![12, 16])
+            .to_dtype(TensorType::Q8_0)
+            .unwrap();
+        let full = odd.dequantize_to_f32().unwrap();
+        let rows = odd.slice_dim0(5, 9).unwrap();
+        assert_eq!(rows.as_f32_slice().unwrap(), &full[5 * 12..9 * 12]);
+    }
+
+    #[test]
+    fn to_dtype_round_trips_through_q8_0() {
+        let values: Vec<f32> = (0..64).map(|i| (i as f32 - 30.0) / 8.0).collect();
+        let t = f32_tensor(&values, vec![32, 2]);
+        let q = t.to_dtype(TensorType::Q8_0).unwrap();
+        assert_eq!(q.dtype(), TensorType::Q8_0);
+        assert_eq!(q.dimensions(), &[32, 2]);
+        assert_eq!(q.buffer().len(), 2 * Q8_0_BLOCK_SIZE);
+        let back = q.to_dtype(TensorType::F32).unwrap();
+        for (a, b) in values.iter().zip(back.as_f32_slice().unwrap()) {
+            assert!((a - b).abs() < 0.02, "{a} vs {b}");
+        }
+        assert!(
+            f32_tensor(&[1.0; 16], vec![16])
+                .to_dtype(TensorType::Q8_0)
+                .is_err()
+        );
+        assert!(t.to_dtype(TensorType::Q4K).is_err());
+    }
+
+    #[test]
+    fn column_tiles_move_whole_blocks_and_read_back_in_ggml_order() {
+        // Q8_0 [64, 8]: two blocks per column, block `b` of column `col` filled with `col * 2 + b`.
+        let (k, n) = (2 * Q8_0_BLOCK_ELEMENTS, 2 * COLUMN_TILE);
+        let mut bytes = Vec::new();
+        for block in 0..n * 2 {
+            bytes.extend([0x00, 0x3c]);
+            bytes.extend([block as u8; Q8_0_BLOCK_ELEMENTS]);
+        }
+        let ggml = Tensor::new(TensorType::Q8_0, Arc::new(bytes), vec![k, n]);
+        let tiled = ggml.with_layout(WeightLayout::ColumnTiles).unwrap();
+        assert_eq!(tiled.layout(), WeightLayout::ColumnTiles);
+        // Tile 0 starts with block 0 of columns 0..4, then block 1 of the same columns.
+        let quant_of = |t: &Tensor, i: usize| t.buffer()[i * Q8_0_BLOCK_SIZE + 2];
+        let order: Vec<u8> = (0..2 * COLUMN_TILE).map(|i| quant_of(&tiled, i)).collect();
+        assert_eq!(order, [0, 2, 4, 6, 1, 3, 5, 7]);
+
+        assert_eq!(
+            tiled.dequantize_to_f32().unwrap(),
+            ggml.dequantize_to_f32().unwrap()
+        );
+        assert_eq!(tiled.to_string(), ggml.to_string());
+        let back = tiled.with_layout(WeightLayout::Ggml).unwrap();
+        assert_eq!(back.layout(), WeightLayout::Ggml);
+        assert_eq!(back.buffer(), ggml.buffer());
+        assert_eq!(
+            tiled.to_dtype(TensorType::Q8_0).unwrap().layout(),
+            WeightLayout::ColumnTiles
+        );
+
+        // F32, partial-block columns and a column count off the tile are left alone.
+        let q8_0 = |dims: Vec<usize>| {
+            let len = byte_len(TensorType::Q8_0, dims.iter().product());
+            Tensor::new(TensorType::Q8_0, Arc::new(vec![0u8; len]), dims)
+        };
+        for t in [
+            f32_tensor(&[0.0; 32 * 4], vec![32, 4]),
+            q8_0(vec![16, 8]),
+            q8_0(vec![32, COLUMN_TILE + 2]),
+            q8_0(vec![32 * COLUMN_TILE]),
+        ] {
+            assert!(!t.supports_layout(WeightLayout::ColumnTiles), "{t:?}");
+            assert!(t.with_layout(WeightLayout::ColumnTiles).is_err(), "{t:?}");
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn to_ndarray2_uses_ggml_row_order() {
+        // ggml dims [3, 2]: two contiguous rows of three.
+        let t = f32_tensor(&[0.0, 1.0, 2.0, 10.0, 11.0, 12.0], vec![3, 2]);
+        let arr = t.to_ndarray2().unwrap();
+        assert_eq!(arr.shape(), &[2, 3]);
+        assert_eq!(arr[[0, 2]], 2.0);
+        assert_eq!(arr[[1, 0]], 10.0);
+        assert!(f32_tensor(&[1.0], vec![1]).to_ndarray2().is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! The [`Instant`] every timing in the crate uses: [`std::time::Instant`], except in browser
+//! builds (`wasm32-unknown-unknown`), where std's panics on first use and this one reads
+//! `Date.now()` instead.
+//!
+//! `Date.now()` is wall-clock time in whole milliseconds and may step backwards; the shim's
+//! differences saturate at zero, as std's do, so a step only ever shortens one measurement.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use std::time::Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub use browser::Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod browser {
+    use std::ops::{Add, AddAssign, Sub};
+    use std::time::Duration;
+
+    /// Time since the Unix epoch according to `Date.now()`, with the methods of
+    /// [`std::time::Instant`] the crate calls.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant(Duration);
+
+    impl Instant {
+        pub fn now() -> Self {
+            Self(Duration::from_millis(js_sys::Date::now() as u64))
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Self::now().saturating_duration_since(*self)
+        }
+
+        pub fn duration_since(&self, earlier: Self) -> Duration {
+            self.saturating_duration_since(earlier)
+        }
+
+        pub fn saturating_duration_since(&self, earlier: Self) -> Duration {
+            self.0.saturating_sub(earlier.0)
+        }
+
+        pub fn checked_duration_since(&self, earlier: Self) -> Option<Duration> {
+            self.0.checked_sub(earlier.0)
+        }
+
+        pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+            self.0.checked_add(duration).map(Self)
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Self;
+
+        fn add(self, rhs: Duration) -> Self {
+            Self(self.0 + rhs)
+        }
+    }
+
+    impl AddAssign<Duration> for Instant {
+        fn add_assign(&mut self, rhs: Duration) {
+            self.0 += rhs;
+        }
+    }
+
+    impl Sub for Instant {
+        type Output = Duration;
+
+        fn sub(self, rhs: Self) -> Duration {
+            self.saturating_duration_since(rhs)
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Token accounting for one [`InferenceSession`](crate::engine::session::InferenceSession).
+//!
+//! Every operation that writes KV cache rows goes through the session's [`TokenBudget`] first,
+//! so running out of context is reported as [`BudgetError::Exceeded`] before any forward pass
+//! starts, instead of as `KVCacheFull` from inside a layer halfway through the stack.
+//!
+//! Invariants, checked by the session after every operation:
+//! - [`TokenBudget::used`] `<=` [`TokenBudget::context_length`];
+//! - [`TokenBudget::used`] equals the session's cache position.
+
+use thiserror::Error;
+
+/// Why tokens entered the cache. Only used for the per-kind tallies and error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenUse {
+    /// Prompt tokens (including chat template tokens) fed by a prefill.
+    Prompt,
+    /// Tokens forced after the prompt (e.g. a reply prefix); fed but never sampled.
+    Forced,
+    /// Sampled tokens fed back with a decode step.
+    Generated,
+    /// Tokens brought back by restoring a KV snapshot, or re-prefilled by a [`ContextShift`].
+    Restored,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum BudgetError {
+    #[error(
+        "token budget exceeded: {requested} {kind:?} token(s) requested with {used} of {context_length} already used"
+    )]
+    Exceeded {
+        kind: TokenUse,
+        requested: usize,
+        used: usize,
+        context_length: usize,
+    },
+}
+
+/// How many cache rows a session has used, and for what.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBudget {
+    context_length: usize,
+    prompt: usize,
+    forced: usize,
+    generated: usize,
+    restored: usize,
+}
+
+impl TokenBudget {
+    pub fn new(context_length: usize) -> Self {
+        Self {
+            context_length,
+            prompt: 0,
+            forced: 0,
+            generated: 0,
+            restored: 0,
+        }
+    }
+
+    /// Rows available in total (the smallest KV cache capacity of the session).
+    pub fn context_length(&self) -> usize {
+        self.context_length
+    }
+
+    pub fn used(&self) -> usize {
+        self.prompt + self.forced + self.generated + self.restored
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.context_length - self.used()
+    }
+
+    pub fn count(&self, kind: TokenUse) -> usize {
+        match kind {
+            TokenUse::Prompt => self.prompt,
+            TokenUse::Forced => self.forced,
+            TokenUse::Generated => self.generated,
+            TokenUse::Restored => self.restored,
+        }
+    }
+
+    /// Whether `n` more tokens of `kind` fit.
+    pub fn check(&self, kind: TokenUse, n: usize) -> Result<(), BudgetError> {
+        if n > self.remaining() {
+            return Err(BudgetError::Exceeded {
+                kind,
+                requested: n,
+                used: self.used(),
+                context_length: self.context_length,
+            });
+        }
+        Ok(())
+    }
+
+    /// [`Self::check`], then record the tokens.
+    pub fn consume(&mut self, kind: TokenUse, n: usize) -> Result<(), BudgetError> {
+        self.check(kind, n)?;
+        *self.tally_mut(kind) += n;
+        Ok(())
+    }
+
+    /// Forget everything (the cache was cleared).
+    pub fn clear(&mut self) {
+        *self = Self::new(self.context_length);
+    }
+
+    /// After a forward pass failed part-way, charge whatever did reach the cache to `kind` so
+    /// [`Self::used`] matches `position` again.
+    pub(crate) fn sync_to(&mut self, kind: TokenUse, position: usize) {
+        let used = self.used();
+        if position >= used {
+            *self.tally_mut(kind) += position - used;
+        } else {
+            // Rows vanished (should not happen); restart the tally from the cache.
+            self.clear();
+            self.restored = position;
+        }
+    }
+
+    fn tally_mut(&mut self, kind: TokenUse) -> &mut usize {
+        match kind {
+            TokenUse::Prompt => &mut self.prompt,
+            TokenUse::Forced => &mut self.forced,
+            TokenUse::Generated => &mut self.generated,
+            TokenUse::Restored => &mut self.restored,
+        }
+    }
+}
+
+/// What a generation loop does when the next token would not fit: keep the first `keep` cached
+/// tokens (e.g. a system prompt), drop the `discard` after them, and re-prefill the rest
+/// (see [`InferenceSession::set_context_shift`](crate::engine::session::InferenceSession::set_context_shift)).
+///
+/// Re-prefilling costs a forward pass over the kept tokens, but keeps RoPE positions exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextShift {
+    pub keep: usize,
+    pub discard: usize,
+}
+
+impl ContextShift {
+    /// `(keep, discard)` for a cache holding `cached` tokens, or `None` if nothing can be
+    /// dropped. Always discards at least one token.
+    pub fn plan(&self, cached: usize) -> Option<(usize, usize)> {
+        let keep = self.keep.min(cached);
+        let discard = self.discard.max(1).min(cached - keep);
+        (discard > 0).then_some((keep, discard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consume_tracks_kinds_and_rejects_overflow() {
+        let mut b = TokenBudget::new(10);
+        b.consume(TokenUse::Prompt, 4).unwrap();
+        b.consume(TokenUse::Forced, 2).unwrap();
+        b.consume(TokenUse::Generated, 1).unwrap();
+        assert_eq!((b.used(), b.remaining()), (7, 3));
+        assert_eq!(b.count(TokenUse::Forced), 2);
+
+        let err = b.consume(TokenUse::Generated, 4).unwrap_err();
+        assert_eq!(
+            err,
+            BudgetError::Exceeded {
+                kind: TokenUse::Generated,
+                requested: 4,
+                used: 7,
+                context_length: 10
+            }
+        );
+        assert_eq!(b.used(), 7, "a rejected request records nothing");
+        b.consume(TokenUse::Generated, 3).unwrap();
+        assert_eq!(b.remaining(), 0);
+
+        b.clear();
+        assert_eq!((b.used(), b.context_length()), (0, 10));
+    }
+
+    #[test]
+    fn sync_charges_partial_progress() {
+        let mut b = TokenBudget::new(8);
+        b.consume(TokenUse::Prompt, 3).unwrap();
+        b.sync_to(TokenUse::Generated, 4);
+        assert_eq!((b.used(), b.count(TokenUse::Generated)), (4, 1));
+        b.sync_to(TokenUse::Prompt, 2);
+        assert_eq!((b.used(), b.count(TokenUse::Restored)), (2, 2));
+    }
+
+    #[test]
+    fn context_shift_plan_clamps_to_the_cache() {
+        let shift = ContextShift {
+            keep: 4,
+            discard: 8,
+        };
+        assert_eq!(shift.plan(32), Some((4, 8)));
+        assert_eq!(shift.plan(10), Some((4, 6)));
+        assert_eq!(shift.plan(4), None);
+        let at_least_one = ContextShift {
+            keep: 0,
+            discard: 0,
+        };
+        assert_eq!(at_least_one.plan(3), Some((0, 1)));
+    }
+}
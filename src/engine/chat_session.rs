@@ -0,0 +1,130 @@
+//! Multi-turn chat on top of [`InferenceSession`]: keeps the transcript, renders it with a
+//! [`ChatPromptStyle`], and records each reply.
+//!
+//! Like `src/bin/chat.rs`, every turn re-prefills the full transcript (simple and correct).
+
+use crate::EngineError;
+use crate::chat_prompt::{ChatMessage, ChatPromptStyle, gemma4_e2b_assistant_visible};
+use crate::engine::generation::{GenerationConfig, GenerationOutput, generate_with_forced_prefix};
+use crate::engine::session::InferenceSession;
+use crate::loaded_model::LoadedModel;
+use crate::model_config::vocab_token_id;
+use crate::tokenizer::Tokenizer;
+
+pub struct ChatSession<'a> {
+    session: InferenceSession<'a>,
+    tokenizer: Tokenizer,
+    style: ChatPromptStyle,
+    history: Vec<ChatMessage>,
+    /// Id of [`ChatPromptStyle::turn_end_piece`] in the model vocabulary; always a stop token.
+    turn_end: Option<u32>,
+}
+
+impl<'a> ChatSession<'a> {
+    pub fn new(
+        model: &'a LoadedModel,
+        tokenizer: Tokenizer,
+        style: ChatPromptStyle,
+    ) -> Result<Self, EngineError> {
+        if matches!(style, ChatPromptStyle::Raw) {
+            return Err(EngineError::Model(
+                "ChatSession needs an instruct style (gemma4-e2b or mistral-instruct)".into(),
+            ));
+        }
+        let turn_end = style
+            .turn_end_piece()
+            .and_then(|piece| vocab_token_id(model.gguf(), piece));
+        Ok(Self {
+            session: InferenceSession::new(model)?,
+            tokenizer,
+            style,
+            history: Vec::new(),
+            turn_end,
+        })
+    }
+
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.history
+    }
+
+    pub fn tokenizer_mut(&mut self) -> &mut Tokenizer {
+        &mut self.tokenizer
+    }
+
+    /// Add a user turn and generate the assistant reply. With
+    /// [`GenerationConfig::json_schema`], the schema is appended to the rendered user turn as an
+    /// instruction ([`crate::engine::json_schema::JsonSchema::hint`]) and the reply is constrained
+    /// to it.
+    pub fn send(
+        &mut self,
+        user: &str,
+        config: &GenerationConfig,
+    ) -> Result<GenerationOutput, EngineError> {
+        self.send_with_forced_prefix(user, "", config)
+    }
+
+    /// Add a user turn and generate a reply that starts with `forced_prefix`. The prefix is fed
+    /// right after the assistant role header (e.g. `<|turn>model\n`), as if the model had
+    /// written it; the stored reply includes it.
+    ///
+    /// An empty or whitespace-only `user` message fails with [`EngineError::EmptyPrompt`] and
+    /// leaves the history unchanged: the template would still render, but the model would be
+    /// answering nothing.
+    pub fn send_with_forced_prefix(
+        &mut self,
+        user: &str,
+        forced_prefix: &str,
+        config: &GenerationConfig,
+    ) -> Result<GenerationOutput, EngineError> {
+        if user.trim().is_empty() {
+            return Err(EngineError::EmptyPrompt("empty user message".into()));
+        }
+        self.history.push(ChatMessage::user(user));
+        let result = self.reply(forced_prefix, config);
+        match &result {
+            Ok(out) => {
+                let reply = match self.style {
+                    ChatPromptStyle::Gemma4E2b => gemma4_e2b_assistant_visible(&out.text),
+                    _ => out.text.trim_end().to_string(),
+                };
+                self.history.push(ChatMessage::assistant(reply));
+            }
+            // Leave the transcript as it was so the caller can retry.
+            Err(_) => {
+                self.history.pop();
+            }
+        }
+        result
+    }
+
+    fn reply(
+        &mut self,
+        forced_prefix: &str,
+        config: &GenerationConfig,
+    ) -> Result<GenerationOutput, EngineError> {
+        // With a JSON schema, the model is told what the grammar will enforce; the stored
+        // history keeps the user's own words.
+        let mut history = std::borrow::Cow::Borrowed(&self.history);
+        if let Some(schema) = &config.json_schema {
+            if let Some(last) = history.to_mut().last_mut() {
+                last.content = format!("{}\n\n{}", last.content.trim_end(), schema.hint());
+            }
+        }
+        // Rendering ends with the generation prompt (assistant header).
+        let prompt = self
+            .style
+            .render_conversation(&history)
+            .map_err(|e| EngineError::Model(e.to_string()))?;
+        let mut config = config.clone();
+        if let Some(id) = self.turn_end {
+            config.stop_token_ids.push(id);
+        }
+        generate_with_forced_prefix(
+            &mut self.session,
+            &mut self.tokenizer,
+            &prompt,
+            forced_prefix,
+            &config,
+        )
+    }
+}
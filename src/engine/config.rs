@@ -0,0 +1,167 @@
+//! Runtime settings that belong to the engine rather than to the model file.
+
+use std::sync::Arc;
+
+use rayon::ThreadPool;
+
+use crate::EngineError;
+use crate::engine::thread_pool::{ThreadAffinity, shared_pool};
+use crate::engine::watchdog::WatchdogConfig;
+use crate::layers::attention::CacheDtype;
+use crate::model_config::ModelConfig;
+
+/// Engine options applied by [`crate::engine::session::InferenceSession::with_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EngineConfig {
+    /// Worker threads for the parallel kernels (matmul rows, attention heads). `None` uses
+    /// rayon's global pool, which defaults to one thread per logical core.
+    pub num_threads: Option<usize>,
+    /// KV cache storage; [`CacheDtype::F16`] halves its memory for a small accuracy cost.
+    /// [`crate::engine::kv_policy::KvCachePolicy`] picks one to fit a memory budget.
+    pub kv_cache_dtype: CacheDtype,
+    /// Run [`crate::ops::self_test::startup_self_test`] (once per process) when a session is
+    /// built; kernels that disagree with their scalar reference are disabled with a warning.
+    pub startup_self_test: bool,
+    /// Time every layer of each forward pass; read with
+    /// [`crate::engine::session::InferenceSession::layer_timings`].
+    pub layer_timings: bool,
+    /// Experimental: run only some transformer blocks, trading quality for speed.
+    pub layer_schedule: LayerSchedule,
+    /// Pin workers to the performance cores of a hybrid CPU (see
+    /// [`crate::engine::thread_pool`]); implies a dedicated pool even without `num_threads`.
+    pub thread_affinity: ThreadAffinity,
+    /// Watch every decode step for stalls (see [`crate::engine::watchdog`]); `None` runs no
+    /// monitor thread.
+    pub watchdog: Option<WatchdogConfig>,
+}
+
+/// Which transformer blocks run on each forward pass (experimental, for draft-quality output).
+///
+/// A skipped block passes the hidden state through unchanged and never writes its KV cache. The
+/// schedule is therefore **constant for a session**: a block that ran for some tokens and not
+/// others would have holes in its cache. [`crate::engine::session::InferenceSession`] takes it
+/// from [`EngineConfig::layer_schedule`] and resets its cache whenever it changes. The final norm
+/// and LM head always apply to the output of the last block that ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerSchedule {
+    #[default]
+    All,
+    /// Blocks `0..n` only.
+    FirstN(usize),
+    /// Every block except `start`, `start + stride`, `start + 2 * stride`, ...
+    SkipEvery { start: usize, stride: usize },
+}
+
+impl LayerSchedule {
+    /// Whether block `layer_idx` runs.
+    pub fn runs(self, layer_idx: usize) -> bool {
+        match self {
+            LayerSchedule::All => true,
+            LayerSchedule::FirstN(n) => layer_idx < n,
+            LayerSchedule::SkipEvery { start, stride } => {
+                layer_idx < start || stride == 0 || (layer_idx - start) % stride != 0
+            }
+        }
+    }
+
+    /// Lowest block that runs under this schedule, if any of `n_layers` does.
+    pub fn first_layer(self, n_layers: usize) -> Option<usize> {
+        (0..n_layers).find(|&i| self.runs(i))
+    }
+
+    /// Reject schedules that run no block, name blocks the model does not have, or skip a block
+    /// whose KV cache a running block reads (Gemma 4 shared-KV layers).
+    pub fn validate(self, config: &ModelConfig) -> Result<(), EngineError> {
+        let n_layers = config.n_layers;
+        match self {
+            LayerSchedule::All => return Ok(()),
+            LayerSchedule::FirstN(n) if n > n_layers => {
+                return Err(EngineError::Model(format!(
+                    "layer schedule FirstN({n}): model has {n_layers} layers"
+                )));
+            }
+            LayerSchedule::SkipEvery { stride: 0, .. } => {
+                return Err(EngineError::Model(
+                    "layer schedule SkipEvery: stride must be at least 1".into(),
+                ));
+            }
+            _ => {}
+        }
+        if self.first_layer(n_layers).is_none() {
+            return Err(EngineError::Model(format!(
+                "layer schedule {self:?} runs none of the {n_layers} layers"
+            )));
+        }
+        let skipped_source =
+            config
+                .gemma4_kv_borrow_from
+                .iter()
+                .enumerate()
+                .find_map(|(layer, src)| {
+                    src.filter(|&s| self.runs(layer) && !self.runs(s))
+                        .map(|s| (layer, s))
+                });
+        if let Some((layer, src)) = skipped_source {
+            return Err(EngineError::Model(format!(
+                "layer schedule {self:?} skips layer {src}, whose KV cache layer {layer} reads"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl EngineConfig {
+    pub fn with_threads(num_threads: usize) -> Self {
+        Self {
+            num_threads: Some(num_threads),
+            ..Self::default()
+        }
+    }
+
+    /// A dedicated pool when `num_threads` or `thread_affinity` is set, so capping one session
+    /// does not touch the global pool other code in the process may share. Sessions with the
+    /// same settings get the same pool ([`shared_pool`]), built on first use.
+    ///
+    /// WebAssembly without the `atomics` target feature cannot start threads; there the settings
+    /// are ignored and everything runs on the calling thread (rayon's global pool does the same).
+    pub fn build_thread_pool(&self) -> Result<Option<Arc<ThreadPool>>, EngineError> {
+        if self.num_threads.is_none() && self.thread_affinity == ThreadAffinity::None {
+            return Ok(None);
+        }
+        if cfg!(all(target_arch = "wasm32", not(target_feature = "atomics"))) {
+            log::warn!("this wasm32 build has no threads; running single-threaded");
+            return Ok(None);
+        }
+        shared_pool(self.num_threads, self.thread_affinity).map(Some)
+    }
+}
+
+/// Run `op` inside `pool` if there is one, otherwise on the current (usually global) pool.
+pub(crate) fn install<T: Send>(pool: Option<&ThreadPool>, op: impl FnOnce() -> T + Send) -> T {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_pool_size_follows_config() {
+        assert!(
+            EngineConfig::default()
+                .build_thread_pool()
+                .unwrap()
+                .is_none()
+        );
+        let pool = EngineConfig::with_threads(2).build_thread_pool().unwrap();
+        let pool = pool.expect("pool for explicit thread count");
+        assert_eq!(pool.current_num_threads(), 2);
+        assert_eq!(install(Some(&pool), rayon::current_num_threads), 2);
+        assert!(EngineConfig::with_threads(0).build_thread_pool().is_err());
+        let again = EngineConfig::with_threads(2).build_thread_pool().unwrap();
+        assert!(Arc::ptr_eq(&pool, &again.unwrap()));
+    }
+}
@@ -0,0 +1,178 @@
+//! Wall-clock limits for generation ([`GenerationConfig::deadline`]).
+//!
+//! Time is read from the session's [`Clock`] ([`InferenceSession::set_clock`]), a monotonic
+//! source that tests replace with a [`ManualClock`]. The generation loops check the deadline
+//! before every forward pass: each prefill chunk (the prompt is prefilled
+//! [`DEADLINE_PREFILL_CHUNK`] tokens at a time while a deadline is set) and each decode step. A
+//! forward pass that has started always finishes, so a run overshoots by at most one chunk or
+//! step. Tokens whose logits are already computed are still sampled.
+//!
+//! [`GenerationConfig::min_tokens`] keeps generation going past the deadline until that many
+//! tokens exist, but never past [`DEADLINE_HARD_MULTIPLE`] times the deadline.
+//!
+//! [`InferenceSession::set_clock`]: crate::engine::session::InferenceSession::set_clock
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::core::time::Instant;
+use crate::engine::generation::GenerationConfig;
+
+/// Prompt tokens per prefill chunk while a deadline is set.
+pub const DEADLINE_PREFILL_CHUNK: usize = 32;
+
+/// [`GenerationConfig::min_tokens`] may extend a run up to this many deadlines.
+pub const DEADLINE_HARD_MULTIPLE: u32 = 2;
+
+/// Monotonic time source.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// [`Instant::now`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to: by [`Self::advance`], and by a fixed tick on every
+/// [`Clock::now`] call if built with [`Self::ticking`] (so each deadline check costs the same
+/// simulated time, whatever the real forward passes take).
+#[derive(Debug)]
+pub struct ManualClock {
+    origin: Instant,
+    elapsed_nanos: AtomicU64,
+    tick_nanos: u64,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::ticking(Duration::ZERO)
+    }
+
+    pub fn ticking(tick: Duration) -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed_nanos: AtomicU64::new(0),
+            tick_nanos: nanos(tick),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed_nanos.fetch_add(nanos(by), Ordering::Relaxed);
+    }
+
+    /// Simulated time since construction.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        let elapsed = self
+            .elapsed_nanos
+            .fetch_add(self.tick_nanos, Ordering::Relaxed);
+        self.origin + Duration::from_nanos(elapsed)
+    }
+}
+
+fn nanos(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// A running deadline: started when generation starts, asked before each forward pass.
+pub(crate) struct DeadlineTimer {
+    clock: Arc<dyn Clock>,
+    start: Instant,
+    soft: Duration,
+    hard: Duration,
+    min_tokens: usize,
+}
+
+impl DeadlineTimer {
+    /// `None` without [`GenerationConfig::deadline`].
+    pub(crate) fn start(clock: Arc<dyn Clock>, config: &GenerationConfig) -> Option<Self> {
+        let soft = config.deadline?;
+        let hard = if config.min_tokens > 0 {
+            soft.saturating_mul(DEADLINE_HARD_MULTIPLE)
+        } else {
+            soft
+        };
+        Some(Self {
+            start: clock.now(),
+            clock,
+            soft,
+            hard,
+            min_tokens: config.min_tokens,
+        })
+    }
+
+    /// Whether to stop with `produced` tokens generated so far. The deadline is reached when
+    /// the elapsed time equals it.
+    pub(crate) fn expired(&self, produced: usize) -> bool {
+        let elapsed = self.clock.now().saturating_duration_since(self.start);
+        elapsed >= self.hard || (elapsed >= self.soft && produced >= self.min_tokens)
+    }
+}
+
+/// [`DeadlineTimer::expired`] for an optional timer.
+pub(crate) fn expired(timer: Option<&DeadlineTimer>, produced: usize) -> bool {
+    timer.is_some_and(|t| t.expired(produced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(clock: &Arc<ManualClock>, deadline_ms: u64, min_tokens: usize) -> DeadlineTimer {
+        let config = GenerationConfig {
+            deadline: Some(Duration::from_millis(deadline_ms)),
+            min_tokens,
+            ..GenerationConfig::default()
+        };
+        DeadlineTimer::start(clock.clone(), &config).unwrap()
+    }
+
+    #[test]
+    fn deadline_is_reached_at_equality() {
+        let clock = Arc::new(ManualClock::new());
+        let t = timer(&clock, 10, 0);
+        clock.advance(Duration::from_millis(9));
+        assert!(!t.expired(0));
+        clock.advance(Duration::from_millis(1));
+        assert!(t.expired(0));
+    }
+
+    #[test]
+    fn min_tokens_extend_up_to_the_hard_limit() {
+        let clock = Arc::new(ManualClock::new());
+        let t = timer(&clock, 10, 3);
+        clock.advance(Duration::from_millis(15));
+        assert!(!t.expired(2));
+        assert!(t.expired(3));
+        clock.advance(Duration::from_millis(5));
+        assert!(t.expired(0));
+    }
+
+    #[test]
+    fn ticking_clock_advances_per_read() {
+        let clock = ManualClock::ticking(Duration::from_millis(2));
+        let a = clock.now();
+        let b = clock.now();
+        assert_eq!(b - a, Duration::from_millis(2));
+        assert_eq!(clock.elapsed(), Duration::from_millis(4));
+        assert!(DeadlineTimer::start(Arc::new(clock), &GenerationConfig::default()).is_none());
+    }
+}
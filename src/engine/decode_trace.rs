@@ -0,0 +1,174 @@
+//! Per-token decode trace for watching a generation step by step (`--verbose-decode`).
+//!
+//! A [`DecodeStep`] records what was emitted (id, text, logprob), the [`TRACE_ALTERNATIVES`] most
+//! likely other tokens, the time since generation started, and which sampling stage, if any,
+//! picked something other than the argmax, as the sampler reported it ([`SamplingStage`]). It
+//! renders as one aligned table row ([`fmt::Display`], with [`escape_token_text`] keeping newlines
+//! and control characters on the line) or as one JSON object per line
+//! ([`DecodeStep::to_json_line`]).
+//!
+//! Min-p never moves the choice on its own, there are no penalty or bias processors, and grammar
+//! constraints ([`GenerationConfig::json_schema`](crate::engine::generation::GenerationConfig::json_schema))
+//! are not available on the traced path, so in practice [`DecodeStep::changed_by`] is
+//! `"temperature"` or nothing.
+
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+use crate::engine::generation::logprob_or_err;
+use crate::engine::sampling::{SamplingStage, top_candidates};
+use crate::tokenizer::Tokenizer;
+
+/// Alternatives listed per step.
+pub const TRACE_ALTERNATIVES: usize = 3;
+
+/// Columns [`escape_token_text`]'d token text is padded to in the table.
+const TEXT_WIDTH: usize = 16;
+
+/// A token the model ranked highly at a step but did not emit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alternative {
+    pub id: u32,
+    /// The piece on its own (unescaped).
+    pub text: String,
+    pub logprob: f32,
+}
+
+/// One generated token; see the module docs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecodeStep {
+    /// 0-based position among the generated tokens.
+    pub index: usize,
+    pub id: u32,
+    /// Text this token adds (unescaped); empty while a multi-byte character is incomplete.
+    pub text: String,
+    /// Log-probability under the unscaled logits.
+    pub logprob: f32,
+    /// The most likely tokens other than `id`, most likely first.
+    pub alternatives: Vec<Alternative>,
+    /// Time from the start of generation (including prefill) to this token.
+    pub elapsed_ms: f64,
+    /// [`SamplingStage::name`] of the stage that moved the choice off the argmax.
+    pub changed_by: Option<String>,
+}
+
+impl DecodeStep {
+    /// Trace `id`, sampled from `logits`; `changed_by` is the stage the sampler reported as
+    /// moving the choice off the argmax. `text` is what the token adds to the output (e.g. from
+    /// [`crate::tokenizer::IncrementalDecoder`]); alternatives are decoded one piece at a time
+    /// with `tokenizer`.
+    pub fn record(
+        index: usize,
+        logits: &[f32],
+        id: u32,
+        text: String,
+        tokenizer: &Tokenizer,
+        changed_by: Option<SamplingStage>,
+        elapsed: Duration,
+    ) -> Result<Self, EngineError> {
+        let alternatives = top_candidates(logits, TRACE_ALTERNATIVES + 1)
+            .into_iter()
+            .filter(|&(alt, _)| alt != id)
+            .take(TRACE_ALTERNATIVES)
+            .map(|(alt, prob)| {
+                Ok(Alternative {
+                    id: alt,
+                    text: tokenizer.decode_piece_ids(&[alt])?,
+                    logprob: prob.ln(),
+                })
+            })
+            .collect::<Result<_, EngineError>>()?;
+        Ok(Self {
+            index,
+            id,
+            text,
+            logprob: logprob_or_err(logits, id)?,
+            alternatives,
+            elapsed_ms: elapsed.as_secs_f64() * 1e3,
+            changed_by: changed_by.map(|stage| stage.name().to_string()),
+        })
+    }
+
+    /// The step as a single line of JSON (no trailing newline).
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("decode step serializes")
+    }
+}
+
+/// Header matching [`DecodeStep`]'s table rows.
+pub fn table_header() -> String {
+    format!(
+        "{:>5} {:>7} {:<TEXT_WIDTH$} {:>8} {:>10}  {:<11} alternatives",
+        "step", "id", "text", "logprob", "ms", "changed by"
+    )
+}
+
+/// `step  id  "text"  logprob  ms  changed-by  alternatives`, padded to [`table_header`].
+impl fmt::Display for DecodeStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = format!("\"{}\"", escape_token_text(&self.text));
+        write!(
+            f,
+            "{:>5} {:>7} {text:<TEXT_WIDTH$} {:>8.3} {:>10.1}  {:<11}",
+            self.index,
+            self.id,
+            self.logprob,
+            self.elapsed_ms,
+            self.changed_by.as_deref().unwrap_or("-")
+        )?;
+        for (i, alt) in self.alternatives.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(
+                f,
+                "{sep}\"{}\" ({}) {:.3}",
+                escape_token_text(&alt.text),
+                alt.id,
+                alt.logprob
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `text` on one line: `\n`, `\r`, `\t`, `\\` and `"` as their escapes, other control
+/// characters as `\u{..}`. Printable text, including non-ASCII, is left as is.
+pub fn escape_token_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping_keeps_the_token_on_one_line() {
+        assert_eq!(escape_token_text("a\nb\r\tc"), "a\\nb\\r\\tc");
+        assert_eq!(escape_token_text("\u{1b}[0m\0"), "\\u{1b}[0m\\u{0}");
+        assert_eq!(
+            escape_token_text("say \"hi\" \\ ünï"),
+            "say \\\"hi\\\" \\\\ ünï"
+        );
+        assert!(
+            !escape_token_text("\u{7f}\u{85}")
+                .chars()
+                .any(char::is_control)
+        );
+    }
+}
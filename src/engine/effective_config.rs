@@ -0,0 +1,336 @@
+//! The [`GenerationConfig`] a run actually uses, merged from every source, with where each field
+//! came from.
+//!
+//! Sources are [`GenerationSettings`] layers, each tagged with a [`Provenance`]. Precedence is
+//! the [`Provenance`] order, lowest first:
+//!
+//! 1. [`Provenance::Default`], from [`GenerationConfig::default`]
+//! 2. [`Provenance::Metadata`], the model's `general.sampling.*` keys
+//!    ([`GenerationSettings::from_metadata`])
+//! 3. [`Provenance::Auto`], values the engine derived (e.g. from available memory)
+//! 4. [`Provenance::UserConfig`], an application's saved settings
+//! 5. [`Provenance::CliFlag`], command-line flags
+//! 6. [`Provenance::Override`], values the caller forces (e.g. a greedy-only front end)
+//!
+//! Of two layers with the same provenance, the later one wins. Combinations that cannot run are
+//! rejected by [`EffectiveConfig::resolve`], naming the source of each conflicting value.
+//!
+//! The KV cache dtype is an engine setting, not a generation one; it is listed too once
+//! [`EffectiveConfig::record_kv_cache`] has been given the [`KvCachePolicy`] decision.
+//!
+//! [`KvCachePolicy`]: crate::engine::kv_policy::KvCachePolicy
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::EngineError;
+use crate::engine::generation::{ConfigError, GenerationConfig, GenerationOutput, generate};
+use crate::engine::guidance::Guidance;
+use crate::engine::json_schema::JsonSchema;
+use crate::engine::kv_policy::KvCacheChoice;
+use crate::engine::quality::QualityThresholds;
+use crate::engine::session::InferenceSession;
+use crate::layers::attention::CacheDtype;
+use crate::model_loader::gguf_types::{Data, GGUFData};
+use crate::tokenizer::Tokenizer;
+
+/// Where a setting came from; later variants take precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Provenance {
+    Default,
+    Metadata,
+    Auto,
+    UserConfig,
+    CliFlag,
+    Override,
+}
+
+impl Provenance {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provenance::Default => "default",
+            Provenance::Metadata => "metadata",
+            Provenance::Auto => "auto",
+            Provenance::UserConfig => "user config",
+            Provenance::CliFlag => "cli flag",
+            Provenance::Override => "override",
+        }
+    }
+}
+
+/// One source's settings: `None` leaves the field to lower-precedence sources.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationSettings {
+    pub max_new_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    pub min_p: Option<f32>,
+    pub seed: Option<u64>,
+    pub stop_token_ids: Option<Vec<u32>>,
+    pub guidance: Option<Guidance>,
+    pub deadline: Option<Duration>,
+    pub min_tokens: Option<usize>,
+    pub json_schema: Option<JsonSchema>,
+    pub quality: Option<QualityThresholds>,
+}
+
+impl GenerationSettings {
+    /// The recommended sampling settings some GGUF files carry (`general.sampling.temp`,
+    /// `general.sampling.min_p`, `general.sampling.seed`).
+    pub fn from_metadata(gguf: &GGUFData) -> Self {
+        let float = |key: &str| match gguf.get_metadata(key)? {
+            Data::Float32(v) => Some(*v),
+            Data::Float64(v) => Some(*v as f32),
+            _ => None,
+        };
+        let seed = match gguf.get_metadata("general.sampling.seed") {
+            Some(Data::Uint32(v)) => Some(u64::from(*v)),
+            Some(Data::Uint64(v)) => Some(*v),
+            _ => None,
+        };
+        Self {
+            temperature: float("general.sampling.temp"),
+            min_p: float("general.sampling.min_p"),
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+/// Field names of [`GenerationConfig`], in listing order.
+pub const FIELDS: [&str; 10] = [
+    "max_new_tokens",
+    "temperature",
+    "min_p",
+    "seed",
+    "stop_token_ids",
+    "guidance",
+    "deadline",
+    "min_tokens",
+    "json_schema",
+    "quality",
+];
+
+/// Name under which [`EffectiveConfig::record_kv_cache`] lists the KV cache dtype.
+pub const KV_CACHE_FIELD: &str = "kv_cache_dtype";
+
+/// A merged [`GenerationConfig`] that cannot run, naming the layer each offending value came
+/// from.
+#[derive(Debug, Error, Clone, Copy, PartialEq)]
+pub enum EffectiveConfigError {
+    #[error(
+        "{} {} (from {}) must be {}",
+        .error.field(),
+        .error.value(),
+        .from.name(),
+        .error.allowed()
+    )]
+    OutOfRange {
+        error: ConfigError,
+        from: Provenance,
+    },
+    #[error(
+        "min_tokens {min_tokens} (from {}) exceeds max_new_tokens {max_new_tokens} (from {})",
+        .min_tokens_from.name(),
+        .max_new_tokens_from.name()
+    )]
+    MinTokensAboveMax {
+        min_tokens: usize,
+        min_tokens_from: Provenance,
+        max_new_tokens: usize,
+        max_new_tokens_from: Provenance,
+    },
+}
+
+/// A resolved [`GenerationConfig`] and the [`Provenance`] of each of its fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveConfig {
+    pub config: GenerationConfig,
+    /// Set by [`Self::record_kv_cache`].
+    pub kv_cache_dtype: Option<CacheDtype>,
+    provenance: BTreeMap<&'static str, Provenance>,
+}
+
+impl EffectiveConfig {
+    /// Merge `layers` over the defaults in precedence order and validate the result.
+    pub fn resolve(
+        layers: &[(Provenance, GenerationSettings)],
+    ) -> Result<Self, EffectiveConfigError> {
+        let mut config = GenerationConfig::default();
+        let mut provenance: BTreeMap<&'static str, Provenance> =
+            FIELDS.iter().map(|&f| (f, Provenance::Default)).collect();
+        let mut ordered: Vec<&(Provenance, GenerationSettings)> = layers.iter().collect();
+        ordered.sort_by_key(|(p, _)| *p);
+
+        for (source, layer) in ordered {
+            let mut set = |field: &'static str| {
+                provenance.insert(field, *source);
+            };
+            if let Some(v) = layer.max_new_tokens {
+                config.max_new_tokens = v;
+                set("max_new_tokens");
+            }
+            if let Some(v) = layer.temperature {
+                config.temperature = v;
+                set("temperature");
+            }
+            if let Some(v) = layer.min_p {
+                config.min_p = v;
+                set("min_p");
+            }
+            if let Some(v) = layer.seed {
+                config.seed = v;
+                set("seed");
+            }
+            if let Some(v) = &layer.stop_token_ids {
+                config.stop_token_ids = v.clone();
+                set("stop_token_ids");
+            }
+            if let Some(v) = &layer.guidance {
+                config.guidance = Some(v.clone());
+                set("guidance");
+            }
+            if let Some(v) = layer.deadline {
+                config.deadline = Some(v);
+                set("deadline");
+            }
+            if let Some(v) = layer.min_tokens {
+                config.min_tokens = v;
+                set("min_tokens");
+            }
+            if let Some(v) = &layer.json_schema {
+                config.json_schema = Some(v.clone());
+                set("json_schema");
+            }
+            if let Some(v) = layer.quality {
+                config.quality = Some(v);
+                set("quality");
+            }
+        }
+        let resolved = Self {
+            config,
+            kv_cache_dtype: None,
+            provenance,
+        };
+        resolved.validate()?;
+        Ok(resolved)
+    }
+
+    /// Source of `field` (one of [`FIELDS`], or [`KV_CACHE_FIELD`] once recorded).
+    pub fn provenance(&self, field: &str) -> Option<Provenance> {
+        self.provenance.get(field).copied()
+    }
+
+    /// List the KV cache dtype the session will use, with the choice's provenance.
+    pub fn record_kv_cache(&mut self, choice: &KvCacheChoice) {
+        self.kv_cache_dtype = Some(choice.dtype);
+        self.provenance.insert(KV_CACHE_FIELD, choice.provenance);
+    }
+
+    fn validate(&self) -> Result<(), EffectiveConfigError> {
+        let c = &self.config;
+        let origin = |field: &str| self.provenance(field).unwrap_or(Provenance::Default);
+        if let Err(error) = c.validate() {
+            let from = origin(error.field().split('.').next().unwrap_or_default());
+            return Err(EffectiveConfigError::OutOfRange { error, from });
+        }
+        if c.min_tokens > c.max_new_tokens {
+            return Err(EffectiveConfigError::MinTokensAboveMax {
+                min_tokens: c.min_tokens,
+                min_tokens_from: origin("min_tokens"),
+                max_new_tokens: c.max_new_tokens,
+                max_new_tokens_from: origin("max_new_tokens"),
+            });
+        }
+        Ok(())
+    }
+
+    fn value(&self, field: &str) -> String {
+        let c = &self.config;
+        match field {
+            "max_new_tokens" => c.max_new_tokens.to_string(),
+            "temperature" => c.temperature.to_string(),
+            "min_p" => c.min_p.to_string(),
+            "seed" => c.seed.to_string(),
+            "stop_token_ids" => format!("{:?}", c.stop_token_ids),
+            "guidance" => c.guidance.as_ref().map_or("none".into(), |g| {
+                format!("{:?} x {}", g.negative_prompt, g.scale)
+            }),
+            "deadline" => c.deadline.map_or("none".into(), |d| format!("{d:?}")),
+            "min_tokens" => c.min_tokens.to_string(),
+            "json_schema" => c
+                .json_schema
+                .as_ref()
+                .map_or("none".into(), |s| s.source().to_string()),
+            "quality" => c.quality.map_or("none".into(), |q| {
+                let limit = |v: Option<f32>| v.map_or("none".into(), |v| v.to_string());
+                format!(
+                    "window {}, surprise {}, entropy {}",
+                    q.window,
+                    limit(q.mean_surprise),
+                    limit(q.mean_entropy)
+                )
+            }),
+            KV_CACHE_FIELD => self.kv_cache_dtype.map_or("", CacheDtype::name).to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// One line per field: `name = value  (source)`.
+impl fmt::Display for EffectiveConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kv_cache = self.kv_cache_dtype.map(|_| KV_CACHE_FIELD);
+        for (i, field) in FIELDS.iter().chain(&kv_cache).enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let source = self.provenance(field).map_or("?", Provenance::name);
+            write!(f, "{field:<15} = {:<20} ({source})", self.value(field))?;
+        }
+        Ok(())
+    }
+}
+
+/// [`generate`] with `effective.config`, attaching `effective` to the output.
+pub fn generate_effective(
+    session: &mut InferenceSession<'_>,
+    tokenizer: &mut Tokenizer,
+    prompt: &str,
+    effective: &EffectiveConfig,
+) -> Result<GenerationOutput, EngineError> {
+    let mut out = generate(session, tokenizer, prompt, &effective.config)?;
+    out.effective_config = Some(effective.clone());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_names_every_field_with_its_source() {
+        let effective = EffectiveConfig::resolve(&[(
+            Provenance::CliFlag,
+            GenerationSettings {
+                max_new_tokens: Some(7),
+                ..GenerationSettings::default()
+            },
+        )])
+        .unwrap();
+        let listing = effective.to_string();
+        assert_eq!(listing.lines().count(), FIELDS.len());
+        assert!(
+            listing
+                .lines()
+                .next()
+                .unwrap()
+                .starts_with("max_new_tokens  = 7"),
+            "{listing}"
+        );
+        assert!(listing.lines().next().unwrap().ends_with("(cli flag)"));
+        assert!(listing.contains("(default)"));
+    }
+}
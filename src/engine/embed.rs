@@ -19,7 +19,7 @@ pub fn prefill_from_tokens(
     token_ids: &[u32],
 ) -> Result<ForwardState, EngineError> {
     if token_ids.is_empty() {
-        return Err(EngineError::Model("prefill: empty token list".into()));
+        return Err(EngineError::EmptyPrompt("prefill: empty token list".into()));
     }
     if token_ids.len() > config.context_length {
         return Err(EngineError::Model(format!(
@@ -107,7 +107,7 @@ pub fn prefill_from_tokens_loaded(
     token_ids: &[u32],
 ) -> Result<ForwardState, EngineError> {
     if token_ids.is_empty() {
-        return Err(EngineError::Model("prefill: empty token list".into()));
+        return Err(EngineError::EmptyPrompt("prefill: empty token list".into()));
     }
     if token_ids.len() > config.context_length {
         return Err(EngineError::Model(format!(
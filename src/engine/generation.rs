@@ -1,9 +1,33 @@
+use std::fmt;
+use std::time::Duration;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
 use crate::EngineError;
-use crate::engine::sampling::sample_greedy;
+use crate::core::time::Instant;
+use crate::engine::deadline::{DEADLINE_PREFILL_CHUNK, DeadlineTimer, expired};
+use crate::engine::effective_config::EffectiveConfig;
+use crate::engine::grammar::TokenConstraint;
+use crate::engine::guidance::Guidance;
+use crate::engine::json_schema::JsonSchema;
+use crate::engine::observer::{
+    EngineFailed, GenerationFinished, GenerationStarted, Operation, PrefillProgress, TokenGenerated,
+};
+use crate::engine::quality::{QualityMonitor, QualityStats, QualityThresholds};
+use crate::engine::sampling::{
+    SamplingStage, argmax_index, sample_greedy, sample_min_p, sample_temperature, token_logprob,
+};
 use crate::engine::session::InferenceSession;
 use crate::engine::state::ForwardState;
+use crate::engine::transcript::Transcript;
+use crate::mem_profile::{MemoryStats, memory_stats};
+use crate::model_config::TokenizerPromptConfig;
+use crate::tokenizer::Tokenizer;
 
-/// Choose the next token greedily from the session's last-token logits.
+/// Choose the next token greedily from the session's last-token logits (padding rows excluded).
 ///
 /// This is intentionally one-step policy only. Callers still own loop behavior,
 /// stop criteria, streaming, and text postprocessing.
@@ -11,6 +35,639 @@ pub fn greedy_next_token(
     session: &InferenceSession<'_>,
     state: &ForwardState,
 ) -> Result<u32, EngineError> {
-    let logits = session.logits_last_token(state)?;
+    let logits = session.next_token_logits(state)?;
     sample_greedy(&logits).map_err(EngineError::from)
 }
+
+/// Counters a generation loop fills in as it goes (see `src/main.rs`); the library loops report
+/// theirs in [`GenerationOutput::stats`], [`TokenIter::stats`](crate::engine::token_iter::TokenIter::stats)
+/// and [`StreamEnd::stats`](crate::engine::text_stream::StreamEnd::stats).
+///
+/// Memory snapshots are `None` unless the crate is built with the `mem-profile` feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationStats {
+    pub prompt_tokens: usize,
+    pub generated_tokens: usize,
+    /// After weights are resident, before any KV cache or activations exist.
+    pub memory_post_load: Option<MemoryStats>,
+    /// After prompt prefill (KV cache populated for the prompt).
+    pub memory_post_prefill: Option<MemoryStats>,
+    /// After the last decode step; its `peak_bytes` is the whole-run high-water mark.
+    pub memory_decode: Option<MemoryStats>,
+    /// Surprise and entropy of the sampled tokens, from a [`QualityMonitor`] the loop fed;
+    /// `None` unless [`GenerationConfig::quality`] (or `--quality`) asked for it.
+    pub quality: Option<QualityStats>,
+}
+
+impl GenerationStats {
+    pub fn sample_post_load(&mut self) {
+        self.memory_post_load = memory_stats();
+    }
+
+    pub fn sample_post_prefill(&mut self) {
+        self.memory_post_prefill = memory_stats();
+    }
+
+    pub fn sample_decode(&mut self) {
+        self.memory_decode = memory_stats();
+    }
+
+    /// Highest `peak_bytes` across the recorded snapshots.
+    pub fn peak_bytes(&self) -> Option<usize> {
+        [
+            self.memory_post_load,
+            self.memory_post_prefill,
+            self.memory_decode,
+        ]
+        .iter()
+        .flatten()
+        .map(|s| s.peak_bytes)
+        .max()
+    }
+}
+
+/// Decoding policy for [`generate`] and [`generate_with_forced_prefix`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub max_new_tokens: usize,
+    /// `0.0` picks the argmax; a positive value samples from `softmax(logits / temperature)`.
+    /// Negative or non-finite values are rejected by [`Self::validate`].
+    pub temperature: f32,
+    /// With a positive temperature, drop tokens below `min_p * max_prob` first (see
+    /// [`sample_min_p`]); `0.0` disables the filter and `1.0` keeps only the most likely
+    /// tokens. Must be in `[0, 1]`.
+    pub min_p: f32,
+    /// Seed for the sampling RNG (unused when greedy).
+    pub seed: u64,
+    /// Stop ids in addition to the model's terminators (EOS plus any EOT / EOM ids, see
+    /// [`TokenizerPromptConfig::terminator_ids`]). The stop token itself is not emitted.
+    pub stop_token_ids: Vec<u32>,
+    /// Classifier-free guidance against a negative prompt. Needs a second context, so only
+    /// [`crate::engine::guidance::GuidedSession`] honours it; the single-session entry points
+    /// reject it rather than silently ignore it.
+    pub guidance: Option<Guidance>,
+    /// Wall-clock limit from the start of generation; when it passes, generation ends with
+    /// [`FinishReason::DeadlineExceeded`] and whatever was produced (see
+    /// [`crate::engine::deadline`]). `None`: no limit.
+    #[serde(default)]
+    pub deadline: Option<Duration>,
+    /// Tokens to produce before [`Self::deadline`] may end generation, within
+    /// [`crate::engine::deadline::DEADLINE_HARD_MULTIPLE`] deadlines. Stop tokens and
+    /// `max_new_tokens` still end it earlier; without a deadline this has no effect.
+    #[serde(default)]
+    pub min_tokens: usize,
+    /// Constrain the sampled text to JSON matching this schema (see
+    /// [`crate::engine::json_schema`]) and validate it once generation stops on a stop token.
+    /// Needs the tokenizer, so only the text entry points ([`generate`],
+    /// [`generate_with_forced_prefix`], [`crate::engine::chat_session::ChatSession`]) honour it.
+    #[serde(default)]
+    pub json_schema: Option<JsonSchema>,
+    /// Track how surprised the model is by its samples ([`crate::engine::quality`]) into
+    /// [`GenerationStats::quality`], and report threshold crossings to the session's observer.
+    /// Honoured by [`generate_from_ids`], the text entry points built on it,
+    /// [`crate::engine::token_iter::TokenIter`] and guided generation. `None`: off, and no
+    /// per-step cost.
+    #[serde(default)]
+    pub quality: Option<QualityThresholds>,
+}
+
+impl GenerationConfig {
+    /// Check every sampling value is in range, so a bad one fails here, naming the field,
+    /// instead of as NaN logits or an empty candidate set mid-generation. Every generation entry
+    /// point calls this first. Guidance scales above
+    /// [`MAX_GUIDANCE_SCALE`](crate::engine::guidance::MAX_GUIDANCE_SCALE) are valid; they are
+    /// clamped when applied.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.temperature >= 0.0 && self.temperature.is_finite()) {
+            return Err(ConfigError::Temperature(self.temperature));
+        }
+        if !(0.0..=1.0).contains(&self.min_p) {
+            return Err(ConfigError::MinP(self.min_p));
+        }
+        if let Some(guidance) = &self.guidance
+            && !(guidance.scale >= 0.0 && guidance.scale.is_finite())
+        {
+            return Err(ConfigError::GuidanceScale(guidance.scale));
+        }
+        Ok(())
+    }
+
+    /// Set [`Self::json_schema`] from a schema document.
+    pub fn json_schema(mut self, schema: &str) -> Result<Self, EngineError> {
+        self.json_schema = Some(JsonSchema::parse(schema)?);
+        Ok(self)
+    }
+
+    /// The model's terminators plus [`Self::stop_token_ids`].
+    pub fn stop_tokens(&self, prompt: &TokenizerPromptConfig) -> StopTokens {
+        StopTokens::new(
+            prompt
+                .terminator_ids()
+                .chain(self.stop_token_ids.iter().copied()),
+        )
+    }
+}
+
+/// A [`GenerationConfig`] value outside its allowed range. Displays as
+/// `<field> <value> must be <allowed>`.
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum ConfigError {
+    Temperature(f32),
+    MinP(f32),
+    GuidanceScale(f32),
+}
+
+impl ConfigError {
+    /// The offending field, as named in [`GenerationConfig`].
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::Temperature(_) => "temperature",
+            Self::MinP(_) => "min_p",
+            Self::GuidanceScale(_) => "guidance.scale",
+        }
+    }
+
+    /// The value given.
+    pub fn value(&self) -> f32 {
+        match *self {
+            Self::Temperature(v) | Self::MinP(v) | Self::GuidanceScale(v) => v,
+        }
+    }
+
+    /// The allowed range and what its boundary values mean.
+    pub fn allowed(&self) -> &'static str {
+        match self {
+            Self::Temperature(_) => "finite and >= 0 (0 = greedy)",
+            Self::MinP(_) => "in [0, 1] (0 = disabled)",
+            Self::GuidanceScale(_) => "finite and >= 0 (0 = disabled)",
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} must be {}",
+            self.field(),
+            self.value(),
+            self.allowed()
+        )
+    }
+}
+
+/// Token ids that end generation, kept sorted and deduplicated so the per-token membership check
+/// is a binary search over a handful of ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StopTokens(Vec<u32>);
+
+impl StopTokens {
+    pub fn new(ids: impl IntoIterator<Item = u32>) -> Self {
+        let mut ids: Vec<u32> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        Self(ids)
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.0.binary_search(&id).is_ok()
+    }
+
+    /// Ascending.
+    pub fn ids(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+/// Why a generation stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FinishReason {
+    /// `max_new_tokens` were generated.
+    #[default]
+    Length,
+    /// `token_id` was sampled and is one of the [`StopTokens`] (EOS, an EOT / EOM id, or a
+    /// caller's stop id). It is not part of the output.
+    Eos { token_id: u32 },
+    /// [`GenerationConfig::deadline`] passed; the output holds the tokens produced before it
+    /// (possibly none, if it passed during prefill).
+    DeadlineExceeded,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            max_new_tokens: 64,
+            temperature: 0.0,
+            min_p: 0.0,
+            seed: 0,
+            stop_token_ids: Vec::new(),
+            guidance: None,
+            deadline: None,
+            min_tokens: 0,
+            json_schema: None,
+            quality: None,
+        }
+    }
+}
+
+/// Tokens and text of one generation: a forced span (fed, never sampled) followed by the
+/// sampled span. `text[..forced_text_len]` is the forced part.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationOutput {
+    pub prompt_tokens: usize,
+    pub forced_token_ids: Vec<u32>,
+    /// Log-probability of each forced token given everything before it.
+    pub forced_logprobs: Vec<f32>,
+    pub generated_token_ids: Vec<u32>,
+    /// Log-probability of each sampled token under the unscaled logits.
+    pub generated_logprobs: Vec<f32>,
+    pub text: String,
+    pub forced_text_len: usize,
+    pub finish_reason: FinishReason,
+    /// The resolved settings and their sources, when run through
+    /// [`crate::engine::effective_config::generate_effective`].
+    pub effective_config: Option<EffectiveConfig>,
+    /// Token counts of the run and, with [`GenerationConfig::quality`], the quality aggregates
+    /// over the sampled span.
+    pub stats: GenerationStats,
+}
+
+impl GenerationOutput {
+    pub fn forced_text(&self) -> &str {
+        &self.text[..self.forced_text_len]
+    }
+
+    pub fn generated_text(&self) -> &str {
+        &self.text[self.forced_text_len..]
+    }
+
+    /// Decode forced + generated ids together (so joins between the spans render as the model
+    /// would) and record where the forced span ends.
+    fn decode_text(&mut self, tokenizer: &Tokenizer) -> Result<(), EngineError> {
+        let forced = tokenizer.decode_piece_ids(&self.forced_token_ids)?;
+        let all: Vec<u32> = self
+            .forced_token_ids
+            .iter()
+            .chain(&self.generated_token_ids)
+            .copied()
+            .collect();
+        let full = tokenizer.decode_piece_ids(&all)?;
+        self.text = if full.starts_with(&forced) {
+            full
+        } else {
+            forced.clone() + &tokenizer.decode_piece_ids(&self.generated_token_ids)?
+        };
+        self.forced_text_len = forced.len();
+        Ok(())
+    }
+}
+
+/// Encode `prompt` (with the model's BOS/EOS policy) and sample a continuation.
+pub fn generate(
+    session: &mut InferenceSession<'_>,
+    tokenizer: &mut Tokenizer,
+    prompt: &str,
+    config: &GenerationConfig,
+) -> Result<GenerationOutput, EngineError> {
+    generate_with_forced_prefix(session, tokenizer, prompt, "", config)
+}
+
+/// Like [`generate`], but the reply is forced to start with `forced_prefix` (e.g.
+/// `"Sure, here is the JSON:"`). The prefix tokens go through the model in the same prefill as
+/// the prompt, so they occupy context and get logprobs, but are never sampled.
+///
+/// The prefix is encoded on its own, without BOS; for chat, pass the prompt rendered up to and
+/// including the assistant role header (see [`crate::engine::chat_session::ChatSession`]).
+///
+/// With [`GenerationConfig::json_schema`], every sampled token must keep the generated text (the
+/// prefix is not part of it) a prefix of schema-valid JSON, and stop tokens are allowed only once
+/// it is complete. A reply that ends on a stop token is validated and fails with
+/// [`JsonSchemaError::Violations`](crate::engine::json_schema::JsonSchemaError::Violations) if
+/// it does not match; one cut short by `max_new_tokens` or the deadline is returned as is.
+pub fn generate_with_forced_prefix(
+    session: &mut InferenceSession<'_>,
+    tokenizer: &mut Tokenizer,
+    prompt: &str,
+    forced_prefix: &str,
+    config: &GenerationConfig,
+) -> Result<GenerationOutput, EngineError> {
+    let prompt_ids =
+        tokenizer.encode_with_prompt_config(prompt, session.model().tokenizer_prompt())?;
+    let forced_ids = if forced_prefix.is_empty() {
+        Vec::new()
+    } else {
+        tokenizer.encode(forced_prefix)?
+    };
+    let constraint = match &config.json_schema {
+        Some(schema) => Some(schema.token_constraint(tokenizer)?),
+        None => None,
+    };
+    let mut out = generate_constrained(session, &prompt_ids, &forced_ids, config, constraint)?;
+    out.decode_text(tokenizer)?;
+    if let (Some(schema), FinishReason::Eos { .. }) = (&config.json_schema, out.finish_reason) {
+        schema.validate(out.generated_text())?;
+    }
+    Ok(out)
+}
+
+/// Token-level core of [`generate_with_forced_prefix`]; `text` is left empty.
+///
+/// Resets the session first: the KV cache ends up holding prompt + forced + generated tokens
+/// (except the last sampled one, which is never fed back), less whatever a
+/// [`ContextShift`](crate::engine::budget::ContextShift) dropped. Writes a transcript when the
+/// session has one set ([`InferenceSession::set_transcript`]) and reports progress to its
+/// observer ([`crate::engine::observer`]).
+///
+/// With [`GenerationConfig::deadline`], a deadline reached during prefill returns no tokens and
+/// no forced logprobs; the cache then holds the chunks prefilled so far.
+pub fn generate_from_ids(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    forced_ids: &[u32],
+    config: &GenerationConfig,
+) -> Result<GenerationOutput, EngineError> {
+    generate_constrained(session, prompt_ids, forced_ids, config, None)
+}
+
+/// [`generate_from_ids`] sampling only among the tokens `constraint` allows, which must be given
+/// when the config has a [`GenerationConfig::json_schema`].
+fn generate_constrained(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    forced_ids: &[u32],
+    config: &GenerationConfig,
+    constraint: Option<TokenConstraint>,
+) -> Result<GenerationOutput, EngineError> {
+    generate_from_ids_inner(session, prompt_ids, forced_ids, config, constraint).inspect_err(|e| {
+        session.emit(|o| o.error(&EngineFailed::new(Operation::Generation, e)));
+    })
+}
+
+fn generate_from_ids_inner(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    forced_ids: &[u32],
+    config: &GenerationConfig,
+    mut constraint: Option<TokenConstraint>,
+) -> Result<GenerationOutput, EngineError> {
+    config.validate()?;
+    reject_guidance(config)?;
+    if constraint.is_none() {
+        reject_json_schema(config)?;
+    }
+    let started = session.clock().now();
+    session.emit(|o| {
+        o.generation_started(&GenerationStarted {
+            prompt_tokens: prompt_ids.len(),
+            forced_tokens: forced_ids.len(),
+            max_new_tokens: config.max_new_tokens,
+        })
+    });
+    let timer = DeadlineTimer::start(session.clock().clone(), config);
+    let mut out = GenerationOutput {
+        prompt_tokens: prompt_ids.len(),
+        forced_token_ids: forced_ids.to_vec(),
+        stats: GenerationStats {
+            prompt_tokens: prompt_ids.len(),
+            quality: config.quality.map(|_| QualityStats::default()),
+            ..GenerationStats::default()
+        },
+        ..GenerationOutput::default()
+    };
+    let Some((state, forced_logprobs)) =
+        prefill_scored_until(session, prompt_ids, forced_ids, timer.as_ref())?
+    else {
+        out.finish_reason = FinishReason::DeadlineExceeded;
+        return finish(session, prompt_ids, forced_ids, config, started, out);
+    };
+    let mut quality = config.quality.map(QualityMonitor::new);
+    out.forced_logprobs = forced_logprobs;
+    let stops = config.stop_tokens(session.model().tokenizer_prompt());
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut cached_ids: Vec<u32> = prompt_ids.iter().chain(forced_ids).copied().collect();
+
+    let mut logits = session.next_token_logits(&state)?;
+    for step in 0..config.max_new_tokens {
+        let next = match constraint.as_mut() {
+            Some(constraint) => {
+                let next = sample_allowed(&logits, constraint, &stops, config, &mut rng)?.id;
+                if !stops.contains(next) {
+                    constraint.accept(next)?;
+                }
+                next
+            }
+            None => sample_next(&logits, config, &mut rng)?.id,
+        };
+        if stops.contains(next) {
+            out.finish_reason = FinishReason::Eos { token_id: next };
+            break;
+        }
+        let logprob = logprob_or_err(&logits, next)?;
+        out.generated_logprobs.push(logprob);
+        out.generated_token_ids.push(next);
+        session.emit(|o| {
+            o.token(&TokenGenerated {
+                index: step,
+                id: next,
+                logprob,
+            })
+        });
+        if let Some(monitor) = quality.as_mut() {
+            for alert in monitor.observe(&logits, next, logprob) {
+                session.emit(|o| o.quality_alert(&alert));
+            }
+        }
+        if step + 1 == config.max_new_tokens {
+            break;
+        }
+        if expired(timer.as_ref(), out.generated_token_ids.len()) {
+            out.finish_reason = FinishReason::DeadlineExceeded;
+            break;
+        }
+        session.shift_if_full(&mut cached_ids)?;
+        let state = session.decode_token(next)?;
+        cached_ids.push(next);
+        logits = session.next_token_logits(&state)?;
+    }
+    out.stats.generated_tokens = out.generated_token_ids.len();
+    out.stats.quality = quality.map(QualityMonitor::into_stats);
+    finish(session, prompt_ids, forced_ids, config, started, out)
+}
+
+/// Write the session's transcript, if any, report the finish and hand `out` back.
+fn finish(
+    session: &InferenceSession<'_>,
+    prompt_ids: &[u32],
+    forced_ids: &[u32],
+    config: &GenerationConfig,
+    started: Instant,
+    out: GenerationOutput,
+) -> Result<GenerationOutput, EngineError> {
+    if let Some(path) = session.transcript() {
+        Transcript::record(session.model(), prompt_ids, forced_ids, config, &out).save(path)?;
+    }
+    session.emit(|o| {
+        o.generation_finished(&GenerationFinished {
+            generated: out.generated_token_ids.len(),
+            reason: out.finish_reason,
+            elapsed: session.clock().now().saturating_duration_since(started),
+        })
+    });
+    Ok(out)
+}
+
+/// Per-token log-probabilities of each completion given `prompt_ids` (teacher forcing).
+///
+/// Each completion is scored from a fresh cache, so results do not depend on order.
+pub fn score_completions(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    completions: &[Vec<u32>],
+) -> Result<Vec<Vec<f32>>, EngineError> {
+    completions
+        .iter()
+        .map(|c| prefill_scored(session, prompt_ids, c).map(|(_, lps)| lps))
+        .collect()
+}
+
+/// Single-session generation cannot apply [`GenerationConfig::guidance`].
+pub(crate) fn reject_guidance(config: &GenerationConfig) -> Result<(), EngineError> {
+    match config.guidance {
+        Some(_) => Err(EngineError::Model(
+            "GenerationConfig::guidance needs a negative context; use GuidedSession".into(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Single-session token-level generation cannot apply [`GenerationConfig::json_schema`].
+pub(crate) fn reject_json_schema(config: &GenerationConfig) -> Result<(), EngineError> {
+    match config.json_schema {
+        Some(_) => Err(EngineError::Model(
+            "GenerationConfig::json_schema needs the tokenizer; use generate or ChatSession".into(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// [`sample_next`] over the ids `constraint` allows only: the policy sees just their logits, so
+/// temperature and min-p renormalize over the allowed set. The constraint is the stage that
+/// changed the choice when it ruled out the argmax.
+fn sample_allowed(
+    logits: &[f32],
+    constraint: &TokenConstraint,
+    stops: &StopTokens,
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) -> Result<Sampled, EngineError> {
+    let allowed = constraint.allowed(logits.len(), stops)?;
+    let subset: Vec<f32> = allowed.iter().map(|&id| logits[id as usize]).collect();
+    let sampled = sample_next(&subset, config, rng)?;
+    let allowed_argmax = argmax_index(&subset).map(|i| allowed[i] as usize);
+    let changed_by = if allowed_argmax != argmax_index(logits) {
+        Some(SamplingStage::Constraint)
+    } else {
+        sampled.changed_by
+    };
+    Ok(Sampled {
+        id: allowed[sampled.id as usize],
+        changed_by,
+    })
+}
+
+/// A sampled token and the stage that moved it off the argmax, if one did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Sampled {
+    pub(crate) id: u32,
+    pub(crate) changed_by: Option<SamplingStage>,
+}
+
+pub(crate) fn sample_next(
+    logits: &[f32],
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) -> Result<Sampled, EngineError> {
+    if config.temperature <= 0.0 {
+        return Ok(Sampled {
+            id: sample_greedy(logits)?,
+            changed_by: None,
+        });
+    }
+    let id = if config.min_p > 0.0 {
+        sample_min_p(logits, config.min_p, config.temperature, rng)?
+    } else {
+        sample_temperature(logits, config.temperature, rng)?
+    };
+    Ok(Sampled {
+        id,
+        changed_by: (argmax_index(logits) != Some(id as usize))
+            .then_some(SamplingStage::Temperature),
+    })
+}
+
+/// Reset, prefill `prompt_ids ++ continuation`, and score every continuation token.
+pub(crate) fn prefill_scored(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    continuation: &[u32],
+) -> Result<(ForwardState, Vec<f32>), EngineError> {
+    prefill_scored_until(session, prompt_ids, continuation, None)?
+        .ok_or_else(|| EngineError::Model("prefill stopped without a deadline".into()))
+}
+
+/// [`prefill_scored`] that, with a `timer`, prefills [`DEADLINE_PREFILL_CHUNK`] tokens at a time
+/// and returns `None` if the deadline passes before a chunk. The returned state holds the rows
+/// of the last chunk only.
+pub(crate) fn prefill_scored_until(
+    session: &mut InferenceSession<'_>,
+    prompt_ids: &[u32],
+    continuation: &[u32],
+    timer: Option<&DeadlineTimer>,
+) -> Result<Option<(ForwardState, Vec<f32>)>, EngineError> {
+    if prompt_ids.is_empty() {
+        return Err(EngineError::EmptyPrompt(
+            "generation needs at least one prompt token (e.g. BOS)".into(),
+        ));
+    }
+    session.reset();
+    let ids: Vec<u32> = prompt_ids.iter().chain(continuation).copied().collect();
+    let n_prompt = prompt_ids.len();
+    let chunk = if timer.is_some() {
+        DEADLINE_PREFILL_CHUNK
+    } else {
+        ids.len()
+    };
+    let mut logprobs = Vec::with_capacity(continuation.len());
+    let mut last = None;
+    for start in (0..ids.len()).step_by(chunk) {
+        if expired(timer, 0) {
+            return Ok(None);
+        }
+        let end = (start + chunk).min(ids.len());
+        let split = n_prompt.clamp(start, end);
+        let state = session.prefill_with_forced(&ids[start..split], &ids[split..end])?;
+        session.emit(|o| {
+            o.prefill_progress(&PrefillProgress {
+                done: end,
+                total: ids.len(),
+            })
+        });
+        // Row `r` holds the prediction for position `r + 1`.
+        for r in start.max(n_prompt - 1)..end.min(ids.len() - 1) {
+            let row = r - start;
+            let logits = session.logits_last_token(&state.rows(row, row + 1)?)?;
+            logprobs.push(logprob_or_err(&logits, ids[r + 1])?);
+        }
+        last = Some(state);
+    }
+    Ok(last.map(|state| (state, logprobs)))
+}
+
+pub(crate) fn logprob_or_err(logits: &[f32], token: u32) -> Result<f32, EngineError> {
+    token_logprob(logits, token).ok_or_else(|| {
+        EngineError::Model(format!(
+            "token id {token} outside vocab of {} logits",
+            logits.len()
+        ))
+    })
+}
@@ -0,0 +1,566 @@
+//! Grammar-constrained decoding: a context-free grammar over characters, an incremental
+//! recognizer for it, and a [`TokenConstraint`] that narrows each sampling step to the tokens
+//! whose text keeps the output a prefix of some sentence of the grammar.
+//!
+//! Rules are alternatives of element sequences, like llama.cpp's GBNF, and
+//! [`Grammar::to_gbnf`] prints them in that syntax. The recognizer follows llama.cpp's design as
+//! well: its state is the set of stacks of rule positions, one per live parse, advanced one
+//! character at a time, so no backtracking is needed. That requires rules that are not left
+//! recursive, which [`GrammarBuilder::build`] checks.
+//!
+//! Grammars are built programmatically; [`crate::engine::json_schema`] is the main producer.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::EngineError;
+use crate::engine::generation::StopTokens;
+
+pub type RuleId = usize;
+
+/// One step of a rule alternative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    /// One character inside (or, when `negated`, outside) any of the inclusive ranges.
+    Chars {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Rule(RuleId),
+}
+
+impl Element {
+    pub fn char(c: char) -> Self {
+        Self::range(c, c)
+    }
+
+    pub fn range(lo: char, hi: char) -> Self {
+        Self::Chars {
+            ranges: vec![(lo, hi)],
+            negated: false,
+        }
+    }
+
+    /// Any character outside `ranges`.
+    pub fn none_of(ranges: &[(char, char)]) -> Self {
+        Self::Chars {
+            ranges: ranges.to_vec(),
+            negated: true,
+        }
+    }
+
+    /// The characters of `text`, one element each.
+    pub fn literal(text: &str) -> Vec<Self> {
+        text.chars().map(Self::char).collect()
+    }
+
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Chars { ranges, negated } => {
+                ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)) != *negated
+            }
+            Self::Rule(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    name: String,
+    alternatives: Vec<Vec<Element>>,
+}
+
+/// A validated grammar with a root rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grammar {
+    rules: Vec<Rule>,
+    root: RuleId,
+}
+
+/// Collects rules; ids are handed out by [`Self::declare`] so rules can refer to each other
+/// before they are defined.
+#[derive(Debug, Default)]
+pub struct GrammarBuilder {
+    rules: Vec<Option<Rule>>,
+    names: Vec<String>,
+}
+
+impl GrammarBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve a rule named `name` (suffixed with `-N` if taken) and return its id.
+    pub fn declare(&mut self, name: &str) -> RuleId {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.names.contains(&unique) {
+            unique = format!("{name}-{n}");
+            n += 1;
+        }
+        self.names.push(unique);
+        self.rules.push(None);
+        self.rules.len() - 1
+    }
+
+    /// Set the alternatives of a declared rule. An empty alternative matches the empty string.
+    pub fn define(&mut self, id: RuleId, alternatives: Vec<Vec<Element>>) {
+        self.rules[id] = Some(Rule {
+            name: self.names[id].clone(),
+            alternatives,
+        });
+    }
+
+    /// [`Self::declare`] and [`Self::define`] in one step.
+    pub fn add(&mut self, name: &str, alternatives: Vec<Vec<Element>>) -> RuleId {
+        let id = self.declare(name);
+        self.define(id, alternatives);
+        id
+    }
+
+    /// Check that every declared rule is defined, every reference resolves, and no rule can
+    /// reach itself without consuming a character.
+    pub fn build(self, root: RuleId) -> Result<Grammar, EngineError> {
+        let mut rules = Vec::with_capacity(self.rules.len());
+        for (id, rule) in self.rules.into_iter().enumerate() {
+            let rule = rule.ok_or_else(|| {
+                grammar_error(format!(
+                    "rule '{}' is declared but never defined",
+                    self.names[id]
+                ))
+            })?;
+            rules.push(rule);
+        }
+        let grammar = Grammar { rules, root };
+        if root >= grammar.rules.len() {
+            return Err(grammar_error(format!("root rule {root} does not exist")));
+        }
+        for rule in &grammar.rules {
+            for element in rule.alternatives.iter().flatten() {
+                if let Element::Rule(r) = element {
+                    if *r >= grammar.rules.len() {
+                        return Err(grammar_error(format!(
+                            "rule '{}' refers to missing rule {r}",
+                            rule.name
+                        )));
+                    }
+                }
+            }
+        }
+        grammar.check_left_recursion()?;
+        Ok(grammar)
+    }
+}
+
+fn grammar_error(message: String) -> EngineError {
+    EngineError::Model(format!("grammar: {message}"))
+}
+
+impl Grammar {
+    pub fn root(&self) -> RuleId {
+        self.root
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// The rules in GBNF, root first, e.g. `root ::= "{" ws obj-kv "}"`.
+    pub fn to_gbnf(&self) -> String {
+        let order =
+            std::iter::once(self.root).chain((0..self.rules.len()).filter(|&r| r != self.root));
+        let mut out = String::new();
+        for id in order {
+            let rule = &self.rules[id];
+            let alternatives: Vec<String> = rule
+                .alternatives
+                .iter()
+                .map(|alt| self.gbnf_sequence(alt))
+                .collect();
+            let _ = writeln!(out, "{} ::= {}", rule.name, alternatives.join(" | "));
+        }
+        out
+    }
+
+    fn gbnf_sequence(&self, elements: &[Element]) -> String {
+        if elements.is_empty() {
+            return "\"\"".to_string();
+        }
+        let mut parts: Vec<String> = Vec::new();
+        let mut literal = String::new();
+        let flush = |literal: &mut String, parts: &mut Vec<String>| {
+            if !literal.is_empty() {
+                parts.push(format!("\"{literal}\""));
+                literal.clear();
+            }
+        };
+        for element in elements {
+            match element {
+                Element::Chars { ranges, negated }
+                    if !negated && ranges.len() == 1 && ranges[0].0 == ranges[0].1 =>
+                {
+                    literal.push_str(&gbnf_escape(ranges[0].0, '"'));
+                }
+                Element::Chars { ranges, negated } => {
+                    flush(&mut literal, &mut parts);
+                    let mut class = String::from(if *negated { "[^" } else { "[" });
+                    for &(lo, hi) in ranges {
+                        class.push_str(&gbnf_escape(lo, ']'));
+                        if hi != lo {
+                            class.push('-');
+                            class.push_str(&gbnf_escape(hi, ']'));
+                        }
+                    }
+                    class.push(']');
+                    parts.push(class);
+                }
+                Element::Rule(r) => {
+                    flush(&mut literal, &mut parts);
+                    parts.push(self.rules[*r].name.clone());
+                }
+            }
+        }
+        flush(&mut literal, &mut parts);
+        parts.join(" ")
+    }
+
+    /// Rules that can match the empty string, by fixpoint.
+    fn nullable(&self) -> Vec<bool> {
+        let mut nullable = vec![false; self.rules.len()];
+        loop {
+            let mut changed = false;
+            for (id, rule) in self.rules.iter().enumerate() {
+                if nullable[id] {
+                    continue;
+                }
+                let empty = rule.alternatives.iter().any(|alt| {
+                    alt.iter()
+                        .all(|e| matches!(e, Element::Rule(r) if nullable[*r]))
+                });
+                if empty {
+                    nullable[id] = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return nullable;
+            }
+        }
+    }
+
+    fn check_left_recursion(&self) -> Result<(), EngineError> {
+        let nullable = self.nullable();
+        // Rules each rule can start with, before consuming a character.
+        let leading: Vec<Vec<RuleId>> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let mut out = Vec::new();
+                for alt in &rule.alternatives {
+                    for element in alt {
+                        match element {
+                            Element::Rule(r) => {
+                                out.push(*r);
+                                if !nullable[*r] {
+                                    break;
+                                }
+                            }
+                            Element::Chars { .. } => break,
+                        }
+                    }
+                }
+                out
+            })
+            .collect();
+        for start in 0..self.rules.len() {
+            let mut seen = vec![false; self.rules.len()];
+            let mut todo = leading[start].clone();
+            while let Some(r) = todo.pop() {
+                if r == start {
+                    return Err(grammar_error(format!(
+                        "rule '{}' is left recursive",
+                        self.rules[start].name
+                    )));
+                }
+                if !std::mem::replace(&mut seen[r], true) {
+                    todo.extend(&leading[r]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn gbnf_escape(c: char, delimiter: char) -> String {
+    match c {
+        '\\' => "\\\\".into(),
+        '\n' => "\\n".into(),
+        '\r' => "\\r".into(),
+        '\t' => "\\t".into(),
+        c if c == delimiter || (delimiter == ']' && (c == '^' || c == '-')) => format!("\\{c}"),
+        c if (c as u32) < 0x20 => format!("\\x{:02X}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// A position in one alternative of one rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Frame {
+    rule: RuleId,
+    alt: usize,
+    pos: usize,
+}
+
+/// Where a parse of a [`Grammar`] stands after some text: every way the text so far can
+/// continue. Each stack's top frame waits on a character element; an empty stack means the root
+/// rule has been matched completely.
+#[derive(Debug, Clone)]
+pub struct GrammarState {
+    grammar: Arc<Grammar>,
+    stacks: BTreeSet<Vec<Frame>>,
+}
+
+impl GrammarState {
+    pub fn new(grammar: Arc<Grammar>) -> Self {
+        let mut stacks = BTreeSet::new();
+        for alt in 0..grammar.rules[grammar.root].alternatives.len() {
+            let frame = Frame {
+                rule: grammar.root,
+                alt,
+                pos: 0,
+            };
+            expand(&grammar, vec![frame], &mut stacks);
+        }
+        Self { grammar, stacks }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The text so far is a whole sentence of the grammar.
+    pub fn is_complete(&self) -> bool {
+        self.stacks.iter().any(|s| s.is_empty())
+    }
+
+    /// Some non-empty continuation is still possible.
+    pub fn can_continue(&self) -> bool {
+        self.stacks.iter().any(|s| !s.is_empty())
+    }
+
+    /// Whether appending `text` keeps the output a prefix of some sentence.
+    pub fn accepts(&self, text: &str) -> bool {
+        advance_all(&self.grammar, &self.stacks, text).is_some()
+    }
+
+    /// Append `text`, or fail (leaving the state unchanged) if the grammar does not allow it.
+    pub fn advance(&mut self, text: &str) -> Result<(), EngineError> {
+        let stacks = advance_all(&self.grammar, &self.stacks, text)
+            .ok_or_else(|| grammar_error(format!("text {text:?} is not allowed here")))?;
+        self.stacks = stacks;
+        Ok(())
+    }
+}
+
+/// Push frames until every stack's top waits on a character, forking at rule references.
+fn expand(grammar: &Grammar, mut stack: Vec<Frame>, out: &mut BTreeSet<Vec<Frame>>) {
+    loop {
+        let Some(&top) = stack.last() else {
+            out.insert(stack);
+            return;
+        };
+        let alternative = &grammar.rules[top.rule].alternatives[top.alt];
+        match alternative.get(top.pos) {
+            None => {
+                stack.pop();
+                if let Some(parent) = stack.last_mut() {
+                    parent.pos += 1;
+                }
+            }
+            Some(Element::Chars { .. }) => {
+                out.insert(stack);
+                return;
+            }
+            Some(&Element::Rule(rule)) => {
+                for alt in 0..grammar.rules[rule].alternatives.len() {
+                    let mut forked = stack.clone();
+                    forked.push(Frame { rule, alt, pos: 0 });
+                    expand(grammar, forked, out);
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn advance_all(
+    grammar: &Grammar,
+    stacks: &BTreeSet<Vec<Frame>>,
+    text: &str,
+) -> Option<BTreeSet<Vec<Frame>>> {
+    let mut current = stacks.clone();
+    for c in text.chars() {
+        let mut next = BTreeSet::new();
+        for stack in &current {
+            let Some(&top) = stack.last() else {
+                continue;
+            };
+            if grammar.rules[top.rule].alternatives[top.alt][top.pos].matches(c) {
+                let mut moved = stack.clone();
+                moved.last_mut().expect("non-empty").pos += 1;
+                expand(grammar, moved, &mut next);
+            }
+        }
+        if next.is_empty() {
+            return None;
+        }
+        current = next;
+    }
+    Some(current)
+}
+
+/// A [`GrammarState`] over a vocabulary: which token ids may come next, and the state after one.
+#[derive(Debug, Clone)]
+pub struct TokenConstraint {
+    state: GrammarState,
+    /// Text each id appends to the output ([`crate::tokenizer::Tokenizer::decode_appended`]).
+    token_text: Vec<String>,
+}
+
+impl TokenConstraint {
+    pub fn new(grammar: Arc<Grammar>, token_text: Vec<String>) -> Self {
+        Self {
+            state: GrammarState::new(grammar),
+            token_text,
+        }
+    }
+
+    pub fn state(&self) -> &GrammarState {
+        &self.state
+    }
+
+    /// Ids among the first `vocab` that may come next: tokens whose text the grammar accepts,
+    /// plus `stops` once the output is complete. Tokens with no text (control pieces) are never
+    /// allowed. Fails when nothing is: e.g. a piece that starts a literal the vocabulary has no
+    /// pieces to finish (vocabularies with single-character or byte pieces never get here).
+    pub fn allowed(&self, vocab: usize, stops: &StopTokens) -> Result<Vec<u32>, EngineError> {
+        let complete = self.state.is_complete();
+        let allowed: Vec<u32> = (0..vocab as u32)
+            .filter(|&id| {
+                if stops.contains(id) {
+                    return complete;
+                }
+                match self.token_text.get(id as usize) {
+                    Some(text) if !text.is_empty() => self.state.accepts(text),
+                    _ => false,
+                }
+            })
+            .collect();
+        if allowed.is_empty() {
+            return Err(grammar_error(
+                "no token in the vocabulary can continue the output".into(),
+            ));
+        }
+        Ok(allowed)
+    }
+
+    /// Advance past token `id`.
+    pub fn accept(&mut self, id: u32) -> Result<(), EngineError> {
+        let text = self
+            .token_text
+            .get(id as usize)
+            .ok_or_else(|| grammar_error(format!("token id {id} outside the vocabulary")))?;
+        self.state.advance(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `list ::= "[" items? "]"` over digits, with right-recursive items.
+    fn digit_list() -> Arc<Grammar> {
+        let mut b = GrammarBuilder::new();
+        let root = b.declare("root");
+        let items = b.declare("items");
+        let more = b.declare("more");
+        let digit = Element::range('0', '9');
+        b.define(
+            root,
+            vec![
+                Element::literal("[]"),
+                vec![Element::char('['), Element::Rule(items), Element::char(']')],
+            ],
+        );
+        b.define(items, vec![vec![digit.clone(), Element::Rule(more)]]);
+        b.define(
+            more,
+            vec![vec![], vec![Element::char(','), digit, Element::Rule(more)]],
+        );
+        Arc::new(b.build(root).unwrap())
+    }
+
+    #[test]
+    fn recognizer_tracks_prefixes_and_completion() {
+        let grammar = digit_list();
+        let mut state = GrammarState::new(grammar);
+        assert!(!state.is_complete());
+        assert!(state.accepts("[1,2"));
+        assert!(!state.accepts("[1,,"));
+        assert!(!state.accepts("]"));
+        state.advance("[3,4").unwrap();
+        assert!(!state.is_complete());
+        assert!(state.advance("x").is_err());
+        state.advance("]").unwrap();
+        assert!(state.is_complete());
+        assert!(!state.can_continue());
+    }
+
+    #[test]
+    fn gbnf_rendering_and_left_recursion() {
+        assert_eq!(
+            digit_list().to_gbnf(),
+            "root ::= \"[]\" | \"[\" items \"]\"\nitems ::= [0-9] more\nmore ::= \"\" | \",\" [0-9] more\n"
+        );
+
+        let mut b = GrammarBuilder::new();
+        let a = b.declare("a");
+        let opt = b.add("opt", vec![vec![], Element::literal("x")]);
+        b.define(
+            a,
+            vec![
+                vec![Element::Rule(opt), Element::Rule(a)],
+                Element::literal("y"),
+            ],
+        );
+        let err = b.build(a).unwrap_err().to_string();
+        assert!(err.contains("rule 'a' is left recursive"), "{err}");
+
+        let mut b = GrammarBuilder::new();
+        let root = b.declare("root");
+        b.declare("dangling");
+        b.define(root, vec![Element::literal("z")]);
+        let err = b.build(root).unwrap_err().to_string();
+        assert!(
+            err.contains("'dangling' is declared but never defined"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn token_constraint_allows_stops_only_when_complete() {
+        let texts = ["<s>", "[", "]", "1", ",2", "[]", ""]
+            .map(String::from)
+            .to_vec();
+        let stops = StopTokens::new([0]);
+        let mut constraint = TokenConstraint::new(digit_list(), texts);
+        assert_eq!(constraint.allowed(7, &stops).unwrap(), [1, 5]);
+        constraint.accept(1).unwrap();
+        constraint.accept(3).unwrap();
+        assert_eq!(constraint.allowed(7, &stops).unwrap(), [2, 4]);
+        constraint.accept(2).unwrap();
+        assert_eq!(constraint.allowed(7, &stops).unwrap(), [0]);
+        assert!(constraint.accept(3).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Classifier-free guidance (negative prompting) over two contexts advanced in lockstep.
+//!
+//! The main session holds the real prompt, the negative session the prompt to steer away from.
+//! Each step both produce next-token logits, which are combined as
+//! `l_g + scale * (l_g - l_n)` before sampling; the one sampled token is then fed to both.
+//! `scale == 0` is plain generation on the main prompt.
+//!
+//! Two contexts means two KV caches: [`GuidedSession::kv_cache_bytes`] reports both.
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+use crate::engine::budget::TokenUse;
+use crate::engine::deadline::{DeadlineTimer, expired};
+use crate::engine::generation::{
+    FinishReason, GenerationConfig, GenerationOutput, GenerationStats, logprob_or_err,
+    reject_json_schema, sample_next,
+};
+use crate::engine::quality::QualityMonitor;
+use crate::engine::session::InferenceSession;
+use crate::tokenizer::Tokenizer;
+
+/// [`GenerationConfig::guidance`]: what to steer away from, and how hard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guidance {
+    /// Encoded with the model's BOS policy, like the main prompt. May be empty text (BOS only)
+    /// for unconditional guidance.
+    pub negative_prompt: String,
+    /// `0.0` disables guidance; around `1.0`–`3.0` is typical. Must be finite and `>= 0`;
+    /// values above [`MAX_GUIDANCE_SCALE`] are clamped to it.
+    pub scale: f32,
+}
+
+/// Largest guidance scale applied. Past it the combined logits are mostly the difference
+/// between the contexts, and sampling degenerates into repeating whatever the negative prompt
+/// disfavours most.
+pub const MAX_GUIDANCE_SCALE: f32 = 10.0;
+
+impl Guidance {
+    /// [`Self::scale`] clamped to [`MAX_GUIDANCE_SCALE`].
+    pub fn effective_scale(&self) -> f32 {
+        self.scale.min(MAX_GUIDANCE_SCALE)
+    }
+}
+
+/// `guided + scale * (guided - negative)`, element-wise.
+pub fn combine_guided_logits(
+    guided: &[f32],
+    negative: &[f32],
+    scale: f32,
+) -> Result<Vec<f32>, EngineError> {
+    if guided.len() != negative.len() {
+        return Err(EngineError::Model(format!(
+            "guidance: main context has {} logits, negative context {}",
+            guided.len(),
+            negative.len()
+        )));
+    }
+    if scale == 0.0 {
+        return Ok(guided.to_vec());
+    }
+    Ok(guided
+        .iter()
+        .zip(negative)
+        .map(|(&g, &n)| g + scale * (g - n))
+        .collect())
+}
+
+/// A main and a negative [`InferenceSession`] on the same model, always advanced together.
+pub struct GuidedSession<'a> {
+    main: InferenceSession<'a>,
+    negative: InferenceSession<'a>,
+}
+
+impl<'a> GuidedSession<'a> {
+    pub fn new(main: InferenceSession<'a>, negative: InferenceSession<'a>) -> Self {
+        Self { main, negative }
+    }
+
+    pub fn main(&self) -> &InferenceSession<'a> {
+        &self.main
+    }
+
+    pub fn negative(&self) -> &InferenceSession<'a> {
+        &self.negative
+    }
+
+    pub fn into_parts(self) -> (InferenceSession<'a>, InferenceSession<'a>) {
+        (self.main, self.negative)
+    }
+
+    /// KV cache memory of both contexts.
+    pub fn kv_cache_bytes(&self) -> usize {
+        self.main.kv_cache_bytes() + self.negative.kv_cache_bytes()
+    }
+
+    /// Encode `prompt` and `config.guidance`'s negative prompt, then [`Self::generate_from_ids`].
+    pub fn generate(
+        &mut self,
+        tokenizer: &mut Tokenizer,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<GenerationOutput, EngineError> {
+        let guidance = required(config)?;
+        let prompt_cfg = self.main.model().tokenizer_prompt();
+        let prompt_ids = tokenizer.encode_with_prompt_config(prompt, prompt_cfg)?;
+        let negative_ids =
+            tokenizer.encode_with_prompt_config(&guidance.negative_prompt, prompt_cfg)?;
+        let mut out = self.generate_from_ids(&prompt_ids, &negative_ids, config)?;
+        out.text = tokenizer.decode_piece_ids(&out.generated_token_ids)?;
+        Ok(out)
+    }
+
+    /// Reset both sessions, prefill `prompt_ids` / `negative_ids`, and sample from the combined
+    /// logits until a stop token or `max_new_tokens`. `generated_logprobs` are under the
+    /// combined logits (what was sampled from); `text` is left empty.
+    ///
+    /// A step is fed to both sessions or to neither: both budgets are checked before either
+    /// cache grows, and a stop token ends generation for the pair. [`GenerationConfig::deadline`]
+    /// (on the main session's clock) is checked between tokens; the two prompts are prefilled
+    /// whole. [`GenerationConfig::quality`] is tracked under the combined logits, with alerts
+    /// reported to the main session's observer.
+    pub fn generate_from_ids(
+        &mut self,
+        prompt_ids: &[u32],
+        negative_ids: &[u32],
+        config: &GenerationConfig,
+    ) -> Result<GenerationOutput, EngineError> {
+        config.validate()?;
+        let scale = required(config)?.effective_scale();
+        reject_json_schema(config)?;
+        if prompt_ids.is_empty() || negative_ids.is_empty() {
+            return Err(EngineError::Model(
+                "guided generation needs at least one token (e.g. BOS) in both prompts".into(),
+            ));
+        }
+        let timer = DeadlineTimer::start(self.main.clock().clone(), config);
+        self.main.reset();
+        self.negative.reset();
+        let main_state = self.main.prefill(prompt_ids)?;
+        let negative_state = self.negative.prefill(negative_ids)?;
+
+        let stops = config.stop_tokens(self.main.model().tokenizer_prompt());
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut out = GenerationOutput {
+            prompt_tokens: prompt_ids.len(),
+            ..GenerationOutput::default()
+        };
+        let mut quality = config.quality.map(QualityMonitor::new);
+
+        let mut logits = combine_guided_logits(
+            &self.main.next_token_logits(&main_state)?,
+            &self.negative.next_token_logits(&negative_state)?,
+            scale,
+        )?;
+        for step in 0..config.max_new_tokens {
+            let next = sample_next(&logits, config, &mut rng)?.id;
+            if stops.contains(next) {
+                out.finish_reason = FinishReason::Eos { token_id: next };
+                break;
+            }
+            let logprob = logprob_or_err(&logits, next)?;
+            out.generated_logprobs.push(logprob);
+            out.generated_token_ids.push(next);
+            if let Some(monitor) = quality.as_mut() {
+                for alert in monitor.observe(&logits, next, logprob) {
+                    self.main.emit(|o| o.quality_alert(&alert));
+                }
+            }
+            if step + 1 == config.max_new_tokens {
+                break;
+            }
+            if expired(timer.as_ref(), out.generated_token_ids.len()) {
+                out.finish_reason = FinishReason::DeadlineExceeded;
+                break;
+            }
+            self.main.budget().check(TokenUse::Generated, 1)?;
+            self.negative.budget().check(TokenUse::Generated, 1)?;
+            let main_state = self.main.decode_token(next)?;
+            let negative_state = self.negative.decode_token(next)?;
+            logits = combine_guided_logits(
+                &self.main.next_token_logits(&main_state)?,
+                &self.negative.next_token_logits(&negative_state)?,
+                scale,
+            )?;
+        }
+        out.stats = GenerationStats {
+            prompt_tokens: prompt_ids.len(),
+            generated_tokens: out.generated_token_ids.len(),
+            quality: quality.map(QualityMonitor::into_stats),
+            ..GenerationStats::default()
+        };
+        Ok(out)
+    }
+}
+
+fn required(config: &GenerationConfig) -> Result<&Guidance, EngineError> {
+    config.guidance.as_ref().ok_or_else(|| {
+        EngineError::Model("guided generation needs GenerationConfig::guidance".into())
+    })
+}
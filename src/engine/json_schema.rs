@@ -0,0 +1,887 @@
+//! JSON mode: a practical subset of JSON Schema compiled to a [`Grammar`] for constrained
+//! decoding ([`crate::engine::generation::GenerationConfig::json_schema`]), plus a validator for
+//! the finished output.
+//!
+//! Supported:
+//!
+//! - `"type"`: `"object"` (`properties`, `required`, nested objects, `additionalProperties:
+//!   false`), `"string"` (`minLength`, `maxLength`), `"number"`, `"integer"`, `"boolean"`,
+//!   `"null"`, `"array"` (`items`, `minItems`, `maxItems`);
+//! - `"enum"` and `"const"` with any JSON values;
+//! - the annotations `title`, `description`, `$schema`, `$comment`, `default` and `examples`.
+//!
+//! Anything else (`$ref`, `oneOf`, `pattern`, `format`, type arrays, ...) is rejected with
+//! [`JsonSchemaError::Unsupported`] rather than silently ignored. Objects never take properties
+//! beyond `properties`, which are emitted in sorted key order (as `serde_json` stores them);
+//! optional ones may be left out.
+//!
+//! The grammar produces compact JSON: an optional space before the value and after each `:` and
+//! `,`, nothing else. Unbounded strings, numbers and arrays can run until `max_new_tokens`; give
+//! `maxLength` / `maxItems` (or enums) where the output must finish.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::EngineError;
+use crate::engine::grammar::{Element, Grammar, GrammarBuilder, RuleId, TokenConstraint};
+use crate::tokenizer::Tokenizer;
+
+/// Every keyword some supported type takes.
+const KEYWORDS: [&str; 11] = [
+    "type",
+    "enum",
+    "const",
+    "properties",
+    "required",
+    "additionalProperties",
+    "minLength",
+    "maxLength",
+    "items",
+    "minItems",
+    "maxItems",
+];
+
+const ANNOTATIONS: [&str; 6] = [
+    "title",
+    "description",
+    "$schema",
+    "$comment",
+    "default",
+    "examples",
+];
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum JsonSchemaError {
+    /// A keyword or value outside the supported subset, at schema location `path` (`#/...`).
+    #[error("JSON schema: unsupported feature at {path}: {feature}")]
+    Unsupported { path: String, feature: String },
+    /// Not a well-formed schema (wrong value types, `required` naming a missing property, ...).
+    #[error("JSON schema: {0}")]
+    Invalid(String),
+    /// The generated text does not match the schema; every violation found is listed.
+    #[error("output does not match the JSON schema: {}", list_violations(.0))]
+    Violations(Vec<SchemaViolation>),
+}
+
+/// One mismatch between an output and its schema, at data location `path` (`$.a[0]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+fn list_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Object {
+        properties: Vec<(String, Node)>,
+        required: Vec<String>,
+    },
+    String {
+        min_length: usize,
+        max_length: Option<usize>,
+    },
+    Number,
+    Integer,
+    Boolean,
+    Null,
+    Array {
+        items: Box<Node>,
+        min_items: usize,
+        max_items: Option<usize>,
+    },
+    Enum(Vec<Value>),
+}
+
+/// A parsed schema and its compiled grammar. (De)serializes as the schema document itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct JsonSchema {
+    source: Value,
+    root: Node,
+    grammar: Arc<Grammar>,
+}
+
+impl PartialEq for JsonSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl TryFrom<Value> for JsonSchema {
+    type Error = JsonSchemaError;
+
+    fn try_from(source: Value) -> Result<Self, Self::Error> {
+        let root = parse_node(&source, "#")?;
+        let grammar = Compiler::compile(&root)
+            .map_err(|e| JsonSchemaError::Invalid(format!("grammar compilation failed: {e}")))?;
+        Ok(Self {
+            source,
+            root,
+            grammar: Arc::new(grammar),
+        })
+    }
+}
+
+impl From<JsonSchema> for Value {
+    fn from(schema: JsonSchema) -> Self {
+        schema.source
+    }
+}
+
+impl JsonSchema {
+    /// Parse a schema document.
+    pub fn parse(schema: &str) -> Result<Self, JsonSchemaError> {
+        let value: Value = serde_json::from_str(schema)
+            .map_err(|e| JsonSchemaError::Invalid(format!("not valid JSON: {e}")))?;
+        Self::try_from(value)
+    }
+
+    pub fn source(&self) -> &Value {
+        &self.source
+    }
+
+    pub fn grammar(&self) -> &Arc<Grammar> {
+        &self.grammar
+    }
+
+    /// A constraint over `tokenizer`'s vocabulary, each id standing for the text it adds when
+    /// appended ([`Tokenizer::decode_appended`]), so a word-initial piece's space counts.
+    pub fn token_constraint(&self, tokenizer: &Tokenizer) -> Result<TokenConstraint, EngineError> {
+        let token_text = (0..tokenizer.vocab_size() as u32)
+            .map(|id| tokenizer.decode_appended(&[id]))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TokenConstraint::new(self.grammar.clone(), token_text))
+    }
+
+    /// Instruction appended to the prompt so the model knows what the grammar will enforce.
+    pub fn hint(&self) -> String {
+        format!(
+            "Respond only with JSON matching this JSON Schema: {}",
+            self.source
+        )
+    }
+
+    /// Check `text` (one JSON value, surrounding whitespace allowed) against the schema.
+    pub fn validate(&self, text: &str) -> Result<(), JsonSchemaError> {
+        let mut violations = Vec::new();
+        match serde_json::from_str::<Value>(text) {
+            Ok(value) => check(&self.root, &value, "$", &mut violations),
+            Err(e) => violations.push(SchemaViolation {
+                path: "$".into(),
+                message: format!("not valid JSON: {e}"),
+            }),
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(JsonSchemaError::Violations(violations))
+        }
+    }
+}
+
+fn unsupported(path: &str, feature: impl Into<String>) -> JsonSchemaError {
+    JsonSchemaError::Unsupported {
+        path: path.to_string(),
+        feature: feature.into(),
+    }
+}
+
+fn count(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<usize>, JsonSchemaError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(v) => v.as_u64().map(|n| Some(n as usize)).ok_or_else(|| {
+            JsonSchemaError::Invalid(format!("{path}/{key} must be a non-negative integer"))
+        }),
+    }
+}
+
+fn check_bounds(
+    min: usize,
+    max: Option<usize>,
+    what: &str,
+    path: &str,
+) -> Result<(), JsonSchemaError> {
+    match max {
+        Some(max) if max < min => Err(JsonSchemaError::Invalid(format!(
+            "{path}: max{what} {max} is below min{what} {min}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn parse_node(schema: &Value, path: &str) -> Result<Node, JsonSchemaError> {
+    let object = match schema {
+        Value::Object(object) => object,
+        Value::Bool(_) => return Err(unsupported(path, "boolean schemas")),
+        _ => {
+            return Err(JsonSchemaError::Invalid(format!(
+                "{path}: a schema must be an object"
+            )));
+        }
+    };
+    // Unknown keywords first, so `{"$ref": ...}` is reported as such rather than as untyped.
+    if let Some(key) = object
+        .keys()
+        .find(|k| !KEYWORDS.contains(&k.as_str()) && !ANNOTATIONS.contains(&k.as_str()))
+    {
+        return Err(unsupported(path, format!("keyword '{key}'")));
+    }
+    let keys_other_than = |allowed: &[&str]| -> Result<(), JsonSchemaError> {
+        match object
+            .keys()
+            .find(|k| !allowed.contains(&k.as_str()) && !ANNOTATIONS.contains(&k.as_str()))
+        {
+            Some(key) => Err(unsupported(path, format!("keyword '{key}'"))),
+            None => Ok(()),
+        }
+    };
+
+    if let Some(values) = object.get("enum") {
+        keys_other_than(&["enum", "type"])?;
+        let values = values.as_array().filter(|v| !v.is_empty()).ok_or_else(|| {
+            JsonSchemaError::Invalid(format!("{path}/enum must be a non-empty array"))
+        })?;
+        return Ok(Node::Enum(values.clone()));
+    }
+    if let Some(value) = object.get("const") {
+        keys_other_than(&["const", "type"])?;
+        return Ok(Node::Enum(vec![value.clone()]));
+    }
+
+    let kind = match object.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        Some(Value::Array(_)) => return Err(unsupported(path, "type arrays (unions)")),
+        Some(_) => {
+            return Err(JsonSchemaError::Invalid(format!(
+                "{path}/type must be a string"
+            )));
+        }
+        None => return Err(unsupported(path, "schemas without a 'type' or 'enum'")),
+    };
+    match kind {
+        "object" => {
+            keys_other_than(&["type", "properties", "required", "additionalProperties"])?;
+            match object.get("additionalProperties") {
+                None | Some(Value::Bool(false)) => {}
+                Some(_) => {
+                    return Err(unsupported(path, "additionalProperties other than false"));
+                }
+            }
+            let mut properties = Vec::new();
+            match object.get("properties") {
+                None => {}
+                Some(Value::Object(props)) => {
+                    for (name, schema) in props {
+                        let node = parse_node(schema, &format!("{path}/properties/{name}"))?;
+                        properties.push((name.clone(), node));
+                    }
+                }
+                Some(_) => {
+                    return Err(JsonSchemaError::Invalid(format!(
+                        "{path}/properties must be an object"
+                    )));
+                }
+            }
+            let mut required = Vec::new();
+            if let Some(list) = object.get("required") {
+                let list = list.as_array().ok_or_else(|| {
+                    JsonSchemaError::Invalid(format!("{path}/required must be an array"))
+                })?;
+                for name in list {
+                    let name = name.as_str().ok_or_else(|| {
+                        JsonSchemaError::Invalid(format!("{path}/required must list strings"))
+                    })?;
+                    if !properties.iter().any(|(p, _)| p == name) {
+                        return Err(JsonSchemaError::Invalid(format!(
+                            "{path}/required names '{name}', which is not in properties"
+                        )));
+                    }
+                    required.push(name.to_string());
+                }
+            }
+            Ok(Node::Object {
+                properties,
+                required,
+            })
+        }
+        "string" => {
+            keys_other_than(&["type", "minLength", "maxLength"])?;
+            let min_length = count(object, "minLength", path)?.unwrap_or(0);
+            let max_length = count(object, "maxLength", path)?;
+            check_bounds(min_length, max_length, "Length", path)?;
+            Ok(Node::String {
+                min_length,
+                max_length,
+            })
+        }
+        "number" | "integer" | "boolean" | "null" => {
+            keys_other_than(&["type"])?;
+            Ok(match kind {
+                "number" => Node::Number,
+                "integer" => Node::Integer,
+                "boolean" => Node::Boolean,
+                _ => Node::Null,
+            })
+        }
+        "array" => {
+            keys_other_than(&["type", "items", "minItems", "maxItems"])?;
+            let items = object
+                .get("items")
+                .ok_or_else(|| unsupported(path, "arrays without an 'items' schema"))?;
+            let items = parse_node(items, &format!("{path}/items"))?;
+            let min_items = count(object, "minItems", path)?.unwrap_or(0);
+            let max_items = count(object, "maxItems", path)?;
+            check_bounds(min_items, max_items, "Items", path)?;
+            Ok(Node::Array {
+                items: Box::new(items),
+                min_items,
+                max_items,
+            })
+        }
+        other => Err(unsupported(path, format!("type '{other}'"))),
+    }
+}
+
+fn json_literal(value: &Value) -> Vec<Element> {
+    Element::literal(&value.to_string())
+}
+
+/// Builds rules for a [`Node`] tree, sharing the primitive rules (`ws`, `char`, numbers).
+struct Compiler {
+    b: GrammarBuilder,
+    ws: Option<RuleId>,
+    string_char: Option<RuleId>,
+    digits: Option<RuleId>,
+    integer: Option<RuleId>,
+    number: Option<RuleId>,
+}
+
+impl Compiler {
+    fn compile(root: &Node) -> Result<Grammar, EngineError> {
+        let mut c = Self {
+            b: GrammarBuilder::new(),
+            ws: None,
+            string_char: None,
+            digits: None,
+            integer: None,
+            number: None,
+        };
+        let root_id = c.b.declare("root");
+        let ws = c.ws();
+        let value = c.node(root, "value")?;
+        c.b.define(root_id, vec![vec![Element::Rule(ws), Element::Rule(value)]]);
+        c.b.build(root_id)
+    }
+
+    /// `"" | " "`.
+    fn ws(&mut self) -> RuleId {
+        if let Some(id) = self.ws {
+            return id;
+        }
+        let id = self.b.add("ws", vec![vec![], vec![Element::char(' ')]]);
+        *self.ws.insert(id)
+    }
+
+    /// One character of a JSON string body: unescaped, or a backslash escape.
+    fn string_char(&mut self) -> RuleId {
+        if let Some(id) = self.string_char {
+            return id;
+        }
+        let hex = || Element::Chars {
+            ranges: vec![('0', '9'), ('a', 'f'), ('A', 'F')],
+            negated: false,
+        };
+        let escape = self.b.add(
+            "escape",
+            vec![
+                vec![Element::Chars {
+                    ranges: ['"', '\\', '/', 'b', 'f', 'n', 'r', 't']
+                        .map(|c| (c, c))
+                        .to_vec(),
+                    negated: false,
+                }],
+                vec![Element::char('u'), hex(), hex(), hex(), hex()],
+            ],
+        );
+        let id = self.b.add(
+            "char",
+            vec![
+                vec![Element::none_of(&[
+                    ('"', '"'),
+                    ('\\', '\\'),
+                    ('\0', '\x1f'),
+                ])],
+                vec![Element::char('\\'), Element::Rule(escape)],
+            ],
+        );
+        *self.string_char.insert(id)
+    }
+
+    /// `[0-9]*`.
+    fn digits(&mut self) -> RuleId {
+        if let Some(id) = self.digits {
+            return id;
+        }
+        let id = self.repeat("digits", vec![Element::range('0', '9')], None);
+        *self.digits.insert(id)
+    }
+
+    /// `"-"? ("0" | [1-9] [0-9]*)`.
+    fn integer(&mut self) -> RuleId {
+        if let Some(id) = self.integer {
+            return id;
+        }
+        let digits = self.digits();
+        let minus = self.b.add("minus", vec![vec![], vec![Element::char('-')]]);
+        let id = self.b.add(
+            "integer",
+            vec![
+                vec![Element::Rule(minus), Element::char('0')],
+                vec![
+                    Element::Rule(minus),
+                    Element::range('1', '9'),
+                    Element::Rule(digits),
+                ],
+            ],
+        );
+        *self.integer.insert(id)
+    }
+
+    /// Integer, optional fraction, optional exponent.
+    fn number(&mut self) -> RuleId {
+        if let Some(id) = self.number {
+            return id;
+        }
+        let (integer, digits) = (self.integer(), self.digits());
+        let digit = Element::range('0', '9');
+        let frac = self.b.add(
+            "frac",
+            vec![
+                vec![],
+                vec![Element::char('.'), digit.clone(), Element::Rule(digits)],
+            ],
+        );
+        let sign = self.b.add(
+            "sign",
+            vec![
+                vec![],
+                vec![Element::Chars {
+                    ranges: vec![('+', '+'), ('-', '-')],
+                    negated: false,
+                }],
+            ],
+        );
+        let exp = self.b.add(
+            "exp",
+            vec![
+                vec![],
+                vec![
+                    Element::Chars {
+                        ranges: vec![('e', 'e'), ('E', 'E')],
+                        negated: false,
+                    },
+                    Element::Rule(sign),
+                    digit,
+                    Element::Rule(digits),
+                ],
+            ],
+        );
+        let id = self.b.add(
+            "number",
+            vec![vec![
+                Element::Rule(integer),
+                Element::Rule(frac),
+                Element::Rule(exp),
+            ]],
+        );
+        *self.number.insert(id)
+    }
+
+    /// Zero or more `unit`s, at most `max` of them. Right recursive, so the recognizer never
+    /// loops: `r ::= "" | unit r`, or for a bound a chain of such rules.
+    fn repeat(&mut self, name: &str, unit: Vec<Element>, max: Option<usize>) -> RuleId {
+        let with = |mut unit: Vec<Element>, rest: RuleId| {
+            unit.push(Element::Rule(rest));
+            unit
+        };
+        match max {
+            None => {
+                let id = self.b.declare(name);
+                self.b.define(id, vec![vec![], with(unit, id)]);
+                id
+            }
+            Some(max) => {
+                let mut id = self.b.add(&format!("{name}-0"), vec![vec![]]);
+                for n in 1..=max {
+                    id = self
+                        .b
+                        .add(&format!("{name}-{n}"), vec![vec![], with(unit.clone(), id)]);
+                }
+                id
+            }
+        }
+    }
+
+    fn node(&mut self, node: &Node, name: &str) -> Result<RuleId, EngineError> {
+        Ok(match node {
+            Node::Boolean => self.b.add(
+                name,
+                vec![Element::literal("true"), Element::literal("false")],
+            ),
+            Node::Null => self.b.add(name, vec![Element::literal("null")]),
+            Node::Integer => self.integer(),
+            Node::Number => self.number(),
+            Node::Enum(values) => self.b.add(name, values.iter().map(json_literal).collect()),
+            Node::String {
+                min_length,
+                max_length,
+            } => {
+                let c = self.string_char();
+                let mut body = vec![Element::char('"')];
+                body.extend(std::iter::repeat_n(Element::Rule(c), *min_length));
+                let extra = max_length.map(|max| max - *min_length);
+                if extra != Some(0) {
+                    let tail = self.repeat(&format!("{name}-chars"), vec![Element::Rule(c)], extra);
+                    body.push(Element::Rule(tail));
+                }
+                body.push(Element::char('"'));
+                self.b.add(name, vec![body])
+            }
+            Node::Array {
+                items,
+                min_items,
+                max_items,
+            } => {
+                let item = self.node(items, &format!("{name}-item"))?;
+                let ws = self.ws();
+                let mut alternatives = Vec::new();
+                if *min_items == 0 {
+                    alternatives.push(Element::literal("[]"));
+                }
+                if *max_items != Some(0) {
+                    let next = vec![Element::char(','), Element::Rule(ws), Element::Rule(item)];
+                    let mut body = vec![Element::char('['), Element::Rule(item)];
+                    for _ in 1..*min_items {
+                        body.extend(next.iter().cloned());
+                    }
+                    let extra = max_items.map(|max| max - (*min_items).max(1));
+                    if extra != Some(0) {
+                        let tail = self.repeat(&format!("{name}-more"), next, extra);
+                        body.push(Element::Rule(tail));
+                    }
+                    body.push(Element::char(']'));
+                    alternatives.push(body);
+                }
+                self.b.add(name, alternatives)
+            }
+            Node::Object {
+                properties,
+                required,
+            } => self.object(name, properties, required)?,
+        })
+    }
+
+    /// `"{" first-0 "}"`, where `first-i` lists properties `i..` with none written yet and
+    /// `rest-i` lists them after at least one (so each starts with a comma). Optional properties
+    /// get an alternative that skips them.
+    fn object(
+        &mut self,
+        name: &str,
+        properties: &[(String, Node)],
+        required: &[String],
+    ) -> Result<RuleId, EngineError> {
+        let ws = self.ws();
+        let object = self.b.declare(name);
+        let mut members = Vec::with_capacity(properties.len());
+        for (key, node) in properties {
+            let value = self.node(node, &format!("{name}-{key}"))?;
+            let mut member = json_literal(&Value::String(key.clone()));
+            member.extend([Element::char(':'), Element::Rule(ws), Element::Rule(value)]);
+            members.push((member, required.contains(key)));
+        }
+        let end = self.b.add(&format!("{name}-end"), vec![vec![]]);
+        let (mut first, mut rest) = (end, end);
+        for (i, (member, is_required)) in members.into_iter().enumerate().rev() {
+            let take_first = [member.clone(), vec![Element::Rule(rest)]].concat();
+            let mut take_rest = vec![Element::char(','), Element::Rule(ws)];
+            take_rest.extend(take_first.iter().cloned());
+            let (mut first_alts, mut rest_alts) = (vec![take_first], vec![take_rest]);
+            if !is_required {
+                first_alts.push(vec![Element::Rule(first)]);
+                rest_alts.push(vec![Element::Rule(rest)]);
+            }
+            let next_rest = self.b.add(&format!("{name}-rest-{i}"), rest_alts);
+            first = self.b.add(&format!("{name}-first-{i}"), first_alts);
+            rest = next_rest;
+        }
+        self.b.define(
+            object,
+            vec![vec![
+                Element::char('{'),
+                Element::Rule(first),
+                Element::char('}'),
+            ]],
+        );
+        Ok(object)
+    }
+}
+
+fn check(node: &Node, value: &Value, path: &str, out: &mut Vec<SchemaViolation>) {
+    let mut violation = |message: String| {
+        out.push(SchemaViolation {
+            path: path.to_string(),
+            message,
+        })
+    };
+    match node {
+        Node::Enum(values) => {
+            if !values.contains(value) {
+                violation(format!("{value} is not one of the enum values"));
+            }
+        }
+        Node::Null if !value.is_null() => violation(format!("expected null, got {value}")),
+        Node::Boolean if !value.is_boolean() => {
+            violation(format!("expected a boolean, got {value}"))
+        }
+        Node::Number if !value.is_number() => violation(format!("expected a number, got {value}")),
+        Node::Integer if !(value.is_i64() || value.is_u64()) => {
+            violation(format!("expected an integer, got {value}"))
+        }
+        Node::String {
+            min_length,
+            max_length,
+        } => match value.as_str() {
+            None => violation(format!("expected a string, got {value}")),
+            Some(s) => {
+                let len = s.chars().count();
+                if len < *min_length || max_length.is_some_and(|max| len > max) {
+                    violation(format!(
+                        "string of length {len} outside minLength {min_length}..maxLength {}",
+                        max_length.map_or("none".to_string(), |m| m.to_string())
+                    ));
+                }
+            }
+        },
+        Node::Array {
+            items,
+            min_items,
+            max_items,
+        } => match value.as_array() {
+            None => violation(format!("expected an array, got {value}")),
+            Some(elements) => {
+                if elements.len() < *min_items || max_items.is_some_and(|max| elements.len() > max)
+                {
+                    violation(format!(
+                        "array of {} items outside minItems {min_items}..maxItems {}",
+                        elements.len(),
+                        max_items.map_or("none".to_string(), |m| m.to_string())
+                    ));
+                }
+                for (i, element) in elements.iter().enumerate() {
+                    check(items, element, &format!("{path}[{i}]"), out);
+                }
+            }
+        },
+        Node::Object {
+            properties,
+            required,
+        } => match value.as_object() {
+            None => violation(format!("expected an object, got {value}")),
+            Some(object) => {
+                for name in required {
+                    if !object.contains_key(name) {
+                        violation(format!("missing required property '{name}'"));
+                    }
+                }
+                for key in object.keys() {
+                    if !properties.iter().any(|(p, _)| p == key) {
+                        violation(format!("unexpected property '{key}'"));
+                    }
+                }
+                for (name, node) in properties {
+                    if let Some(v) = object.get(name) {
+                        check(node, v, &format!("{path}.{name}"), out);
+                    }
+                }
+            }
+        },
+        Node::Null | Node::Boolean | Node::Number | Node::Integer => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::grammar::GrammarState;
+
+    fn accepts(schema: &JsonSchema, text: &str) -> bool {
+        let mut state = GrammarState::new(schema.grammar().clone());
+        state.advance(text).is_ok() && state.is_complete()
+    }
+
+    #[test]
+    fn nested_objects_and_enums_compile_to_grammar() {
+        let schema = JsonSchema::parse(
+            r#"{"type":"object","properties":{
+                "name":{"type":"string","maxLength":2},
+                "color":{"enum":["red","blue"]},
+                "meta":{"type":"object","properties":{"ok":{"type":"boolean"}},"required":["ok"]}
+            },"required":["name","meta"]}"#,
+        )
+        .unwrap();
+        let gbnf = schema.grammar().to_gbnf();
+        assert!(gbnf.starts_with("root ::= ws value\n"), "{gbnf}");
+        assert!(
+            gbnf.contains("value-color ::= \"\\\"red\\\"\" | \"\\\"blue\\\"\"\n"),
+            "{gbnf}"
+        );
+        assert!(
+            gbnf.contains("value-meta-ok ::= \"true\" | \"false\"\n"),
+            "{gbnf}"
+        );
+        assert!(
+            gbnf.contains("value-name ::= \"\\\"\" value-name-chars-2 \"\\\"\"\n"),
+            "{gbnf}"
+        );
+        assert!(
+            gbnf.contains("char ::= [^\"\\\\\\x00-\\x1F] | \"\\\\\" escape\n"),
+            "{gbnf}"
+        );
+
+        for ok in [
+            r#"{"meta":{"ok":true},"name":"ab"}"#,
+            r#" {"color": "blue", "meta": {"ok":false}, "name":""}"#,
+            r#"{"color":"red","meta":{"ok":true},"name":"\n"}"#,
+        ] {
+            assert!(accepts(&schema, ok), "{ok}");
+            schema.validate(ok).unwrap();
+        }
+        for bad in [
+            r#"{"meta":{"ok":true},"name":"abc"}"#,
+            r#"{"name":"a","meta":{"ok":true}}"#,
+            r#"{"color":"green","meta":{"ok":true},"name":"a"}"#,
+            r#"{"meta":{},"name":"a"}"#,
+            r#"{"name":"a"}"#,
+        ] {
+            assert!(!accepts(&schema, bad), "{bad}");
+        }
+    }
+
+    #[test]
+    fn arrays_numbers_and_optional_properties() {
+        let schema = JsonSchema::parse(
+            r#"{"type":"object","properties":{
+                "n":{"type":"integer"},"x":{"type":"number"},
+                "tags":{"type":"array","items":{"const":"a"},"minItems":1,"maxItems":2}
+            }}"#,
+        )
+        .unwrap();
+        for ok in [
+            "{}",
+            r#"{"x":-0.5e+3}"#,
+            r#"{"n":10,"tags":["a", "a"]}"#,
+            r#"{"tags":["a"]}"#,
+        ] {
+            assert!(accepts(&schema, ok), "{ok}");
+        }
+        for bad in [
+            r#"{"n":01}"#,
+            r#"{"n":1.5}"#,
+            r#"{"tags":[]}"#,
+            r#"{"tags":["a","a","a"]}"#,
+            r#"{,"n":1}"#,
+            r#"{"n":1,}"#,
+        ] {
+            assert!(!accepts(&schema, bad), "{bad}");
+        }
+    }
+
+    #[test]
+    fn validation_lists_every_violation() {
+        let schema = JsonSchema::parse(
+            r#"{"type":"object","properties":{"a":{"type":"integer"},"b":{"type":"array","items":{"type":"string"}}},"required":["a","b"]}"#,
+        )
+        .unwrap();
+        let Err(JsonSchemaError::Violations(violations)) =
+            schema.validate(r#"{"b":["x",2],"c":null}"#)
+        else {
+            panic!("expected violations");
+        };
+        let listed: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            listed,
+            [
+                "$: missing required property 'a'",
+                "$: unexpected property 'c'",
+                "$.b[1]: expected a string, got 2",
+            ]
+        );
+        let err = schema.validate("{\"a\":").unwrap_err().to_string();
+        assert!(
+            err.starts_with("output does not match the JSON schema: $: not valid JSON"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn unsupported_features_are_named() {
+        for (schema, message) in [
+            (
+                r##"{"$ref":"#/defs/x"}"##,
+                "unsupported feature at #: keyword '$ref'",
+            ),
+            (
+                r#"{"oneOf":[{"type":"string"}],"type":"string"}"#,
+                "unsupported feature at #: keyword 'oneOf'",
+            ),
+            (
+                r#"{"type":"object","properties":{"id":{"type":"string","pattern":"^a"}}}"#,
+                "unsupported feature at #/properties/id: keyword 'pattern'",
+            ),
+            (
+                r#"{"type":["string","null"]}"#,
+                "unsupported feature at #: type arrays (unions)",
+            ),
+            (
+                r#"{"type":"object","additionalProperties":true}"#,
+                "unsupported feature at #: additionalProperties other than false",
+            ),
+            (
+                r#"{"type":"array"}"#,
+                "unsupported feature at #: arrays without an 'items' schema",
+            ),
+        ] {
+            let err = JsonSchema::parse(schema).unwrap_err().to_string();
+            assert!(err.contains(message), "{schema}: {err}");
+        }
+        let err = JsonSchema::parse(r#"{"type":"object","properties":{},"required":["x"]}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("required names 'x', which is not in properties"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Choosing the KV cache dtype from the context length and the memory there is to hold it.
+//!
+//! [`KvCachePolicy::Auto`] tries [`CacheDtype::F32`], then [`CacheDtype::F16`], then
+//! [`CacheDtype::Q8`], and takes the first whose cache fits the [`MemoryBudget`]; if even q8 does
+//! not fit, it fails with [`KvCachePolicyError::DoesNotFit`], which says how long a context would.
+//! [`KvCachePolicy::Pinned`] skips the search. Sizes come from
+//! [`kv_cache_bytes`](crate::layers::attention::kv_cache_bytes), the same estimate
+//! [`KVCache::allocated_bytes`](crate::layers::attention::KVCache::allocated_bytes) reports once
+//! the cache exists.
+//!
+//! The budget covers the KV cache alone, however the weights are stored (heap, mapped or shared
+//! cache). The policy runs after the model is loaded, and [`MemoryBudget::Detect`] measures the
+//! memory available at that point.
+
+use thiserror::Error;
+
+use crate::engine::effective_config::Provenance;
+use crate::layers::attention::{CacheDtype, kv_cache_bytes};
+use crate::model_config::ModelConfig;
+
+/// Dtypes [`KvCachePolicy::Auto`] tries, most exact first.
+pub const AUTO_ORDER: [CacheDtype; 3] = [CacheDtype::F32, CacheDtype::F16, CacheDtype::Q8];
+
+/// Memory the KV cache may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryBudget {
+    /// What the OS reports as available ([`available_memory`]). Where that is unknown, the cache
+    /// is not limited and [`CacheDtype::F32`] is chosen with a warning.
+    #[default]
+    Detect,
+    Bytes(usize),
+}
+
+/// How the KV cache dtype is picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvCachePolicy {
+    /// The most exact dtype that fits the budget.
+    Auto(MemoryBudget),
+    /// Always `dtype`, whatever it costs; `source` is recorded as its provenance.
+    Pinned {
+        dtype: CacheDtype,
+        source: Provenance,
+    },
+}
+
+impl Default for KvCachePolicy {
+    fn default() -> Self {
+        KvCachePolicy::Auto(MemoryBudget::Detect)
+    }
+}
+
+/// What [`KvCachePolicy::resolve`] decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KvCacheChoice {
+    pub dtype: CacheDtype,
+    /// Size of the chosen cache for the requested context.
+    pub bytes: usize,
+    /// Budget the choice was checked against; `None` when pinned or undetectable.
+    pub budget: Option<usize>,
+    /// [`Provenance::Auto`] unless pinned.
+    pub provenance: Provenance,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum KvCachePolicyError {
+    #[error(
+        "KV cache for {context_len} tokens does not fit in the {} memory budget: it needs {} as f32, {} as f16 and {} as q8. Use a context of at most {max_context} tokens, or allow more memory",
+        human_bytes(*.budget),
+        human_bytes(.needed[0]),
+        human_bytes(.needed[1]),
+        human_bytes(.needed[2])
+    )]
+    DoesNotFit {
+        context_len: usize,
+        budget: usize,
+        /// Cache size for each of [`AUTO_ORDER`].
+        needed: [usize; 3],
+        /// Longest context whose q8 cache fits `budget`.
+        max_context: usize,
+    },
+}
+
+impl KvCachePolicy {
+    /// Pick the dtype for a cache of `context_len` timesteps per layer of `config`.
+    pub fn resolve(
+        self,
+        config: &ModelConfig,
+        context_len: usize,
+    ) -> Result<KvCacheChoice, KvCachePolicyError> {
+        self.resolve_with(config, context_len, available_memory)
+    }
+
+    /// [`Self::resolve`] with `detect` standing in for [`available_memory`].
+    pub fn resolve_with(
+        self,
+        config: &ModelConfig,
+        context_len: usize,
+        detect: impl FnOnce() -> Option<usize>,
+    ) -> Result<KvCacheChoice, KvCachePolicyError> {
+        let budget = match self {
+            KvCachePolicy::Pinned { dtype, source } => {
+                let choice = KvCacheChoice {
+                    dtype,
+                    bytes: kv_cache_bytes(config, context_len, dtype),
+                    budget: None,
+                    provenance: source,
+                };
+                log_choice(&choice, context_len);
+                return Ok(choice);
+            }
+            KvCachePolicy::Auto(MemoryBudget::Bytes(bytes)) => Some(bytes),
+            KvCachePolicy::Auto(MemoryBudget::Detect) => detect(),
+        };
+        let Some(budget) = budget else {
+            log::warn!("available memory is unknown; KV cache left at f32");
+            let choice = KvCacheChoice {
+                dtype: CacheDtype::F32,
+                bytes: kv_cache_bytes(config, context_len, CacheDtype::F32),
+                budget: None,
+                provenance: Provenance::Auto,
+            };
+            log_choice(&choice, context_len);
+            return Ok(choice);
+        };
+
+        let needed = AUTO_ORDER.map(|dtype| kv_cache_bytes(config, context_len, dtype));
+        let Some(i) = needed.iter().position(|&bytes| bytes <= budget) else {
+            let per_token = kv_cache_bytes(config, 1, CacheDtype::Q8);
+            let err = KvCachePolicyError::DoesNotFit {
+                context_len,
+                budget,
+                needed,
+                max_context: budget.checked_div(per_token).unwrap_or(usize::MAX),
+            };
+            log::error!("{err}");
+            return Err(err);
+        };
+        let choice = KvCacheChoice {
+            dtype: AUTO_ORDER[i],
+            bytes: needed[i],
+            budget: Some(budget),
+            provenance: Provenance::Auto,
+        };
+        log_choice(&choice, context_len);
+        Ok(choice)
+    }
+}
+
+fn log_choice(choice: &KvCacheChoice, context_len: usize) {
+    let budget = choice
+        .budget
+        .map_or(String::new(), |b| format!(", budget {}", human_bytes(b)));
+    log::info!(
+        "KV cache: {} ({}) for {context_len} tokens, {}{budget}",
+        choice.dtype.name(),
+        choice.provenance.name(),
+        human_bytes(choice.bytes)
+    );
+}
+
+/// `1536` → `"1.5 KiB"`.
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Memory the OS could hand out now without swapping (`MemAvailable` on Linux); `None` where
+/// there is no such figure.
+pub fn available_memory() -> Option<usize> {
+    imp::available_memory()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    pub fn available_memory() -> Option<usize> {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        super::parse_mem_available(&meminfo)
+    }
+}
+
+/// Platforms without a supported memory query. Compiled everywhere so it is tested everywhere.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+mod fallback {
+    pub fn available_memory() -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+use fallback as imp;
+
+/// `MemAvailable` from `/proc/meminfo` contents, in bytes.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mem_available(meminfo: &str) -> Option<usize> {
+    let line = meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))?;
+    let kib: usize = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    kib.checked_mul(1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meminfo_parsing_and_fallback() {
+        let meminfo = "MemTotal:       16314204 kB\nMemFree:         1043828 kB\n\
+                       MemAvailable:    9817172 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(9817172 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+        assert_eq!(parse_mem_available("MemAvailable: lots\n"), None);
+        assert_eq!(fallback::available_memory(), None);
+        if cfg!(target_os = "linux") {
+            assert!(available_memory().is_some_and(|b| b > 0));
+        }
+    }
+
+    #[test]
+    fn bytes_are_shown_in_the_largest_whole_unit() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(3 << 30), "3.0 GiB");
+    }
+}
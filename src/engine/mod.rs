@@ -1,6 +1,26 @@
+pub mod budget;
+pub mod chat_session;
+pub mod config;
+pub mod deadline;
+pub mod decode_trace;
+pub mod effective_config;
 pub mod embed;
 pub mod generation;
+pub mod grammar;
+pub mod guidance;
+pub mod json_schema;
+pub mod kv_policy;
+pub mod observer;
+pub mod quality;
 pub mod runtime;
 pub mod sampling;
+pub mod seed;
 pub mod session;
 pub mod state;
+pub mod text_stream;
+pub mod thread_pool;
+pub mod token_iter;
+#[cfg(feature = "async")]
+pub mod token_stream;
+pub mod transcript;
+pub mod watchdog;
@@ -0,0 +1,255 @@
+//! Engine events for front ends that show more than the generated text: load progress, prefill
+//! progress, each token, context shifts, cancellation and errors.
+//!
+//! Install an [`EngineObserver`] with [`InferenceSession::set_observer`] (generation events) or
+//! pass one to [`LoadedModel::load_observed`] (load events). Every method has an empty default,
+//! so an observer implements only what it shows. With no observer set, each emit point costs one
+//! `Option` check and builds no payload.
+//!
+//! Events are delivered synchronously, on the thread that called the operation, in the order
+//! they happen. Every event of an operation is delivered before that operation returns, and none
+//! after. A UI thread that cannot be called into directly can take them from a channel through
+//! [`ObserverToChannel`].
+//!
+//! Which operations emit:
+//! - [`LoadedModel::load_observed`]: `load_started`, `load_finished` or `error`;
+//! - [`generate_from_ids`] (so `generate` and friends) and [`TokenIter`] (so
+//!   [`InferenceSession::tokens`] and `stream_text`): `generation_started`, `prefill_progress`
+//!   after each prefill chunk, `token`, `context_shift`, `generation_finished` or `error`;
+//!   both also report `quality_alert` (as does guided generation) when
+//!   [`GenerationConfig::quality`](crate::engine::generation::GenerationConfig::quality) is set;
+//! - `stream_text` also reports `cancelled` when its callback stops generation.
+//! - [`score_completions`] reports `prefill_progress` only.
+//! - A session's watchdog ([`InferenceSession::set_watchdog`]) reports `stall_detected` from its
+//!   own thread while the stalled decode step is still running, the one exception to the rules
+//!   above.
+//!
+//! Otherwise direct [`InferenceSession::prefill`] / [`InferenceSession::decode_token`] calls emit
+//! nothing.
+//!
+//! [`LoadedModel::load_observed`]: crate::loaded_model::LoadedModel::load_observed
+//! [`generate_from_ids`]: crate::engine::generation::generate_from_ids
+//! [`score_completions`]: crate::engine::generation::score_completions
+//! [`TokenIter`]: crate::engine::token_iter::TokenIter
+//! [`InferenceSession::set_observer`]: crate::engine::session::InferenceSession::set_observer
+//! [`InferenceSession::tokens`]: crate::engine::session::InferenceSession::tokens
+//! [`InferenceSession::set_watchdog`]: crate::engine::session::InferenceSession::set_watchdog
+//! [`InferenceSession::prefill`]: crate::engine::session::InferenceSession::prefill
+//! [`InferenceSession::decode_token`]: crate::engine::session::InferenceSession::decode_token
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use crate::EngineError;
+use crate::engine::generation::FinishReason;
+use crate::engine::quality::QualityAlert;
+use crate::engine::watchdog::StallReport;
+
+/// A model load began.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadStarted {
+    pub path: String,
+}
+
+/// A model load completed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadFinished {
+    pub path: String,
+    pub tensors: usize,
+    pub bytes_read: u64,
+    pub elapsed: Duration,
+}
+
+/// Prompt tokens through the model so far, out of `total`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefillProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Generation began; sent before the prefill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStarted {
+    pub prompt_tokens: usize,
+    pub forced_tokens: usize,
+    pub max_new_tokens: usize,
+}
+
+/// One sampled token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenGenerated {
+    /// Position in the generated span, from 0.
+    pub index: usize,
+    pub id: u32,
+    pub logprob: f32,
+}
+
+/// Why the cache was shifted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftReason {
+    /// The next token would not fit in the context.
+    ContextFull,
+}
+
+/// Tokens were dropped from the cache to make room (see
+/// [`ContextShift`](crate::engine::budget::ContextShift)).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextShifted {
+    pub discarded: usize,
+    /// Tokens left in the cache.
+    pub kept: usize,
+    pub reason: ShiftReason,
+}
+
+/// Generation ended normally.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationFinished {
+    pub generated: usize,
+    pub reason: FinishReason,
+    pub elapsed: Duration,
+}
+
+/// The caller stopped generation early.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cancelled {
+    pub generated: usize,
+}
+
+/// What was running when an [`EngineFailed`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Load,
+    Generation,
+}
+
+/// An operation failed; its error is returned right after this event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineFailed {
+    pub operation: Operation,
+    pub message: String,
+}
+
+impl EngineFailed {
+    pub fn new(operation: Operation, error: &EngineError) -> Self {
+        Self {
+            operation,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Receives engine events; see the module docs for delivery guarantees.
+pub trait EngineObserver: Send + Sync {
+    fn load_started(&self, _event: &LoadStarted) {}
+    fn load_finished(&self, _event: &LoadFinished) {}
+    fn prefill_progress(&self, _event: &PrefillProgress) {}
+    fn generation_started(&self, _event: &GenerationStarted) {}
+    fn token(&self, _event: &TokenGenerated) {}
+    fn context_shift(&self, _event: &ContextShifted) {}
+    fn generation_finished(&self, _event: &GenerationFinished) {}
+    fn cancelled(&self, _event: &Cancelled) {}
+    fn error(&self, _event: &EngineFailed) {}
+    fn stall_detected(&self, _event: &StallReport) {}
+    fn quality_alert(&self, _event: &QualityAlert) {}
+}
+
+/// Any event, as sent by [`ObserverToChannel`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    LoadStarted(LoadStarted),
+    LoadFinished(LoadFinished),
+    PrefillProgress(PrefillProgress),
+    GenerationStarted(GenerationStarted),
+    Token(TokenGenerated),
+    ContextShift(ContextShifted),
+    GenerationFinished(GenerationFinished),
+    Cancelled(Cancelled),
+    Error(EngineFailed),
+    StallDetected(StallReport),
+    QualityAlert(QualityAlert),
+}
+
+/// Forwards every event to a channel, e.g. for a UI thread to drain. Events sent after the
+/// receiver is dropped are discarded.
+#[derive(Debug, Clone)]
+pub struct ObserverToChannel {
+    sender: Sender<EngineEvent>,
+}
+
+impl ObserverToChannel {
+    pub fn new(sender: Sender<EngineEvent>) -> Self {
+        Self { sender }
+    }
+
+    fn send(&self, event: EngineEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+impl EngineObserver for ObserverToChannel {
+    fn load_started(&self, event: &LoadStarted) {
+        self.send(EngineEvent::LoadStarted(event.clone()));
+    }
+    fn load_finished(&self, event: &LoadFinished) {
+        self.send(EngineEvent::LoadFinished(event.clone()));
+    }
+    fn prefill_progress(&self, event: &PrefillProgress) {
+        self.send(EngineEvent::PrefillProgress(event.clone()));
+    }
+    fn generation_started(&self, event: &GenerationStarted) {
+        self.send(EngineEvent::GenerationStarted(event.clone()));
+    }
+    fn token(&self, event: &TokenGenerated) {
+        self.send(EngineEvent::Token(event.clone()));
+    }
+    fn context_shift(&self, event: &ContextShifted) {
+        self.send(EngineEvent::ContextShift(event.clone()));
+    }
+    fn generation_finished(&self, event: &GenerationFinished) {
+        self.send(EngineEvent::GenerationFinished(event.clone()));
+    }
+    fn cancelled(&self, event: &Cancelled) {
+        self.send(EngineEvent::Cancelled(event.clone()));
+    }
+    fn error(&self, event: &EngineFailed) {
+        self.send(EngineEvent::Error(event.clone()));
+    }
+    fn stall_detected(&self, event: &StallReport) {
+        self.send(EngineEvent::StallDetected(event.clone()));
+    }
+    fn quality_alert(&self, event: &QualityAlert) {
+        self.send(EngineEvent::QualityAlert(event.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct OnlyTokens(std::sync::Mutex<Vec<u32>>);
+
+    impl EngineObserver for OnlyTokens {
+        fn token(&self, event: &TokenGenerated) {
+            self.0.lock().unwrap().push(event.id);
+        }
+    }
+
+    #[test]
+    fn default_methods_ignore_events_and_channel_forwards_them() {
+        let observer = OnlyTokens(Default::default());
+        observer.cancelled(&Cancelled { generated: 1 });
+        observer.token(&TokenGenerated {
+            index: 0,
+            id: 7,
+            logprob: -0.5,
+        });
+        assert_eq!(*observer.0.lock().unwrap(), [7]);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let forward = ObserverToChannel::new(tx);
+        forward.cancelled(&Cancelled { generated: 3 });
+        drop(forward);
+        let events: Vec<_> = rx.iter().collect();
+        assert_eq!(events, [EngineEvent::Cancelled(Cancelled { generated: 3 })]);
+    }
+}
@@ -0,0 +1,222 @@
+//! Per-generation quality telemetry: how surprised the model is by its own samples.
+//!
+//! A generation that goes off the rails (a quantization bug, a broken context shift) samples
+//! tokens the model itself found unlikely, from flat distributions. With
+//! [`GenerationConfig::quality`](crate::engine::generation::GenerationConfig::quality) set, each
+//! sampled token's surprise (`-logprob`) and the entropy of the model's next-token distribution
+//! are fed to a [`QualityMonitor`], which keeps whole-run and rolling-window aggregates in
+//! [`QualityStats`] and raises a [`QualityAlert`] (an observer event, see
+//! [`crate::engine::observer`]) when a rolling mean crosses a [`QualityThresholds`] limit.
+//!
+//! Both signals are in nats over the unscaled logits, like
+//! [`GenerationOutput::generated_logprobs`](crate::engine::generation::GenerationOutput), so
+//! they do not move with the temperature. Entropy costs one pass over the logits per step;
+//! everything else is O(1) per step.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// A sample counts as near-argmax when its probability is at least half the most likely
+/// token's, i.e. its logit is within `ln 2` of the largest.
+pub const NEAR_ARGMAX_NATS: f32 = std::f32::consts::LN_2;
+
+/// When to raise a [`QualityAlert`]. Means are over the last [`Self::window`] sampled tokens and
+/// only checked once that many have been sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QualityThresholds {
+    /// Tokens in the rolling window; 0 is taken as 1.
+    pub window: usize,
+    /// Alert when the rolling mean surprise exceeds this many nats. `None`: never.
+    pub mean_surprise: Option<f32>,
+    /// Alert when the rolling mean entropy exceeds this many nats. `None`: never.
+    pub mean_entropy: Option<f32>,
+}
+
+impl Default for QualityThresholds {
+    /// A 16-token window; alert past 5 nats of mean surprise (the sampled tokens averaging
+    /// under 1% probability), no entropy limit.
+    fn default() -> Self {
+        Self {
+            window: 16,
+            mean_surprise: Some(5.0),
+            mean_entropy: None,
+        }
+    }
+}
+
+/// Which rolling mean crossed its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QualitySignal {
+    MeanSurprise,
+    MeanEntropy,
+}
+
+/// A rolling mean went above its [`QualityThresholds`] limit. Raised once per crossing: the
+/// signal must drop back to or below the limit before it can fire again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityAlert {
+    /// Index in the generated span of the token that tripped it.
+    pub index: usize,
+    pub signal: QualitySignal,
+    /// The rolling mean, in nats.
+    pub value: f32,
+    pub threshold: f32,
+    pub window: usize,
+}
+
+/// Aggregates of one generation's samples. Surprise and entropy are in nats.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QualityStats {
+    pub tokens: usize,
+    pub mean_surprise: f32,
+    pub max_surprise: f32,
+    /// Over the last `window` tokens (all of them until the window fills).
+    pub rolling_mean_surprise: f32,
+    pub mean_entropy: f32,
+    pub max_entropy: f32,
+    pub rolling_mean_entropy: f32,
+    /// Samples within [`NEAR_ARGMAX_NATS`] of the most likely token.
+    pub near_argmax: usize,
+    /// Every other sample.
+    pub tail: usize,
+    /// [`QualityAlert`]s raised.
+    pub alerts: usize,
+}
+
+/// Running sums behind [`QualityStats`], fed one sampled token at a time.
+#[derive(Debug, Clone)]
+pub struct QualityMonitor {
+    thresholds: QualityThresholds,
+    /// `(surprise, entropy)` of the last `window` tokens.
+    recent: VecDeque<(f64, f64)>,
+    recent_sums: (f64, f64),
+    sums: (f64, f64),
+    /// Signals currently above their threshold, so each crossing alerts once.
+    tripped: [bool; 2],
+    stats: QualityStats,
+}
+
+impl QualityMonitor {
+    pub fn new(thresholds: QualityThresholds) -> Self {
+        let window = thresholds.window.max(1);
+        Self {
+            thresholds: QualityThresholds {
+                window,
+                ..thresholds
+            },
+            recent: VecDeque::with_capacity(window),
+            recent_sums: (0.0, 0.0),
+            sums: (0.0, 0.0),
+            tripped: [false; 2],
+            stats: QualityStats::default(),
+        }
+    }
+
+    /// Record token `token` sampled from `logits` with log-probability `logprob` (under the
+    /// same logits). Returns the alerts this token raised, at most one per signal.
+    pub fn observe(&mut self, logits: &[f32], token: u32, logprob: f32) -> Vec<QualityAlert> {
+        let (entropy, max_logit) = entropy_and_max(logits);
+        let surprise = -f64::from(logprob);
+        let near = logits
+            .get(token as usize)
+            .is_some_and(|&l| l >= max_logit - NEAR_ARGMAX_NATS);
+
+        let index = self.stats.tokens;
+        let s = &mut self.stats;
+        s.tokens += 1;
+        s.max_surprise = s.max_surprise.max(surprise as f32);
+        s.max_entropy = s.max_entropy.max(entropy as f32);
+        if near {
+            s.near_argmax += 1;
+        } else {
+            s.tail += 1;
+        }
+        self.sums.0 += surprise;
+        self.sums.1 += entropy;
+        if self.recent.len() == self.thresholds.window
+            && let Some((old_s, old_e)) = self.recent.pop_front()
+        {
+            self.recent_sums.0 -= old_s;
+            self.recent_sums.1 -= old_e;
+        }
+        self.recent.push_back((surprise, entropy));
+        self.recent_sums.0 += surprise;
+        self.recent_sums.1 += entropy;
+
+        let n = s.tokens as f64;
+        let recent = self.recent.len() as f64;
+        s.mean_surprise = (self.sums.0 / n) as f32;
+        s.mean_entropy = (self.sums.1 / n) as f32;
+        s.rolling_mean_surprise = (self.recent_sums.0 / recent) as f32;
+        s.rolling_mean_entropy = (self.recent_sums.1 / recent) as f32;
+
+        let full = self.recent.len() == self.thresholds.window;
+        let checks = [
+            (
+                QualitySignal::MeanSurprise,
+                self.thresholds.mean_surprise,
+                s.rolling_mean_surprise,
+            ),
+            (
+                QualitySignal::MeanEntropy,
+                self.thresholds.mean_entropy,
+                s.rolling_mean_entropy,
+            ),
+        ];
+        let mut alerts = Vec::new();
+        for ((signal, threshold, value), tripped) in checks.into_iter().zip(&mut self.tripped) {
+            let Some(threshold) = threshold else { continue };
+            let above = full && value > threshold;
+            if above && !*tripped {
+                alerts.push(QualityAlert {
+                    index,
+                    signal,
+                    value,
+                    threshold,
+                    window: self.thresholds.window,
+                });
+            }
+            *tripped = above;
+        }
+        s.alerts += alerts.len();
+        alerts
+    }
+
+    pub fn stats(&self) -> &QualityStats {
+        &self.stats
+    }
+
+    pub fn into_stats(self) -> QualityStats {
+        self.stats
+    }
+}
+
+/// Entropy of `softmax(logits)` in nats, and the largest logit. `H = log Z - sum(p_i * x_i)`
+/// with `x_i` shifted by the max, in f64.
+fn entropy_and_max(logits: &[f32]) -> (f64, f32) {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let (mut z, mut weighted) = (0.0f64, 0.0f64);
+    for &l in logits {
+        let x = f64::from(l - max);
+        let e = x.exp();
+        if e > 0.0 {
+            z += e;
+            weighted += e * x;
+        }
+    }
+    ((z.ln() - weighted / z).max(0.0), max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entropy_of_uniform_and_peaked_distributions() {
+        let (h, max) = entropy_and_max(&[0.0; 8]);
+        assert!((h - 8f64.ln()).abs() < 1e-12 && max == 0.0);
+        let (h, _) = entropy_and_max(&[100.0, 0.0, 0.0, f32::NEG_INFINITY]);
+        assert!(h < 1e-12, "{h}");
+    }
+}
@@ -1,14 +1,18 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::EngineError;
 use crate::core::tensor::{Tensor, TensorType};
+use crate::core::time::Instant;
+use crate::engine::config::LayerSchedule;
 use crate::engine::state::ForwardState;
+use crate::engine::watchdog::checkpoint;
 use crate::layers::attention::KVCache;
 use crate::layers::block::{decode_layer_block, prefill_layer_block};
 use crate::model_config::ModelConfig;
 use crate::model_weights::ModelWeights;
 use crate::ops::matmul::matmul;
-use crate::ops::rmsnorm::rmsnorm;
+use crate::ops::rmsnorm::rmsnorm_with_offset;
 
 /// Run the transformer stack over prompt activations and populate KV caches.
 pub fn prefill_forward(
@@ -16,6 +20,42 @@ pub fn prefill_forward(
     config: &ModelConfig,
     weights: &ModelWeights,
     kv_caches: &mut [KVCache],
+) -> Result<ForwardState, EngineError> {
+    prefill_forward_timed(input, config, weights, kv_caches, None)
+}
+
+/// [`prefill_forward`], also writing each layer's wall time into `layer_times` (resized to the
+/// layer count, indexed by layer) when given.
+pub fn prefill_forward_timed(
+    input: &ForwardState,
+    config: &ModelConfig,
+    weights: &ModelWeights,
+    kv_caches: &mut [KVCache],
+    layer_times: Option<&mut Vec<Duration>>,
+) -> Result<ForwardState, EngineError> {
+    prefill_forward_with(
+        input,
+        config,
+        weights,
+        kv_caches,
+        LayerSchedule::All,
+        layer_times,
+        None,
+    )
+}
+
+/// [`prefill_forward_timed`] running only the blocks `schedule` selects; skipped layers keep
+/// their cache untouched and a zero time. The caller keeps `schedule` constant across a
+/// session (see [`LayerSchedule`]). `mask` replaces causal masking in every layer (see
+/// [`crate::layers::attention::prefill_attention_layer`]).
+pub fn prefill_forward_with(
+    input: &ForwardState,
+    config: &ModelConfig,
+    weights: &ModelWeights,
+    kv_caches: &mut [KVCache],
+    schedule: LayerSchedule,
+    layer_times: Option<&mut Vec<Duration>>,
+    mask: Option<&[f32]>,
 ) -> Result<ForwardState, EngineError> {
     if kv_caches.len() != weights.layers.len() {
         return Err(EngineError::Model(
@@ -23,13 +63,17 @@ pub fn prefill_forward(
         ));
     }
 
-    let mut state = input.replace_hidden(input.hidden().to_vec())?;
-
-    for (layer_idx, layer_weights) in weights.layers.iter().enumerate() {
-        state = prefill_layer_block(&state, config, layer_idx, layer_weights, kv_caches)?;
-    }
-
-    Ok(state)
+    let state = input.replace_hidden(input.hidden().to_vec())?;
+    run_layers(state, weights, schedule, layer_times, |state, layer_idx| {
+        prefill_layer_block(
+            state,
+            config,
+            layer_idx,
+            &weights.layers[layer_idx],
+            kv_caches,
+            mask,
+        )
+    })
 }
 
 /// One autoregressive step: `input` must be a single token (`seq_len == 1`). Each layer appends
@@ -39,6 +83,39 @@ pub fn decode_forward(
     config: &ModelConfig,
     weights: &ModelWeights,
     kv_caches: &mut [KVCache],
+) -> Result<ForwardState, EngineError> {
+    decode_forward_timed(input, config, weights, kv_caches, None)
+}
+
+/// [`decode_forward`] with per-layer timing, as in [`prefill_forward_timed`].
+pub fn decode_forward_timed(
+    input: &ForwardState,
+    config: &ModelConfig,
+    weights: &ModelWeights,
+    kv_caches: &mut [KVCache],
+    layer_times: Option<&mut Vec<Duration>>,
+) -> Result<ForwardState, EngineError> {
+    decode_forward_with(
+        input,
+        config,
+        weights,
+        kv_caches,
+        LayerSchedule::All,
+        layer_times,
+        None,
+    )
+}
+
+/// [`decode_forward_timed`] under a [`LayerSchedule`] and optional `mask`, as in
+/// [`prefill_forward_with`].
+pub fn decode_forward_with(
+    input: &ForwardState,
+    config: &ModelConfig,
+    weights: &ModelWeights,
+    kv_caches: &mut [KVCache],
+    schedule: LayerSchedule,
+    layer_times: Option<&mut Vec<Duration>>,
+    mask: Option<&[f32]>,
 ) -> Result<ForwardState, EngineError> {
     if input.seq_len() != 1 {
         return Err(EngineError::Model(
@@ -51,12 +128,49 @@ pub fn decode_forward(
         ));
     }
 
-    let mut state = input.replace_hidden(input.hidden().to_vec())?;
+    let state = input.replace_hidden(input.hidden().to_vec())?;
+    run_layers(state, weights, schedule, layer_times, |state, layer_idx| {
+        decode_layer_block(
+            state,
+            config,
+            layer_idx,
+            &weights.layers[layer_idx],
+            kv_caches,
+            mask,
+        )
+    })
+}
 
-    for (layer_idx, layer_weights) in weights.layers.iter().enumerate() {
-        state = decode_layer_block(&state, config, layer_idx, layer_weights, kv_caches)?;
+/// Apply `layer` for every scheduled layer in order, timing each call if `layer_times` is given.
+fn run_layers(
+    mut state: ForwardState,
+    weights: &ModelWeights,
+    schedule: LayerSchedule,
+    layer_times: Option<&mut Vec<Duration>>,
+    mut layer: impl FnMut(&ForwardState, usize) -> Result<ForwardState, EngineError>,
+) -> Result<ForwardState, EngineError> {
+    let n_layers = weights.layers.len();
+    match layer_times {
+        None => {
+            for layer_idx in (0..n_layers).filter(|&i| schedule.runs(i)) {
+                checkpoint()?;
+                state = layer(&state, layer_idx)?;
+            }
+        }
+        Some(times) => {
+            times.clear();
+            times.resize(n_layers, Duration::ZERO);
+            for (layer_idx, time) in times.iter_mut().enumerate() {
+                if !schedule.runs(layer_idx) {
+                    continue;
+                }
+                let start = Instant::now();
+                checkpoint()?;
+                state = layer(&state, layer_idx)?;
+                *time = start.elapsed();
+            }
+        }
     }
-
     Ok(state)
 }
 
@@ -73,16 +187,46 @@ pub fn decode_from_embedding_row(
     decode_forward(&input, config, weights, kv_caches)
 }
 
+/// LM head logits for the last position: [`final_norm_last_token`], then `lm_head`, then the
+/// optional final logit soft-capping. `config.vocab_size` wide; head rows past it are dropped and
+/// padding rows (ids `>= config.tokenizer_vocab_size`) are `-inf`, so they carry no probability.
 pub fn final_logits_last_token(
     input: &ForwardState,
     config: &ModelConfig,
     weights: &ModelWeights,
+) -> Result<Vec<f32>, EngineError> {
+    let normed = final_norm_last_token(input, config, weights)?;
+    let hidden_dim = normed.len();
+    let input_tensor = tensor_from_f32_slice(&normed, vec![1, hidden_dim]);
+    let head_rows = weights.lm_head.dimensions().get(1).copied();
+    let mut logits_tensor = empty_f32_tensor(vec![1, head_rows.unwrap_or(config.vocab_size)]);
+    matmul(&input_tensor, weights.lm_head, &mut logits_tensor)?;
+
+    let mut logits = logits_tensor.as_f32_slice()?.to_vec();
+    logits.truncate(config.vocab_size);
+    if let Some(cap) = config.final_logit_softcapping {
+        for z in logits.iter_mut() {
+            *z = cap * (*z / cap).tanh();
+        }
+    }
+    if let Some(padding) = logits.get_mut(config.tokenizer_vocab_size..) {
+        padding.fill(f32::NEG_INFINITY);
+    }
+    Ok(logits)
+}
+
+/// The last position's hidden state after the final RMSNorm (`output_norm.weight`, with the
+/// model's norm weight offset): exactly what the LM head is applied to.
+pub fn final_norm_last_token(
+    input: &ForwardState,
+    config: &ModelConfig,
+    weights: &ModelWeights,
 ) -> Result<Vec<f32>, EngineError> {
     let seq_len = input.seq_len();
     let hidden_dim = input.hidden_dim();
     if seq_len == 0 {
         return Err(EngineError::Model(
-            "final_logits_last_token: empty input".into(),
+            "final_norm_last_token: empty input".into(),
         ));
     }
 
@@ -90,29 +234,24 @@ pub fn final_logits_last_token(
     let last_end = last_start + hidden_dim;
     let last_hidden = &input.hidden()[last_start..last_end];
 
-    let norm_weights = weights.output_norm.as_f32_slice()?;
+    let norm_weights = weights.output_norm.as_vector()?;
     if norm_weights.len() != hidden_dim {
         return Err(EngineError::Model(format!(
-            "final_logits_last_token: output_norm len {} != hidden_dim {}",
+            "final_norm_last_token: output_norm len {} != hidden_dim {}",
             norm_weights.len(),
             hidden_dim
         )));
     }
 
     let mut normed = vec![0.0f32; hidden_dim];
-    rmsnorm(last_hidden, norm_weights, config.rms_norm_eps, &mut normed)?;
-
-    let input_tensor = tensor_from_f32_slice(&normed, vec![1, hidden_dim]);
-    let mut logits_tensor = empty_f32_tensor(vec![1, config.vocab_size]);
-    matmul(&input_tensor, weights.lm_head, &mut logits_tensor)?;
-
-    let mut logits = logits_tensor.as_f32_slice()?.to_vec();
-    if let Some(cap) = config.final_logit_softcapping {
-        for z in logits.iter_mut() {
-            *z = cap * (*z / cap).tanh();
-        }
-    }
-    Ok(logits)
+    rmsnorm_with_offset(
+        last_hidden,
+        norm_weights,
+        config.norm_weight_offset,
+        config.rms_norm_eps,
+        &mut normed,
+    )?;
+    Ok(normed)
 }
 
 fn tensor_from_f32_slice(data: &[f32], dimensions: Vec<usize>) -> Tensor {
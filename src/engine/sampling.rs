@@ -18,18 +18,62 @@ pub enum SamplingError {
 
     #[error("softmax failed")]
     SoftmaxFailed,
+
+    #[error("min_p must be in [0, 1], got {0}")]
+    InvalidMinP(f32),
+}
+
+/// Which index [`argmax_index_by`] returns when several logits equal the maximum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The lowest tied index, as [`sample_greedy`] and [`top_candidates`] do.
+    #[default]
+    LowestIndex,
+    /// The highest tied index.
+    HighestIndex,
+}
+
+/// A stage of the sampling pipeline that can move the chosen token off the argmax of the logits.
+/// Min-p is not one: it only drops tokens less likely than the argmax, never the argmax itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingStage {
+    /// A token constraint (grammar or JSON schema) ruled the argmax out.
+    Constraint,
+    /// The stochastic draw (temperature, over the min-p survivors if min-p is on) picked another
+    /// token.
+    Temperature,
 }
 
-/// Index of the largest logit. `None` if `logits` is empty or any entry is non-finite.
-/// Prefers the **first** index among ties (same as a stable greedy tie-break).
+impl SamplingStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Constraint => "constraint",
+            Self::Temperature => "temperature",
+        }
+    }
+}
+
+/// Index of the largest logit, lowest index among ties. `None` if `logits` is empty or any
+/// entry is non-finite.
 pub fn argmax_index(logits: &[f32]) -> Option<usize> {
+    argmax_index_by(logits, TieBreak::LowestIndex)
+}
+
+/// [`argmax_index`] with an explicit tie-break. The scan is sequential scalar comparisons, so the
+/// result is the same on every platform; values tie when they compare equal (`-0.0 == 0.0`).
+/// A NaN or infinite logit anywhere returns `None` rather than winning or being skipped.
+pub fn argmax_index_by(logits: &[f32], tie_break: TieBreak) -> Option<usize> {
     if logits.is_empty() || logits.iter().any(|x| !x.is_finite()) {
         return None;
     }
     let mut best = 0usize;
     let mut best_v = logits[0];
     for (i, &v) in logits.iter().enumerate().skip(1) {
-        if v > best_v {
+        let wins = match tie_break {
+            TieBreak::LowestIndex => v > best_v,
+            TieBreak::HighestIndex => v >= best_v,
+        };
+        if wins {
             best_v = v;
             best = i;
         }
@@ -37,7 +81,8 @@ pub fn argmax_index(logits: &[f32]) -> Option<usize> {
     Some(best)
 }
 
-/// Greedy choice: token id = argmax over logits.
+/// Greedy choice: token id = argmax over logits, lowest id among ties. Errors on any NaN or
+/// infinite logit.
 pub fn sample_greedy(logits: &[f32]) -> Result<u32, SamplingError> {
     argmax_index(logits).map(|i| i as u32).ok_or({
         if logits.is_empty() {
@@ -48,6 +93,27 @@ pub fn sample_greedy(logits: &[f32]) -> Result<u32, SamplingError> {
     })
 }
 
+/// Natural-log probability of `token_id` under `softmax(logits)`, computed in f64.
+/// `None` if `token_id` is out of range or `logits` is empty.
+pub fn token_logprob(logits: &[f32], token_id: u32) -> Option<f32> {
+    let target = *logits.get(token_id as usize)? as f64;
+    Some((target - log_sum_exp(logits)) as f32)
+}
+
+/// `ln(sum(exp(logits)))` in f64, shifted by the max so large logits do not overflow; a logit
+/// minus this is its log-probability.
+pub fn log_sum_exp(logits: &[f32]) -> f64 {
+    let max = logits
+        .iter()
+        .fold(f64::NEG_INFINITY, |m, &x| m.max(x as f64));
+    let log_sum = logits
+        .iter()
+        .map(|&x| (x as f64 - max).exp())
+        .sum::<f64>()
+        .ln();
+    max + log_sum
+}
+
 /// Stochastic choice: softmax(logits / `temperature`) then sample one index with `rng`.
 ///
 /// As `temperature → 0`, behavior approaches greedy (use [`sample_greedy`] for exact argmax).
@@ -78,6 +144,97 @@ pub fn sample_temperature<R: Rng + ?Sized>(
     Ok((probs.len() - 1) as u32)
 }
 
+/// Min-p filter: `(id, prob)` for every token whose probability under
+/// `softmax(logits / temperature)` is at least `p * max_prob`, renormalized to sum to 1.
+///
+/// Unlike a fixed top-k / top-p cut, the threshold scales with the model's confidence: a peaked
+/// distribution keeps only near-max tokens, a flat one keeps many. `p = 0` keeps everything.
+pub fn min_p_candidates(
+    logits: &[f32],
+    p: f32,
+    temperature: f32,
+) -> Result<Vec<(u32, f32)>, SamplingError> {
+    if logits.is_empty() {
+        return Err(SamplingError::EmptyLogits);
+    }
+    if !temperature.is_finite() || temperature <= 0.0 {
+        return Err(SamplingError::InvalidTemperature(temperature));
+    }
+    if !(0.0..=1.0).contains(&p) {
+        return Err(SamplingError::InvalidMinP(p));
+    }
+
+    let scaled: Vec<f32> = logits.iter().map(|&x| x / temperature).collect();
+    let mut probs = vec![0.0f32; scaled.len()];
+    softmax(&scaled, &mut probs).map_err(|_| SamplingError::SoftmaxFailed)?;
+
+    let max_prob = probs.iter().copied().fold(0.0f32, f32::max);
+    let threshold = p * max_prob;
+    let mut kept: Vec<(u32, f32)> = probs
+        .iter()
+        .enumerate()
+        .filter(|&(_, &q)| q >= threshold)
+        .map(|(i, &q)| (i as u32, q))
+        .collect();
+    let total: f32 = kept.iter().map(|&(_, q)| q).sum();
+    for (_, q) in &mut kept {
+        *q /= total;
+    }
+    Ok(kept)
+}
+
+/// Sample from [`min_p_candidates`]`(logits, p, temperature)`.
+pub fn sample_min_p<R: Rng + ?Sized>(
+    logits: &[f32],
+    p: f32,
+    temperature: f32,
+    rng: &mut R,
+) -> Result<u32, SamplingError> {
+    let kept = min_p_candidates(logits, p, temperature)?;
+    let r: f32 = rng.gen_range(0.0f32..1.0f32);
+    let mut cum = 0.0f32;
+    for &(id, q) in &kept {
+        cum += q;
+        if r < cum {
+            return Ok(id);
+        }
+    }
+    // Rounding left `cum` just under 1.
+    Ok(kept[kept.len() - 1].0)
+}
+
+/// The `n` most likely tokens with their `softmax(logits)` probabilities (over the **full**
+/// vocabulary, not renormalized to the top `n`), most likely first; equal logits keep the lower
+/// id first. Returns fewer than `n` entries if the vocabulary is smaller, none if `logits` is empty.
+pub fn top_candidates(logits: &[f32], n: usize) -> Vec<(u32, f32)> {
+    let n = n.min(logits.len());
+    if n == 0 {
+        return Vec::new();
+    }
+    let max = logits
+        .iter()
+        .fold(f64::NEG_INFINITY, |m, &x| m.max(x as f64));
+    let sum: f64 = logits.iter().map(|&x| (x as f64 - max).exp()).sum();
+
+    let by_rank = |a: &u32, b: &u32| {
+        logits[*b as usize]
+            .total_cmp(&logits[*a as usize])
+            .then(a.cmp(b))
+    };
+    let mut ids: Vec<u32> = (0..logits.len() as u32).collect();
+    if n < ids.len() {
+        ids.select_nth_unstable_by(n - 1, by_rank);
+        ids.truncate(n);
+    }
+    ids.sort_unstable_by(by_rank);
+    ids.into_iter()
+        .map(|id| {
+            let prob = (logits[id as usize] as f64 - max).exp() / sum;
+            (id, prob as f32)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +248,47 @@ mod tests {
         assert_eq!(sample_greedy(&logits).unwrap(), 1);
     }
 
+    #[test]
+    fn argmax_ties_are_broken_by_index() {
+        let logits = [1.0f32, 3.0, -2.0, 3.0, 3.0];
+        assert_eq!(argmax_index(&logits), Some(1));
+        assert_eq!(sample_greedy(&logits).unwrap(), 1);
+        assert_eq!(argmax_index_by(&logits, TieBreak::HighestIndex), Some(4));
+        // Signed zeros compare equal, so they tie too.
+        assert_eq!(argmax_index(&[-0.0f32, 0.0]), Some(0));
+        assert_eq!(
+            argmax_index_by(&[0.0f32, -0.0], TieBreak::HighestIndex),
+            Some(1)
+        );
+        // A single element is its own argmax either way.
+        assert_eq!(argmax_index_by(&[5.0f32], TieBreak::HighestIndex), Some(0));
+    }
+
+    #[test]
+    fn argmax_rejects_nan_wherever_it_is() {
+        for logits in [
+            [f32::NAN, 1.0, 2.0],
+            [1.0, f32::NAN, 2.0],
+            [1.0, 2.0, f32::NAN],
+        ] {
+            assert_eq!(argmax_index(&logits), None, "{logits:?}");
+            assert_eq!(argmax_index_by(&logits, TieBreak::HighestIndex), None);
+            assert!(matches!(
+                sample_greedy(&logits),
+                Err(SamplingError::InvalidLogits)
+            ));
+        }
+        assert_eq!(argmax_index(&[1.0f32, f32::INFINITY]), None);
+    }
+
+    #[test]
+    fn token_logprob_matches_softmax() {
+        let logits = [0.0f32, 0.0, (2.0f32).ln()];
+        assert!((token_logprob(&logits, 2).unwrap() - 0.5f32.ln()).abs() < 1e-6);
+        assert!((token_logprob(&logits, 0).unwrap() - 0.25f32.ln()).abs() < 1e-6);
+        assert!(token_logprob(&logits, 3).is_none());
+    }
+
     #[test]
     fn greedy_empty_errors() {
         assert!(sample_greedy(&[]).is_err());
@@ -113,4 +311,58 @@ mod tests {
         assert!(sample_temperature(&logits, 0.0, &mut rng).is_err());
         assert!(sample_temperature(&logits, -1.0, &mut rng).is_err());
     }
+
+    #[test]
+    fn min_p_keeps_only_near_max_when_peaked() {
+        // probs ~ [0.88, 0.12, 0.0002, ...]: with p = 0.1 only the top two survive.
+        let logits = [4.0f32, 2.0, -4.0, -4.0, -5.0];
+        let kept = min_p_candidates(&logits, 0.1, 1.0).unwrap();
+        let ids: Vec<u32> = kept.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![0, 1]);
+        let total: f32 = kept.iter().map(|&(_, q)| q).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            assert!(sample_min_p(&logits, 0.1, 1.0, &mut rng).unwrap() <= 1);
+        }
+    }
+
+    #[test]
+    fn min_p_keeps_more_when_flat() {
+        let logits = [1.0f32, 0.9, 0.8, 0.7, 0.6];
+        assert_eq!(min_p_candidates(&logits, 0.1, 1.0).unwrap().len(), 5);
+        // Even a strict p drops only the tail of a flat distribution.
+        assert_eq!(min_p_candidates(&logits, 0.7, 1.0).unwrap().len(), 4);
+        assert_eq!(min_p_candidates(&logits, 1.0, 1.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn min_p_rejects_bad_arguments() {
+        let logits = [1.0f32, 2.0];
+        assert!(matches!(
+            min_p_candidates(&logits, 1.5, 1.0),
+            Err(SamplingError::InvalidMinP(_))
+        ));
+        assert!(min_p_candidates(&logits, 0.1, 0.0).is_err());
+        assert!(min_p_candidates(&[], 0.1, 1.0).is_err());
+    }
+
+    #[test]
+    fn top_candidates_sorted_with_full_vocab_probabilities() {
+        let logits = [0.5f32, 3.0, -1.0, 2.0, 3.0];
+        let top = top_candidates(&logits, 3);
+        let ids: Vec<u32> = top.iter().map(|&(id, _)| id).collect();
+        // Tie between 1 and 4 keeps the lower id first.
+        assert_eq!(ids, [1, 4, 3]);
+        assert!(top.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!((top[0].1 - token_logprob(&logits, 1).unwrap().exp()).abs() < 1e-6);
+
+        let all = top_candidates(&logits, 10);
+        assert_eq!(all.len(), logits.len());
+        let total: f32 = all.iter().map(|&(_, p)| p).sum();
+        assert!((total - 1.0).abs() < 1e-6, "{total}");
+        assert!(top_candidates(&[], 3).is_empty());
+        assert!(top_candidates(&logits, 0).is_empty());
+    }
 }
@@ -0,0 +1,92 @@
+//! Per-request sampling seeds for serving many requests from one configured seed.
+//!
+//! Giving every request the same [`GenerationConfig::seed`] makes identical prompts sample
+//! identical text; one RNG shared across requests makes each output depend on the others and on
+//! their order. A [`SeedSequence`] instead gives request `n` the seed [`derive_seed`]`(base, n)`:
+//! independent streams, yet a whole run replays exactly from the base seed and each request's
+//! index.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::engine::generation::GenerationConfig;
+
+/// 2^64 / golden ratio, the SplitMix64 increment.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The seed of request `index` under `base`: SplitMix64 of the base, then of that plus the
+/// index. Nearby bases and indices give unrelated seeds, and no index maps back to `base`.
+pub fn derive_seed(base: u64, index: u64) -> u64 {
+    mix(mix(base).wrapping_add(GOLDEN_GAMMA.wrapping_mul(index.wrapping_add(1))))
+}
+
+/// SplitMix64's output function.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hands out request indices and their [`derive_seed`] seeds; shareable across server threads.
+#[derive(Debug)]
+pub struct SeedSequence {
+    base: u64,
+    next: AtomicU64,
+}
+
+impl SeedSequence {
+    pub fn new(base: u64) -> Self {
+        Self::starting_at(base, 0)
+    }
+
+    /// A sequence whose first request is `index`, e.g. to resume a run.
+    pub fn starting_at(base: u64, index: u64) -> Self {
+        Self {
+            base,
+            next: AtomicU64::new(index),
+        }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Seed of request `index`, without advancing the sequence.
+    pub fn seed(&self, index: u64) -> u64 {
+        derive_seed(self.base, index)
+    }
+
+    /// Claim the next request index, returning it with its seed.
+    pub fn next_seed(&self) -> (u64, u64) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        (index, self.seed(index))
+    }
+
+    /// `template` with the seed of request `index`.
+    pub fn config_for(&self, template: &GenerationConfig, index: u64) -> GenerationConfig {
+        GenerationConfig {
+            seed: self.seed(index),
+            ..template.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_are_distinct_per_index_and_base() {
+        let mut seen: Vec<u64> = (0..3)
+            .flat_map(|base| (0..100).map(move |i| derive_seed(base, i)))
+            .collect();
+        assert!(seen.iter().all(|&s| s > 2), "no seed stays near its base");
+        seen.sort_unstable();
+        seen.dedup();
+        assert_eq!(seen.len(), 300);
+
+        let sequence = SeedSequence::starting_at(42, 5);
+        assert_eq!(sequence.next_seed(), (5, derive_seed(42, 5)));
+        assert_eq!(sequence.next_seed(), (6, derive_seed(42, 6)));
+        assert_eq!(sequence.seed(5), derive_seed(42, 5));
+    }
+}
@@ -1,75 +1,650 @@
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rayon::ThreadPool;
+
 use crate::EngineError;
+use crate::engine::budget::{ContextShift, TokenBudget, TokenUse};
+use crate::engine::config::{EngineConfig, LayerSchedule, install};
+use crate::engine::deadline::{Clock, SystemClock};
 use crate::engine::embed::{prefill_from_tokens_loaded, prefill_state_for_single_token_loaded};
-use crate::engine::runtime::{decode_forward, final_logits_last_token, prefill_forward};
+use crate::engine::generation::GenerationConfig;
+use crate::engine::observer::{ContextShifted, EngineObserver, ShiftReason};
+use crate::engine::runtime::{
+    decode_forward_with, final_logits_last_token, final_norm_last_token, prefill_forward_with,
+};
 use crate::engine::state::ForwardState;
-use crate::layers::attention::{KVCache, kv_caches_for_config};
+use crate::engine::text_stream::{StreamEnd, stream_text, write_text};
+use crate::engine::token_iter::TokenIter;
+use crate::engine::watchdog::{Watchdog, WatchdogConfig};
+use crate::layers::attention::{
+    CacheDtype, KVCache, KVCacheSnapshot, kv_caches_for_config_with_dtype,
+};
+use crate::layers::lora::{self, LoraAdapter};
 use crate::loaded_model::LoadedModel;
 use crate::model_weights::ModelWeights;
+use crate::tokenizer::{Granularity, TextChunk, Tokenizer};
 
 /// Mutable inference state for one generation run.
 ///
 /// A session owns KV caches. The model owns immutable tensor storage and metadata.
+///
+/// Every operation that adds cache rows is charged to the session's [`TokenBudget`] first and
+/// fails with [`crate::engine::budget::BudgetError`] if it would not fit.
 pub struct InferenceSession<'a> {
     model: &'a LoadedModel,
     weights: ModelWeights<'a>,
     kv_caches: Vec<KVCache>,
+    /// Storage for `kv_caches`, kept so [`Self::reset`] rebuilds the same kind.
+    kv_dtype: CacheDtype,
+    /// Rows used in `kv_caches`; always equal to [`Self::position`].
+    budget: TokenBudget,
+    /// Dedicated rayon pool from [`EngineConfig::num_threads`]; `None` uses the global pool.
+    pool: Option<Arc<ThreadPool>>,
+    /// Per-layer wall time of the last forward pass; `None` when timing is off.
+    layer_times: Option<Vec<Duration>>,
+    /// Where [`crate::engine::generation::generate_from_ids`] writes a
+    /// [`crate::engine::transcript::Transcript`]; `None` when recording is off.
+    transcript: Option<PathBuf>,
+    /// Blocks run by every forward pass; constant between cache resets.
+    layer_schedule: LayerSchedule,
+    /// Time source for [`GenerationConfig::deadline`].
+    clock: Arc<dyn Clock>,
+    /// Receives generation events; `None` when nobody listens.
+    observer: Option<Arc<dyn EngineObserver>>,
+    /// What generation loops do when the context is full; `None` fails the step instead.
+    context_shift: Option<ContextShift>,
+    /// Monitors decode steps; `None` when no watchdog is configured.
+    watchdog: Option<Watchdog>,
 }
 
 impl<'a> InferenceSession<'a> {
     pub fn new(model: &'a LoadedModel) -> Result<Self, EngineError> {
         let weights = model.weights()?;
+        let kv_caches = kv_caches_for_config_with_dtype(model.config(), CacheDtype::F32);
         Ok(Self {
             model,
             weights,
-            kv_caches: kv_caches_for_config(model.config()),
+            budget: budget_for(model, &kv_caches),
+            kv_caches,
+            kv_dtype: CacheDtype::F32,
+            pool: None,
+            layer_times: None,
+            transcript: None,
+            layer_schedule: LayerSchedule::All,
+            clock: Arc::new(SystemClock),
+            observer: None,
+            context_shift: None,
+            watchdog: None,
         })
     }
 
+    /// Like [`Self::new`], but runs every forward pass on a pool sized by `engine` and stores
+    /// the KV cache as `engine.kv_cache_dtype`.
+    pub fn with_config(model: &'a LoadedModel, engine: &EngineConfig) -> Result<Self, EngineError> {
+        let mut session = Self::new(model)?;
+        session.pool = engine.build_thread_pool()?;
+        session.set_layer_timing(engine.layer_timings);
+        session.set_layer_schedule(engine.layer_schedule)?;
+        session.set_watchdog(engine.watchdog)?;
+        if engine.startup_self_test {
+            crate::ops::self_test::startup_self_test();
+        }
+        if engine.kv_cache_dtype != CacheDtype::F32 {
+            session.kv_dtype = engine.kv_cache_dtype;
+            session.reset();
+        }
+        Ok(session)
+    }
+
     pub fn from_parts(
         model: &'a LoadedModel,
         weights: ModelWeights<'a>,
         kv_caches: Vec<KVCache>,
     ) -> Self {
+        let kv_dtype = kv_caches.first().map_or(CacheDtype::F32, KVCache::dtype);
+        let mut budget = budget_for(model, &kv_caches);
+        budget.sync_to(
+            TokenUse::Restored,
+            kv_caches.first().map_or(0, KVCache::current_pos),
+        );
         Self {
             model,
             weights,
             kv_caches,
+            kv_dtype,
+            budget,
+            pool: None,
+            layer_times: None,
+            transcript: None,
+            layer_schedule: LayerSchedule::All,
+            clock: Arc::new(SystemClock),
+            observer: None,
+            context_shift: None,
+            watchdog: None,
+        }
+    }
+
+    /// Workers the forward passes run on: the configured pool's, else the global pool's.
+    pub fn num_threads(&self) -> usize {
+        self.pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |p| p.current_num_threads())
+    }
+
+    pub fn model(&self) -> &'a LoadedModel {
+        self.model
+    }
+
+    /// Tokens currently held in the KV cache (of the first layer the schedule runs).
+    pub fn position(&self) -> usize {
+        let first = self
+            .layer_schedule
+            .first_layer(self.kv_caches.len())
+            .unwrap_or(0);
+        self.kv_caches.get(first).map_or(0, KVCache::current_pos)
+    }
+
+    /// Memory held by this session's KV caches (allocated for the full context).
+    pub fn kv_cache_bytes(&self) -> usize {
+        self.kv_caches.iter().map(KVCache::allocated_bytes).sum()
+    }
+
+    /// Tokens used so far, by kind, against the cache capacity.
+    pub fn budget(&self) -> &TokenBudget {
+        &self.budget
+    }
+
+    /// Record how long each layer takes in every following prefill / decode step.
+    pub fn set_layer_timing(&mut self, enabled: bool) {
+        self.layer_times = enabled.then(Vec::new);
+    }
+
+    /// Wall time of each layer in the last prefill or decode step, indexed by layer. Empty when
+    /// timing is off and before the first step.
+    pub fn layer_timings(&self) -> &[Duration] {
+        self.layer_times.as_deref().unwrap_or(&[])
+    }
+
+    /// Run only the blocks `schedule` selects (experimental). Skipped blocks never fill their
+    /// cache, so a different schedule also [resets](Self::reset) the session.
+    pub fn set_layer_schedule(&mut self, schedule: LayerSchedule) -> Result<(), EngineError> {
+        schedule.validate(self.model.config())?;
+        if schedule != self.layer_schedule {
+            self.layer_schedule = schedule;
+            self.reset();
+        }
+        Ok(())
+    }
+
+    pub fn layer_schedule(&self) -> LayerSchedule {
+        self.layer_schedule
+    }
+
+    /// Record every following [`crate::engine::generation::generate_from_ids`] run (and so
+    /// [`crate::engine::generation::generate`]) to `path`, replacing the previous recording;
+    /// `None` stops recording. Replay with [`crate::engine::transcript::replay`].
+    pub fn set_transcript(&mut self, path: Option<PathBuf>) {
+        self.transcript = path;
+    }
+
+    pub fn transcript(&self) -> Option<&Path> {
+        self.transcript.as_deref()
+    }
+
+    /// Clock that generation deadlines are measured on ([`SystemClock`] by default).
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Send generation events to `observer` (see [`crate::engine::observer`]), replacing the
+    /// previous one.
+    pub fn set_observer(&mut self, observer: Arc<dyn EngineObserver>) {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_observer(Some(Arc::clone(&observer)));
+        }
+        self.observer = Some(observer);
+    }
+
+    pub fn clear_observer(&mut self) {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_observer(None);
+        }
+        self.observer = None;
+    }
+
+    /// Watch every following [`Self::decode_token`] with a [`Watchdog`] (one monitor thread per
+    /// session), replacing the previous one; `None` stops watching. Stalls go to the observer as
+    /// `stall_detected`; a step cancelled past [`WatchdogConfig::cancel_after`] fails with
+    /// [`crate::engine::watchdog::StallCancelled`] and leaves the cache as it was before the step.
+    pub fn set_watchdog(&mut self, config: Option<WatchdogConfig>) -> Result<(), EngineError> {
+        self.watchdog = None;
+        if let Some(config) = config {
+            self.watchdog = Some(Watchdog::start(config, self.observer.clone())?);
+        }
+        Ok(())
+    }
+
+    /// The watchdog of [`Self::set_watchdog`], e.g. to set a
+    /// [stage hook](crate::engine::watchdog::Watchdog::set_stage_hook).
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    pub fn watchdog_config(&self) -> Option<WatchdogConfig> {
+        self.watchdog.as_ref().map(Watchdog::config)
+    }
+
+    /// Call `f` with the observer, if one is set.
+    pub(crate) fn emit(&self, f: impl FnOnce(&dyn EngineObserver)) {
+        if let Some(observer) = &self.observer {
+            f(observer.as_ref());
+        }
+    }
+
+    /// Let generation loops make room with `shift` when the context is full; `None` (the
+    /// default) makes them fail with [`crate::engine::budget::BudgetError`] instead.
+    pub fn set_context_shift(&mut self, shift: Option<ContextShift>) {
+        self.context_shift = shift;
+    }
+
+    pub fn context_shift(&self) -> Option<ContextShift> {
+        self.context_shift
+    }
+
+    /// If the cache is full and a [`ContextShift`] is set, drop the discarded span from
+    /// `cached_ids` (the ids the cache holds, in order) and rebuild the cache from what is left.
+    /// Returns whether it shifted.
+    pub(crate) fn shift_if_full(&mut self, cached_ids: &mut Vec<u32>) -> Result<bool, EngineError> {
+        if self.budget.remaining() > 0 {
+            return Ok(false);
+        }
+        debug_assert_eq!(
+            cached_ids.len(),
+            self.position(),
+            "ids out of sync with cache"
+        );
+        let Some((keep, discard)) = self
+            .context_shift
+            .and_then(|shift| shift.plan(cached_ids.len()))
+        else {
+            return Ok(false);
+        };
+        cached_ids.drain(keep..keep + discard);
+        self.reset();
+        if !cached_ids.is_empty() {
+            let input =
+                prefill_from_tokens_loaded(self.model.gguf(), self.model.config(), cached_ids)?;
+            self.accounted(&[(TokenUse::Restored, cached_ids.len())], |s| {
+                s.forward_prefill(&input, None)
+            })?;
         }
+        self.emit(|o| {
+            o.context_shift(&ContextShifted {
+                discarded: discard,
+                kept: cached_ids.len(),
+                reason: ShiftReason::ContextFull,
+            })
+        });
+        Ok(true)
+    }
+
+    /// Load the LoRA adapter GGUF at `path` and apply it with `scale` (see
+    /// [`Self::apply_lora_adapter`]).
+    pub fn apply_lora(&mut self, path: impl AsRef<Path>, scale: f32) -> Result<(), EngineError> {
+        self.apply_lora_adapter(&LoraAdapter::load(path)?, scale)
+    }
+
+    /// Add `adapter`'s low-rank deltas, scaled by `scale`, to every following forward pass (see
+    /// [`crate::layers::lora`]); adapters applied earlier stay, and their deltas add up. The
+    /// adapter is checked against the model's weights first. Cached keys and values were
+    /// computed without it, so this [resets](Self::reset) the session.
+    pub fn apply_lora_adapter(
+        &mut self,
+        adapter: &LoraAdapter,
+        scale: f32,
+    ) -> Result<(), EngineError> {
+        lora::attach(&mut self.weights, adapter, scale)?;
+        self.reset();
+        Ok(())
+    }
+
+    /// Drop every applied adapter, back to the base model's outputs, and
+    /// [reset](Self::reset) the session.
+    pub fn remove_lora(&mut self) {
+        lora::detach_all(&mut self.weights);
+        self.reset();
     }
 
     pub fn reset(&mut self) {
-        self.kv_caches = kv_caches_for_config(self.model.config());
+        self.kv_caches = kv_caches_for_config_with_dtype(self.model.config(), self.kv_dtype);
+        self.budget = budget_for(self.model, &self.kv_caches);
+    }
+
+    /// Snapshot every layer's cache, e.g. after prefilling a system prompt shared by many
+    /// requests; [`Self::restore`] it before each request instead of re-running that prefill.
+    pub fn snapshot(&self) -> Vec<KVCacheSnapshot> {
+        self.kv_caches.iter().map(KVCache::snapshot).collect()
+    }
+
+    /// Restore every layer from [`Self::snapshot`]. All layers are checked before any is
+    /// written, so a mismatched snapshot leaves the caches and budget as they were.
+    pub fn restore(&mut self, snapshot: &[KVCacheSnapshot]) -> Result<(), EngineError> {
+        if snapshot.len() != self.kv_caches.len() {
+            return Err(EngineError::Model(format!(
+                "KV snapshot has {} layers, session has {}",
+                snapshot.len(),
+                self.kv_caches.len()
+            )));
+        }
+        for (layer, (cache, snap)) in self.kv_caches.iter().zip(snapshot).enumerate() {
+            cache
+                .check_restore(snap)
+                .map_err(|e| EngineError::Model(format!("KV snapshot layer {layer}: {e}")))?;
+        }
+        for (cache, snap) in self.kv_caches.iter_mut().zip(snapshot) {
+            cache.restore(snap)?;
+        }
+        self.budget.clear();
+        self.budget.sync_to(TokenUse::Restored, self.position());
+        Ok(())
     }
 
     pub fn prefill(&mut self, token_ids: &[u32]) -> Result<ForwardState, EngineError> {
+        self.budget.check(TokenUse::Prompt, token_ids.len())?;
         let input = prefill_from_tokens_loaded(self.model.gguf(), self.model.config(), token_ids)?;
         self.prefill_prepared(&input)
     }
 
-    pub fn prefill_prepared(&mut self, input: &ForwardState) -> Result<ForwardState, EngineError> {
-        prefill_forward(
-            input,
-            self.model.config(),
-            &self.weights,
-            self.kv_caches.as_mut_slice(),
+    /// Prefill `prompt_ids ++ forced_ids` in one pass, charging the second part as
+    /// [`TokenUse::Forced`].
+    pub(crate) fn prefill_with_forced(
+        &mut self,
+        prompt_ids: &[u32],
+        forced_ids: &[u32],
+    ) -> Result<ForwardState, EngineError> {
+        let ids: Vec<u32> = prompt_ids.iter().chain(forced_ids).copied().collect();
+        self.budget.check(TokenUse::Prompt, ids.len())?;
+        let input = prefill_from_tokens_loaded(self.model.gguf(), self.model.config(), &ids)?;
+        self.accounted(
+            &[
+                (TokenUse::Prompt, prompt_ids.len()),
+                (TokenUse::Forced, forced_ids.len()),
+            ],
+            |s| s.forward_prefill(&input, None),
         )
     }
 
+    pub fn prefill_prepared(&mut self, input: &ForwardState) -> Result<ForwardState, EngineError> {
+        self.accounted(&[(TokenUse::Prompt, input.seq_len())], |s| {
+            s.forward_prefill(input, None)
+        })
+    }
+
+    /// [`Self::prefill`] with an additive attention mask instead of causal masking: row-major
+    /// `[token_ids.len(), position() + token_ids.len()]`, one row per new token over every key
+    /// it could see, cached ones included (see
+    /// [`crate::layers::attention::prefill_attention_layer`]). Allows e.g. a prefix-LM prompt
+    /// whose tokens all attend to each other.
+    pub fn prefill_masked(
+        &mut self,
+        token_ids: &[u32],
+        mask: &[f32],
+    ) -> Result<ForwardState, EngineError> {
+        self.budget.check(TokenUse::Prompt, token_ids.len())?;
+        let input = prefill_from_tokens_loaded(self.model.gguf(), self.model.config(), token_ids)?;
+        self.accounted(&[(TokenUse::Prompt, input.seq_len())], |s| {
+            s.forward_prefill(&input, Some(mask))
+        })
+    }
+
+    fn forward_prefill(
+        &mut self,
+        input: &ForwardState,
+        mask: Option<&[f32]>,
+    ) -> Result<ForwardState, EngineError> {
+        let (config, weights, kv_caches) =
+            (self.model.config(), &self.weights, &mut self.kv_caches);
+        let (schedule, layer_times) = (self.layer_schedule, self.layer_times.as_mut());
+        install(self.pool.as_deref(), || {
+            prefill_forward_with(
+                input,
+                config,
+                weights,
+                kv_caches.as_mut_slice(),
+                schedule,
+                layer_times,
+                mask,
+            )
+        })
+    }
+
+    /// Check `uses` against the budget, run `op`, then record them. If `op` fails, the budget
+    /// is re-synced to whatever reached the cache.
+    fn accounted<T>(
+        &mut self,
+        uses: &[(TokenUse, usize)],
+        op: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        let kind = uses.first().map_or(TokenUse::Prompt, |u| u.0);
+        self.budget.check(kind, uses.iter().map(|u| u.1).sum())?;
+        let result = op(self);
+        match result {
+            Ok(_) => {
+                for &(kind, n) in uses {
+                    self.budget.consume(kind, n)?;
+                }
+            }
+            Err(_) => self.budget.sync_to(kind, self.position()),
+        }
+        debug_assert_eq!(self.budget.used(), self.position(), "budget out of sync");
+        result
+    }
+
     pub fn decode_token(&mut self, token_id: u32) -> Result<ForwardState, EngineError> {
+        self.decode_step(token_id, None)
+    }
+
+    /// [`Self::decode_token`] with an additive attention mask over the `position() + 1` keys
+    /// including this token, in place of causal masking.
+    pub fn decode_token_masked(
+        &mut self,
+        token_id: u32,
+        mask: &[f32],
+    ) -> Result<ForwardState, EngineError> {
+        self.decode_step(token_id, Some(mask))
+    }
+
+    fn decode_step(
+        &mut self,
+        token_id: u32,
+        mask: Option<&[f32]>,
+    ) -> Result<ForwardState, EngineError> {
+        self.budget.check(TokenUse::Generated, 1)?;
         let input = prefill_state_for_single_token_loaded(
             self.model.gguf(),
             self.model.config(),
             token_id,
         )?;
-        decode_forward(
-            &input,
-            self.model.config(),
-            &self.weights,
-            self.kv_caches.as_mut_slice(),
-        )
+        let before = self.position();
+        self.accounted(&[(TokenUse::Generated, 1)], |s| {
+            let (config, weights, kv_caches) = (s.model.config(), &s.weights, &mut s.kv_caches);
+            let (schedule, layer_times) = (s.layer_schedule, s.layer_times.as_mut());
+            let Some(watchdog) = &s.watchdog else {
+                return install(s.pool.as_deref(), || {
+                    decode_forward_with(
+                        &input,
+                        config,
+                        weights,
+                        kv_caches.as_mut_slice(),
+                        schedule,
+                        layer_times,
+                        mask,
+                    )
+                });
+            };
+            let _armed = watchdog.arm(before);
+            let result = install(s.pool.as_deref(), || {
+                watchdog.scope(|| {
+                    decode_forward_with(
+                        &input,
+                        config,
+                        weights,
+                        kv_caches.as_mut_slice(),
+                        schedule,
+                        layer_times,
+                        mask,
+                    )
+                })
+            });
+            if result.is_err() {
+                // A cancelled step stopped between layers; drop the rows the earlier ones wrote.
+                kv_caches.iter_mut().for_each(|c| c.truncate(before));
+            }
+            result
+        })
     }
 
     pub fn logits_last_token(&self, state: &ForwardState) -> Result<Vec<f32>, EngineError> {
-        final_logits_last_token(state, self.model.config(), &self.weights)
+        install(self.pool.as_deref(), || {
+            final_logits_last_token(state, self.model.config(), &self.weights)
+        })
     }
+
+    /// The last position of `state` after the final norm: the LM head's input in
+    /// [`Self::logits_last_token`].
+    pub fn final_hidden_last_token(&self, state: &ForwardState) -> Result<Vec<f32>, EngineError> {
+        final_norm_last_token(state, self.model.config(), &self.weights)
+    }
+
+    /// [`Self::logits_last_token`] without the (`-inf`) padding rows (ids `>=`
+    /// [`ModelConfig::tokenizer_vocab_size`](crate::model_config::ModelConfig::tokenizer_vocab_size)),
+    /// so no sampler can pick them. Used by every generation loop.
+    pub fn next_token_logits(&self, state: &ForwardState) -> Result<Vec<f32>, EngineError> {
+        let mut logits = self.logits_last_token(state)?;
+        logits.truncate(self.model.config().tokenizer_vocab_size);
+        Ok(logits)
+    }
+
+    /// Health check: one forward pass on the BOS token, failing with [`EngineError::Model`] if
+    /// the logits are not [`ModelConfig::vocab_size`](crate::model_config::ModelConfig::vocab_size)
+    /// wide, any real-vocab logit is not finite, or they are all zero or all equal (e.g. a
+    /// zeroed or truncated LM head). Runs on one-row scratch caches; the session's cache, budget
+    /// and [`Self::layer_timings`] are left as they were.
+    pub fn self_check(&mut self) -> Result<(), EngineError> {
+        let config = self.model.config();
+        let bos = self.model.tokenizer_prompt().bos_token_id;
+        if bos as usize >= config.tokenizer_vocab_size {
+            return Err(EngineError::Model(format!(
+                "self-check: BOS id {bos} is outside the {}-token vocabulary",
+                config.tokenizer_vocab_size
+            )));
+        }
+        // One row per layer is all a single-token forward writes.
+        let scratch: Vec<KVCache> = self
+            .kv_caches
+            .iter()
+            .map(|c| KVCache::with_dtype(1, c.n_kv_heads(), c.head_dim(), c.dtype()))
+            .collect();
+        let scratch_budget = budget_for(self.model, &scratch);
+        let kv_caches = std::mem::replace(&mut self.kv_caches, scratch);
+        let budget = std::mem::replace(&mut self.budget, scratch_budget);
+        let layer_times = self.layer_times.clone();
+        let logits = self
+            .prefill(&[bos])
+            .and_then(|state| self.logits_last_token(&state));
+        self.kv_caches = kv_caches;
+        self.budget = budget;
+        self.layer_times = layer_times;
+        check_logits(&logits?, config.vocab_size, config.tokenizer_vocab_size)
+    }
+
+    /// Generate from `prompt_ids` as an iterator of tokens (see [`TokenIter`]).
+    pub fn tokens<'s, 't>(
+        &'s mut self,
+        tokenizer: &'t Tokenizer,
+        prompt_ids: &[u32],
+        config: &GenerationConfig,
+    ) -> TokenIter<'a, &'s mut Self, &'t Tokenizer> {
+        TokenIter::new(self, tokenizer, prompt_ids, config)
+    }
+
+    /// Generate from `prompt_ids`, passing the text to `on_chunk` in `granularity`-sized pieces
+    /// (see [`crate::engine::text_stream`]).
+    pub fn stream_text(
+        &mut self,
+        tokenizer: &Tokenizer,
+        prompt_ids: &[u32],
+        config: &GenerationConfig,
+        granularity: Granularity,
+        on_chunk: impl FnMut(&TextChunk) -> ControlFlow<()>,
+    ) -> Result<StreamEnd, EngineError> {
+        stream_text(
+            self.tokens(tokenizer, prompt_ids, config),
+            granularity,
+            on_chunk,
+        )
+    }
+
+    /// Generate from `prompt_ids`, writing the text to `out` as it is produced and flushing
+    /// after every token (see [`write_text`]).
+    pub fn generate_to_writer(
+        &mut self,
+        tokenizer: &Tokenizer,
+        prompt_ids: &[u32],
+        config: &GenerationConfig,
+        out: &mut impl Write,
+    ) -> Result<StreamEnd, EngineError> {
+        write_text(self.tokens(tokenizer, prompt_ids, config), out)
+    }
+}
+
+/// The sanity conditions of [`InferenceSession::self_check`] on one position's logits.
+fn check_logits(logits: &[f32], rows: usize, tokens: usize) -> Result<(), EngineError> {
+    if logits.len() != rows {
+        return Err(EngineError::Model(format!(
+            "self-check: {} logits, expected {rows}",
+            logits.len()
+        )));
+    }
+    let real = &logits[..tokens];
+    if let Some(id) = real.iter().position(|l| !l.is_finite()) {
+        return Err(EngineError::Model(format!(
+            "self-check: logit for token {id} is {}",
+            real[id]
+        )));
+    }
+    let (min, max) = real
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &l| {
+            (lo.min(l), hi.max(l))
+        });
+    if max == 0.0 && min == 0.0 {
+        return Err(EngineError::Model(
+            "self-check: every logit is zero (is the LM head zeroed?)".into(),
+        ));
+    }
+    if max == min {
+        return Err(EngineError::Model(format!(
+            "self-check: every logit is {max}; the model cannot prefer any token"
+        )));
+    }
+    Ok(())
+}
+
+/// Empty budget sized by the smallest cache (the first one to fill up).
+fn budget_for(model: &LoadedModel, kv_caches: &[KVCache]) -> TokenBudget {
+    let capacity = kv_caches
+        .iter()
+        .map(KVCache::max_seq_len)
+        .min()
+        .unwrap_or(model.config().context_length);
+    TokenBudget::new(capacity)
 }
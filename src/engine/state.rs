@@ -153,6 +153,30 @@ impl ForwardState {
         })
     }
 
+    /// Copy rows `start..end` (hidden and PLE payload) into a new state, e.g. one position.
+    pub fn rows(&self, start: usize, end: usize) -> Result<Self, EngineError> {
+        if start >= end || end > self.seq_len {
+            return Err(EngineError::Model(format!(
+                "ForwardState: row range {start}..{end} invalid for seq_len {}",
+                self.seq_len
+            )));
+        }
+        let stride = self.ple_n_layers * self.ple_dim;
+        let per_layer_packed = if stride > 0 {
+            self.per_layer_packed[start * stride..end * stride].to_vec()
+        } else {
+            Vec::new()
+        };
+        Self::from_flat_with_ple(
+            self.hidden[start * self.hidden_dim..end * self.hidden_dim].to_vec(),
+            end - start,
+            self.hidden_dim,
+            per_layer_packed,
+            self.ple_n_layers,
+            self.ple_dim,
+        )
+    }
+
     pub fn row(&self, idx: usize) -> Result<&[f32], EngineError> {
         if idx >= self.seq_len {
             return Err(EngineError::Model(
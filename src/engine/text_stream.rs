@@ -0,0 +1,111 @@
+//! Callback streaming of generated text in [`Granularity`]-sized chunks, on top of
+//! [`TokenIter`], and [`write_text`] for streaming into any [`Write`].
+//!
+//! Boundaries come from [`TextChunker`], which looks at the decoded text. Whatever is still
+//! buffered is always delivered as a last chunk: at EOS / stop ids / `max_new_tokens` / the
+//! deadline, when the callback cancels, and before a generation error is returned.
+
+use std::borrow::{Borrow, BorrowMut};
+use std::io::Write;
+use std::ops::ControlFlow;
+
+use crate::EngineError;
+use crate::engine::generation::{FinishReason, GenerationStats};
+use crate::engine::observer::Cancelled;
+use crate::engine::session::InferenceSession;
+use crate::engine::token_iter::TokenIter;
+use crate::tokenizer::{Granularity, TextChunk, TextChunker, Tokenizer};
+
+/// How a [`stream_text`] run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEnd {
+    /// Tokens generated (and fed to the session).
+    pub token_count: usize,
+    /// The callback returned [`ControlFlow::Break`].
+    pub cancelled: bool,
+    /// Why generation stopped; `None` when cancelled.
+    pub finish_reason: Option<FinishReason>,
+    /// [`TokenIter::stats`] at the end of the run.
+    pub stats: GenerationStats,
+}
+
+/// Drive `tokens` to the end, handing `on_chunk` each completed chunk. Returning
+/// [`ControlFlow::Break`] stops generation; the final flush after that ignores the return value.
+pub fn stream_text<'a, S, T>(
+    mut tokens: TokenIter<'a, S, T>,
+    granularity: Granularity,
+    mut on_chunk: impl FnMut(&TextChunk) -> ControlFlow<()>,
+) -> Result<StreamEnd, EngineError>
+where
+    S: BorrowMut<InferenceSession<'a>>,
+    T: Borrow<Tokenizer>,
+{
+    let mut chunker = TextChunker::new(granularity);
+    let mut end = StreamEnd {
+        token_count: 0,
+        cancelled: false,
+        finish_reason: None,
+        stats: GenerationStats::default(),
+    };
+    let mut failed = None;
+    for (index, item) in tokens.by_ref().enumerate() {
+        let token = match item {
+            Ok(token) => token,
+            Err(e) => {
+                failed = Some(e);
+                break;
+            }
+        };
+        end.token_count += 1;
+        if let Some(chunk) = chunker.push(index, &token.text)
+            && on_chunk(&chunk).is_break()
+        {
+            end.cancelled = true;
+            break;
+        }
+    }
+    if let Some(chunk) = chunker.flush() {
+        let _ = on_chunk(&chunk);
+    }
+    end.finish_reason = tokens.finish_reason();
+    end.stats = tokens.stats();
+    if end.cancelled {
+        let generated = end.token_count;
+        tokens
+            .session()
+            .emit(|o| o.cancelled(&Cancelled { generated }));
+    }
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(end),
+    }
+}
+
+/// Drive `tokens` to the end, writing each token's text to `out` and flushing after every token.
+///
+/// Token text comes from [`crate::tokenizer::IncrementalDecoder`], so a multi-byte character
+/// split across tokens is written once its last byte arrives, never as invalid UTF-8; one still
+/// incomplete when generation ends is not written. A failed write or flush stops generation and
+/// is returned as [`EngineError::Io`].
+pub fn write_text<'a, S, T>(
+    mut tokens: TokenIter<'a, S, T>,
+    out: &mut impl Write,
+) -> Result<StreamEnd, EngineError>
+where
+    S: BorrowMut<InferenceSession<'a>>,
+    T: Borrow<Tokenizer>,
+{
+    let mut token_count = 0;
+    for item in tokens.by_ref() {
+        let token = item?;
+        token_count += 1;
+        out.write_all(token.text.as_bytes())?;
+        out.flush()?;
+    }
+    Ok(StreamEnd {
+        token_count,
+        cancelled: false,
+        finish_reason: tokens.finish_reason(),
+        stats: tokens.stats(),
+    })
+}
@@ -0,0 +1,212 @@
+//! Worker pools shared by every session with the same thread settings.
+//!
+//! The parallel ops (matmul rows, attention heads) use rayon's `par_*` iterators, which run on
+//! whatever pool they are called from; a session enters its pool once per forward pass (see
+//! [`EngineConfig::build_thread_pool`](crate::engine::config::EngineConfig::build_thread_pool)).
+//! [`shared_pool`] builds each distinct pool once per process and hands out the same
+//! [`Arc`] afterwards, so sessions created per request do not each spawn (and leave behind) a
+//! set of workers. Ops too small to gain from splitting run inline on the calling thread below
+//! their own work thresholds.
+//!
+//! [`ThreadAffinity::PerformanceCores`] pins the workers to the fastest cores of a big.LITTLE
+//! (hybrid) CPU on Linux, so a decode step is not held up by a worker that landed on an
+//! efficiency core. Elsewhere, or on CPUs whose cores are all alike, it pins nothing.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use rayon::ThreadPool;
+
+use crate::EngineError;
+
+/// Cores within this fraction of the fastest one's capacity count as performance cores (P-cores
+/// of one CPU differ by a few percent of turbo headroom; E-cores are well below).
+const PERFORMANCE_CORE_FRACTION: f64 = 0.85;
+
+/// Which CPUs pool workers may run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ThreadAffinity {
+    /// Wherever the OS schedules them.
+    #[default]
+    None,
+    /// The performance cores only ([`performance_cores`]); without a thread count, one worker
+    /// per performance core.
+    PerformanceCores,
+}
+
+type PoolKey = (Option<usize>, ThreadAffinity);
+
+static POOLS: OnceLock<Mutex<HashMap<PoolKey, Arc<ThreadPool>>>> = OnceLock::new();
+
+/// The process-wide pool for `threads` workers (`None`: one per logical core, or per performance
+/// core when pinned) under `affinity`, built on the first call with these settings.
+pub fn shared_pool(
+    threads: Option<usize>,
+    affinity: ThreadAffinity,
+) -> Result<Arc<ThreadPool>, EngineError> {
+    if threads == Some(0) {
+        return Err(EngineError::Model("num_threads must be at least 1".into()));
+    }
+    let pools = POOLS.get_or_init(Mutex::default);
+    let mut pools = pools.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pool) = pools.get(&(threads, affinity)) {
+        return Ok(Arc::clone(pool));
+    }
+    let pool = Arc::new(build_pool(threads, affinity)?);
+    pools.insert((threads, affinity), Arc::clone(&pool));
+    Ok(pool)
+}
+
+fn build_pool(threads: Option<usize>, affinity: ThreadAffinity) -> Result<ThreadPool, EngineError> {
+    let cores = match affinity {
+        ThreadAffinity::None => None,
+        ThreadAffinity::PerformanceCores => {
+            let cores = performance_cores();
+            match &cores {
+                Some(c) => log::info!("pinning engine workers to performance cores {c:?}"),
+                None => log::info!("no performance/efficiency core split found; not pinning"),
+            }
+            cores
+        }
+    };
+    let mut builder = rayon::ThreadPoolBuilder::new().thread_name(|i| format!("engine-worker-{i}"));
+    if let Some(n) = threads.or(cores.as_ref().map(Vec::len)) {
+        builder = builder.num_threads(n);
+    }
+    if let Some(cores) = cores {
+        builder = builder.start_handler(move |i| {
+            if !imp::pin_current_thread(&cores) {
+                log::warn!("could not pin engine-worker-{i} to {cores:?}");
+            }
+        });
+    }
+    builder
+        .build()
+        .map_err(|e| EngineError::Model(format!("failed to build thread pool: {e}")))
+}
+
+/// CPU ids of the performance cores, when this machine has cores of different speeds (Linux:
+/// `cpu_capacity`, else `cpufreq/cpuinfo_max_freq`, per CPU in sysfs). `None` where that is
+/// unknown or every core is alike.
+pub fn performance_cores() -> Option<Vec<usize>> {
+    fastest_cpus(&imp::cpu_capacities())
+}
+
+/// CPUs whose capacity is within [`PERFORMANCE_CORE_FRACTION`] of the largest; `None` if that
+/// is all of them (or there are none).
+fn fastest_cpus(capacities: &[(usize, u64)]) -> Option<Vec<usize>> {
+    let max = capacities.iter().map(|&(_, c)| c).max()?;
+    let fast: Vec<usize> = capacities
+        .iter()
+        .filter(|&&(_, c)| c as f64 >= max as f64 * PERFORMANCE_CORE_FRACTION)
+        .map(|&(cpu, _)| cpu)
+        .collect();
+    (fast.len() < capacities.len()).then_some(fast)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::path::Path;
+
+    pub fn cpu_capacities() -> Vec<(usize, u64)> {
+        let Ok(dir) = std::fs::read_dir("/sys/devices/system/cpu") else {
+            return Vec::new();
+        };
+        let mut caps: Vec<(usize, u64)> = dir
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let cpu = name.to_str()?.strip_prefix("cpu")?.parse().ok()?;
+                Some((cpu, capacity(&entry.path())?))
+            })
+            .collect();
+        caps.sort_unstable();
+        caps
+    }
+
+    fn capacity(cpu_dir: &Path) -> Option<u64> {
+        ["cpu_capacity", "cpufreq/cpuinfo_max_freq"]
+            .iter()
+            .find_map(|f| {
+                std::fs::read_to_string(cpu_dir.join(f))
+                    .ok()?
+                    .trim()
+                    .parse()
+                    .ok()
+            })
+    }
+
+    pub fn pin_current_thread(cpus: &[usize]) -> bool {
+        // SAFETY: `set` is a plain bitmask initialised by CPU_ZERO before use, and
+        // sched_setaffinity only reads it (pid 0 is the calling thread).
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            for &cpu in cpus {
+                if cpu < libc::CPU_SETSIZE as usize {
+                    libc::CPU_SET(cpu, &mut set);
+                }
+            }
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        }
+    }
+}
+
+/// Platforms without core capacities or affinity. Compiled everywhere so it is tested everywhere.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+mod fallback {
+    pub fn cpu_capacities() -> Vec<(usize, u64)> {
+        Vec::new()
+    }
+
+    pub fn pin_current_thread(_cpus: &[usize]) -> bool {
+        false
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+use fallback as imp;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_mixed_cpu_has_performance_cores() {
+        // Hybrid: two P-cores (one with a little more turbo), four E-cores.
+        let hybrid = [
+            (0, 5_400_000),
+            (1, 5_000_000),
+            (2, 3_800_000),
+            (3, 3_800_000),
+            (4, 3_800_000),
+            (5, 3_800_000),
+        ];
+        assert_eq!(fastest_cpus(&hybrid), Some(vec![0, 1]));
+        // ARM capacities, big cores listed last.
+        assert_eq!(
+            fastest_cpus(&[(0, 446), (1, 446), (2, 1024), (3, 1024)]),
+            Some(vec![2, 3])
+        );
+        assert_eq!(fastest_cpus(&[(0, 1024), (1, 1024)]), None);
+        assert_eq!(fastest_cpus(&[]), None);
+        assert!(fallback::cpu_capacities().is_empty());
+        assert!(!fallback::pin_current_thread(&[0]));
+    }
+
+    #[test]
+    fn pools_are_built_once_per_setting() {
+        let a = shared_pool(Some(3), ThreadAffinity::None).unwrap();
+        let b = shared_pool(Some(3), ThreadAffinity::None).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.current_num_threads(), 3);
+        let other = shared_pool(Some(2), ThreadAffinity::None).unwrap();
+        assert!(!Arc::ptr_eq(&a, &other));
+        assert!(shared_pool(Some(0), ThreadAffinity::None).is_err());
+
+        // Pinning falls back to an unpinned pool where there is nothing to pin to.
+        let pinned = shared_pool(Some(2), ThreadAffinity::PerformanceCores).unwrap();
+        assert_eq!(pinned.current_num_threads(), 2);
+        assert_eq!(pinned.install(|| 1 + 1), 2);
+    }
+}
@@ -0,0 +1,255 @@
+//! Generation as a pull-based [`Iterator`] of tokens, for TUIs and services that want to drive
+//! the loop themselves instead of passing callbacks.
+//!
+//! Each sampled token is fed to the session **before** it is yielded, so at any point (including
+//! after dropping the iterator early) the KV cache holds exactly the prompt plus every yielded
+//! token (less whatever a [`crate::engine::budget::ContextShift`] dropped), and the caller can keep stepping with [`InferenceSession::decode_token`]. The price is
+//! that the final forward pass is never used for sampling.
+
+use std::borrow::{Borrow, BorrowMut};
+use std::marker::PhantomData;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::EngineError;
+use crate::core::time::Instant;
+use crate::engine::deadline::{DeadlineTimer, expired};
+use crate::engine::decode_trace::DecodeStep;
+use crate::engine::generation::{
+    FinishReason, GenerationConfig, GenerationStats, StopTokens, logprob_or_err,
+    prefill_scored_until, reject_guidance, reject_json_schema, sample_next,
+};
+use crate::engine::observer::{
+    EngineFailed, GenerationFinished, GenerationStarted, Operation, TokenGenerated,
+};
+use crate::engine::quality::QualityMonitor;
+use crate::engine::session::InferenceSession;
+use crate::loaded_model::LoadedModel;
+use crate::tokenizer::{IncrementalDecoder, Tokenizer};
+
+/// One sampled token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedToken {
+    pub id: u32,
+    /// Text this token adds (from [`IncrementalDecoder`]); empty while a multi-byte character is
+    /// still incomplete.
+    pub text: String,
+    /// Log-probability under the unscaled logits.
+    pub logprob: f32,
+}
+
+/// Iterator over the tokens of one generation; see the module docs for cache semantics.
+///
+/// `S` / `T` are the session and tokenizer, borrowed or owned (owned lets the iterator be
+/// `'static`, e.g. for [`crate::engine::token_stream`]). The session is reset and the prompt
+/// prefilled lazily, on the first call to `next`, which also starts
+/// [`GenerationConfig::deadline`]. Iteration ends at a terminator or stop id, `max_new_tokens`,
+/// the deadline (checked before each token is fed), or after the first error.
+pub struct TokenIter<'a, S, T> {
+    session: S,
+    tokenizer: T,
+    prompt_ids: Vec<u32>,
+    config: GenerationConfig,
+    stops: StopTokens,
+    rng: StdRng,
+    /// Started with the prefill.
+    timer: Option<DeadlineTimer>,
+    decoder: IncrementalDecoder,
+    /// Logits for the next position; `None` until the prompt has been prefilled.
+    logits: Option<Vec<f32>>,
+    /// Ids in the session's cache, for a [`crate::engine::budget::ContextShift`].
+    cached_ids: Vec<u32>,
+    /// When the first `next` call began, for [`GenerationFinished::elapsed`].
+    started: Option<Instant>,
+    yielded: usize,
+    finish_reason: Option<FinishReason>,
+    /// Set by [`Self::traced`]; then holds the last yielded token's step.
+    trace: Option<Option<DecodeStep>>,
+    /// Fed every yielded token when [`GenerationConfig::quality`] is set.
+    quality: Option<QualityMonitor>,
+    done: bool,
+    _model: PhantomData<&'a LoadedModel>,
+}
+
+impl<'a, S, T> TokenIter<'a, S, T>
+where
+    S: BorrowMut<InferenceSession<'a>>,
+    T: Borrow<Tokenizer>,
+{
+    pub fn new(session: S, tokenizer: T, prompt_ids: &[u32], config: &GenerationConfig) -> Self {
+        let stops = config.stop_tokens(session.borrow().model().tokenizer_prompt());
+        Self {
+            session,
+            tokenizer,
+            prompt_ids: prompt_ids.to_vec(),
+            config: config.clone(),
+            stops,
+            rng: StdRng::seed_from_u64(config.seed),
+            timer: None,
+            decoder: IncrementalDecoder::new(),
+            logits: None,
+            cached_ids: Vec::new(),
+            started: None,
+            yielded: 0,
+            finish_reason: None,
+            trace: None,
+            quality: config.quality.map(QualityMonitor::new),
+            done: false,
+            _model: PhantomData,
+        }
+    }
+
+    pub fn session(&self) -> &InferenceSession<'a> {
+        self.session.borrow()
+    }
+
+    /// Ids yielded so far.
+    pub fn token_ids(&self) -> &[u32] {
+        self.decoder.ids()
+    }
+
+    /// Why iteration ended; `None` while it is still running or after an error.
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.finish_reason
+    }
+
+    /// Token counts so far and, with [`GenerationConfig::quality`], the quality aggregates over
+    /// the yielded tokens.
+    pub fn stats(&self) -> GenerationStats {
+        GenerationStats {
+            prompt_tokens: self.prompt_ids.len(),
+            generated_tokens: self.yielded,
+            quality: self.quality.as_ref().map(|m| m.stats().clone()),
+            ..GenerationStats::default()
+        }
+    }
+
+    /// Record a [`DecodeStep`] for every token, read with [`Self::last_step`] after each `next`.
+    pub fn traced(mut self) -> Self {
+        self.trace = Some(None);
+        self
+    }
+
+    /// Trace of the token `next` last yielded; `None` unless [`Self::traced`].
+    pub fn last_step(&self) -> Option<&DecodeStep> {
+        self.trace.as_ref()?.as_ref()
+    }
+
+    /// Give back the session and tokenizer (e.g. to continue with another generation).
+    pub fn into_parts(self) -> (S, T) {
+        (self.session, self.tokenizer)
+    }
+
+    fn step(&mut self) -> Result<Option<GeneratedToken>, EngineError> {
+        if self.yielded >= self.config.max_new_tokens {
+            self.finish_reason = Some(FinishReason::Length);
+            return Ok(None);
+        }
+        let session = self.session.borrow_mut();
+        let logits = match self.logits.take() {
+            Some(logits) => logits,
+            None => {
+                self.config.validate()?;
+                reject_guidance(&self.config)?;
+                reject_json_schema(&self.config)?;
+                self.started = Some(session.clock().now());
+                session.emit(|o| {
+                    o.generation_started(&GenerationStarted {
+                        prompt_tokens: self.prompt_ids.len(),
+                        forced_tokens: 0,
+                        max_new_tokens: self.config.max_new_tokens,
+                    })
+                });
+                self.timer = DeadlineTimer::start(session.clock().clone(), &self.config);
+                let prefilled =
+                    prefill_scored_until(session, &self.prompt_ids, &[], self.timer.as_ref())?;
+                let Some((state, _)) = prefilled else {
+                    self.finish_reason = Some(FinishReason::DeadlineExceeded);
+                    return Ok(None);
+                };
+                self.cached_ids = self.prompt_ids.clone();
+                session.next_token_logits(&state)?
+            }
+        };
+        if expired(self.timer.as_ref(), self.yielded) {
+            self.finish_reason = Some(FinishReason::DeadlineExceeded);
+            return Ok(None);
+        }
+        let sampled = sample_next(&logits, &self.config, &mut self.rng)?;
+        let id = sampled.id;
+        if self.stops.contains(id) {
+            self.finish_reason = Some(FinishReason::Eos { token_id: id });
+            return Ok(None);
+        }
+        let logprob = logprob_or_err(&logits, id)?;
+        let text = self.decoder.push(self.tokenizer.borrow(), id)?;
+        if let Some(trace) = &mut self.trace {
+            let started = self.started.unwrap_or_else(|| session.clock().now());
+            *trace = Some(DecodeStep::record(
+                self.yielded,
+                &logits,
+                id,
+                text.clone(),
+                self.tokenizer.borrow(),
+                sampled.changed_by,
+                session.clock().now().saturating_duration_since(started),
+            )?);
+        }
+
+        session.shift_if_full(&mut self.cached_ids)?;
+        let state = session.decode_token(id)?;
+        self.cached_ids.push(id);
+        session.emit(|o| {
+            o.token(&TokenGenerated {
+                index: self.yielded,
+                id,
+                logprob,
+            })
+        });
+        if let Some(monitor) = self.quality.as_mut() {
+            for alert in monitor.observe(&logits, id, logprob) {
+                session.emit(|o| o.quality_alert(&alert));
+            }
+        }
+        self.yielded += 1;
+        if self.yielded < self.config.max_new_tokens {
+            self.logits = Some(session.next_token_logits(&state)?);
+        }
+        Ok(Some(GeneratedToken { id, text, logprob }))
+    }
+}
+
+impl<'a, S, T> Iterator for TokenIter<'a, S, T>
+where
+    S: BorrowMut<InferenceSession<'a>>,
+    T: Borrow<Tokenizer>,
+{
+    type Item = Result<GeneratedToken, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.step().transpose();
+        if !matches!(item, Some(Ok(_))) {
+            self.done = true;
+            let session = self.session.borrow();
+            match (&item, self.finish_reason) {
+                (Some(Err(e)), _) => {
+                    session.emit(|o| o.error(&EngineFailed::new(Operation::Generation, e)));
+                }
+                (_, Some(reason)) => session.emit(|o| {
+                    let started = self.started.unwrap_or_else(|| session.clock().now());
+                    o.generation_finished(&GenerationFinished {
+                        generated: self.yielded,
+                        reason,
+                        elapsed: session.clock().now().saturating_duration_since(started),
+                    })
+                }),
+                _ => {}
+            }
+        }
+        item
+    }
+}
@@ -0,0 +1,82 @@
+//! [`futures_core::Stream`] adapter for blocking iterators such as
+//! [`TokenIter`](crate::engine::token_iter::TokenIter) (feature **`async`**).
+//!
+//! Forward passes block for milliseconds to seconds, so they must not run on an async executor's
+//! worker threads. The adapter does not pick a runtime: every `next()` is wrapped in a closure and
+//! handed to a caller-provided `spawn`, e.g. with tokio
+//! `|step| async move { tokio::task::spawn_blocking(step).await.expect("generation step") }`.
+//! Thread-spawning runtimes need a `'static` iterator, i.e. an owned session and tokenizer.
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures_core::Stream;
+
+/// One blocking `next()` call: gives the iterator back together with its item.
+pub type BlockingStep<'i, I> = Box<dyn FnOnce() -> (I, Option<<I as Iterator>::Item>) + Send + 'i>;
+
+/// Stream that runs each `next()` of `I` through `spawn`; at most one step is in flight.
+pub struct BlockingStream<'i, I: Iterator, Sp, Fut> {
+    /// `None` while a step is in flight or after the iterator is exhausted.
+    iter: Option<I>,
+    spawn: Sp,
+    in_flight: Option<Pin<Box<Fut>>>,
+    _step: PhantomData<fn() -> BlockingStep<'i, I>>,
+}
+
+impl<'i, I, Sp, Fut> BlockingStream<'i, I, Sp, Fut>
+where
+    I: Iterator + Send + 'i,
+    I::Item: Send,
+    Sp: FnMut(BlockingStep<'i, I>) -> Fut,
+    Fut: Future<Output = (I, Option<I::Item>)>,
+{
+    pub fn new(iter: I, spawn: Sp) -> Self {
+        Self {
+            iter: Some(iter),
+            spawn,
+            in_flight: None,
+            _step: PhantomData,
+        }
+    }
+
+    /// The iterator, unless a step is in flight or it has been exhausted.
+    pub fn into_inner(self) -> Option<I> {
+        self.iter
+    }
+}
+
+impl<'i, I, Sp, Fut> Stream for BlockingStream<'i, I, Sp, Fut>
+where
+    I: Iterator + Send + Unpin + 'i,
+    I::Item: Send,
+    Sp: FnMut(BlockingStep<'i, I>) -> Fut + Unpin,
+    Fut: Future<Output = (I, Option<I::Item>)>,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        let this = self.get_mut();
+        let fut = match &mut this.in_flight {
+            Some(fut) => fut,
+            None => {
+                let Some(mut iter) = this.iter.take() else {
+                    return Poll::Ready(None);
+                };
+                let step: BlockingStep<'i, I> = Box::new(move || {
+                    let item = iter.next();
+                    (iter, item)
+                });
+                this.in_flight.insert(Box::pin((this.spawn)(step)))
+            }
+        };
+        let (iter, item) = ready!(fut.as_mut().poll(cx));
+        this.in_flight = None;
+        if item.is_some() {
+            this.iter = Some(iter);
+        }
+        Poll::Ready(item)
+    }
+}
@@ -0,0 +1,263 @@
+//! Replayable generation transcripts, for reproducing "it generated nonsense" reports.
+//!
+//! A [`Transcript`] holds everything [`generate_from_ids`] consumed — which model (by content
+//! hash), how it was loaded, the full [`GenerationConfig`] including the seed, the prompt and
+//! forced ids — and every token it sampled, the stop token included. Enable recording with
+//! [`InferenceSession::set_transcript`]; [`replay`] runs the recording again and reports the
+//! first sampled token that differs, with the replaying model's view of that step.
+//!
+//! Ids, not text, are recorded, so a replay does not depend on the tokenizer. Unless a
+//! [`ContextShift`](crate::engine::budget::ContextShift) is set (the replaying session needs
+//! the same one), a full budget fails the step, so the ids alone fix every forward pass. The
+//! file is JSON.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+use crate::engine::generation::{
+    FinishReason, GenerationConfig, GenerationOutput, generate_from_ids,
+};
+use crate::engine::sampling::{token_logprob, top_candidates};
+use crate::engine::session::InferenceSession;
+use crate::loaded_model::LoadedModel;
+use crate::model_loader::gguf_types::LoadOptions;
+
+/// Bumped when a field changes meaning; [`replay`] rejects other versions.
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+/// Candidates listed in a [`Divergence`].
+pub const DIVERGENCE_TOP_K: usize = 5;
+
+/// Which model a transcript was recorded on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelIdentity {
+    /// File name only, for humans; not compared.
+    pub file_name: String,
+    /// [`crate::model_loader::gguf_types::GGUFData::model_id`] (metadata only).
+    pub model_id: String,
+    /// [`LoadedModel::content_hash`] (weights and metadata).
+    pub content_hash: String,
+}
+
+impl ModelIdentity {
+    /// Identity of `model`; hashes every weight byte the first time it is called on a model.
+    pub fn of(model: &LoadedModel) -> Self {
+        let file_name = Path::new(model.model_path())
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+        Self {
+            file_name,
+            model_id: model.gguf().model_id(),
+            content_hash: model.content_hash().to_string(),
+        }
+    }
+}
+
+/// One recorded [`generate_from_ids`] run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub version: u32,
+    pub model: ModelIdentity,
+    pub load_options: LoadOptions,
+    pub config: GenerationConfig,
+    pub prompt_ids: Vec<u32>,
+    pub forced_ids: Vec<u32>,
+    /// Every sampled token in order; ends with the stop token when one fired.
+    pub sampled_ids: Vec<u32>,
+    pub finish_reason: FinishReason,
+}
+
+impl Transcript {
+    pub fn record(
+        model: &LoadedModel,
+        prompt_ids: &[u32],
+        forced_ids: &[u32],
+        config: &GenerationConfig,
+        out: &GenerationOutput,
+    ) -> Self {
+        Self {
+            version: TRANSCRIPT_VERSION,
+            model: ModelIdentity::of(model),
+            load_options: model.load_options().clone(),
+            config: config.clone(),
+            prompt_ids: prompt_ids.to_vec(),
+            forced_ids: forced_ids.to_vec(),
+            sampled_ids: sampled_ids(out),
+            finish_reason: out.finish_reason,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(self).map_err(|e| transcript_error(path, e))?;
+        Ok(std::fs::write(path, json)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        let transcript: Self =
+            serde_json::from_slice(&std::fs::read(path)?).map_err(|e| transcript_error(path, e))?;
+        if transcript.version != TRANSCRIPT_VERSION {
+            return Err(EngineError::Model(format!(
+                "transcript {}: version {} (this build reads {TRANSCRIPT_VERSION})",
+                path.display(),
+                transcript.version
+            )));
+        }
+        Ok(transcript)
+    }
+}
+
+/// The first sampled token that differs between a recording and its replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index into [`Transcript::sampled_ids`].
+    pub step: usize,
+    /// Recorded token; `None` if the recording stopped before this step.
+    pub expected: Option<u32>,
+    /// Replayed token; `None` if the replay stopped before this step.
+    pub actual: Option<u32>,
+    /// The replaying model's [`DIVERGENCE_TOP_K`] most likely tokens at this step, with their
+    /// probabilities, most likely first.
+    pub top: Vec<(u32, f32)>,
+    /// Log-probability of `expected` / `actual` on the replaying model.
+    pub expected_logprob: Option<f32>,
+    pub actual_logprob: Option<f32>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = |id: Option<u32>, lp: Option<f32>| match (id, lp) {
+            (Some(id), Some(lp)) => format!("{id} (logprob {lp:.4})"),
+            (Some(id), None) => id.to_string(),
+            (None, _) => "end".to_string(),
+        };
+        write!(
+            f,
+            "step {}: recorded {}, replayed {}; top:",
+            self.step,
+            token(self.expected, self.expected_logprob),
+            token(self.actual, self.actual_logprob)
+        )?;
+        for (id, p) in &self.top {
+            write!(f, " {id}={p:.4}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of [`replay`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    pub transcript: Transcript,
+    /// The session's model has the recorded [`ModelIdentity::content_hash`]. A mismatch does
+    /// not stop the replay; it usually explains a divergence.
+    pub model_matches: bool,
+    /// Sampled tokens that matched before the divergence (all of them without one).
+    pub steps_matched: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl ReplayReport {
+    /// Same model, same tokens.
+    pub fn is_exact(&self) -> bool {
+        self.model_matches && self.divergence.is_none()
+    }
+}
+
+/// Load the transcript at `path` and [`replay_transcript`] it.
+pub fn replay(
+    session: &mut InferenceSession<'_>,
+    path: impl AsRef<Path>,
+) -> Result<ReplayReport, EngineError> {
+    replay_transcript(session, Transcript::load(path)?)
+}
+
+/// Run `transcript`'s generation again on `session` and compare each sampled token with the
+/// recording. The session's own transcript recording is suspended meanwhile. At a divergence,
+/// the shared history is prefilled once more to report the model's candidates for that step.
+///
+/// The replay has no deadline: a run that ended at [`FinishReason::DeadlineExceeded`] is
+/// replayed up to the number of tokens it sampled.
+pub fn replay_transcript(
+    session: &mut InferenceSession<'_>,
+    transcript: Transcript,
+) -> Result<ReplayReport, EngineError> {
+    let model_matches = session.model().content_hash() == transcript.model.content_hash;
+    let recording = session.transcript().map(Path::to_path_buf);
+    session.set_transcript(None);
+    let mut config = transcript.config.clone();
+    config.deadline = None;
+    if transcript.finish_reason == FinishReason::DeadlineExceeded {
+        config.max_new_tokens = transcript.sampled_ids.len();
+    }
+    let out = generate_from_ids(
+        session,
+        &transcript.prompt_ids,
+        &transcript.forced_ids,
+        &config,
+    );
+    session.set_transcript(recording);
+
+    let actual = sampled_ids(&out?);
+    let expected = &transcript.sampled_ids;
+    let step = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i));
+    let divergence = match step {
+        Some(step) => Some(divergence_at(
+            session,
+            &transcript,
+            step,
+            actual.get(step).copied(),
+        )?),
+        None => None,
+    };
+    Ok(ReplayReport {
+        model_matches,
+        steps_matched: step.unwrap_or(expected.len()),
+        divergence,
+        transcript,
+    })
+}
+
+fn divergence_at(
+    session: &mut InferenceSession<'_>,
+    transcript: &Transcript,
+    step: usize,
+    actual: Option<u32>,
+) -> Result<Divergence, EngineError> {
+    let history: Vec<u32> = transcript
+        .prompt_ids
+        .iter()
+        .chain(&transcript.forced_ids)
+        .chain(&transcript.sampled_ids[..step])
+        .copied()
+        .collect();
+    session.reset();
+    let state = session.prefill(&history)?;
+    let logits = session.next_token_logits(&state)?;
+    let expected = transcript.sampled_ids.get(step).copied();
+    Ok(Divergence {
+        step,
+        expected,
+        actual,
+        top: top_candidates(&logits, DIVERGENCE_TOP_K),
+        expected_logprob: expected.and_then(|id| token_logprob(&logits, id)),
+        actual_logprob: actual.and_then(|id| token_logprob(&logits, id)),
+    })
+}
+
+/// Generated ids plus the stop token, if one ended the run.
+fn sampled_ids(out: &GenerationOutput) -> Vec<u32> {
+    let mut ids = out.generated_token_ids.clone();
+    if let FinishReason::Eos { token_id } = out.finish_reason {
+        ids.push(token_id);
+    }
+    ids
+}
+
+fn transcript_error(path: &Path, e: serde_json::Error) -> EngineError {
+    EngineError::Model(format!("transcript {}: {e}", path.display()))
+}
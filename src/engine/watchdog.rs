@@ -0,0 +1,439 @@
+//! Decode-step watchdog for runs that look hung (thermal throttling, swapping, a stuck kernel).
+//!
+//! A [`Watchdog`] owns one monitor thread. The session arms it for each decode step
+//! ([`Watchdog::arm`]); the forward pass marks the [`Stage`] it is in with [`enter_stage`], one
+//! relaxed atomic store per layer and op. If a step is still running after
+//! [`WatchdogConfig::stall_after`], the monitor logs a warning and sends
+//! [`EngineObserver::stall_detected`] with a [`StallReport`]: the stage, the time spent and the
+//! allocator counters. Past [`WatchdogConfig::cancel_after`] it also requests cancellation, which
+//! the forward pass honours at its next [`checkpoint`] (between layers) by failing with
+//! [`StallCancelled`]. Work already inside a kernel is never interrupted.
+//!
+//! [`enter_stage`] and [`checkpoint`] act on the watchdog whose [`Watchdog::scope`] the calling
+//! thread is in, and do nothing outside one, so the forward pass needs no extra parameters.
+//! Stall events are delivered on the monitor thread while the step is still running, unlike
+//! every other observer event.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::EngineError;
+use crate::core::time::Instant;
+use crate::engine::observer::EngineObserver;
+use crate::mem_profile::{MemoryStats, memory_stats};
+
+/// Where a forward pass is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardOp {
+    /// Not in a forward pass (or between steps).
+    #[default]
+    Idle,
+    Attention,
+    Ffn,
+}
+
+impl ForwardOp {
+    const ALL: [ForwardOp; 3] = [Self::Idle, Self::Attention, Self::Ffn];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Attention => "attention",
+            Self::Ffn => "ffn",
+        }
+    }
+}
+
+/// A layer and op of the forward pass, as last marked by [`enter_stage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stage {
+    pub layer: Option<usize>,
+    pub op: ForwardOp,
+}
+
+impl Stage {
+    pub fn layer(layer: usize, op: ForwardOp) -> Self {
+        Self {
+            layer: Some(layer),
+            op,
+        }
+    }
+
+    /// Packed as `op | (layer + 1) << 8`, with layer bits 0 for none.
+    fn pack(self) -> u64 {
+        let layer = self.layer.map_or(0, |l| l as u64 + 1);
+        (layer << 8) | self.op as u64
+    }
+
+    fn unpack(bits: u64) -> Self {
+        Self {
+            layer: (bits >> 8).checked_sub(1).map(|l| l as usize),
+            op: ForwardOp::ALL
+                .get((bits & 0xff) as usize)
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.layer {
+            Some(layer) => write!(f, "layer {layer} {}", self.op.name()),
+            None => f.write_str(self.op.name()),
+        }
+    }
+}
+
+/// When the watchdog reports and when it cancels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// A decode step running this long is reported as stalled (once per step).
+    pub stall_after: Duration,
+    /// A decode step running this long is cancelled; `None` only reports.
+    pub cancel_after: Option<Duration>,
+}
+
+/// What a stalled step was doing; sent as [`EngineObserver::stall_detected`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StallReport {
+    /// Cache position of the token being decoded.
+    pub position: usize,
+    pub stage: Stage,
+    /// Time since the step started.
+    pub elapsed: Duration,
+    /// Allocator counters (`None` without the `mem-profile` feature).
+    pub memory: Option<MemoryStats>,
+}
+
+impl fmt::Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "decode step at position {} has run {:.1?}, in {}",
+            self.position, self.elapsed, self.stage
+        )?;
+        if let Some(mem) = &self.memory {
+            write!(f, " ({} MiB allocated)", mem.current_bytes >> 20)?;
+        }
+        Ok(())
+    }
+}
+
+/// A step the watchdog cancelled past [`WatchdogConfig::cancel_after`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("cancelled by the watchdog: {0}")]
+pub struct StallCancelled(pub Box<StallReport>);
+
+/// Called on every [`enter_stage`] inside a scope; see [`Watchdog::set_stage_hook`].
+pub type StageHook = Arc<dyn Fn(Stage) + Send + Sync>;
+
+/// The armed step, if any. `generation` changes on every arm, so the monitor can tell a new step
+/// from the one it was timing.
+#[derive(Default)]
+struct ArmState {
+    generation: u64,
+    step: Option<(usize, Instant)>,
+    shutdown: bool,
+}
+
+struct Shared {
+    config: WatchdogConfig,
+    stage: AtomicU64,
+    cancel: AtomicBool,
+    cancelled: Mutex<Option<StallReport>>,
+    armed: Mutex<ArmState>,
+    wake: Condvar,
+    observer: Mutex<Option<Arc<dyn EngineObserver>>>,
+    /// Set while `stage_hook` holds a hook, so [`enter_stage`] skips the lock otherwise.
+    hooked: AtomicBool,
+    stage_hook: Mutex<Option<StageHook>>,
+}
+
+impl Shared {
+    fn lock_armed(&self) -> MutexGuard<'_, ArmState> {
+        self.armed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn report(&self, position: usize, started: Instant) -> StallReport {
+        StallReport {
+            position,
+            stage: Stage::unpack(self.stage.load(Ordering::Relaxed)),
+            elapsed: started.elapsed(),
+            memory: memory_stats(),
+        }
+    }
+
+    fn monitor(&self) {
+        let mut armed = self.lock_armed();
+        // (generation, stall reported, cancellation requested) for the step being timed.
+        let mut seen = (u64::MAX, false, false);
+        loop {
+            if armed.shutdown {
+                return;
+            }
+            let Some((position, started)) = armed.step else {
+                armed = self.wake.wait(armed).unwrap_or_else(|e| e.into_inner());
+                continue;
+            };
+            if seen.0 != armed.generation {
+                seen = (armed.generation, false, false);
+            }
+            let elapsed = started.elapsed();
+            let stall_due = !seen.1 && elapsed >= self.config.stall_after;
+            let cancel_due = !seen.2 && self.config.cancel_after.is_some_and(|c| elapsed >= c);
+            if stall_due || cancel_due {
+                let report = self.report(position, started);
+                drop(armed);
+                if stall_due {
+                    seen.1 = true;
+                    log::warn!("watchdog: {report}");
+                    let observer = self.observer.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Some(observer) = observer.as_ref() {
+                        observer.stall_detected(&report);
+                    }
+                }
+                if cancel_due {
+                    seen.2 = true;
+                    log::warn!("watchdog: cancelling the step");
+                    *self.cancelled.lock().unwrap_or_else(|e| e.into_inner()) = Some(report);
+                    self.cancel.store(true, Ordering::Release);
+                }
+                armed = self.lock_armed();
+                continue;
+            }
+            // Sleep until the next threshold this step has not passed, or until re-armed.
+            let next = [
+                (!seen.1).then_some(self.config.stall_after),
+                self.config.cancel_after.filter(|_| !seen.2),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            armed = match next {
+                Some(at) => {
+                    self.wake
+                        .wait_timeout(armed, at.saturating_sub(elapsed))
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.wake.wait(armed).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+/// The monitor thread and the stage marker it reads; see the module docs. Dropping it stops the
+/// thread.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start the monitor thread. Stall events go to `observer`, if any (see
+    /// [`Self::set_observer`]).
+    pub fn start(
+        config: WatchdogConfig,
+        observer: Option<Arc<dyn EngineObserver>>,
+    ) -> Result<Self, EngineError> {
+        let shared = Arc::new(Shared {
+            config,
+            stage: AtomicU64::new(Stage::default().pack()),
+            cancel: AtomicBool::new(false),
+            cancelled: Mutex::new(None),
+            armed: Mutex::new(ArmState::default()),
+            wake: Condvar::new(),
+            observer: Mutex::new(observer),
+            hooked: AtomicBool::new(false),
+            stage_hook: Mutex::new(None),
+        });
+        let monitor = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("engine-watchdog".into())
+            .spawn(move || monitor.monitor())?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn config(&self) -> WatchdogConfig {
+        self.shared.config
+    }
+
+    pub fn set_observer(&self, observer: Option<Arc<dyn EngineObserver>>) {
+        *self
+            .shared
+            .observer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = observer;
+    }
+
+    /// Call `hook` on the forward pass's thread each time it enters a stage, after the marker is
+    /// stored; e.g. to trace the stages, or to stall one in a test. `None` removes it.
+    pub fn set_stage_hook(&self, hook: Option<StageHook>) {
+        let mut slot = self
+            .shared
+            .stage_hook
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        self.shared.hooked.store(hook.is_some(), Ordering::Release);
+        *slot = hook;
+    }
+
+    /// Start timing the step that decodes the token at `position`, until the guard drops.
+    /// Clears any earlier cancellation.
+    pub fn arm(&self, position: usize) -> ArmedStep<'_> {
+        self.shared.cancel.store(false, Ordering::Release);
+        *self
+            .shared
+            .cancelled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+        self.shared
+            .stage
+            .store(Stage::default().pack(), Ordering::Relaxed);
+        let mut armed = self.shared.lock_armed();
+        armed.generation = armed.generation.wrapping_add(1);
+        armed.step = Some((position, Instant::now()));
+        self.shared.wake.notify_all();
+        ArmedStep { watchdog: self }
+    }
+
+    /// Run `f` with this watchdog as the calling thread's target for [`enter_stage`] and
+    /// [`checkpoint`]. Enter it on the thread that runs the forward pass (inside the pool's
+    /// `install`, not around it).
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = CURRENT.with(|c| c.replace(Some(Arc::clone(&self.shared))));
+        let _restore = RestoreCurrent(previous);
+        f()
+    }
+
+    /// The stage last marked in a [`Self::scope`].
+    pub fn stage(&self) -> Stage {
+        Stage::unpack(self.shared.stage.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.lock_armed().shutdown = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Returned by [`Watchdog::arm`]; disarms on drop.
+pub struct ArmedStep<'w> {
+    watchdog: &'w Watchdog,
+}
+
+impl Drop for ArmedStep<'_> {
+    fn drop(&mut self) {
+        let shared = &self.watchdog.shared;
+        shared
+            .stage
+            .store(Stage::default().pack(), Ordering::Relaxed);
+        shared.lock_armed().step = None;
+        shared.wake.notify_all();
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+struct RestoreCurrent(Option<Arc<Shared>>);
+
+impl Drop for RestoreCurrent {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Mark the forward pass as being in `stage`. No-op outside a [`Watchdog::scope`].
+pub fn enter_stage(stage: Stage) {
+    CURRENT.with(|c| {
+        let current = c.borrow();
+        let Some(shared) = current.as_ref() else {
+            return;
+        };
+        shared.stage.store(stage.pack(), Ordering::Relaxed);
+        if shared.hooked.load(Ordering::Acquire) {
+            let hook = shared
+                .stage_hook
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            if let Some(hook) = hook {
+                hook(stage);
+            }
+        }
+    });
+}
+
+/// Fail with [`StallCancelled`] if the watchdog of the current [`Watchdog::scope`] cancelled the
+/// step. Always `Ok` outside a scope.
+pub fn checkpoint() -> Result<(), EngineError> {
+    CURRENT.with(|c| {
+        let current = c.borrow();
+        let Some(shared) = current.as_ref() else {
+            return Ok(());
+        };
+        if !shared.cancel.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let report = shared
+            .cancelled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .unwrap_or_default();
+        Err(StallCancelled(Box::new(report)).into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_round_trip_through_the_marker_bits() {
+        for stage in [
+            Stage::default(),
+            Stage::layer(0, ForwardOp::Attention),
+            Stage::layer(31, ForwardOp::Ffn),
+        ] {
+            assert_eq!(Stage::unpack(stage.pack()), stage);
+        }
+        assert_eq!(Stage::layer(3, ForwardOp::Ffn).to_string(), "layer 3 ffn");
+    }
+
+    #[test]
+    fn markers_and_checkpoints_are_inert_outside_a_scope() {
+        enter_stage(Stage::layer(1, ForwardOp::Ffn));
+        assert!(checkpoint().is_ok());
+        let watchdog = Watchdog::start(
+            WatchdogConfig {
+                stall_after: Duration::from_secs(60),
+                cancel_after: None,
+            },
+            None,
+        )
+        .unwrap();
+        enter_stage(Stage::layer(1, ForwardOp::Ffn));
+        assert_eq!(watchdog.stage(), Stage::default());
+        watchdog.scope(|| enter_stage(Stage::layer(2, ForwardOp::Attention)));
+        assert_eq!(watchdog.stage(), Stage::layer(2, ForwardOp::Attention));
+    }
+}
@@ -2,6 +2,9 @@
 
 use thiserror::Error;
 
+/// `Result` with [`EngineError`] as the default error type, re-exported as `crate::Result`.
+pub type Result<T, E = EngineError> = std::result::Result<T, E>;
+
 #[derive(Debug, Error)]
 pub enum EngineError {
     #[error(transparent)]
@@ -14,9 +17,17 @@ pub enum EngineError {
     #[error("GGUF: {0}")]
     Gguf(String),
 
+    /// A header string or array broke a [`MetadataLimits`](crate::model_loader::gguf_types::MetadataLimits) ceiling.
+    #[error("GGUF: {0}")]
+    MetadataLimit(#[from] crate::model_loader::gguf_types::MetadataLimitError),
+
     #[error("tensor: {0}")]
     Tensor(String),
 
+    /// Size arithmetic on untrusted dimensions (element or byte counts) overflowed `usize`.
+    #[error("size overflow: {0}")]
+    Overflow(String),
+
     #[error("matmul: {0}")]
     MatMul(String),
 
@@ -26,13 +37,61 @@ pub enum EngineError {
     #[error("tokenizer: {0}")]
     Tokenizer(String),
 
+    /// Nothing to run the model on: a prompt that encodes to zero tokens with no BOS added, an
+    /// empty token list, or an empty chat message. Raised before the model is touched.
+    #[error("empty prompt: {0}")]
+    EmptyPrompt(String),
+
+    /// Prompt text the vocabulary cannot encode, under
+    /// [`UnknownTokenPolicy::Error`](crate::tokenizer::UnknownTokenPolicy::Error).
+    #[error("tokenizer: {0}")]
+    UnknownText(#[from] crate::tokenizer::UnknownTextError),
+
     #[error(transparent)]
     KvCache(#[from] crate::layers::attention::KVCacheError),
 
+    #[error(transparent)]
+    Budget(#[from] crate::engine::budget::BudgetError),
+
+    #[error(transparent)]
+    KvCachePolicy(#[from] crate::engine::kv_policy::KvCachePolicyError),
+
+    #[error(transparent)]
+    Registry(#[from] crate::model_loader::registry::RegistryError),
+
     #[error(transparent)]
     Sampling(#[from] crate::engine::sampling::SamplingError),
 
+    /// A [`GenerationConfig`](crate::engine::generation::GenerationConfig) value out of range.
+    #[error("generation config: {0}")]
+    Config(#[from] crate::engine::generation::ConfigError),
+
+    /// A merged [`EffectiveConfig`](crate::engine::effective_config::EffectiveConfig) that cannot
+    /// run, with the source of each conflicting value.
+    #[error("generation config: {0}")]
+    EffectiveConfig(#[from] crate::engine::effective_config::EffectiveConfigError),
+
+    #[error(transparent)]
+    JsonSchema(#[from] crate::engine::json_schema::JsonSchemaError),
+
+    /// A decode step the session's watchdog cancelled.
+    #[error(transparent)]
+    Stalled(#[from] crate::engine::watchdog::StallCancelled),
+
     /// Invalid arguments to a low-level op (e.g. RoPE dimensions).
     #[error("invalid op: {0}")]
     Op(String),
 }
+
+impl From<crate::ops::math::OpsError> for EngineError {
+    /// Short quant blocks are tensor errors and blocks past a weight's buffer matmul errors, as
+    /// before the kernels moved to [`crate::ops::math`]; everything else is an invalid op.
+    fn from(e: crate::ops::math::OpsError) -> Self {
+        use crate::ops::math::OpsError;
+        match e {
+            OpsError::ShortBlock { .. } => Self::Tensor(e.to_string()),
+            OpsError::BlockOutOfBounds { .. } => Self::MatMul(e.to_string()),
+            _ => Self::Op(e.to_string()),
+        }
+    }
+}
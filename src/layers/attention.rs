@@ -1,21 +1,171 @@
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::ops::Range;
 use std::sync::Arc;
 use thiserror::Error;
 
 use crate::EngineError;
 use crate::core::tensor::{Tensor, TensorType};
 use crate::engine::state::ForwardState;
+use crate::layers::kv_store::{self, KVStore};
+use crate::layers::lora::LoraTarget;
 use crate::model_config::{LayerAttentionSpec, LayerDims, ModelConfig, ModelFamily};
 use crate::model_weights::LayerWeights;
-use crate::ops::matmul::matmul;
+use crate::ops::matmul::{matmul, matmul_add};
+use crate::ops::quant::utils::f16_to_f32_lut;
 use crate::ops::residual_add::residual_add;
-use crate::ops::rmsnorm::{rmsnorm, rmsnorm_inplace_no_scale};
+use crate::ops::rmsnorm::{rmsnorm_inplace_no_scale, rmsnorm_with_offset};
 use crate::ops::rope::rope;
-use crate::ops::softmax::softmax;
+use crate::ops::specialized;
+
+/// Element type of [`KVCache`] storage (engine option, see [`crate::engine::config::EngineConfig`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheDtype {
+    /// Exact: what the attention kernel computed.
+    #[default]
+    F32,
+    /// Half the memory; K/V are rounded to f16 (round-to-nearest-even) on append and widened
+    /// per element when attention reads them. The llama.cpp default.
+    F16,
+    /// About a quarter of the memory: each `[head_dim]` row is stored as i8 with one f32 scale
+    /// (absmax / 127), like a `Q8_0` block the width of a head.
+    Q8,
+}
+
+impl CacheDtype {
+    /// `f32` / `f16` / `q8` (as on the command line).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "f32" => Some(Self::F32),
+            "f16" => Some(Self::F16),
+            "q8" => Some(Self::Q8),
+            _ => None,
+        }
+    }
+
+    /// Inverse of [`Self::parse`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F16 => "f16",
+            Self::Q8 => "q8",
+        }
+    }
+
+    /// Bytes per stored element, not counting [`CacheDtype::Q8`]'s per-row scale; see
+    /// [`Self::row_bytes`].
+    pub fn bytes_per_element(self) -> usize {
+        match self {
+            Self::F32 => 4,
+            Self::F16 => 2,
+            Self::Q8 => 1,
+        }
+    }
+
+    /// Bytes for one `[head_dim]` K or V row, scale included.
+    pub fn row_bytes(self, head_dim: usize) -> usize {
+        let scale = match self {
+            Self::Q8 => size_of::<f32>(),
+            Self::F32 | Self::F16 => 0,
+        };
+        head_dim * self.bytes_per_element() + scale
+    }
+}
+
+/// One cached `[head_dim]` key or value vector, in the cache's storage dtype.
+///
+/// f16 and q8 rows are widened per element inside [`Self::dot`] / [`Self::axpy_into`], so
+/// attention never materializes an f32 copy of the cache.
+#[derive(Debug, Clone, Copy)]
+pub enum KvRow<'a> {
+    F32(&'a [f32]),
+    F16(&'a [u16]),
+    /// Element `i` is `quants[i] as f32 * scale`.
+    Q8 {
+        quants: &'a [i8],
+        scale: f32,
+    },
+}
+
+impl KvRow<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::F32(r) => r.len(),
+            Self::F16(r) => r.len(),
+            Self::Q8 { quants, .. } => quants.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `q · row`. f32 rows go through [`specialized::dot`]; f16 rows use the same
+    /// [`specialized::LANES`]-way accumulation.
+    pub fn dot(&self, q: &[f32]) -> f32 {
+        match self {
+            Self::F32(r) => specialized::dot(q, r),
+            Self::F16(r) => {
+                assert_eq!(q.len(), r.len(), "dot: length mismatch");
+                let mut acc = [0.0f32; specialized::LANES];
+                let (cq, cr) = (
+                    q.chunks_exact(specialized::LANES),
+                    r.chunks_exact(specialized::LANES),
+                );
+                let tail: f32 = cq
+                    .remainder()
+                    .iter()
+                    .zip(cr.remainder())
+                    .map(|(&x, &h)| x * f16_to_f32_lut(h))
+                    .sum();
+                for (xq, xr) in cq.zip(cr) {
+                    for l in 0..specialized::LANES {
+                        acc[l] += xq[l] * f16_to_f32_lut(xr[l]);
+                    }
+                }
+                acc.iter().sum::<f32>() + tail
+            }
+            Self::Q8 { quants, scale } => {
+                assert_eq!(q.len(), quants.len(), "dot: length mismatch");
+                let sum: f32 = q.iter().zip(*quants).map(|(&x, &w)| x * w as f32).sum();
+                sum * scale
+            }
+        }
+    }
+
+    /// `out[i] += alpha * row[i]`.
+    pub fn axpy_into(&self, alpha: f32, out: &mut [f32]) {
+        match self {
+            Self::F32(r) => specialized::axpy(alpha, r, out),
+            Self::F16(r) => {
+                assert_eq!(r.len(), out.len(), "axpy: length mismatch");
+                for (o, &h) in out.iter_mut().zip(*r) {
+                    *o += alpha * f16_to_f32_lut(h);
+                }
+            }
+            Self::Q8 { quants, scale } => {
+                assert_eq!(quants.len(), out.len(), "axpy: length mismatch");
+                let a = alpha * scale;
+                for (o, &w) in out.iter_mut().zip(*quants) {
+                    *o += a * w as f32;
+                }
+            }
+        }
+    }
+
+    pub fn to_f32_vec(&self) -> Vec<f32> {
+        match self {
+            Self::F32(r) => r.to_vec(),
+            Self::F16(r) => r.iter().map(|&h| f16_to_f32_lut(h)).collect(),
+            Self::Q8 { quants, scale } => quants.iter().map(|&w| w as f32 * scale).collect(),
+        }
+    }
+}
+
 /// Per-layer KV cache: one `[head_dim]` slice per **KV head** per timestep (GQA/MQA).
 pub struct KVCache {
-    k_cache: Vec<f32>,
-    v_cache: Vec<f32>,
+    k_cache: Box<dyn KVStore>,
+    v_cache: Box<dyn KVStore>,
     current_pos: usize,
     max_seq_len: usize,
     /// Number of key/value heads (≤ query head count; equal for standard MHA).
@@ -25,12 +175,21 @@ pub struct KVCache {
 
 impl KVCache {
     pub fn new(max_seq_len: usize, n_kv_heads: usize, head_dim: usize) -> Self {
+        Self::with_dtype(max_seq_len, n_kv_heads, head_dim, CacheDtype::F32)
+    }
+
+    pub fn with_dtype(
+        max_seq_len: usize,
+        n_kv_heads: usize,
+        head_dim: usize,
+        dtype: CacheDtype,
+    ) -> Self {
         let stride = n_kv_heads * head_dim;
         let total_size = max_seq_len * stride;
 
         Self {
-            k_cache: vec![0.0; total_size],
-            v_cache: vec![0.0; total_size],
+            k_cache: kv_store::zeros(dtype, total_size, head_dim),
+            v_cache: kv_store::zeros(dtype, total_size, head_dim),
             current_pos: 0,
             max_seq_len,
             n_kv_heads,
@@ -46,11 +205,38 @@ impl KVCache {
         self.head_dim
     }
 
+    pub fn dtype(&self) -> CacheDtype {
+        self.k_cache.dtype()
+    }
+
     /// Number of timesteps stored in the cache (next write index).
     pub fn current_pos(&self) -> usize {
         self.current_pos
     }
 
+    /// Drop the timesteps from `len` on (no-op if the cache holds fewer); their rows are
+    /// overwritten by the next appends.
+    pub fn truncate(&mut self, len: usize) {
+        self.current_pos = self.current_pos.min(len);
+    }
+
+    /// Timesteps the cache can hold.
+    pub fn max_seq_len(&self) -> usize {
+        self.max_seq_len
+    }
+
+    /// Bytes held by K and V for the full `max_seq_len` (from [`kv_cache_layer_bytes`]).
+    pub fn allocated_bytes(&self) -> usize {
+        kv_cache_layer_bytes(
+            self.max_seq_len,
+            self.n_kv_heads,
+            self.head_dim,
+            self.dtype(),
+        )
+    }
+
+    /// Append one timestep; with [`CacheDtype::F16`] / [`CacheDtype::Q8`] the values are rounded
+    /// here.
     pub fn append_kv(&mut self, k: &[f32], v: &[f32]) -> Result<(), KVCacheError> {
         if self.current_pos >= self.max_seq_len {
             return Err(KVCacheError::KVCacheFull {
@@ -66,15 +252,14 @@ impl KVCache {
 
         let start_idx = self.current_pos * expected_len;
 
-        self.k_cache[start_idx..start_idx + expected_len].copy_from_slice(k);
-        self.v_cache[start_idx..start_idx + expected_len].copy_from_slice(v);
+        self.k_cache.write(start_idx, k);
+        self.v_cache.write(start_idx, v);
 
         self.current_pos += 1;
         Ok(())
     }
 
-    /// Key vector for timestep `position` and KV head `kv_head` (length `head_dim`).
-    pub fn get_k_slice(&self, position: usize, kv_head: usize) -> Result<&[f32], KVCacheError> {
+    fn row_start(&self, position: usize, kv_head: usize) -> Result<usize, KVCacheError> {
         if position >= self.current_pos {
             return Err(KVCacheError::PositionOutOfBounds {
                 position,
@@ -87,26 +272,212 @@ impl KVCache {
                 n_kv_heads: self.n_kv_heads,
             });
         }
-        let start_pos = position * self.n_kv_heads * self.head_dim + kv_head * self.head_dim;
-        Ok(&self.k_cache[start_pos..start_pos + self.head_dim])
+        Ok(position * self.n_kv_heads * self.head_dim + kv_head * self.head_dim)
+    }
+
+    /// Key vector for timestep `position` and KV head `kv_head`, in storage dtype.
+    pub fn k_row(&self, position: usize, kv_head: usize) -> Result<KvRow<'_>, KVCacheError> {
+        let start = self.row_start(position, kv_head)?;
+        Ok(self.k_cache.row(start, self.head_dim))
+    }
+
+    /// Value vector for timestep `position` and KV head `kv_head`, in storage dtype.
+    pub fn v_row(&self, position: usize, kv_head: usize) -> Result<KvRow<'_>, KVCacheError> {
+        let start = self.row_start(position, kv_head)?;
+        Ok(self.v_cache.row(start, self.head_dim))
+    }
+
+    /// Key vector for timestep `position` and KV head `kv_head` (length `head_dim`).
+    /// Only for [`CacheDtype::F32`] caches; use [`Self::k_row`] for any dtype.
+    pub fn get_k_slice(&self, position: usize, kv_head: usize) -> Result<&[f32], KVCacheError> {
+        match self.k_row(position, kv_head)? {
+            KvRow::F32(row) => Ok(row),
+            _ => Err(KVCacheError::NotF32(self.dtype())),
+        }
     }
 
     /// Value vector for timestep `position` and KV head `kv_head` (length `head_dim`).
+    /// Only for [`CacheDtype::F32`] caches; use [`Self::v_row`] for any dtype.
     pub fn get_v_slice(&self, position: usize, kv_head: usize) -> Result<&[f32], KVCacheError> {
-        if position >= self.current_pos {
-            return Err(KVCacheError::PositionOutOfBounds {
-                position,
-                current_pos: self.current_pos,
+        match self.v_row(position, kv_head)? {
+            KvRow::F32(row) => Ok(row),
+            _ => Err(KVCacheError::NotF32(self.dtype())),
+        }
+    }
+
+    /// Copy of the filled timesteps, e.g. right after prefilling a shared system prompt.
+    pub fn snapshot(&self) -> KVCacheSnapshot {
+        let filled = self.current_pos * self.n_kv_heads * self.head_dim;
+        KVCacheSnapshot {
+            k: self.k_cache.prefix(filled),
+            v: self.v_cache.prefix(filled),
+            len: self.current_pos,
+            n_kv_heads: self.n_kv_heads,
+            head_dim: self.head_dim,
+        }
+    }
+
+    /// Rewind or fast-forward to `snapshot`; later appends continue from its length. On error
+    /// the cache is unchanged.
+    pub fn restore(&mut self, snapshot: &KVCacheSnapshot) -> Result<(), KVCacheError> {
+        self.check_restore(snapshot)?;
+        self.k_cache.copy_prefix_from(&*snapshot.k);
+        self.v_cache.copy_prefix_from(&*snapshot.v);
+        self.current_pos = snapshot.len;
+        Ok(())
+    }
+
+    /// Whether [`Self::restore`] would accept `snapshot`: same head count, head width and dtype,
+    /// and no longer than the cache. Lets a caller validate every layer before touching any.
+    pub fn check_restore(&self, snapshot: &KVCacheSnapshot) -> Result<(), KVCacheError> {
+        if snapshot.n_kv_heads != self.n_kv_heads
+            || snapshot.head_dim != self.head_dim
+            || snapshot.len > self.max_seq_len
+        {
+            return Err(KVCacheError::SnapshotMismatch {
+                snapshot: (snapshot.len, snapshot.n_kv_heads, snapshot.head_dim),
+                cache: (self.max_seq_len, self.n_kv_heads, self.head_dim),
             });
         }
-        if kv_head >= self.n_kv_heads {
-            return Err(KVCacheError::KvHeadOutOfBounds {
-                kv_head,
-                n_kv_heads: self.n_kv_heads,
+        if snapshot.dtype() != self.dtype() {
+            return Err(KVCacheError::SnapshotDtypeMismatch {
+                snapshot: snapshot.dtype(),
+                cache: self.dtype(),
             });
         }
-        let start_pos = position * self.n_kv_heads * self.head_dim + kv_head * self.head_dim;
-        Ok(&self.v_cache[start_pos..start_pos + self.head_dim])
+        Ok(())
+    }
+}
+
+/// Filled part of one [`KVCache`], from [`KVCache::snapshot`]; keeps the cache's dtype.
+#[derive(Debug)]
+pub struct KVCacheSnapshot {
+    k: Box<dyn KVStore>,
+    v: Box<dyn KVStore>,
+    len: usize,
+    n_kv_heads: usize,
+    head_dim: usize,
+}
+
+impl Clone for KVCacheSnapshot {
+    fn clone(&self) -> Self {
+        Self {
+            k: self.k.prefix(self.k.len()),
+            v: self.v.prefix(self.v.len()),
+            ..*self
+        }
+    }
+}
+
+/// Equal when the encodings match: same shape, dtype and stored bits.
+impl PartialEq for KVCacheSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+/// Leading bytes of [`KVCacheSnapshot::to_bytes`].
+const SNAPSHOT_MAGIC: &[u8; 4] = b"KVS1";
+
+/// Multiply-adds (scores plus weighted values, over all heads) below which one query's heads
+/// run inline: splitting a short-context decode step across the pool costs more than it saves.
+/// Sized with `cargo bench --bench thread_pool -- attention_heads` (measurements there).
+const ATTENTION_PARALLEL_MIN_OPS: usize = 32 * 1024;
+
+/// Run `per_head` over the `head_dim` chunks of `out` with their head index, in parallel once
+/// `work` reaches [`ATTENTION_PARALLEL_MIN_OPS`]. Each head writes only its own chunk, so the
+/// result does not depend on which way it ran.
+fn for_each_head<F>(
+    out: &mut [f32],
+    head_dim: usize,
+    work: usize,
+    per_head: F,
+) -> Result<(), EngineError>
+where
+    F: Fn((usize, &mut [f32])) -> Result<(), EngineError> + Send + Sync,
+{
+    if work >= ATTENTION_PARALLEL_MIN_OPS {
+        out.par_chunks_mut(head_dim)
+            .enumerate()
+            .try_for_each(per_head)
+    } else {
+        out.chunks_mut(head_dim).enumerate().try_for_each(per_head)
+    }
+}
+
+impl KVCacheSnapshot {
+    /// Timesteps captured.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn dtype(&self) -> CacheDtype {
+        self.k.dtype()
+    }
+
+    /// Little-endian encoding: magic, dtype byte (0 = f32, 1 = f16, 2 = q8), `len`,
+    /// `n_kv_heads`, `head_dim` as u64, then K and V in storage dtype (for q8, each buffer's i8
+    /// values followed by its f32 row scales). f16 snapshots are half the size.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rows = self.len * self.n_kv_heads;
+        let mut out = Vec::with_capacity(29 + 2 * rows * self.dtype().row_bytes(self.head_dim));
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(match self.dtype() {
+            CacheDtype::F32 => 0,
+            CacheDtype::F16 => 1,
+            CacheDtype::Q8 => 2,
+        });
+        for dim in [self.len, self.n_kv_heads, self.head_dim] {
+            out.extend_from_slice(&(dim as u64).to_le_bytes());
+        }
+        self.k.extend_le_bytes(&mut out);
+        self.v.extend_le_bytes(&mut out);
+        out
+    }
+
+    /// Inverse of [`Self::to_bytes`]; rejects truncated or trailing data.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KVCacheError> {
+        let bad = |msg: String| KVCacheError::InvalidSnapshotBytes(msg);
+        if bytes.len() < 29 || &bytes[..4] != SNAPSHOT_MAGIC {
+            return Err(bad("missing KVS1 header".into()));
+        }
+        let dtype = match bytes[4] {
+            0 => CacheDtype::F32,
+            1 => CacheDtype::F16,
+            2 => CacheDtype::Q8,
+            other => return Err(bad(format!("unknown dtype tag {other}"))),
+        };
+        let dim = |i: usize| -> Result<usize, KVCacheError> {
+            let raw = u64::from_le_bytes(bytes[5 + 8 * i..13 + 8 * i].try_into().unwrap());
+            usize::try_from(raw).map_err(|_| bad(format!("dimension {raw} does not fit usize")))
+        };
+        let (len, n_kv_heads, head_dim) = (dim(0)?, dim(1)?, dim(2)?);
+        let rows = len
+            .checked_mul(n_kv_heads)
+            .ok_or_else(|| bad("element count overflows".into()))?;
+        let n = rows
+            .checked_mul(head_dim)
+            .ok_or_else(|| bad("element count overflows".into()))?;
+        let row_bytes = dtype.row_bytes(head_dim);
+        let payload = &bytes[29..];
+        if rows.checked_mul(2 * row_bytes) != Some(payload.len()) {
+            return Err(bad(format!(
+                "expected {rows} K and {rows} V rows of {row_bytes} bytes, got {} payload bytes",
+                payload.len()
+            )));
+        }
+        let (k, v) = payload.split_at(rows * row_bytes);
+        Ok(Self {
+            k: kv_store::from_le_bytes(dtype, k, n, head_dim),
+            v: kv_store::from_le_bytes(dtype, v, n, head_dim),
+            len,
+            n_kv_heads,
+            head_dim,
+        })
     }
 }
 
@@ -123,14 +494,67 @@ pub enum KVCacheError {
 
     #[error("KV head index {kv_head} is out of bounds (n_kv_heads is {n_kv_heads})")]
     KvHeadOutOfBounds { kv_head: usize, n_kv_heads: usize },
+
+    #[error(
+        "KV snapshot (len, n_kv_heads, head_dim) = {snapshot:?} does not fit cache (max_len, n_kv_heads, head_dim) = {cache:?}"
+    )]
+    SnapshotMismatch {
+        snapshot: (usize, usize, usize),
+        cache: (usize, usize, usize),
+    },
+
+    #[error("KV snapshot dtype {snapshot:?} does not match cache dtype {cache:?}")]
+    SnapshotDtypeMismatch {
+        snapshot: CacheDtype,
+        cache: CacheDtype,
+    },
+
+    #[error("KV cache stores {0:?}; f32 slices are only available from an F32 cache")]
+    NotF32(CacheDtype),
+
+    #[error("invalid KV snapshot bytes: {0}")]
+    InvalidSnapshotBytes(String),
 }
 
 /// One [`KVCache`] per layer, sized from [`ModelConfig::layer_dims`] (per-layer head width).
 pub fn kv_caches_for_config(config: &ModelConfig) -> Vec<KVCache> {
+    kv_caches_for_config_with_dtype(config, CacheDtype::F32)
+}
+
+/// Bytes of K and V in one layer's [`KVCache`] holding `context_len` timesteps. Saturates
+/// instead of overflowing, so absurd sizes still compare as too large.
+pub fn kv_cache_layer_bytes(
+    context_len: usize,
+    n_kv_heads: usize,
+    head_dim: usize,
+    dtype: CacheDtype,
+) -> usize {
+    context_len
+        .saturating_mul(n_kv_heads)
+        .saturating_mul(dtype.row_bytes(head_dim))
+        .saturating_mul(2)
+}
+
+/// Memory [`kv_caches_for_config_with_dtype`] would take with `context_len` timesteps per
+/// layer. The one place cache sizes are computed: [`KVCache::allocated_bytes`] and
+/// [`crate::engine::kv_policy`] both go through [`kv_cache_layer_bytes`].
+pub fn kv_cache_bytes(config: &ModelConfig, context_len: usize, dtype: CacheDtype) -> usize {
+    config.layer_dims.iter().fold(0usize, |total, d| {
+        total.saturating_add(kv_cache_layer_bytes(
+            context_len,
+            config.n_kv_heads,
+            d.head_dim,
+            dtype,
+        ))
+    })
+}
+
+/// [`kv_caches_for_config`] with `dtype` storage.
+pub fn kv_caches_for_config_with_dtype(config: &ModelConfig, dtype: CacheDtype) -> Vec<KVCache> {
     config
         .layer_dims
         .iter()
-        .map(|d| KVCache::new(config.context_length, config.n_kv_heads, d.head_dim))
+        .map(|d| KVCache::with_dtype(config.context_length, config.n_kv_heads, d.head_dim, dtype))
         .collect()
 }
 
@@ -146,6 +570,27 @@ fn rope_freq_slice<'a>(weights: &'a LayerWeights<'a>) -> Option<&'a [f32]> {
         .filter(|s| !s.is_empty())
 }
 
+/// The `freq_factors` [`rope`] gets for this layer: the checkpoint's `rope_freqs` times the
+/// context-extension scales of [`LayerAttentionSpec::rope_freq_scales`] (a zero checkpoint factor
+/// counts as 1, as in [`rope`]). Borrowed when only one of the two is present.
+fn rope_factors<'a>(
+    weights: &'a LayerWeights<'a>,
+    layer_attn: &'a LayerAttentionSpec,
+) -> Option<Cow<'a, [f32]>> {
+    match (
+        rope_freq_slice(weights),
+        layer_attn.rope_freq_scales.as_deref(),
+    ) {
+        (Some(ff), Some(scales)) => Some(Cow::Owned(
+            ff.iter()
+                .zip(scales)
+                .map(|(&f, &s)| if f == 0.0 { s } else { f * s })
+                .collect(),
+        )),
+        (ff, scales) => ff.or(scales).map(Cow::Borrowed),
+    }
+}
+
 pub fn unpack_llama_gguf_qk_row(row: &mut [f32], n_groups: usize, head_dim: usize) {
     assert!(
         head_dim % 2 == 0,
@@ -171,7 +616,15 @@ pub fn unpack_llama_gguf_qk_row(row: &mut [f32], n_groups: usize, head_dim: usiz
     }
 }
 
-#[allow(clippy::needless_range_loop)]
+/// Causal self-attention over `input`, appending its K/V to `kv_caches[layer_idx]`. Returns the
+/// `attn_output` projection, or `residual` plus that projection (fused, see
+/// [`matmul_add`]) when `residual` is given.
+///
+/// `mask`, when given, replaces causal masking: an additive `[seq_len, n_keys]` row-major mask,
+/// where `n_keys` is the cache length after this chunk is appended. Every row attends over all
+/// `n_keys` positions and the mask alone decides what it sees (future keys included, e.g. for a
+/// prefix-LM's bidirectional prefix; the sliding window is not applied).
+#[allow(clippy::needless_range_loop, clippy::too_many_arguments)]
 pub fn prefill_attention_layer(
     input: &ForwardState,
     config: &ModelConfig,
@@ -180,6 +633,8 @@ pub fn prefill_attention_layer(
     weights: &LayerWeights,
     kv_caches: &mut [KVCache],
     layer_idx: usize,
+    residual: Option<&[f32]>,
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let seq_len = input.seq_len();
     let hidden_dim = input.hidden_dim();
@@ -232,14 +687,24 @@ pub fn prefill_attention_layer(
                 "prefill attention: borrow source KV shape mismatch".into(),
             ));
         }
-        if ksrc.current_pos() != seq_len {
+        if ksrc.current_pos() < seq_len {
             return Err(EngineError::Model(format!(
-                "prefill attention: borrow layer {layer_idx} expected source cache len {seq_len}, got {}",
+                "prefill attention: borrow layer {layer_idx} expected source cache len >= {seq_len}, got {}",
                 ksrc.current_pos()
             )));
         }
     }
 
+    // Absolute position of the first row: non-zero when the prompt is prefilled in chunks
+    // (earlier chunks are already in the cache). A borrowing layer's source has already
+    // appended this chunk, so its start is `len - seq_len`.
+    let start_pos = match borrow_src {
+        Some(src) => kv_caches[src].current_pos() - seq_len,
+        None => own.current_pos(),
+    };
+    let n_keys = start_pos + seq_len;
+    check_layer_mask("prefill attention", mask, seq_len, n_keys)?;
+
     let group_size = config.n_heads / config.n_kv_heads;
 
     let input_tensor = tensor_from_f32_slice(input.hidden(), vec![seq_len, hidden_dim]);
@@ -248,9 +713,18 @@ pub fn prefill_attention_layer(
     let mut v_tensor = empty_f32_tensor(vec![seq_len, kv_dim]);
 
     matmul(&input_tensor, weights.wq, &mut q_tensor)?;
+    weights
+        .lora
+        .apply(LoraTarget::Q, &input_tensor, &mut q_tensor)?;
     if borrow_src.is_none() {
         matmul(&input_tensor, weights.wk, &mut k_tensor)?;
         matmul(&input_tensor, weights.wv, &mut v_tensor)?;
+        weights
+            .lora
+            .apply(LoraTarget::K, &input_tensor, &mut k_tensor)?;
+        weights
+            .lora
+            .apply(LoraTarget::V, &input_tensor, &mut v_tensor)?;
     }
 
     let q_data = q_tensor.as_f32_slice_mut()?;
@@ -281,6 +755,7 @@ pub fn prefill_attention_layer(
             config.n_heads,
             head_dim,
             weights.attn_q_norm,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut head_scratch,
         )?;
@@ -290,6 +765,7 @@ pub fn prefill_attention_layer(
                 config.n_kv_heads,
                 head_dim,
                 weights.attn_k_norm,
+                config.norm_weight_offset,
                 config.rms_norm_eps,
                 &mut head_scratch,
             )?;
@@ -309,7 +785,7 @@ pub fn prefill_attention_layer(
 
     let rope_base = layer_attn.rope_theta;
     let rope_rotary = layer_attn.rope_rotary_dim as u32;
-    let rope_ff = rope_freq_slice(weights);
+    let rope_ff = rope_factors(weights, layer_attn);
 
     for pos in 0..seq_len {
         let q_row = pos * q_dim;
@@ -319,10 +795,10 @@ pub fn prefill_attention_layer(
             rope(
                 &mut q_data[head_start..head_end],
                 rope_base,
-                pos as u32,
+                (start_pos + pos) as u32,
                 head_dim as u32,
                 rope_rotary,
-                rope_ff,
+                rope_ff.as_deref(),
             )?;
         }
         if borrow_src.is_none() {
@@ -333,10 +809,10 @@ pub fn prefill_attention_layer(
                 rope(
                     &mut k_data[head_start..head_end],
                     rope_base,
-                    pos as u32,
+                    (start_pos + pos) as u32,
                     head_dim as u32,
                     rope_rotary,
-                    rope_ff,
+                    rope_ff.as_deref(),
                 )?;
             }
         }
@@ -358,67 +834,179 @@ pub fn prefill_attention_layer(
         ModelFamily::Gemma4 => 1.0f32,
         ModelFamily::MistralLlama => 1.0f32 / (head_dim as f32).sqrt(),
     };
+    let softcap = config.attn_logit_softcapping;
 
     let src_idx = borrow_src.unwrap_or(layer_idx);
+    let caches: &[KVCache] = kv_caches;
 
     for pos in 0..seq_len {
-        let j_min = layer_attn
-            .sliding_window
-            .map(|w| pos.saturating_sub(w.saturating_sub(1)))
-            .unwrap_or(0);
+        let abs_pos = start_pos + pos;
+        // Row `pos` sees keys up to its own position (the causal lower triangle), or whatever
+        // its mask row allows among all of them.
+        let row_mask = mask.map(|m| &m[pos * n_keys..(pos + 1) * n_keys]);
+        let keys = match row_mask {
+            Some(_) => 0..n_keys,
+            None => visible_keys(abs_pos, layer_attn.sliding_window),
+        };
         let out_row = &mut attn_out[pos * q_dim..(pos + 1) * q_dim];
-        out_row.par_chunks_mut(head_dim).enumerate().try_for_each(
+        let work = q_dim * keys.len() * 2;
+        for_each_head(
+            out_row,
+            head_dim,
+            work,
             |(head, out)| -> Result<(), EngineError> {
                 let kv_head = head / group_size;
                 let q_start = pos * q_dim + head * head_dim;
                 let q = &q_data[q_start..q_start + head_dim];
 
-                let mut scores = vec![f32::NEG_INFINITY; pos + 1];
-                for j in j_min..=pos {
-                    let mut dot = 0.0f32;
-                    if borrow_src.is_some() {
-                        let k_vec = kv_caches[src_idx].get_k_slice(j, kv_head)?;
-                        for d in 0..head_dim {
-                            dot += q[d] * k_vec[d];
-                        }
-                    } else {
-                        let k_start = j * kv_dim + kv_head * head_dim;
-                        let k = &k_data[k_start..k_start + head_dim];
-                        for d in 0..head_dim {
-                            dot += q[d] * k[d];
-                        }
-                    }
-                    scores[j] = dot * scale;
-                }
-
-                let mut weights_buf = vec![0.0f32; pos + 1];
-                softmax(&scores, &mut weights_buf)?;
-
-                for j in j_min..=pos {
-                    let w = weights_buf[j];
-                    if borrow_src.is_some() {
-                        let v_vec = kv_caches[src_idx].get_v_slice(j, kv_head)?;
-                        for d in 0..head_dim {
-                            out[d] += w * v_vec[d];
-                        }
-                    } else {
-                        let v_start = j * kv_dim + kv_head * head_dim;
-                        let v = &v_data[v_start..v_start + head_dim];
-                        for d in 0..head_dim {
-                            out[d] += w * v[d];
-                        }
-                    }
-                }
-                Ok(())
+                // Keys before this chunk (or all keys, when borrowed) come from the cache.
+                let cached = |j: usize| borrow_src.is_some() || j < start_pos;
+                let row = |j: usize| (j - start_pos) * kv_dim + kv_head * head_dim;
+                attend_online(
+                    keys.clone(),
+                    row_mask,
+                    out,
+                    |j| {
+                        let k = if cached(j) {
+                            caches[src_idx].k_row(j, kv_head)?
+                        } else {
+                            KvRow::F32(&k_data[row(j)..row(j) + head_dim])
+                        };
+                        Ok(attention_score(k.dot(q), scale, softcap))
+                    },
+                    |j| {
+                        Ok(if cached(j) {
+                            caches[src_idx].v_row(j, kv_head)?
+                        } else {
+                            KvRow::F32(&v_data[row(j)..row(j) + head_dim])
+                        })
+                    },
+                )
             },
         )?;
     }
 
-    let attn_tensor = tensor_from_f32_slice(&attn_out, vec![seq_len, q_dim]);
-    let mut projected = empty_f32_tensor(vec![seq_len, hidden_dim]);
-    matmul(&attn_tensor, weights.wo, &mut projected)?;
+    project_attention_output(
+        "prefill attention",
+        &attn_out,
+        seq_len,
+        hidden_dim,
+        weights,
+        residual,
+    )
+}
 
-    Ok(projected.as_f32_slice()?.to_vec())
+/// Key positions a query at absolute position `query_pos` attends to: none after it (causal),
+/// and only the last `sliding_window` when set. Prefill row `i` of a chunk starting at `start`
+/// has `query_pos = start + i`, so a chunk sees a lower triangle plus everything cached before
+/// it; a decode step's query is the newest cache row, so it sees the whole cache.
+pub fn visible_keys(query_pos: usize, sliding_window: Option<usize>) -> Range<usize> {
+    let first = sliding_window.map_or(0, |w| query_pos.saturating_sub(w.saturating_sub(1)));
+    first..query_pos + 1
+}
+
+/// [`visible_keys`] as an additive mask over `n_keys` positions: `0.0` where the query at
+/// `query_pos` may attend, `-inf` elsewhere. Attending over `0..n_keys` with this mask is the
+/// same as attending over `visible_keys(query_pos, sliding_window)` without one.
+pub fn causal_mask(query_pos: usize, n_keys: usize, sliding_window: Option<usize>) -> Vec<f32> {
+    let visible = visible_keys(query_pos, sliding_window);
+    (0..n_keys)
+        .map(|j| {
+            if visible.contains(&j) {
+                0.0
+            } else {
+                f32::NEG_INFINITY
+            }
+        })
+        .collect()
+}
+
+/// Check that a layer-level `mask` is `[rows, n_keys]`.
+fn check_layer_mask(
+    what: &str,
+    mask: Option<&[f32]>,
+    rows: usize,
+    n_keys: usize,
+) -> Result<(), EngineError> {
+    match mask {
+        Some(m) if m.len() != rows * n_keys => Err(EngineError::Op(format!(
+            "{what}: mask has {} entries, expected {rows} rows x {n_keys} keys",
+            m.len()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that an additive `mask` has an entry for every key position in `keys`.
+fn check_mask(mask: Option<&[f32]>, keys: &Range<usize>) -> Result<(), EngineError> {
+    match mask {
+        Some(m) if m.len() < keys.end => Err(EngineError::Op(format!(
+            "attention mask has {} entries, keys reach position {}",
+            m.len(),
+            keys.end
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Attention logit for one query/key pair: `dot * scale`, then `tanh(s / cap) * cap` when
+/// `logit_softcap` is set (Gemma 2), which bounds it to `±cap`.
+pub fn attention_score(dot: f32, scale: f32, logit_softcap: Option<f32>) -> f32 {
+    let s = dot * scale;
+    match logit_softcap {
+        Some(cap) => cap * (s / cap).tanh(),
+        None => s,
+    }
+}
+
+/// `out += softmax(scores) · values` over `keys` in one sweep (online softmax): a running max,
+/// a running denominator and the unnormalized weighted sum in `out`, rescaled by
+/// `exp(old_max - new_max)` whenever the max grows, then divided by the denominator at the end.
+/// No score vector is materialized, and every exponent is `<= 0`, so extreme logits neither
+/// overflow nor underflow the sum. `-inf` scores (masked) are skipped; if every score is masked
+/// `out` is left as it was, like [`crate::ops::softmax::softmax`]'s all-zero weights. `out` must
+/// start zeroed.
+///
+/// `mask`, indexed by key position and at least `keys.end` long, is added to each score before
+/// the softmax (e.g. for prefix-LM or custom patterns); keys it sets to `-inf` are not scored.
+/// The layers pass `keys = 0..n_keys` with a mask, so causal attention is the [`causal_mask`]
+/// special case, and `visible_keys` with no mask otherwise.
+pub fn attend_online<'v>(
+    keys: Range<usize>,
+    mask: Option<&[f32]>,
+    out: &mut [f32],
+    mut score: impl FnMut(usize) -> Result<f32, EngineError>,
+    mut value: impl FnMut(usize) -> Result<KvRow<'v>, EngineError>,
+) -> Result<(), EngineError> {
+    check_mask(mask, &keys)?;
+    let mut max = f32::NEG_INFINITY;
+    let mut denom = 0.0f32;
+    for j in keys {
+        let bias = mask.map_or(0.0, |m| m[j]);
+        if bias == f32::NEG_INFINITY {
+            continue;
+        }
+        let s = score(j)? + bias;
+        if s == f32::NEG_INFINITY {
+            continue;
+        }
+        if s > max {
+            let correction = (max - s).exp();
+            if correction != 1.0 {
+                out.iter_mut().for_each(|o| *o *= correction);
+            }
+            denom *= correction;
+            max = s;
+        }
+        let w = (s - max).exp();
+        denom += w;
+        value(j)?.axpy_into(w, out);
+    }
+    if denom > 0.0 {
+        let inv = 1.0 / denom;
+        out.iter_mut().for_each(|o| *o *= inv);
+    }
+    Ok(())
 }
 
 fn apply_optional_head_rmsnorm(
@@ -426,13 +1014,14 @@ fn apply_optional_head_rmsnorm(
     n_groups: usize,
     head_dim: usize,
     norm: Option<&Tensor>,
+    weight_offset: f32,
     eps: f32,
     scratch: &mut [f32],
 ) -> Result<(), EngineError> {
     let Some(t) = norm else {
         return Ok(());
     };
-    let w = t.as_f32_slice()?;
+    let w = t.as_vector()?;
     if w.len() != head_dim {
         return Err(EngineError::Model(format!(
             "attn q/k norm weight len {} != head_dim {}",
@@ -448,7 +1037,7 @@ fn apply_optional_head_rmsnorm(
     let tmp = &mut scratch[..head_dim];
     for g in 0..n_groups {
         let s = g * head_dim;
-        rmsnorm(&row[s..s + head_dim], w, eps, tmp)?;
+        rmsnorm_with_offset(&row[s..s + head_dim], w, weight_offset, eps, tmp)?;
         row[s..s + head_dim].copy_from_slice(tmp);
     }
     Ok(())
@@ -457,8 +1046,10 @@ fn apply_optional_head_rmsnorm(
 /// Single-token attention for autoregressive decode. `input` must have `seq_len == 1`.
 ///
 /// RoPE uses position `kv_cache.current_pos` (0-based index of this token in the full sequence).
-/// Past keys/values are read from `kv_cache`; the new K/V are appended after RoPE.
-#[allow(clippy::needless_range_loop)]
+/// Past keys/values are read from `kv_cache`; the new K/V are appended after RoPE. `residual`
+/// and `mask` work as in [`prefill_attention_layer`]; here `mask` is one row over the cache
+/// including this token.
+#[allow(clippy::needless_range_loop, clippy::too_many_arguments)]
 pub fn decode_attention_layer(
     input: &ForwardState,
     config: &ModelConfig,
@@ -467,6 +1058,8 @@ pub fn decode_attention_layer(
     weights: &LayerWeights,
     kv_caches: &mut [KVCache],
     layer_idx: usize,
+    residual: Option<&[f32]>,
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let seq_len = input.seq_len();
     if seq_len != 1 {
@@ -520,6 +1113,12 @@ pub fn decode_attention_layer(
     };
 
     let group_size = config.n_heads / config.n_kv_heads;
+    // Keys after this step: the borrowed source already holds this token's row.
+    let n_keys = match borrow_src {
+        Some(src) => kv_caches[src].current_pos(),
+        None => own.current_pos() + 1,
+    };
+    check_layer_mask("decode attention", mask, 1, n_keys)?;
 
     let input_tensor = tensor_from_f32_slice(input.hidden(), vec![1, hidden_dim]);
     let mut q_tensor = empty_f32_tensor(vec![1, q_dim]);
@@ -527,9 +1126,18 @@ pub fn decode_attention_layer(
     let mut v_tensor = empty_f32_tensor(vec![1, kv_dim]);
 
     matmul(&input_tensor, weights.wq, &mut q_tensor)?;
+    weights
+        .lora
+        .apply(LoraTarget::Q, &input_tensor, &mut q_tensor)?;
     if borrow_src.is_none() {
         matmul(&input_tensor, weights.wk, &mut k_tensor)?;
         matmul(&input_tensor, weights.wv, &mut v_tensor)?;
+        weights
+            .lora
+            .apply(LoraTarget::K, &input_tensor, &mut k_tensor)?;
+        weights
+            .lora
+            .apply(LoraTarget::V, &input_tensor, &mut v_tensor)?;
     }
 
     let q_data = q_tensor.as_f32_slice_mut()?;
@@ -549,6 +1157,7 @@ pub fn decode_attention_layer(
         config.n_heads,
         head_dim,
         weights.attn_q_norm,
+        config.norm_weight_offset,
         config.rms_norm_eps,
         &mut head_scratch,
     )?;
@@ -558,6 +1167,7 @@ pub fn decode_attention_layer(
             config.n_kv_heads,
             head_dim,
             weights.attn_k_norm,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut head_scratch,
         )?;
@@ -572,7 +1182,7 @@ pub fn decode_attention_layer(
 
     let rope_base = layer_attn.rope_theta;
     let rope_rotary = layer_attn.rope_rotary_dim as u32;
-    let rope_ff = rope_freq_slice(weights);
+    let rope_ff = rope_factors(weights, layer_attn);
 
     for head in 0..config.n_heads {
         let head_start = head * head_dim;
@@ -583,7 +1193,7 @@ pub fn decode_attention_layer(
             rope_pos,
             head_dim as u32,
             rope_rotary,
-            rope_ff,
+            rope_ff.as_deref(),
         )?;
     }
     if borrow_src.is_none() {
@@ -596,7 +1206,7 @@ pub fn decode_attention_layer(
                 rope_pos,
                 head_dim as u32,
                 rope_rotary,
-                rope_ff,
+                rope_ff.as_deref(),
             )?;
         }
     }
@@ -606,50 +1216,84 @@ pub fn decode_attention_layer(
     }
 
     let src_idx = borrow_src.unwrap_or(layer_idx);
-    let total_pos = kv_caches[src_idx].current_pos();
-    let j_min = layer_attn
-        .sliding_window
-        .map(|w| total_pos.saturating_sub(w))
-        .unwrap_or(0);
+    let caches: &[KVCache] = kv_caches;
+    // The one query is the newest cache row (appended above, or checked non-empty for a borrowed
+    // cache), so nothing after it exists and it sees every earlier prefill and decode row.
+    let keys = match mask {
+        Some(_) => 0..n_keys,
+        None => visible_keys(n_keys - 1, layer_attn.sliding_window),
+    };
     let mut attn_out = vec![0.0f32; q_dim];
     let scale = match config.family {
         ModelFamily::Gemma4 => 1.0f32,
         ModelFamily::MistralLlama => 1.0f32 / (head_dim as f32).sqrt(),
     };
+    let softcap = config.attn_logit_softcapping;
 
-    attn_out.par_chunks_mut(head_dim).enumerate().try_for_each(
+    let work = q_dim * keys.len() * 2;
+    for_each_head(
+        &mut attn_out,
+        head_dim,
+        work,
         |(head, out)| -> Result<(), EngineError> {
             let kv_head = head / group_size;
             let q_start = head * head_dim;
             let q = &q_data[q_start..q_start + head_dim];
 
-            let mut scores = vec![f32::NEG_INFINITY; total_pos];
-            for j in j_min..total_pos {
-                let k_vec = kv_caches[src_idx].get_k_slice(j, kv_head)?;
-                let mut dot = 0.0f32;
-                for d in 0..head_dim {
-                    dot += q[d] * k_vec[d];
-                }
-                scores[j] = dot * scale;
-            }
-
-            let mut weights_buf = vec![0.0f32; total_pos];
-            softmax(&scores, &mut weights_buf)?;
-
-            for j in j_min..total_pos {
-                let w = weights_buf[j];
-                let v_vec = kv_caches[src_idx].get_v_slice(j, kv_head)?;
-                for d in 0..head_dim {
-                    out[d] += w * v_vec[d];
-                }
-            }
-            Ok(())
+            let cache = &caches[src_idx];
+            attend_online(
+                keys.clone(),
+                mask,
+                out,
+                |j| {
+                    Ok(attention_score(
+                        cache.k_row(j, kv_head)?.dot(q),
+                        scale,
+                        softcap,
+                    ))
+                },
+                |j| Ok(cache.v_row(j, kv_head)?),
+            )
         },
     )?;
 
-    let attn_tensor = tensor_from_f32_slice(&attn_out, vec![1, q_dim]);
-    let mut projected = empty_f32_tensor(vec![1, hidden_dim]);
-    matmul(&attn_tensor, weights.wo, &mut projected)?;
+    project_attention_output(
+        "decode attention",
+        &attn_out,
+        1,
+        hidden_dim,
+        weights,
+        residual,
+    )
+}
+
+/// Project the concatenated head outputs (`[rows, q_dim]`) back to `[rows, hidden_dim]` through
+/// `attn_output.weight`, plus `residual` when given. `n_heads * head_dim` need not equal
+/// `hidden_dim`, but `wo` must be exactly `[q_dim, hidden_dim]`.
+fn project_attention_output(
+    what: &str,
+    attn_out: &[f32],
+    rows: usize,
+    hidden_dim: usize,
+    weights: &LayerWeights,
+    residual: Option<&[f32]>,
+) -> Result<Vec<f32>, EngineError> {
+    let q_dim = attn_out.len() / rows;
+    if weights.wo.dimensions() != [q_dim, hidden_dim] {
+        return Err(EngineError::Model(format!(
+            "{what}: output projection has dims {:?}, expected [q_dim {q_dim}, hidden_dim {hidden_dim}]",
+            weights.wo.dimensions()
+        )));
+    }
+    let attn_tensor = tensor_from_f32_slice(attn_out, vec![rows, q_dim]);
+    let mut projected = empty_f32_tensor(vec![rows, hidden_dim]);
+    match residual {
+        Some(r) => matmul_add(&attn_tensor, weights.wo, r, &mut projected)?,
+        None => matmul(&attn_tensor, weights.wo, &mut projected)?,
+    }
+    weights
+        .lora
+        .apply(LoraTarget::Output, &attn_tensor, &mut projected)?;
 
     Ok(projected.as_f32_slice()?.to_vec())
 }
@@ -690,7 +1334,9 @@ mod unpack_tests {
 // ── Attention sub-layer with pre/post normalization ──────────────────────────
 //
 // These wrappers apply input RMSNorm, run the attention sub-layer, apply the
-// optional post-norm (Gemma 4 only), and add the residual connection.
+// optional post-norm (Gemma 4 only), and add the residual connection. Without a
+// post-norm the residual add is fused into the output projection. `mask` is passed through to
+// [`prefill_attention_layer`] / [`decode_attention_layer`].
 // They live here because they depend directly on the attention primitives above.
 
 pub fn prefill_attention_with_norm(
@@ -699,13 +1345,14 @@ pub fn prefill_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     match config.family {
         ModelFamily::MistralLlama => {
-            mistral_prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches)
+            mistral_prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)
         }
         ModelFamily::Gemma4 => {
-            gemma4_prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches)
+            gemma4_prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)
         }
     }
 }
@@ -716,11 +1363,12 @@ fn mistral_prefill_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let seq_len = input.seq_len();
     let hidden_dim = input.hidden_dim();
 
-    let attn_norm_weights = weights.attn_norm.as_f32_slice()?;
+    let attn_norm_weights = weights.attn_norm.as_vector()?;
     if attn_norm_weights.len() != hidden_dim {
         return Err(EngineError::Model(format!(
             "attn_norm weights len {} != hidden_dim {}",
@@ -733,9 +1381,10 @@ fn mistral_prefill_attention_with_norm(
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
-        rmsnorm(
+        rmsnorm_with_offset(
             &input.hidden()[start..end],
             attn_norm_weights,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut normed[start..end],
         )?;
@@ -744,7 +1393,7 @@ fn mistral_prefill_attention_with_norm(
     let normed_state = ForwardState::from_flat(normed, seq_len, hidden_dim)?;
     let layer_attn = config.layer_attention_for(layer_idx)?;
     let layer_dims = config.layer_dims_for(layer_idx)?;
-    let attn_out = prefill_attention_layer(
+    prefill_attention_layer(
         &normed_state,
         config,
         layer_dims,
@@ -752,11 +1401,9 @@ fn mistral_prefill_attention_with_norm(
         weights,
         kv_caches,
         layer_idx,
-    )?;
-
-    let mut residual_out = vec![0.0f32; seq_len * hidden_dim];
-    residual_add(input.hidden(), &attn_out, &mut residual_out)?;
-    Ok(residual_out)
+        Some(input.hidden()),
+        mask,
+    )
 }
 
 fn gemma4_prefill_attention_with_norm(
@@ -765,15 +1412,16 @@ fn gemma4_prefill_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let seq_len = input.seq_len();
     let hidden_dim = input.hidden_dim();
 
-    let attn_norm_weights = weights.attn_norm.as_f32_slice()?;
+    let attn_norm_weights = weights.attn_norm.as_vector()?;
     let post_attn_w = weights
         .attn_post_norm
         .ok_or_else(|| EngineError::Model("Gemma 4: missing post_attention_norm".into()))?
-        .as_f32_slice()?;
+        .as_vector()?;
     if attn_norm_weights.len() != hidden_dim || post_attn_w.len() != hidden_dim {
         return Err(EngineError::Model(
             "Gemma 4: attn norm weight length mismatch".into(),
@@ -784,9 +1432,10 @@ fn gemma4_prefill_attention_with_norm(
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
-        rmsnorm(
+        rmsnorm_with_offset(
             &input.hidden()[start..end],
             attn_norm_weights,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut normed[start..end],
         )?;
@@ -803,15 +1452,18 @@ fn gemma4_prefill_attention_with_norm(
         weights,
         kv_caches,
         layer_idx,
+        None,
+        mask,
     )?;
 
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
         let mut tmp = vec![0.0f32; hidden_dim];
-        rmsnorm(
+        rmsnorm_with_offset(
             &attn_out[start..end],
             post_attn_w,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut tmp,
         )?;
@@ -829,6 +1481,7 @@ pub fn decode_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     if input.seq_len() != 1 {
         return Err(EngineError::Model(
@@ -837,10 +1490,10 @@ pub fn decode_attention_with_norm(
     }
     match config.family {
         ModelFamily::MistralLlama => {
-            mistral_decode_attention_with_norm(input, config, layer_idx, weights, kv_caches)
+            mistral_decode_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)
         }
         ModelFamily::Gemma4 => {
-            gemma4_decode_attention_with_norm(input, config, layer_idx, weights, kv_caches)
+            gemma4_decode_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)
         }
     }
 }
@@ -851,10 +1504,11 @@ fn mistral_decode_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let hidden_dim = input.hidden_dim();
 
-    let attn_norm_weights = weights.attn_norm.as_f32_slice()?;
+    let attn_norm_weights = weights.attn_norm.as_vector()?;
     if attn_norm_weights.len() != hidden_dim {
         return Err(EngineError::Model(format!(
             "attn_norm weights len {} != hidden_dim {}",
@@ -864,9 +1518,10 @@ fn mistral_decode_attention_with_norm(
     }
 
     let mut normed = vec![0.0f32; hidden_dim];
-    rmsnorm(
+    rmsnorm_with_offset(
         input.hidden(),
         attn_norm_weights,
+        config.norm_weight_offset,
         config.rms_norm_eps,
         &mut normed,
     )?;
@@ -874,7 +1529,7 @@ fn mistral_decode_attention_with_norm(
     let normed_state = ForwardState::from_flat(normed, 1, hidden_dim)?;
     let layer_attn = config.layer_attention_for(layer_idx)?;
     let layer_dims = config.layer_dims_for(layer_idx)?;
-    let attn_out = decode_attention_layer(
+    decode_attention_layer(
         &normed_state,
         config,
         layer_dims,
@@ -882,11 +1537,9 @@ fn mistral_decode_attention_with_norm(
         weights,
         kv_caches,
         layer_idx,
-    )?;
-
-    let mut residual_out = vec![0.0f32; hidden_dim];
-    residual_add(input.hidden(), &attn_out, &mut residual_out)?;
-    Ok(residual_out)
+        Some(input.hidden()),
+        mask,
+    )
 }
 
 fn gemma4_decode_attention_with_norm(
@@ -895,14 +1548,15 @@ fn gemma4_decode_attention_with_norm(
     layer_idx: usize,
     weights: &crate::model_weights::LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     let hidden_dim = input.hidden_dim();
 
-    let attn_norm_weights = weights.attn_norm.as_f32_slice()?;
+    let attn_norm_weights = weights.attn_norm.as_vector()?;
     let post_attn_w = weights
         .attn_post_norm
         .ok_or_else(|| EngineError::Model("Gemma 4: missing post_attention_norm".into()))?
-        .as_f32_slice()?;
+        .as_vector()?;
     if attn_norm_weights.len() != hidden_dim || post_attn_w.len() != hidden_dim {
         return Err(EngineError::Model(
             "Gemma 4: attn norm weight length mismatch".into(),
@@ -910,9 +1564,10 @@ fn gemma4_decode_attention_with_norm(
     }
 
     let mut normed = vec![0.0f32; hidden_dim];
-    rmsnorm(
+    rmsnorm_with_offset(
         input.hidden(),
         attn_norm_weights,
+        config.norm_weight_offset,
         config.rms_norm_eps,
         &mut normed,
     )?;
@@ -928,13 +1583,420 @@ fn gemma4_decode_attention_with_norm(
         weights,
         kv_caches,
         layer_idx,
+        None,
+        mask,
     )?;
 
     let mut tmp = vec![0.0f32; hidden_dim];
-    rmsnorm(&attn_out, post_attn_w, config.rms_norm_eps, &mut tmp)?;
+    rmsnorm_with_offset(
+        &attn_out,
+        post_attn_w,
+        config.norm_weight_offset,
+        config.rms_norm_eps,
+        &mut tmp,
+    )?;
     attn_out.copy_from_slice(&tmp);
 
     let mut residual_out = vec![0.0f32; hidden_dim];
     residual_add(input.hidden(), &attn_out, &mut residual_out)?;
     Ok(residual_out)
 }
+
+#[cfg(test)]
+mod kv_cache_tests {
+    use std::ops::Range;
+
+    use super::{
+        CacheDtype, KVCache, KVCacheError, KVCacheSnapshot, KvRow, attend_online, causal_mask,
+        check_mask, visible_keys,
+    };
+    use crate::EngineError;
+    use crate::ops::softmax::softmax;
+
+    fn step(t: usize) -> (Vec<f32>, Vec<f32>) {
+        let k = (0..6).map(|i| (t * 10 + i) as f32).collect();
+        let v = (0..6).map(|i| -((t * 10 + i) as f32)).collect();
+        (k, v)
+    }
+
+    #[test]
+    fn restore_reproduces_snapshot_state() {
+        let mut cache = KVCache::new(8, 2, 3);
+        for t in 0..3 {
+            let (k, v) = step(t);
+            cache.append_kv(&k, &v).unwrap();
+        }
+        let snap = cache.snapshot();
+        assert_eq!(snap.len(), 3);
+
+        for t in 3..6 {
+            let (k, v) = step(t + 100);
+            cache.append_kv(&k, &v).unwrap();
+        }
+        cache.restore(&snap).unwrap();
+        assert_eq!(cache.current_pos(), 3);
+        assert_eq!(cache.snapshot(), snap);
+        assert_eq!(cache.get_k_slice(2, 1).unwrap(), &[23.0, 24.0, 25.0]);
+        assert!(cache.get_k_slice(3, 0).is_err());
+
+        // Appending after a restore overwrites the discarded timesteps.
+        let (k, v) = step(3);
+        cache.append_kv(&k, &v).unwrap();
+        assert_eq!(cache.get_v_slice(3, 0).unwrap(), &[-30.0, -31.0, -32.0]);
+
+        let mut other = KVCache::new(8, 1, 3);
+        assert!(matches!(
+            other.restore(&snap),
+            Err(KVCacheError::SnapshotMismatch { .. })
+        ));
+    }
+
+    /// The materializing reference for [`attend_online`]: every score into a `keys.end`-long
+    /// vector (`-inf` before `keys.start`) plus `mask`, [`softmax`], then the weighted sum of
+    /// values into `out`. Returns the attention weights.
+    pub(super) fn attend_materialized<'v>(
+        keys: Range<usize>,
+        mask: Option<&[f32]>,
+        out: &mut [f32],
+        mut score: impl FnMut(usize) -> Result<f32, EngineError>,
+        mut value: impl FnMut(usize) -> Result<KvRow<'v>, EngineError>,
+    ) -> Result<Vec<f32>, EngineError> {
+        check_mask(mask, &keys)?;
+        let mut scores = vec![f32::NEG_INFINITY; keys.end];
+        for j in keys.clone() {
+            let bias = mask.map_or(0.0, |m| m[j]);
+            if bias != f32::NEG_INFINITY {
+                scores[j] = score(j)? + bias;
+            }
+        }
+        let mut weights = vec![0.0f32; keys.end];
+        softmax(&scores, &mut weights)?;
+        for j in keys {
+            value(j)?.axpy_into(weights[j], out);
+        }
+        Ok(weights)
+    }
+
+    pub(super) fn random(len: usize, seed: u64) -> Vec<f32> {
+        let mut s = seed;
+        (0..len)
+            .map(|_| {
+                s = s
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                ((s >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    /// Single-query attention over every cached position, through the kernel's row API.
+    fn attend(cache: &KVCache, q: &[f32], kv_head: usize) -> Vec<f32> {
+        let n = cache.current_pos();
+        let scale = 1.0 / (q.len() as f32).sqrt();
+        let scores: Vec<f32> = (0..n)
+            .map(|j| cache.k_row(j, kv_head).unwrap().dot(q) * scale)
+            .collect();
+        let mut weights = vec![0.0; n];
+        softmax(&scores, &mut weights).unwrap();
+        let mut out = vec![0.0; q.len()];
+        for (j, &w) in weights.iter().enumerate() {
+            cache.v_row(j, kv_head).unwrap().axpy_into(w, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn additive_mask_zeroes_blocked_keys_and_causal_is_a_special_case() {
+        let head_dim = 8;
+        let scores = [0.5f32, 2.0, -1.0, 1.0];
+        let values: Vec<Vec<f32>> = (0..4).map(|j| random(head_dim, 50 + j as u64)).collect();
+        let score = |j: usize| Ok(scores[j]);
+        let value = |j: usize| Ok(KvRow::F32(&values[j]));
+
+        // Block position 1, the highest-scoring key.
+        let mask = [0.0, f32::NEG_INFINITY, 0.0, 0.0];
+        let mut out = vec![0.0f32; head_dim];
+        let weights = attend_materialized(0..4, Some(&mask), &mut out, score, value).unwrap();
+        assert_eq!(weights[1], 0.0);
+        assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        let mut online = vec![0.0f32; head_dim];
+        attend_online(0..4, Some(&mask), &mut online, score, value).unwrap();
+        // Same as attending over the other three keys with the blocked one left out entirely.
+        let kept = [scores[0], f32::NEG_INFINITY, scores[2], scores[3]];
+        let mut expected = vec![0.0f32; head_dim];
+        attend_online(0..4, None, &mut expected, |j| Ok(kept[j]), value).unwrap();
+        for (i, (x, y)) in online.iter().zip(&expected).enumerate() {
+            assert!((x - y).abs() < 1e-6 && (x - out[i]).abs() < 1e-5, "[{i}]");
+        }
+
+        // Finite entries shift scores: +ln 2 doubles a key's unnormalized weight.
+        let bias = [0.0, 0.0, 2f32.ln(), 0.0];
+        let mut out = vec![0.0f32; head_dim];
+        let weights = attend_materialized(0..4, Some(&bias), &mut out, score, value).unwrap();
+        let plain =
+            attend_materialized(0..4, None, &mut vec![0.0; head_dim], score, value).unwrap();
+        let ratio = (weights[2] / weights[0]) / (plain[2] / plain[0]);
+        assert!((ratio - 2.0).abs() < 1e-5, "{ratio}");
+
+        for window in [None, Some(2)] {
+            for pos in 0..4 {
+                let causal = causal_mask(pos, 4, window);
+                let mut masked = vec![0.0f32; head_dim];
+                attend_online(0..4, Some(&causal), &mut masked, score, value).unwrap();
+                let mut ranged = vec![0.0f32; head_dim];
+                attend_online(visible_keys(pos, window), None, &mut ranged, score, value).unwrap();
+                assert_eq!(masked, ranged, "pos {pos}, window {window:?}");
+            }
+        }
+
+        let err = attend_online(0..4, Some(&mask[..3]), &mut out, score, value).unwrap_err();
+        assert!(err.to_string().contains("mask has 3 entries"), "{err}");
+    }
+
+    #[test]
+    fn f16_cache_attention_matches_f32_within_1e_3() {
+        let (n_kv_heads, head_dim, steps) = (2, 64, 40);
+        let mut exact = KVCache::new(steps, n_kv_heads, head_dim);
+        let mut half = KVCache::with_dtype(steps, n_kv_heads, head_dim, CacheDtype::F16);
+        for t in 0..steps {
+            let k = random(n_kv_heads * head_dim, 2 * t as u64);
+            let v = random(n_kv_heads * head_dim, 2 * t as u64 + 1);
+            exact.append_kv(&k, &v).unwrap();
+            half.append_kv(&k, &v).unwrap();
+        }
+        assert_eq!(half.dtype(), CacheDtype::F16);
+        assert_eq!(2 * half.allocated_bytes(), exact.allocated_bytes());
+        assert!(matches!(
+            half.get_k_slice(0, 0),
+            Err(KVCacheError::NotF32(CacheDtype::F16))
+        ));
+
+        for (i, kv_head) in [0, 1, 1].into_iter().enumerate() {
+            let q = random(head_dim, 1000 + i as u64);
+            let a = attend(&exact, &q, kv_head);
+            let b = attend(&half, &q, kv_head);
+            for (x, y) in a.iter().zip(&b) {
+                assert!((x - y).abs() < 1e-3, "{x} vs {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn q8_cache_attention_matches_f32_within_1e_2() {
+        let (n_kv_heads, head_dim, steps) = (2, 64, 40);
+        let mut exact = KVCache::new(steps, n_kv_heads, head_dim);
+        let mut q8 = KVCache::with_dtype(steps, n_kv_heads, head_dim, CacheDtype::Q8);
+        for t in 0..steps {
+            let k = random(n_kv_heads * head_dim, 2 * t as u64);
+            let v = random(n_kv_heads * head_dim, 2 * t as u64 + 1);
+            exact.append_kv(&k, &v).unwrap();
+            q8.append_kv(&k, &v).unwrap();
+        }
+        assert_eq!(
+            q8.allocated_bytes(),
+            2 * steps * n_kv_heads * (head_dim + 4)
+        );
+        assert!(matches!(
+            q8.get_v_slice(0, 0),
+            Err(KVCacheError::NotF32(CacheDtype::Q8))
+        ));
+
+        for (i, kv_head) in [0, 1, 1].into_iter().enumerate() {
+            let q = random(head_dim, 1000 + i as u64);
+            let a = attend(&exact, &q, kv_head);
+            let b = attend(&q8, &q, kv_head);
+            for (x, y) in a.iter().zip(&b) {
+                assert!((x - y).abs() < 1e-2, "{x} vs {y}");
+            }
+        }
+
+        let snap = q8.snapshot();
+        let bytes = snap.to_bytes();
+        assert_eq!(bytes.len(), 29 + q8.allocated_bytes());
+        let back = KVCacheSnapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(back, snap);
+        let mut fresh = KVCache::with_dtype(steps, n_kv_heads, head_dim, CacheDtype::Q8);
+        fresh.restore(&back).unwrap();
+        assert_eq!(
+            fresh.k_row(7, 1).unwrap().to_f32_vec(),
+            q8.k_row(7, 1).unwrap().to_f32_vec()
+        );
+        assert!(KVCacheSnapshot::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn snapshot_bytes_round_trip_for_each_dtype() {
+        for dtype in [CacheDtype::F32, CacheDtype::F16] {
+            let mut cache = KVCache::with_dtype(8, 2, 3, dtype);
+            for t in 0..3 {
+                let (k, v) = step(t);
+                cache.append_kv(&k, &v).unwrap();
+            }
+            let snap = cache.snapshot();
+            let bytes = snap.to_bytes();
+            assert_eq!(bytes.len(), 29 + 2 * 18 * dtype.bytes_per_element());
+            let back = KVCacheSnapshot::from_bytes(&bytes).unwrap();
+            assert_eq!(back, snap);
+
+            let mut fresh = KVCache::with_dtype(8, 2, 3, dtype);
+            fresh.restore(&back).unwrap();
+            assert_eq!(
+                fresh.k_row(2, 1).unwrap().to_f32_vec(),
+                vec![23.0, 24.0, 25.0]
+            );
+
+            assert!(KVCacheSnapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            assert!(KVCacheSnapshot::from_bytes(b"KVS2").is_err());
+        }
+
+        let f16_snap = KVCache::with_dtype(8, 2, 3, CacheDtype::F16).snapshot();
+        assert!(matches!(
+            KVCache::new(8, 2, 3).restore(&f16_snap),
+            Err(KVCacheError::SnapshotDtypeMismatch { .. })
+        ));
+    }
+}
+
+#[cfg(test)]
+mod softcap_tests {
+    use super::attention_score;
+
+    #[test]
+    fn softcap_bounds_scores_and_none_is_a_no_op() {
+        let cap = 50.0;
+        for dot in [-1e6f32, -400.0, -3.0, 0.0, 0.5, 120.0, 1e6] {
+            let capped = attention_score(dot, 0.125, Some(cap));
+            assert!(capped.abs() <= cap, "{dot} -> {capped}");
+            assert_eq!(attention_score(dot, 0.125, None), dot * 0.125);
+        }
+        // Near zero the cap barely changes the score.
+        assert!((attention_score(1.0, 1.0, Some(cap)) - 1.0).abs() < 1e-3);
+    }
+}
+
+#[cfg(test)]
+mod causal_range_tests {
+    use super::visible_keys;
+
+    #[test]
+    fn prefill_rows_see_a_triangle_and_decode_sees_the_whole_cache() {
+        // Prefill of 3 tokens: row i sees 0..=i.
+        let rows: Vec<_> = (0..3).map(|i| visible_keys(i, None)).collect();
+        assert_eq!(rows, [0..1, 0..2, 0..3]);
+        // The decode step after it is appended at position 3 and sees all 4 rows.
+        assert_eq!(visible_keys(3, None), 0..4);
+        // A second prefill chunk starting at 3 sees the cached rows plus its own triangle.
+        assert_eq!(visible_keys(3 + 1, None), 0..5);
+        // Sliding windows count the query itself.
+        assert_eq!(visible_keys(3, Some(2)), 2..4);
+        assert_eq!(visible_keys(1, Some(8)), 0..2);
+    }
+}
+
+#[cfg(test)]
+mod head_split_tests {
+    use super::{ATTENTION_PARALLEL_MIN_OPS, for_each_head};
+
+    #[test]
+    fn heads_fill_the_same_output_inline_and_in_parallel() {
+        let fill = |work: usize| {
+            let mut out = vec![0.0f32; 8 * 16];
+            for_each_head(&mut out, 16, work, |(head, chunk)| {
+                for (i, x) in chunk.iter_mut().enumerate() {
+                    *x = (head * 16 + i) as f32 * 0.5;
+                }
+                Ok(())
+            })
+            .unwrap();
+            out
+        };
+        let inline = fill(0);
+        assert_eq!(inline, fill(ATTENTION_PARALLEL_MIN_OPS));
+        assert_eq!(inline[16 * 7 + 3], (16 * 7 + 3) as f32 * 0.5);
+        let failed = for_each_head(&mut [0.0; 4], 2, 0, |(head, _)| {
+            if head == 1 {
+                Err(crate::EngineError::Op("head 1".into()))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(failed.is_err());
+    }
+}
+
+#[cfg(test)]
+mod online_softmax_tests {
+    use super::kv_cache_tests::{attend_materialized, random};
+    use super::{KvRow, attend_online};
+
+    /// Online and materializing attention over `keys` agree within 1e-4 for `scores`.
+    fn assert_kernels_agree(keys: std::ops::Range<usize>, scores: &[f32]) {
+        let head_dim = 64;
+        let values: Vec<Vec<f32>> = (0..scores.len())
+            .map(|j| random(head_dim, 7000 + j as u64))
+            .collect();
+        let run = |online: bool| {
+            let mut out = vec![0.0f32; head_dim];
+            let score = |j: usize| Ok(scores[j]);
+            let value = |j: usize| Ok(KvRow::F32(&values[j]));
+            if online {
+                attend_online(keys.clone(), None, &mut out, score, value).unwrap();
+            } else {
+                attend_materialized(keys.clone(), None, &mut out, score, value).unwrap();
+            }
+            out
+        };
+        let (online, reference) = (run(true), run(false));
+        for (i, (x, y)) in online.iter().zip(&reference).enumerate() {
+            assert!(
+                x.is_finite() && (x - y).abs() < 1e-4,
+                "{} keys, [{i}]: {x} vs {y}",
+                scores.len()
+            );
+        }
+    }
+
+    #[test]
+    fn online_softmax_matches_the_materializing_kernel() {
+        for n in [1, 32, 4096] {
+            let scores: Vec<f32> = random(n, n as u64).iter().map(|s| s * 8.0).collect();
+            assert_kernels_agree(0..n, &scores);
+            // A sliding window: keys before the start are never scored.
+            assert_kernels_agree(n / 2..n, &scores);
+        }
+
+        let n = 4096;
+        let adversarial: [Vec<f32>; 6] = [
+            // Far beyond exp's range in both directions.
+            (0..n)
+                .map(|j| if j % 3 == 0 { 1e30 } else { -1e30 })
+                .collect(),
+            // A new max on every key, so every step rescales.
+            (0..n).map(|j| j as f32 * 0.5).collect(),
+            (0..n).map(|j| -(j as f32) * 0.5).collect(),
+            // Alternating around exp's overflow point.
+            (0..n)
+                .map(|j| if j % 2 == 0 { 90.0 } else { -90.0 })
+                .collect(),
+            // One spike at the end dwarfs everything before it.
+            (0..n).map(|j| if j == n - 1 { 1e4 } else { 0.0 }).collect(),
+            vec![3.0; n],
+        ];
+        for scores in &adversarial {
+            assert_kernels_agree(0..n, scores);
+        }
+
+        // Fully masked: no keys contribute.
+        let mut out = vec![0.0f32; 4];
+        let row = [1.0f32; 4];
+        attend_online(
+            0..3,
+            None,
+            &mut out,
+            |_| Ok(f32::NEG_INFINITY),
+            |_| Ok(KvRow::F32(&row)),
+        )
+        .unwrap();
+        assert_eq!(out, [0.0; 4]);
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::EngineError;
 use crate::engine::state::ForwardState;
+use crate::engine::watchdog::{ForwardOp, Stage, enter_stage};
 use crate::layers::attention::{KVCache, decode_attention_with_norm, prefill_attention_with_norm};
 use crate::layers::ffn::{
     apply_gemma_layer_output_scale, apply_per_layer_tail, prefill_ffn_with_norm,
@@ -9,17 +10,22 @@ use crate::layers::ffn::{
 use crate::model_config::ModelConfig;
 use crate::model_weights::LayerWeights;
 
+/// One transformer block over a prompt chunk. `mask` is the optional additive attention mask of
+/// [`crate::layers::attention::prefill_attention_layer`].
 pub fn prefill_layer_block(
     input: &ForwardState,
     config: &ModelConfig,
     layer_idx: usize,
     weights: &LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<ForwardState, EngineError> {
-    let attn_out = prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches)?;
+    enter_stage(Stage::layer(layer_idx, ForwardOp::Attention));
+    let attn_out = prefill_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)?;
     let seq_len = input.seq_len();
     let hidden_dim = input.hidden_dim();
     let ffn_dim = config.layer_dims_for(layer_idx)?.ffn_dim;
+    enter_stage(Stage::layer(layer_idx, ForwardOp::Ffn));
     let mut ffn_out =
         prefill_ffn_with_norm(&attn_out, seq_len, hidden_dim, ffn_dim, config, weights)?;
 
@@ -47,12 +53,14 @@ pub fn prefill_layer_block(
     )
 }
 
+/// One transformer block for a single decode token; `mask` as in [`prefill_layer_block`].
 pub fn decode_layer_block(
     input: &ForwardState,
     config: &ModelConfig,
     layer_idx: usize,
     weights: &LayerWeights,
     kv_caches: &mut [KVCache],
+    mask: Option<&[f32]>,
 ) -> Result<ForwardState, EngineError> {
     if input.seq_len() != 1 {
         return Err(EngineError::Model(
@@ -60,8 +68,10 @@ pub fn decode_layer_block(
         ));
     }
     let hidden_dim = input.hidden_dim();
-    let attn_out = decode_attention_with_norm(input, config, layer_idx, weights, kv_caches)?;
+    enter_stage(Stage::layer(layer_idx, ForwardOp::Attention));
+    let attn_out = decode_attention_with_norm(input, config, layer_idx, weights, kv_caches, mask)?;
     let ffn_dim = config.layer_dims_for(layer_idx)?.ffn_dim;
+    enter_stage(Stage::layer(layer_idx, ForwardOp::Ffn));
     let mut ffn_out = prefill_ffn_with_norm(&attn_out, 1, hidden_dim, ffn_dim, config, weights)?;
 
     if config.embedding_length_per_layer > 0 {
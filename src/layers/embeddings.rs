@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use crate::EngineError;
 use crate::core::tensor::{Tensor, TensorType};
 use crate::model_loader::gguf_types::GGUFData;
@@ -16,6 +18,53 @@ fn embedding_buffer_index(hidden_dim: usize, token_id: u32, h: usize) -> usize {
     (token_id as usize) * hidden_dim + h
 }
 
+/// What an embedding lookup does with a token id `>= vocab_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnOutOfVocab {
+    /// Fail the whole lookup (the default).
+    #[default]
+    Error,
+    /// Look up this id instead, typically the tokenizer's UNK. Errors if it is out of range too.
+    Unk(u32),
+    /// Look up the last row (`vocab_size - 1`).
+    Clamp,
+}
+
+impl OnOutOfVocab {
+    /// `token_ids` with every out-of-range id replaced per `self`; borrowed when none is.
+    fn resolve<'a>(
+        self,
+        token_ids: &'a [u32],
+        vocab_size: usize,
+    ) -> Result<Cow<'a, [u32]>, EngineError> {
+        let in_vocab = |id: u32| (id as usize) < vocab_size;
+        let Some(&first_bad) = token_ids.iter().find(|&&id| !in_vocab(id)) else {
+            return Ok(Cow::Borrowed(token_ids));
+        };
+        let replacement = match self {
+            Self::Error => None,
+            Self::Unk(unk) if in_vocab(unk) => Some(unk),
+            Self::Unk(unk) => {
+                return Err(EngineError::Model(format!(
+                    "UNK token ID {unk} out of vocabulary range [0, {vocab_size})"
+                )));
+            }
+            Self::Clamp => vocab_size.checked_sub(1).map(|last| last as u32),
+        };
+        let Some(replacement) = replacement else {
+            return Err(EngineError::Model(format!(
+                "token ID {first_bad} out of vocabulary range [0, {vocab_size})"
+            )));
+        };
+        Ok(Cow::Owned(
+            token_ids
+                .iter()
+                .map(|&id| if in_vocab(id) { id } else { replacement })
+                .collect(),
+        ))
+    }
+}
+
 /// Lookup embeddings for a sequence of token IDs.
 ///
 /// This is the usual first step in the inference pipeline: tokenizer IDs → dense rows.
@@ -31,7 +80,8 @@ fn embedding_buffer_index(hidden_dim: usize, token_id: u32, h: usize) -> usize {
 ///
 /// # Errors
 ///
-/// Missing embedding tensor, out-of-vocab IDs, or unexpected shape/dtype.
+/// Missing embedding tensor, out-of-vocab IDs (see [`lookup_embeddings_with`] to tolerate them),
+/// or unexpected shape/dtype.
 ///
 /// # Performance
 ///
@@ -46,6 +96,17 @@ pub fn lookup_embeddings(
     gguf_data: &mut GGUFData,
     file_path: &str,
     token_ids: &[u32],
+) -> Result<Vec<Vec<f32>>, EngineError> {
+    lookup_embeddings_with(gguf_data, file_path, token_ids, OnOutOfVocab::Error)
+}
+
+/// [`lookup_embeddings`] with a choice of what happens to out-of-vocab ids, e.g.
+/// [`OnOutOfVocab::Unk`] so one bad id in a served batch does not fail the request.
+pub fn lookup_embeddings_with(
+    gguf_data: &mut GGUFData,
+    file_path: &str,
+    token_ids: &[u32],
+    on_oov: OnOutOfVocab,
 ) -> Result<Vec<Vec<f32>>, EngineError> {
     let embedding_tensor_name = resolve_embedding_tensor_name(gguf_data)?;
 
@@ -53,7 +114,7 @@ pub fn lookup_embeddings(
         gguf_data.load_single_tensor(file_path, embedding_tensor_name)?;
     }
 
-    lookup_embeddings_loaded(gguf_data, token_ids)
+    lookup_embeddings_loaded_with(gguf_data, token_ids, on_oov)
 }
 
 /// Same as [`lookup_embeddings`], but only reads an **already-loaded** embedding tensor.
@@ -64,16 +125,57 @@ pub fn lookup_embeddings_loaded(
     gguf_data: &GGUFData,
     token_ids: &[u32],
 ) -> Result<Vec<Vec<f32>>, EngineError> {
+    lookup_embeddings_loaded_with(gguf_data, token_ids, OnOutOfVocab::Error)
+}
+
+/// [`lookup_embeddings_loaded`] with an [`OnOutOfVocab`] policy.
+pub fn lookup_embeddings_loaded_with(
+    gguf_data: &GGUFData,
+    token_ids: &[u32],
+    on_oov: OnOutOfVocab,
+) -> Result<Vec<Vec<f32>>, EngineError> {
+    lookup_embedding_rows(loaded_embedding_tensor(gguf_data)?, token_ids, on_oov)
+}
+
+/// Like [`lookup_embeddings`], but returns all rows in one contiguous `[seq_len, hidden_dim]`
+/// buffer (row `i` at `i * hidden_dim`), ready to use as a prefill activation matrix, together
+/// with `hidden_dim`.
+pub fn lookup_embeddings_matrix(
+    gguf_data: &mut GGUFData,
+    file_path: &str,
+    token_ids: &[u32],
+) -> Result<(Vec<f32>, usize), EngineError> {
     let embedding_tensor_name = resolve_embedding_tensor_name(gguf_data)?;
-    let embedding_tensor = gguf_data
+
+    if gguf_data.get_tensor(embedding_tensor_name).is_none() {
+        gguf_data.load_single_tensor(file_path, embedding_tensor_name)?;
+    }
+
+    lookup_embeddings_matrix_loaded(gguf_data, token_ids)
+}
+
+/// [`lookup_embeddings_matrix`] over an **already-loaded** embedding tensor (see
+/// [`lookup_embeddings_loaded`]).
+pub fn lookup_embeddings_matrix_loaded(
+    gguf_data: &GGUFData,
+    token_ids: &[u32],
+) -> Result<(Vec<f32>, usize), EngineError> {
+    lookup_embedding_matrix(
+        loaded_embedding_tensor(gguf_data)?,
+        token_ids,
+        OnOutOfVocab::Error,
+    )
+}
+
+fn loaded_embedding_tensor(gguf_data: &GGUFData) -> Result<&Tensor, EngineError> {
+    let embedding_tensor_name = resolve_embedding_tensor_name(gguf_data)?;
+    gguf_data
         .get_tensor(embedding_tensor_name)
                .ok_or_else(|| {
             EngineError::Model(format!(
                 "embedding tensor '{embedding_tensor_name}' not loaded; call load_single_tensor or lookup_embeddings first"
             ))
-        })?;
-
-    lookup_embedding_rows(embedding_tensor, token_ids)
+        })
 }
 
 fn resolve_embedding_tensor_name(gguf_data: &GGUFData) -> Result<&'static str, EngineError> {
@@ -83,7 +185,7 @@ fn resolve_embedding_tensor_name(gguf_data: &GGUFData) -> Result<&'static str, E
         "embeddings.weight",
     ];
     for &name in &NAMES {
-        if gguf_data.tensors_metadata().iter().any(|t| t.name == name) {
+        if gguf_data.tensor_symbol(name).is_some() {
             return Ok(name);
         }
     }
@@ -95,7 +197,22 @@ fn resolve_embedding_tensor_name(gguf_data: &GGUFData) -> Result<&'static str, E
 fn lookup_embedding_rows(
     embedding_tensor: &Tensor,
     token_ids: &[u32],
+    on_oov: OnOutOfVocab,
 ) -> Result<Vec<Vec<f32>>, EngineError> {
+    let (flat, hidden_dim) = lookup_embedding_matrix(embedding_tensor, token_ids, on_oov)?;
+    if hidden_dim == 0 {
+        return Ok(vec![Vec::new(); token_ids.len()]);
+    }
+    Ok(flat.chunks_exact(hidden_dim).map(<[f32]>::to_vec).collect())
+}
+
+/// Rows for `token_ids`, back to back in one `[token_ids.len(), hidden_dim]` buffer. Shared by
+/// every lookup so flat and per-row results are identical.
+fn lookup_embedding_matrix(
+    embedding_tensor: &Tensor,
+    token_ids: &[u32],
+    on_oov: OnOutOfVocab,
+) -> Result<(Vec<f32>, usize), EngineError> {
     let dims = embedding_tensor.dimensions();
     if dims.len() != 2 {
         return Err(EngineError::Tensor(format!(
@@ -116,97 +233,92 @@ fn lookup_embedding_rows(
     };
 
     // Validate token IDs are within vocabulary range
-    for &token_id in token_ids {
-        if token_id as usize >= vocab_size {
-            return Err(EngineError::Model(format!(
-                "token ID {token_id} out of vocabulary range [0, {vocab_size})"
-            )));
-        }
-    }
+    let token_ids = on_oov.resolve(token_ids, vocab_size)?;
 
-    let buf = embedding_tensor.buffer();
-    let mut embeddings = Vec::with_capacity(token_ids.len());
+    let mut flat = vec![0.0f32; token_ids.len() * hidden_dim];
+    if hidden_dim == 0 {
+        return Ok((flat, 0));
+    }
+    let rows = token_ids.iter().zip(flat.chunks_exact_mut(hidden_dim));
 
     match embedding_tensor.dtype() {
         TensorType::F32 => {
-            for &token_id in token_ids {
-                let mut embedding = vec![0.0f32; hidden_dim];
-                for (h, slot) in embedding.iter_mut().enumerate() {
+            for (&token_id, row) in rows {
+                for (h, slot) in row.iter_mut().enumerate() {
                     let idx = embedding_buffer_index(hidden_dim, token_id, h);
                     *slot = embedding_tensor.f32_at(idx)?;
                 }
-                embeddings.push(embedding);
             }
         }
         TensorType::Q4K => {
-            for &token_id in token_ids {
-                let mut embedding = vec![0.0f32; hidden_dim];
-                let mut cached_block = usize::MAX;
-                let mut decoded = [0.0f32; BLOCK_ELEMENTS];
-                for (h, slot) in embedding.iter_mut().enumerate() {
-                    let idx = embedding_buffer_index(hidden_dim, token_id, h);
-                    let block_idx = idx / BLOCK_ELEMENTS;
-                    let el = idx % BLOCK_ELEMENTS;
-                    if block_idx != cached_block {
-                        let start = block_idx * Q4K_BLOCK_SIZE;
-                        let block = buf.get(start..start + Q4K_BLOCK_SIZE).ok_or_else(|| {
-                            EngineError::Tensor("Q4K embedding block out of bounds".into())
-                        })?;
-                        dequantize_q4k_block(block, &mut decoded)?;
-                        cached_block = block_idx;
-                    }
-                    *slot = decoded[el];
-                }
-                embeddings.push(embedding);
+            let block = QuantBlocks {
+                elements: BLOCK_ELEMENTS,
+                bytes: Q4K_BLOCK_SIZE,
+                name: "Q4K",
+                dequantize: dequantize_q4k_block,
+            };
+            for (&token_id, row) in rows {
+                block.read_row(embedding_tensor, token_id, row)?;
             }
         }
         TensorType::Q6K => {
-            for &token_id in token_ids {
-                let mut embedding = vec![0.0f32; hidden_dim];
-                let mut cached_block = usize::MAX;
-                let mut decoded = [0.0f32; BLOCK_ELEMENTS];
-                for (h, slot) in embedding.iter_mut().enumerate() {
-                    let idx = embedding_buffer_index(hidden_dim, token_id, h);
-                    let block_idx = idx / BLOCK_ELEMENTS;
-                    let el = idx % BLOCK_ELEMENTS;
-                    if block_idx != cached_block {
-                        let start = block_idx * Q6K_BLOCK_SIZE;
-                        let block = buf.get(start..start + Q6K_BLOCK_SIZE).ok_or_else(|| {
-                            EngineError::Tensor("Q6K embedding block out of bounds".into())
-                        })?;
-                        dequantize_q6k_block(block, &mut decoded)?;
-                        cached_block = block_idx;
-                    }
-                    *slot = decoded[el];
-                }
-                embeddings.push(embedding);
+            let block = QuantBlocks {
+                elements: BLOCK_ELEMENTS,
+                bytes: Q6K_BLOCK_SIZE,
+                name: "Q6K",
+                dequantize: dequantize_q6k_block,
+            };
+            for (&token_id, row) in rows {
+                block.read_row(embedding_tensor, token_id, row)?;
             }
         }
         TensorType::Q8_0 => {
-            for &token_id in token_ids {
-                let mut embedding = vec![0.0f32; hidden_dim];
-                let mut cached_block = usize::MAX;
-                let mut decoded = [0.0f32; Q8_0_BLOCK_ELEMENTS];
-                for (h, slot) in embedding.iter_mut().enumerate() {
-                    let idx = embedding_buffer_index(hidden_dim, token_id, h);
-                    let block_idx = idx / Q8_0_BLOCK_ELEMENTS;
-                    let el = idx % Q8_0_BLOCK_ELEMENTS;
-                    if block_idx != cached_block {
-                        let start = block_idx * Q8_0_BLOCK_SIZE;
-                        let block = buf.get(start..start + Q8_0_BLOCK_SIZE).ok_or_else(|| {
-                            EngineError::Tensor("Q8_0 embedding block out of bounds".into())
-                        })?;
-                        dequantize_q8_0_block(block, &mut decoded)?;
-                        cached_block = block_idx;
-                    }
-                    *slot = decoded[el];
-                }
-                embeddings.push(embedding);
+            let block = QuantBlocks {
+                elements: Q8_0_BLOCK_ELEMENTS,
+                bytes: Q8_0_BLOCK_SIZE,
+                name: "Q8_0",
+                dequantize: dequantize_q8_0_block,
+            };
+            for (&token_id, row) in rows {
+                block.read_row(embedding_tensor, token_id, row)?;
             }
         }
     }
 
-    Ok(embeddings)
+    Ok((flat, hidden_dim))
+}
+
+/// Block layout of a quantized embedding table.
+struct QuantBlocks {
+    elements: usize,
+    bytes: usize,
+    name: &'static str,
+    dequantize: fn(&[u8], &mut [f32]) -> Result<(), EngineError>,
+}
+
+impl QuantBlocks {
+    /// Dequantize row `token_id` into `row`, decoding each block it touches once.
+    fn read_row(&self, tensor: &Tensor, token_id: u32, row: &mut [f32]) -> Result<(), EngineError> {
+        let buf = tensor.buffer();
+        let hidden_dim = row.len();
+        let mut cached_block = usize::MAX;
+        let mut decoded = [0.0f32; BLOCK_ELEMENTS];
+        let decoded = &mut decoded[..self.elements];
+        for (h, slot) in row.iter_mut().enumerate() {
+            let idx = embedding_buffer_index(hidden_dim, token_id, h);
+            let block_idx = idx / self.elements;
+            if block_idx != cached_block {
+                let start = block_idx * self.bytes;
+                let block = buf.get(start..start + self.bytes).ok_or_else(|| {
+                    EngineError::Tensor(format!("{} embedding block out of bounds", self.name))
+                })?;
+                (self.dequantize)(block, decoded)?;
+                cached_block = block_idx;
+            }
+            *slot = decoded[idx % self.elements];
+        }
+        Ok(())
+    }
 }
 
 /// Read a single logical row `token_id` from a 2D embedding table (same layout rules as
@@ -215,7 +327,7 @@ pub fn read_token_row_f32(
     embedding_tensor: &Tensor,
     token_id: u32,
 ) -> Result<Vec<f32>, EngineError> {
-    let rows = lookup_embedding_rows(embedding_tensor, &[token_id])?;
+    let rows = lookup_embedding_rows(embedding_tensor, &[token_id], OnOutOfVocab::Error)?;
     rows.into_iter()
         .next()
         .ok_or_else(|| EngineError::Tensor("read_token_row_f32: empty row".into()))
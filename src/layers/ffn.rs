@@ -4,15 +4,18 @@ use std::sync::Arc;
 
 use crate::EngineError;
 use crate::core::tensor::{Tensor, TensorType};
+use crate::layers::lora::LoraTarget;
 use crate::model_config::{ModelConfig, ModelFamily};
 use crate::model_weights::LayerWeights;
 use crate::ops::gelu::gelu_tanh;
-use crate::ops::matmul::matmul;
+use crate::ops::matmul::{matmul, matmul_add};
 use crate::ops::quant::quant_k_handler::{Q8_0_BLOCK_SIZE, dequantize_q8_0_block};
 use crate::ops::residual_add::residual_add;
-use crate::ops::rmsnorm::rmsnorm;
+use crate::ops::rmsnorm::rmsnorm_with_offset;
 use crate::ops::swiglu::swiglu;
 
+/// Gate/up projections, activation and down projection over `seq_len` rows of `input`. With
+/// `residual`, returns `residual + down(...)`, the add fused into the down projection.
 pub fn prefill_ffn(
     input: &[f32],
     seq_len: usize,
@@ -20,6 +23,7 @@ pub fn prefill_ffn(
     ffn_dim: usize,
     config: &ModelConfig,
     weights: &LayerWeights,
+    residual: Option<&[f32]>,
 ) -> Result<Vec<f32>, EngineError> {
     if input.len() != seq_len * hidden_dim {
         return Err(EngineError::Model(
@@ -39,6 +43,12 @@ pub fn prefill_ffn(
 
     matmul(&input_tensor, weights.w_gate, &mut gate_tensor)?;
     matmul(&input_tensor, weights.w_up, &mut up_tensor)?;
+    weights
+        .lora
+        .apply(LoraTarget::Gate, &input_tensor, &mut gate_tensor)?;
+    weights
+        .lora
+        .apply(LoraTarget::Up, &input_tensor, &mut up_tensor)?;
 
     let gate = gate_tensor.as_f32_slice()?;
     let up = up_tensor.as_f32_slice()?;
@@ -58,7 +68,13 @@ pub fn prefill_ffn(
 
     let activated_tensor = tensor_from_f32_slice(&activated, vec![seq_len, ffn_dim]);
     let mut down_tensor = empty_f32_tensor(vec![seq_len, hidden_dim]);
-    matmul(&activated_tensor, weights.w_down, &mut down_tensor)?;
+    match residual {
+        Some(r) => matmul_add(&activated_tensor, weights.w_down, r, &mut down_tensor)?,
+        None => matmul(&activated_tensor, weights.w_down, &mut down_tensor)?,
+    }
+    weights
+        .lora
+        .apply(LoraTarget::Down, &activated_tensor, &mut down_tensor)?;
 
     Ok(down_tensor.as_f32_slice()?.to_vec())
 }
@@ -89,7 +105,7 @@ fn mistral_prefill_ffn_with_norm(
     config: &ModelConfig,
     weights: &LayerWeights,
 ) -> Result<Vec<f32>, EngineError> {
-    let ffn_norm_weights = weights.ffn_norm.as_f32_slice()?;
+    let ffn_norm_weights = weights.ffn_norm.as_vector()?;
     if ffn_norm_weights.len() != hidden_dim {
         return Err(EngineError::Model(format!(
             "ffn_norm weights len {} != hidden_dim {}",
@@ -102,18 +118,24 @@ fn mistral_prefill_ffn_with_norm(
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
-        rmsnorm(
+        rmsnorm_with_offset(
             &input[start..end],
             ffn_norm_weights,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut normed[start..end],
         )?;
     }
 
-    let ffn_out = prefill_ffn(&normed, seq_len, hidden_dim, ffn_dim, config, weights)?;
-    let mut residual_out = vec![0.0f32; seq_len * hidden_dim];
-    residual_add(input, &ffn_out, &mut residual_out)?;
-    Ok(residual_out)
+    prefill_ffn(
+        &normed,
+        seq_len,
+        hidden_dim,
+        ffn_dim,
+        config,
+        weights,
+        Some(input),
+    )
 }
 
 fn gemma4_prefill_ffn_with_norm(
@@ -124,11 +146,11 @@ fn gemma4_prefill_ffn_with_norm(
     config: &ModelConfig,
     weights: &LayerWeights,
 ) -> Result<Vec<f32>, EngineError> {
-    let ffn_norm_weights = weights.ffn_norm.as_f32_slice()?;
+    let ffn_norm_weights = weights.ffn_norm.as_vector()?;
     let post_ffn_w = weights
         .ffn_post_norm
         .ok_or_else(|| EngineError::Model("Gemma 4: missing post_ffw_norm".into()))?
-        .as_f32_slice()?;
+        .as_vector()?;
     if ffn_norm_weights.len() != hidden_dim || post_ffn_w.len() != hidden_dim {
         return Err(EngineError::Model(
             "Gemma 4: ffn norm weight length mismatch".into(),
@@ -139,22 +161,24 @@ fn gemma4_prefill_ffn_with_norm(
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
-        rmsnorm(
+        rmsnorm_with_offset(
             &input[start..end],
             ffn_norm_weights,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut normed[start..end],
         )?;
     }
 
-    let mut ffn_out = prefill_ffn(&normed, seq_len, hidden_dim, ffn_dim, config, weights)?;
+    let mut ffn_out = prefill_ffn(&normed, seq_len, hidden_dim, ffn_dim, config, weights, None)?;
     for pos in 0..seq_len {
         let start = pos * hidden_dim;
         let end = start + hidden_dim;
         let mut tmp = vec![0.0f32; hidden_dim];
-        rmsnorm(
+        rmsnorm_with_offset(
             &ffn_out[start..end],
             post_ffn_w,
+            config.norm_weight_offset,
             config.rms_norm_eps,
             &mut tmp,
         )?;
@@ -221,7 +245,7 @@ pub(super) fn apply_per_layer_tail(
     matmul(&go_t, proj, &mut out_t)?;
     let proj_out = out_t.as_f32_slice()?.to_vec();
 
-    let w_post = post_n.as_f32_slice()?;
+    let w_post = post_n.as_vector()?;
     if w_post.len() != hidden_dim {
         return Err(EngineError::Model(
             "PLE post_norm weight len mismatch".into(),
@@ -231,7 +255,13 @@ pub(super) fn apply_per_layer_tail(
     let mut normed_row = vec![0.0f32; hidden_dim];
     for p in 0..seq_len {
         let h0 = p * hidden_dim;
-        rmsnorm(&proj_out[h0..h0 + hidden_dim], w_post, eps, &mut normed_row)?;
+        rmsnorm_with_offset(
+            &proj_out[h0..h0 + hidden_dim],
+            w_post,
+            config.norm_weight_offset,
+            eps,
+            &mut normed_row,
+        )?;
         for i in 0..hidden_dim {
             hidden[h0 + i] += normed_row[i];
         }
@@ -8,7 +8,7 @@ use crate::layers::embeddings::read_token_row_f32;
 use crate::model_config::ModelConfig;
 use crate::model_weights::Gemma4PleTensors;
 use crate::ops::matmul::matmul;
-use crate::ops::rmsnorm::rmsnorm;
+use crate::ops::rmsnorm::rmsnorm_with_offset;
 
 fn tensor_from_f32_slice(data: &[f32], dimensions: Vec<usize>) -> Tensor {
     let mut bytes = Vec::with_capacity(data.len() * 4);
@@ -24,6 +24,7 @@ fn empty_f32_tensor(dimensions: Vec<usize>) -> Tensor {
 }
 
 /// Packed layout `[seq, n_layers * ple_dim]` row-major (positions contiguous).
+#[allow(clippy::needless_range_loop)]
 pub fn compute_packed_per_layer_inputs(
     ple: &Gemma4PleTensors<'_>,
     config: &ModelConfig,
@@ -67,7 +68,7 @@ pub fn compute_packed_per_layer_inputs(
     let sqrt_ple = (ple_dim as f32).sqrt();
     let combine = config.ple_combine_scale;
 
-    let norm_w = ple.per_layer_proj_norm.as_f32_slice()?;
+    let norm_w = ple.per_layer_proj_norm.as_vector()?;
     if norm_w.len() != ple_dim {
         return Err(EngineError::Model(format!(
             "per_layer_proj_norm len {} != ple_dim {}",
@@ -97,7 +98,13 @@ pub fn compute_packed_per_layer_inputs(
         let base = p * pack;
         for l in 0..n_layers {
             let off = base + l * ple_dim;
-            rmsnorm(&proj[off..off + ple_dim], norm_w, eps, &mut normed_chunk)?;
+            rmsnorm_with_offset(
+                &proj[off..off + ple_dim],
+                norm_w,
+                config.norm_weight_offset,
+                eps,
+                &mut normed_chunk,
+            )?;
             let tok_off = l * ple_dim;
             for i in 0..ple_dim {
                 out[off + i] = (normed_chunk[i] + token_row[tok_off + i]) * combine;
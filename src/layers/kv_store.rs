@@ -0,0 +1,286 @@
+//! Element storage behind [`KVCache`]: the K or V rows of one layer, one `[head_dim]` row per KV
+//! head per timestep, held as [`KVCacheF32`], [`KVCacheF16`] or [`KVCacheQ8`].
+//!
+//! A store rounds rows on [`KVStore::write`] and hands them back in its own dtype through
+//! [`KvRow`], so attention widens f16 / q8 values per element instead of materializing an f32
+//! copy of the cache. [`KVCache`] picks the store from its [`CacheDtype`] with [`zeros`].
+//!
+//! [`KVCache`]: crate::layers::attention::KVCache
+
+use std::any::Any;
+use std::fmt;
+
+use crate::layers::attention::{CacheDtype, KvRow};
+use crate::ops::quant::utils::f32_to_f16;
+
+/// K or V storage for every timestep of one layer. `start` and lengths are element offsets into
+/// the flat `[timestep][kv_head][head_dim]` buffer and always cover whole rows.
+pub trait KVStore: fmt::Debug + Send + Sync {
+    fn dtype(&self) -> CacheDtype;
+
+    /// Elements held, written or not.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Store `src` from element `start`, rounding to the storage dtype.
+    fn write(&mut self, start: usize, src: &[f32]);
+
+    /// The `len` elements from `start`, in storage dtype; `start..start + len` is one row.
+    fn row(&self, start: usize, len: usize) -> KvRow<'_>;
+
+    /// Copy of the first `len` elements, e.g. the filled part for a snapshot.
+    fn prefix(&self, len: usize) -> Box<dyn KVStore>;
+
+    /// Overwrite the front with `src`. Panics unless `src` has the same dtype (and row width), so
+    /// callers check [`Self::dtype`] first.
+    fn copy_prefix_from(&mut self, src: &dyn KVStore);
+
+    /// Append the little-endian encoding of every element (for q8, the i8 values followed by the
+    /// f32 row scales).
+    fn extend_le_bytes(&self, out: &mut Vec<u8>);
+
+    /// For [`Self::copy_prefix_from`], which needs the concrete type of `src`.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// `len` zeroed elements in `dtype`, as `row`-element rows (only [`KVCacheQ8`] uses the width).
+pub fn zeros(dtype: CacheDtype, len: usize, row: usize) -> Box<dyn KVStore> {
+    match dtype {
+        CacheDtype::F32 => Box::new(KVCacheF32(vec![0.0; len])),
+        CacheDtype::F16 => Box::new(KVCacheF16(vec![0; len])),
+        CacheDtype::Q8 => Box::new(KVCacheQ8 {
+            quants: vec![0; len],
+            scales: vec![0.0; len.checked_div(row).unwrap_or(0)],
+            row,
+        }),
+    }
+}
+
+/// Inverse of [`KVStore::extend_le_bytes`] for a store of `len` elements; `raw` must be exactly
+/// `len / row` rows of [`CacheDtype::row_bytes`].
+pub fn from_le_bytes(dtype: CacheDtype, raw: &[u8], len: usize, row: usize) -> Box<dyn KVStore> {
+    let f32s = |raw: &[u8]| -> Vec<f32> {
+        raw.chunks_exact(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    };
+    match dtype {
+        CacheDtype::F32 => Box::new(KVCacheF32(f32s(raw))),
+        CacheDtype::F16 => Box::new(KVCacheF16(
+            raw.chunks_exact(2)
+                .map(|c| u16::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+        )),
+        CacheDtype::Q8 => {
+            let (quants, scales) = raw.split_at(len);
+            Box::new(KVCacheQ8 {
+                quants: quants.iter().map(|&b| b as i8).collect(),
+                scales: f32s(scales),
+                row,
+            })
+        }
+    }
+}
+
+fn downcast<T: 'static>(src: &dyn KVStore) -> &T {
+    src.as_any()
+        .downcast_ref()
+        .expect("KV store dtype checked before copy")
+}
+
+/// Exact storage: what the attention kernel computed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KVCacheF32(Vec<f32>);
+
+impl KVStore for KVCacheF32 {
+    fn dtype(&self) -> CacheDtype {
+        CacheDtype::F32
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn write(&mut self, start: usize, src: &[f32]) {
+        self.0[start..start + src.len()].copy_from_slice(src);
+    }
+
+    fn row(&self, start: usize, len: usize) -> KvRow<'_> {
+        KvRow::F32(&self.0[start..start + len])
+    }
+
+    fn prefix(&self, len: usize) -> Box<dyn KVStore> {
+        Box::new(Self(self.0[..len].to_vec()))
+    }
+
+    fn copy_prefix_from(&mut self, src: &dyn KVStore) {
+        let src = &downcast::<Self>(src).0;
+        self.0[..src.len()].copy_from_slice(src);
+    }
+
+    fn extend_le_bytes(&self, out: &mut Vec<u8>) {
+        self.0
+            .iter()
+            .for_each(|x| out.extend_from_slice(&x.to_le_bytes()));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// f16 bit patterns, rounded to nearest even on write and widened through the lookup table when
+/// attention reads them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KVCacheF16(Vec<u16>);
+
+impl KVStore for KVCacheF16 {
+    fn dtype(&self) -> CacheDtype {
+        CacheDtype::F16
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn write(&mut self, start: usize, src: &[f32]) {
+        for (dst, &x) in self.0[start..start + src.len()].iter_mut().zip(src) {
+            *dst = f32_to_f16(x);
+        }
+    }
+
+    fn row(&self, start: usize, len: usize) -> KvRow<'_> {
+        KvRow::F16(&self.0[start..start + len])
+    }
+
+    fn prefix(&self, len: usize) -> Box<dyn KVStore> {
+        Box::new(Self(self.0[..len].to_vec()))
+    }
+
+    fn copy_prefix_from(&mut self, src: &dyn KVStore) {
+        let src = &downcast::<Self>(src).0;
+        self.0[..src.len()].copy_from_slice(src);
+    }
+
+    fn extend_le_bytes(&self, out: &mut Vec<u8>) {
+        self.0
+            .iter()
+            .for_each(|x| out.extend_from_slice(&x.to_le_bytes()));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// One f32 scale (absmax / 127) per `row`-element row of i8 `quants`, like a `Q8_0` block the
+/// width of a head.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KVCacheQ8 {
+    quants: Vec<i8>,
+    scales: Vec<f32>,
+    row: usize,
+}
+
+/// Symmetric absmax quantization of one cache row; returns the scale.
+fn quantize_q8_row(src: &[f32], dst: &mut [i8]) -> f32 {
+    let amax = src.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    let scale = amax / 127.0;
+    let inv = if scale > 0.0 { 1.0 / scale } else { 0.0 };
+    for (q, &x) in dst.iter_mut().zip(src) {
+        *q = (x * inv).round().clamp(-127.0, 127.0) as i8;
+    }
+    scale
+}
+
+impl KVStore for KVCacheQ8 {
+    fn dtype(&self) -> CacheDtype {
+        CacheDtype::Q8
+    }
+
+    fn len(&self) -> usize {
+        self.quants.len()
+    }
+
+    fn write(&mut self, start: usize, src: &[f32]) {
+        let row = self.row;
+        let dst = self.quants[start..start + src.len()].chunks_exact_mut(row);
+        for (i, (q, x)) in dst.zip(src.chunks_exact(row)).enumerate() {
+            self.scales[start / row + i] = quantize_q8_row(x, q);
+        }
+    }
+
+    fn row(&self, start: usize, len: usize) -> KvRow<'_> {
+        KvRow::Q8 {
+            quants: &self.quants[start..start + len],
+            scale: self.scales[start / self.row],
+        }
+    }
+
+    fn prefix(&self, len: usize) -> Box<dyn KVStore> {
+        Box::new(Self {
+            quants: self.quants[..len].to_vec(),
+            scales: self.scales[..len.checked_div(self.row).unwrap_or(0)].to_vec(),
+            row: self.row,
+        })
+    }
+
+    fn copy_prefix_from(&mut self, src: &dyn KVStore) {
+        let src = downcast::<Self>(src);
+        assert_eq!(src.row, self.row, "KV store row width checked before copy");
+        self.quants[..src.quants.len()].copy_from_slice(&src.quants);
+        self.scales[..src.scales.len()].copy_from_slice(&src.scales);
+    }
+
+    fn extend_le_bytes(&self, out: &mut Vec<u8>) {
+        out.extend(self.quants.iter().map(|&q| q as u8));
+        self.scales
+            .iter()
+            .for_each(|x| out.extend_from_slice(&x.to_le_bytes()));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_dtype_round_trips_its_bytes_and_prefix() {
+        let values: Vec<f32> = (0..12).map(|i| (i as f32 - 5.5) / 4.0).collect();
+        for dtype in [CacheDtype::F32, CacheDtype::F16, CacheDtype::Q8] {
+            let mut store = zeros(dtype, 24, 4);
+            store.write(4, &values);
+            assert_eq!(store.dtype(), dtype);
+            assert_eq!(store.len(), 24);
+
+            let prefix = store.prefix(16);
+            let mut bytes = Vec::new();
+            prefix.extend_le_bytes(&mut bytes);
+            assert_eq!(bytes.len(), 4 * dtype.row_bytes(4));
+            let back = from_le_bytes(dtype, &bytes, 16, 4);
+            for start in (0..16).step_by(4) {
+                let (a, b) = (prefix.row(start, 4), back.row(start, 4));
+                assert_eq!(a.to_f32_vec(), b.to_f32_vec(), "{dtype:?} row {start}");
+            }
+
+            let mut other = zeros(dtype, 24, 4);
+            other.copy_prefix_from(&*back);
+            assert_eq!(other.row(8, 4).to_f32_vec(), store.row(8, 4).to_f32_vec());
+            assert!(other.row(16, 4).to_f32_vec().iter().all(|&x| x == 0.0));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "dtype checked")]
+    fn copying_across_dtypes_panics() {
+        let mut f16 = zeros(CacheDtype::F16, 8, 4);
+        f16.copy_prefix_from(&*zeros(CacheDtype::F32, 8, 4));
+    }
+}
@@ -0,0 +1,346 @@
+//! LoRA adapters applied at matmul time, so they can be swapped without reloading the base model.
+//!
+//! An adapter GGUF (as exported by llama.cpp's `convert_lora_to_gguf.py`) holds a low-rank pair
+//! per adapted weight: `blk.N.<weight>.weight.lora_a` with dims `[in, rank]` and `.lora_b` with
+//! `[rank, out]`, the same `[K, N]` layout as the base weight `[in, out]`. Instead of merging
+//! `scale · A · B` into the (possibly quantized) base weight, every projection with attached
+//! pairs adds `scale · (x · A) · B` to its output: two thin matmuls per pair. A projection with
+//! nothing attached runs exactly as before, so detaching every adapter restores the base outputs
+//! bit for bit.
+//!
+//! The effective scale follows llama.cpp: the caller's scale times `alpha / rank` when the
+//! adapter sets `adapter.lora.alpha`, else the caller's scale alone. Several adapters (or the
+//! same one twice) compose additively.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::EngineError;
+use crate::core::tensor::{Tensor, TensorType};
+use crate::model_loader::file_loader::read_file;
+use crate::model_loader::gguf_types::Data;
+use crate::model_weights::{LayerWeights, ModelWeights};
+use crate::ops::matmul::matmul;
+
+/// A block weight an adapter can target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoraTarget {
+    Q,
+    K,
+    V,
+    Output,
+    Gate,
+    Up,
+    Down,
+}
+
+impl LoraTarget {
+    pub const ALL: [LoraTarget; 7] = [
+        Self::Q,
+        Self::K,
+        Self::V,
+        Self::Output,
+        Self::Gate,
+        Self::Up,
+        Self::Down,
+    ];
+
+    /// The weight's name within a block (`blk.N.<name>.weight`).
+    pub fn tensor_name(self) -> &'static str {
+        match self {
+            Self::Q => "attn_q",
+            Self::K => "attn_k",
+            Self::V => "attn_v",
+            Self::Output => "attn_output",
+            Self::Gate => "ffn_gate",
+            Self::Up => "ffn_up",
+            Self::Down => "ffn_down",
+        }
+    }
+
+    fn from_tensor_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.tensor_name() == name)
+    }
+
+    /// The base weight this target adapts.
+    pub fn base<'w>(self, weights: &LayerWeights<'w>) -> &'w Tensor {
+        match self {
+            Self::Q => weights.wq,
+            Self::K => weights.wk,
+            Self::V => weights.wv,
+            Self::Output => weights.wo,
+            Self::Gate => weights.w_gate,
+            Self::Up => weights.w_up,
+            Self::Down => weights.w_down,
+        }
+    }
+}
+
+impl fmt::Display for LoraTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.tensor_name())
+    }
+}
+
+/// One adapted weight's low-rank factors, both F32.
+#[derive(Debug)]
+pub struct LoraPair {
+    /// `[in, rank]`.
+    pub a: Tensor,
+    /// `[rank, out]`.
+    pub b: Tensor,
+}
+
+impl LoraPair {
+    pub fn rank(&self) -> usize {
+        self.a.dimensions()[1]
+    }
+}
+
+/// A parsed adapter file, shareable between sessions.
+#[derive(Debug)]
+pub struct LoraAdapter {
+    path: String,
+    alpha: Option<f32>,
+    pairs: BTreeMap<(usize, LoraTarget), Arc<LoraPair>>,
+}
+
+impl LoraAdapter {
+    /// Read every `lora_a` / `lora_b` pair of the adapter GGUF at `path`. Fails on a tensor that
+    /// is not a block-weight pair, on a half pair, and on factors whose ranks disagree.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let path = path
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| EngineError::Model("adapter path is not valid UTF-8".into()))?
+            .to_string();
+        let mut gguf = read_file(&path)?;
+        if let Some(Data::String(kind)) = gguf.get_metadata("adapter.type") {
+            if kind != "lora" {
+                return Err(lora_error(
+                    &path,
+                    format!("adapter.type is {kind:?}, not \"lora\""),
+                ));
+            }
+        }
+        let alpha = match gguf.get_metadata("adapter.lora.alpha") {
+            Some(Data::Float32(a)) if *a > 0.0 => Some(*a),
+            _ => None,
+        };
+        gguf.load_tensors(&path)?;
+
+        let mut halves: BTreeMap<(usize, LoraTarget), [Option<Tensor>; 2]> = BTreeMap::new();
+        for (name, tensor) in gguf.loaded_tensors() {
+            let (layer, target, half) = parse_name(name)
+                .ok_or_else(|| lora_error(&path, format!("unsupported tensor '{name}'")))?;
+            let tensor = tensor.to_dtype(TensorType::F32)?;
+            if tensor.dimensions().len() != 2 {
+                return Err(lora_error(
+                    &path,
+                    format!("'{name}' must be 2-D, got {:?}", tensor.dimensions()),
+                ));
+            }
+            halves.entry((layer, target)).or_default()[half] = Some(tensor);
+        }
+
+        let mut pairs = BTreeMap::new();
+        for ((layer, target), [a, b]) in halves {
+            let name = format!("blk.{layer}.{target}.weight");
+            let (Some(a), Some(b)) = (a, b) else {
+                return Err(lora_error(
+                    &path,
+                    format!("'{name}' has only one of lora_a / lora_b"),
+                ));
+            };
+            if a.dimensions()[1] != b.dimensions()[0] {
+                return Err(lora_error(
+                    &path,
+                    format!(
+                        "'{name}': lora_a {:?} and lora_b {:?} disagree on the rank",
+                        a.dimensions(),
+                        b.dimensions()
+                    ),
+                ));
+            }
+            pairs.insert((layer, target), Arc::new(LoraPair { a, b }));
+        }
+        if pairs.is_empty() {
+            return Err(lora_error(&path, "no lora_a / lora_b tensors".into()));
+        }
+        Ok(Self { path, alpha, pairs })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// `adapter.lora.alpha`, if set and positive.
+    pub fn alpha(&self) -> Option<f32> {
+        self.alpha
+    }
+
+    /// Adapted weights, by layer then target.
+    pub fn pairs(&self) -> impl Iterator<Item = ((usize, LoraTarget), &LoraPair)> {
+        self.pairs.iter().map(|(&key, pair)| (key, pair.as_ref()))
+    }
+
+    /// The scale a pair of rank `rank` is applied with for a caller's `scale`.
+    pub fn effective_scale(&self, scale: f32, rank: usize) -> f32 {
+        match self.alpha {
+            Some(alpha) => scale * alpha / rank as f32,
+            None => scale,
+        }
+    }
+
+    /// Check every pair against `weights`: the layer exists, `A` is `[in, rank]` and `B` is
+    /// `[rank, out]` for a base weight `[in, out]`.
+    pub fn validate(&self, weights: &ModelWeights<'_>) -> Result<(), EngineError> {
+        for (&(layer, target), pair) in &self.pairs {
+            let name = format!("blk.{layer}.{target}.weight");
+            let Some(layer_weights) = weights.layers.get(layer) else {
+                return Err(lora_error(
+                    &self.path,
+                    format!(
+                        "'{name}' targets layer {layer}, the model has {}",
+                        weights.layers.len()
+                    ),
+                ));
+            };
+            let base = target.base(layer_weights).dimensions();
+            let (a, b) = (pair.a.dimensions(), pair.b.dimensions());
+            if base.len() != 2 || a[0] != base[0] || b[1] != base[1] {
+                return Err(lora_error(
+                    &self.path,
+                    format!(
+                        "'{name}': lora_a {a:?} x lora_b {b:?} does not fit the base weight {base:?}"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `(layer, target, 0 for A / 1 for B)` of `blk.N.<target>.weight.lora_{a,b}`.
+fn parse_name(name: &str) -> Option<(usize, LoraTarget, usize)> {
+    let rest = name.strip_prefix("blk.")?;
+    let (layer, rest) = rest.split_once('.')?;
+    let (weight, half) = rest.split_once(".weight.lora_")?;
+    let half = match half {
+        "a" => 0,
+        "b" => 1,
+        _ => return None,
+    };
+    Some((
+        layer.parse().ok()?,
+        LoraTarget::from_tensor_name(weight)?,
+        half,
+    ))
+}
+
+fn lora_error(path: &str, message: String) -> EngineError {
+    EngineError::Model(format!("LoRA adapter {path}: {message}"))
+}
+
+/// One pair attached to a projection.
+#[derive(Debug, Clone)]
+struct LoraTerm {
+    target: LoraTarget,
+    pair: Arc<LoraPair>,
+    scale: f32,
+}
+
+/// The adapter pairs attached to one block's projections; empty unless an adapter is applied.
+#[derive(Debug, Clone, Default)]
+pub struct LayerLora {
+    terms: Vec<LoraTerm>,
+}
+
+impl LayerLora {
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Add `scale · (input · A) · B` of every pair on `target` to `output` (`[rows, out]`).
+    pub fn apply(
+        &self,
+        target: LoraTarget,
+        input: &Tensor,
+        output: &mut Tensor,
+    ) -> Result<(), EngineError> {
+        for term in self.terms.iter().filter(|t| t.target == target) {
+            let rows = input.dimensions()[0];
+            let rank = term.pair.rank();
+            let mut down = empty_f32_tensor(vec![rows, rank]);
+            matmul(input, &term.pair.a, &mut down)?;
+            let mut up = empty_f32_tensor(output.dimensions().to_vec());
+            matmul(&down, &term.pair.b, &mut up)?;
+            for (o, d) in output
+                .as_f32_slice_mut()?
+                .iter_mut()
+                .zip(up.as_f32_slice()?)
+            {
+                *o += term.scale * d;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validate `adapter` against `weights` and attach every pair with `scale` (see the module docs
+/// for the effective scale).
+pub fn attach(
+    weights: &mut ModelWeights<'_>,
+    adapter: &LoraAdapter,
+    scale: f32,
+) -> Result<(), EngineError> {
+    adapter.validate(weights)?;
+    for (&(layer, target), pair) in &adapter.pairs {
+        weights.layers[layer].lora.terms.push(LoraTerm {
+            target,
+            pair: Arc::clone(pair),
+            scale: adapter.effective_scale(scale, pair.rank()),
+        });
+    }
+    Ok(())
+}
+
+/// Detach every adapter from `weights`.
+pub fn detach_all(weights: &mut ModelWeights<'_>) {
+    for layer in &mut weights.layers {
+        layer.lora.terms.clear();
+    }
+}
+
+fn empty_f32_tensor(dimensions: Vec<usize>) -> Tensor {
+    let len = dimensions.iter().product::<usize>();
+    Tensor::new(TensorType::F32, Arc::new(vec![0u8; len * 4]), dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapter_tensor_names_parse() {
+        assert_eq!(
+            parse_name("blk.3.attn_q.weight.lora_a"),
+            Some((3, LoraTarget::Q, 0))
+        );
+        assert_eq!(
+            parse_name("blk.12.ffn_down.weight.lora_b"),
+            Some((12, LoraTarget::Down, 1))
+        );
+        for bad in [
+            "blk.0.attn_q.weight",
+            "blk.x.attn_q.weight.lora_a",
+            "blk.0.attn_norm.weight.lora_a",
+            "token_embd.weight.lora_a",
+            "blk.0.attn_q.weight.lora_c",
+        ] {
+            assert_eq!(parse_name(bad), None, "{bad}");
+        }
+    }
+}
@@ -3,3 +3,5 @@ pub mod block;
 pub mod embeddings;
 pub mod ffn;
 pub mod gemma4_ple;
+pub mod kv_store;
+pub mod lora;
@@ -1,15 +1,24 @@
+extern crate alloc;
+
 pub mod error;
 
-pub use error::EngineError;
+pub use error::{EngineError, Result};
 
 pub mod bench_metrics;
 pub mod chat_prompt;
+pub mod compare;
 pub mod core;
 pub mod engine;
 pub mod layers;
 pub mod loaded_model;
+pub mod logging;
+pub mod mem_profile;
 pub mod model_config;
 pub mod model_loader;
 pub mod model_weights;
 pub mod ops;
+#[cfg(test)]
+mod test_utils;
 pub mod tokenizer;
+#[cfg(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown"))]
+pub mod wasm;
@@ -1,9 +1,12 @@
 use std::path::Path;
+use std::sync::OnceLock;
 
 use crate::EngineError;
+use crate::core::time::Instant;
+use crate::engine::observer::{EngineFailed, EngineObserver, LoadFinished, LoadStarted, Operation};
 use crate::model_config::{ModelConfig, TokenizerPromptConfig};
-use crate::model_loader::file_loader::read_file;
-use crate::model_loader::gguf_types::GGUFData;
+use crate::model_loader::file_loader::{read_file, read_from_bytes};
+use crate::model_loader::gguf_types::{GGUFData, LoadOptions, LoadStats};
 use crate::model_weights::{ModelWeightNames, ModelWeights};
 
 /// Fully loaded model storage plus metadata.
@@ -17,10 +20,48 @@ pub struct LoadedModel {
     config: ModelConfig,
     names: ModelWeightNames,
     tokenizer_prompt: TokenizerPromptConfig,
+    load_options: LoadOptions,
+    load_stats: LoadStats,
+    /// [`GGUFData::content_hash`], computed on first use.
+    content_hash: OnceLock<String>,
 }
 
 impl LoadedModel {
     pub fn load(model_path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        Self::load_with(model_path, &LoadOptions::default())
+    }
+
+    /// [`Self::load_with`], reporting the start, the finish or the error to `observer`.
+    pub fn load_observed(
+        model_path: impl AsRef<Path>,
+        options: &LoadOptions,
+        observer: &dyn EngineObserver,
+    ) -> Result<Self, EngineError> {
+        let path = model_path.as_ref().display().to_string();
+        let started = Instant::now();
+        observer.load_started(&LoadStarted { path: path.clone() });
+        match Self::load_with(model_path, options) {
+            Ok(model) => {
+                observer.load_finished(&LoadFinished {
+                    path,
+                    tensors: model.load_stats.tensors_loaded,
+                    bytes_read: model.load_stats.bytes_read,
+                    elapsed: started.elapsed(),
+                });
+                Ok(model)
+            }
+            Err(e) => {
+                observer.error(&EngineFailed::new(Operation::Load, &e));
+                Err(e)
+            }
+        }
+    }
+
+    /// [`Self::load`] with explicit tensor-loading options.
+    pub fn load_with(
+        model_path: impl AsRef<Path>,
+        options: &LoadOptions,
+    ) -> Result<Self, EngineError> {
         let model_path = model_path.as_ref();
         if !model_path.is_file() {
             return Err(EngineError::Model(format!(
@@ -38,7 +79,7 @@ impl LoadedModel {
         let tokenizer_prompt = TokenizerPromptConfig::from_gguf(&gguf)?;
         let config = ModelConfig::from_gguf(&gguf)?;
         let names = ModelWeightNames::resolve(&gguf, &config)?;
-        names.load_all(&mut gguf, model_path.as_str())?;
+        let load_stats = names.load_all_with(&mut gguf, model_path.as_str(), options)?;
 
         Ok(Self {
             model_path,
@@ -46,15 +87,93 @@ impl LoadedModel {
             config,
             names,
             tokenizer_prompt,
+            load_options: options.clone(),
+            load_stats,
+            content_hash: OnceLock::new(),
         })
     }
 
+    /// [`Self::load_with`] for a split GGUF set (`name-00001-of-0000N.gguf`, ...), `shard_paths`
+    /// in shard order as [`resolve_model_path`](crate::model_loader::discovery::resolve_model_path)
+    /// returns them. Every tensor of every shard is read, then the shards are joined with
+    /// [`GGUFData::merge_split`]; [`Self::model_path`] is the first shard. A single path is an
+    /// ordinary [`Self::load_with`].
+    pub fn load_split_with(
+        shard_paths: &[impl AsRef<Path>],
+        options: &LoadOptions,
+    ) -> Result<Self, EngineError> {
+        let [first, rest @ ..] = shard_paths else {
+            return Err(EngineError::Model("split GGUF set has no shards".into()));
+        };
+        if rest.is_empty() {
+            return Self::load_with(first, options);
+        }
+        let mut shards = Vec::with_capacity(shard_paths.len());
+        let mut load_stats = LoadStats::default();
+        for path in shard_paths {
+            let path = path.as_ref();
+            if !path.is_file() {
+                return Err(EngineError::Model(format!(
+                    "model shard not found: {}",
+                    path.display()
+                )));
+            }
+            let path = path
+                .to_str()
+                .ok_or_else(|| EngineError::Model("model path is not valid UTF-8".into()))?;
+            let mut shard = read_file(path)?;
+            load_stats.add(shard.load_tensors_with(path, options)?);
+            shards.push(shard);
+        }
+        let gguf = GGUFData::merge_split(shards)?;
+        let tokenizer_prompt = TokenizerPromptConfig::from_gguf(&gguf)?;
+        let config = ModelConfig::from_gguf(&gguf)?;
+        let names = ModelWeightNames::resolve(&gguf, &config)?;
+        Ok(Self::from_loaded_parts(
+            first.as_ref().display().to_string(),
+            gguf,
+            config,
+            names,
+            tokenizer_prompt,
+            options.clone(),
+            load_stats,
+        ))
+    }
+
+    /// [`Self::load`] from a whole GGUF file already in memory ([`read_from_bytes`]), e.g. in a
+    /// browser or from a network download. The weights are copied out, so `bytes` can be dropped
+    /// afterwards; [`Self::model_path`] is `<memory>`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EngineError> {
+        Self::from_bytes_with(bytes, &LoadOptions::default())
+    }
+
+    /// [`Self::from_bytes`] with explicit tensor-loading options (readahead hints and the shared
+    /// cache need a file and are ignored).
+    pub fn from_bytes_with(bytes: &[u8], options: &LoadOptions) -> Result<Self, EngineError> {
+        let mut gguf = read_from_bytes(bytes)?;
+        let tokenizer_prompt = TokenizerPromptConfig::from_gguf(&gguf)?;
+        let config = ModelConfig::from_gguf(&gguf)?;
+        let names = ModelWeightNames::resolve(&gguf, &config)?;
+        let load_stats = names.load_all_from_bytes(&mut gguf, bytes, options)?;
+        Ok(Self::from_loaded_parts(
+            "<memory>".into(),
+            gguf,
+            config,
+            names,
+            tokenizer_prompt,
+            options.clone(),
+            load_stats,
+        ))
+    }
+
     pub(crate) fn from_loaded_parts(
         model_path: String,
         gguf: GGUFData,
         config: ModelConfig,
         names: ModelWeightNames,
         tokenizer_prompt: TokenizerPromptConfig,
+        load_options: LoadOptions,
+        load_stats: LoadStats,
     ) -> Self {
         Self {
             model_path,
@@ -62,6 +181,9 @@ impl LoadedModel {
             config,
             names,
             tokenizer_prompt,
+            load_options,
+            load_stats,
+            content_hash: OnceLock::new(),
         }
     }
 
@@ -81,6 +203,22 @@ impl LoadedModel {
         &self.tokenizer_prompt
     }
 
+    /// Options the weights were loaded with.
+    pub fn load_options(&self) -> &LoadOptions {
+        &self.load_options
+    }
+
+    /// What loading the weights read, and whether readahead hints were applied.
+    pub fn load_stats(&self) -> &LoadStats {
+        &self.load_stats
+    }
+
+    /// Hash of the loaded weights and metadata (see [`GGUFData::content_hash`]). The first call
+    /// reads every weight byte; later calls return the cached value.
+    pub fn content_hash(&self) -> &str {
+        self.content_hash.get_or_init(|| self.gguf.content_hash())
+    }
+
     pub fn weights(&self) -> Result<ModelWeights<'_>, EngineError> {
         ModelWeights::from_loaded(&self.gguf, &self.names)
     }
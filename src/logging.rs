@@ -0,0 +1,76 @@
+//! Log levels used across the crate, and the logger setup shared by the binaries.
+//!
+//! The library only emits through the `log` facade, by this scheme:
+//! - `error`: an operation failed and its error is about to be returned;
+//! - `warn`: the input is suspect but usable (e.g. duplicate metadata keys);
+//! - `info`: milestones, once per operation (start and finish of a model load);
+//! - `debug`: per-item progress (each tensor loaded, each readahead hint that failed).
+//!
+//! So the default `warn` filter stays silent on a healthy run, and `info` shows what happened
+//! without one line per tensor.
+
+use log::LevelFilter;
+
+/// How much the binaries log, from `--quiet` / `--verbose`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Errors only; `RUST_LOG` is ignored.
+    Quiet,
+    /// Warnings and errors, unless `RUST_LOG` says otherwise.
+    #[default]
+    Normal,
+    /// Milestones too (`-v`).
+    Verbose,
+    /// Per-item progress too (`-vv`).
+    Debug,
+}
+
+impl Verbosity {
+    /// `quiet` wins over any number of `-v`.
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+
+    pub fn level_filter(self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Error,
+            Verbosity::Normal => LevelFilter::Warn,
+            Verbosity::Verbose => LevelFilter::Info,
+            Verbosity::Debug => LevelFilter::Debug,
+        }
+    }
+}
+
+/// Install `env_logger` on stderr. [`Verbosity::Normal`] defers to `RUST_LOG`; the other levels
+/// replace it. Does nothing if a logger is already installed.
+pub fn init(verbosity: Verbosity) {
+    let mut builder = if verbosity == Verbosity::Normal {
+        let default = verbosity.level_filter().as_str();
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default))
+    } else {
+        let mut builder = env_logger::Builder::new();
+        builder.filter_level(verbosity.level_filter());
+        builder
+    };
+    let _ = builder.try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_wins_and_each_v_adds_a_level() {
+        assert_eq!(Verbosity::from_flags(true, 2), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(false, 5), Verbosity::Debug);
+        assert_eq!(Verbosity::Quiet.level_filter(), LevelFilter::Error);
+        assert_eq!(Verbosity::default().level_filter(), LevelFilter::Warn);
+    }
+}
@@ -4,27 +4,65 @@
 //! cargo run --release -- --help
 //! cargo run --release -- "Rust will rule the"
 //! cargo run --release -- -n 32 -m model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf "Hello"
+//! cargo run --release -- -m model/mistral-7b-v0.1 "Hello"   # directory: finds the GGUF + tokenizer
 //! cargo run --release -- --chat gemma4-e2b -m model/gemma-4-e2b-it/gemma-4-E2B-it-Q8_0.gguf \
 //!   -t model/gemma-4-e2b-it/tokenizer.json "Hello"
+//! cargo run --release -- --inspect -m model/mistral-7b-v0.1   # header + metadata warnings only
+//! cargo run --release -- --self-test   # kernels vs scalar reference on this CPU
+//! cargo run --release -- --show-config "Hello"   # resolved settings and their sources
+//! cargo run --release -- --load-timing "Hello"   # load time split into read / convert / alloc
+//! cargo run --release -- --shared-cache "Hello"   # map weights so other processes share them
+//! cargo run --release -- -vv "Hello"   # log load milestones and per-tensor progress (-q: errors only)
+//! cargo run --release -- --verbose-decode "Hello"   # one line per token: id, logprob, top-3, time
+//! cargo run --release -- models add mistral model/mistral-7b-v0.1   # then: -m mistral "Hello"
 //! ```
 
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use inference_engine_rust::EngineError;
 use inference_engine_rust::chat_prompt::{
     ChatPromptStyle, gemma4_e2b_assistant_visible, gemma4_e2b_decode_has_structure_marker,
 };
-use inference_engine_rust::engine::generation::greedy_next_token;
+use inference_engine_rust::core::stats::StatsReport;
+use inference_engine_rust::engine::config::{EngineConfig, LayerSchedule};
+use inference_engine_rust::engine::decode_trace::{DecodeStep, table_header};
+use inference_engine_rust::engine::effective_config::{
+    EffectiveConfig, GenerationSettings, Provenance,
+};
+use inference_engine_rust::engine::generation::{GenerationStats, StopTokens};
+use inference_engine_rust::engine::kv_policy::{KvCachePolicy, MemoryBudget};
+use inference_engine_rust::engine::quality::{QualityMonitor, QualityThresholds};
+use inference_engine_rust::engine::sampling::{sample_greedy, token_logprob};
 use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::thread_pool::ThreadAffinity;
+use inference_engine_rust::engine::watchdog::WatchdogConfig;
+use inference_engine_rust::layers::attention::CacheDtype;
 use inference_engine_rust::loaded_model::LoadedModel;
-use inference_engine_rust::tokenizer::Tokenizer;
+use inference_engine_rust::logging::{self, Verbosity};
+use inference_engine_rust::model_loader::discovery::{ResolvedModel, resolve_model_path};
+use inference_engine_rust::model_loader::file_loader::read_file;
+use inference_engine_rust::model_loader::gguf_types::{Data, LoadOptions};
+use inference_engine_rust::model_loader::registry::{
+    ModelRegistry, default_registry_path, resolve_model,
+};
+use inference_engine_rust::model_loader::weight_layout::LAYOUT_ROLES;
+use inference_engine_rust::ops::kernel_stats;
+use inference_engine_rust::ops::self_test;
+use inference_engine_rust::tokenizer::{IncrementalDecoder, Tokenizer};
 
 #[derive(Parser, Debug)]
 #[command(name = "inference_engine_rust")]
 #[command(about = "Greedy LM generation (GGUF + tokenizer .model or .json)", long_about = None)]
 struct Args {
-    /// GGUF model path (relative to cwd is fine)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// GGUF model: a file, a directory holding one model (or one split set), a glob, or an
+    /// alias from `models add`
     #[arg(
         short,
         long,
@@ -33,8 +71,9 @@ struct Args {
     model: PathBuf,
 
     /// Tokenizer: SentencePiece `tokenizer.model` or Hugging Face `tokenizer.json`
-    #[arg(short, long, default_value = "model/mistral-7b-v0.1/tokenizer.model")]
-    tokenizer: PathBuf,
+    /// (default: the one next to the model)
+    #[arg(short, long)]
+    tokenizer: Option<PathBuf>,
 
     /// How many new tokens to append after the prompt
     #[arg(short = 'n', long, default_value_t = 20)]
@@ -44,15 +83,176 @@ struct Args {
     #[arg(long, default_value = "raw")]
     chat: String,
 
+    /// Worker threads for matmul/attention (default: one per logical core)
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Pin the worker threads to the performance cores of a hybrid CPU (Linux; no-op elsewhere)
+    #[arg(long)]
+    pin_performance_cores: bool,
+
+    /// Skip the kernel self-test run when the session starts (kernels that fail it are otherwise
+    /// disabled with a warning)
+    #[arg(long)]
+    no_startup_self_test: bool,
+
+    /// Warn when a decode step takes longer than this many milliseconds, naming the layer and op
+    /// it is stuck in, and cancel it at four times as long
+    #[arg(long)]
+    watchdog_ms: Option<u64>,
+
+    /// KV cache storage: `auto` (default: the most exact of f32, f16, q8 that fits in memory),
+    /// or pin `f32`, `f16` (half the memory) or `q8` (about a quarter)
+    #[arg(long, default_value = "auto")]
+    kv_cache: String,
+
+    /// Memory the KV cache may use with `--kv-cache auto`, in MiB (default: what the OS reports
+    /// as available)
+    #[arg(long, value_name = "MIB")]
+    kv_budget_mib: Option<usize>,
+
+    /// Print GGUF header counts, architecture, quantization scheme and metadata warnings
+    /// (duplicate keys), then exit without loading tensors or reading a prompt
+    #[arg(long)]
+    inspect: bool,
+
+    /// Check every kernel variant against its scalar reference on this CPU, print a pass/fail
+    /// report and exit (non-zero status if any kernel fails)
+    #[arg(long)]
+    self_test: bool,
+
+    /// Print per-tensor weight statistics (dequantized min/max/mean/stddev, histogram, block
+    /// scales) as `table` or `json`, then exit without generating
+    #[arg(long, value_name = "FORMAT")]
+    stats: Option<String>,
+
+    /// With --stats / --stats-diff: only tensors whose name contains this string
+    #[arg(long, value_name = "SUBSTR")]
+    stats_filter: Option<String>,
+
+    /// Compare the weight statistics with a reference stats JSON (same layout as `--stats json`)
+    /// and print each tensor's largest deviation, then exit
+    #[arg(long, value_name = "JSON")]
+    stats_diff: Option<PathBuf>,
+
+    /// After generating, print which matmul kernel ran for each weight type (stderr)
+    #[arg(long)]
+    kernel_stats: bool,
+
+    /// After loading, print where load time went per tensor type: file reads (MB/s),
+    /// conversions (Melem/s) and allocation (stderr)
+    #[arg(long)]
+    load_timing: bool,
+
+    /// Map the weights read-only and keep converted ones in a sidecar file next to the model, so
+    /// several processes on one machine share them through the page cache
+    #[arg(long)]
+    shared_cache: bool,
+
+    /// Reorder the quantized attention and FFN weights at load into column tiles, for faster
+    /// decode matmul (costs one copy of those weights at load)
+    #[arg(long)]
+    optimize_layout: bool,
+
+    /// Before generating, print the resolved generation settings and where each came from
+    /// (stderr)
+    #[arg(long)]
+    show_config: bool,
+
+    /// Print one line per generated token as it is produced: text (escaped), id, logprob, the
+    /// top-3 alternatives, time since the start, and whether sampling moved off the argmax
+    /// (stderr)
+    #[arg(long)]
+    verbose_decode: bool,
+
+    /// Track how surprised the model is by its own tokens: warn when the mean surprise over the
+    /// last 16 tokens passes 5 nats, and print the surprise/entropy summary at the end (stderr)
+    #[arg(long)]
+    quality: bool,
+
+    /// Like --verbose-decode, but one JSON object per token on stdout, in place of the
+    /// continuation text
+    #[arg(long, conflicts_with = "verbose_decode")]
+    verbose_decode_json: bool,
+
+    /// Model registry file for aliases (default: $INFERENCE_ENGINE_REGISTRY, else
+    /// models.json under the user config directory)
+    #[arg(long, value_name = "FILE", global = true)]
+    registry: Option<PathBuf>,
+
+    /// Log errors only (overrides RUST_LOG and --verbose)
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Log load milestones (-v) or per-tensor progress too (-vv); overrides RUST_LOG
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
     /// Prompt text. If omitted, one line is read from stdin
     #[arg(value_name = "PROMPT")]
     prompt: Option<String>,
 }
 
-fn main() -> Result<(), EngineError> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage model aliases usable as --model
+    #[command(subcommand)]
+    Models(ModelsCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum ModelsCommand {
+    /// Register PATH (a file, a directory or a glob, as for --model) as ALIAS
+    Add { alias: String, path: PathBuf },
+    /// List registered models with their cached metadata
+    List,
+    /// Forget ALIAS (the model file is left alone)
+    Rm { alias: String },
+}
 
+/// Errors print as one `error: ...` line (their `Display`), not as a `Debug` dump.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), EngineError> {
     let args = Args::parse();
+    logging::init(Verbosity::from_flags(args.quiet, args.verbose));
+    let registry = args.registry.clone().or_else(default_registry_path);
+    if let Some(Command::Models(command)) = &args.command {
+        let registry = registry.ok_or_else(|| {
+            EngineError::Model("no config directory (HOME unset); pass --registry".into())
+        })?;
+        return models(command, registry);
+    }
+    if args.inspect {
+        return inspect(&args.model, registry.as_deref());
+    }
+    if args.stats.is_some() || args.stats_diff.is_some() {
+        return weight_stats(
+            &args.model,
+            registry.as_deref(),
+            args.stats.as_deref(),
+            args.stats_filter.as_deref(),
+            args.stats_diff.as_deref(),
+        );
+    }
+    if args.self_test {
+        let report = self_test::self_test();
+        print!("{report}");
+        return match report.failures().count() {
+            0 => Ok(()),
+            n => Err(EngineError::Model(format!(
+                "{n} kernel(s) failed self-test"
+            ))),
+        };
+    }
 
     let prompt = match args.prompt {
         Some(p) if !p.trim().is_empty() => p,
@@ -82,29 +282,157 @@ fn main() -> Result<(), EngineError> {
     })?;
     let prompt = chat_style.wrap(&prompt);
 
-    if !args.tokenizer.is_file() {
+    let kv_policy = if args.kv_cache.eq_ignore_ascii_case("auto") {
+        KvCachePolicy::Auto(match args.kv_budget_mib {
+            Some(mib) => MemoryBudget::Bytes(mib.saturating_mul(1 << 20)),
+            None => MemoryBudget::Detect,
+        })
+    } else {
+        let dtype = CacheDtype::parse(&args.kv_cache).ok_or_else(|| {
+            EngineError::Model(format!(
+                "unknown --kv-cache {:?}: use auto | f32 | f16 | q8",
+                args.kv_cache
+            ))
+        })?;
+        KvCachePolicy::Pinned {
+            dtype,
+            source: Provenance::CliFlag,
+        }
+    };
+    let resolved = resolve(&args.model, registry.as_deref())?;
+    let tokenizer_path = args
+        .tokenizer
+        .clone()
+        .or_else(|| resolved.tokenizer_path.clone())
+        .ok_or_else(|| {
+            EngineError::Model(format!(
+                "no tokenizer.json or tokenizer.model next to {}; pass --tokenizer",
+                resolved.primary().display()
+            ))
+        })?;
+    if !tokenizer_path.is_file() {
         return Err(EngineError::Model(format!(
             "tokenizer file not found: {}",
-            args.tokenizer.display()
+            tokenizer_path.display()
         )));
     }
 
-    let model = LoadedModel::load(&args.model)?;
-    let mut tokenizer = Tokenizer::load_from_file(&args.tokenizer)?;
+    let load_options = LoadOptions {
+        shared_cache: args.shared_cache,
+        optimize_layout: if args.optimize_layout {
+            LAYOUT_ROLES.into_iter().collect()
+        } else {
+            BTreeSet::new()
+        },
+        ..LoadOptions::default()
+    };
+    let model = LoadedModel::load_split_with(&resolved.gguf_paths, &load_options)?;
+    if args.load_timing {
+        eprint!("{}", model.load_stats().timing_report());
+    }
+    // Decoding here is greedy whatever the model recommends.
+    let mut effective = EffectiveConfig::resolve(&[
+        (
+            Provenance::Metadata,
+            GenerationSettings::from_metadata(model.gguf()),
+        ),
+        (
+            Provenance::CliFlag,
+            GenerationSettings {
+                max_new_tokens: Some(args.new_tokens),
+                ..GenerationSettings::default()
+            },
+        ),
+        (
+            Provenance::Override,
+            GenerationSettings {
+                temperature: Some(0.0),
+                min_p: Some(0.0),
+                ..GenerationSettings::default()
+            },
+        ),
+    ])?;
+    let kv_cache = kv_policy.resolve(model.config(), model.config().context_length)?;
+    effective.record_kv_cache(&kv_cache);
+    if args.show_config {
+        eprintln!("{effective}");
+    }
+    let max_new_tokens = effective.config.max_new_tokens;
+    let mut stats = GenerationStats::default();
+    stats.sample_post_load();
+    let mut tokenizer = Tokenizer::load_from_file(&tokenizer_path)?;
     let tok_prompt = model.tokenizer_prompt();
 
     let prompt_ids = tokenizer.encode_with_prompt_config(&prompt, tok_prompt)?;
-    let mut session = InferenceSession::new(&model)?;
+    let engine = EngineConfig {
+        num_threads: args.threads,
+        kv_cache_dtype: kv_cache.dtype,
+        startup_self_test: !args.no_startup_self_test,
+        layer_timings: false,
+        layer_schedule: LayerSchedule::All,
+        thread_affinity: if args.pin_performance_cores {
+            ThreadAffinity::PerformanceCores
+        } else {
+            ThreadAffinity::None
+        },
+        watchdog: args.watchdog_ms.map(|ms| WatchdogConfig {
+            stall_after: Duration::from_millis(ms),
+            cancel_after: Some(Duration::from_millis(ms.saturating_mul(4))),
+        }),
+    };
+    let mut session = InferenceSession::with_config(&model, &engine)?;
+    let started = Instant::now();
     let mut state = session.prefill(&prompt_ids)?;
+    stats.prompt_tokens = prompt_ids.len();
+    stats.sample_post_prefill();
 
-    let stop_id = tok_prompt.eos_token_id;
-    let mut generated = Vec::with_capacity(args.new_tokens);
-    for _ in 0..args.new_tokens {
-        let next_id = greedy_next_token(&session, &state)?;
-        if next_id == stop_id {
+    let stops = StopTokens::new(tok_prompt.terminator_ids());
+    let mut generated = Vec::with_capacity(max_new_tokens);
+    let tracing = args.verbose_decode || args.verbose_decode_json;
+    let mut trace_decoder = IncrementalDecoder::new();
+    if args.verbose_decode {
+        eprintln!("{}", table_header());
+    }
+    let mut quality = args
+        .quality
+        .then(|| QualityMonitor::new(QualityThresholds::default()));
+    for index in 0..max_new_tokens {
+        let logits = session.next_token_logits(&state)?;
+        let next_id = sample_greedy(&logits)?;
+        if stops.contains(next_id) {
             break;
         }
         generated.push(next_id);
+        if let (Some(monitor), Some(logprob)) = (quality.as_mut(), token_logprob(&logits, next_id))
+        {
+            for alert in monitor.observe(&logits, next_id, logprob) {
+                log::warn!(
+                    "token {}: {:?} {:.2} nats over the last {} tokens (threshold {})",
+                    alert.index,
+                    alert.signal,
+                    alert.value,
+                    alert.window,
+                    alert.threshold
+                );
+            }
+        }
+        if tracing {
+            let text = trace_decoder.push(&tokenizer, next_id)?;
+            let step = DecodeStep::record(
+                index,
+                &logits,
+                next_id,
+                text,
+                &tokenizer,
+                None,
+                started.elapsed(),
+            )?;
+            if args.verbose_decode_json {
+                println!("{}", step.to_json_line());
+            } else {
+                eprintln!("{step}");
+            }
+        }
         if matches!(chat_style, ChatPromptStyle::Gemma4E2b) {
             let full = tokenizer.decode_piece_ids(&generated)?;
             if gemma4_e2b_decode_has_structure_marker(&full) {
@@ -114,6 +442,9 @@ fn main() -> Result<(), EngineError> {
 
         state = session.decode_token(next_id)?;
     }
+    stats.generated_tokens = generated.len();
+    stats.sample_decode();
+    stats.quality = quality.map(QualityMonitor::into_stats);
 
     let raw = tokenizer.decode_piece_ids(&generated)?;
     let continuation = if matches!(chat_style, ChatPromptStyle::Gemma4E2b) {
@@ -122,6 +453,175 @@ fn main() -> Result<(), EngineError> {
         raw.trim_end().to_string()
     };
 
-    println!("{continuation}");
+    if !args.verbose_decode_json {
+        println!("{continuation}");
+    }
+    print_memory_stats(&stats);
+    if let Some(q) = &stats.quality {
+        eprintln!(
+            "quality: surprise mean {:.3} max {:.3} nats, entropy mean {:.3} max {:.3} nats, \
+             {} near-argmax / {} tail, {} alerts",
+            q.mean_surprise,
+            q.max_surprise,
+            q.mean_entropy,
+            q.max_entropy,
+            q.near_argmax,
+            q.tail,
+            q.alerts
+        );
+    }
+    if args.kernel_stats {
+        eprintln!("matmul kernels:\n{}", kernel_stats::kernel_stats());
+    }
+    Ok(())
+}
+
+/// Memory section on stderr (only when built with `--features mem-profile`).
+fn print_memory_stats(stats: &GenerationStats) {
+    use inference_engine_rust::mem_profile::{SIZE_CLASS_COUNT, size_class_label};
+
+    let phases = [
+        ("post-load", stats.memory_post_load),
+        ("post-prefill", stats.memory_post_prefill),
+        ("decode", stats.memory_decode),
+    ];
+    if phases.iter().all(|(_, s)| s.is_none()) {
+        return;
+    }
+    let mib = |b: usize| b as f64 / (1024.0 * 1024.0);
+    eprintln!(
+        "memory ({} prompt + {} generated tokens):",
+        stats.prompt_tokens, stats.generated_tokens
+    );
+    for (label, snap) in phases {
+        if let Some(s) = snap {
+            eprintln!(
+                "  {label:<13} current {:>9.1} MiB  peak {:>9.1} MiB  allocs {}",
+                mib(s.current_bytes),
+                mib(s.peak_bytes),
+                s.alloc_count
+            );
+        }
+    }
+    if let Some(peak) = stats.peak_bytes() {
+        eprintln!("  peak          {:>9.1} MiB", mib(peak));
+    }
+    if let Some(last) = stats.memory_decode {
+        let classes: Vec<String> = (0..SIZE_CLASS_COUNT)
+            .map(|i| format!("{}:{}", size_class_label(i), last.size_class_counts[i]))
+            .collect();
+        eprintln!("  size classes  {}", classes.join(" "));
+    }
+}
+
+/// `--stats` / `--stats-diff`: per-tensor weight statistics, optionally against a reference.
+fn weight_stats(
+    model: &Path,
+    registry: Option<&Path>,
+    format: Option<&str>,
+    filter: Option<&str>,
+    diff: Option<&Path>,
+) -> Result<(), EngineError> {
+    if !matches!(format, None | Some("table" | "json")) {
+        return Err(EngineError::Model(format!(
+            "unknown --stats {:?}: use table | json",
+            format.unwrap_or_default()
+        )));
+    }
+    let resolved = resolve(model, registry)?;
+    let path = resolved
+        .primary()
+        .to_str()
+        .ok_or_else(|| EngineError::Model("model path is not valid UTF-8".into()))?;
+    let wanted = |name: &str| filter.is_none_or(|f| name.contains(f));
+    let mut gguf = read_file(path)?;
+    gguf.load_where(path, |name, _| wanted(name))?;
+    let report = gguf.stats_report(wanted)?;
+    match format {
+        Some("json") => println!("{}", report.to_json()),
+        Some(_) => println!("{report}"),
+        None => {}
+    }
+    if let Some(reference) = diff {
+        let reference = StatsReport::from_json(&std::fs::read_to_string(reference)?)?;
+        let diff = report.diff(&reference);
+        println!("{diff}");
+        println!("max deviation: {:.4e}", diff.max_deviation());
+    }
+    Ok(())
+}
+
+/// `--inspect`: metadata-only summary of the resolved GGUF.
+fn inspect(model: &Path, registry: Option<&Path>) -> Result<(), EngineError> {
+    let resolved = resolve(model, registry)?;
+    let path = resolved
+        .primary()
+        .to_str()
+        .ok_or_else(|| EngineError::Model("model path is not valid UTF-8".into()))?;
+    let gguf = read_file(path)?;
+    let architecture = match gguf.get_metadata("general.architecture") {
+        Some(Data::String(a)) => a.as_str(),
+        _ => "?",
+    };
+    println!("{path}");
+    println!("  GGUF version:  {}", gguf.version());
+    println!("  architecture:  {architecture}");
+    println!("  metadata keys: {}", gguf.total_key_vals());
+    println!("  tensors:       {}", gguf.total_tensors());
+    println!("  quantization:  {}", gguf.quant_scheme_name());
+    if let Some(tok) = &resolved.tokenizer_path {
+        println!("  tokenizer:     {}", tok.display());
+    }
+    let warnings = gguf.duplicate_keys();
+    if warnings.is_empty() {
+        println!("  warnings:      none");
+    } else {
+        println!("  warnings:      {}", warnings.len());
+        for w in warnings {
+            println!("    {w}");
+        }
+    }
+    Ok(())
+}
+
+/// `--model` through the alias registry when there is one.
+fn resolve(model: &Path, registry: Option<&Path>) -> Result<ResolvedModel, EngineError> {
+    match registry {
+        Some(registry) => resolve_model(model, registry),
+        None => resolve_model_path(model),
+    }
+}
+
+/// `models add | list | rm`.
+fn models(command: &ModelsCommand, registry: PathBuf) -> Result<(), EngineError> {
+    let mut registry = ModelRegistry::open(registry)?;
+    match command {
+        ModelsCommand::Add { alias, path } => {
+            let entry = registry.add(alias, path)?;
+            println!("{alias} -> {}", entry.path.display());
+            registry.save()?;
+        }
+        ModelsCommand::Rm { alias } => {
+            let entry = registry.remove(alias)?;
+            println!("removed {alias} ({})", entry.path.display());
+            registry.save()?;
+        }
+        ModelsCommand::List => {
+            println!(
+                "{:<20} {:<10} {:>8} {:>5} {:>10}  path",
+                "alias", "arch", "params", "ftype", "size"
+            );
+            for (alias, e) in registry.entries() {
+                let file_type = e.file_type.map_or("-".into(), |t| t.to_string());
+                println!(
+                    "{alias:<20} {:<10} {:>7.2}B {file_type:>5} {:>6.2} GiB  {}",
+                    e.architecture.as_deref().unwrap_or("?"),
+                    e.parameter_count as f64 / 1e9,
+                    e.size as f64 / (1u64 << 30) as f64,
+                    e.path.display()
+                );
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,200 @@
+//! Opt-in heap accounting for "how much RAM does a generation need?" questions.
+//!
+//! With the **`mem-profile`** feature, [`CountingAllocator`] is installed as the global allocator
+//! and every allocation updates a handful of relaxed atomics: live bytes, peak bytes, call counts,
+//! and a per-size-class histogram. [`memory_stats`] returns a snapshot; without the feature it
+//! returns `None` and nothing is counted.
+//!
+//! Counters are process-wide, so snapshots taken while other threads allocate include their work.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Number of allocation size classes tracked in [`MemoryStats::size_class_counts`].
+pub const SIZE_CLASS_COUNT: usize = 8;
+
+/// Upper bound (inclusive, bytes) of each size class; the last class is unbounded.
+/// Classes grow by 8× so both small metadata strings and multi-MB tensors land somewhere useful.
+const SIZE_CLASS_LIMITS: [usize; SIZE_CLASS_COUNT - 1] =
+    [64, 512, 4 << 10, 32 << 10, 256 << 10, 2 << 20, 16 << 20];
+
+static CURRENT_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOC_COUNT: AtomicU64 = AtomicU64::new(0);
+static DEALLOC_COUNT: AtomicU64 = AtomicU64::new(0);
+static REALLOC_COUNT: AtomicU64 = AtomicU64::new(0);
+static SIZE_CLASS_COUNTS: [AtomicU64; SIZE_CLASS_COUNT] =
+    [const { AtomicU64::new(0) }; SIZE_CLASS_COUNT];
+
+/// Point-in-time copy of the allocator counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    /// Bytes currently allocated (requested sizes, not including allocator overhead).
+    pub current_bytes: usize,
+    /// High-water mark of `current_bytes` since start or the last [`reset_peak`].
+    pub peak_bytes: usize,
+    pub alloc_count: u64,
+    pub dealloc_count: u64,
+    pub realloc_count: u64,
+    /// Allocations (and growing/shrinking reallocs) per size class; see [`size_class_label`].
+    pub size_class_counts: [u64; SIZE_CLASS_COUNT],
+}
+
+/// Snapshot of the counters, or `None` when the crate was built without `mem-profile`.
+pub fn memory_stats() -> Option<MemoryStats> {
+    if !cfg!(feature = "mem-profile") {
+        return None;
+    }
+    let mut size_class_counts = [0u64; SIZE_CLASS_COUNT];
+    for (slot, counter) in size_class_counts.iter_mut().zip(&SIZE_CLASS_COUNTS) {
+        *slot = counter.load(Ordering::Relaxed);
+    }
+    let current_bytes = CURRENT_BYTES.load(Ordering::Relaxed);
+    // Load peak after current and clamp: a racing allocation may bump current first.
+    let peak_bytes = PEAK_BYTES.load(Ordering::Relaxed).max(current_bytes);
+    Some(MemoryStats {
+        current_bytes,
+        peak_bytes,
+        alloc_count: ALLOC_COUNT.load(Ordering::Relaxed),
+        dealloc_count: DEALLOC_COUNT.load(Ordering::Relaxed),
+        realloc_count: REALLOC_COUNT.load(Ordering::Relaxed),
+        size_class_counts,
+    })
+}
+
+/// Restart peak tracking from the current live size (e.g. to measure decode alone).
+pub fn reset_peak() {
+    PEAK_BYTES.store(CURRENT_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Human-readable bound for size class `idx`, e.g. `"<=4KiB"` or `">16MiB"`.
+pub fn size_class_label(idx: usize) -> String {
+    fn human(bytes: usize) -> String {
+        if bytes >= 1 << 20 {
+            format!("{}MiB", bytes >> 20)
+        } else if bytes >= 1 << 10 {
+            format!("{}KiB", bytes >> 10)
+        } else {
+            format!("{bytes}B")
+        }
+    }
+    match SIZE_CLASS_LIMITS.get(idx) {
+        Some(&limit) => format!("<={}", human(limit)),
+        None => format!(">{}", human(SIZE_CLASS_LIMITS[SIZE_CLASS_COUNT - 2])),
+    }
+}
+
+#[inline]
+fn size_class(size: usize) -> usize {
+    SIZE_CLASS_LIMITS
+        .iter()
+        .position(|&limit| size <= limit)
+        .unwrap_or(SIZE_CLASS_COUNT - 1)
+}
+
+#[inline]
+fn record_grow(bytes: usize) {
+    let now = CURRENT_BYTES.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK_BYTES.fetch_max(now, Ordering::Relaxed);
+}
+
+#[inline]
+fn record_shrink(bytes: usize) {
+    CURRENT_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+/// [`System`] wrapper that maintains the counters behind [`memory_stats`].
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+            SIZE_CLASS_COUNTS[size_class(layout.size())].fetch_add(1, Ordering::Relaxed);
+            record_grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            ALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+            SIZE_CLASS_COUNTS[size_class(layout.size())].fetch_add(1, Ordering::Relaxed);
+            record_grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        DEALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+        record_shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        // On failure the old block is untouched, so the counters stay as they were.
+        if !new_ptr.is_null() {
+            REALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+            SIZE_CLASS_COUNTS[size_class(new_size)].fetch_add(1, Ordering::Relaxed);
+            let old_size = layout.size();
+            if new_size >= old_size {
+                record_grow(new_size - old_size);
+            } else {
+                record_shrink(old_size - new_size);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(feature = "mem-profile")]
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_classes_cover_every_size() {
+        assert_eq!(size_class(0), 0);
+        assert_eq!(size_class(64), 0);
+        assert_eq!(size_class(65), 1);
+        assert_eq!(size_class(16 << 20), SIZE_CLASS_COUNT - 2);
+        assert_eq!(size_class(usize::MAX), SIZE_CLASS_COUNT - 1);
+        assert_eq!(size_class_label(2), "<=4KiB");
+        assert_eq!(size_class_label(SIZE_CLASS_COUNT - 1), ">16MiB");
+    }
+
+    #[cfg(not(feature = "mem-profile"))]
+    #[test]
+    fn stats_unavailable_without_feature() {
+        assert!(memory_stats().is_none());
+    }
+
+    #[cfg(feature = "mem-profile")]
+    #[test]
+    fn peak_never_below_current_across_alloc_realloc_free() {
+        let check = || {
+            let s = memory_stats().expect("feature enabled");
+            assert!(s.peak_bytes >= s.current_bytes, "{s:?}");
+            s
+        };
+        let before = check();
+        let mut v: Vec<u8> = Vec::with_capacity(1 << 20);
+        check();
+        v.resize(4 << 20, 1);
+        let grown = check();
+        assert!(grown.realloc_count > before.realloc_count);
+        assert!(grown.peak_bytes >= 4 << 20);
+        let zeroed = vec![0u64; 1 << 16];
+        check();
+        drop(v);
+        drop(zeroed);
+        let after = check();
+        assert!(after.dealloc_count >= before.dealloc_count + 2);
+    }
+}
@@ -1,5 +1,8 @@
+use log::info;
+
 use crate::EngineError;
 use crate::model_loader::gguf_types::{Data, GGUFData};
+use crate::ops::rope::RopeScaling;
 
 /// Tokenizer special-token policy read from GGUF (same keys as llama.cpp / `tokenizer.ggml.*`).
 ///
@@ -10,8 +13,16 @@ pub struct TokenizerPromptConfig {
     pub add_bos_token: bool,
     /// If true, append [`Self::eos_token_id`] after the encoded prompt (rare for raw completion).
     pub add_eos_token: bool,
+    /// With [`Self::add_bos_token`], skip the implicit BOS when the prompt already starts with
+    /// one (as an id, or as the BOS piece text), so the model never sees a double BOS. On by
+    /// default; turn off to reproduce tokenizers that always prepend.
+    pub dedupe_bos: bool,
     pub bos_token_id: u32,
     pub eos_token_id: u32,
+    /// Further end-of-generation ids from `tokenizer.ggml.eot_token_id` / `eom_token_id` (e.g.
+    /// Llama 3's `<|eot_id|>` / `<|eom_id|>` next to `<|end_of_text|>`). Generation stops on
+    /// these as well as on EOS; they are never appended to prompts.
+    pub eot_token_ids: Vec<u32>,
 }
 
 impl Default for TokenizerPromptConfig {
@@ -19,8 +30,10 @@ impl Default for TokenizerPromptConfig {
         Self {
             add_bos_token: false,
             add_eos_token: false,
+            dedupe_bos: true,
             bos_token_id: 1,
             eos_token_id: 2,
+            eot_token_ids: Vec::new(),
         }
     }
 }
@@ -40,13 +53,38 @@ impl TokenizerPromptConfig {
         let add_eos_token = get_bool(gguf, "tokenizer.ggml.add_eos_token").unwrap_or(default_eos);
         let bos_token_id = get_u32(gguf, "tokenizer.ggml.bos_token_id").unwrap_or(1);
         let eos_token_id = get_u32(gguf, "tokenizer.ggml.eos_token_id").unwrap_or(2);
+        let mut eot_token_ids: Vec<u32> =
+            ["tokenizer.ggml.eot_token_id", "tokenizer.ggml.eom_token_id"]
+                .iter()
+                .filter_map(|key| get_u32(gguf, key))
+                .filter(|&id| id != eos_token_id)
+                .collect();
+        eot_token_ids.dedup();
         Ok(Self {
             add_bos_token,
             add_eos_token,
+            dedupe_bos: true,
             bos_token_id,
             eos_token_id,
+            eot_token_ids,
         })
     }
+
+    /// EOS followed by every [`Self::eot_token_ids`] entry.
+    pub fn terminator_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        std::iter::once(self.eos_token_id).chain(self.eot_token_ids.iter().copied())
+    }
+}
+
+/// Id of `piece` in the GGUF vocabulary (`tokenizer.ggml.tokens`), if present.
+pub fn vocab_token_id(gguf: &GGUFData, piece: &str) -> Option<u32> {
+    let Some(Data::Array(tokens)) = gguf.get_metadata("tokenizer.ggml.tokens") else {
+        return None;
+    };
+    let id = tokens
+        .iter()
+        .position(|t| matches!(t, Data::String(s) if s == piece))?;
+    u32::try_from(id).ok()
 }
 
 /// Implicit `add_bos` / `add_eos` when GGUF omits `tokenizer.ggml.add_*` (see llama.cpp vocab load).
@@ -69,6 +107,16 @@ pub enum ModelFamily {
     Gemma4,
 }
 
+impl ModelFamily {
+    /// Default [`ModelConfig::norm_weight_offset`]. 0.0 for every supported family: their GGUFs
+    /// store full scales (llama.cpp's Gemma converters already add the `+1` at conversion).
+    pub fn norm_weight_offset(self) -> f32 {
+        match self {
+            ModelFamily::MistralLlama | ModelFamily::Gemma4 => 0.0,
+        }
+    }
+}
+
 /// Per-layer attention / RoPE settings. Dense models use the same spec on every layer.
 #[derive(Debug, Clone, PartialEq)]
 pub struct LayerAttentionSpec {
@@ -77,6 +125,9 @@ pub struct LayerAttentionSpec {
     pub rope_theta: f32,
     /// RoPE applies to the first `rope_rotary_dim` elements of each head (even, ≤ model `head_dim`).
     pub rope_rotary_dim: usize,
+    /// Per-pair frequency divisors from [`ModelConfig::rope_scaling`] for this layer's base and
+    /// rotary span (see [`RopeScaling::freq_scales`]); `None` without scaling.
+    pub rope_freq_scales: Option<Vec<f32>>,
 }
 
 impl LayerAttentionSpec {
@@ -85,6 +136,7 @@ impl LayerAttentionSpec {
             sliding_window: None,
             rope_theta,
             rope_rotary_dim: head_dim,
+            rope_freq_scales: None,
         }
     }
 }
@@ -107,17 +159,28 @@ pub struct ModelConfig {
     pub n_layers: usize,
     pub n_heads: usize,
     pub n_kv_heads: usize,
-    /// Representative head width (dense: `hidden_dim / n_heads`; Gemma 4: max across layers).
+    /// Representative head width (dense: `{arch}.attention.key_length`, else
+    /// `hidden_dim / n_heads`; Gemma 4: max across layers).
     pub head_dim: usize,
-    /// Representative FFN inner size (dense: global metadata; Gemma 4: max across layers).
+    /// Representative FFN inner size (dense: `{arch}.feed_forward_length`, else the
+    /// `blk.0.ffn_gate.weight` shape; Gemma 4: max across layers).
     pub ffn_dim: usize,
     /// One entry per `blk.{i}`; authoritative for matmul and KV head width.
     pub layer_dims: Vec<LayerDims>,
     /// Default / “global” RoPE base from GGUF (`llama.rope.theta`). Per-layer values live in
     /// [`Self::layer_attention`]; this stays for diagnostics and Gemma-free checkpoints.
     pub rope_theta: f32,
+    /// `{arch}.rope.scaling.*` context extension; applied per layer through
+    /// [`LayerAttentionSpec::rope_freq_scales`].
+    pub rope_scaling: Option<RopeScaling>,
     pub rms_norm_eps: f32,
+    /// Rows of the embedding / LM head, i.e. the logits width. May include padding rows; see
+    /// [`Self::tokenizer_vocab_size`].
     pub vocab_size: usize,
+    /// Tokens the tokenizer actually defines (`tokenizer.ggml.tokens`, minus trailing `UNUSED`
+    /// entries), at most [`Self::vocab_size`]. Ids from here up are padding rows added to round
+    /// the matrix size and must never be sampled.
+    pub tokenizer_vocab_size: usize,
     /// If true, undo HF→GGUF `LlamaModel.permute` on Q/K **activations** (`convert_hf_to_gguf.py`).
     /// Mistral GGUFs (`MistralModel.undo_permute = false`) default **false** via `general.name` … `mistral`;
     /// Llama permuted checkpoints default **true**. Override: `INFERENCE_ENGINE_GGUF_QK_UNPACK=0|1` or
@@ -137,10 +200,17 @@ pub struct ModelConfig {
     pub gemma4_kv_borrow_from: Vec<Option<usize>>,
     /// Gemma 4: `gemma4.final_logit_softcapping` — `tanh(x/cap)*cap` on LM logits; `None` if absent.
     pub final_logit_softcapping: Option<f32>,
+    /// `{arch}.attn_logit_softcapping` (Gemma 2 style) — `tanh(s/cap)*cap` on every attention
+    /// score before softmax; `None` if absent.
+    pub attn_logit_softcapping: Option<f32>,
+    /// Added to every stored RMSNorm weight before scaling: `1.0` for checkpoints that store the
+    /// scale as a delta (`weight = 1 + stored`). See [`ModelFamily::norm_weight_offset`].
+    pub norm_weight_offset: f32,
 }
 
 impl ModelConfig {
     pub fn from_gguf(gguf: &GGUFData) -> Result<Self, EngineError> {
+        reject_mixture_of_experts(gguf)?;
         let context_length =
             get_usize_alt(gguf, &["llama.context_length", "gemma4.context_length"])?;
         let hidden_dim =
@@ -153,10 +223,12 @@ impl ModelConfig {
         let n_kv_heads = get_usize_opt(gguf, "llama.attention.head_count_kv")
             .or_else(|| get_usize_opt(gguf, "gemma4.attention.head_count_kv"))
             .unwrap_or(n_heads);
-        let ffn_dim_meta = get_usize_alt(
-            gguf,
-            &["llama.feed_forward_length", "gemma4.feed_forward_length"],
-        )?;
+        let ffn_dim_meta = match get_usize_opt(gguf, "llama.feed_forward_length")
+            .or_else(|| get_usize_opt(gguf, "gemma4.feed_forward_length"))
+        {
+            Some(v) => v,
+            None => ffn_dim_from_weights(gguf, hidden_dim)?,
+        };
         let rope_theta = get_f32_opt(gguf, "llama.rope.theta")
             .or_else(|| get_f32_opt(gguf, "gemma4.rope.freq_base"))
             .unwrap_or(10000.0);
@@ -167,33 +239,55 @@ impl ModelConfig {
                 "gemma4.attention.layer_norm_rms_epsilon",
             ],
         )?;
-        let vocab_size = if let Some(v) = get_usize_opt(gguf, "llama.vocab_size")
+        let tokenizer_vocab = unpadded_token_count(gguf);
+        let vocab_size = match table_rows(gguf)
+            .or_else(|| get_usize_opt(gguf, "llama.vocab_size"))
             .or_else(|| get_usize_opt(gguf, "gemma4.vocab_size"))
+            .or(tokenizer_vocab)
         {
-            v
-        } else {
-            get_array_len(gguf, "tokenizer.ggml.tokens")?
+            Some(v) => v,
+            None => get_array_len(gguf, "tokenizer.ggml.tokens")?,
         };
+        let tokenizer_vocab_size = tokenizer_vocab.unwrap_or(vocab_size);
+        if tokenizer_vocab_size > vocab_size {
+            return Err(EngineError::Model(format!(
+                "tokenizer defines {tokenizer_vocab_size} tokens but the embedding / LM head \
+                 only has {vocab_size} rows; ids {vocab_size}.. would have no logits"
+            )));
+        }
+        if tokenizer_vocab_size < vocab_size {
+            info!(
+                "vocab: {} padding rows after the tokenizer's {tokenizer_vocab_size} tokens are \
+                 never sampled",
+                vocab_size - tokenizer_vocab_size
+            );
+        }
 
         // Gemma 4 may report `gemma4.attention.key_length` for KV heads that do not match
         // `embedding_length / head_count` (hybrid SWA/global). Use the quotient when it matches;
-        // otherwise fall back to `hidden_dim / n_heads` for this dense path.
+        // otherwise fall back to `hidden_dim / n_heads` (per-layer widths come from tensors).
+        // Llama/Mistral: `llama.attention.key_length` is authoritative, so checkpoints with
+        // `head_dim * n_heads != hidden_dim` keep their real head width; divide only when absent.
         let head_dim = if let Some(kd) = get_usize_opt(gguf, "gemma4.attention.key_length") {
             if kd * n_heads == hidden_dim {
                 kd
-            } else if hidden_dim % n_heads != 0 {
+            } else {
+                derived_head_dim(hidden_dim, n_heads)?
+            }
+        } else if let Some(kd) = get_usize_opt(gguf, "llama.attention.key_length") {
+            if let Some(vd) = get_usize_opt(gguf, "llama.attention.value_length")
+                && vd != kd
+            {
                 return Err(EngineError::Model(format!(
-                    "hidden_dim {hidden_dim} not divisible by n_heads {n_heads}"
+                    "llama.attention.value_length {vd} != key_length {kd} (separate V head width is unsupported)"
                 )));
-            } else {
-                hidden_dim / n_heads
             }
-        } else if hidden_dim % n_heads != 0 {
-            return Err(EngineError::Model(format!(
-                "hidden_dim {hidden_dim} not divisible by n_heads {n_heads}"
-            )));
+            if kd == 0 {
+                return Err(EngineError::Model("llama.attention.key_length is 0".into()));
+            }
+            kd
         } else {
-            hidden_dim / n_heads
+            derived_head_dim(hidden_dim, n_heads)?
         };
 
         if n_heads % n_kv_heads != 0 {
@@ -250,8 +344,15 @@ impl ModelConfig {
                 .ok_or_else(|| EngineError::Model("layer_dims empty".into()))?,
         };
 
-        let layer_attention =
+        let mut layer_attention =
             build_layer_attention_specs(gguf, family, n_layers, &layer_dims, rope_theta)?;
+        let rope_scaling = rope_scaling_from_gguf(gguf)?;
+        if let Some(scaling) = rope_scaling {
+            for spec in &mut layer_attention {
+                spec.rope_freq_scales =
+                    Some(scaling.freq_scales(spec.rope_theta, spec.rope_rotary_dim as u32));
+            }
+        }
 
         let embedding_length_per_layer = match family {
             ModelFamily::Gemma4 => {
@@ -280,6 +381,9 @@ impl ModelConfig {
 
         let final_logit_softcapping = get_f32_opt(gguf, "gemma4.final_logit_softcapping")
             .filter(|&x| x > 0.0 && x.is_finite());
+        let attn_logit_softcapping = get_string(gguf, "general.architecture")
+            .and_then(|arch| get_f32_opt(gguf, &format!("{arch}.attn_logit_softcapping")))
+            .filter(|&x| x > 0.0 && x.is_finite());
 
         Ok(Self {
             family,
@@ -292,8 +396,10 @@ impl ModelConfig {
             ffn_dim,
             layer_dims,
             rope_theta,
+            rope_scaling,
             rms_norm_eps,
             vocab_size,
+            tokenizer_vocab_size,
             unpack_llama_gguf_qk,
             layer_attention,
             token_embedding_scale,
@@ -302,9 +408,16 @@ impl ModelConfig {
             ple_model_proj_scale,
             gemma4_kv_borrow_from,
             final_logit_softcapping,
+            attn_logit_softcapping,
+            norm_weight_offset: family.norm_weight_offset(),
         })
     }
 
+    /// Padding rows at the end of the embedding matrix (`vocab_size - tokenizer_vocab_size`).
+    pub fn padding_token_count(&self) -> usize {
+        self.vocab_size - self.tokenizer_vocab_size
+    }
+
     pub fn layer_dims_for(&self, layer_idx: usize) -> Result<&LayerDims, EngineError> {
         self.layer_dims.get(layer_idx).ok_or_else(|| {
             EngineError::Model(format!(
@@ -327,6 +440,16 @@ impl ModelConfig {
     }
 }
 
+/// `hidden_dim / n_heads`, for checkpoints that do not store the head width.
+fn derived_head_dim(hidden_dim: usize, n_heads: usize) -> Result<usize, EngineError> {
+    if n_heads == 0 || hidden_dim % n_heads != 0 {
+        return Err(EngineError::Model(format!(
+            "hidden_dim {hidden_dim} not divisible by n_heads {n_heads}"
+        )));
+    }
+    Ok(hidden_dim / n_heads)
+}
+
 /// HF `Gemma4TextAttention`: last `num_kv_shared` layers reuse K/V from the last earlier layer with the same
 /// sliding vs full pattern (`layer_types` match).
 fn build_gemma4_kv_borrow_from(
@@ -371,6 +494,7 @@ fn build_gemma4_kv_borrow_from(
     Ok(out)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn infer_layer_dims(
     gguf: &GGUFData,
     family: ModelFamily,
@@ -445,9 +569,7 @@ fn infer_layer_dims(
 
 fn tensor_weight_k_n(gguf: &GGUFData, name: &str) -> Result<(usize, usize), EngineError> {
     let meta = gguf
-        .tensors_metadata()
-        .iter()
-        .find(|t| t.name == name)
+        .tensor_info(name)
         .ok_or_else(|| EngineError::Model(format!("missing tensor metadata '{name}'")))?;
     if meta.dimensions.len() < 2 {
         return Err(EngineError::Model(format!(
@@ -458,6 +580,59 @@ fn tensor_weight_k_n(gguf: &GGUFData, name: &str) -> Result<(usize, usize), Engi
     Ok((meta.dimensions[0], meta.dimensions[1]))
 }
 
+/// FFN inner size from `blk.0.ffn_gate.weight` (GGUF dims `[hidden, ffn]`), for checkpoints
+/// without `{arch}.feed_forward_length`.
+pub fn ffn_dim_from_weights(gguf: &GGUFData, hidden_dim: usize) -> Result<usize, EngineError> {
+    let name = "blk.0.ffn_gate.weight";
+    let (k, n) = tensor_weight_k_n(gguf, name)?;
+    if k != hidden_dim {
+        return Err(EngineError::Model(format!(
+            "{name}: expected input dim {hidden_dim}, got {k}"
+        )));
+    }
+    Ok(n)
+}
+
+/// Rows of `name` from tensor metadata (GGUF dims `[hidden, vocab]`; the larger one, as in
+/// [`crate::layers::embeddings::get_vocab_size`]).
+fn vocab_rows(gguf: &GGUFData, name: &str) -> Option<usize> {
+    let meta = gguf.tensor_info(name)?;
+    meta.dimensions.iter().copied().max()
+}
+
+/// Token ids both the embedding and the LM head cover: `min(embedding rows, head rows)`, with a
+/// tied head (no `output.weight` / `lm_head.weight`) sharing the embedding's rows. Extra rows
+/// in the larger of the two are never reached.
+fn table_rows(gguf: &GGUFData) -> Option<usize> {
+    let embedding = vocab_rows(gguf, "token_embd.weight");
+    let head = vocab_rows(gguf, "output.weight").or_else(|| vocab_rows(gguf, "lm_head.weight"));
+    match (embedding, head) {
+        (Some(e), Some(h)) => Some(e.min(h)),
+        (e, h) => e.or(h),
+    }
+}
+
+/// `tokenizer.ggml.tokens` length without trailing entries whose `tokenizer.ggml.token_type` is
+/// `UNUSED` (5), which converters append when padding the vocab. `None` without a token list.
+fn unpadded_token_count(gguf: &GGUFData) -> Option<usize> {
+    const TOKEN_TYPE_UNUSED: usize = 5;
+    let Some(Data::Array(tokens)) = gguf.get_metadata("tokenizer.ggml.tokens") else {
+        return None;
+    };
+    let Some(Data::Array(types)) = gguf.get_metadata("tokenizer.ggml.token_type") else {
+        return Some(tokens.len());
+    };
+    if types.len() != tokens.len() {
+        return Some(tokens.len());
+    }
+    let padding = types
+        .iter()
+        .rev()
+        .take_while(|t| get_usize_opt_from_data_elem(t) == Some(TOKEN_TYPE_UNUSED))
+        .count();
+    Some(tokens.len() - padding)
+}
+
 fn detect_model_family(gguf: &GGUFData) -> ModelFamily {
     let arch = get_string(gguf, "general.architecture")
         .unwrap_or_default()
@@ -623,11 +798,97 @@ fn build_gemma4_layer_attention(
             sliding_window: swa,
             rope_theta: theta,
             rope_rotary_dim: rotary_dim,
+            rope_freq_scales: None,
         });
     }
     Ok(out)
 }
 
+/// `{arch}.rope.scaling.type` with its `factor`, `original_context_length` and YaRN betas.
+/// `None` for an absent or `none` type, or a factor of 1; other types are rejected rather than
+/// run unscaled past the trained context.
+fn rope_scaling_from_gguf(gguf: &GGUFData) -> Result<Option<RopeScaling>, EngineError> {
+    let Some(arch) = get_string(gguf, "general.architecture") else {
+        return Ok(None);
+    };
+    let key = |k: &str| format!("{arch}.rope.scaling.{k}");
+    let Some(kind) = get_string(gguf, &key("type")) else {
+        return Ok(None);
+    };
+    if kind == "none" {
+        return Ok(None);
+    }
+    let factor = get_f32_opt(gguf, &key("factor")).unwrap_or(1.0);
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(EngineError::Model(format!(
+            "{} must be finite and > 0 (got {factor})",
+            key("factor")
+        )));
+    }
+    if factor == 1.0 {
+        return Ok(None);
+    }
+    match kind.as_str() {
+        "linear" => Ok(Some(RopeScaling::Linear { factor })),
+        "yarn" => {
+            let original_context_length = get_usize_opt(gguf, &key("original_context_length"))
+                .ok_or_else(|| {
+                    EngineError::Model(format!(
+                        "YaRN rope scaling needs {}",
+                        key("original_context_length")
+                    ))
+                })?;
+            let beta_fast =
+                get_f32_opt(gguf, &key("yarn_beta_fast")).unwrap_or(RopeScaling::YARN_BETA_FAST);
+            let beta_slow =
+                get_f32_opt(gguf, &key("yarn_beta_slow")).unwrap_or(RopeScaling::YARN_BETA_SLOW);
+            if beta_fast.partial_cmp(&beta_slow) != Some(std::cmp::Ordering::Greater) {
+                return Err(EngineError::Model(format!(
+                    "YaRN beta_fast {beta_fast} must exceed beta_slow {beta_slow}"
+                )));
+            }
+            Ok(Some(RopeScaling::Yarn {
+                factor,
+                original_context_length,
+                beta_fast,
+                beta_slow,
+            }))
+        }
+        other => Err(EngineError::Model(format!(
+            "unsupported rope scaling type '{other}' ({}); expected none, linear or yarn",
+            key("type")
+        ))),
+    }
+}
+
+/// Mixtral-style GGUFs route each token through `expert_count` FFNs (3-D `ffn_*_exps` tensors
+/// plus an `ffn_gate_inp` router); the dense forward path would misread those dims, so refuse.
+fn reject_mixture_of_experts(gguf: &GGUFData) -> Result<(), EngineError> {
+    let expert_count = get_usize_opt(gguf, "llama.expert_count")
+        .or_else(|| get_usize_opt(gguf, "gemma4.expert_count"))
+        .unwrap_or(0);
+    if expert_count == 0 {
+        return Ok(());
+    }
+    let moe_tensors: Vec<&str> = gguf
+        .tensor_names()
+        .iter()
+        .map(|(_, name)| name)
+        .filter(|n| n.contains("_exps.") || n.contains("ffn_gate_inp."))
+        .collect();
+    let shown = moe_tensors.len().min(4);
+    Err(EngineError::Model(format!(
+        "MoE not supported: expert_count = {expert_count}; expert tensors ({} total): {}{}",
+        moe_tensors.len(),
+        moe_tensors[..shown].join(", "),
+        if moe_tensors.len() > shown {
+            ", ..."
+        } else {
+            ""
+        }
+    )))
+}
+
 fn get_usize_alt(gguf: &GGUFData, keys: &[&str]) -> Result<usize, EngineError> {
     for key in keys {
         if let Some(v) = get_usize_opt(gguf, key) {
@@ -692,12 +953,12 @@ fn get_f32_opt(gguf: &GGUFData, key: &str) -> Option<f32> {
 }
 
 fn get_array_len(gguf: &GGUFData, key: &str) -> Result<usize, EngineError> {
-    match gguf.get_metadata(key) {
-        Some(Data::Array(v)) => Ok(v.len()),
-        Some(_) => Err(EngineError::Model(format!(
+    match (gguf.array_len(key), gguf.get_metadata(key)) {
+        (Some(len), _) => Ok(len),
+        (None, Some(_)) => Err(EngineError::Model(format!(
             "metadata key '{key}' is not an array"
         ))),
-        None => Err(EngineError::Model(format!("missing metadata key '{key}'"))),
+        (None, None) => Err(EngineError::Model(format!("missing metadata key '{key}'"))),
     }
 }
 
@@ -0,0 +1,392 @@
+//! Turn what a user typed for `--model` (a file, a directory, or a glob) into concrete paths.
+//!
+//! Directories are typically Hugging Face repo snapshots: one or more `.gguf` files, possibly a
+//! split set (`name-00001-of-00003.gguf`, …), READMEs, and a `tokenizer.json` /
+//! `tokenizer.model`. A split set counts as one candidate (its shards in order); more than one
+//! candidate is an error listing them, so the user can pick.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::EngineError;
+
+/// Tokenizer file names looked up next to the model, in order of preference.
+pub const TOKENIZER_FILE_NAMES: [&str; 2] = ["tokenizer.json", "tokenizer.model"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedModel {
+    /// The model file, or every shard of a split set in shard order.
+    pub gguf_paths: Vec<PathBuf>,
+    /// First of [`TOKENIZER_FILE_NAMES`] found in the model's directory.
+    pub tokenizer_path: Option<PathBuf>,
+}
+
+impl ResolvedModel {
+    /// The single file, or the first shard of a split set.
+    pub fn primary(&self) -> &Path {
+        &self.gguf_paths[0]
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.gguf_paths.len() > 1
+    }
+}
+
+/// Resolve `input`: a `.gguf` file (a shard pulls in its siblings), a directory searched for
+/// exactly one model, or a file-name glob (`*`, `?`) such as `models/*Q4_K_M*.gguf`.
+pub fn resolve_model_path(input: impl AsRef<Path>) -> Result<ResolvedModel, EngineError> {
+    let input = input.as_ref();
+    let candidates = if input.is_dir() {
+        let files = gguf_files_in(input, |_| true)?;
+        if files.is_empty() {
+            return Err(EngineError::Model(format!(
+                "no .gguf files in directory {}",
+                input.display()
+            )));
+        }
+        group_candidates(files)
+    } else if is_glob(input) {
+        let dir = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let pattern = input
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| EngineError::Model(format!("bad glob {}", input.display())))?;
+        let files = gguf_files_in(dir, |name| glob_match(pattern, name))?;
+        if files.is_empty() {
+            return Err(EngineError::Model(format!(
+                "no .gguf files match {}",
+                input.display()
+            )));
+        }
+        group_candidates(files)
+    } else if input.is_file() {
+        match split_name(input) {
+            Some(split) => vec![Candidate::Split(split.shard_paths())],
+            None => vec![Candidate::Single(input.to_path_buf())],
+        }
+    } else {
+        return Err(EngineError::Model(format!(
+            "model path not found: {}",
+            input.display()
+        )));
+    };
+
+    let gguf_paths = match <[Candidate; 1]>::try_from(candidates) {
+        Ok([only]) => only.into_paths()?,
+        Err(many) => {
+            let list: Vec<String> = many.iter().map(|c| format!("  {c}")).collect();
+            return Err(EngineError::Model(format!(
+                "{} matches {} models; pick one:\n{}",
+                input.display(),
+                many.len(),
+                list.join("\n")
+            )));
+        }
+    };
+    let dir = gguf_paths[0].parent().unwrap_or(Path::new("."));
+    let tokenizer_path = TOKENIZER_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|p| p.is_file());
+    Ok(ResolvedModel {
+        gguf_paths,
+        tokenizer_path,
+    })
+}
+
+/// One loadable model among the files found.
+enum Candidate {
+    Single(PathBuf),
+    /// Expected shard paths `1..=N`, some possibly missing on disk.
+    Split(Vec<PathBuf>),
+}
+
+impl Candidate {
+    fn into_paths(self) -> Result<Vec<PathBuf>, EngineError> {
+        match self {
+            Candidate::Single(path) => Ok(vec![path]),
+            Candidate::Split(paths) => {
+                let missing: Vec<String> = paths
+                    .iter()
+                    .filter(|p| !p.is_file())
+                    .map(|p| p.display().to_string())
+                    .collect();
+                if missing.is_empty() {
+                    Ok(paths)
+                } else {
+                    Err(EngineError::Model(format!(
+                        "split GGUF is incomplete; missing {}",
+                        missing.join(", ")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Candidate::Single(path) => write!(f, "{}", path.display()),
+            Candidate::Split(paths) => {
+                write!(f, "{} ({} shards)", paths[0].display(), paths.len())
+            }
+        }
+    }
+}
+
+/// `<stem>-<index>-of-<count>.gguf`, as written by llama.cpp's `gguf-split`.
+struct SplitName {
+    dir: PathBuf,
+    stem: String,
+    width: usize,
+    count: usize,
+}
+
+impl SplitName {
+    fn shard_paths(&self) -> Vec<PathBuf> {
+        (1..=self.count)
+            .map(|i| {
+                self.dir.join(format!(
+                    "{}-{i:0w$}-of-{:0w$}.gguf",
+                    self.stem,
+                    self.count,
+                    w = self.width
+                ))
+            })
+            .collect()
+    }
+}
+
+fn split_name(path: &Path) -> Option<SplitName> {
+    let name = path.file_name()?.to_str()?;
+    let base = strip_gguf_extension(name)?;
+    let (rest, count) = base.rsplit_once("-of-")?;
+    let (stem, index) = rest.rsplit_once('-')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if stem.is_empty() || !digits(index) || !digits(count) || index.len() != count.len() {
+        return None;
+    }
+    let width = index.len();
+    let count: usize = count.parse().ok()?;
+    let index: usize = index.parse().ok()?;
+    if count == 0 || index == 0 || index > count {
+        return None;
+    }
+    Some(SplitName {
+        dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        stem: stem.to_string(),
+        width,
+        count,
+    })
+}
+
+fn strip_gguf_extension(name: &str) -> Option<&str> {
+    let dot = name.rfind('.')?;
+    name[dot + 1..]
+        .eq_ignore_ascii_case("gguf")
+        .then(|| &name[..dot])
+}
+
+/// `.gguf` files directly in `dir` whose name passes `keep`, sorted by name.
+fn gguf_files_in(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>, EngineError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_file() && strip_gguf_extension(name).is_some() && keep(name) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Collapse the shards of each split set into one candidate (shards keep name order).
+fn group_candidates(files: Vec<PathBuf>) -> Vec<Candidate> {
+    let mut out: Vec<Candidate> = Vec::new();
+    let mut seen_splits: Vec<(PathBuf, String, usize)> = Vec::new();
+    for path in files {
+        match split_name(&path) {
+            Some(split) => {
+                let key = (split.dir.clone(), split.stem.clone(), split.count);
+                if !seen_splits.contains(&key) {
+                    seen_splits.push(key);
+                    out.push(Candidate::Split(split.shard_paths()));
+                }
+            }
+            None => out.push(Candidate::Single(path)),
+        }
+    }
+    out
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.contains(['*', '?']))
+}
+
+/// `*` matches any run of characters, `?` exactly one; everything else is literal.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    // Position after the last `*` and the name index it is currently absorbing up to.
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi + 1, ni));
+            pi += 1;
+        } else if let Some((after, absorbed)) = star {
+            pi = after;
+            ni = absorbed + 1;
+            star = Some((after, ni));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    /// Fresh directory under the system temp dir, holding the given (empty) files.
+    fn temp_layout(name: &str, files: &[&str]) -> TempDir {
+        let dir = TempDir::new(&format!("discovery_{name}"));
+        for f in files {
+            std::fs::write(dir.join(f), b"").unwrap();
+        }
+        dir
+    }
+
+    fn err(input: &Path) -> String {
+        resolve_model_path(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn single_file_in_directory_with_tokenizer() {
+        let dir = temp_layout(
+            "single",
+            &["model.Q4_K_M.gguf", "README.md", "tokenizer.model"],
+        );
+        let resolved = resolve_model_path(&dir).unwrap();
+        assert_eq!(resolved.gguf_paths, vec![dir.join("model.Q4_K_M.gguf")]);
+        assert_eq!(resolved.tokenizer_path, Some(dir.join("tokenizer.model")));
+        assert!(!resolved.is_split());
+
+        // A direct file path resolves the same way.
+        assert_eq!(
+            resolve_model_path(dir.join("model.Q4_K_M.gguf")).unwrap(),
+            resolved
+        );
+    }
+
+    #[test]
+    fn tokenizer_json_preferred_and_tokenizer_optional() {
+        let dir = temp_layout(
+            "tokenizers",
+            &["m.gguf", "tokenizer.model", "tokenizer.json"],
+        );
+        let resolved = resolve_model_path(&dir).unwrap();
+        assert_eq!(resolved.tokenizer_path, Some(dir.join("tokenizer.json")));
+
+        let dir = temp_layout("no_tokenizer", &["m.gguf"]);
+        assert_eq!(resolve_model_path(&dir).unwrap().tokenizer_path, None);
+    }
+
+    #[test]
+    fn shards_resolve_to_ordered_set_from_dir_or_any_shard() {
+        let shards = [
+            "llama-00002-of-00003.gguf",
+            "llama-00001-of-00003.gguf",
+            "llama-00003-of-00003.gguf",
+        ];
+        let dir = temp_layout("shards", &shards);
+        let expected: Vec<PathBuf> = (1..=3)
+            .map(|i| dir.join(format!("llama-0000{i}-of-00003.gguf")))
+            .collect();
+        let resolved = resolve_model_path(&dir).unwrap();
+        assert_eq!(resolved.gguf_paths, expected);
+        assert_eq!(resolved.primary(), dir.join(shards[1]));
+        assert!(resolved.is_split());
+        assert_eq!(
+            resolve_model_path(dir.join(shards[0])).unwrap().gguf_paths,
+            expected
+        );
+    }
+
+    #[test]
+    fn incomplete_shard_set_names_missing_files() {
+        let dir = temp_layout(
+            "shards_missing",
+            &["x-00001-of-00003.gguf", "x-00003-of-00003.gguf"],
+        );
+        let e = err(&dir);
+        assert!(e.contains("incomplete"), "{e}");
+        assert!(e.contains("x-00002-of-00003.gguf"), "{e}");
+    }
+
+    #[test]
+    fn multiple_unrelated_models_are_ambiguous() {
+        let dir = temp_layout(
+            "ambiguous",
+            &[
+                "a.Q4_K_M.gguf",
+                "b.Q8_0.gguf",
+                "c-00001-of-00002.gguf",
+                "c-00002-of-00002.gguf",
+            ],
+        );
+        let e = err(&dir);
+        assert!(e.contains("matches 3 models"), "{e}");
+        assert!(e.contains("a.Q4_K_M.gguf"), "{e}");
+        assert!(e.contains("b.Q8_0.gguf"), "{e}");
+        assert!(e.contains("c-00001-of-00002.gguf (2 shards)"), "{e}");
+    }
+
+    #[test]
+    fn glob_narrows_ambiguous_directory() {
+        let dir = temp_layout("glob", &["a.Q4_K_M.gguf", "a.Q8_0.gguf", "notes.txt"]);
+        let resolved = resolve_model_path(dir.join("*Q8*")).unwrap();
+        assert_eq!(resolved.gguf_paths, vec![dir.join("a.Q8_0.gguf")]);
+        assert!(err(&dir.join("*.gguf")).contains("matches 2 models"));
+        assert!(err(&dir.join("*F16*")).contains("no .gguf files match"));
+    }
+
+    #[test]
+    fn empty_and_missing_paths_error() {
+        let dir = temp_layout("empty", &["README.md"]);
+        assert!(err(&dir).contains("no .gguf files in directory"));
+        assert!(err(&dir.join("absent.gguf")).contains("not found"));
+    }
+
+    #[test]
+    fn glob_matching_rules() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*Q4_K_M*", "mistral.Q4_K_M.gguf"));
+        assert!(glob_match("a?c.gguf", "abc.gguf"));
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(!glob_match("a?c.gguf", "ac.gguf"));
+        assert!(!glob_match("*.gguf", "model.bin"));
+    }
+
+    #[test]
+    fn split_name_requires_matching_widths_and_valid_index() {
+        assert!(split_name(Path::new("m-00001-of-00002.gguf")).is_some());
+        assert!(split_name(Path::new("m-1-of-00002.gguf")).is_none());
+        assert!(split_name(Path::new("m-00003-of-00002.gguf")).is_none());
+        assert!(split_name(Path::new("m-00001-of-00002.bin")).is_none());
+        assert!(split_name(Path::new("m.gguf")).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Per-role in-memory dtype overrides, applied as tensors are loaded.
+//!
+//! [`super::gguf_types::LoadOptions::role_dtype_overrides`] maps a [`WeightRole`] to the
+//! [`InMemoryDtype`] its tensors are held in, e.g. to keep a quantization-sensitive `ffn_down` in
+//! F32 while everything else stays as stored. Roles come from the ggml tensor name alone, so a
+//! tensor the model does not use still gets one. Every tensor whose dtype actually changed is
+//! listed in [`super::gguf_types::LoadStats::dtype_overrides`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+use crate::core::tensor::{Tensor, TensorType};
+
+/// What a weight tensor is for, by its ggml name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum WeightRole {
+    /// `token_embd`
+    TokenEmbedding,
+    /// `output` (LM head)
+    Output,
+    /// Any `*norm` tensor.
+    Norm,
+    AttnQ,
+    AttnK,
+    AttnV,
+    AttnOutput,
+    FfnGate,
+    FfnUp,
+    FfnDown,
+    /// Anything else (RoPE factors, per-layer embeddings, ...).
+    Other,
+}
+
+impl WeightRole {
+    /// Every role, in declaration order.
+    pub const ALL: [WeightRole; 11] = [
+        Self::TokenEmbedding,
+        Self::Output,
+        Self::Norm,
+        Self::AttnQ,
+        Self::AttnK,
+        Self::AttnV,
+        Self::AttnOutput,
+        Self::FfnGate,
+        Self::FfnUp,
+        Self::FfnDown,
+        Self::Other,
+    ];
+
+    /// Role of the tensor called `name`, e.g. `blk.3.ffn_down.weight` -> [`Self::FfnDown`].
+    pub fn of(name: &str) -> Self {
+        let base = name
+            .strip_suffix(".weight")
+            .or_else(|| name.strip_suffix(".bias"))
+            .unwrap_or(name);
+        let stem = base.rsplit('.').next().unwrap_or(base);
+        match stem {
+            "token_embd" => Self::TokenEmbedding,
+            "output" => Self::Output,
+            "attn_q" => Self::AttnQ,
+            "attn_k" => Self::AttnK,
+            "attn_v" => Self::AttnV,
+            "attn_output" => Self::AttnOutput,
+            "ffn_gate" => Self::FfnGate,
+            "ffn_up" => Self::FfnUp,
+            "ffn_down" => Self::FfnDown,
+            s if s.ends_with("norm") => Self::Norm,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// How a tensor is held in memory once loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InMemoryDtype {
+    /// As stored in the file.
+    #[default]
+    AsIs,
+    /// Dequantized to F32 (promoted).
+    F32,
+    /// int8 with one f16 scale per 32 weights, i.e. Q8_0: compacts F32 tensors, and requantizes
+    /// K-quants. Needs an element count that is a multiple of 32, and is refused for
+    /// [`WeightRole::Norm`] (see [`check_overrides`]).
+    F16Scales,
+}
+
+impl InMemoryDtype {
+    /// Dtype a tensor stored as `stored` ends up with.
+    pub fn resolve(self, stored: TensorType) -> TensorType {
+        match self {
+            Self::AsIs => stored,
+            Self::F32 => TensorType::F32,
+            Self::F16Scales => TensorType::Q8_0,
+        }
+    }
+}
+
+/// One tensor whose in-memory dtype differs from the file's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedOverride {
+    pub name: String,
+    pub role: WeightRole,
+    pub requested: InMemoryDtype,
+    /// Dtype in the file.
+    pub from: TensorType,
+    /// Dtype in memory.
+    pub to: TensorType,
+}
+
+impl fmt::Display for AppliedOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({:?}): {:?} -> {:?}",
+            self.name, self.role, self.from, self.to
+        )
+    }
+}
+
+/// Refuse overrides no load should apply: [`InMemoryDtype::F16Scales`] on
+/// [`WeightRole::Norm`]. Norms are read back as F32 slices by RMSNorm, and are too small for the
+/// saving to matter.
+pub fn check_overrides(overrides: &BTreeMap<WeightRole, InMemoryDtype>) -> Result<(), EngineError> {
+    match overrides.get(&WeightRole::Norm) {
+        Some(InMemoryDtype::F16Scales) => Err(EngineError::Model(
+            "dtype override F16Scales is not supported for Norm tensors; use AsIs or F32".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Convert `tensor` as `overrides` asks for its role. Returns the tensor unchanged, with no
+/// record, when the role has no override or the tensor already has the requested dtype.
+pub(crate) fn apply_override(
+    name: &str,
+    tensor: Tensor,
+    overrides: &BTreeMap<WeightRole, InMemoryDtype>,
+) -> Result<(Tensor, Option<AppliedOverride>), EngineError> {
+    let Some(applied) = planned_override(name, tensor.dtype(), overrides) else {
+        return Ok((tensor, None));
+    };
+    let converted = tensor.to_dtype(applied.to)?;
+    Ok((converted, Some(applied)))
+}
+
+/// What [`apply_override`] would record for a tensor called `name` held as `from`, without
+/// converting anything.
+pub(crate) fn planned_override(
+    name: &str,
+    from: TensorType,
+    overrides: &BTreeMap<WeightRole, InMemoryDtype>,
+) -> Option<AppliedOverride> {
+    let role = WeightRole::of(name);
+    let requested = overrides.get(&role).copied().unwrap_or_default();
+    let to = requested.resolve(from);
+    (to != from).then(|| AppliedOverride {
+        name: name.to_string(),
+        role,
+        requested,
+        from,
+        to,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_follow_ggml_names() {
+        let cases = [
+            ("token_embd.weight", WeightRole::TokenEmbedding),
+            ("output.weight", WeightRole::Output),
+            ("output_norm.weight", WeightRole::Norm),
+            ("blk.0.attn_q.weight", WeightRole::AttnQ),
+            ("blk.0.attn_q_norm.weight", WeightRole::Norm),
+            ("blk.1.attn_k.bias", WeightRole::AttnK),
+            ("blk.1.attn_v.weight", WeightRole::AttnV),
+            ("blk.2.attn_output.weight", WeightRole::AttnOutput),
+            ("blk.2.ffn_gate.weight", WeightRole::FfnGate),
+            ("blk.2.ffn_up.weight", WeightRole::FfnUp),
+            ("blk.31.ffn_down.weight", WeightRole::FfnDown),
+            ("rope_freqs.weight", WeightRole::Other),
+        ];
+        for (name, role) in cases {
+            assert_eq!(WeightRole::of(name), role, "{name}");
+        }
+        let mut all = WeightRole::ALL.to_vec();
+        all.dedup();
+        assert_eq!(all.len(), WeightRole::ALL.len());
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn f16_scales_norms_are_refused() {
+        let only = |role, dtype| BTreeMap::from([(role, dtype)]);
+        assert!(check_overrides(&only(WeightRole::Norm, InMemoryDtype::F16Scales)).is_err());
+        assert!(check_overrides(&only(WeightRole::Norm, InMemoryDtype::F32)).is_ok());
+        assert!(check_overrides(&only(WeightRole::FfnDown, InMemoryDtype::F16Scales)).is_ok());
+        assert!(check_overrides(&BTreeMap::new()).is_ok());
+    }
+
+    #[test]
+    fn resolve_keeps_or_replaces_the_stored_dtype() {
+        assert_eq!(
+            InMemoryDtype::AsIs.resolve(TensorType::Q4K),
+            TensorType::Q4K
+        );
+        assert_eq!(InMemoryDtype::F32.resolve(TensorType::Q6K), TensorType::F32);
+        assert_eq!(
+            InMemoryDtype::F16Scales.resolve(TensorType::F32),
+            TensorType::Q8_0
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Cursor, Seek};
 
 use crate::EngineError;
-use crate::model_loader::gguf_types::{Data, GGUFData};
+use crate::model_loader::gguf_types::{Data, GGUFData, SizeLimits};
 use crate::model_loader::reader::Reader;
 
 use super::parser::*;
@@ -23,10 +23,50 @@ fn tensor_data_section_offset(
 
 /// Read GGUF file metadata and return GGUFData structure
 /// Note: This only reads metadata, not tensor data. Call load_tensors() to load actual tensor weights.
+///
+/// Tensor sizes are checked against [`SizeLimits::from_env`].
 pub fn read_file(path: &str) -> Result<GGUFData, EngineError> {
-    let file = File::open(path)?;
-    let mut reader = Reader::new(BufReader::new(file), 0);
+    read_file_with_limits(path, &SizeLimits::from_env())
+}
+
+/// [`read_file`] with explicit tensor size ceilings.
+pub fn read_file_with_limits(path: &str, limits: &SizeLimits) -> Result<GGUFData, EngineError> {
+    File::open(path)
+        .map_err(EngineError::from)
+        .and_then(|file| read_header(&mut Reader::new(BufReader::new(file), 0), limits, path))
+        .inspect_err(|e| log::error!("{path}: reading GGUF header failed: {e}"))
+}
+
+/// [`read_file`] for a GGUF file already in memory (received over the network, embedded, or
+/// loaded in a browser): no file system needed. Load its tensors from the same buffer with
+/// [`GGUFData::load_tensors_from_bytes`].
+pub fn read_from_bytes(bytes: &[u8]) -> Result<GGUFData, EngineError> {
+    read_from_bytes_with_limits(bytes, &SizeLimits::from_env())
+}
+
+/// [`read_from_bytes`] with explicit tensor size ceilings.
+pub fn read_from_bytes_with_limits(
+    bytes: &[u8],
+    limits: &SizeLimits,
+) -> Result<GGUFData, EngineError> {
+    read_header(&mut Reader::new(Cursor::new(bytes), 0), limits, "<memory>")
+        .inspect_err(|e| log::error!("<memory>: reading GGUF header failed: {e}"))
+}
 
+/// Parse a GGUF header held in memory, for the `gguf_header` fuzz target (`fuzz/`).
+#[cfg(feature = "fuzzing")]
+pub fn parse_header_bytes(bytes: &[u8]) -> Result<GGUFData, EngineError> {
+    let mut reader = Reader::new(Cursor::new(bytes), 0);
+    read_header(&mut reader, &SizeLimits::default(), "<memory>")
+}
+
+/// Header, KV metadata and tensor table from the start of `reader`; `source` names the input in
+/// warnings.
+fn read_header<R: BufRead + Seek>(
+    reader: &mut Reader<R>,
+    limits: &SizeLimits,
+    source: &str,
+) -> Result<GGUFData, EngineError> {
     // GGUF Header is 4 bytes, so u32
     let _header: String = String::from_utf8(reader.read_bytes(4)?)?;
 
@@ -43,12 +83,17 @@ pub fn read_file(path: &str) -> Result<GGUFData, EngineError> {
     log::debug!("GGUF metadata count: {metadata_count}");
 
     // Read metadata tree
-    let kv = get_kv_metadata(&mut reader, metadata_count)?;
+    let (kv, duplicate_keys) = get_kv_metadata_checked(reader, metadata_count)?;
+    for dup in &duplicate_keys {
+        log::warn!("{source}: {dup}");
+    }
     //println!("Metadata: {:?}", kv);
 
     // Read tensors metadata
-    let tensors_metadata = get_tensors_metadata(&mut reader, tensor_count)?;
+    let (tensors_metadata, tensor_names) = get_tensors_metadata(reader, tensor_count)?;
     log::debug!("GGUF tensors metadata: {} tensors", tensors_metadata.len());
+    let tensor_bytes = limits.check(&tensors_metadata, &tensor_names)?;
+    log::debug!("GGUF tensor data: {tensor_bytes} bytes");
 
     // GGUF: tensor offsets are relative to the aligned start of the tensor data blob (see gguf.cpp).
     let tensor_data_offset = tensor_data_section_offset(&kv, reader.position());
@@ -59,8 +104,10 @@ pub fn read_file(path: &str) -> Result<GGUFData, EngineError> {
         metadata_count,
         kv,
         tensors_metadata,
+        tensor_names,
         tensor_data_offset,
-    );
+    )
+    .with_duplicate_keys(duplicate_keys);
     Ok(loaded_data)
 }
 
@@ -76,6 +123,13 @@ mod test {
         assert!(result.is_ok(), "Failed to read file: {:?}", result.err());
     }
 
+    #[test]
+    #[ignore = "requires ./model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf (cargo test -- --ignored)"]
+    fn test_quant_scheme_name() {
+        let gguf_data = read_file("./model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf").unwrap();
+        assert_eq!(gguf_data.quant_scheme_name(), "Q4_K_M");
+    }
+
     #[test]
     #[ignore = "requires ./model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf (cargo test -- --ignored)"]
     fn test_load_tensors() {
@@ -114,9 +168,8 @@ mod test {
 
         // Find a small F32 tensor (norm weights are small - 4096 elements)
         let tensor_info = gguf_data
-            .tensors_metadata()
-            .iter()
-            .find(|t| t.name == "blk.0.attn_norm.weight" && t.type_id == 0)
+            .tensor_info("blk.0.attn_norm.weight")
+            .filter(|t| t.type_id == 0)
             .expect("Should have blk.0.attn_norm.weight tensor");
 
         // Load just this one tensor manually
@@ -1,7 +1,22 @@
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::EngineError;
+use crate::core::stats::StatsReport;
 use crate::core::tensor::Tensor;
+use crate::core::time::Instant;
+use crate::model_loader::dtype_overrides::{
+    AppliedOverride, InMemoryDtype, WeightRole, apply_override, check_overrides,
+};
+use crate::model_loader::interner::{StringInterner, Symbol};
+use crate::model_loader::storage::{Advice, Advisor, SystemAdvisor};
+use crate::model_loader::tensor::GgmlType;
+use crate::model_loader::weight_layout::{apply_layout, check_layout_roles};
 
 #[derive(Debug, Clone)]
 pub enum Data {
@@ -20,7 +35,7 @@ pub enum Data {
     Float64(f64),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
     Uint8,
     Int8,
@@ -44,13 +59,474 @@ pub struct ReadingInfo {
 // Struct to define the metadata from the GGUF file, describing where all the tensor information is
 #[derive(Debug, Clone)]
 pub struct TensorInfo {
-    pub name: String,
+    /// Interned in the owning [`GGUFData::tensor_names`]; see [`GGUFData::tensor_name`].
+    pub name: Symbol,
     pub n_dimensions: usize,
     pub dimensions: Vec<usize>,
     pub type_id: u32,
     pub offset: usize,
 }
 
+impl TensorInfo {
+    /// Product of the dimensions; [`EngineError::Overflow`] if it does not fit `usize`.
+    pub fn num_elements(&self) -> Result<usize, EngineError> {
+        self.dimensions.iter().try_fold(1usize, |acc, &d| {
+            acc.checked_mul(d).ok_or_else(|| {
+                EngineError::Overflow(format!("element count of {:?}", self.dimensions))
+            })
+        })
+    }
+
+    /// Bytes the tensor occupies in the file (whole blocks for quantized types).
+    pub fn byte_size(&self) -> Result<usize, EngineError> {
+        let ggml_type = GgmlType::try_from(self.type_id)?;
+        let (block_elements, block_bytes) = ggml_type
+            .block_layout()
+            .ok_or_else(|| EngineError::Tensor(format!("no size rule for {ggml_type:?}")))?;
+        self.num_elements()?
+            .div_ceil(block_elements)
+            .checked_mul(block_bytes)
+            .ok_or_else(|| {
+                EngineError::Overflow(format!(
+                    "byte size of {:?} as {ggml_type:?}",
+                    self.dimensions
+                ))
+            })
+    }
+}
+
+/// Name a quantization scheme from `(tensor name, ggml type id, dims)` the way llama.cpp's
+/// quantizer names its presets. The base type is the one holding most block-weight (2-D
+/// `blk.*`) elements; the embedding and output matrices are left out since every preset
+/// quantizes them its own way. The `_S` / `_M` / `_L` variants of the K-quants differ only in
+/// which block weights are bumped to a larger type:
+/// - Q4_K and Q5_K: `_M` stores some `attn_v` / `ffn_down` weights as Q6_K, `_S` none;
+/// - Q3_K: `_L` stores `attn_output` as Q5_K, `_M` stores some `attn_v` as Q4_K or Q5_K, `_S`
+///   neither.
+///
+/// Other base types are named after the type itself. `None` without 2-D block weights.
+pub fn quant_scheme_from_mix<'a>(
+    tensors: impl IntoIterator<Item = (&'a str, u32, &'a [usize])>,
+) -> Option<String> {
+    let mut elements_by_type: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut weights: Vec<(&str, GgmlType)> = Vec::new();
+    for (name, type_id, dims) in tensors {
+        let Some(weight) = name.strip_prefix("blk.") else {
+            continue;
+        };
+        let Ok(ggml_type) = GgmlType::try_from(type_id) else {
+            continue;
+        };
+        if dims.len() < 2 {
+            continue;
+        }
+        let elements = dims.iter().map(|&d| d as u64).product::<u64>();
+        *elements_by_type.entry(type_id).or_default() += elements;
+        weights.push((weight, ggml_type));
+    }
+    let (&base_id, _) = elements_by_type.iter().max_by_key(|&(_, &n)| n)?;
+    let base = GgmlType::try_from(base_id).ok()?;
+    let any = |tensor: &str, types: &[GgmlType]| {
+        weights
+            .iter()
+            .any(|(name, t)| name.contains(tensor) && types.contains(t))
+    };
+    let name = match base {
+        GgmlType::Q4_K | GgmlType::Q5_K => {
+            let size = if any("attn_v.", &[GgmlType::Q6_K]) || any("ffn_down.", &[GgmlType::Q6_K]) {
+                "M"
+            } else {
+                "S"
+            };
+            format!("{base:?}_{size}")
+        }
+        GgmlType::Q3_K => {
+            let size = if any("attn_output.", &[GgmlType::Q5_K]) {
+                "L"
+            } else if any("attn_v.", &[GgmlType::Q4_K, GgmlType::Q5_K]) {
+                "M"
+            } else {
+                "S"
+            };
+            format!("Q3_K_{size}")
+        }
+        other => format!("{other:?}"),
+    };
+    Some(name)
+}
+
+/// Ceilings checked against the tensor table before any tensor data is read, so a corrupted or
+/// hostile header cannot trigger a giant allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    pub max_tensor_bytes: usize,
+    pub max_total_bytes: usize,
+}
+
+impl Default for SizeLimits {
+    /// 32 GiB per tensor, 1 TiB per model: far above any real checkpoint, far below `usize::MAX`
+    /// (on 32-bit targets such as wasm32, both are `usize::MAX`).
+    fn default() -> Self {
+        let cap = |bytes: u64| usize::try_from(bytes).unwrap_or(usize::MAX);
+        Self {
+            max_tensor_bytes: cap(32 << 30),
+            max_total_bytes: cap(1 << 40),
+        }
+    }
+}
+
+impl SizeLimits {
+    /// Defaults, overridden by `INFERENCE_ENGINE_MAX_TENSOR_BYTES` / `INFERENCE_ENGINE_MAX_MODEL_BYTES`.
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok().and_then(|v| v.trim().parse().ok());
+        let default = Self::default();
+        Self {
+            max_tensor_bytes: var("INFERENCE_ENGINE_MAX_TENSOR_BYTES")
+                .unwrap_or(default.max_tensor_bytes),
+            max_total_bytes: var("INFERENCE_ENGINE_MAX_MODEL_BYTES")
+                .unwrap_or(default.max_total_bytes),
+        }
+    }
+
+    /// Check every tensor (and their sum) against the limits; returns the total byte size.
+    ///
+    /// Types without a size rule only get their element count checked; they cannot be loaded
+    /// anyway.
+    pub fn check(
+        &self,
+        tensors: &[TensorInfo],
+        names: &StringInterner,
+    ) -> Result<usize, EngineError> {
+        let mut total = 0usize;
+        for info in tensors {
+            let name = names.resolve(info.name);
+            let elements = info.num_elements().map_err(|e| with_tensor_name(e, name))?;
+            let bytes = match GgmlType::try_from(info.type_id)
+                .ok()
+                .and_then(GgmlType::block_layout)
+            {
+                Some(_) => info.byte_size().map_err(|e| with_tensor_name(e, name))?,
+                None => {
+                    log::debug!("tensor {name}: {elements} elements of unsized type");
+                    continue;
+                }
+            };
+            if bytes > self.max_tensor_bytes {
+                return Err(EngineError::Gguf(format!(
+                    "tensor {name}: {bytes} bytes exceeds per-tensor limit {}",
+                    self.max_tensor_bytes
+                )));
+            }
+            total = total
+                .checked_add(bytes)
+                .ok_or_else(|| EngineError::Overflow(format!("total tensor bytes at {name}")))?;
+            if total > self.max_total_bytes {
+                return Err(EngineError::Gguf(format!(
+                    "tensor data exceeds model limit {} bytes (at {name})",
+                    self.max_total_bytes
+                )));
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// Ceilings on the header's strings and (possibly nested) arrays, checked by the
+/// [`Reader`](crate::model_loader::reader::Reader) before it allocates for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataLimits {
+    /// Arrays of arrays deeper than this are rejected; real files nest at most once.
+    pub max_array_depth: usize,
+    /// Elements in one array (the largest real ones are vocabularies and merge lists).
+    pub max_array_len: u64,
+    /// Bytes in one string (keys, values, tensor names).
+    pub max_string_bytes: u64,
+    /// Decoded bytes held across all strings and array elements read so far.
+    pub max_metadata_bytes: u64,
+}
+
+impl Default for MetadataLimits {
+    /// Room for a 16M-entry vocabulary and a 16 MiB chat template, 1 GiB of decoded metadata.
+    fn default() -> Self {
+        Self {
+            max_array_depth: 8,
+            max_array_len: 1 << 24,
+            max_string_bytes: 16 << 20,
+            max_metadata_bytes: 1 << 30,
+        }
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MetadataLimitError {
+    #[error("array at offset {offset} nested {depth} deep exceeds max depth {max}")]
+    ArrayTooDeep {
+        offset: u64,
+        depth: usize,
+        max: usize,
+    },
+    #[error("array at offset {offset} declares {len} elements, max {max}")]
+    ArrayTooLong { offset: u64, len: u64, max: u64 },
+    #[error(
+        "array at offset {offset} declares {len} elements needing at least {min_bytes} bytes, only {remaining} remain"
+    )]
+    ArrayPastEnd {
+        offset: u64,
+        len: u64,
+        min_bytes: u64,
+        remaining: u64,
+    },
+    #[error("string at offset {offset} declares {len} bytes, max {max}")]
+    StringTooLong { offset: u64, len: u64, max: u64 },
+    #[error("metadata at offset {offset} exceeds the {max}-byte budget")]
+    BudgetExceeded { offset: u64, max: u64 },
+}
+
+/// Prefix a per-tensor error (from [`TensorInfo`] methods, which only know the symbol) with the
+/// tensor's name, keeping the variant.
+fn with_tensor_name(e: EngineError, name: &str) -> EngineError {
+    match e {
+        EngineError::Overflow(m) => EngineError::Overflow(format!("tensor {name}: {m}")),
+        EngineError::Tensor(m) => EngineError::Tensor(format!("tensor {name}: {m}")),
+        EngineError::Gguf(m) => EngineError::Gguf(format!("tensor {name}: {m}")),
+        other => other,
+    }
+}
+
+/// How [`GGUFData`] reads tensor data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadOptions {
+    /// Send readahead hints ([`crate::model_loader::storage`]) while loading. Harmless where
+    /// unsupported; turn off to measure the device without them.
+    pub prefetch: bool,
+    /// In-memory dtype per weight role ([`crate::model_loader::dtype_overrides`]); roles not
+    /// listed stay as stored. Applies to the `_with` loaders.
+    #[serde(default)]
+    pub role_dtype_overrides: BTreeMap<WeightRole, InMemoryDtype>,
+    /// Dequantize every weight to F32 at load, so every matmul takes the F32 path; overrides
+    /// [`Self::role_dtype_overrides`]. For separating quantization error from kernel error (see
+    /// [`crate::compare::compare_weight_precision`]); costs 4 bytes per weight.
+    #[serde(default)]
+    pub force_f32_weights: bool,
+    /// Share weights between processes ([`crate::model_loader::sidecar`]): map the GGUF
+    /// read-only instead of copying tensors kept as stored, and keep converted tensors in a
+    /// sidecar file next to the model for later loads to map. Applies to the `_with` loaders;
+    /// off by default, since it writes next to the model.
+    #[serde(default)]
+    pub shared_cache: bool,
+    /// Roles whose quantized weights are reordered at load into
+    /// [`WeightLayout::ColumnTiles`](crate::core::tensor::WeightLayout::ColumnTiles) for faster
+    /// decode matmul ([`crate::model_loader::weight_layout`]). Only the attention and FFN
+    /// projections can be listed; empty by default. Applies to the `_with` loaders.
+    #[serde(default)]
+    pub optimize_layout: BTreeSet<WeightRole>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            prefetch: true,
+            role_dtype_overrides: BTreeMap::new(),
+            force_f32_weights: false,
+            shared_cache: false,
+            optimize_layout: BTreeSet::new(),
+        }
+    }
+}
+
+impl LoadOptions {
+    /// The per-role dtypes a load applies: [`Self::role_dtype_overrides`], or every role to F32
+    /// with [`Self::force_f32_weights`].
+    pub fn effective_overrides(&self) -> Cow<'_, BTreeMap<WeightRole, InMemoryDtype>> {
+        if self.force_f32_weights {
+            Cow::Owned(
+                WeightRole::ALL
+                    .into_iter()
+                    .map(|role| (role, InMemoryDtype::F32))
+                    .collect(),
+            )
+        } else {
+            Cow::Borrowed(&self.role_dtype_overrides)
+        }
+    }
+
+    fn advisor(&self) -> Option<&'static dyn Advisor> {
+        self.prefetch.then_some(&SystemAdvisor as &dyn Advisor)
+    }
+}
+
+/// What one load call read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Tensors read by this call (already-loaded ones are skipped).
+    pub tensors_loaded: usize,
+    /// Tensor data bytes read from the file.
+    pub bytes_read: u64,
+    /// At least one readahead hint was accepted by the OS. `false` when prefetch is off, on
+    /// platforms without a readahead API, or when every hint failed.
+    pub hints_applied: bool,
+    pub hints_issued: usize,
+    /// Hints the OS rejected; the load carried on without them.
+    pub hints_failed: usize,
+    /// Tensors converted by [`LoadOptions::role_dtype_overrides`], in load order.
+    pub dtype_overrides: Vec<AppliedOverride>,
+    /// Wall time of the whole call, file open to last insert.
+    pub elapsed: Duration,
+    /// Where [`Self::elapsed`] went, summed over every tensor; the phases add up to it.
+    pub timing: LoadTiming,
+    /// [`Self::timing`] split by each tensor's type in the file.
+    pub timing_by_type: BTreeMap<GgmlType, LoadTiming>,
+    /// With [`LoadOptions::shared_cache`]: tensors kept as stored, viewed in the mapped GGUF
+    /// rather than read.
+    pub tensors_mapped: usize,
+    /// With [`LoadOptions::shared_cache`]: converted tensors mapped from the sidecar instead of
+    /// converted by this process.
+    pub sidecar_hits: usize,
+    /// This call converted tensors the sidecar lacked and wrote a new one. A failed write is
+    /// logged and leaves this `false`; the load itself still succeeds.
+    pub sidecar_written: bool,
+}
+
+/// Wall time a load spent per phase. Measured with a couple of `Instant` reads per tensor, so
+/// the phases are exact sums of per-tensor intervals, never per-element samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadTiming {
+    pub tensors: usize,
+    /// Opening, seeking and reading the file, including readahead hints.
+    pub read: Duration,
+    /// Widening BF16 and dtype overrides (dequantizing or requantizing). Quantized tensors kept
+    /// as stored spend nothing here: their blocks are unpacked at matmul time.
+    pub convert: Duration,
+    /// Allocating tensor buffers and inserting tensors into the map.
+    pub alloc: Duration,
+    pub bytes_read: u64,
+    /// Elements that went through [`Self::convert`].
+    pub elements_converted: u64,
+}
+
+impl LoadTiming {
+    pub fn total(&self) -> Duration {
+        self.read + self.convert + self.alloc
+    }
+
+    /// Read throughput in MB/s (10^6 bytes); `None` if no read time was measured.
+    pub fn read_mb_per_s(&self) -> Option<f64> {
+        per_second(self.bytes_read, self.read).map(|b| b / 1e6)
+    }
+
+    /// Conversion throughput in millions of elements per second; `None` if nothing was converted.
+    pub fn convert_melem_per_s(&self) -> Option<f64> {
+        per_second(self.elements_converted, self.convert).map(|e| e / 1e6)
+    }
+
+    fn add(&mut self, other: &LoadTiming) {
+        self.tensors += other.tensors;
+        self.read += other.read;
+        self.convert += other.convert;
+        self.alloc += other.alloc;
+        self.bytes_read += other.bytes_read;
+        self.elements_converted += other.elements_converted;
+    }
+}
+
+fn per_second(count: u64, time: Duration) -> Option<f64> {
+    (count > 0 && !time.is_zero()).then(|| count as f64 / time.as_secs_f64())
+}
+
+impl std::fmt::Display for LoadTiming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1e3;
+        write!(
+            f,
+            "{:>4} tensors  read {:>9.1} ms",
+            self.tensors,
+            ms(self.read)
+        )?;
+        match self.read_mb_per_s() {
+            Some(rate) => write!(f, " ({rate:>8.1} MB/s)")?,
+            None => write!(f, " {:>13}", "")?,
+        }
+        write!(f, "  convert {:>9.1} ms", ms(self.convert))?;
+        match self.convert_melem_per_s() {
+            Some(rate) => write!(f, " ({rate:>8.1} Melem/s)")?,
+            None => write!(f, " {:>16}", "")?,
+        }
+        write!(f, "  alloc {:>7.1} ms", ms(self.alloc))
+    }
+}
+
+impl LoadStats {
+    /// Multi-line phase breakdown: the total, then one line per tensor type.
+    pub fn timing_report(&self) -> String {
+        let mut out = format!(
+            "loaded in {:.1} ms\n  {:<6} {}\n",
+            self.elapsed.as_secs_f64() * 1e3,
+            "all",
+            self.timing
+        );
+        for (ty, timing) in &self.timing_by_type {
+            out.push_str(&format!("  {:<6} {timing}\n", format!("{ty:?}")));
+        }
+        out
+    }
+
+    /// Fold in the stats of another load call, e.g. for the next shard of a split set.
+    pub fn add(&mut self, other: LoadStats) {
+        self.tensors_loaded += other.tensors_loaded;
+        self.bytes_read += other.bytes_read;
+        self.hints_applied |= other.hints_applied;
+        self.hints_issued += other.hints_issued;
+        self.hints_failed += other.hints_failed;
+        self.dtype_overrides.extend(other.dtype_overrides);
+        self.elapsed += other.elapsed;
+        self.timing.add(&other.timing);
+        for (ty, timing) in &other.timing_by_type {
+            self.timing_by_type.entry(*ty).or_default().add(timing);
+        }
+        self.tensors_mapped += other.tensors_mapped;
+        self.sidecar_hits += other.sidecar_hits;
+        self.sidecar_written |= other.sidecar_written;
+    }
+}
+
+/// A metadata key that occurs more than once in the KV section. Parsing keeps the **last** value
+/// (as llama.cpp does), but a repeat usually means a broken converter or a hand edit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey {
+    pub key: String,
+    /// File offset where each occurrence starts, in file order.
+    pub offsets: Vec<u64>,
+}
+
+impl std::fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "duplicate metadata key '{}' at byte offsets {:?} (last value wins)",
+            self.key, self.offsets
+        )
+    }
+}
+
+/// Where [`GGUFData`] reads tensor bytes from.
+#[derive(Debug, Clone, Copy)]
+enum TensorInput<'a> {
+    /// A GGUF file on disk.
+    File(&'a str),
+    /// A whole GGUF file already in memory.
+    Bytes(&'a [u8]),
+}
+
+impl TensorInput<'_> {
+    /// Names the input in log lines.
+    fn name(&self) -> &str {
+        match self {
+            Self::File(path) => path,
+            Self::Bytes(_) => "<memory>",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GGUFData {
     version: u32,
@@ -61,9 +537,14 @@ pub struct GGUFData {
     tensor_data_offset: u64,
     /// Tensor metadata (offsets, type_ids) - used during loading process
     tensors_metadata: Vec<TensorInfo>,
+    /// Every tensor name, interned in table order, so `Symbol::index` is also the entry's index
+    /// in `tensors_metadata` (duplicate names are rejected by the parser).
+    tensor_names: StringInterner,
     /// Loaded tensors: HashMap keyed by tensor name
     /// Populated during tensor loading phase
-    tensors: HashMap<String, Tensor>,
+    tensors: HashMap<Symbol, Tensor>,
+    /// Repeated KV keys found while parsing.
+    duplicate_keys: Vec<DuplicateKey>,
 }
 
 impl GGUFData {
@@ -73,8 +554,16 @@ impl GGUFData {
         nb_key_vals: u64,
         kv: BTreeMap<String, Data>,
         tensors_metadata: Vec<TensorInfo>,
+        tensor_names: StringInterner,
         tensor_data_offset: u64,
     ) -> Self {
+        debug_assert!(
+            tensors_metadata
+                .iter()
+                .enumerate()
+                .all(|(i, t)| t.name.index() == i),
+            "tensor names must be interned in table order"
+        );
         Self {
             version,
             nb_tensors,
@@ -82,69 +571,178 @@ impl GGUFData {
             kv,
             tensor_data_offset,
             tensors_metadata,
+            tensor_names,
             tensors: HashMap::new(),
+            duplicate_keys: Vec::new(),
         }
     }
 
+    /// Record repeated metadata keys (see [`Self::duplicate_keys`]).
+    pub fn with_duplicate_keys(mut self, duplicate_keys: Vec<DuplicateKey>) -> Self {
+        self.duplicate_keys = duplicate_keys;
+        self
+    }
+
+    /// Metadata keys that appeared more than once; empty for well-formed files.
+    pub fn duplicate_keys(&self) -> &[DuplicateKey] {
+        &self.duplicate_keys
+    }
+
     /// Byte offset in the GGUF file where tensor data begins (after metadata + tensor info table).
     pub fn tensor_data_offset(&self) -> u64 {
         self.tensor_data_offset
     }
 
     /// Load all tensors from the GGUF file
-    /// Opens the file, reads tensor data based on tensors_metadata, and populates the tensors HashMap
-    /// Uses a larger buffer (1MB) for better I/O performance
+    /// Opens the file once and reads every tensor in on-disk order through a 1MB buffer, with
+    /// readahead hints enabled (see [`LoadOptions`])
     pub fn load_tensors(&mut self, file_path: &str) -> Result<(), EngineError> {
-        use crate::model_loader::tensor_loader::load_tensor;
-        use log::info;
-        use std::fs::File;
-        use std::io::BufReader;
+        self.load_tensors_with(file_path, &LoadOptions::default())
+            .map(|_| ())
+    }
 
-        let file = File::open(file_path)?;
-        // For random access (seeking to different tensor offsets), use File directly
-        // BufReader is optimized for sequential reads and can cause buffer invalidation issues
-        // when seeking frequently. We wrap it in BufReader only for the Reader abstraction.
-        // Note: For truly random access, File is more appropriate, but Reader expects BufRead + Seek
-        let buf_reader = BufReader::with_capacity(1024 * 1024, file);
-        let mut reader = crate::model_loader::reader::Reader::new(buf_reader, 0);
+    /// [`Self::load_tensors`] with explicit options; returns what was read and whether the OS
+    /// accepted the readahead hints.
+    pub fn load_tensors_with(
+        &mut self,
+        file_path: &str,
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        use log::{error, info};
 
         let total_tensors = self.tensors_metadata.len();
-        info!("Starting to load {total_tensors} tensors...");
-
-        for (idx, tensor_info) in self.tensors_metadata.iter().enumerate() {
-            let progress = ((idx + 1) * 100) / total_tensors;
-            info!(
-                "Loading tensor {}/{} ({}%): {} (offset: {}, type_id: {})",
-                idx + 1,
-                total_tensors,
-                progress,
-                tensor_info.name,
-                tensor_info.offset,
-                tensor_info.type_id
-            );
+        info!("{file_path}: loading {total_tensors} tensors");
+        let stats = self
+            .load_entries(
+                TensorInput::File(file_path),
+                (0..total_tensors).collect(),
+                options.advisor(),
+                &options.effective_overrides(),
+                options.shared_cache,
+                &options.optimize_layout,
+            )
+            .inspect_err(|e| error!("{file_path}: loading tensors failed: {e}"))?;
+        info!(
+            "{file_path}: loaded {total_tensors} tensors ({} bytes read, readahead hints {})",
+            stats.bytes_read,
+            if stats.hints_applied {
+                "applied"
+            } else {
+                "not applied"
+            }
+        );
+        Ok(stats)
+    }
 
-            let tensor =
-                load_tensor(&mut reader, tensor_info, self.tensor_data_offset).map_err(|e| {
-                    EngineError::Gguf(format!(
-                        "tensor {}/{} '{}' (offset {}, type_id {}): {}",
-                        idx + 1,
-                        total_tensors,
-                        tensor_info.name,
-                        tensor_info.offset,
-                        tensor_info.type_id,
-                        e
-                    ))
+    /// Join the shards of a split GGUF set (llama.cpp `gguf-split`), given in shard order with
+    /// every tensor already loaded, into one: the first shard's metadata, and the tensor tables
+    /// and tensors of all shards in order. The `split.*` keys are checked where present. Tensor
+    /// offsets stay relative to their own shard, so the result has no file to load more from.
+    pub fn merge_split(shards: Vec<GGUFData>) -> Result<GGUFData, EngineError> {
+        let count = shards.len();
+        let split_key = |shard: &GGUFData, key: &str| match shard.kv.get(key) {
+            Some(Data::Uint16(v)) => Some(u64::from(*v)),
+            Some(Data::Uint32(v)) => Some(u64::from(*v)),
+            Some(Data::Int32(v)) => u64::try_from(*v).ok(),
+            _ => None,
+        };
+        let mut shards = shards.into_iter();
+        let first = shards
+            .next()
+            .ok_or_else(|| EngineError::Model("split GGUF set has no shards".into()))?;
+        let mut merged = GGUFData::new(
+            first.version,
+            0,
+            first.nb_key_vals,
+            first.kv.clone(),
+            Vec::new(),
+            StringInterner::new(),
+            0,
+        );
+        for (no, mut shard) in std::iter::once(first).chain(shards).enumerate() {
+            if let Some(declared) = split_key(&shard, "split.count")
+                && declared != count as u64
+            {
+                return Err(EngineError::Model(format!(
+                    "shard {no} declares split.count {declared}, but {count} shards were given"
+                )));
+            }
+            if let Some(declared) = split_key(&shard, "split.no")
+                && declared != no as u64
+            {
+                return Err(EngineError::Model(format!(
+                    "shard {no} declares split.no {declared}; shards are out of order"
+                )));
+            }
+            for info in std::mem::take(&mut shard.tensors_metadata) {
+                let name = shard.tensor_names.resolve(info.name);
+                let tensor = shard.tensors.remove(&info.name).ok_or_else(|| {
+                    EngineError::Model(format!("shard {no}: tensor '{name}' is not loaded"))
                 })?;
-            self.tensors.insert(tensor_info.name.clone(), tensor);
+                if merged.tensor_names.get(name).is_some() {
+                    return Err(EngineError::Model(format!(
+                        "tensor '{name}' appears in more than one shard"
+                    )));
+                }
+                let symbol = merged.tensor_names.intern(name)?;
+                merged.tensors.insert(symbol, tensor);
+                merged.tensors_metadata.push(TensorInfo {
+                    name: symbol,
+                    ..info
+                });
+            }
         }
-
-        info!("Successfully loaded all {total_tensors} tensors");
-        Ok(())
+        merged.nb_tensors = merged.tensors_metadata.len() as u64;
+        if let Some(declared) = split_key(&merged, "split.tensors.count")
+            && declared != merged.nb_tensors
+        {
+            return Err(EngineError::Model(format!(
+                "split GGUF declares {declared} tensors, but its shards hold {}",
+                merged.nb_tensors
+            )));
+        }
+        Ok(merged)
     }
 
     /// Get a tensor by name (only if already loaded)
     pub fn get_tensor(&self, name: &str) -> Option<&Tensor> {
-        self.tensors.get(name)
+        self.get_tensor_by_symbol(self.tensor_symbol(name)?)
+    }
+
+    /// [`Self::get_tensor`] for a name resolved once with [`Self::tensor_symbol`]: an integer
+    /// map hit, with no string hashing.
+    pub fn get_tensor_by_symbol(&self, name: Symbol) -> Option<&Tensor> {
+        self.tensors.get(&name)
+    }
+
+    /// Every loaded tensor with its name, in table (file) order, e.g. to dump, checksum or
+    /// validate them all. Tensors listed but not loaded are skipped.
+    pub fn loaded_tensors(&self) -> impl Iterator<Item = (&str, &Tensor)> {
+        self.tensors_metadata.iter().filter_map(|t| {
+            let tensor = self.tensors.get(&t.name)?;
+            Some((self.tensor_names.resolve(t.name), tensor))
+        })
+    }
+
+    /// Symbol of a tensor listed in the metadata table.
+    pub fn tensor_symbol(&self, name: &str) -> Option<Symbol> {
+        self.tensor_names.get(name)
+    }
+
+    /// Name of a tensor from this file's table.
+    pub fn tensor_name(&self, info: &TensorInfo) -> &str {
+        self.tensor_names.resolve(info.name)
+    }
+
+    /// All tensor names, interned in table order.
+    pub fn tensor_names(&self) -> &StringInterner {
+        &self.tensor_names
+    }
+
+    /// Metadata for a tensor by name, without scanning the table.
+    pub fn tensor_info(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensor_symbol(name)
+            .and_then(|sym| self.tensors_metadata.get(sym.index()))
     }
 
     /// Load a single tensor by name without loading all tensors
@@ -166,18 +764,14 @@ impl GGUFData {
         use std::io::BufReader;
 
         // Find the tensor in metadata
-        let tensor_info = self
-            .tensors_metadata
-            .iter()
-            .find(|t| t.name == tensor_name)
-            .ok_or_else(|| {
-                EngineError::Model(format!(
-                    "tensor '{tensor_name}' not found in model metadata"
-                ))
-            })?;
+        let tensor_info = self.tensor_info(tensor_name).ok_or_else(|| {
+            EngineError::Model(format!(
+                "tensor '{tensor_name}' not found in model metadata"
+            ))
+        })?;
 
         // Check if already loaded
-        if self.tensors.contains_key(tensor_name) {
+        if self.tensors.contains_key(&tensor_info.name) {
             return Ok(()); // Already loaded, nothing to do
         }
 
@@ -186,8 +780,9 @@ impl GGUFData {
         let buf_reader = BufReader::with_capacity(1024 * 1024, file);
         let mut reader = crate::model_loader::reader::Reader::new(buf_reader, 0);
 
-        let tensor = load_tensor(&mut reader, tensor_info, self.tensor_data_offset)?;
-        self.tensors.insert(tensor_name.to_string(), tensor);
+        let tensor = load_tensor(&mut reader, tensor_info, self.tensor_data_offset)
+            .map_err(|e| with_tensor_name(e, tensor_name))?;
+        self.tensors.insert(tensor_info.name, tensor);
 
         Ok(())
     }
@@ -202,42 +797,401 @@ impl GGUFData {
         file_path: &str,
         tensor_names: &[String],
     ) -> Result<(), EngineError> {
-        use crate::model_loader::tensor_loader::load_tensor;
-        use std::fs::File;
-        use std::io::BufReader;
+        self.load_named_tensors_with(file_path, tensor_names, &LoadOptions::default())
+            .map(|_| ())
+    }
 
-        let mut indices: Vec<usize> = Vec::new();
-        for name in tensor_names {
-            if self.tensors.contains_key(name.as_str()) {
-                continue;
-            }
-            let idx = self
-                .tensors_metadata
-                .iter()
-                .position(|t| t.name == name.as_str())
-                .ok_or_else(|| {
+    /// [`Self::load_named_tensors`] with explicit options.
+    pub fn load_named_tensors_with(
+        &mut self,
+        file_path: &str,
+        tensor_names: &[String],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        let indices = self.named_indices(tensor_names)?;
+        self.load_entries(
+            TensorInput::File(file_path),
+            indices,
+            options.advisor(),
+            &options.effective_overrides(),
+            options.shared_cache,
+            &options.optimize_layout,
+        )
+    }
+
+    /// [`Self::load_named_tensors`] sending readahead hints to `advisor` (`None`: no hints).
+    /// Custom advisors are mainly for observing which byte ranges a load touches.
+    pub fn load_named_tensors_advised(
+        &mut self,
+        file_path: &str,
+        tensor_names: &[String],
+        advisor: Option<&dyn Advisor>,
+    ) -> Result<LoadStats, EngineError> {
+        let indices = self.named_indices(tensor_names)?;
+        self.load_entries(
+            TensorInput::File(file_path),
+            indices,
+            advisor,
+            &BTreeMap::new(),
+            false,
+            &BTreeSet::new(),
+        )
+    }
+
+    /// [`Self::load_tensors_with`] from `bytes`, the whole GGUF file already in memory (as
+    /// parsed by [`read_from_bytes`](crate::model_loader::file_loader::read_from_bytes)).
+    /// Tensors are copied out of `bytes`. Readahead hints and the shared cache are file features
+    /// and do not apply.
+    pub fn load_tensors_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        let all = (0..self.tensors_metadata.len()).collect();
+        self.load_entries(
+            TensorInput::Bytes(bytes),
+            all,
+            None,
+            &options.effective_overrides(),
+            false,
+            &options.optimize_layout,
+        )
+    }
+
+    /// [`Self::load_named_tensors_with`] from a GGUF file held in `bytes`, like
+    /// [`Self::load_tensors_from_bytes`].
+    pub fn load_named_tensors_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        tensor_names: &[String],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        let indices = self.named_indices(tensor_names)?;
+        self.load_entries(
+            TensorInput::Bytes(bytes),
+            indices,
+            None,
+            &options.effective_overrides(),
+            false,
+            &options.optimize_layout,
+        )
+    }
+
+    /// [`Self::load_named_tensors_with`] for names already resolved with [`Self::tensor_symbol`].
+    pub fn load_symbols_with(
+        &mut self,
+        file_path: &str,
+        symbols: &[Symbol],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        self.load_entries(
+            TensorInput::File(file_path),
+            self.symbol_indices(symbols)?,
+            options.advisor(),
+            &options.effective_overrides(),
+            options.shared_cache,
+            &options.optimize_layout,
+        )
+    }
+
+    /// [`Self::load_named_tensors_from_bytes`] for names already resolved with
+    /// [`Self::tensor_symbol`].
+    pub fn load_symbols_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        symbols: &[Symbol],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        self.load_entries(
+            TensorInput::Bytes(bytes),
+            self.symbol_indices(symbols)?,
+            None,
+            &options.effective_overrides(),
+            false,
+            &options.optimize_layout,
+        )
+    }
+
+    fn symbol_indices(&self, symbols: &[Symbol]) -> Result<Vec<usize>, EngineError> {
+        symbols
+            .iter()
+            .map(|sym| {
+                if sym.index() < self.tensors_metadata.len() {
+                    Ok(sym.index())
+                } else {
+                    Err(EngineError::Model(format!(
+                        "tensor symbol {} is not in this model's table",
+                        sym.index()
+                    )))
+                }
+            })
+            .collect()
+    }
+
+    fn named_indices(&self, tensor_names: &[String]) -> Result<Vec<usize>, EngineError> {
+        tensor_names
+            .iter()
+            .map(|name| {
+                let sym = self.tensor_symbol(name).ok_or_else(|| {
                     EngineError::Model(format!("tensor '{name}' not found in model metadata"))
                 })?;
-            indices.push(idx);
-        }
+                Ok(sym.index())
+            })
+            .collect()
+    }
+
+    /// Load every tensor whose name and metadata satisfy `pred` (e.g. all F32 norms, or all
+    /// `blk.0.` tensors) in one pass, like [`Self::load_named_tensors`]. Already-loaded tensors
+    /// are skipped. Returns how many tensors matched.
+    pub fn load_where(
+        &mut self,
+        file_path: &str,
+        pred: impl Fn(&str, &TensorInfo) -> bool,
+    ) -> Result<usize, EngineError> {
+        let indices: Vec<usize> = (0..self.tensors_metadata.len())
+            .filter(|&i| {
+                let info = &self.tensors_metadata[i];
+                pred(self.tensor_names.resolve(info.name), info)
+            })
+            .collect();
+        let matched = indices.len();
+        self.load_entries(
+            TensorInput::File(file_path),
+            indices,
+            LoadOptions::default().advisor(),
+            &BTreeMap::new(),
+            false,
+            &BTreeSet::new(),
+        )?;
+        Ok(matched)
+    }
+
+    /// Read the given table entries that are not loaded yet, in file order, with one reader over
+    /// `input`. Hints and the shared cache need a file; they are skipped for bytes.
+    ///
+    /// With an `advisor`, the whole span being read is first marked sequential, and each tensor's
+    /// successor is announced (`WillNeed`) before the tensor itself is read, so the OS can fetch
+    /// it while this one is copied out. Hint failures are counted, never returned. Each tensor is
+    /// converted as `overrides` asks for its role before it is stored.
+    ///
+    /// With `shared_cache`, tensors are first looked up in the [`SharedCache`]; only the ones it
+    /// cannot view are read, and if any of those were converted the sidecar is rewritten with
+    /// every converted tensor, old and new, in table order.
+    ///
+    /// Last, the roles in `optimize_layout` are reordered into column tiles
+    /// ([`crate::model_loader::weight_layout`]).
+    fn load_entries(
+        &mut self,
+        input: TensorInput<'_>,
+        mut indices: Vec<usize>,
+        advisor: Option<&dyn Advisor>,
+        overrides: &BTreeMap<WeightRole, InMemoryDtype>,
+        shared_cache: bool,
+        optimize_layout: &BTreeSet<WeightRole>,
+    ) -> Result<LoadStats, EngineError> {
+        use crate::model_loader::reader::{GgufRead, Reader};
+        use crate::model_loader::sidecar::{Hit, SharedCache};
+        use crate::model_loader::tensor_loader::{LoadClock, load_tensor_timed};
+        use log::debug;
+        use std::fs::File;
+        use std::io::{BufReader, Cursor};
+        use std::ops::Range;
+
+        check_overrides(overrides)?;
+        check_layout_roles(optimize_layout)?;
+        let mut stats = LoadStats::default();
+        indices.retain(|&i| !self.tensors.contains_key(&self.tensors_metadata[i].name));
         if indices.is_empty() {
-            return Ok(());
+            return Ok(stats);
         }
-        indices.sort_by_key(|&i| self.tensors_metadata[i].offset);
+        let started = Instant::now();
+        let mut clock = LoadClock::start();
+        indices.sort_unstable_by_key(|&i| (self.tensors_metadata[i].offset, i));
+        indices.dedup();
 
-        let file = File::open(file_path)?;
-        let buf_reader = BufReader::with_capacity(1024 * 1024, file);
-        let mut reader = crate::model_loader::reader::Reader::new(buf_reader, 0);
+        let file_path = input.name();
+        // For a file, the reader gets its own handle to it so hints can be issued on `file`.
+        let (file, source): (Option<File>, Box<dyn GgufRead + '_>) = match input {
+            TensorInput::File(path) => {
+                let file = File::open(path)?;
+                let buf_reader = BufReader::with_capacity(1024 * 1024, file.try_clone()?);
+                (Some(file), Box::new(buf_reader))
+            }
+            TensorInput::Bytes(bytes) => (None, Box::new(Cursor::new(bytes))),
+        };
+        let mut reader = Reader::new(source, 0);
+        let shared = match &file {
+            Some(file) if shared_cache => {
+                Some(SharedCache::open(Path::new(file_path), file, overrides)?)
+            }
+            _ => None,
+        };
+        let mut fresh = HashSet::new();
 
-        for idx in indices {
-            let name = self.tensors_metadata[idx].name.clone();
-            let tensor = {
-                let info = &self.tensors_metadata[idx];
-                load_tensor(&mut reader, info, self.tensor_data_offset)?
+        // Absolute byte range of a table entry, if its type has a known size.
+        let range = |idx: usize| -> Option<(u64, u64)> {
+            let info = &self.tensors_metadata[idx];
+            let start = self.tensor_data_offset.checked_add(info.offset as u64)?;
+            Some((start, info.byte_size().ok()? as u64))
+        };
+        // The same range as a slice index into the mapped file.
+        let source = |idx: usize| -> Option<Range<usize>> {
+            let (start, len) = range(idx)?;
+            let start = usize::try_from(start).ok()?;
+            Some(start..start.checked_add(usize::try_from(len).ok()?)?)
+        };
+        let hint = |stats: &mut LoadStats, (offset, len): (u64, u64), advice: Advice| {
+            let (Some(advisor), Some(file)) = (advisor, &file) else {
+                return;
             };
-            self.tensors.insert(name, tensor);
+            stats.hints_issued += 1;
+            match advisor.advise(file, offset, len, advice) {
+                Ok(applied) => stats.hints_applied |= applied,
+                Err(e) => {
+                    stats.hints_failed += 1;
+                    debug!("{advice:?} hint for {len} bytes at {offset} failed: {e}");
+                }
+            }
+        };
+
+        if let (Some(first), Some(last)) = (range(indices[0]), range(indices[indices.len() - 1])) {
+            let end = last.0 + last.1;
+            hint(
+                &mut stats,
+                (first.0, end.saturating_sub(first.0)),
+                Advice::Sequential,
+            );
         }
-        Ok(())
+
+        let total = indices.len();
+        for (pos, &idx) in indices.iter().enumerate() {
+            if let Some(next) = indices.get(pos + 1).and_then(|&next| range(next)) {
+                hint(&mut stats, next, Advice::WillNeed);
+            }
+            let info = &self.tensors_metadata[idx];
+            let name = self.tensor_names.resolve(info.name);
+            debug!(
+                "Loading tensor {}/{} ({}%): {} (offset: {}, type_id: {})",
+                pos + 1,
+                total,
+                (pos + 1) * 100 / total,
+                name,
+                info.offset,
+                info.type_id
+            );
+            // Time before the first charge (file open, hints, this log line) counts as reading.
+            let mut timing = LoadTiming {
+                tensors: 1,
+                ..LoadTiming::default()
+            };
+            let ggml = GgmlType::try_from(info.type_id).ok();
+            let hit = shared.as_ref().and_then(|cache| {
+                cache.lookup(name, ggml?, source(idx)?, &info.dimensions, overrides)
+            });
+            let (tensor, applied) = match hit {
+                Some((tensor, applied, hit)) => {
+                    match hit {
+                        Hit::Gguf => stats.tensors_mapped += 1,
+                        Hit::Sidecar => stats.sidecar_hits += 1,
+                    }
+                    (tensor, applied)
+                }
+                None => {
+                    let (tensor, applied) = load_tensor_timed(
+                        &mut reader,
+                        info,
+                        self.tensor_data_offset,
+                        &mut clock,
+                        &mut timing,
+                    )
+                    .and_then(|t| {
+                        let elements = t.element_count() as u64;
+                        let (t, applied) = apply_override(name, t, overrides)?;
+                        if applied.is_some() {
+                            timing.elements_converted += elements;
+                        }
+                        clock.charge(&mut timing.convert);
+                        Ok((t, applied))
+                    })
+                    .map_err(|e| with_tensor_name(e, name))?;
+                    stats.bytes_read += range(idx).map_or(0, |(_, len)| len);
+                    if ggml.and_then(|g| g.to_tensor_type().ok()) != Some(tensor.dtype()) {
+                        fresh.insert(info.name);
+                    }
+                    (tensor, applied)
+                }
+            };
+            stats.dtype_overrides.extend(applied);
+            stats.tensors_loaded += 1;
+            self.tensors.insert(info.name, tensor);
+            clock.charge(&mut timing.alloc);
+
+            stats.timing.add(&timing);
+            // `load_tensor_timed` already rejected unknown type ids.
+            if let Some(ty) = ggml {
+                stats.timing_by_type.entry(ty).or_default().add(&timing);
+            }
+        }
+        if let Some(cache) = &shared
+            && !fresh.is_empty()
+        {
+            // Earlier sidecar entries this call did not convert, carried into the new one if
+            // their source bytes are unchanged.
+            let carried: HashMap<Symbol, Tensor> = self
+                .tensors_metadata
+                .iter()
+                .enumerate()
+                .filter(|(_, info)| !fresh.contains(&info.name))
+                .filter_map(|(idx, info)| {
+                    let name = self.tensor_names.resolve(info.name);
+                    Some((info.name, cache.cached(name, source(idx)?)?))
+                })
+                .collect();
+            let converted: Vec<(&str, &Tensor, Range<usize>)> = self
+                .tensors_metadata
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, info)| {
+                    let tensor = if fresh.contains(&info.name) {
+                        self.tensors.get(&info.name)
+                    } else {
+                        carried.get(&info.name)
+                    };
+                    Some((self.tensor_names.resolve(info.name), tensor?, source(idx)?))
+                })
+                .collect();
+            match cache.write(&converted) {
+                Ok(()) => stats.sidecar_written = true,
+                Err(e) => log::warn!("{file_path}: writing the shared-cache sidecar failed: {e}"),
+            }
+        }
+        // After the sidecar is written: it holds ggml-order tensors only.
+        if !optimize_layout.is_empty() {
+            for &idx in &indices {
+                let sym = self.tensors_metadata[idx].name;
+                if let Some(tensor) = self.tensors.remove(&sym) {
+                    let name = self.tensor_names.resolve(sym);
+                    let tensor = apply_layout(name, tensor, optimize_layout)
+                        .map_err(|e| with_tensor_name(e, name))?;
+                    self.tensors.insert(sym, tensor);
+                }
+            }
+        }
+        stats.elapsed = started.elapsed();
+        Ok(stats)
+    }
+
+    /// [`Tensor::stats`] of every loaded tensor whose name passes `filter` (e.g.
+    /// `|n| n.starts_with("blk.0.")`). Tensors listed but not loaded are skipped.
+    pub fn stats_report(&self, filter: impl Fn(&str) -> bool) -> Result<StatsReport, EngineError> {
+        let mut report = StatsReport::default();
+        for (name, tensor) in self.loaded_tensors().filter(|(name, _)| filter(name)) {
+            let stats = tensor
+                .stats()
+                .map_err(|e| EngineError::Tensor(format!("{name}: {e}")))?;
+            report.tensors.insert(name.to_string(), stats);
+        }
+        Ok(report)
     }
 
     /// Get the number of loaded tensors
@@ -255,6 +1209,30 @@ impl GGUFData {
         self.nb_tensors
     }
 
+    /// Weights in the model: elements summed over every tensor in the table, from the dims
+    /// alone (nothing needs to be loaded). Counts what the file stores, so a tied LM head is
+    /// counted once and an untied one twice, as with the "7B" in a model's name.
+    pub fn parameter_count(&self) -> u64 {
+        self.tensors_metadata
+            .iter()
+            .map(|t| t.dimensions.iter().map(|&d| d as u64).product::<u64>())
+            .fold(0u64, u64::saturating_add)
+    }
+
+    /// llama.cpp's name for the file's quantization scheme ("Q4_K_M", "Q8_0", "F16", ...),
+    /// inferred from the types of the block weights (see [`quant_scheme_from_mix`]), or
+    /// `"unknown"` for a file without quantized or float block weights.
+    pub fn quant_scheme_name(&self) -> String {
+        quant_scheme_from_mix(self.tensors_metadata.iter().map(|t| {
+            (
+                self.tensor_names.resolve(t.name),
+                t.type_id,
+                &t.dimensions[..],
+            )
+        }))
+        .unwrap_or_else(|| "unknown".into())
+    }
+
     /// Total number of key/value metadata entries
     pub fn total_key_vals(&self) -> u64 {
         self.nb_key_vals
@@ -271,8 +1249,208 @@ impl GGUFData {
         self.kv.get(key)
     }
 
+    /// Element count of an array-valued key (e.g. `tokenizer.ggml.tokens` for the vocab size),
+    /// without touching the elements. `None` if `key` is missing or not an array.
+    pub fn array_len(&self, key: &str) -> Option<usize> {
+        match self.kv.get(key)? {
+            Data::Array(items) => Some(items.len()),
+            _ => None,
+        }
+    }
+
     /// Get all metadata keys (for debugging/inspection)
     pub fn metadata_keys(&self) -> Vec<&String> {
         self.kv.keys().collect()
     }
+
+    /// Stable identity for cache keys: 16 hex digits of FNV-1a over the GGUF version,
+    /// `general.architecture`, KV / tensor counts, the data offset, and the name, type, dims and
+    /// offset of up to [`MODEL_ID_TENSOR_SAMPLE`] evenly spaced tensors (always including the
+    /// last). Identical files give identical ids across runs and builds. Only metadata is hashed:
+    /// two files that differ solely in tensor bytes share an id.
+    pub fn model_id(&self) -> String {
+        let mut h = Fnv1a::default();
+        h.write(&self.version.to_le_bytes());
+        match self.kv.get("general.architecture") {
+            Some(Data::String(arch)) => h.write_str(arch),
+            _ => h.write_str(""),
+        }
+        h.write(&self.nb_key_vals.to_le_bytes());
+        h.write(&self.nb_tensors.to_le_bytes());
+        h.write(&self.tensor_data_offset.to_le_bytes());
+
+        let n = self.tensors_metadata.len();
+        let step = n.div_ceil(MODEL_ID_TENSOR_SAMPLE).max(1);
+        let sample = (0..n)
+            .step_by(step)
+            .chain(n.checked_sub(1).filter(|last| last % step != 0));
+        for i in sample {
+            let t = &self.tensors_metadata[i];
+            h.write_str(self.tensor_names.resolve(t.name));
+            h.write(&t.type_id.to_le_bytes());
+            for &d in &t.dimensions {
+                h.write(&(d as u64).to_le_bytes());
+            }
+            h.write(&(t.offset as u64).to_le_bytes());
+        }
+        format!("{:016x}", h.0)
+    }
+
+    /// Like [`Self::model_id`], but over what the model computes with: the name, type, dims and
+    /// **bytes** of every loaded tensor in table order, plus the KV metadata. Tensors that are
+    /// not loaded contribute their name only. Reads every weight byte, so it costs about one
+    /// pass over the model; meant for transcripts and bug reports, not for every load.
+    pub fn content_hash(&self) -> String {
+        let mut h = Fnv1a::default();
+        for (key, value) in &self.kv {
+            h.write_str(key);
+            h.write_data(value);
+        }
+        for t in &self.tensors_metadata {
+            h.write_str(self.tensor_names.resolve(t.name));
+            let Some(tensor) = self.tensors.get(&t.name) else {
+                h.write(&[0]);
+                continue;
+            };
+            h.write(&[1]);
+            h.write(&t.type_id.to_le_bytes());
+            for &d in &t.dimensions {
+                h.write(&(d as u64).to_le_bytes());
+            }
+            h.write(tensor.buffer());
+        }
+        format!("{:016x}", h.0)
+    }
+}
+
+/// Tensors hashed by [`GGUFData::model_id`] (all of them for smaller models).
+pub const MODEL_ID_TENSOR_SAMPLE: usize = 256;
+
+/// 64-bit FNV-1a; unlike `std`'s `DefaultHasher` its output is fixed across Rust releases.
+pub(crate) struct Fnv1a(pub(crate) u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Length-prefixed, so adjacent strings cannot run together.
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+
+    /// A KV value as its GGUF type id and little-endian bytes (arrays length-prefixed), so e.g. a
+    /// vocabulary is hashed as its token bytes, not a formatted copy of them.
+    pub(crate) fn write_data(&mut self, value: &Data) {
+        match value {
+            Data::Uint8(v) => self.tagged(0, &v.to_le_bytes()),
+            Data::Int8(v) => self.tagged(1, &v.to_le_bytes()),
+            Data::Uint16(v) => self.tagged(2, &v.to_le_bytes()),
+            Data::Int16(v) => self.tagged(3, &v.to_le_bytes()),
+            Data::Uint32(v) => self.tagged(4, &v.to_le_bytes()),
+            Data::Int32(v) => self.tagged(5, &v.to_le_bytes()),
+            Data::Float32(v) => self.tagged(6, &v.to_le_bytes()),
+            Data::Bool(v) => self.tagged(7, &[u8::from(*v)]),
+            Data::String(v) => {
+                self.write(&[8]);
+                self.write_str(v);
+            }
+            Data::Array(items) => {
+                self.tagged(9, &(items.len() as u64).to_le_bytes());
+                items.iter().for_each(|item| self.write_data(item));
+            }
+            Data::Uint64(v) => self.tagged(10, &v.to_le_bytes()),
+            Data::Int64(v) => self.tagged(11, &v.to_le_bytes()),
+            Data::Float64(v) => self.tagged(12, &v.to_le_bytes()),
+        }
+    }
+
+    fn tagged(&mut self, type_id: u8, bytes: &[u8]) {
+        self.write(&[type_id]);
+        self.write(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block weights of a two-layer model, with `attn_v` / `ffn_down` / `attn_output` types as
+    /// given and everything else `base`.
+    fn mix(base: GgmlType, v: GgmlType, down: GgmlType, out: GgmlType) -> Option<String> {
+        let dims = [64usize, 64];
+        let norm = [64usize];
+        let mut tensors: Vec<(String, u32, &[usize])> = vec![
+            ("token_embd.weight".into(), GgmlType::Q6_K as u32, &dims),
+            ("output.weight".into(), GgmlType::Q6_K as u32, &dims),
+        ];
+        for l in 0..2 {
+            for (w, t) in [
+                ("attn_q", base),
+                ("attn_k", base),
+                ("attn_v", v),
+                ("attn_output", out),
+                ("ffn_gate", base),
+                ("ffn_up", base),
+                ("ffn_down", down),
+            ] {
+                tensors.push((format!("blk.{l}.{w}.weight"), t as u32, &dims));
+            }
+            tensors.push((format!("blk.{l}.attn_norm.weight"), 0, &norm));
+        }
+        quant_scheme_from_mix(tensors.iter().map(|(n, t, d)| (n.as_str(), *t, *d)))
+    }
+
+    #[test]
+    fn k_quant_variants_follow_the_bumped_tensors() {
+        use GgmlType::*;
+        let name = |base, v, down, out| mix(base, v, down, out).unwrap();
+        assert_eq!(name(Q4_K, Q6_K, Q6_K, Q4_K), "Q4_K_M");
+        assert_eq!(name(Q4_K, Q5_K, Q4_K, Q4_K), "Q4_K_S");
+        assert_eq!(name(Q5_K, Q5_K, Q6_K, Q5_K), "Q5_K_M");
+        assert_eq!(name(Q5_K, Q5_K, Q5_K, Q5_K), "Q5_K_S");
+        assert_eq!(name(Q3_K, Q5_K, Q5_K, Q5_K), "Q3_K_L");
+        assert_eq!(name(Q3_K, Q4_K, Q4_K, Q3_K), "Q3_K_M");
+        assert_eq!(name(Q3_K, Q3_K, Q3_K, Q3_K), "Q3_K_S");
+        assert_eq!(name(Q8_0, Q8_0, Q8_0, Q8_0), "Q8_0");
+        assert_eq!(name(F16, F16, F16, F16), "F16");
+        assert_eq!(name(F32, F32, F32, F32), "F32");
+        assert_eq!(
+            quant_scheme_from_mix([("blk.0.attn_norm.weight", 0, &[8usize][..])]),
+            None
+        );
+    }
+
+    #[test]
+    fn kv_values_hash_by_type_and_bytes() {
+        let hash = |value: &Data| {
+            let mut h = Fnv1a::default();
+            h.write_data(value);
+            h.0
+        };
+        let mut tokens = Fnv1a::default();
+        tokens.write(&[9]);
+        tokens.write(&2u64.to_le_bytes());
+        for token in ["<s>", "é"] {
+            tokens.write(&[8]);
+            tokens.write_str(token);
+        }
+        let vocab = Data::Array(vec![Data::String("<s>".into()), Data::String("é".into())]);
+        assert_eq!(hash(&vocab), tokens.0);
+        assert_ne!(hash(&Data::Uint32(1)), hash(&Data::Int32(1)));
+        assert_ne!(
+            hash(&Data::String("a".into())),
+            hash(&Data::Array(vec![Data::String("a".into())]))
+        );
+    }
 }
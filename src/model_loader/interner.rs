@@ -0,0 +1,155 @@
+//! Tensor-name interning.
+//!
+//! Every distinct tensor name is stored once, back to back in one arena string, and referred to
+//! everywhere else ([`super::gguf_types::TensorInfo`], the loaded-tensor map) by a 4-byte
+//! [`Symbol`]. Metadata for a model with thousands of tensors then holds one copy of the names
+//! instead of one per table, and a lookup by symbol hashes a `u32` instead of the whole name.
+//!
+//! Symbols are handed out in first-interned order starting at 0 and never change for the life of
+//! the interner; they are meaningless for any other interner.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::EngineError;
+
+/// Interned string id; copy-cheap and usable as a map key. Resolve with
+/// [`StringInterner::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Position in interning order (0 for the first string interned).
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// No other symbol shares this hash.
+const END_OF_CHAIN: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Default)]
+pub struct StringInterner {
+    /// Every interned string, concatenated.
+    arena: String,
+    /// `(start, end)` byte range in `arena`, indexed by symbol.
+    spans: Vec<(u32, u32)>,
+    /// Next symbol whose string has the same hash, indexed by symbol.
+    chain: Vec<u32>,
+    /// String hash -> most recently interned symbol with that hash.
+    heads: HashMap<u64, u32>,
+}
+
+impl StringInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Symbol for `s`, adding it if it is new. Errors only if the arena outgrows `u32` offsets.
+    pub fn intern(&mut self, s: &str) -> Result<Symbol, EngineError> {
+        let hash = hash_str(s);
+        if let Some(sym) = self.find(hash, s) {
+            return Ok(sym);
+        }
+        let overflow = || EngineError::Overflow(format!("interning tensor name '{s}'"));
+        let start = u32::try_from(self.arena.len()).map_err(|_| overflow())?;
+        let end = u32::try_from(self.arena.len() + s.len()).map_err(|_| overflow())?;
+        let id = u32::try_from(self.spans.len())
+            .ok()
+            .filter(|&id| id != END_OF_CHAIN)
+            .ok_or_else(overflow)?;
+        self.arena.push_str(s);
+        self.spans.push((start, end));
+        self.chain
+            .push(self.heads.insert(hash, id).unwrap_or(END_OF_CHAIN));
+        Ok(Symbol(id))
+    }
+
+    /// Symbol for `s` if it was interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.find(hash_str(s), s)
+    }
+
+    /// The string behind `sym`.
+    ///
+    /// # Panics
+    /// If `sym` did not come from this interner.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        let (start, end) = self.spans[sym.index()];
+        &self.arena[start as usize..end as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// All symbols with their strings, in interning order.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> {
+        (0..self.spans.len() as u32).map(|id| (Symbol(id), self.resolve(Symbol(id))))
+    }
+
+    /// Approximate heap footprint: arena, per-symbol tables and the hash index.
+    pub fn heap_bytes(&self) -> usize {
+        self.arena.capacity()
+            + self.spans.capacity() * std::mem::size_of::<(u32, u32)>()
+            + self.chain.capacity() * std::mem::size_of::<u32>()
+            + self.heads.capacity() * (std::mem::size_of::<(u64, u32)>() + 1)
+    }
+
+    fn find(&self, hash: u64, s: &str) -> Option<Symbol> {
+        let mut id = *self.heads.get(&hash)?;
+        while id != END_OF_CHAIN {
+            if self.resolve(Symbol(id)) == s {
+                return Some(Symbol(id));
+            }
+            id = self.chain[id as usize];
+        }
+        None
+    }
+}
+
+fn hash_str(s: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    s.hash(&mut h);
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_are_stable_and_round_trip() {
+        let mut names = StringInterner::new();
+        let a = names.intern("blk.0.attn_q.weight").unwrap();
+        let b = names.intern("blk.0.attn_k.weight").unwrap();
+        let empty = names.intern("").unwrap();
+        assert_eq!((a.index(), b.index(), empty.index()), (0, 1, 2));
+        assert_eq!(names.intern("blk.0.attn_q.weight").unwrap(), a);
+        assert_eq!(names.len(), 3);
+
+        assert_eq!(names.get("blk.0.attn_k.weight"), Some(b));
+        assert_eq!(names.get("blk.0.attn_v.weight"), None);
+        assert_eq!(names.resolve(a), "blk.0.attn_q.weight");
+        assert_eq!(names.resolve(empty), "");
+        let all: Vec<&str> = names.iter().map(|(_, s)| s).collect();
+        assert_eq!(all, ["blk.0.attn_q.weight", "blk.0.attn_k.weight", ""]);
+    }
+
+    #[test]
+    fn many_names_resolve_to_themselves() {
+        let mut names = StringInterner::new();
+        let syms: Vec<Symbol> = (0..5000)
+            .map(|i| names.intern(&format!("blk.{i}.ffn_down.weight")).unwrap())
+            .collect();
+        for (i, &sym) in syms.iter().enumerate() {
+            let name = format!("blk.{i}.ffn_down.weight");
+            assert_eq!(names.resolve(sym), name);
+            assert_eq!(names.get(&name), Some(sym));
+        }
+    }
+}
@@ -1,6 +1,13 @@
+pub mod discovery;
+pub mod dtype_overrides;
 pub mod file_loader;
 pub mod gguf_types;
+pub mod interner;
 pub mod parser;
 pub mod reader;
+pub mod registry;
+pub mod sidecar;
+pub mod storage;
 pub mod tensor;
 pub mod tensor_loader;
+pub mod weight_layout;
@@ -1,40 +1,70 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{BufRead, Seek};
 
 use crate::EngineError;
-use crate::model_loader::gguf_types::{Data, DataType, ReadingInfo, TensorInfo};
+use crate::model_loader::gguf_types::{Data, DataType, DuplicateKey, ReadingInfo, TensorInfo};
+use crate::model_loader::interner::StringInterner;
 use crate::model_loader::reader::Reader;
 
 pub fn get_tensors_metadata<R: BufRead + Seek>(
     reader: &mut Reader<R>,
     tensor_count: u64,
-) -> Result<Vec<TensorInfo>, EngineError> {
-    let mut all_tensors: Vec<TensorInfo> = Vec::with_capacity(tensor_count as usize);
+) -> Result<(Vec<TensorInfo>, StringInterner), EngineError> {
+    // `tensor_count` is untrusted: grow as entries actually parse instead of reserving it all.
+    let mut all_tensors: Vec<TensorInfo> =
+        Vec::with_capacity(tensor_count.min(MAX_TENSOR_PREALLOC) as usize);
     let mut unique_types: HashSet<u32> = HashSet::new();
-    for _ in 0..tensor_count {
-        let curr_tensor: TensorInfo = get_tensor_metadata(reader)?;
+    let mut names = StringInterner::new();
+    for idx in 0..tensor_count as usize {
+        let curr_tensor: TensorInfo = get_tensor_metadata(reader, &mut names)?;
+        // A new name gets the next symbol, so an older symbol is the index of the first entry.
+        // A second entry would silently replace the first tensor when loading, so it is an error
+        // rather than a warning.
+        let first = curr_tensor.name.index();
+        if first != idx {
+            return Err(EngineError::Gguf(format!(
+                "duplicate tensor name '{}': entry {first} (data offset {}) and entry {idx} (data offset {})",
+                names.resolve(curr_tensor.name),
+                all_tensors[first].offset,
+                curr_tensor.offset
+            )));
+        }
         if !unique_types.contains(&curr_tensor.type_id) {
             unique_types.insert(curr_tensor.type_id);
         }
         all_tensors.push(curr_tensor);
     }
     log::debug!("GGUF unique tensor type_ids: {unique_types:?}");
-    Ok(all_tensors)
+    Ok((all_tensors, names))
 }
 
+/// Parse one tensor-table entry, interning its name into `names`.
 pub fn get_tensor_metadata<R: BufRead + Seek>(
     reader: &mut Reader<R>,
+    names: &mut StringInterner,
 ) -> Result<TensorInfo, EngineError> {
     let name = reader.read_string()?;
     let n_dimensions = reader.read_u32()? as usize;
+    if n_dimensions > GGML_MAX_DIMS {
+        return Err(EngineError::Gguf(format!(
+            "tensor {name}: {n_dimensions} dimensions exceeds max {GGML_MAX_DIMS}"
+        )));
+    }
     let mut dimensions = Vec::with_capacity(n_dimensions);
     for _ in 0..n_dimensions {
-        dimensions.push(reader.read_u64()? as usize);
+        let dim = reader.read_u64()?;
+        dimensions.push(usize::try_from(dim).map_err(|_| {
+            EngineError::Overflow(format!("tensor {name}: dimension {dim} exceeds usize"))
+        })?);
     }
     let type_id = reader.read_u32()?;
-    let offset = reader.read_u64()? as usize;
+    let offset = reader.read_u64()?;
+    let offset = usize::try_from(offset).map_err(|_| {
+        EngineError::Overflow(format!("tensor {name}: offset {offset} exceeds usize"))
+    })?;
+    validate_tensor_dims(&name, &dimensions)?;
     Ok(TensorInfo {
-        name,
+        name: names.intern(&name)?,
         n_dimensions,
         dimensions,
         type_id,
@@ -42,17 +72,89 @@ pub fn get_tensor_metadata<R: BufRead + Seek>(
     })
 }
 
+/// ggml supports at most 4 dimensions (`GGML_MAX_DIMS`).
+const GGML_MAX_DIMS: usize = 4;
+
+/// Tensor-table entries reserved up front; real models have a few thousand at most.
+const MAX_TENSOR_PREALLOC: u64 = 4096;
+
+/// Accept scalars (`n_dimensions == 0`) and 1-D vectors like norm weights; reject any dim of
+/// size zero, which would make the tensor empty and break every downstream length check.
+fn validate_tensor_dims(name: &str, dimensions: &[usize]) -> Result<(), EngineError> {
+    if dimensions.len() > GGML_MAX_DIMS {
+        return Err(EngineError::Gguf(format!(
+            "tensor {name}: {} dimensions exceeds max {GGML_MAX_DIMS}",
+            dimensions.len()
+        )));
+    }
+    if dimensions.contains(&0) {
+        return Err(EngineError::Gguf(format!(
+            "tensor {name}: zero-element dimension in {dimensions:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Stream `kv_count` metadata pairs to `on_kv` in file order without retaining them.
+///
+/// Each pair is dropped after the callback returns, so callers that only need a few keys
+/// (e.g. skipping a large `tokenizer.ggml.merges` table) never hold the whole tree.
+pub fn parse_metadata<R, F>(
+    reader: &mut Reader<R>,
+    kv_count: u64,
+    mut on_kv: F,
+) -> Result<(), EngineError>
+where
+    R: BufRead + Seek,
+    F: FnMut(&str, &Data),
+{
+    for _ in 0..kv_count {
+        let (key, val) = get_kv_pair(reader)?;
+        on_kv(&key, &val);
+    }
+    Ok(())
+}
+
+/// Collect all metadata pairs into a map (thin wrapper over [`parse_metadata`]).
+/// Repeated keys keep the last value; use [`get_kv_metadata_checked`] to see them.
 pub fn get_kv_metadata<R: BufRead + Seek>(
     reader: &mut Reader<R>,
     kv_count: u64,
 ) -> Result<BTreeMap<String, Data>, EngineError> {
-    let mut kv: std::collections::BTreeMap<String, Data> = std::collections::BTreeMap::new();
+    let mut kv = BTreeMap::new();
+    parse_metadata(reader, kv_count, |key, val| {
+        kv.insert(key.to_string(), val.clone());
+    })?;
+    Ok(kv)
+}
 
-    for _i in 0..kv_count {
+/// Like [`get_kv_metadata`] (last value wins), but also reports every key that occurs more than
+/// once, with the file offset of each occurrence, in order of first appearance.
+pub fn get_kv_metadata_checked<R: BufRead + Seek>(
+    reader: &mut Reader<R>,
+    kv_count: u64,
+) -> Result<(BTreeMap<String, Data>, Vec<DuplicateKey>), EngineError> {
+    let mut kv = BTreeMap::new();
+    let mut offsets: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut order = Vec::new();
+    for _ in 0..kv_count {
+        let start = reader.position();
         let (key, val) = get_kv_pair(reader)?;
+        let seen = offsets.entry(key.clone()).or_default();
+        if seen.len() == 1 {
+            order.push(key.clone());
+        }
+        seen.push(start);
         kv.insert(key, val);
     }
-    Ok(kv)
+    let duplicates = order
+        .into_iter()
+        .map(|key| {
+            let offsets = offsets.remove(&key).unwrap_or_default();
+            DuplicateKey { key, offsets }
+        })
+        .collect();
+    Ok((kv, duplicates))
 }
 
 pub fn get_kv_pair<R: BufRead + Seek>(
@@ -69,10 +171,7 @@ pub fn get_kv_pair<R: BufRead + Seek>(
 }
 
 pub fn get_k<R: BufRead + Seek>(reader: &mut Reader<R>) -> Result<String, EngineError> {
-    let key_len = reader.read_u64()?;
-    let key_as_bytes = reader.read_bytes(key_len)?;
-    let key = String::from_utf8(key_as_bytes)?;
-    Ok(key)
+    reader.read_string()
 }
 
 pub fn get_value_type<R: BufRead + Seek>(reader: &mut Reader<R>) -> Result<DataType, EngineError> {
@@ -112,22 +211,7 @@ impl ReadBytesAsType for ReadingInfo {
         &mut self,
         reader: &mut Reader<R>,
     ) -> Result<Data, EngineError> {
-        let data = match self.data_type {
-            DataType::Uint8 => Data::Uint8(reader.read_u8()?),
-            DataType::Int8 => Data::Int8(reader.read_i8()?),
-            DataType::Uint16 => Data::Uint16(reader.read_u16()?),
-            DataType::Int16 => Data::Int16(reader.read_i16()?),
-            DataType::Uint32 => Data::Uint32(reader.read_u32()?),
-            DataType::Int32 => Data::Int32(reader.read_i32()?),
-            DataType::Float32 => Data::Float32(reader.read_f32()?),
-            DataType::Bool => Data::Bool(reader.read_bool()?),
-            DataType::Uint64 => Data::Uint64(reader.read_u64()?),
-            DataType::Int64 => Data::Int64(reader.read_i64()?),
-            DataType::Float64 => Data::Float64(reader.read_f64()?),
-            DataType::String => Data::String(reader.read_string()?),
-            DataType::Array => Data::Array(reader.read_array()?),
-        };
-        Ok(data)
+        reader.read_value(self.data_type)
     }
 }
 
@@ -151,3 +235,302 @@ pub fn u32_to_data_type(value: u32) -> Result<DataType, EngineError> {
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn push_key(buf: &mut Vec<u8>, key: &str, type_code: u32) {
+        buf.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        buf.extend_from_slice(key.as_bytes());
+        buf.extend_from_slice(&type_code.to_le_bytes());
+    }
+
+    fn sample_kv_bytes() -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_key(&mut buf, "general.architecture", 8);
+        buf.extend_from_slice(&5u64.to_le_bytes());
+        buf.extend_from_slice(b"llama");
+        push_key(&mut buf, "llama.block_count", 4);
+        buf.extend_from_slice(&2u32.to_le_bytes());
+        push_key(&mut buf, "tokenizer.ggml.scores", 9);
+        buf.extend_from_slice(&6u32.to_le_bytes());
+        buf.extend_from_slice(&3u64.to_le_bytes());
+        for v in [0.0f32, -1.0, -2.0] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        push_key(&mut buf, "general.alignment", 4);
+        buf.extend_from_slice(&32u32.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn parse_metadata_visits_every_key_once_in_order() {
+        let bytes = sample_kv_bytes();
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        let mut seen = Vec::new();
+        parse_metadata(&mut reader, 4, |k, _| seen.push(k.to_string())).unwrap();
+        assert_eq!(
+            seen,
+            [
+                "general.architecture",
+                "llama.block_count",
+                "tokenizer.ggml.scores",
+                "general.alignment",
+            ]
+        );
+        assert_eq!(reader.position(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn get_kv_metadata_matches_streamed_pairs() {
+        let bytes = sample_kv_bytes();
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        let kv = get_kv_metadata(&mut reader, 4).unwrap();
+        assert_eq!(kv.len(), 4);
+        assert!(matches!(kv.get("llama.block_count"), Some(Data::Uint32(2))));
+        assert!(matches!(kv.get("tokenizer.ggml.scores"), Some(Data::Array(a)) if a.len() == 3));
+    }
+
+    fn tensor_info_bytes(name: &str, dims: &[u64], n_dims: u32, type_id: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(name.len() as u64).to_le_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        buf.extend_from_slice(&n_dims.to_le_bytes());
+        for d in dims {
+            buf.extend_from_slice(&d.to_le_bytes());
+        }
+        buf.extend_from_slice(&type_id.to_le_bytes());
+        buf.extend_from_slice(&0u64.to_le_bytes());
+        buf
+    }
+
+    fn tensor_info_at(name: &str, offset: u64) -> Vec<u8> {
+        let mut buf = tensor_info_bytes(name, &[4], 1, 0);
+        let n = buf.len();
+        buf[n - 8..].copy_from_slice(&offset.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn duplicate_kv_keys_are_reported_and_last_value_wins() {
+        let mut bytes = sample_kv_bytes();
+        let second_at = bytes.len() as u64;
+        push_key(&mut bytes, "llama.block_count", 4);
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        let (kv, duplicates) = get_kv_metadata_checked(&mut reader, 5).unwrap();
+        assert!(matches!(kv.get("llama.block_count"), Some(Data::Uint32(7))));
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].key, "llama.block_count");
+        // First occurrence follows `general.architecture` (8 + 20 + 4 + 8 + 5 bytes).
+        assert_eq!(duplicates[0].offsets, [45, second_at]);
+        assert!(duplicates[0].to_string().contains("last value wins"));
+
+        let mut reader = Reader::new(Cursor::new(sample_kv_bytes()), 0);
+        let (_, none) = get_kv_metadata_checked(&mut reader, 4).unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn duplicate_tensor_names_are_an_error_naming_both_offsets() {
+        let mut bytes = tensor_info_at("blk.0.attn_norm.weight", 0);
+        bytes.extend(tensor_info_at("blk.0.ffn_norm.weight", 32));
+        bytes.extend(tensor_info_at("blk.0.attn_norm.weight", 64));
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        let err = get_tensors_metadata(&mut reader, 3)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(
+                "'blk.0.attn_norm.weight': entry 0 (data offset 0) and entry 2 (data offset 64)"
+            ),
+            "{err}"
+        );
+
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        let (tensors, names) = get_tensors_metadata(&mut reader, 2).unwrap();
+        assert_eq!(tensors.len(), 2);
+        assert_eq!(names.resolve(tensors[1].name), "blk.0.ffn_norm.weight");
+    }
+
+    fn parse_and_check(bytes: &[u8]) -> Result<usize, EngineError> {
+        let mut reader = Reader::new(Cursor::new(bytes), 0);
+        let mut names = StringInterner::new();
+        let info = get_tensor_metadata(&mut reader, &mut names)?;
+        crate::model_loader::gguf_types::SizeLimits::default().check(&[info], &names)
+    }
+
+    #[test]
+    fn huge_dimension_product_is_overflow_error() {
+        let bytes = tensor_info_bytes("evil", &[u64::MAX, 2], 2, 0);
+        assert!(matches!(
+            parse_and_check(&bytes),
+            Err(EngineError::Overflow(_))
+        ));
+        // Element count fits, byte count (x4 for F32) does not.
+        let bytes = tensor_info_bytes("evil", &[1 << 31, 1 << 31, 1 << 1], 3, 0);
+        assert!(matches!(
+            parse_and_check(&bytes),
+            Err(EngineError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn dimension_count_rejected_before_reading_dims() {
+        // No dims follow: the count alone must be rejected, without reserving u32::MAX slots.
+        let bytes = tensor_info_bytes("evil", &[], u32::MAX, 0);
+        let err = parse_and_check(&bytes).unwrap_err().to_string();
+        assert!(err.contains("dimensions exceeds max"), "{err}");
+    }
+
+    #[test]
+    fn size_limits_reject_large_tensor_and_total() {
+        use crate::model_loader::gguf_types::SizeLimits;
+        let mut names = StringInterner::new();
+        let (a, b) = (names.intern("a").unwrap(), names.intern("b").unwrap());
+        let info = |name, n: usize| TensorInfo {
+            name,
+            n_dimensions: 1,
+            dimensions: vec![n],
+            type_id: 0,
+            offset: 0,
+        };
+        let limits = SizeLimits {
+            max_tensor_bytes: 1024,
+            max_total_bytes: 1536,
+        };
+        assert_eq!(limits.check(&[info(a, 256)], &names).unwrap(), 1024);
+        let err = limits
+            .check(&[info(a, 257)], &names)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("per-tensor limit"), "{err}");
+        let err = limits
+            .check(&[info(a, 256), info(b, 256)], &names)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("model limit"), "{err}");
+    }
+
+    /// Random tensor headers biased toward extreme values must parse and size-check to `Ok` or
+    /// a typed error, never panic; every `Ok` must agree with exact 128-bit arithmetic.
+    #[test]
+    fn adversarial_tensor_infos_never_panic() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let interesting = [0, 1, 255, 256, 1 << 32, u64::MAX, u64::MAX / 2, 1 << 62];
+        let types = [0, 1, 8, 12, 14, 30, 16, 41, u32::MAX];
+        for _ in 0..5000 {
+            let n_dims = (next() % 7) as u32;
+            let dims: Vec<u64> = (0..n_dims.min(6))
+                .map(|_| match next() % 3 {
+                    0 => interesting[(next() % interesting.len() as u64) as usize],
+                    1 => next() % 4096 + 1,
+                    _ => next(),
+                })
+                .collect();
+            let type_id = types[(next() % types.len() as u64) as usize];
+            let bytes = tensor_info_bytes("fuzz", &dims, n_dims, type_id);
+            match parse_and_check(&bytes) {
+                Ok(total) => {
+                    let elems: u128 = dims.iter().map(|&d| d as u128).product();
+                    assert!(elems <= usize::MAX as u128, "{dims:?}");
+                    // At most 8 bytes per element, plus one partial quant block.
+                    assert!(total as u128 <= elems * 8 + 292, "{dims:?} -> {total}");
+                }
+                Err(
+                    EngineError::Overflow(_)
+                    | EngineError::Gguf(_)
+                    | EngineError::Tensor(_)
+                    | EngineError::Io(_),
+                ) => {}
+                Err(other) => panic!("unexpected error type for {dims:?}: {other}"),
+            }
+        }
+    }
+
+    fn string_array_kv(n: usize) -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_key(&mut buf, "tokenizer.ggml.tokens", 9);
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        buf.extend_from_slice(&(n as u64).to_le_bytes());
+        for i in 0..n {
+            let token = format!("tok{i:03}");
+            buf.extend_from_slice(&(token.len() as u64).to_le_bytes());
+            buf.extend_from_slice(token.as_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn metadata_limits_are_enforced_with_typed_errors() {
+        use crate::model_loader::gguf_types::{MetadataLimitError, MetadataLimits};
+        let bytes = string_array_kv(100);
+        let parse = |limits: MetadataLimits| {
+            let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0).with_limits(limits);
+            get_kv_metadata(&mut reader, 1).map(|_| reader.metadata_bytes())
+        };
+        let defaults = MetadataLimits::default();
+        let used = parse(defaults).unwrap();
+        assert!(used >= 100 * 6, "{used}");
+
+        let limit_err = |limits| match parse(limits) {
+            Err(EngineError::MetadataLimit(e)) => e,
+            other => panic!("{other:?}"),
+        };
+        let e = limit_err(MetadataLimits {
+            max_array_len: 99,
+            ..defaults
+        });
+        assert!(matches!(
+            e,
+            MetadataLimitError::ArrayTooLong {
+                len: 100,
+                max: 99,
+                ..
+            }
+        ));
+        let e = limit_err(MetadataLimits {
+            max_string_bytes: 5,
+            ..defaults
+        });
+        // The key itself is the first string read.
+        assert_eq!(
+            e,
+            MetadataLimitError::StringTooLong {
+                offset: 0,
+                len: 21,
+                max: 5
+            }
+        );
+        let e = limit_err(MetadataLimits {
+            max_metadata_bytes: used - 1,
+            ..defaults
+        });
+        assert!(matches!(e, MetadataLimitError::BudgetExceeded { .. }));
+        assert!(e.to_string().contains("budget"), "{e}");
+    }
+
+    #[test]
+    fn truncated_input_fails_before_allocating() {
+        let mut bytes = string_array_kv(3);
+        bytes.truncate(bytes.len() - 1);
+        let mut reader = Reader::new(Cursor::new(bytes.as_slice()), 0);
+        assert!(matches!(
+            get_kv_metadata(&mut reader, 1),
+            Err(EngineError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+
+        let mut reader = Reader::new(Cursor::new(&[0u8; 8][..]), 0);
+        assert_eq!(reader.remaining(), 8);
+        let err = reader.read_bytes(u64::MAX).unwrap_err().to_string();
+        assert!(err.contains("past end of input (8 left)"), "{err}");
+    }
+}
@@ -1,7 +1,7 @@
-use std::io::{BufRead, Seek, SeekFrom};
+use std::io::{BufRead, ErrorKind, Read, Seek, SeekFrom};
 
 use crate::EngineError;
-use crate::model_loader::gguf_types::{Data, DataType};
+use crate::model_loader::gguf_types::{Data, DataType, MetadataLimitError, MetadataLimits};
 use crate::model_loader::parser::u32_to_data_type;
 
 fn le_array<const N: usize>(bytes: Vec<u8>) -> Result<[u8; N], EngineError> {
@@ -10,23 +10,124 @@ fn le_array<const N: usize>(bytes: Vec<u8>) -> Result<[u8; N], EngineError> {
         .map_err(|v: Vec<u8>| EngineError::Gguf(format!("expected {N} bytes, got {}", v.len())))
 }
 
+/// A seekable, buffered GGUF byte source: a [`std::io::BufReader`] over a file, or a
+/// [`std::io::Cursor`] over a GGUF already in memory (downloaded, embedded, or a browser upload).
+/// Lets one tensor-loading loop read from either as a `Reader<Box<dyn GgufRead>>`.
+pub trait GgufRead: BufRead + Seek {}
+
+impl<T: BufRead + Seek> GgufRead for T {}
+
+/// [`Read::read_exact`] that says how far it got: a short read fails with
+/// [`ErrorKind::UnexpectedEof`] naming `offset` (the logical position of `buf[0]`, for the message)
+/// and the bytes actually read, instead of the bare "failed to fill whole buffer". Never returns
+/// with part of `buf` left unwritten.
+pub(crate) fn read_exact_checked<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    offset: u64,
+) -> std::io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "short read at offset {offset}: got {filled} of {} bytes",
+                        buf.len()
+                    ),
+                ));
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Smallest encoding of one array element of each type; arrays of arrays need at least their
+/// own type code and length.
+fn min_encoded_size(value_type: DataType) -> u64 {
+    match value_type {
+        DataType::Uint8 | DataType::Int8 | DataType::Bool => 1,
+        DataType::Uint16 | DataType::Int16 => 2,
+        DataType::Uint32 | DataType::Int32 | DataType::Float32 => 4,
+        DataType::Uint64 | DataType::Int64 | DataType::Float64 | DataType::String => 8,
+        DataType::Array => 12,
+    }
+}
+
+/// An array being filled by [`Reader::read_array`].
+struct ArrayFrame {
+    value_type: DataType,
+    remaining: u64,
+    items: Vec<Data>,
+}
+
 pub struct Reader<R: BufRead + Seek> {
     buffer: R,
     pos: u64,
+    /// Logical position of the end of input, if the underlying buffer could report it.
+    end: Option<u64>,
+    limits: MetadataLimits,
+    metadata_bytes: u64,
 }
 
 impl<R: BufRead + Seek> Reader<R> {
-    pub fn new(buffer: R, initial_pos: u64) -> Self {
+    /// `initial_pos` is the logical position of the buffer's current position. Strings and arrays
+    /// are checked against [`MetadataLimits::default`]; see [`Reader::with_limits`].
+    pub fn new(mut buffer: R, initial_pos: u64) -> Self {
+        let end = Self::input_len(&mut buffer).map(|len| initial_pos + len);
         Reader {
             buffer,
             pos: initial_pos,
+            end,
+            limits: MetadataLimits::default(),
+            metadata_bytes: 0,
         }
     }
 
+    /// Bytes from the current position to the end, leaving the position unchanged. Done once,
+    /// before any read, since seeking drops the buffer.
+    fn input_len(buffer: &mut R) -> Option<u64> {
+        let here = buffer.stream_position().ok()?;
+        let end = buffer.seek(SeekFrom::End(0)).ok()?;
+        buffer.seek(SeekFrom::Start(here)).ok()?;
+        Some(end.saturating_sub(here))
+    }
+
+    pub fn with_limits(mut self, limits: MetadataLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub fn position(&self) -> u64 {
         self.pos
     }
 
+    /// Bytes left before the end of input (`u64::MAX` if unknown).
+    pub fn remaining(&self) -> u64 {
+        self.end
+            .map_or(u64::MAX, |end| end.saturating_sub(self.pos))
+    }
+
+    /// Decoded metadata bytes charged against [`MetadataLimits::max_metadata_bytes`] so far.
+    pub fn metadata_bytes(&self) -> u64 {
+        self.metadata_bytes
+    }
+
+    fn charge(&mut self, bytes: u64, offset: u64) -> Result<(), MetadataLimitError> {
+        self.metadata_bytes = self.metadata_bytes.saturating_add(bytes);
+        if self.metadata_bytes > self.limits.max_metadata_bytes {
+            return Err(MetadataLimitError::BudgetExceeded {
+                offset,
+                max: self.limits.max_metadata_bytes,
+            });
+        }
+        Ok(())
+    }
+
     /// Seek to a specific position in the file
     /// Verifies the actual position after seeking to catch buffer synchronization issues
     pub fn seek(&mut self, pos: u64) -> Result<(), EngineError> {
@@ -41,12 +142,39 @@ impl<R: BufRead + Seek> Reader<R> {
     }
 
     pub fn read_bytes(&mut self, size: u64) -> Result<Vec<u8>, EngineError> {
+        // A size from the file is untrusted: fail like `read_exact` would, before allocating.
+        self.check_remaining(size)?;
         let mut vec = vec![0u8; size as usize];
+        self.read_into(&mut vec)?;
+        Ok(vec)
+    }
+
+    /// Fail with `UnexpectedEof` if fewer than `size` bytes are left; call before allocating a
+    /// buffer for an untrusted size.
+    pub fn check_remaining(&self, size: u64) -> Result<(), EngineError> {
+        let remaining = self.remaining();
+        if size > remaining {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "read of {size} bytes at offset {} past end of input ({remaining} left)",
+                    self.pos
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fill `buf` from the current position.
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<(), EngineError> {
         // Read sequentially - BufReader handles buffering automatically
         // No seek needed for sequential reads (seeking invalidates the buffer!)
-        self.buffer.read_exact(&mut vec)?;
-        self.pos += size;
-        Ok(vec)
+        // The length check in `read_bytes` used the size at open; the file can still shrink
+        // under us.
+        read_exact_checked(&mut self.buffer, buf, self.pos)?;
+        self.pos += buf.len() as u64;
+        Ok(())
     }
 
     // Type-specific read methods
@@ -115,43 +243,211 @@ impl<R: BufRead + Seek> Reader<R> {
     }
 
     pub fn read_string(&mut self) -> Result<String, EngineError> {
-        let str_len_bytes = self.read_bytes(8)?;
-        let str_len = u64::from_le_bytes(le_array(str_len_bytes)?);
+        let offset = self.pos;
+        let str_len = self.read_u64()?;
+        if str_len > self.limits.max_string_bytes {
+            return Err(MetadataLimitError::StringTooLong {
+                offset,
+                len: str_len,
+                max: self.limits.max_string_bytes,
+            }
+            .into());
+        }
+        self.charge(str_len, offset)?;
         let str_as_bytes = self.read_bytes(str_len)?;
         let str = String::from_utf8(str_as_bytes)?;
         Ok(str)
     }
 
+    /// Read one value of `value_type` (arrays included).
+    pub fn read_value(&mut self, value_type: DataType) -> Result<Data, EngineError> {
+        let value = match value_type {
+            DataType::Uint8 => Data::Uint8(self.read_u8()?),
+            DataType::Int8 => Data::Int8(self.read_i8()?),
+            DataType::Uint16 => Data::Uint16(self.read_u16()?),
+            DataType::Int16 => Data::Int16(self.read_i16()?),
+            DataType::Uint32 => Data::Uint32(self.read_u32()?),
+            DataType::Int32 => Data::Int32(self.read_i32()?),
+            DataType::Float32 => Data::Float32(self.read_f32()?),
+            DataType::Uint64 => Data::Uint64(self.read_u64()?),
+            DataType::Int64 => Data::Int64(self.read_i64()?),
+            DataType::Float64 => Data::Float64(self.read_f64()?),
+            DataType::Bool => Data::Bool(self.read_bool()?),
+            DataType::String => Data::String(self.read_string()?),
+            DataType::Array => Data::Array(self.read_array()?),
+        };
+        Ok(value)
+    }
+
+    /// Read an array, nested arrays included. Nesting is walked with an explicit stack of
+    /// `ArrayFrame`s, so depth is bounded by [`MetadataLimits::max_array_depth`] rather than
+    /// by the thread's stack.
     pub fn read_array(&mut self) -> Result<Vec<Data>, EngineError> {
-        // First, read the type stored in the array, value type is stored as 4 bytes
-        let value_type_bytes = self.read_bytes(4)?;
-        let value_type: DataType =
-            u32_to_data_type(u32::from_le_bytes(le_array(value_type_bytes)?))?;
+        let mut stack = vec![self.array_frame(1)?];
+        while let Some(top) = stack.last_mut() {
+            if top.remaining == 0 {
+                let done = stack.pop().map(|f| f.items).unwrap_or_default();
+                match stack.last_mut() {
+                    Some(parent) => parent.items.push(Data::Array(done)),
+                    None => return Ok(done),
+                }
+                continue;
+            }
+            top.remaining -= 1;
+            if top.value_type == DataType::Array {
+                let frame = self.array_frame(stack.len() + 1)?;
+                stack.push(frame);
+            } else {
+                let value = self.read_value(top.value_type)?;
+                top.items.push(value);
+            }
+        }
+        unreachable!("the outermost frame returns when it completes")
+    }
 
+    /// Read an array's element type and length, checking them before reserving space.
+    fn array_frame(&mut self, depth: usize) -> Result<ArrayFrame, EngineError> {
+        let offset = self.pos;
+        if depth > self.limits.max_array_depth {
+            return Err(MetadataLimitError::ArrayTooDeep {
+                offset,
+                depth,
+                max: self.limits.max_array_depth,
+            }
+            .into());
+        }
+        // First, read the type stored in the array, value type is stored as 4 bytes
+        let value_type = u32_to_data_type(self.read_u32()?)?;
         // Once you have the type, read the array len
         // Len is u64 so 8 bytes
-        let array_len = self.read_u64()?;
-
-        let mut result: Vec<Data> = Vec::with_capacity(array_len as usize);
-
-        for _ in 0..array_len {
-            let value = match value_type {
-                DataType::Uint8 => Data::Uint8(self.read_u8()?),
-                DataType::Int8 => Data::Int8(self.read_i8()?),
-                DataType::Uint16 => Data::Uint16(self.read_u16()?),
-                DataType::Int16 => Data::Int16(self.read_i16()?),
-                DataType::Uint32 => Data::Uint32(self.read_u32()?),
-                DataType::Int32 => Data::Int32(self.read_i32()?),
-                DataType::Float32 => Data::Float32(self.read_f32()?),
-                DataType::Uint64 => Data::Uint64(self.read_u64()?),
-                DataType::Int64 => Data::Int64(self.read_i64()?),
-                DataType::Float64 => Data::Float64(self.read_f64()?),
-                DataType::Bool => Data::Bool(self.read_bool()?),
-                DataType::String => Data::String(self.read_string()?),
-                DataType::Array => Data::Array(self.read_array()?),
-            };
-            result.push(value);
+        let len = self.read_u64()?;
+        if len > self.limits.max_array_len {
+            return Err(MetadataLimitError::ArrayTooLong {
+                offset,
+                len,
+                max: self.limits.max_array_len,
+            }
+            .into());
+        }
+        let min_bytes = len.saturating_mul(min_encoded_size(value_type));
+        let remaining = self.remaining();
+        if min_bytes > remaining {
+            return Err(MetadataLimitError::ArrayPastEnd {
+                offset,
+                len,
+                min_bytes,
+                remaining,
+            }
+            .into());
+        }
+        self.charge(len.saturating_mul(size_of::<Data>() as u64), offset)?;
+        Ok(ArrayFrame {
+            value_type,
+            remaining: len,
+            items: Vec::with_capacity(len as usize),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use std::io::Cursor;
+
+    /// Hands out at most one byte per call, after one interrupted call.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        interrupted: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.interrupted {
+                self.interrupted = true;
+                return Err(ErrorKind::Interrupted.into());
+            }
+            let n = self.bytes.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_exact_checked_reports_how_much_a_short_read_got() {
+        let mut buf = [0u8; 4];
+        let mut trickle = Trickle {
+            bytes: &[1, 2, 3, 4, 5],
+            interrupted: false,
+        };
+        read_exact_checked(&mut trickle, &mut buf, 0).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        let mut buf = [0u8; 8];
+        let mut trickle = Trickle {
+            bytes: &[7, 7, 7],
+            interrupted: false,
+        };
+        let err = read_exact_checked(&mut trickle, &mut buf, 100).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(
+            err.to_string(),
+            "short read at offset 100: got 3 of 8 bytes"
+        );
+    }
+
+    #[test]
+    fn file_truncated_after_open_is_an_error_not_zeros() {
+        let dir = TempDir::new("truncated_read");
+        let path = dir.join("input.bin");
+        std::fs::write(&path, [0xAB; 64]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let mut reader = Reader::new(std::io::BufReader::with_capacity(8, file), 0);
+        assert_eq!(reader.read_bytes(8).unwrap(), [0xAB; 8]);
+
+        // The size check passed against the size at open; the read itself must catch this.
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(20)
+            .unwrap();
+        assert_eq!(reader.remaining(), 56);
+        let err = reader.read_bytes(32).unwrap_err();
+        assert!(
+            matches!(&err, EngineError::Io(e) if e.kind() == ErrorKind::UnexpectedEof),
+            "{err}"
+        );
+        assert!(
+            err.to_string()
+                .contains("short read at offset 8: got 12 of 32 bytes"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn nested_arrays_round_trip_through_the_explicit_stack() {
+        // [[1, 2], [], [3]] as arrays of u8.
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend_from_slice(&3u64.to_le_bytes());
+        for inner in [&[1u8, 2][..], &[], &[3]] {
+            buf.extend_from_slice(&0u32.to_le_bytes());
+            buf.extend_from_slice(&(inner.len() as u64).to_le_bytes());
+            buf.extend_from_slice(inner);
         }
-        Ok(result)
+        let mut reader = Reader::new(Cursor::new(buf.as_slice()), 0);
+        let outer = reader.read_array().unwrap();
+        let lens: Vec<usize> = outer
+            .iter()
+            .map(|d| match d {
+                Data::Array(a) => a.len(),
+                other => panic!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(lens, [2, 0, 1]);
+        assert!(matches!(&outer[0], Data::Array(a) if matches!(a[1], Data::Uint8(2))));
+        assert_eq!(reader.position(), buf.len() as u64);
     }
 }
@@ -0,0 +1,284 @@
+//! Short aliases for local GGUF files, so `--model mistral` works from any directory.
+//!
+//! The registry is one JSON file ([`default_registry_path`] unless a path is passed in) mapping
+//! each alias to a [`RegistryEntry`]: the absolute path, the file size, a [`content_hash`] of the
+//! first [`HASH_PREFIX_BYTES`] (the header and the start of the weights), and metadata read
+//! without loading tensors. [`ModelRegistry::lookup`] re-checks size and hash; a file that changed
+//! in place (re-downloaded, re-quantized) is re-read with a warning, while a file that is gone is
+//! an error naming the alias, since the registry cannot know where it went.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::EngineError;
+use crate::model_loader::discovery::{ResolvedModel, resolve_model_path};
+use crate::model_loader::file_loader::read_file;
+use crate::model_loader::gguf_types::{Data, Fnv1a};
+
+/// Bytes from the start of the file covered by [`content_hash`].
+pub const HASH_PREFIX_BYTES: u64 = 1 << 20;
+
+/// Overrides [`default_registry_path`].
+pub const REGISTRY_ENV: &str = "INFERENCE_ENGINE_REGISTRY";
+
+/// One registered model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// Absolute path of the GGUF file (the first shard of a split set).
+    pub path: PathBuf,
+    pub size: u64,
+    /// [`content_hash`] when the entry was last refreshed.
+    pub hash: String,
+    /// `general.architecture`.
+    pub architecture: Option<String>,
+    /// [`GGUFData::parameter_count`](crate::model_loader::gguf_types::GGUFData::parameter_count).
+    pub parameter_count: u64,
+    /// `general.file_type` (llama.cpp's `LLAMA_FTYPE_*` number).
+    pub file_type: Option<u32>,
+}
+
+impl RegistryEntry {
+    /// Hash, size and metadata of the file at `path` as it is now.
+    pub fn read(path: &Path) -> Result<Self, EngineError> {
+        let path = std::path::absolute(path)?;
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| EngineError::Model("model path is not valid UTF-8".into()))?;
+        let gguf = read_file(path_str)?;
+        let architecture = match gguf.get_metadata("general.architecture") {
+            Some(Data::String(a)) => Some(a.clone()),
+            _ => None,
+        };
+        let file_type = match gguf.get_metadata("general.file_type") {
+            Some(Data::Uint32(t)) => Some(*t),
+            Some(Data::Int32(t)) => u32::try_from(*t).ok(),
+            _ => None,
+        };
+        Ok(Self {
+            size: std::fs::metadata(&path)?.len(),
+            hash: content_hash(&path)?,
+            architecture,
+            parameter_count: gguf.parameter_count(),
+            file_type,
+            path,
+        })
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    #[error("alias {alias:?} is already registered for {}; remove it first", .path.display())]
+    AliasTaken { alias: String, path: PathBuf },
+
+    #[error("no model registered as {0:?}")]
+    UnknownAlias(String),
+
+    #[error("invalid alias {0:?}: use a non-empty name without path separators or glob characters")]
+    InvalidAlias(String),
+
+    #[error(
+        "model {alias:?} is registered at {} but the file is gone; if it moved, remove the alias and add it again",
+        .path.display()
+    )]
+    Missing { alias: String, path: PathBuf },
+}
+
+/// What [`ModelRegistry::lookup`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub entry: RegistryEntry,
+    /// The file had changed since it was registered and the entry was re-read; the registry
+    /// needs [`ModelRegistry::save`] to keep that.
+    pub refreshed: bool,
+}
+
+/// Aliases loaded from (and saved back to) one registry file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelRegistry {
+    path: PathBuf,
+    models: BTreeMap<String, RegistryEntry>,
+}
+
+/// On-disk layout.
+#[derive(Serialize, Deserialize, Default)]
+struct RegistryFile {
+    models: BTreeMap<String, RegistryEntry>,
+}
+
+impl ModelRegistry {
+    /// Load the registry at `path`; a missing file is an empty registry.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, EngineError> {
+        let path = path.into();
+        let file = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<RegistryFile>(&json).map_err(|e| {
+                EngineError::Model(format!("invalid model registry {}: {e}", path.display()))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RegistryFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            models: file.models,
+        })
+    }
+
+    /// Write the registry back, creating its directory if needed. Written to a temporary file
+    /// and renamed, so an interrupted save leaves the old registry intact.
+    pub fn save(&self) -> Result<(), EngineError> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = RegistryFile {
+            models: self.models.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file).expect("registry serializes");
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Register `model` (a file, directory or glob, as for `--model`) as `alias`. Reads only the
+    /// header and the first [`HASH_PREFIX_BYTES`].
+    pub fn add(&mut self, alias: &str, model: &Path) -> Result<&RegistryEntry, EngineError> {
+        if !is_alias(alias) {
+            return Err(RegistryError::InvalidAlias(alias.into()).into());
+        }
+        if let Some(taken) = self.models.get(alias) {
+            return Err(RegistryError::AliasTaken {
+                alias: alias.into(),
+                path: taken.path.clone(),
+            }
+            .into());
+        }
+        let resolved = resolve_model_path(model)?;
+        let entry = RegistryEntry::read(resolved.primary())?;
+        Ok(self.models.entry(alias.into()).or_insert(entry))
+    }
+
+    /// Drop `alias`, returning what it pointed to. The file itself is left alone.
+    pub fn remove(&mut self, alias: &str) -> Result<RegistryEntry, RegistryError> {
+        self.models
+            .remove(alias)
+            .ok_or_else(|| RegistryError::UnknownAlias(alias.into()))
+    }
+
+    /// Aliases in name order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &RegistryEntry)> {
+        self.models.iter().map(|(alias, e)| (alias.as_str(), e))
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&RegistryEntry> {
+        self.models.get(alias)
+    }
+
+    /// The entry for `alias`, checked against the file: if its size or [`content_hash`] changed,
+    /// the entry is re-read (with a warning) and [`Lookup::refreshed`] is set.
+    pub fn lookup(&mut self, alias: &str) -> Result<Lookup, EngineError> {
+        let entry = self
+            .models
+            .get_mut(alias)
+            .ok_or_else(|| RegistryError::UnknownAlias(alias.into()))?;
+        let size = match std::fs::metadata(&entry.path) {
+            Ok(m) if m.is_file() => m.len(),
+            _ => {
+                return Err(RegistryError::Missing {
+                    alias: alias.into(),
+                    path: entry.path.clone(),
+                }
+                .into());
+            }
+        };
+        if size == entry.size && content_hash(&entry.path)? == entry.hash {
+            return Ok(Lookup {
+                entry: entry.clone(),
+                refreshed: false,
+            });
+        }
+        log::warn!(
+            "{} changed since it was registered as {alias:?}; refreshing its entry",
+            entry.path.display()
+        );
+        *entry = RegistryEntry::read(&entry.path)?;
+        Ok(Lookup {
+            entry: entry.clone(),
+            refreshed: true,
+        })
+    }
+}
+
+/// Resolve `--model`: an existing path, glob or directory goes to [`resolve_model_path`];
+/// otherwise a registered alias in the registry at `registry` (saved back if the entry was
+/// refreshed). Anything else fails as [`resolve_model_path`] would.
+pub fn resolve_model(input: &Path, registry: &Path) -> Result<ResolvedModel, EngineError> {
+    let alias = input.to_str().filter(|a| is_alias(a));
+    if let Some(alias) = alias.filter(|_| !input.exists()) {
+        let mut models = ModelRegistry::open(registry)?;
+        if models.get(alias).is_some() {
+            let lookup = models.lookup(alias)?;
+            if lookup.refreshed {
+                models.save()?;
+            }
+            return resolve_model_path(&lookup.entry.path);
+        }
+    }
+    resolve_model_path(input)
+}
+
+/// 16 hex digits of FNV-1a over the file size and its first [`HASH_PREFIX_BYTES`]: cheap enough
+/// for every lookup, and it covers the GGUF header, so re-quantized or re-converted files differ.
+pub fn content_hash(path: &Path) -> Result<String, EngineError> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut prefix = Vec::new();
+    file.take(HASH_PREFIX_BYTES).read_to_end(&mut prefix)?;
+    let mut h = Fnv1a::default();
+    h.write(&size.to_le_bytes());
+    h.write(&prefix);
+    Ok(format!("{:016x}", h.0))
+}
+
+/// `$INFERENCE_ENGINE_REGISTRY`, else `models.json` under `$XDG_CONFIG_HOME/inference_engine_rust`
+/// or `~/.config/inference_engine_rust`; `None` if neither variable is set.
+pub fn default_registry_path() -> Option<PathBuf> {
+    let var = |key: &str| std::env::var_os(key).filter(|v| !v.is_empty());
+    if let Some(path) = var(REGISTRY_ENV) {
+        return Some(path.into());
+    }
+    let config = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("inference_engine_rust").join("models.json"))
+}
+
+/// Plain names only, so an alias can never be mistaken for a path or a glob.
+fn is_alias(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('.')
+        && !s.contains(['/', '\\', '*', '?'])
+        && !s.chars().any(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_are_plain_names() {
+        for ok in ["mistral", "gemma-4-e2b", "q4_k_m.v2"] {
+            assert!(is_alias(ok), "{ok}");
+        }
+        for bad in ["", ".", "..", "models/x", "a\\b", "*q4*", "two words"] {
+            assert!(!is_alias(bad), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Sidecar files of converted tensors, shared between processes.
+//!
+//! With [`LoadOptions::shared_cache`], tensors a load keeps as stored are views into a read-only
+//! [`Mapping`] of the GGUF, and tensors it converts (BF16 widening, dtype overrides) are written
+//! once to [`sidecar_path`], next to the model. Later loads with the same options map the sidecar
+//! instead of converting again, so every process on the machine shares one copy of each weight
+//! through the page cache.
+//!
+//! Layout: a little-endian `u64` header length, a JSON header, then each tensor's bytes at a
+//! 32-byte aligned offset from the end of the header. The header records the model's
+//! [`content_hash`] and [`options_hash`]; a sidecar whose hashes or version do not match is stale
+//! and gets rebuilt. `content_hash` only covers the start of the file, so each entry also records
+//! a [`source_hash`] of the GGUF bytes it was converted from: an entry whose source changed (a
+//! re-exported fine-tune of the same size and header) is converted again and the sidecar
+//! rewritten. Sidecars are written to a temporary file and renamed into place, so a reader never
+//! sees a partial one.
+//!
+//! [`LoadOptions::shared_cache`]: super::gguf_types::LoadOptions::shared_cache
+//! [`content_hash`]: super::registry::content_hash
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::EngineError;
+use crate::core::tensor::{Tensor, TensorType};
+use crate::model_loader::dtype_overrides::{
+    AppliedOverride, InMemoryDtype, WeightRole, planned_override,
+};
+use crate::model_loader::gguf_types::Fnv1a;
+use crate::model_loader::registry::content_hash;
+use crate::model_loader::storage::Mapping;
+use crate::model_loader::tensor::GgmlType;
+
+const MAGIC: &str = "inference_engine_rust sidecar";
+/// Bump when the layout or any conversion changes, so old sidecars read as stale.
+const VERSION: u32 = 2;
+const ALIGN: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    magic: String,
+    version: u32,
+    model_hash: String,
+    options_hash: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    name: String,
+    dtype: TensorType,
+    dims: Vec<usize>,
+    /// From the start of the data section.
+    offset: usize,
+    len: usize,
+    /// [`source_hash`] of the GGUF bytes this tensor was converted from.
+    #[serde(default)]
+    source_hash: String,
+}
+
+/// Hash of every byte a converted tensor was made from, so a sidecar entry is only reused for
+/// the exact data it came from.
+pub fn source_hash(bytes: &[u8]) -> String {
+    let mut h = Fnv1a::default();
+    h.write(bytes);
+    format!("{:016x}", h.0)
+}
+
+/// Hash of the conversions a load applies, as given by
+/// [`super::gguf_types::LoadOptions::effective_overrides`]. `AsIs` entries are skipped, so they
+/// hash like an absent role.
+pub fn options_hash(overrides: &BTreeMap<WeightRole, InMemoryDtype>) -> u64 {
+    let mut h = Fnv1a::default();
+    h.write(&VERSION.to_le_bytes());
+    for (role, dtype) in overrides {
+        if *dtype != InMemoryDtype::AsIs {
+            h.write_str(&format!("{role:?}={dtype:?}"));
+        }
+    }
+    h.0
+}
+
+/// `<model>.<options hash>.sidecar`: one sidecar per set of options.
+pub fn sidecar_path(model: &Path, options_hash: u64) -> PathBuf {
+    let mut name = OsString::from(model.as_os_str());
+    name.push(format!(".{options_hash:016x}.sidecar"));
+    name.into()
+}
+
+/// One load's view of the shared cache: the mapped GGUF and, if a valid one exists, its sidecar.
+pub(crate) struct SharedCache {
+    gguf: Arc<Mapping>,
+    model_hash: String,
+    options_hash: u64,
+    path: PathBuf,
+    sidecar: Option<Sidecar>,
+}
+
+/// Where [`SharedCache::lookup`] found a tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hit {
+    /// Kept as stored: a view of the mapped GGUF.
+    Gguf,
+    /// Converted by an earlier load: a view of the sidecar.
+    Sidecar,
+}
+
+impl SharedCache {
+    /// Map the GGUF at `model` (open as `file`) and the sidecar for `overrides`, if valid.
+    pub(crate) fn open(
+        model: &Path,
+        file: &File,
+        overrides: &BTreeMap<WeightRole, InMemoryDtype>,
+    ) -> Result<Self, EngineError> {
+        let model_hash = content_hash(model)?;
+        let options_hash = options_hash(overrides);
+        let path = sidecar_path(model, options_hash);
+        Ok(Self {
+            gguf: Arc::new(Mapping::open(file)?),
+            sidecar: Sidecar::open(&path, &model_hash, options_hash),
+            model_hash,
+            options_hash,
+            path,
+        })
+    }
+
+    /// The tensor called `name`, stored as `ggml` at `range` of the GGUF, as the load would
+    /// produce it, without reading or converting anything. `None` when it has to be loaded: a
+    /// converted tensor the sidecar lacks, or data the mapping cannot view (misaligned F32).
+    pub(crate) fn lookup(
+        &self,
+        name: &str,
+        ggml: GgmlType,
+        range: Range<usize>,
+        dims: &[usize],
+        overrides: &BTreeMap<WeightRole, InMemoryDtype>,
+    ) -> Option<(Tensor, Option<AppliedOverride>, Hit)> {
+        let widened = ggml == GgmlType::BF16;
+        let held = if widened {
+            TensorType::F32
+        } else {
+            ggml.to_tensor_type().ok()?
+        };
+        let applied = planned_override(name, held, overrides);
+        if widened || applied.is_some() {
+            let tensor = self.cached(name, range)?;
+            return Some((tensor, applied, Hit::Sidecar));
+        }
+        let tensor = Tensor::mapped(held, Arc::clone(&self.gguf), range, dims.to_vec()).ok()?;
+        Some((tensor, None, Hit::Gguf))
+    }
+
+    /// Tensor `name` from the existing sidecar, if it was converted from the GGUF bytes now at
+    /// `source`; used for lookups and to carry entries over into a rebuilt sidecar.
+    pub(crate) fn cached(&self, name: &str, source: Range<usize>) -> Option<Tensor> {
+        let source = self.gguf.get(source)?;
+        self.sidecar.as_ref()?.tensor(name, source)?.ok()
+    }
+
+    /// Replace the sidecar with `tensors`, each with the GGUF byte range it was converted from.
+    pub(crate) fn write(
+        &self,
+        tensors: &[(&str, &Tensor, Range<usize>)],
+    ) -> Result<(), EngineError> {
+        let hashed: Vec<(&str, &Tensor, String)> = tensors
+            .iter()
+            .map(|(name, t, source)| {
+                let bytes = self.gguf.get(source.clone()).ok_or_else(|| {
+                    EngineError::Model(format!("{name}: source range {source:?} past end of file"))
+                })?;
+                Ok((*name, *t, source_hash(bytes)))
+            })
+            .collect::<Result<_, EngineError>>()?;
+        write(&self.path, &self.model_hash, self.options_hash, &hashed)
+    }
+}
+
+/// A mapped sidecar whose hashes matched the model and options.
+struct Sidecar {
+    map: Arc<Mapping>,
+    data_start: usize,
+    entries: HashMap<String, Entry>,
+}
+
+impl Sidecar {
+    /// Map the sidecar at `path`; `None` if there is none, or it is stale or unreadable (logged).
+    fn open(path: &Path, model_hash: &str, options_hash: u64) -> Option<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("{}: cannot open sidecar: {e}", path.display());
+                return None;
+            }
+        };
+        let map = match Mapping::open(&file) {
+            Ok(map) => map,
+            Err(e) => {
+                warn!("{}: cannot map sidecar: {e}", path.display());
+                return None;
+            }
+        };
+        let Some((header, data_start)) = parse_header(&map) else {
+            warn!("{}: unreadable sidecar, rebuilding", path.display());
+            return None;
+        };
+        if header.magic != MAGIC
+            || header.version != VERSION
+            || header.model_hash != model_hash
+            || header.options_hash != format!("{options_hash:016x}")
+        {
+            info!("{}: stale sidecar, rebuilding", path.display());
+            return None;
+        }
+        debug!(
+            "{}: sidecar with {} tensors",
+            path.display(),
+            header.entries.len()
+        );
+        Some(Self {
+            map: Arc::new(map),
+            data_start,
+            entries: header
+                .entries
+                .into_iter()
+                .map(|e| (e.name.clone(), e))
+                .collect(),
+        })
+    }
+
+    /// The tensor called `name`, mapped without copying; `None` if the sidecar does not hold it
+    /// or holds one converted from other bytes than `source`.
+    fn tensor(&self, name: &str, source: &[u8]) -> Option<Result<Tensor, EngineError>> {
+        let entry = self.entries.get(name)?;
+        if entry.source_hash != source_hash(source) {
+            info!("{name}: GGUF data changed since the sidecar was written, converting again");
+            return None;
+        }
+        let start = self.data_start.checked_add(entry.offset)?;
+        let end = start.checked_add(entry.len)?;
+        Some(Tensor::mapped(
+            entry.dtype,
+            Arc::clone(&self.map),
+            start..end,
+            entry.dims.clone(),
+        ))
+    }
+}
+
+fn parse_header(bytes: &[u8]) -> Option<(Header, usize)> {
+    let len = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+    let end = 8usize.checked_add(usize::try_from(len).ok()?)?;
+    let header = serde_json::from_slice(bytes.get(8..end)?).ok()?;
+    Some((header, end.next_multiple_of(ALIGN)))
+}
+
+/// Write `tensors` as the sidecar at `path`, replacing any existing one atomically.
+fn write(
+    path: &Path,
+    model_hash: &str,
+    options_hash: u64,
+    tensors: &[(&str, &Tensor, String)],
+) -> Result<(), EngineError> {
+    let mut offset = 0;
+    let entries = tensors
+        .iter()
+        .map(|(name, t, source_hash)| {
+            let entry = Entry {
+                name: name.to_string(),
+                dtype: t.dtype(),
+                dims: t.dimensions().to_vec(),
+                offset,
+                len: t.buffer().len(),
+                source_hash: source_hash.clone(),
+            };
+            offset = (offset + entry.len).next_multiple_of(ALIGN);
+            entry
+        })
+        .collect();
+    let header = serde_json::to_vec(&Header {
+        magic: MAGIC.into(),
+        version: VERSION,
+        model_hash: model_hash.into(),
+        options_hash: format!("{options_hash:016x}"),
+        entries,
+    })
+    .expect("sidecar header serializes");
+
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let written = (|| -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        w.write_all(&(header.len() as u64).to_le_bytes())?;
+        w.write_all(&header)?;
+        pad(&mut w, 8 + header.len())?;
+        for (_, t, _) in tensors {
+            w.write_all(t.buffer())?;
+            pad(&mut w, t.buffer().len())?;
+        }
+        w.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(written?)
+}
+
+/// Zeros from `written` up to the next multiple of [`ALIGN`].
+fn pad(w: &mut impl Write, written: usize) -> io::Result<()> {
+    w.write_all(&[0; ALIGN][..written.next_multiple_of(ALIGN) - written])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TempDir, f32_tensor};
+
+    const MODEL_HASH: &str = "0123456789abcdef";
+    const OPTIONS_HASH: u64 = 7;
+
+    /// A 34-byte Q8_0 tensor `a` and a 3-element F32 tensor `b`, both converted from `source`.
+    fn tensors() -> (Tensor, Tensor) {
+        let a = Tensor::from_bytes(TensorType::Q8_0, (0..34).collect(), vec![32]).unwrap();
+        (a, f32_tensor(&[1.0, -2.0, 0.5], vec![3]))
+    }
+
+    fn write_sidecar(dir: &TempDir) -> PathBuf {
+        let path = dir.join("model.gguf.sidecar");
+        let (a, b) = tensors();
+        let hash = source_hash(b"source");
+        let entries = [("a", &a, hash.clone()), ("b", &b, hash)];
+        write(&path, MODEL_HASH, OPTIONS_HASH, &entries).unwrap();
+        path
+    }
+
+    #[test]
+    fn header_parses_back_and_every_tensor_starts_aligned() {
+        let dir = TempDir::new("sidecar_header");
+        let path = write_sidecar(&dir);
+        let bytes = fs::read(&path).unwrap();
+        let (header, data_start) = parse_header(&bytes).unwrap();
+        assert_eq!((header.magic.as_str(), header.version), (MAGIC, VERSION));
+        assert_eq!(header.model_hash, MODEL_HASH);
+        assert_eq!(header.options_hash, format!("{OPTIONS_HASH:016x}"));
+        assert_eq!(data_start % ALIGN, 0);
+        let layout: Vec<(&str, usize, usize)> = header
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.offset, e.len))
+            .collect();
+        assert_eq!(layout, [("a", 0, 34), ("b", 64, 12)]);
+        assert_eq!(bytes.len(), data_start + 64 + ALIGN);
+
+        let sidecar = Sidecar::open(&path, MODEL_HASH, OPTIONS_HASH).unwrap();
+        let (a, b) = tensors();
+        let mapped_a = sidecar.tensor("a", b"source").unwrap().unwrap();
+        assert_eq!(mapped_a.buffer(), a.buffer());
+        let mapped_b = sidecar.tensor("b", b"source").unwrap().unwrap();
+        assert_eq!(mapped_b.as_f32_slice().unwrap(), b.as_f32_slice().unwrap());
+    }
+
+    #[test]
+    fn truncated_or_malformed_headers_do_not_parse() {
+        let json = br#"{"magic":"x"}"#;
+        let framed = |len: u64, body: &[u8]| [&len.to_le_bytes()[..], body].concat();
+        assert!(parse_header(&[]).is_none());
+        assert!(parse_header(&[0; 7]).is_none());
+        assert!(parse_header(&framed(json.len() as u64 + 1, json)).is_none());
+        assert!(parse_header(&framed(u64::MAX, json)).is_none());
+        assert!(parse_header(&framed(9, b"{not json")).is_none());
+        // Well-formed JSON missing fields is not a header either.
+        assert!(parse_header(&framed(json.len() as u64, json)).is_none());
+    }
+
+    #[test]
+    fn stale_hashes_and_changed_sources_are_not_reused() {
+        let dir = TempDir::new("sidecar_stale");
+        let path = write_sidecar(&dir);
+        assert!(Sidecar::open(&path, "fedcba9876543210", OPTIONS_HASH).is_none());
+        assert!(Sidecar::open(&path, MODEL_HASH, OPTIONS_HASH + 1).is_none());
+        assert!(Sidecar::open(&dir.join("absent.sidecar"), MODEL_HASH, OPTIONS_HASH).is_none());
+
+        let sidecar = Sidecar::open(&path, MODEL_HASH, OPTIONS_HASH).unwrap();
+        assert!(sidecar.tensor("a", b"source").is_some());
+        assert!(sidecar.tensor("a", b"re-exported").is_none());
+        assert!(sidecar.tensor("missing", b"source").is_none());
+    }
+}
@@ -0,0 +1,294 @@
+//! OS readahead hints and read-only file mappings for tensor loading.
+//!
+//! Loading streams most of a multi-GB file once, front to back. On slow media (SD cards, network
+//! disks) the kernel's default readahead leaves each `read` waiting on the device; telling it the
+//! access pattern up front, and which byte range comes next, lets it fetch while the previous
+//! tensor is being copied out. Hints are advisory: a failed or unsupported hint never fails a
+//! load, it is only counted in [`super::gguf_types::LoadStats`].
+//!
+//! | Platform | [`Advice::Sequential`]                 | [`Advice::WillNeed`]                 |
+//! |----------|----------------------------------------|--------------------------------------|
+//! | Linux    | `posix_fadvise(POSIX_FADV_SEQUENTIAL)` | `posix_fadvise(POSIX_FADV_WILLNEED)` |
+//! | macOS    | `fcntl(F_RDAHEAD, 1)`                  | `fcntl(F_RDADVISE)`                  |
+//! | other    | no-op                                  | no-op                                |
+//!
+//! [`Mapping`] maps a whole file read-only (`mmap(PROT_READ, MAP_SHARED)` on Unix), so processes
+//! loading the same model share one copy of its pages through the page cache. Elsewhere it reads
+//! the file into memory: same bytes, no sharing.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+/// Expected access to a byte range of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// The range will be read front to back; readahead more aggressively.
+    Sequential,
+    /// The range will be read soon; start fetching it now.
+    WillNeed,
+}
+
+/// Issue `advice` for `len` bytes at `offset` (`len == 0`: to the end of the file). Returns
+/// `Ok(true)` if the OS accepted the hint and `Ok(false)` where hints are not supported.
+pub fn advise(file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool> {
+    imp::advise(file, offset, len, advice)
+}
+
+/// Where [`crate::model_loader::gguf_types::GGUFData`] sends its hints; replaceable so tests can
+/// observe them.
+pub trait Advisor {
+    fn advise(&self, file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool>;
+}
+
+/// [`advise`] for the current platform.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemAdvisor;
+
+impl Advisor for SystemAdvisor {
+    fn advise(&self, file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool> {
+        advise(file, offset, len, advice)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::Advice;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    pub fn advise(file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool> {
+        let flag = match advice {
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        };
+        let offset = libc::off_t::try_from(offset).unwrap_or(libc::off_t::MAX);
+        let len = libc::off_t::try_from(len).unwrap_or(0);
+        // SAFETY: plain syscall on a valid, open descriptor; no memory is passed.
+        match unsafe { libc::posix_fadvise(file.as_raw_fd(), offset, len, flag) } {
+            0 => Ok(true),
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::Advice;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    pub fn advise(file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool> {
+        let fd = file.as_raw_fd();
+        // SAFETY: `fcntl` on a valid, open descriptor; `ra` outlives the call.
+        let rc = match advice {
+            Advice::Sequential => unsafe { libc::fcntl(fd, libc::F_RDAHEAD, 1) },
+            Advice::WillNeed => {
+                let ra = libc::radvisory {
+                    ra_offset: libc::off_t::try_from(offset).unwrap_or(libc::off_t::MAX),
+                    // `len == 0` means "to the end"; F_RDADVISE needs an explicit count.
+                    ra_count: libc::c_int::try_from(len)
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .unwrap_or(libc::c_int::MAX),
+                };
+                unsafe { libc::fcntl(fd, libc::F_RDADVISE, &ra) }
+            }
+        };
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+}
+
+/// Platforms without a readahead API. Compiled everywhere so it is tested everywhere.
+#[cfg_attr(any(target_os = "linux", target_os = "macos"), allow(dead_code))]
+mod fallback {
+    use super::Advice;
+    use std::fs::File;
+    use std::io;
+
+    pub fn advise(_file: &File, _offset: u64, _len: u64, _advice: Advice) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+use fallback as imp;
+
+/// A whole file, mapped read-only. The file must not be modified in place while mapped (a
+/// truncated mapping faults on access); GGUF files and load sidecars are only ever replaced by
+/// rename, which leaves existing mappings on the old contents.
+pub struct Mapping {
+    map: map::Map,
+}
+
+impl Mapping {
+    pub fn open(file: &File) -> io::Result<Self> {
+        Ok(Self {
+            map: map::Map::open(file)?,
+        })
+    }
+
+    /// Whether the bytes are shared with other processes (`false` where they were read in).
+    pub fn is_shared(&self) -> bool {
+        cfg!(unix)
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.bytes()
+    }
+}
+
+impl std::fmt::Debug for Mapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mapping")
+            .field("len", &self.len())
+            .field("shared", &self.is_shared())
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+mod map {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    pub struct Map {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    // SAFETY: the mapping is read-only and owned by `Map` until drop.
+    unsafe impl Send for Map {}
+    unsafe impl Sync for Map {}
+
+    impl Map {
+        pub fn open(file: &File) -> io::Result<Self> {
+            let len = usize::try_from(file.metadata()?.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "file too large to map")
+            })?;
+            if len == 0 {
+                // `mmap` rejects empty lengths.
+                return Ok(Self {
+                    ptr: std::ptr::null_mut(),
+                    len: 0,
+                });
+            }
+            // SAFETY: a fresh read-only mapping of a valid descriptor; checked for MAP_FAILED.
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: `ptr..ptr + len` stays mapped and readable until drop.
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Map {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                // SAFETY: unmaps exactly what `open` mapped; no borrows outlive `self`.
+                unsafe { libc::munmap(self.ptr, self.len) };
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod map {
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    pub struct Map(Vec<u8>);
+
+    impl Map {
+        pub fn open(mut file: &File) -> io::Result<Self> {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(Self(bytes))
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_sees_the_file_bytes() {
+        let path = std::env::temp_dir().join(format!(
+            "inference_engine_rust_mapping_{}.bin",
+            std::process::id()
+        ));
+        let bytes: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &bytes).unwrap();
+        let map = Mapping::open(&File::open(&path).unwrap()).unwrap();
+        assert_eq!(&map[..], &bytes[..]);
+        // Replacing the file by rename leaves the mapping on the old contents.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, [1u8; 4]).unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+        assert_eq!(&map[..], &bytes[..]);
+
+        std::fs::write(&path, []).unwrap();
+        assert!(
+            Mapping::open(&File::open(&path).unwrap())
+                .unwrap()
+                .is_empty()
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn hints_never_fail_on_a_regular_file() {
+        let path = std::env::temp_dir().join(format!(
+            "inference_engine_rust_advise_{}.bin",
+            std::process::id()
+        ));
+        std::fs::write(&path, vec![0u8; 8192]).unwrap();
+        let file = File::open(&path).unwrap();
+        let supported = cfg!(any(target_os = "linux", target_os = "macos"));
+        for advice in [Advice::Sequential, Advice::WillNeed] {
+            assert_eq!(advise(&file, 0, 4096, advice).unwrap(), supported);
+            assert_eq!(advise(&file, 4096, 0, advice).unwrap(), supported);
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn fallback_reports_hints_as_not_applied() {
+        let file = File::open(std::env::current_exe().unwrap()).unwrap();
+        for advice in [Advice::Sequential, Advice::WillNeed] {
+            assert!(!fallback::advise(&file, 0, 1, advice).unwrap());
+        }
+    }
+}
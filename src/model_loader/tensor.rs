@@ -3,7 +3,7 @@ use crate::core::tensor::TensorType;
 
 #[allow(non_camel_case_types)]
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum GgmlType {
     F32 = 0,
     F16 = 1,
@@ -84,6 +84,30 @@ impl TryFrom<u32> for GgmlType {
 }
 
 impl GgmlType {
+    /// `(elements, bytes)` per storage block, or `None` for layouts this crate does not size
+    /// (the IQ* / TQ* / MXFP4 families).
+    pub fn block_layout(self) -> Option<(usize, usize)> {
+        Some(match self {
+            GgmlType::F32 | GgmlType::I32 => (1, 4),
+            GgmlType::F16 | GgmlType::BF16 | GgmlType::I16 => (1, 2),
+            GgmlType::F64 | GgmlType::I64 => (1, 8),
+            GgmlType::I8 => (1, 1),
+            GgmlType::Q4_0 => (32, 18),
+            GgmlType::Q4_1 => (32, 20),
+            GgmlType::Q5_0 => (32, 22),
+            GgmlType::Q5_1 => (32, 24),
+            GgmlType::Q8_0 => (32, 34),
+            GgmlType::Q8_1 => (32, 36),
+            GgmlType::Q2_K => (256, 84),
+            GgmlType::Q3_K => (256, 110),
+            GgmlType::Q4_K => (256, 144),
+            GgmlType::Q5_K => (256, 176),
+            GgmlType::Q6_K => (256, 210),
+            GgmlType::Q8_K => (256, 292),
+            _ => return None,
+        })
+    }
+
     pub fn to_tensor_type(self) -> Result<TensorType, EngineError> {
         match self {
             GgmlType::F32 => Ok(TensorType::F32),
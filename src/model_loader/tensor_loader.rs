@@ -1,15 +1,22 @@
 use std::io::{BufRead, Seek};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::EngineError;
 use crate::core::tensor::Tensor;
-use crate::model_loader::gguf_types::TensorInfo;
+use crate::core::time::Instant;
+use crate::model_loader::gguf_types::{LoadTiming, TensorInfo};
 use crate::model_loader::reader::Reader;
 use crate::model_loader::tensor::GgmlType;
 use crate::ops::quant::quant_k_handler::{
-    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE,
+    dequantize_q4k_block, dequantize_q6k_block, dequantize_q8_0_block,
 };
-const BLOCK_ELEMENTS: usize = 256;
+
+/// Values per chunk [`load_tensor_into`] reads at a time, and the largest block it decodes (a
+/// Q4_K / Q6_K superblock).
+const CHUNK_ELEMENTS: usize = 256;
+/// Bytes of the largest chunk or block: 256 F32 values.
+const CHUNK_BYTES: usize = CHUNK_ELEMENTS * 4;
 
 /// Load a single tensor from the file based on TensorInfo.
 /// This reads raw bytes into the tensor buffer without decoding.
@@ -28,26 +35,152 @@ pub fn load_tensor<R: BufRead + Seek>(
     reader: &mut Reader<R>,
     tensor_info: &TensorInfo,
     tensor_data_base: u64,
+) -> Result<Tensor, EngineError> {
+    let mut clock = LoadClock::start();
+    load_tensor_timed(
+        reader,
+        tensor_info,
+        tensor_data_base,
+        &mut clock,
+        &mut LoadTiming::default(),
+    )
+}
+
+/// Read the tensor and write it, dequantized to F32, into `f32_out` (e.g. a slice of the
+/// caller's arena) instead of allocating a [`Tensor`]. `f32_out` must hold exactly the tensor's
+/// element count. Nothing is allocated: the file is read one 256-value chunk or quantization
+/// block at a time through stack buffers and decoded straight into `f32_out`.
+///
+/// Supports F32, BF16, Q8_0, Q4_K and Q6_K, like [`load_tensor`]. `tensor_data_base` is as for
+/// [`load_tensor`].
+pub fn load_tensor_into<R: BufRead + Seek>(
+    reader: &mut Reader<R>,
+    tensor_info: &TensorInfo,
+    tensor_data_base: u64,
+    f32_out: &mut [f32],
+) -> Result<(), EngineError> {
+    let ggml_type = GgmlType::try_from(tensor_info.type_id)?;
+    let num_elements = tensor_info.num_elements()?;
+    if f32_out.len() != num_elements {
+        return Err(EngineError::Tensor(format!(
+            "load_tensor_into: buffer holds {} values, tensor {:?} has {num_elements}",
+            f32_out.len(),
+            tensor_info.dimensions
+        )));
+    }
+    let decode: fn(&[u8], &mut [f32]) -> Result<(), EngineError> = match ggml_type {
+        GgmlType::F32 => |raw, out| {
+            for (v, b) in out.iter_mut().zip(raw.chunks_exact(4)) {
+                *v = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            }
+            Ok(())
+        },
+        GgmlType::BF16 => |raw, out| {
+            for (v, b) in out.iter_mut().zip(raw.chunks_exact(2)) {
+                *v = bf16_le_to_f32([b[0], b[1]]);
+            }
+            Ok(())
+        },
+        GgmlType::Q8_0 => dequantize_q8_0_block,
+        GgmlType::Q4_K => dequantize_q4k_block,
+        GgmlType::Q6_K => dequantize_q6k_block,
+        other => {
+            return Err(EngineError::Tensor(format!(
+                "load_tensor_into: unsupported GGML type {other:?}"
+            )));
+        }
+    };
+    // Unquantized types are read CHUNK_ELEMENTS values at a time; quantized ones a block at a
+    // time, and a block is decoded whole even when the tensor ends partway through it.
+    let (block_elements, block_bytes) = ggml_type
+        .block_layout()
+        .ok_or_else(|| EngineError::Tensor(format!("no size rule for {ggml_type:?}")))?;
+    let step = if block_elements == 1 {
+        CHUNK_ELEMENTS
+    } else {
+        block_elements
+    };
+
+    let abs_offset = tensor_data_base
+        .checked_add(tensor_info.offset as u64)
+        .ok_or_else(|| EngineError::Overflow("tensor offset".into()))?;
+    reader.seek(abs_offset)?;
+    reader.check_remaining(tensor_info.byte_size()? as u64)?;
+
+    let mut raw = [0u8; CHUNK_BYTES];
+    let mut block = [0f32; CHUNK_ELEMENTS];
+    for out in f32_out.chunks_mut(step) {
+        let raw = &mut raw[..out.len().div_ceil(block_elements) * block_bytes];
+        reader.read_into(raw)?;
+        if block_elements == 1 || out.len() == block_elements {
+            decode(raw, out)?;
+        } else {
+            decode(raw, &mut block[..block_elements])?;
+            let n = out.len();
+            out.copy_from_slice(&block[..n]);
+        }
+    }
+    Ok(())
+}
+
+/// Charges wall time to load phases: [`Self::charge`] adds the time since the previous charge
+/// (or the start) to a phase, so consecutive charges split an interval without gaps. Two
+/// `Instant` reads per phase per tensor, nothing per element.
+pub(crate) struct LoadClock {
+    mark: Instant,
+}
+
+impl LoadClock {
+    pub(crate) fn start() -> Self {
+        Self {
+            mark: Instant::now(),
+        }
+    }
+
+    pub(crate) fn charge(&mut self, phase: &mut Duration) {
+        let now = Instant::now();
+        *phase += now.saturating_duration_since(self.mark);
+        self.mark = now;
+    }
+}
+
+/// [`load_tensor`], charging seek and read time to `timing.read`, buffer allocation to
+/// `timing.alloc` and BF16 widening to `timing.convert`.
+pub(crate) fn load_tensor_timed<R: BufRead + Seek>(
+    reader: &mut Reader<R>,
+    tensor_info: &TensorInfo,
+    tensor_data_base: u64,
+    clock: &mut LoadClock,
+    timing: &mut LoadTiming,
 ) -> Result<Tensor, EngineError> {
     let ggml_type = GgmlType::try_from(tensor_info.type_id)?;
-    let num_elements = tensor_info.dimensions.iter().product::<usize>();
+    let num_elements = tensor_info.num_elements()?;
 
     let abs_offset = tensor_data_base
         .checked_add(tensor_info.offset as u64)
-        .ok_or_else(|| EngineError::Gguf("tensor offset overflow".into()))?;
+        .ok_or_else(|| EngineError::Overflow("tensor offset".into()))?;
 
     reader.seek(abs_offset)?;
+    let byte_len = tensor_info.byte_size()?;
+    reader.check_remaining(byte_len as u64)?;
+    clock.charge(&mut timing.read);
+    let mut raw = vec![0u8; byte_len];
+    clock.charge(&mut timing.alloc);
+    reader.read_into(&mut raw)?;
+    clock.charge(&mut timing.read);
+    timing.bytes_read += byte_len as u64;
 
     if ggml_type == GgmlType::BF16 {
-        let byte_len = num_elements
-            .checked_mul(2)
-            .ok_or_else(|| EngineError::Gguf("BF16 tensor byte length overflow".into()))?;
-        let raw = reader.read_bytes(byte_len as u64)?;
-        let mut f32_bytes = Vec::with_capacity(num_elements * 4);
+        let f32_len = num_elements
+            .checked_mul(4)
+            .ok_or_else(|| EngineError::Overflow("BF16 tensor widened to f32".into()))?;
+        let mut f32_bytes = Vec::with_capacity(f32_len);
         for chunk in raw.chunks_exact(2) {
             let f = bf16_le_to_f32([chunk[0], chunk[1]]);
             f32_bytes.extend_from_slice(&f.to_le_bytes());
         }
+        timing.elements_converted += num_elements as u64;
+        clock.charge(&mut timing.convert);
         return Ok(Tensor::new(
             crate::core::tensor::TensorType::F32,
             Arc::new(f32_bytes),
@@ -56,37 +189,13 @@ pub fn load_tensor<R: BufRead + Seek>(
     }
 
     let tensor_type = ggml_type.to_tensor_type()?;
-    let byte_len = expected_byte_len(tensor_type, num_elements)?;
-    let buffer = reader.read_bytes(byte_len as u64)?;
-
     Ok(Tensor::new(
         tensor_type,
-        Arc::new(buffer),
+        Arc::new(raw),
         tensor_info.dimensions.clone(),
     ))
 }
 
-fn expected_byte_len(
-    tensor_type: crate::core::tensor::TensorType,
-    num_elements: usize,
-) -> Result<usize, EngineError> {
-    match tensor_type {
-        crate::core::tensor::TensorType::F32 => Ok(num_elements * 4),
-        crate::core::tensor::TensorType::Q4K => {
-            let num_blocks = num_elements.div_ceil(BLOCK_ELEMENTS);
-            Ok(num_blocks * Q4K_BLOCK_SIZE)
-        }
-        crate::core::tensor::TensorType::Q6K => {
-            let num_blocks = num_elements.div_ceil(BLOCK_ELEMENTS);
-            Ok(num_blocks * Q6K_BLOCK_SIZE)
-        }
-        crate::core::tensor::TensorType::Q8_0 => {
-            let num_blocks = num_elements.div_ceil(Q8_0_BLOCK_ELEMENTS);
-            Ok(num_blocks * Q8_0_BLOCK_SIZE)
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::bf16_le_to_f32;
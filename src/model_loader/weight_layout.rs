@@ -0,0 +1,93 @@
+//! Load-time block reordering for decode matmul, selected per [`WeightRole`] by
+//! [`super::gguf_types::LoadOptions::optimize_layout`].
+//!
+//! A selected weight is stored as [`WeightLayout::ColumnTiles`]: whole quant blocks are moved, so
+//! Q4_K/Q6_K scales stay next to their quants, and matmul picks the matching kernel from the
+//! tensor. Only the matmul projections ([`LAYOUT_ROLES`]) can be selected; embeddings are read a
+//! row at a time and gain nothing. A tensor whose shape cannot be tiled (F32, or a column count
+//! that is not a multiple of [`COLUMN_TILE`](crate::ops::math::matmul::COLUMN_TILE)) stays in
+//! ggml order. Tiling copies the weight, so with `shared_cache` the selected roles are held in
+//! this process's heap instead of the mapping.
+
+use std::collections::BTreeSet;
+
+use crate::EngineError;
+use crate::core::tensor::{Tensor, WeightLayout};
+use crate::model_loader::dtype_overrides::WeightRole;
+
+/// Roles [`super::gguf_types::LoadOptions::optimize_layout`] accepts: the attention and FFN
+/// projections.
+pub const LAYOUT_ROLES: [WeightRole; 7] = [
+    WeightRole::AttnQ,
+    WeightRole::AttnK,
+    WeightRole::AttnV,
+    WeightRole::AttnOutput,
+    WeightRole::FfnGate,
+    WeightRole::FfnUp,
+    WeightRole::FfnDown,
+];
+
+/// Refuse roles outside [`LAYOUT_ROLES`].
+pub fn check_layout_roles(roles: &BTreeSet<WeightRole>) -> Result<(), EngineError> {
+    match roles.iter().find(|role| !LAYOUT_ROLES.contains(role)) {
+        Some(role) => Err(EngineError::Model(format!(
+            "optimize_layout is not supported for {role:?} tensors; only attention and FFN \
+             projections are reordered"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// `tensor` reordered into [`WeightLayout::ColumnTiles`] if `roles` selects the role of `name`
+/// and the tensor can be tiled; otherwise `tensor` unchanged.
+pub(crate) fn apply_layout(
+    name: &str,
+    tensor: Tensor,
+    roles: &BTreeSet<WeightRole>,
+) -> Result<Tensor, EngineError> {
+    if !roles.contains(&WeightRole::of(name)) || !tensor.supports_layout(WeightLayout::ColumnTiles)
+    {
+        return Ok(tensor);
+    }
+    tensor.with_layout(WeightLayout::ColumnTiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tensor::TensorType;
+    use crate::ops::math::matmul::COLUMN_TILE;
+
+    #[test]
+    fn only_projection_roles_are_accepted() {
+        assert!(check_layout_roles(&LAYOUT_ROLES.into_iter().collect()).is_ok());
+        for role in [
+            WeightRole::TokenEmbedding,
+            WeightRole::Output,
+            WeightRole::Norm,
+        ] {
+            let err = check_layout_roles(&BTreeSet::from([role])).unwrap_err();
+            assert!(err.to_string().contains(&format!("{role:?}")), "{err}");
+        }
+    }
+
+    #[test]
+    fn unselected_or_untileable_tensors_keep_the_ggml_order() {
+        let q8_0 = |n: usize| {
+            Tensor::from_bytes(TensorType::Q8_0, vec![0u8; 34 * n], vec![32, n]).unwrap()
+        };
+        let ffn = BTreeSet::from([WeightRole::FfnUp]);
+        let tiled = apply_layout("blk.0.ffn_up.weight", q8_0(COLUMN_TILE), &ffn).unwrap();
+        assert_eq!(tiled.layout(), WeightLayout::ColumnTiles);
+        for (name, tensor) in [
+            ("blk.0.ffn_down.weight", q8_0(COLUMN_TILE)),
+            ("blk.0.ffn_up.weight", q8_0(COLUMN_TILE + 1)),
+        ] {
+            assert_eq!(
+                apply_layout(name, tensor, &ffn).unwrap().layout(),
+                WeightLayout::Ggml,
+                "{name}"
+            );
+        }
+    }
+}
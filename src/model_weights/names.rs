@@ -1,68 +1,76 @@
-use std::collections::HashSet;
-
 use crate::EngineError;
 use crate::model_config::{ModelConfig, ModelFamily};
-use crate::model_loader::gguf_types::GGUFData;
+use crate::model_loader::gguf_types::{GGUFData, LoadOptions, LoadStats, TensorInfo};
+use crate::model_loader::interner::{StringInterner, Symbol};
+use crate::model_loader::tensor::GgmlType;
 
 /// Resolved GGUF tensor names for a single transformer block.
 ///
-/// Fields are `pub(crate)` so [`super::view`] can build borrowed [`super::view::LayerWeights`]
-/// from the same names without exposing the raw strings publicly.
+/// Each name is a [`Symbol`] in the [`GGUFData::tensor_names`] it was resolved against, so the
+/// views look tensors up without hashing strings. Fields are `pub(crate)` so [`super::view`] can
+/// build borrowed [`super::view::LayerWeights`] from the same names.
 #[derive(Debug)]
 pub struct LayerNames {
-    pub(crate) attn_norm: String,
-    pub(crate) ffn_norm: String,
-    pub(crate) attn_post_norm: Option<String>,
-    pub(crate) ffn_post_norm: Option<String>,
-    pub(crate) attn_q_norm: Option<String>,
-    pub(crate) attn_k_norm: Option<String>,
-    pub(crate) wq: String,
-    pub(crate) wk: String,
-    pub(crate) wv: String,
-    pub(crate) wo: String,
-    pub(crate) w_gate: String,
-    pub(crate) w_up: String,
-    pub(crate) w_down: String,
-    pub(crate) ple_inp_gate: Option<String>,
-    pub(crate) ple_proj: Option<String>,
-    pub(crate) ple_post_norm: Option<String>,
-    pub(crate) rope_freqs: Option<String>,
-    pub(crate) layer_output_scale: Option<String>,
+    pub(crate) attn_norm: Symbol,
+    pub(crate) ffn_norm: Symbol,
+    pub(crate) attn_post_norm: Option<Symbol>,
+    pub(crate) ffn_post_norm: Option<Symbol>,
+    pub(crate) attn_q_norm: Option<Symbol>,
+    pub(crate) attn_k_norm: Option<Symbol>,
+    pub(crate) wq: Symbol,
+    pub(crate) wk: Symbol,
+    pub(crate) wv: Symbol,
+    pub(crate) wo: Symbol,
+    pub(crate) w_gate: Symbol,
+    pub(crate) w_up: Symbol,
+    pub(crate) w_down: Symbol,
+    pub(crate) ple_inp_gate: Option<Symbol>,
+    pub(crate) ple_proj: Option<Symbol>,
+    pub(crate) ple_post_norm: Option<Symbol>,
+    pub(crate) rope_freqs: Option<Symbol>,
+    pub(crate) layer_output_scale: Option<Symbol>,
 }
 
 /// Resolved names for Gemma 4 global PLE tensors (not per-layer).
 #[derive(Debug)]
 pub struct Gemma4PleNames {
-    pub per_layer_token_embd: String,
-    pub per_layer_model_proj: String,
-    pub per_layer_proj_norm: String,
+    pub per_layer_token_embd: Symbol,
+    pub per_layer_model_proj: Symbol,
+    pub per_layer_proj_norm: Symbol,
 }
 
 /// Resolved GGUF tensor names for the entire model, ready to be loaded.
 #[derive(Debug)]
 pub struct ModelWeightNames {
-    pub(crate) token_embeddings: String,
-    pub(crate) output_norm: String,
-    pub(crate) lm_head: String,
+    pub(crate) token_embeddings: Symbol,
+    pub(crate) output_norm: Symbol,
+    pub(crate) lm_head: Symbol,
     pub(crate) layers: Vec<LayerNames>,
     pub(crate) gemma4_ple: Option<Gemma4PleNames>,
+    /// Model-wide RoPE frequency factors (`rope_freqs.weight`, Llama 3.1 long-context scaling),
+    /// shared by every layer without its own `blk.*.rope_freqs`. Llama-family only.
+    pub(crate) rope_freqs: Option<Symbol>,
 }
 
 impl ModelWeightNames {
     pub fn resolve(gguf: &GGUFData, config: &ModelConfig) -> Result<Self, EngineError> {
-        let available = available_tensor_names(gguf);
+        let available = gguf.tensor_names();
 
         let token_embeddings = resolve_name_from_strs(
-            &available,
+            available,
             &[
                 "token_embd.weight",
                 "tok_embeddings.weight",
                 "embeddings.weight",
             ],
         )?;
-        let output_norm =
-            resolve_name_from_strs(&available, &["output_norm.weight", "norm.weight"])?;
-        let lm_head = resolve_lm_head(&available)?;
+        let output_norm = resolve_output_norm(available)?;
+        let lm_head = resolve_lm_head(available)?;
+        // Gemma 4 applies its factors to full-attention layers only, via `blk.*.rope_freqs`.
+        let global_rope_freqs = match config.family {
+            ModelFamily::MistralLlama => optional_name_from_strs(available, &["rope_freqs.weight"]),
+            ModelFamily::Gemma4 => None,
+        };
 
         let mut layer_names = Vec::with_capacity(config.n_layers);
         for layer_idx in 0..config.n_layers {
@@ -71,11 +79,11 @@ impl ModelWeightNames {
                 match config.family {
                     ModelFamily::Gemma4 => {
                         let attn_post_norm = Some(resolve_name_from_strings(
-                            &available,
+                            available,
                             &[format!("{prefix}post_attention_norm.weight")],
                         )?);
                         let ffn_post_norm = Some(resolve_name_from_strings(
-                            &available,
+                            available,
                             &[format!("{prefix}post_ffw_norm.weight")],
                         )?);
                         if config.embedding_length_per_layer > 0 {
@@ -83,15 +91,15 @@ impl ModelWeightNames {
                                 attn_post_norm,
                                 ffn_post_norm,
                                 Some(resolve_name_from_strings(
-                                    &available,
+                                    available,
                                     &[format!("{prefix}inp_gate.weight")],
                                 )?),
                                 Some(resolve_name_from_strings(
-                                    &available,
+                                    available,
                                     &[format!("{prefix}proj.weight")],
                                 )?),
                                 Some(resolve_name_from_strings(
-                                    &available,
+                                    available,
                                     &[format!("{prefix}post_norm.weight")],
                                 )?),
                             )
@@ -103,14 +111,14 @@ impl ModelWeightNames {
                 };
             layer_names.push(LayerNames {
                 attn_norm: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}attn_norm.weight"),
                         format!("{prefix}attention_norm.weight"),
                     ],
                 )?,
                 ffn_norm: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}ffn_norm.weight"),
                         format!("{prefix}feed_forward_norm.weight"),
@@ -119,74 +127,75 @@ impl ModelWeightNames {
                 attn_post_norm,
                 ffn_post_norm,
                 wq: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}attn_q.weight"),
                         format!("{prefix}wq.weight"),
                     ],
                 )?,
                 wk: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}attn_k.weight"),
                         format!("{prefix}wk.weight"),
                     ],
                 )?,
                 wv: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}attn_v.weight"),
                         format!("{prefix}wv.weight"),
                     ],
                 )?,
                 wo: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}attn_output.weight"),
                         format!("{prefix}wo.weight"),
                     ],
                 )?,
                 w_gate: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}ffn_gate.weight"),
                         format!("{prefix}w1.weight"),
                     ],
                 )?,
                 w_up: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}ffn_up.weight"),
                         format!("{prefix}w3.weight"),
                     ],
                 )?,
                 w_down: resolve_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}ffn_down.weight"),
                         format!("{prefix}w2.weight"),
                     ],
                 )?,
                 attn_q_norm: optional_name_from_strings(
-                    &available,
+                    available,
                     &[format!("{prefix}attn_q_norm.weight")],
                 ),
                 attn_k_norm: optional_name_from_strings(
-                    &available,
+                    available,
                     &[format!("{prefix}attn_k_norm.weight")],
                 ),
                 ple_inp_gate,
                 ple_proj,
                 ple_post_norm,
                 rope_freqs: optional_name_from_strings(
-                    &available,
+                    available,
                     &[
                         format!("{prefix}rope_freqs.weight"),
                         format!("{prefix}rope_freqs"),
                     ],
-                ),
+                )
+                .or(global_rope_freqs),
                 layer_output_scale: optional_name_from_strings(
-                    &available,
+                    available,
                     &[format!("{prefix}layer_output_scale.weight")],
                 ),
             });
@@ -196,15 +205,15 @@ impl ModelWeightNames {
             if config.family == ModelFamily::Gemma4 && config.embedding_length_per_layer > 0 {
                 Some(Gemma4PleNames {
                     per_layer_token_embd: resolve_name_from_strs(
-                        &available,
+                        available,
                         &["per_layer_token_embd.weight"],
                     )?,
                     per_layer_model_proj: resolve_name_from_strs(
-                        &available,
+                        available,
                         &["per_layer_model_proj.weight"],
                     )?,
                     per_layer_proj_norm: resolve_name_from_strs(
-                        &available,
+                        available,
                         &["per_layer_proj_norm.weight"],
                     )?,
                 })
@@ -212,125 +221,229 @@ impl ModelWeightNames {
                 None
             };
 
-        Ok(Self {
+        let names = Self {
             token_embeddings,
             output_norm,
             lm_head,
             layers: layer_names,
             gemma4_ple,
-        })
+            rope_freqs: global_rope_freqs,
+        };
+        names.check_shapes(gguf, config)?;
+        Ok(names)
     }
 
-    pub fn load_all(&self, gguf: &mut GGUFData, file_path: &str) -> Result<(), EngineError> {
-        let mut names_to_load = Vec::new();
-        names_to_load.push(self.token_embeddings.clone());
-        names_to_load.push(self.output_norm.clone());
-        names_to_load.push(self.lm_head.clone());
-        for layer in &self.layers {
-            names_to_load.push(layer.attn_norm.clone());
-            names_to_load.push(layer.ffn_norm.clone());
-            if let Some(ref n) = layer.attn_post_norm {
-                names_to_load.push(n.clone());
-            }
-            if let Some(ref n) = layer.ffn_post_norm {
-                names_to_load.push(n.clone());
-            }
-            if let Some(ref n) = layer.attn_q_norm {
-                names_to_load.push(n.clone());
-            }
-            if let Some(ref n) = layer.attn_k_norm {
-                names_to_load.push(n.clone());
-            }
-            names_to_load.push(layer.wq.clone());
-            names_to_load.push(layer.wk.clone());
-            names_to_load.push(layer.wv.clone());
-            names_to_load.push(layer.wo.clone());
-            names_to_load.push(layer.w_gate.clone());
-            names_to_load.push(layer.w_up.clone());
-            names_to_load.push(layer.w_down.clone());
-            if let Some(ref n) = layer.ple_inp_gate {
-                names_to_load.push(n.clone());
-            }
-            if let Some(ref n) = layer.ple_proj {
-                names_to_load.push(n.clone());
-            }
-            if let Some(ref n) = layer.ple_post_norm {
-                names_to_load.push(n.clone());
+    /// Compare tensor-table dims (ggml order: `[in, out]` for matrices) with the widths the
+    /// forward pass will use, so a head width that disagrees with the projections fails here,
+    /// naming the tensor, instead of silently mixing heads in attention. Runs on metadata only.
+    pub fn check_shapes(&self, gguf: &GGUFData, config: &ModelConfig) -> Result<(), EngineError> {
+        let hidden = config.hidden_dim;
+        expect_dims(gguf, self.output_norm, &[hidden], "hidden_dim")?;
+        expect_leading_dim(gguf, self.token_embeddings, hidden, "hidden_dim")?;
+        expect_leading_dim(gguf, self.lm_head, hidden, "hidden_dim")?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let d = config.layer_dims_for(i)?;
+            let why = |what: &str| {
+                format!(
+                    "layer {i} {what} (n_heads {}, n_kv_heads {}, head_dim {}, hidden_dim {hidden}, ffn_dim {})",
+                    config.n_heads, config.n_kv_heads, d.head_dim, d.ffn_dim
+                )
+            };
+            expect_dims(gguf, layer.attn_norm, &[hidden], &why("attn_norm"))?;
+            expect_dims(gguf, layer.ffn_norm, &[hidden], &why("ffn_norm"))?;
+            expect_dims(gguf, layer.wq, &[hidden, d.q_dim], &why("Q projection"))?;
+            expect_dims(gguf, layer.wk, &[hidden, d.kv_dim], &why("K projection"))?;
+            expect_dims(gguf, layer.wv, &[hidden, d.kv_dim], &why("V projection"))?;
+            expect_dims(
+                gguf,
+                layer.wo,
+                &[d.q_dim, hidden],
+                &why("output projection"),
+            )?;
+            expect_dims(gguf, layer.w_gate, &[hidden, d.ffn_dim], &why("FFN gate"))?;
+            expect_dims(gguf, layer.w_up, &[hidden, d.ffn_dim], &why("FFN up"))?;
+            expect_dims(gguf, layer.w_down, &[d.ffn_dim, hidden], &why("FFN down"))?;
+            for norm in [layer.attn_q_norm, layer.attn_k_norm].into_iter().flatten() {
+                expect_dims(gguf, norm, &[d.head_dim], &why("per-head norm"))?;
             }
-            if let Some(ref n) = layer.rope_freqs {
-                names_to_load.push(n.clone());
+            // The model-wide table must cover exactly the rotated pairs of every layer it is
+            // shared with; per-layer Gemma tables are only required to be long enough (see rope).
+            if let Some(name) = self.rope_freqs.filter(|&g| layer.rope_freqs == Some(g)) {
+                let rotary_dim = config.layer_attention_for(i)?.rope_rotary_dim;
+                let why = format!(
+                    "rotary_dim/2 (one factor per rotated pair, layer {i} rotary_dim {rotary_dim})"
+                );
+                expect_dims(gguf, name, &[rotary_dim / 2], &why)?;
             }
-            if let Some(ref n) = layer.layer_output_scale {
-                names_to_load.push(n.clone());
+        }
+        if let Some(name) = self.rope_freqs {
+            let info = tensor_info(gguf, name);
+            if info.type_id != GgmlType::F32 as u32 {
+                return Err(EngineError::Model(format!(
+                    "tensor '{}' has ggml type {}, expected F32 (0) for RoPE frequency factors",
+                    gguf.tensor_names().resolve(name),
+                    info.type_id
+                )));
             }
         }
+        Ok(())
+    }
+
+    pub fn load_all(&self, gguf: &mut GGUFData, file_path: &str) -> Result<(), EngineError> {
+        self.load_all_with(gguf, file_path, &LoadOptions::default())
+            .map(|_| ())
+    }
+
+    /// [`Self::load_all`] with explicit [`LoadOptions`].
+    pub fn load_all_with(
+        &self,
+        gguf: &mut GGUFData,
+        file_path: &str,
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        gguf.load_symbols_with(file_path, &self.all_names(), options)
+    }
+
+    /// [`Self::load_all_with`] from `bytes`, the whole GGUF file in memory (see
+    /// [`GGUFData::load_symbols_from_bytes`]).
+    pub fn load_all_from_bytes(
+        &self,
+        gguf: &mut GGUFData,
+        bytes: &[u8],
+        options: &LoadOptions,
+    ) -> Result<LoadStats, EngineError> {
+        gguf.load_symbols_from_bytes(bytes, &self.all_names(), options)
+    }
+
+    /// Every tensor the model reads.
+    fn all_names(&self) -> Vec<Symbol> {
+        let mut names_to_load = vec![self.token_embeddings, self.output_norm, self.lm_head];
+        for layer in &self.layers {
+            names_to_load.extend([
+                layer.attn_norm,
+                layer.ffn_norm,
+                layer.wq,
+                layer.wk,
+                layer.wv,
+                layer.wo,
+                layer.w_gate,
+                layer.w_up,
+                layer.w_down,
+            ]);
+            names_to_load.extend(
+                [
+                    layer.attn_post_norm,
+                    layer.ffn_post_norm,
+                    layer.attn_q_norm,
+                    layer.attn_k_norm,
+                    layer.ple_inp_gate,
+                    layer.ple_proj,
+                    layer.ple_post_norm,
+                    layer.rope_freqs,
+                    layer.layer_output_scale,
+                ]
+                .into_iter()
+                .flatten(),
+            );
+        }
         if let Some(ref g) = self.gemma4_ple {
-            names_to_load.push(g.per_layer_token_embd.clone());
-            names_to_load.push(g.per_layer_model_proj.clone());
-            names_to_load.push(g.per_layer_proj_norm.clone());
+            names_to_load.extend([
+                g.per_layer_token_embd,
+                g.per_layer_model_proj,
+                g.per_layer_proj_norm,
+            ]);
         }
+        names_to_load
+    }
+}
 
-        gguf.load_named_tensors(file_path, &names_to_load)?;
+fn tensor_info(gguf: &GGUFData, name: Symbol) -> &TensorInfo {
+    &gguf.tensors_metadata()[name.index()]
+}
 
-        Ok(())
+fn expect_dims(
+    gguf: &GGUFData,
+    name: Symbol,
+    expected: &[usize],
+    why: &str,
+) -> Result<(), EngineError> {
+    let dims = &tensor_info(gguf, name).dimensions;
+    if dims != expected {
+        return Err(EngineError::Model(format!(
+            "tensor '{}' has dims {dims:?}, expected {expected:?} for {why}",
+            gguf.tensor_names().resolve(name)
+        )));
     }
+    Ok(())
 }
 
-fn available_tensor_names(gguf: &GGUFData) -> HashSet<String> {
-    gguf.tensors_metadata()
-        .iter()
-        .map(|t| t.name.clone())
-        .collect()
+/// Embedding / LM head: `[hidden, vocab]`; the vocab side is checked elsewhere.
+fn expect_leading_dim(
+    gguf: &GGUFData,
+    name: Symbol,
+    expected: usize,
+    why: &str,
+) -> Result<(), EngineError> {
+    let dims = &tensor_info(gguf, name).dimensions;
+    if dims.len() != 2 || dims[0] != expected {
+        return Err(EngineError::Model(format!(
+            "tensor '{}' has dims {dims:?}, expected [{expected}, vocab] for {why}",
+            gguf.tensor_names().resolve(name)
+        )));
+    }
+    Ok(())
 }
 
 fn resolve_name_from_strs(
-    available: &HashSet<String>,
+    available: &StringInterner,
     candidates: &[&str],
-) -> Result<String, EngineError> {
-    for candidate in candidates {
-        if available.contains(*candidate) {
-            return Ok(candidate.to_string());
-        }
-    }
-    Err(EngineError::Model(format!(
-        "none of the candidate tensor names were found: {candidates:?}"
-    )))
+) -> Result<Symbol, EngineError> {
+    optional_name_from_strs(available, candidates).ok_or_else(|| {
+        EngineError::Model(format!(
+            "none of the candidate tensor names were found: {candidates:?}"
+        ))
+    })
 }
 
-fn resolve_lm_head(available: &HashSet<String>) -> Result<String, EngineError> {
-    if let Ok(n) = resolve_name_from_strs(available, &["output.weight", "lm_head.weight"]) {
-        return Ok(n);
-    }
-    if available.contains("token_embd.weight") {
-        return Ok("token_embd.weight".to_string());
-    }
-    Err(EngineError::Model(
-        "LM head: none of output.weight, lm_head.weight, token_embd.weight (tied) found".into(),
-    ))
+fn optional_name_from_strs(available: &StringInterner, candidates: &[&str]) -> Option<Symbol> {
+    candidates.iter().find_map(|c| available.get(c))
 }
 
-fn optional_name_from_strings(
-    available: &HashSet<String>,
-    candidates: &[String],
-) -> Option<String> {
-    for candidate in candidates {
-        if available.contains(candidate) {
-            return Some(candidate.clone());
-        }
-    }
-    None
+/// The final RMSNorm applied to the last hidden state before the LM head. Every supported
+/// architecture has one, so a file without it is rejected here rather than producing logits from
+/// an unnormalized residual stream.
+fn resolve_output_norm(available: &StringInterner) -> Result<Symbol, EngineError> {
+    resolve_name_from_strs(available, &["output_norm.weight", "norm.weight"]).map_err(|_| {
+        EngineError::Model(
+            "final norm: none of output_norm.weight, norm.weight found (applied before the LM head)"
+                .into(),
+        )
+    })
+}
+
+fn resolve_lm_head(available: &StringInterner) -> Result<Symbol, EngineError> {
+    optional_name_from_strs(
+        available,
+        &["output.weight", "lm_head.weight", "token_embd.weight"],
+    )
+    .ok_or_else(|| {
+        EngineError::Model(
+            "LM head: none of output.weight, lm_head.weight, token_embd.weight (tied) found".into(),
+        )
+    })
+}
+
+fn optional_name_from_strings(available: &StringInterner, candidates: &[String]) -> Option<Symbol> {
+    candidates.iter().find_map(|c| available.get(c))
 }
 
 fn resolve_name_from_strings(
-    available: &HashSet<String>,
+    available: &StringInterner,
     candidates: &[String],
-) -> Result<String, EngineError> {
-    for candidate in candidates {
-        if available.contains(candidate) {
-            return Ok(candidate.clone());
-        }
-    }
-    Err(EngineError::Model(format!(
-        "none of the candidate tensor names were found: {candidates:?}"
-    )))
+) -> Result<Symbol, EngineError> {
+    optional_name_from_strings(available, candidates).ok_or_else(|| {
+        EngineError::Model(format!(
+            "none of the candidate tensor names were found: {candidates:?}"
+        ))
+    })
 }
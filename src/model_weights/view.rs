@@ -1,6 +1,8 @@
 use crate::EngineError;
 use crate::core::tensor::Tensor;
+use crate::layers::lora::LayerLora;
 use crate::model_loader::gguf_types::GGUFData;
+use crate::model_loader::interner::Symbol;
 
 use super::names::{Gemma4PleNames, LayerNames, ModelWeightNames};
 
@@ -37,10 +39,13 @@ pub struct LayerWeights<'a> {
     pub ple_proj: Option<&'a Tensor>,
     /// PLE: RMSNorm on projected vector before residual (`blk.*.post_norm`).
     pub ple_post_norm: Option<&'a Tensor>,
-    /// Gemma 4 full-attention: `blk.*.rope_freqs.weight` for proportional RoPE (optional).
+    /// RoPE frequency factors (optional): Gemma 4 full-attention `blk.*.rope_freqs.weight`
+    /// (proportional RoPE), or the model-wide Llama 3.1 `rope_freqs.weight` shared by all layers.
     pub rope_freqs: Option<&'a Tensor>,
     /// Gemma 4: `blk.*.layer_output_scale.weight` (length 1); applied after PLE.
     pub layer_output_scale: Option<&'a Tensor>,
+    /// LoRA pairs added to the projections (see [`crate::layers::lora`]); empty by default.
+    pub lora: LayerLora,
 }
 
 /// Borrowed view of all model tensors needed for a forward pass.
@@ -66,10 +71,20 @@ impl<'a> ModelWeights<'a> {
             None
         };
 
+        let output_norm = get_loaded(gguf, names.output_norm)?;
+        // Checked here, not on first use, so a session cannot be built around a final norm the
+        // forward pass would reject after prefill.
+        output_norm.as_vector().map_err(|e| {
+            EngineError::Model(format!(
+                "final norm '{}' must be a 1-D F32 vector: {e}",
+                gguf.tensor_names().resolve(names.output_norm)
+            ))
+        })?;
+
         Ok(Self {
-            token_embeddings: get_loaded(gguf, &names.token_embeddings)?,
-            output_norm: get_loaded(gguf, &names.output_norm)?,
-            lm_head: get_loaded(gguf, &names.lm_head)?,
+            token_embeddings: get_loaded(gguf, names.token_embeddings)?,
+            output_norm,
+            lm_head: get_loaded(gguf, names.lm_head)?,
             layers,
             gemma4_ple,
         })
@@ -81,60 +96,40 @@ fn build_layer_weights<'a>(
     layer: &LayerNames,
 ) -> Result<LayerWeights<'a>, EngineError> {
     Ok(LayerWeights {
-        attn_norm: get_loaded(gguf, &layer.attn_norm)?,
-        ffn_norm: get_loaded(gguf, &layer.ffn_norm)?,
+        attn_norm: get_loaded(gguf, layer.attn_norm)?,
+        ffn_norm: get_loaded(gguf, layer.ffn_norm)?,
         attn_post_norm: layer
             .attn_post_norm
-            .as_ref()
             .map(|n| get_loaded(gguf, n))
             .transpose()?,
         ffn_post_norm: layer
             .ffn_post_norm
-            .as_ref()
-            .map(|n| get_loaded(gguf, n))
-            .transpose()?,
-        attn_q_norm: layer
-            .attn_q_norm
-            .as_ref()
-            .map(|n| get_loaded(gguf, n))
-            .transpose()?,
-        attn_k_norm: layer
-            .attn_k_norm
-            .as_ref()
             .map(|n| get_loaded(gguf, n))
             .transpose()?,
-        wq: get_loaded(gguf, &layer.wq)?,
-        wk: get_loaded(gguf, &layer.wk)?,
-        wv: get_loaded(gguf, &layer.wv)?,
-        wo: get_loaded(gguf, &layer.wo)?,
-        w_gate: get_loaded(gguf, &layer.w_gate)?,
-        w_up: get_loaded(gguf, &layer.w_up)?,
-        w_down: get_loaded(gguf, &layer.w_down)?,
+        attn_q_norm: layer.attn_q_norm.map(|n| get_loaded(gguf, n)).transpose()?,
+        attn_k_norm: layer.attn_k_norm.map(|n| get_loaded(gguf, n)).transpose()?,
+        wq: get_loaded(gguf, layer.wq)?,
+        wk: get_loaded(gguf, layer.wk)?,
+        wv: get_loaded(gguf, layer.wv)?,
+        wo: get_loaded(gguf, layer.wo)?,
+        w_gate: get_loaded(gguf, layer.w_gate)?,
+        w_up: get_loaded(gguf, layer.w_up)?,
+        w_down: get_loaded(gguf, layer.w_down)?,
         ple_inp_gate: layer
             .ple_inp_gate
-            .as_ref()
-            .map(|n| get_loaded(gguf, n))
-            .transpose()?,
-        ple_proj: layer
-            .ple_proj
-            .as_ref()
             .map(|n| get_loaded(gguf, n))
             .transpose()?,
+        ple_proj: layer.ple_proj.map(|n| get_loaded(gguf, n)).transpose()?,
         ple_post_norm: layer
             .ple_post_norm
-            .as_ref()
-            .map(|n| get_loaded(gguf, n))
-            .transpose()?,
-        rope_freqs: layer
-            .rope_freqs
-            .as_ref()
             .map(|n| get_loaded(gguf, n))
             .transpose()?,
+        rope_freqs: layer.rope_freqs.map(|n| get_loaded(gguf, n)).transpose()?,
         layer_output_scale: layer
             .layer_output_scale
-            .as_ref()
             .map(|n| get_loaded(gguf, n))
             .transpose()?,
+        lora: LayerLora::default(),
     })
 }
 
@@ -143,13 +138,17 @@ fn build_ple_tensors<'a>(
     g: &Gemma4PleNames,
 ) -> Result<Gemma4PleTensors<'a>, EngineError> {
     Ok(Gemma4PleTensors {
-        per_layer_token_embd: get_loaded(gguf, &g.per_layer_token_embd)?,
-        per_layer_model_proj: get_loaded(gguf, &g.per_layer_model_proj)?,
-        per_layer_proj_norm: get_loaded(gguf, &g.per_layer_proj_norm)?,
+        per_layer_token_embd: get_loaded(gguf, g.per_layer_token_embd)?,
+        per_layer_model_proj: get_loaded(gguf, g.per_layer_model_proj)?,
+        per_layer_proj_norm: get_loaded(gguf, g.per_layer_proj_norm)?,
     })
 }
 
-fn get_loaded<'a>(gguf: &'a GGUFData, name: &str) -> Result<&'a Tensor, EngineError> {
-    gguf.get_tensor(name)
-        .ok_or_else(|| EngineError::Model(format!("tensor '{name}' not found after loading")))
+fn get_loaded(gguf: &GGUFData, name: Symbol) -> Result<&Tensor, EngineError> {
+    gguf.get_tensor_by_symbol(name).ok_or_else(|| {
+        EngineError::Model(format!(
+            "tensor '{}' not found after loading",
+            gguf.tensor_names().resolve(name)
+        ))
+    })
 }
@@ -0,0 +1,179 @@
+//! Which matmul kernel ran, per weight type.
+//!
+//! [`crate::ops::matmul::matmul`] bumps one relaxed atomic counter per call, keyed by the weight
+//! [`TensorType`] and the [`KernelPath`] it dispatched to; [`kernel_stats`] snapshots the counters
+//! together with the detected [`CpuFeatures`]. Every kernel in this crate is currently scalar, so
+//! on a NEON machine the report shows the SIMD capability next to scalar-only counts: that is
+//! the expected result until vector kernels land, and each one must add its own [`KernelPath`]
+//! (which also puts it under the randomized reference checks in `ops::matmul`).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::core::tensor::TensorType;
+use crate::ops::cpu_features::CpuFeatures;
+
+/// How a matmul call was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KernelPath {
+    /// Portable scalar loop on the calling thread (small products).
+    Scalar,
+    /// The same scalar loop split over output rows on the rayon pool.
+    ScalarParallel,
+}
+
+impl KernelPath {
+    pub const ALL: [KernelPath; 2] = [KernelPath::Scalar, KernelPath::ScalarParallel];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KernelPath::Scalar => "scalar",
+            KernelPath::ScalarParallel => "scalar (parallel)",
+        }
+    }
+}
+
+/// Weight types with a matmul kernel, in counter order.
+pub(crate) const WEIGHT_TYPES: [TensorType; 4] = [
+    TensorType::F32,
+    TensorType::Q4K,
+    TensorType::Q6K,
+    TensorType::Q8_0,
+];
+
+const SLOTS: usize = WEIGHT_TYPES.len() * KernelPath::ALL.len();
+
+static CALLS: [AtomicU64; SLOTS] = [const { AtomicU64::new(0) }; SLOTS];
+
+fn slot(weight: TensorType, path: KernelPath) -> usize {
+    let t = WEIGHT_TYPES
+        .iter()
+        .position(|&w| w == weight)
+        .expect("every TensorType has a matmul kernel");
+    let p = KernelPath::ALL.iter().position(|&k| k == path).unwrap_or(0);
+    t * KernelPath::ALL.len() + p
+}
+
+/// Count one matmul call with `weight` run on `path`.
+pub(crate) fn record(weight: TensorType, path: KernelPath) {
+    CALLS[slot(weight, path)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Calls of one kernel since start-up (or the last [`reset_kernel_stats`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelCount {
+    pub weight: TensorType,
+    pub path: KernelPath,
+    pub calls: u64,
+}
+
+/// Snapshot from [`kernel_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelStats {
+    pub cpu: CpuFeatures,
+    /// Kernels that ran at least once, by weight type then path.
+    pub counts: Vec<KernelCount>,
+}
+
+impl KernelStats {
+    /// Calls recorded for `weight` on `path`.
+    pub fn calls(&self, weight: TensorType, path: KernelPath) -> u64 {
+        self.counts
+            .iter()
+            .find(|c| c.weight == weight && c.path == path)
+            .map_or(0, |c| c.calls)
+    }
+
+    pub fn total_calls(&self) -> u64 {
+        self.counts.iter().map(|c| c.calls).sum()
+    }
+}
+
+impl fmt::Display for KernelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "CPU SIMD: {}", self.cpu.describe())?;
+        if self.counts.is_empty() {
+            return write!(f, "no matmul calls recorded");
+        }
+        for (i, c) in self.counts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let weight = format!("{:?}", c.weight);
+            write!(f, "  {weight:<5} {:<18} {} calls", c.path.name(), c.calls)?;
+        }
+        Ok(())
+    }
+}
+
+/// Process-wide matmul kernel counts. Counters are shared by all threads and sessions.
+pub fn kernel_stats() -> KernelStats {
+    let counts = WEIGHT_TYPES
+        .iter()
+        .flat_map(|&weight| KernelPath::ALL.map(|path| (weight, path)))
+        .map(|(weight, path)| KernelCount {
+            weight,
+            path,
+            calls: CALLS[slot(weight, path)].load(Ordering::Relaxed),
+        })
+        .filter(|c| c.calls > 0)
+        .collect();
+    KernelStats {
+        cpu: CpuFeatures::detect(),
+        counts,
+    }
+}
+
+/// Zero every counter (e.g. between benchmark phases). Calls racing with the reset may or may
+/// not be counted.
+pub fn reset_kernel_stats() {
+    for c in &CALLS {
+        c.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tensor::Tensor;
+    use crate::ops::matmul::matmul;
+    use crate::test_utils::f32_tensor;
+
+    /// `0.0, 1.0, ...` as a tensor of shape `dims`.
+    fn ramp(dims: Vec<usize>) -> Tensor {
+        let values: Vec<f32> = (0..dims.iter().product()).map(|i| i as f32).collect();
+        f32_tensor(&values, dims)
+    }
+
+    /// Run an `m x k` by `k x n` F32 matmul and return the calls it added on `path`. Other tests
+    /// share the counters, so only a lower bound is meaningful.
+    fn calls_added(m: usize, k: usize, n: usize, path: KernelPath) -> u64 {
+        let before = kernel_stats().calls(TensorType::F32, path);
+        let a = ramp(vec![m, k]);
+        let b = ramp(vec![k, n]);
+        let mut out = ramp(vec![m, n]);
+        matmul(&a, &b, &mut out).unwrap();
+        kernel_stats().calls(TensorType::F32, path) - before
+    }
+
+    #[test]
+    fn matmul_records_the_dispatched_path() {
+        assert!(calls_added(1, 8, 4, KernelPath::Scalar) >= 1);
+        assert!(calls_added(64, 64, 32, KernelPath::ScalarParallel) >= 1);
+
+        let report = kernel_stats().to_string();
+        assert!(report.starts_with("CPU SIMD: "), "{report}");
+        assert!(report.contains("F32   scalar (parallel)"), "{report}");
+    }
+
+    /// NEON is detected, but no vector matmul kernel exists yet, so the scalar path must be the
+    /// one recorded.
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn aarch64_reports_neon_next_to_the_scalar_path() {
+        assert!(calls_added(1, 8, 4, KernelPath::Scalar) >= 1);
+        let stats = kernel_stats();
+        assert!(stats.cpu.neon);
+        assert!(stats.to_string().contains("NEON"));
+    }
+}
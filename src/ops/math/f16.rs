@@ -0,0 +1,154 @@
+//! IEEE half precision conversions, and a lookup table for the hot decode path.
+
+use alloc::boxed::Box;
+
+use super::float::pow2;
+
+/// f16 bits → f32 (Rust has no stable `f16` type).
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = (bits >> 15) & 0x1;
+    let exponent = (bits >> 10) & 0x1F;
+    let mantissa = bits & 0x3FF;
+
+    if exponent == 0 {
+        if mantissa == 0 {
+            if sign == 0 { 0.0 } else { -0.0 }
+        } else {
+            // Subnormal
+            let value = (mantissa as f32) / 1024.0 * pow2(-14);
+            if sign == 0 { value } else { -value }
+        }
+    } else if exponent == 0x1F {
+        if mantissa == 0 {
+            if sign == 0 {
+                f32::INFINITY
+            } else {
+                f32::NEG_INFINITY
+            }
+        } else {
+            f32::NAN
+        }
+    } else {
+        // Normalized
+        let exp = (exponent as i32) - 15;
+        let mant = 1.0 + (mantissa as f32) / 1024.0;
+        let value = mant * pow2(exp);
+        if sign == 0 { value } else { -value }
+    }
+}
+
+/// f32 → f16 bits, round-to-nearest-even; overflow saturates to ±inf, NaN stays a quiet NaN.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+    // Rebias 127 → 15.
+    let half_exp = exponent - 127 + 15;
+    if half_exp >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if half_exp <= 0 {
+        // Subnormal (or zero) half: shift the implicit-1 mantissa into the 10-bit field.
+        if half_exp < -10 {
+            return sign;
+        }
+        let full = mantissa | 0x80_0000;
+        let shift = (14 - half_exp) as u32;
+        let half = full >> shift;
+        let rem = full & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+    let half = ((half_exp as u32) << 10) | (mantissa >> 13);
+    let rem = mantissa & 0x1FFF;
+    let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // A carry out of the mantissa bumps the exponent, up to inf — which is the correct rounding.
+    sign | (half + round as u32) as u16
+}
+
+fn build_table() -> Box<[f32]> {
+    (0..=u16::MAX).map(f16_to_f32).collect()
+}
+
+/// Every f16 bit pattern decoded once; used where f16 is read per element (the f16 KV cache).
+#[cfg(not(feature = "no-std-core"))]
+fn table() -> &'static [f32] {
+    static TABLE: std::sync::OnceLock<Box<[f32]>> = std::sync::OnceLock::new();
+    TABLE.get_or_init(build_table)
+}
+
+/// [`table`] without `std::sync`: the first callers race to build it and one compare-exchange
+/// publishes the winner (the losers free theirs), so every caller sees the same leaked table.
+#[cfg(feature = "no-std-core")]
+fn table() -> &'static [f32] {
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    /// Entries: one per f16 bit pattern.
+    const TABLE_LEN: usize = 1 << 16;
+    static TABLE: AtomicPtr<f32> = AtomicPtr::new(ptr::null_mut());
+    let mut table = TABLE.load(Ordering::Acquire);
+    if table.is_null() {
+        let built = Box::into_raw(build_table()).cast::<f32>();
+        table = match TABLE.compare_exchange(
+            ptr::null_mut(),
+            built,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => built,
+            Err(winner) => {
+                // SAFETY: `built` came from `Box::into_raw` of a `TABLE_LEN` slice just above
+                // and was never published.
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(built, TABLE_LEN)) });
+                winner
+            }
+        };
+    }
+    // SAFETY: a published pointer is a leaked `TABLE_LEN`-element allocation that is never freed
+    // or written again.
+    unsafe { core::slice::from_raw_parts(table, TABLE_LEN) }
+}
+
+/// [`f16_to_f32`] through a 256 KiB lookup table.
+#[inline]
+pub fn f16_to_f32_lut(bits: u16) -> f32 {
+    table()[bits as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_round_trips_every_non_nan_value() {
+        for bits in 0..=u16::MAX {
+            let x = f16_to_f32(bits);
+            if x.is_nan() {
+                assert!(f32_to_f16(x) & 0x7FFF > 0x7C00);
+                continue;
+            }
+            assert_eq!(f32_to_f16(x), bits, "{bits:#06x}");
+            assert_eq!(f16_to_f32_lut(bits).to_bits(), x.to_bits());
+        }
+    }
+
+    #[test]
+    fn f32_to_f16_rounds_to_nearest_even() {
+        // 1 + 2^-11 is halfway between 1.0 and the next f16; ties go to the even mantissa.
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3C02);
+        assert_eq!(f32_to_f16(1.0 + 1.5 * 2f32.powi(-11)), 0x3C01);
+        assert_eq!(f32_to_f16(65520.0), 0x7C00);
+        assert_eq!(f32_to_f16(65519.0), 0x7BFF);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0);
+        assert_eq!(f32_to_f16(-3.0 * 2f32.powi(-26)), 0x8001);
+    }
+}
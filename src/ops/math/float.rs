@@ -0,0 +1,51 @@
+//! The `f32` functions the kernels need that `core` does not provide: `std`'s inherent methods,
+//! or `libm` with the `no-std-core` feature. Results may differ from `std` in the last bit.
+
+#[cfg(not(feature = "no-std-core"))]
+mod imp {
+    #[inline]
+    pub fn exp(x: f32) -> f32 {
+        x.exp()
+    }
+
+    #[inline]
+    pub fn sqrt(x: f32) -> f32 {
+        x.sqrt()
+    }
+
+    #[inline]
+    pub fn powf(x: f32, y: f32) -> f32 {
+        x.powf(y)
+    }
+
+    #[inline]
+    pub fn sin(x: f32) -> f32 {
+        x.sin()
+    }
+
+    #[inline]
+    pub fn cos(x: f32) -> f32 {
+        x.cos()
+    }
+
+    /// Half-way cases away from zero, as [`f32::round`].
+    #[inline]
+    pub fn round(x: f32) -> f32 {
+        x.round()
+    }
+}
+
+#[cfg(feature = "no-std-core")]
+mod imp {
+    pub use libm::{cosf as cos, expf as exp, powf, roundf as round, sinf as sin, sqrtf as sqrt};
+}
+
+pub use imp::*;
+
+/// `2^e` for `e` in the normal `f32` range, built from the exponent bits (exact, unlike a
+/// `powf` that may round).
+#[inline]
+pub fn pow2(e: i32) -> f32 {
+    debug_assert!((-126..=127).contains(&e), "pow2({e}) is not a normal f32");
+    f32::from_bits(((e + 127) as u32) << 23)
+}
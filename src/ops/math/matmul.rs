@@ -0,0 +1,159 @@
+//! Row kernels behind [`crate::ops::matmul`]: one input row against every output column of a
+//! ggml weight, whose `(kk, col)` element is at `kk + col * k` (each column's `k` weights are
+//! contiguous). Quantized weights are decoded a block at a time as the column walk reaches them.
+//! [`quantized_row_tiled`] reads the same blocks reordered into tiles of [`COLUMN_TILE`] columns
+//! (the engine's `WeightLayout::ColumnTiles`).
+
+use super::OpsError;
+use super::quant::{
+    BLOCK_ELEMENTS, Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE,
+    dequantize_q4k_block, dequantize_q6k_block, dequantize_q8_0_block,
+};
+
+/// A ggml block format a quantized weight is stored in.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockFormat {
+    pub name: &'static str,
+    /// Weights per block.
+    pub elements: usize,
+    /// Bytes per block.
+    pub bytes: usize,
+    pub dequantize: fn(&[u8], &mut [f32]) -> Result<(), OpsError>,
+}
+
+pub const Q8_0: BlockFormat = BlockFormat {
+    name: "Q8_0",
+    elements: Q8_0_BLOCK_ELEMENTS,
+    bytes: Q8_0_BLOCK_SIZE,
+    dequantize: dequantize_q8_0_block,
+};
+
+pub const Q4K: BlockFormat = BlockFormat {
+    name: "Q4K",
+    elements: BLOCK_ELEMENTS,
+    bytes: Q4K_BLOCK_SIZE,
+    dequantize: dequantize_q4k_block,
+};
+
+pub const Q6K: BlockFormat = BlockFormat {
+    name: "Q6K",
+    elements: BLOCK_ELEMENTS,
+    bytes: Q6K_BLOCK_SIZE,
+    dequantize: dequantize_q6k_block,
+};
+
+/// Output columns per tile of a [`quantized_row_tiled`] weight.
+pub const COLUMN_TILE: usize = 4;
+
+/// `acc`, plus `residual[idx]` when fusing a residual add.
+#[inline(always)]
+fn with_residual(acc: f32, residual: Option<&[f32]>, idx: usize) -> f32 {
+    match residual {
+        Some(r) => r[idx] + acc,
+        None => acc,
+    }
+}
+
+/// `out_row[col] = residual_row[col] + sum_kk input_row[kk] * weight[kk + col * k]`, with
+/// `k = input_row.len()`; without `residual_row`, just the sum.
+pub fn f32_row(
+    input_row: &[f32],
+    weight: &[f32],
+    residual_row: Option<&[f32]>,
+    out_row: &mut [f32],
+) {
+    let k = input_row.len();
+    for (col, out_cell) in out_row.iter_mut().enumerate() {
+        let mut acc = 0.0f32;
+        for kk in 0..k {
+            acc += input_row[kk] * weight[kk + col * k];
+        }
+        *out_cell = with_residual(acc, residual_row, col);
+    }
+}
+
+/// [`f32_row`] against `weight` stored as `format` blocks. Zero inputs are skipped (their
+/// blocks may never be decoded); a block past the end of `weight` is
+/// [`OpsError::BlockOutOfBounds`].
+pub fn quantized_row(
+    input_row: &[f32],
+    weight: &[u8],
+    format: &BlockFormat,
+    residual_row: Option<&[f32]>,
+    out_row: &mut [f32],
+) -> Result<(), OpsError> {
+    let k = input_row.len();
+    let mut decoded = [0.0f32; BLOCK_ELEMENTS];
+    let decoded = &mut decoded[..format.elements];
+    let mut current_block_idx = usize::MAX;
+    for (col, out_cell) in out_row.iter_mut().enumerate() {
+        let mut acc = 0.0f32;
+        for (kk, &a) in input_row.iter().enumerate() {
+            if a == 0.0 {
+                continue;
+            }
+            let weight_idx = kk + col * k;
+            let block_idx = weight_idx / format.elements;
+            if block_idx != current_block_idx {
+                let block_start = block_idx * format.bytes;
+                let block = weight.get(block_start..block_start + format.bytes).ok_or(
+                    OpsError::BlockOutOfBounds {
+                        format: format.name,
+                    },
+                )?;
+                (format.dequantize)(block, decoded)?;
+                current_block_idx = block_idx;
+            }
+            acc += a * decoded[weight_idx % format.elements];
+        }
+        *out_cell = with_residual(acc, residual_row, col);
+    }
+    Ok(())
+}
+
+/// [`quantized_row`] against a weight whose blocks are tiled: for each tile of [`COLUMN_TILE`]
+/// columns, block `b` of each column in turn, then block `b + 1`. Every input segment is decoded
+/// against the whole tile, accumulating the tile's columns side by side. Needs `k` to be a
+/// multiple of `format.elements` and `out_row.len()` of [`COLUMN_TILE`]; each column's sum is
+/// taken in the same order as [`quantized_row`], so the outputs are bit-identical.
+pub fn quantized_row_tiled(
+    input_row: &[f32],
+    weight: &[u8],
+    format: &BlockFormat,
+    residual_row: Option<&[f32]>,
+    out_row: &mut [f32],
+) -> Result<(), OpsError> {
+    let blocks_per_col = input_row.len() / format.elements;
+    let tile_bytes = COLUMN_TILE * format.bytes;
+    let mut decoded = [[0.0f32; BLOCK_ELEMENTS]; COLUMN_TILE];
+    for (tile, out_tile) in out_row.chunks_mut(COLUMN_TILE).enumerate() {
+        let mut acc = [0.0f32; COLUMN_TILE];
+        for (b, segment) in input_row.chunks_exact(format.elements).enumerate() {
+            if segment.iter().all(|&a| a == 0.0) {
+                continue;
+            }
+            let start = (tile * blocks_per_col + b) * tile_bytes;
+            let blocks =
+                weight
+                    .get(start..start + tile_bytes)
+                    .ok_or(OpsError::BlockOutOfBounds {
+                        format: format.name,
+                    })?;
+            for (block, dst) in blocks.chunks_exact(format.bytes).zip(decoded.iter_mut()) {
+                (format.dequantize)(block, &mut dst[..format.elements])?;
+            }
+            for (i, &a) in segment.iter().enumerate() {
+                if a == 0.0 {
+                    continue;
+                }
+                for (acc, dst) in acc.iter_mut().zip(&decoded) {
+                    *acc += a * dst[i];
+                }
+            }
+        }
+        for (j, out_cell) in out_tile.iter_mut().enumerate() {
+            *out_cell = with_residual(acc[j], residual_row, tile * COLUMN_TILE + j);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! The arithmetic of the CPU kernels in `core` + `alloc` only, for reuse without `std`
+//! (embedded, WASM). The rest of [`crate::ops`] wraps these for the engine: Tensor shapes,
+//! threading, kernel stats, and [`OpsError`] converted into [`crate::EngineError`].
+//!
+//! Nothing here touches threads, clocks, files or CPU feature detection; the only runtime
+//! dispatch is [`specialized`]'s choice of fixed-length loop, which is plain portable code, so a
+//! target without SIMD simply runs the same scalar loops. With the `no-std-core` feature the
+//! transcendental functions come from `libm` ([`float`]) and the f16 table is initialised without
+//! `std::sync` ([`f16`]), exactly as in a `no_std` build, so the crate's own tests cover those
+//! paths too. `no_std_check/` builds this directory as a `#![no_std]` crate and runs its unit
+//! tests. Module files refer to each other only through `super::`, so that crate can include
+//! them by path.
+
+use core::fmt;
+
+pub mod f16;
+pub mod float;
+pub mod matmul;
+pub mod quant;
+pub mod rmsnorm;
+pub mod rope;
+pub mod softmax;
+pub mod specialized;
+pub mod swiglu;
+
+/// Invalid arguments to a kernel. Every variant is `Copy` and carries only lengths and static
+/// names, so reporting an error never allocates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpsError {
+    /// [`softmax::softmax`] over no values.
+    EmptySoftmax,
+    SigmoidLength {
+        input: usize,
+        output: usize,
+    },
+    SwigluLength {
+        gate: usize,
+        up: usize,
+        output: usize,
+    },
+    RotaryExceedsHead {
+        rotary_dim: u32,
+        head_dim: u32,
+    },
+    OddRotarySpan,
+    ShortFreqFactors {
+        len: usize,
+        pairs: usize,
+    },
+    /// A quant block, or the buffer it is decoded into or encoded from, is shorter than
+    /// `format` needs. `buffer` names which one.
+    ShortBlock {
+        format: &'static str,
+        buffer: &'static str,
+    },
+    /// A quantized weight's buffer ends before one of the blocks its shape implies.
+    BlockOutOfBounds {
+        format: &'static str,
+    },
+}
+
+impl fmt::Display for OpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::EmptySoftmax => f.write_str("softmax: empty input"),
+            Self::SigmoidLength { input, output } => {
+                write!(f, "sigmoid: input len {input} != output len {output}")
+            }
+            Self::SwigluLength { gate, up, output } => write!(
+                f,
+                "swiglu: length mismatch (gate {gate}, up {up}, output {output})"
+            ),
+            Self::RotaryExceedsHead {
+                rotary_dim,
+                head_dim,
+            } => write!(f, "RoPE rotary_dim {rotary_dim} > head_dim {head_dim}"),
+            Self::OddRotarySpan => f.write_str("RoPE rotary span must be even"),
+            Self::ShortFreqFactors { len, pairs } => {
+                write!(f, "RoPE freq_factors len {len} < num_pairs {pairs}")
+            }
+            Self::ShortBlock { format, buffer } => write!(f, "{format} {buffer} too small"),
+            Self::BlockOutOfBounds { format } => write!(f, "{format} block out of bounds"),
+        }
+    }
+}
+
+impl core::error::Error for OpsError {}
@@ -0,0 +1,44 @@
+//! RMSNorm over f32 rows. The input should already be dequantized, and the learned weights
+//! are kept unquantized because their precision matters.
+
+use super::float::sqrt;
+use super::specialized::sum_squares;
+
+/// In-place RMS re-scaling only (no learned scale): `x /= sqrt(mean(x^2)+eps)`.
+/// Matches HF `Gemma4RMSNorm` with `with_scale=false` used on attention **values** in Gemma 4.
+pub fn rmsnorm_inplace_no_scale(x: &mut [f32], epsilon: f32) {
+    let dim = x.len();
+    if dim == 0 {
+        return;
+    }
+    let rms = sqrt(sum_squares(x) / dim as f32 + epsilon);
+    if rms > 0.0 {
+        for z in x.iter_mut() {
+            *z /= rms;
+        }
+    }
+}
+
+/// `output[i] = input[i] * (weights[i] + weight_offset) / rms(input)`. The offset is `1.0` for
+/// checkpoints that store the learned scale as a delta from 1.
+pub fn rmsnorm_with_offset(
+    input: &[f32],
+    weights: &[f32],
+    weight_offset: f32,
+    epsilon: f32,
+    output: &mut [f32],
+) {
+    #[cfg(debug_assertions)]
+    debug_assert_eq!(
+        input.len(),
+        weights.len(),
+        "Dimension missmatch for RMSNorm"
+    );
+
+    let dim: usize = input.len();
+    let mean_squared: f32 = sum_squares(input) / (dim as f32);
+    let rms = sqrt(mean_squared + epsilon);
+    for ((out_slot, &x), &w) in output.iter_mut().zip(input.iter()).zip(weights.iter()) {
+        *out_slot = x * (w + weight_offset) / rms;
+    }
+}
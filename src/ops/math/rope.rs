@@ -0,0 +1,333 @@
+//! Rotary position embeddings: the per-pair angles, the channel layouts, and context-extension
+//! scaling.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::OpsError;
+use super::float::{cos, powf, sin};
+
+/// Which two channels of a head each RoPE pair rotates. The angle of pair `k` is the same for
+/// every layout ([`rope_angles`]); a layout only decides where the pair lives, so new
+/// architectures add a layout without touching [`rope_with_layout`]'s loop.
+pub trait RopeLayout {
+    /// Channel indices of pair `k` (`k < rotary_dim / 2`); both must be `< rotary_dim`.
+    fn pair(&self, k: usize, rotary_dim: usize) -> (usize, usize);
+}
+
+/// Adjacent channels `(2k, 2k + 1)`: ggml's default mode (Llama, Mistral), and GLM's partial
+/// rotary when `rotary_dim < head_dim`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Interleaved;
+
+impl RopeLayout for Interleaved {
+    fn pair(&self, k: usize, _rotary_dim: usize) -> (usize, usize) {
+        (2 * k, 2 * k + 1)
+    }
+}
+
+/// Channel `k` with channel `k + rotary_dim / 2`: the first and second halves of the rotated span
+/// (ggml `GGML_ROPE_TYPE_NEOX`, HF `rotate_half`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HalfSplit;
+
+impl RopeLayout for HalfSplit {
+    fn pair(&self, k: usize, rotary_dim: usize) -> (usize, usize) {
+        (k, k + rotary_dim / 2)
+    }
+}
+
+/// RoPE on `vec` (one head): rotate the first `rotary_dim` dimensions in non-overlapping
+/// [`Interleaved`] pairs.
+///
+/// Matches ggml `GGML_OP_ROPE` / `ggml_rope_cache_init` when `freq_factors` is set: per pair `k`,
+/// angle = `theta / ff[k]` where `theta` starts at `pos` and each step `theta *= base^(-2/n_rot)`
+/// with `n_rot = rotary_dim` (llama.cpp `n_dims` passed to `ggml_rope_ext`).
+///
+/// Gemma 4 **full-attention** layers store `blk.*.rope_freqs` (proportional RoPE), Llama 3.1
+/// long-context checkpoints a model-wide `rope_freqs.weight`; pass that slice (length ≥
+/// `rotary_dim/2`, typically `head_dim/2`). Sliding / plain Mistral: use `freq_factors: None`.
+///
+/// Order: the factors divide the **base** per-pair frequencies `base^(-2k/n_rot)`. Any rope
+/// scaling on top (linear / NTK position scaling) applies to those already-factored frequencies,
+/// as in ggml, so a checkpoint's factors are never rescaled. The per-pair angles are exactly
+/// [`rope_angles`].
+pub fn rope(
+    vec: &mut [f32],
+    base: f32,
+    pos: u32,
+    head_dim: u32,
+    rotary_dim: u32,
+    freq_factors: Option<&[f32]>,
+) -> Result<(), OpsError> {
+    rope_with_layout(
+        vec,
+        base,
+        pos,
+        head_dim,
+        rotary_dim,
+        freq_factors,
+        &Interleaved,
+    )
+}
+
+/// [`rope`] with the channel pairing given by `layout`; channels from `rotary_dim` on are left
+/// untouched whatever the layout.
+pub fn rope_with_layout(
+    vec: &mut [f32],
+    base: f32,
+    pos: u32,
+    head_dim: u32,
+    rotary_dim: u32,
+    freq_factors: Option<&[f32]>,
+    layout: &impl RopeLayout,
+) -> Result<(), OpsError> {
+    if rotary_dim > head_dim {
+        return Err(OpsError::RotaryExceedsHead {
+            rotary_dim,
+            head_dim,
+        });
+    }
+
+    let end = (rotary_dim as usize).min(vec.len());
+    if end % 2 != 0 {
+        return Err(OpsError::OddRotarySpan);
+    }
+    let num_pairs = end / 2;
+    if let Some(ff) = freq_factors {
+        if ff.len() < num_pairs {
+            return Err(OpsError::ShortFreqFactors {
+                len: ff.len(),
+                pairs: num_pairs,
+            });
+        }
+    }
+
+    for (k, angle) in angles(base, pos, rotary_dim, freq_factors)
+        .take(num_pairs)
+        .enumerate()
+    {
+        let (i, j) = layout.pair(k, end);
+        let temp_0 = vec[i];
+        let temp_1 = vec[j];
+        let (sin, cos) = (sin(angle), cos(angle));
+        vec[i] = temp_0 * cos - temp_1 * sin;
+        vec[j] = temp_0 * sin + temp_1 * cos;
+    }
+    Ok(())
+}
+
+/// Rotation angle of every pair (`rotary_dim / 2` of them) at `pos`, bit-identical to what
+/// [`rope`] applies. For inspecting the frequency table a checkpoint's factors produce.
+pub fn rope_angles(
+    base: f32,
+    pos: u32,
+    rotary_dim: u32,
+    freq_factors: Option<&[f32]>,
+) -> Result<Vec<f32>, OpsError> {
+    let num_pairs = rotary_dim as usize / 2;
+    if let Some(ff) = freq_factors {
+        if ff.len() < num_pairs {
+            return Err(OpsError::ShortFreqFactors {
+                len: ff.len(),
+                pairs: num_pairs,
+            });
+        }
+    }
+    Ok(angles(base, pos, rotary_dim, freq_factors)
+        .take(num_pairs)
+        .collect())
+}
+
+/// Context-extension scaling from `{arch}.rope.scaling.*`, turned into per-pair factors by
+/// [`Self::freq_scales`]. The factors divide the frequencies like a checkpoint's `rope_freqs`, so
+/// they go through the same `freq_factors` argument of [`rope`] (multiplied with the checkpoint's,
+/// if any).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RopeScaling {
+    /// Position interpolation: every frequency divided by `factor`.
+    Linear { factor: f32 },
+    /// YaRN ("NTK-by-parts"): pairs completing more than `beta_fast` turns over the original
+    /// context keep their frequency, pairs completing fewer than `beta_slow` are divided by
+    /// `factor`, and the ones in between blend linearly in that turn count. The attention
+    /// temperature YaRN also prescribes (`0.1 ln(factor) + 1`) is not applied.
+    Yarn {
+        factor: f32,
+        original_context_length: usize,
+        beta_fast: f32,
+        beta_slow: f32,
+    },
+}
+
+impl RopeScaling {
+    /// llama.cpp's defaults for `rope.scaling.yarn_beta_fast` / `yarn_beta_slow`.
+    pub const YARN_BETA_FAST: f32 = 32.0;
+    pub const YARN_BETA_SLOW: f32 = 1.0;
+
+    /// Divisor of each pair's frequency (`rotary_dim / 2` of them) for a layer with RoPE base
+    /// `base`: 1 leaves the pair as trained, `factor` fully interpolates it.
+    pub fn freq_scales(&self, base: f32, rotary_dim: u32) -> Vec<f32> {
+        let num_pairs = rotary_dim as usize / 2;
+        match *self {
+            Self::Linear { factor } => vec![factor; num_pairs],
+            Self::Yarn {
+                factor,
+                original_context_length,
+                beta_fast,
+                beta_slow,
+            } => angles(base, 1, rotary_dim, None)
+                .take(num_pairs)
+                .map(|freq| {
+                    let turns = original_context_length as f32 * freq / core::f32::consts::TAU;
+                    let ramp = ((turns - beta_slow) / (beta_fast - beta_slow)).clamp(0.0, 1.0);
+                    1.0 / (ramp + (1.0 - ramp) / factor)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// `theta` starts at `pos` and shrinks by `base^(-2/n_rot)` per pair; a zero factor counts as 1.
+fn angles(
+    base: f32,
+    pos: u32,
+    rotary_dim: u32,
+    freq_factors: Option<&[f32]>,
+) -> impl Iterator<Item = f32> {
+    let theta_scale = powf(base, -2.0 / rotary_dim as f32);
+    let mut theta = pos as f32;
+    (0..).map(move |k| {
+        let ff = freq_factors
+            .and_then(|f| f.get(k))
+            .copied()
+            .filter(|x| *x != 0.0)
+            .unwrap_or(1.0);
+        let angle = theta / ff;
+        theta *= theta_scale;
+        angle
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_rope_dim2() {
+        let mut v = [1.0, 2.0];
+        super::rope(&mut v[..], 1.0, 1, 2, 2, None).unwrap();
+        assert!((v[0] + 1.142_639_6).abs() < 1e-5);
+        assert!((v[1] - 1.922_075_6).abs() < 1e-5);
+    }
+
+    /// Pairs must be (0,1) and (2,3), not overlapping (0,1),(1,2),(2,3).
+    #[test]
+    fn test_rope_dim4_pairs_non_overlapping() {
+        let mut v = [1.0f32, 0.0, 1.0, 0.0];
+        super::rope(&mut v[..], 10000.0, 0, 4, 4, None).unwrap();
+        assert!((v[0] - 1.0).abs() < 1e-5 && (v[1] - 0.0).abs() < 1e-5);
+        assert!((v[2] - 1.0).abs() < 1e-5 && (v[3] - 0.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn freq_factor_doubles_effective_angle_for_pair0() {
+        let mut a = [1.0f32, 0.0];
+        let mut b = [1.0f32, 0.0];
+        let ff = [2.0f32];
+        super::rope(&mut a, 10000.0, 1, 2, 2, None).unwrap();
+        super::rope(&mut b, 10000.0, 1, 2, 2, Some(&ff)).unwrap();
+        assert!(a != b);
+        assert!((a[0] - b[0]).abs() > 1e-3);
+    }
+
+    #[test]
+    fn angle_table_divides_base_frequencies_by_factors() {
+        let plain = super::rope_angles(500000.0, 7, 8, None).unwrap();
+        let ff = [1.0f32, 4.0, 8.0, 0.0];
+        let scaled = super::rope_angles(500000.0, 7, 8, Some(&ff)).unwrap();
+        assert_eq!(plain.len(), 4);
+        assert_eq!(plain[0], 7.0);
+        for k in 0..4 {
+            let f = if ff[k] == 0.0 { 1.0 } else { ff[k] };
+            assert_eq!(scaled[k].to_bits(), (plain[k] / f).to_bits(), "pair {k}");
+        }
+        assert!(super::rope_angles(500000.0, 7, 8, Some(&ff[..3])).is_err());
+
+        // Same angles as rope() applies.
+        let mut v = [1.0f32, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        super::rope(&mut v, 500000.0, 7, 8, 8, Some(&ff)).unwrap();
+        for k in 0..4 {
+            assert_eq!(v[2 * k].to_bits(), super::cos(scaled[k]).to_bits());
+        }
+    }
+
+    #[test]
+    fn yarn_keeps_high_frequency_pairs_and_interpolates_low_ones() {
+        let yarn = super::RopeScaling::Yarn {
+            factor: 4.0,
+            original_context_length: 4096,
+            beta_fast: 32.0,
+            beta_slow: 1.0,
+        };
+        let scales = yarn.freq_scales(10000.0, 128);
+        assert_eq!(scales.len(), 64);
+        assert_eq!(scales[0], 1.0);
+        assert!((scales[63] - 4.0).abs() < 1e-6);
+        assert!(scales.windows(2).all(|w| w[0] <= w[1]), "{scales:?}");
+        assert!(scales.iter().any(|&s| s > 1.0 && s < 4.0), "a blended band");
+
+        // Rotation is cut only where the wavelength outgrows the original context.
+        let plain = super::rope_angles(10000.0, 5000, 128, None).unwrap();
+        let scaled = super::rope_angles(10000.0, 5000, 128, Some(&scales)).unwrap();
+        assert_eq!(scaled[0], plain[0]);
+        assert!((scaled[63] * 4.0 - plain[63]).abs() < 1e-6 * plain[63]);
+        let reduction = |k: usize| plain[k] / scaled[k];
+        assert!(reduction(0) < reduction(32) && reduction(32) < reduction(63));
+
+        let linear = super::RopeScaling::Linear { factor: 2.0 }.freq_scales(10000.0, 8);
+        assert_eq!(linear, [2.0; 4]);
+    }
+
+    use super::{HalfSplit, Interleaved, RopeLayout};
+
+    /// Channels whose value changes when only channel `c` is set (pos 1, so every angle is
+    /// non-zero).
+    fn moved_by(layout: &impl RopeLayout, c: usize, head_dim: u32, rotary_dim: u32) -> Vec<usize> {
+        let mut v = vec![0.0f32; head_dim as usize];
+        v[c] = 1.0;
+        super::rope_with_layout(&mut v, 100.0, 1, head_dim, rotary_dim, None, layout).unwrap();
+        (0..v.len())
+            .filter(|&i| v[i] != if i == c { 1.0 } else { 0.0 })
+            .collect()
+    }
+
+    #[test]
+    fn interleaved_rotates_adjacent_channels_of_the_rotary_span() {
+        let pairs: Vec<_> = (0..3).map(|k| Interleaved.pair(k, 6)).collect();
+        assert_eq!(pairs, [(0, 1), (2, 3), (4, 5)]);
+        // Partial rotary (GLM): 4 of 8 channels.
+        assert_eq!(moved_by(&Interleaved, 0, 8, 4), [0, 1]);
+        assert_eq!(moved_by(&Interleaved, 3, 8, 4), [2, 3]);
+        assert!(moved_by(&Interleaved, 5, 8, 4).is_empty());
+
+        let mut a = [0.3f32, -1.0, 2.0, 0.5, 7.0, 8.0];
+        let mut b = a;
+        super::rope(&mut a, 10000.0, 5, 6, 4, None).unwrap();
+        super::rope_with_layout(&mut b, 10000.0, 5, 6, 4, None, &Interleaved).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn half_split_pairs_each_channel_with_its_twin_in_the_second_half() {
+        let pairs: Vec<_> = (0..3).map(|k| HalfSplit.pair(k, 6)).collect();
+        assert_eq!(pairs, [(0, 3), (1, 4), (2, 5)]);
+        assert_eq!(moved_by(&HalfSplit, 0, 8, 4), [0, 2]);
+        assert_eq!(moved_by(&HalfSplit, 3, 8, 4), [1, 3]);
+        assert!(moved_by(&HalfSplit, 4, 8, 4).is_empty());
+
+        // Pair 0 gets the same angle in both layouts.
+        let mut inter = [1.0f32, 0.5, 0.0, 0.0];
+        let mut half = [1.0f32, 0.0, 0.5, 0.0];
+        super::rope_with_layout(&mut inter, 10000.0, 3, 4, 4, None, &Interleaved).unwrap();
+        super::rope_with_layout(&mut half, 10000.0, 3, 4, 4, None, &HalfSplit).unwrap();
+        assert_eq!((inter[0], inter[1]), (half[0], half[2]));
+    }
+}
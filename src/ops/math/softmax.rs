@@ -0,0 +1,83 @@
+//! Numerically stable softmax.
+
+use super::OpsError;
+use super::float::exp;
+
+/// Numerically stable softmax (`exp(x - max) / sum`).
+///
+/// Masked entries are `-inf` and get weight exactly 0. If **every** entry is masked there is no
+/// distribution to normalize: the output is all zeros (not NaN from `exp(-inf - -inf)`), so an
+/// attention row with no visible keys contributes nothing instead of poisoning later layers.
+pub fn softmax(input: &[f32], output: &mut [f32]) -> Result<(), OpsError> {
+    #[cfg(debug_assertions)]
+    debug_assert_eq!(input.len(), output.len(), "Dimenssion mismatch at softmax");
+
+    if input.is_empty() {
+        return Err(OpsError::EmptySoftmax);
+    }
+
+    // Find max value for numerical stability
+    let mut max = input[0];
+    for &x in input.iter() {
+        if x > max {
+            max = x;
+        }
+    }
+    if max == f32::NEG_INFINITY {
+        output.fill(0.0);
+        return Ok(());
+    }
+
+    let mut sum_exp = 0.0f32;
+    for (out_slot, &x) in output.iter_mut().zip(input.iter()) {
+        *out_slot = exp(x - max);
+        sum_exp += *out_slot;
+    }
+    for out_slot in output.iter_mut() {
+        *out_slot /= sum_exp;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::softmax;
+
+    #[test]
+    fn simple_softmax_test() {
+        let input = vec![0.0, 1.0];
+        let mut output = vec![0.0; input.len()];
+
+        softmax(&input, &mut output).unwrap();
+
+        let sum: f32 = output.iter().sum();
+        assert!(
+            (sum - 1.0).abs() < 1e-5,
+            "Softmax outputs should sum to 1.0"
+        );
+
+        assert!((output[0] - 0.268_941_4).abs() < 1e-5);
+        assert!((output[1] - 0.731_058_6).abs() < 1e-5);
+    }
+
+    #[test]
+    fn all_masked_input_gives_zeros_not_nan() {
+        let input = [f32::NEG_INFINITY; 4];
+        let mut output = [1.0f32; 4];
+        softmax(&input, &mut output).unwrap();
+        assert_eq!(output, [0.0; 4]);
+    }
+
+    #[test]
+    fn partially_masked_input_ignores_masked_entries() {
+        let input = [f32::NEG_INFINITY, 2.0, f32::NEG_INFINITY, 2.0];
+        let mut output = [0.0f32; 4];
+        softmax(&input, &mut output).unwrap();
+        assert_eq!(output, [0.0, 0.5, 0.0, 0.5]);
+
+        let mut single = [0.0f32; 3];
+        softmax(&[f32::NEG_INFINITY, -1e30, f32::NEG_INFINITY], &mut single).unwrap();
+        assert_eq!(single, [0.0, 1.0, 0.0]);
+    }
+}
@@ -0,0 +1,75 @@
+//! SiLU-gated FFN activation and the sigmoid under it.
+
+use super::OpsError;
+use super::float::exp;
+
+/// Numerically stable `1 / (1 + exp(-x))`: the exponent is never positive.
+#[inline]
+fn sigmoid_one(x: f32) -> f32 {
+    if x >= 0.0 {
+        1.0 / (1.0 + exp(-x))
+    } else {
+        let z = exp(x);
+        z / (1.0 + z)
+    }
+}
+
+pub fn sigmoid(input: &[f32], output: &mut [f32]) -> Result<(), OpsError> {
+    if input.len() != output.len() {
+        return Err(OpsError::SigmoidLength {
+            input: input.len(),
+            output: output.len(),
+        });
+    }
+    for (out, &x) in output.iter_mut().zip(input) {
+        *out = sigmoid_one(x);
+    }
+    Ok(())
+}
+
+/// Llama/Mistral FFN gated activation: **SiLU(gate) × up** (same as `silu(gate) * up` in HF / llama.cpp).
+/// `gate` is the gate projection row; `up` is the up projection row (same length).
+///
+/// All three slices must have the same length (`ffn_dim` per row); a mismatch is a caller bug and
+/// is reported as [`OpsError::SwigluLength`] in every build profile rather than indexing out of
+/// bounds. Allocation-free: the sigmoid is applied element by element.
+pub fn swiglu(gate: &[f32], up: &[f32], output: &mut [f32]) -> Result<(), OpsError> {
+    if gate.len() != up.len() || gate.len() != output.len() {
+        return Err(OpsError::SwigluLength {
+            gate: gate.len(),
+            up: up.len(),
+            output: output.len(),
+        });
+    }
+    for ((out, &g), &u) in output.iter_mut().zip(gate).zip(up) {
+        *out = g * sigmoid_one(g) * u;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn simple_swiglu() {
+        let gate = vec![0.0, 1.0];
+        let up = vec![1.0, 1.0];
+        let mut output = vec![0.0; gate.len()];
+
+        super::swiglu(&gate, &up, &mut output).unwrap();
+
+        // SiLU(0)*1 = 0; SiLU(1)*1 ≈ 0.731
+        assert!((output[0] - 0.0).abs() < 1e-5);
+        assert!((output[1] - 0.731_058_6).abs() < 1e-3);
+    }
+
+    #[test]
+    fn swiglu_mismatched_lengths_error() {
+        let mut output = vec![0.0; 2];
+        let err = super::swiglu(&[0.0, 1.0], &[1.0], &mut output).unwrap_err();
+        assert!(err.to_string().contains("gate 2, up 1"), "{err}");
+
+        let mut short = vec![0.0; 1];
+        let err = super::swiglu(&[0.0, 1.0], &[1.0, 1.0], &mut short).unwrap_err();
+        assert!(err.to_string().contains("output 1"), "{err}");
+    }
+}
@@ -1,13 +1,24 @@
 //! 2D weight layout matches **ggml / GGUF** (same as llama.cpp): for `ne = [ne0, ne1]`,
 //! element `(i0, i1)` is at **`i0 + i1 * ne0`** (first dimension stride-1), **not** C row-major
 //! `i0 * ne1 + i1`. Matmul uses `W(input_kk, out_col)` at `kk + col * K` with `K = ne0`.
+//!
+//! GGUF weights are therefore already output-feature-major: every output column's `K` weights are
+//! one contiguous run of whole quant blocks (llama.cpp requires `ne0` to be a multiple of the block
+//! size), and the kernels' inner loop over `kk` walks it front to back;
+//! `each_output_column_reads_only_its_own_blocks` pins this. A quantized weight reordered at load
+//! into [`WeightLayout::ColumnTiles`] (see
+//! [`crate::model_loader::gguf_types::LoadOptions::optimize_layout`]) goes through
+//! [`math::matmul::quantized_row_tiled`] instead, with bit-identical results. An F32 weight held
+//! the other way round (`kk * N + col`, as `x @ W` code stores it) is repacked once with
+//! [`repack_input_major`] rather than multiplied with a strided inner loop.
 
-use crate::EngineError;
-use crate::core::tensor::{Tensor, TensorType};
+use crate::core::tensor::{Tensor, TensorType, WeightLayout};
+use crate::ops::kernel_stats::{self, KernelPath};
+use crate::ops::math;
 use crate::ops::quant::quant_k_handler::{
-    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, dequantize_q4k_block,
-    dequantize_q6k_block, dequantize_q8_0_block,
+    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE,
 };
+use crate::{EngineError, Result};
 use rayon::prelude::*;
 
 const BLOCK_ELEMENTS: usize = 256;
@@ -15,16 +26,82 @@ const BLOCK_ELEMENTS: usize = 256;
 // Derived heuristic on minimum number of ops needed for threading to prove useful. Should be further finetuned
 const PARALLEL_MATMUL_MIN_OPS: usize = 64 * 1024;
 
-pub fn matmul(a: &Tensor, b: &Tensor, output: &mut Tensor) -> Result<(), EngineError> {
+/// `output[M, N] = a[M, K] · b` where `b` is a ggml weight with dims `[K, N]`.
+///
+/// The contraction axis is **`b.dimensions()[0]`** (ggml `ne0`, stride-1), which must equal the
+/// input row length `a.dimensions()[1]`. A weight stored the other way round (`[N, K]`) is
+/// reported as a likely transposed layout rather than silently misread.
+pub fn matmul(a: &Tensor, b: &Tensor, output: &mut Tensor) -> Result<()> {
+    dispatch(a, b, None, output)
+}
+
+/// `output = residual + a · b`: [`matmul`] with the residual add fused into the store, so the
+/// projection result never goes through an intermediate buffer. `residual` has the output's
+/// `M * N` elements, row-major. Goes through the same dtype dispatch (and kernel stats) as
+/// [`matmul`], so a kernel added there serves both; each output element is exactly
+/// `residual[i] + (a · b)[i]`, bit-identical to a separate [`matmul`] and
+/// [`crate::ops::residual_add::residual_add`].
+pub fn matmul_add(a: &Tensor, b: &Tensor, residual: &[f32], output: &mut Tensor) -> Result<()> {
+    let len = output.dimensions().iter().product::<usize>();
+    if residual.len() != len {
+        return Err(EngineError::MatMul(format!(
+            "residual has {} elements, output {:?} has {len}",
+            residual.len(),
+            output.dimensions()
+        )));
+    }
+    dispatch(a, b, Some(residual), output)
+}
+
+/// Repack an F32 weight held input-major, C row-major `[in_features, out_features]` with
+/// `W(kk, col)` at `kk * out_features + col` (as `x @ W` code stores it), into the ggml `[K, N]`
+/// tensor [`matmul`] takes: one contiguous run of `in_features` weights per output, i.e.
+/// `[out_features, in_features]` row-major. Done once at load, so the kernel's inner loop reads
+/// contiguous weights instead of striding by `out_features`.
+pub fn repack_input_major(
+    weight: &[f32],
+    in_features: usize,
+    out_features: usize,
+) -> Result<Tensor> {
+    if in_features.checked_mul(out_features) != Some(weight.len()) {
+        return Err(EngineError::MatMul(format!(
+            "input-major weight has {} values, expected {in_features} x {out_features}",
+            weight.len()
+        )));
+    }
+    let mut bytes = Vec::with_capacity(weight.len() * 4);
+    for col in 0..out_features {
+        for kk in 0..in_features {
+            bytes.extend_from_slice(&weight[kk * out_features + col].to_le_bytes());
+        }
+    }
+    Tensor::from_bytes(TensorType::F32, bytes, vec![in_features, out_features])
+}
+
+/// Validate shapes and run the kernel for `b`'s dtype; `residual`, when set, is added to every
+/// output element as it is stored.
+fn dispatch(a: &Tensor, b: &Tensor, residual: Option<&[f32]>, output: &mut Tensor) -> Result<()> {
     // Validate dimensions
     let b_dims = b.dimensions();
     let a_dims = a.dimensions();
     let output_dims = output.dimensions();
 
+    if a_dims.len() != 2 || b_dims.len() != 2 || output_dims.len() != 2 {
+        return Err(EngineError::MatMul(format!(
+            "expected 2D tensors, got input {a_dims:?}, weight {b_dims:?}, output {output_dims:?}"
+        )));
+    }
+
     if a_dims[1] != b_dims[0] {
+        let hint = if a_dims[1] == b_dims[1] {
+            " (weight looks transposed: dims[1] matches the input)"
+        } else {
+            ""
+        };
         return Err(EngineError::MatMul(format!(
-            "inner dim mismatch: input {} vs weight {}",
-            a_dims[1], b_dims[0]
+            "inner dim mismatch: input row length {} vs weight dims [{}, {}] \
+             (contraction axis is weight dims[0]){hint}",
+            a_dims[1], b_dims[0], b_dims[1]
         )));
     }
 
@@ -35,12 +112,34 @@ pub fn matmul(a: &Tensor, b: &Tensor, output: &mut Tensor) -> Result<(), EngineE
         )));
     }
 
-    // Dispatch to appropriate kernel based on weight tensor type
+    // Saturating here prevents overflow, and forces this product to be at most the max possible for usize
+    let ops = a_dims[0]
+        .saturating_mul(b_dims[1])
+        .saturating_mul(a_dims[1]);
+    let path = if ops >= PARALLEL_MATMUL_MIN_OPS {
+        KernelPath::ScalarParallel
+    } else {
+        KernelPath::Scalar
+    };
+    if a.dtype() == TensorType::F32 {
+        kernel_stats::record(b.dtype(), path);
+    }
+    run_kernel(a, b, residual, output, path)
+}
+
+/// Run the kernel for `b`'s dtype on `path`, shapes already validated by [`dispatch`].
+fn run_kernel(
+    a: &Tensor,
+    b: &Tensor,
+    residual: Option<&[f32]>,
+    output: &mut Tensor,
+    path: KernelPath,
+) -> Result<()> {
     match (a.dtype(), b.dtype()) {
-        (TensorType::F32, TensorType::F32) => matmul_f32_f32(a, b, output),
-        (TensorType::F32, TensorType::Q4K) => matmul_f32_q4k(a, b, output),
-        (TensorType::F32, TensorType::Q6K) => matmul_f32_q6k(a, b, output),
-        (TensorType::F32, TensorType::Q8_0) => matmul_f32_q8_0(a, b, output),
+        (TensorType::F32, TensorType::F32) => matmul_f32_f32(a, b, residual, output, path),
+        (TensorType::F32, TensorType::Q4K) => matmul_f32_q4k(a, b, residual, output, path),
+        (TensorType::F32, TensorType::Q6K) => matmul_f32_q6k(a, b, residual, output, path),
+        (TensorType::F32, TensorType::Q8_0) => matmul_f32_q8_0(a, b, residual, output, path),
         _ => Err(EngineError::MatMul(format!(
             "unsupported matmul: {:?} × {:?}",
             a.dtype(),
@@ -49,9 +148,37 @@ pub fn matmul(a: &Tensor, b: &Tensor, output: &mut Tensor) -> Result<(), EngineE
     }
 }
 
+/// The row kernel reading `weight`'s blocks in the order its [`WeightLayout`] stores them.
+fn row_kernel_for(weight: &Tensor) -> QuantizedRowKernel {
+    match weight.layout() {
+        WeightLayout::Ggml => math::matmul::quantized_row,
+        WeightLayout::ColumnTiles => math::matmul::quantized_row_tiled,
+    }
+}
+
+type QuantizedRowKernel = fn(
+    &[f32],
+    &[u8],
+    &math::matmul::BlockFormat,
+    Option<&[f32]>,
+    &mut [f32],
+) -> std::result::Result<(), math::OpsError>;
+
+/// Row `row` (of width `n`) of the residual, when fusing a residual add ([`matmul_add`]).
+#[inline(always)]
+fn residual_row(residual: Option<&[f32]>, row: usize, n: usize) -> Option<&[f32]> {
+    residual.map(|r| &r[row * n..(row + 1) * n])
+}
+
 /// F32 × F32 matrix multiplication  
 /// `output[row, col] = sum_kk input[row, kk] * W(kk, col)` with ggml `W` indexing.
-fn matmul_f32_f32(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Result<(), EngineError> {
+fn matmul_f32_f32(
+    input: &Tensor,
+    weight: &Tensor,
+    residual: Option<&[f32]>,
+    output: &mut Tensor,
+    path: KernelPath,
+) -> Result<()> {
     // Expect input: [M, K], weight: [K, N], output: [M, N]
     if input.dimensions().len() != 2
         || weight.dimensions().len() != 2
@@ -80,39 +207,21 @@ fn matmul_f32_f32(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
     let weight_data = weight.as_f32_slice()?;
     let output_data = output.as_f32_slice_mut()?;
 
-    // Saturating here prevents overflow, and forces this product to be at most the max possible for usize
-    let ops = m.saturating_mul(n).saturating_mul(k);
-
-    if ops >= PARALLEL_MATMUL_MIN_OPS {
+    let row_kernel = |(row, out_row): (usize, &mut [f32])| {
+        math::matmul::f32_row(
+            &input_data[row * k..(row + 1) * k],
+            weight_data,
+            residual_row(residual, row, n),
+            out_row,
+        )
+    };
+    if path == KernelPath::ScalarParallel {
         output_data
             .par_chunks_mut(n)
             .enumerate()
-            .for_each(|(row, out_row)| {
-                let input_row_start = row * k;
-                for (col, out_cell) in out_row.iter_mut().enumerate() {
-                    let mut acc = 0.0f32;
-                    for kk in 0..k {
-                        let a = input_data[input_row_start + kk];
-                        let w = weight_data[kk + col * k];
-                        acc += a * w;
-                    }
-                    *out_cell = acc;
-                }
-            });
+            .for_each(row_kernel);
     } else {
-        for row in 0..m {
-            let input_row_start = row * k;
-            let output_row_start = row * n;
-            for col in 0..n {
-                let mut acc = 0.0f32;
-                for kk in 0..k {
-                    let a = input_data[input_row_start + kk];
-                    let w = weight_data[kk + col * k];
-                    acc += a * w;
-                }
-                output_data[output_row_start + col] = acc;
-            }
-        }
+        output_data.chunks_mut(n).enumerate().for_each(row_kernel);
     }
 
     Ok(())
@@ -121,12 +230,18 @@ fn matmul_f32_f32(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
 /// F32 × Q4K matrix multiplication with fused dequantization
 ///
 /// Scalar implementation with on-the-fly dequantization:
-/// - Dequantize: weight = (quantized * scale) + min
-/// - Scales/mins are per block of 32 weights
+/// - Dequantize: weight = (quantized * scale) - min
+/// - Scales/mins are per sub-block of 32 weights
 /// - Q4K: quantized values are in range 0-15
 ///
 /// This avoids writing dequantized weights to memory, improving cache locality
-fn matmul_f32_q4k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Result<(), EngineError> {
+fn matmul_f32_q4k(
+    input: &Tensor,
+    weight: &Tensor,
+    residual: Option<&[f32]>,
+    output: &mut Tensor,
+    path: KernelPath,
+) -> Result<()> {
     if input.dimensions().len() != 2
         || weight.dimensions().len() != 2
         || output.dimensions().len() != 2
@@ -172,38 +287,17 @@ fn matmul_f32_q4k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
         ));
     }
 
-    let ops = m.saturating_mul(n).saturating_mul(k);
-    let row_kernel = |(row, out_row): (usize, &mut [f32])| -> Result<(), EngineError> {
-        let input_row_start = row * k;
-        let mut decoded_block = [0.0f32; BLOCK_ELEMENTS];
-        let mut current_block_idx = usize::MAX;
-        for (col, out_cell) in out_row.iter_mut().enumerate() {
-            let mut acc = 0.0f32;
-            for kk in 0..k {
-                let a = input_data[input_row_start + kk];
-                if a == 0.0 {
-                    continue;
-                }
-                let weight_idx = kk + col * k;
-                let block_idx = weight_idx / BLOCK_ELEMENTS;
-                if block_idx != current_block_idx {
-                    let block_start = block_idx * Q4K_BLOCK_SIZE;
-                    let block_end = block_start + Q4K_BLOCK_SIZE;
-                    let block = weight_bytes
-                        .get(block_start..block_end)
-                        .ok_or_else(|| EngineError::MatMul("Q4K block out of bounds".into()))?;
-                    dequantize_q4k_block(block, &mut decoded_block)?;
-                    current_block_idx = block_idx;
-                }
-                let w = decoded_block[weight_idx % BLOCK_ELEMENTS];
-                acc += a * w;
-            }
-            *out_cell = acc;
-        }
-        Ok(())
+    let row_kernel = |(row, out_row): (usize, &mut [f32])| {
+        row_kernel_for(weight)(
+            &input_data[row * k..(row + 1) * k],
+            weight_bytes,
+            &math::matmul::Q4K,
+            residual_row(residual, row, n),
+            out_row,
+        )
     };
 
-    if ops >= PARALLEL_MATMUL_MIN_OPS {
+    if path == KernelPath::ScalarParallel {
         output_data
             .par_chunks_mut(n)
             .enumerate()
@@ -222,8 +316,10 @@ fn matmul_f32_q4k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
 fn matmul_f32_q8_0(
     input: &Tensor,
     weight: &Tensor,
+    residual: Option<&[f32]>,
     output: &mut Tensor,
-) -> Result<(), EngineError> {
+    path: KernelPath,
+) -> Result<()> {
     if input.dimensions().len() != 2
         || weight.dimensions().len() != 2
         || output.dimensions().len() != 2
@@ -269,38 +365,17 @@ fn matmul_f32_q8_0(
         ));
     }
 
-    let ops = m.saturating_mul(n).saturating_mul(k);
-    let row_kernel = |(row, out_row): (usize, &mut [f32])| -> Result<(), EngineError> {
-        let input_row_start = row * k;
-        let mut decoded_block = [0.0f32; Q8_0_BLOCK_ELEMENTS];
-        let mut current_block_idx = usize::MAX;
-        for (col, out_cell) in out_row.iter_mut().enumerate() {
-            let mut acc = 0.0f32;
-            for kk in 0..k {
-                let a = input_data[input_row_start + kk];
-                if a == 0.0 {
-                    continue;
-                }
-                let weight_idx = kk + col * k;
-                let block_idx = weight_idx / Q8_0_BLOCK_ELEMENTS;
-                if block_idx != current_block_idx {
-                    let block_start = block_idx * Q8_0_BLOCK_SIZE;
-                    let block_end = block_start + Q8_0_BLOCK_SIZE;
-                    let block = weight_bytes
-                        .get(block_start..block_end)
-                        .ok_or_else(|| EngineError::MatMul("Q8_0 block out of bounds".into()))?;
-                    dequantize_q8_0_block(block, &mut decoded_block)?;
-                    current_block_idx = block_idx;
-                }
-                let w = decoded_block[weight_idx % Q8_0_BLOCK_ELEMENTS];
-                acc += a * w;
-            }
-            *out_cell = acc;
-        }
-        Ok(())
+    let row_kernel = |(row, out_row): (usize, &mut [f32])| {
+        row_kernel_for(weight)(
+            &input_data[row * k..(row + 1) * k],
+            weight_bytes,
+            &math::matmul::Q8_0,
+            residual_row(residual, row, n),
+            out_row,
+        )
     };
 
-    if ops >= PARALLEL_MATMUL_MIN_OPS {
+    if path == KernelPath::ScalarParallel {
         output_data
             .par_chunks_mut(n)
             .enumerate()
@@ -316,13 +391,19 @@ fn matmul_f32_q8_0(
 }
 
 /// F32 × Q6K matrix multiplication with fused dequantization
-/// Similar to Q4K but handles 6-bit quantization (values 0-63)
+/// Similar to Q4K but handles 6-bit quantization (values 0-63) and has no mins
 ///
 /// Scalar implementation with on-the-fly dequantization:
-/// - Dequantize: weight = (quantized * scale) + min
-/// - Scales/mins are per block of 32 weights
+/// - Dequantize: weight = (quantized - 32) * scale
+/// - Scales (int8) are per sub-block of 16 weights
 /// - Q6K: quantized values are in range 0-63
-fn matmul_f32_q6k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Result<(), EngineError> {
+fn matmul_f32_q6k(
+    input: &Tensor,
+    weight: &Tensor,
+    residual: Option<&[f32]>,
+    output: &mut Tensor,
+    path: KernelPath,
+) -> Result<()> {
     if input.dimensions().len() != 2
         || weight.dimensions().len() != 2
         || output.dimensions().len() != 2
@@ -368,38 +449,17 @@ fn matmul_f32_q6k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
         ));
     }
 
-    let ops = m.saturating_mul(n).saturating_mul(k);
-    let row_kernel = |(row, out_row): (usize, &mut [f32])| -> Result<(), EngineError> {
-        let input_row_start = row * k;
-        let mut decoded_block = [0.0f32; BLOCK_ELEMENTS];
-        let mut current_block_idx = usize::MAX;
-        for (col, out_cell) in out_row.iter_mut().enumerate() {
-            let mut acc = 0.0f32;
-            for kk in 0..k {
-                let a = input_data[input_row_start + kk];
-                if a == 0.0 {
-                    continue;
-                }
-                let weight_idx = kk + col * k;
-                let block_idx = weight_idx / BLOCK_ELEMENTS;
-                if block_idx != current_block_idx {
-                    let block_start = block_idx * Q6K_BLOCK_SIZE;
-                    let block_end = block_start + Q6K_BLOCK_SIZE;
-                    let block = weight_bytes
-                        .get(block_start..block_end)
-                        .ok_or_else(|| EngineError::MatMul("Q6K block out of bounds".into()))?;
-                    dequantize_q6k_block(block, &mut decoded_block)?;
-                    current_block_idx = block_idx;
-                }
-                let w = decoded_block[weight_idx % BLOCK_ELEMENTS];
-                acc += a * w;
-            }
-            *out_cell = acc;
-        }
-        Ok(())
+    let row_kernel = |(row, out_row): (usize, &mut [f32])| {
+        row_kernel_for(weight)(
+            &input_data[row * k..(row + 1) * k],
+            weight_bytes,
+            &math::matmul::Q6K,
+            residual_row(residual, row, n),
+            out_row,
+        )
     };
 
-    if ops >= PARALLEL_MATMUL_MIN_OPS {
+    if path == KernelPath::ScalarParallel {
         output_data
             .par_chunks_mut(n)
             .enumerate()
@@ -418,6 +478,7 @@ fn matmul_f32_q6k(input: &Tensor, weight: &Tensor, output: &mut Tensor) -> Resul
 mod tests {
     use super::*;
     use crate::core::tensor::{Tensor, TensorType};
+    use crate::test_utils::{assert_slices_close, compare_slices};
     use std::sync::Arc;
 
     fn f32_bytes(data: &[f32]) -> Vec<u8> {
@@ -428,7 +489,7 @@ mod tests {
         bytes
     }
 
-    fn create_f32_tensor(data: Vec<f32>, dimensions: Vec<usize>) -> Tensor {
+    pub(super) fn create_f32_tensor(data: Vec<f32>, dimensions: Vec<usize>) -> Tensor {
         Tensor::new(TensorType::F32, Arc::new(f32_bytes(&data)), dimensions)
     }
 
@@ -458,8 +519,7 @@ mod tests {
         let mut output = create_zero_f32_tensor(vec![1, 2]);
         matmul(&input, &weight, &mut output).unwrap();
         let out = output.as_f32_slice().unwrap();
-        assert!((out[0] - 5.0).abs() < 1e-5);
-        assert!((out[1] - 11.0).abs() < 1e-5);
+        assert_slices_close(out, &[5.0, 11.0], 1e-5);
     }
 
     #[test]
@@ -469,9 +529,7 @@ mod tests {
         let input = create_f32_tensor(vec![1.0, 2.0], vec![1, 2]);
         let mut output = create_zero_f32_tensor(vec![1, 2]);
         matmul(&input, &weight, &mut output).unwrap();
-        let out = output.as_f32_slice().unwrap();
-        assert!((out[0] - 0.0).abs() < 1e-5);
-        assert!((out[1] - 0.0).abs() < 1e-5);
+        assert_slices_close(output.as_f32_slice().unwrap(), &[0.0, 0.0], 1e-5);
     }
 
     #[test]
@@ -481,8 +539,7 @@ mod tests {
         let input = create_f32_tensor(vec![2.0], vec![1, 1]);
         let mut output = create_zero_f32_tensor(vec![1, 1]);
         matmul(&input, &weight, &mut output).unwrap();
-        let out = output.as_f32_slice().unwrap();
-        assert!((out[0] - 0.0).abs() < 1e-5);
+        assert_slices_close(output.as_f32_slice().unwrap(), &[0.0], 1e-5);
     }
 
     #[test]
@@ -492,7 +549,650 @@ mod tests {
         let input = create_f32_tensor(vec![2.0], vec![1, 1]);
         let mut output = create_zero_f32_tensor(vec![1, 1]);
         matmul(&input, &weight, &mut output).unwrap();
-        let out = output.as_f32_slice().unwrap();
-        assert!((out[0] - 0.0).abs() < 1e-5);
+        assert_slices_close(output.as_f32_slice().unwrap(), &[0.0], 1e-5);
+    }
+
+    /// A K-quant block with scale `d = 1` (f16 at `d_at`) and pseudo-random quants / sub-scales.
+    fn k_quant_block(size: usize, d_at: usize, seed: usize) -> Vec<u8> {
+        let mut block: Vec<u8> = (0..size)
+            .map(|i| ((i * 31 + seed * 17 + 7) % 251) as u8)
+            .collect();
+        block[d_at..d_at + 2].copy_from_slice(&[0x00, 0x3c]);
+        if d_at == 0 {
+            // Q4_K: zero `dmin` so every block is a plain scaled sum.
+            block[2..4].copy_from_slice(&[0, 0]);
+        }
+        block
+    }
+
+    /// Replacing the blocks of every other column leaves column `col`'s output bit-identical, so
+    /// each column's weights are one contiguous run of blocks.
+    #[test]
+    fn each_output_column_reads_only_its_own_blocks() {
+        const K: usize = 2 * BLOCK_ELEMENTS;
+        const N: usize = 3;
+        let blocks_per_col = K / BLOCK_ELEMENTS;
+        let input: Vec<f32> = (0..K).map(|i| ((i % 13) as f32 - 6.0) * 0.1).collect();
+        for (dtype, size, d_at) in [
+            (TensorType::Q4K, Q4K_BLOCK_SIZE, 0),
+            (TensorType::Q6K, Q6K_BLOCK_SIZE, Q6K_BLOCK_SIZE - 2),
+        ] {
+            let run = |seeds: &[usize]| {
+                let bytes: Vec<u8> = seeds
+                    .iter()
+                    .flat_map(|&s| k_quant_block(size, d_at, s))
+                    .collect();
+                let weight = Tensor::new(dtype, Arc::new(bytes), vec![K, N]);
+                let input = create_f32_tensor(input.clone(), vec![1, K]);
+                let mut output = create_zero_f32_tensor(vec![1, N]);
+                matmul(&input, &weight, &mut output).unwrap();
+                output.as_f32_slice().unwrap().to_vec()
+            };
+            let base = run(&(0..N * blocks_per_col).collect::<Vec<_>>());
+            let col = 1;
+            let others: Vec<usize> = (0..N * blocks_per_col)
+                .map(|b| {
+                    if b / blocks_per_col == col {
+                        b
+                    } else {
+                        b + 100
+                    }
+                })
+                .collect();
+            let changed = run(&others);
+            assert_eq!(base[col].to_bits(), changed[col].to_bits(), "{dtype:?}");
+            assert_ne!(base[0], changed[0], "{dtype:?}");
+            assert_ne!(base[2], changed[2], "{dtype:?}");
+        }
+    }
+
+    /// A weight written as `[out_features, in_features]` row-major (one contiguous row per
+    /// output) is exactly the ggml `[K, N]` buffer: no repacking is needed for the F32 kernel's
+    /// inner loop to read contiguous weights.
+    #[test]
+    fn f32_weight_is_already_one_contiguous_row_per_output() {
+        const K: usize = 5;
+        const N: usize = 3;
+        let rows: Vec<Vec<f32>> = (0..N)
+            .map(|col| (0..K).map(|kk| (col * 10 + kk) as f32 * 0.25).collect())
+            .collect();
+        let input_values: Vec<f32> = (0..2 * K).map(|i| i as f32 - 4.0).collect();
+        let weight = create_f32_tensor(rows.concat(), vec![K, N]);
+        let input = create_f32_tensor(input_values.clone(), vec![2, K]);
+        let mut output = create_zero_f32_tensor(vec![2, N]);
+        matmul(&input, &weight, &mut output).unwrap();
+
+        for (row, x) in input_values.chunks(K).enumerate() {
+            for (col, w) in rows.iter().enumerate() {
+                let expected: f32 = x.iter().zip(w).map(|(a, b)| a * b).sum();
+                assert_eq!(output.as_f32_slice().unwrap()[row * N + col], expected);
+            }
+        }
+    }
+
+    /// An input-major weight repacked once multiplies exactly like the strided walk over the
+    /// original buffer.
+    #[test]
+    fn repacked_input_major_weight_matches_the_strided_product() {
+        const K: usize = 7;
+        const N: usize = 5;
+        let input_major: Vec<f32> = (0..K * N).map(|i| (i as f32 - 17.0) * 0.125).collect();
+        let weight = repack_input_major(&input_major, K, N).unwrap();
+        assert_eq!(weight.dimensions(), &[K, N]);
+        for m in [1, 3] {
+            let values: Vec<f32> = (0..m * K).map(|i| (i % 4) as f32 - 1.5).collect();
+            let input = create_f32_tensor(values.clone(), vec![m, K]);
+            let mut output = create_zero_f32_tensor(vec![m, N]);
+            matmul(&input, &weight, &mut output).unwrap();
+            let strided: Vec<f32> = values
+                .chunks(K)
+                .flat_map(|x| {
+                    (0..N).map(|col| {
+                        let mut acc = 0.0f32;
+                        for (kk, a) in x.iter().enumerate() {
+                            acc += a * input_major[kk * N + col];
+                        }
+                        acc
+                    })
+                })
+                .collect();
+            assert_eq!(output.as_f32_slice().unwrap(), &strided[..], "m={m}");
+        }
+        assert!(repack_input_major(&input_major, K, N + 1).is_err());
+    }
+
+    /// `matmul_add` against `matmul` followed by `residual_add`, for every weight dtype, on a
+    /// single row (decode) and on enough rows to take the parallel path (prefill).
+    #[test]
+    fn matmul_add_matches_matmul_then_residual_add() {
+        use crate::ops::residual_add::residual_add;
+
+        const K: usize = 2 * BLOCK_ELEMENTS;
+        const N: usize = 64;
+        let blocks = |size: usize, d_at: usize, elements: usize| -> Vec<u8> {
+            (0..K * N / elements)
+                .flat_map(|b| k_quant_block(size, d_at, b))
+                .collect()
+        };
+        let mut q8_0 = blocks(Q8_0_BLOCK_SIZE, 0, Q8_0_BLOCK_ELEMENTS);
+        for block in q8_0.chunks_exact_mut(Q8_0_BLOCK_SIZE) {
+            block[..2].copy_from_slice(&[0x00, 0x20]);
+        }
+        let f32_weights: Vec<f32> = (0..K * N)
+            .map(|i| ((i * 7 % 29) as f32 - 14.0) * 0.01)
+            .collect();
+        let weights = [
+            create_f32_tensor(f32_weights, vec![K, N]),
+            create_q4k_tensor(blocks(Q4K_BLOCK_SIZE, 0, BLOCK_ELEMENTS), vec![K, N]),
+            create_q6k_tensor(
+                blocks(Q6K_BLOCK_SIZE, Q6K_BLOCK_SIZE - 2, BLOCK_ELEMENTS),
+                vec![K, N],
+            ),
+            create_q8_0_tensor(q8_0, vec![K, N]),
+        ];
+
+        for m in [1, 3] {
+            let values: Vec<f32> = (0..m * K).map(|i| ((i % 13) as f32 - 6.0) * 0.1).collect();
+            let input = create_f32_tensor(values, vec![m, K]);
+            let residual: Vec<f32> = (0..m * N).map(|i| (i as f32 - 40.0) * 0.37).collect();
+            for weight in &weights {
+                let mut projected = create_zero_f32_tensor(vec![m, N]);
+                matmul(&input, weight, &mut projected).unwrap();
+                let mut unfused = vec![0.0f32; m * N];
+                residual_add(&residual, projected.as_f32_slice().unwrap(), &mut unfused).unwrap();
+
+                let mut fused = create_zero_f32_tensor(vec![m, N]);
+                matmul_add(&input, weight, &residual, &mut fused).unwrap();
+                if let Err(msg) = compare_slices(fused.as_f32_slice().unwrap(), &unfused, 1e-5) {
+                    panic!("{:?} m={m}: {msg}", weight.dtype());
+                }
+            }
+        }
+
+        let input = create_f32_tensor(vec![0.0; K], vec![1, K]);
+        let mut output = create_zero_f32_tensor(vec![1, N]);
+        let err = matmul_add(&input, &weights[0], &[0.0; N - 1], &mut output).unwrap_err();
+        assert!(matches!(err, EngineError::MatMul(_)), "{err}");
+    }
+
+    /// Column-tiled Q4_K / Q6_K / Q8_0 weights give bit-identical results to the ggml order, on
+    /// the sequential and parallel paths, with and without a residual, and with inputs whose
+    /// all-zero segments skip whole blocks.
+    #[test]
+    fn column_tiled_weights_match_the_ggml_layout() {
+        const K: usize = 2 * BLOCK_ELEMENTS;
+        const N: usize = 64;
+        let blocks = |size: usize, d_at: usize, elements: usize| -> Vec<u8> {
+            (0..K * N / elements)
+                .flat_map(|b| k_quant_block(size, d_at, b))
+                .collect()
+        };
+        let mut q8_0 = blocks(Q8_0_BLOCK_SIZE, 0, Q8_0_BLOCK_ELEMENTS);
+        for block in q8_0.chunks_exact_mut(Q8_0_BLOCK_SIZE) {
+            block[..2].copy_from_slice(&[0x00, 0x20]);
+        }
+        let weights = [
+            create_q4k_tensor(blocks(Q4K_BLOCK_SIZE, 0, BLOCK_ELEMENTS), vec![K, N]),
+            create_q6k_tensor(
+                blocks(Q6K_BLOCK_SIZE, Q6K_BLOCK_SIZE - 2, BLOCK_ELEMENTS),
+                vec![K, N],
+            ),
+            create_q8_0_tensor(q8_0, vec![K, N]),
+        ];
+
+        for m in [1, 3] {
+            // Zeros over the second 32-weight segment and a scattering of single zeros.
+            let values: Vec<f32> = (0..m * K)
+                .map(|i| match i % K {
+                    32..64 => 0.0,
+                    kk if kk % 7 == 0 => 0.0,
+                    kk => ((kk % 13) as f32 - 6.0) * 0.1 + i as f32 * 1e-3,
+                })
+                .collect();
+            let input = create_f32_tensor(values, vec![m, K]);
+            let residual: Vec<f32> = (0..m * N).map(|i| (i as f32 - 40.0) * 0.37).collect();
+            for weight in &weights {
+                let tiled = weight.with_layout(WeightLayout::ColumnTiles).unwrap();
+                assert_eq!(tiled.layout(), WeightLayout::ColumnTiles);
+                for residual in [None, Some(&residual[..])] {
+                    let run = |w: &Tensor| {
+                        let mut out = create_zero_f32_tensor(vec![m, N]);
+                        match residual {
+                            Some(r) => matmul_add(&input, w, r, &mut out).unwrap(),
+                            None => matmul(&input, w, &mut out).unwrap(),
+                        }
+                        let bits: Vec<u32> = out
+                            .as_f32_slice()
+                            .unwrap()
+                            .iter()
+                            .map(|x| x.to_bits())
+                            .collect();
+                        bits
+                    };
+                    assert_eq!(
+                        run(weight),
+                        run(&tiled),
+                        "{:?} m={m} residual={}",
+                        weight.dtype(),
+                        residual.is_some()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matmul_inner_dim_mismatch_names_both_weight_dims() {
+        let input = create_f32_tensor(vec![1.0, 2.0, 3.0], vec![1, 3]);
+        let weight = create_zero_f32_tensor(vec![2, 3]);
+        let mut output = create_zero_f32_tensor(vec![1, 2]);
+        let err = matmul(&input, &weight, &mut output)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("input row length 3"), "{err}");
+        assert!(err.contains("weight dims [2, 3]"), "{err}");
+        assert!(err.contains("transposed"), "{err}");
+    }
+
+    #[test]
+    fn test_matmul_rank_mismatch_is_matmul_variant() {
+        let input = create_f32_tensor(vec![1.0, 2.0], vec![2]);
+        let weight = create_zero_f32_tensor(vec![2, 1]);
+        let mut output = create_zero_f32_tensor(vec![1, 1]);
+        match matmul(&input, &weight, &mut output) {
+            Err(EngineError::MatMul(msg)) => assert!(msg.contains("2D"), "{msg}"),
+            other => panic!("expected EngineError::MatMul, got {other:?}"),
+        }
+    }
+}
+
+/// Randomized checks of every registered kernel variant (weight type × [`KernelPath`], with and
+/// without a fused residual) against a reference that dequantizes the whole weight and runs a
+/// naive `f64` triple loop. Shapes deliberately include `K`, `N` and `K * N` that are not
+/// multiples of the 32- or 256-weight block, so columns straddle block boundaries and the last
+/// block is partial.
+///
+/// Each variant runs [`DEFAULT_CASES`] cases; set `MATMUL_PROP_CASES` to run more and
+/// `MATMUL_PROP_SEED` to explore from a different base seed. A failure is shrunk to a smaller
+/// shape that still fails and printed as a [`Case`] literal that replays it exactly.
+#[cfg(test)]
+mod property_tests {
+    use super::tests::create_f32_tensor;
+    use super::*;
+    use crate::ops::kernel_stats::WEIGHT_TYPES;
+    use crate::ops::quant::utils::f32_to_f16;
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    const DEFAULT_CASES: u64 = 40;
+
+    /// The signature of [`run_kernel`], so the harness can be pointed at a deliberately broken one.
+    type Kernel = fn(&Tensor, &Tensor, Option<&[f32]>, &mut Tensor, KernelPath) -> Result<()>;
+
+    /// xorshift64*: small, seedable, and good enough for test payloads.
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next_u64() % n as u64) as usize
+        }
+
+        /// Uniform in `[-scale, scale)`.
+        fn symmetric(&mut self, scale: f32) -> f32 {
+            ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0) * scale
+        }
+
+        fn bytes(&mut self, out: &mut [u8]) {
+            out.iter_mut().for_each(|b| *b = self.next_u64() as u8);
+        }
+    }
+
+    /// One generated product; the payload is a pure function of these fields.
+    #[derive(Debug, Clone, Copy)]
+    #[allow(dead_code)] // Read through `Debug` in the replay literal.
+    struct Case {
+        dtype: TensorType,
+        path: KernelPath,
+        m: usize,
+        k: usize,
+        n: usize,
+        fused: bool,
+        seed: u64,
+    }
+
+    /// `(weights per block, bytes per block)`, or `None` for F32.
+    fn block_layout(dtype: TensorType) -> Option<(usize, usize)> {
+        match dtype {
+            TensorType::F32 => None,
+            TensorType::Q8_0 => Some((Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE)),
+            TensorType::Q4K => Some((BLOCK_ELEMENTS, Q4K_BLOCK_SIZE)),
+            TensorType::Q6K => Some((BLOCK_ELEMENTS, Q6K_BLOCK_SIZE)),
+        }
+    }
+
+    /// A random block with every field in its valid range: arbitrary quant and sub-scale bytes,
+    /// finite f16 super-block scales of a realistic magnitude.
+    fn random_block(dtype: TensorType, rng: &mut Rng, out: &mut [u8]) {
+        rng.bytes(out);
+        let mut f16 = |at: usize, scale: f32| {
+            let bits = f32_to_f16(rng.symmetric(scale));
+            out[at..at + 2].copy_from_slice(&bits.to_le_bytes());
+        };
+        match dtype {
+            TensorType::Q8_0 => f16(0, 0.02),
+            TensorType::Q4K => {
+                f16(0, 0.01);
+                f16(2, 0.01);
+            }
+            TensorType::Q6K => f16(Q6K_BLOCK_SIZE - 2, 0.01),
+            TensorType::F32 => unreachable!("F32 has no blocks"),
+        }
+    }
+
+    impl Case {
+        fn generate(dtype: TensorType, path: KernelPath, seed: u64) -> Self {
+            let mut rng = Rng::new(seed);
+            // Block-aligned, block-multiple and arbitrary `K` in roughly equal parts.
+            let k = match rng.below(3) {
+                0 => 32 * (1 + rng.below(24)),
+                1 => 256 * (1 + rng.below(3)),
+                _ => 1 + rng.below(700),
+            };
+            Self {
+                dtype,
+                path,
+                m: 1 + rng.below(5),
+                k,
+                n: 1 + rng.below(40),
+                fused: rng.below(2) == 0,
+                seed,
+            }
+        }
+
+        /// `(input [m, k], weight [k, n], residual [m * n])`.
+        fn payload(&self) -> (Vec<f32>, Tensor, Vec<f32>) {
+            let mut rng = Rng::new(self.seed ^ 0x5eed);
+            // Some exact zeros: the kernels skip them.
+            let input = (0..self.m * self.k)
+                .map(|_| {
+                    if rng.below(8) == 0 {
+                        0.0
+                    } else {
+                        rng.symmetric(2.0)
+                    }
+                })
+                .collect();
+            let weights = self.k * self.n;
+            let buffer = match block_layout(self.dtype) {
+                None => (0..weights)
+                    .flat_map(|_| rng.symmetric(1.0).to_le_bytes())
+                    .collect(),
+                Some((elements, bytes)) => {
+                    let mut buffer = vec![0u8; weights.div_ceil(elements) * bytes];
+                    for block in buffer.chunks_exact_mut(bytes) {
+                        random_block(self.dtype, &mut rng, block);
+                    }
+                    buffer
+                }
+            };
+            let weight = Tensor::new(self.dtype, Arc::new(buffer), vec![self.k, self.n]);
+            let residual = (0..self.m * self.n).map(|_| rng.symmetric(4.0)).collect();
+            (input, weight, residual)
+        }
+
+        fn run(
+            &self,
+            kernel: Kernel,
+            input: &[f32],
+            weight: &Tensor,
+            residual: &[f32],
+        ) -> Vec<f32> {
+            let a = create_f32_tensor(input.to_vec(), vec![self.m, self.k]);
+            let mut output = create_f32_tensor(vec![0.0; self.m * self.n], vec![self.m, self.n]);
+            let residual = self.fused.then_some(residual);
+            kernel(&a, weight, residual, &mut output, self.path).unwrap_or_else(|e| {
+                panic!("{self:?}: kernel failed: {e}");
+            });
+            output.as_f32_slice().unwrap().to_vec()
+        }
+
+        /// Smaller shapes with the same seed, for shrinking.
+        fn shrinks(&self) -> Vec<Self> {
+            let mut out = Vec::new();
+            for m in [1, self.m / 2] {
+                if m >= 1 && m < self.m {
+                    out.push(Self { m, ..*self });
+                }
+            }
+            for n in [1, self.n / 2] {
+                if n >= 1 && n < self.n {
+                    out.push(Self { n, ..*self });
+                }
+            }
+            for k in [self.k / 2, self.k - 1] {
+                if k >= 1 && k < self.k {
+                    out.push(Self { k, ..*self });
+                }
+            }
+            if self.fused {
+                out.push(Self {
+                    fused: false,
+                    ..*self
+                });
+            }
+            out
+        }
+    }
+
+    /// Reference value of output `(row, col)` over input columns `kk_range`, in f64, with the
+    /// tolerance an f32 accumulation of that many products may need.
+    fn reference_cell(
+        case: &Case,
+        input: &[f32],
+        weights: &[f32],
+        residual: &[f32],
+        (row, col): (usize, usize),
+        kk_range: Range<usize>,
+    ) -> (f32, f32) {
+        let (mut acc, mut magnitude) = (0.0f64, 0.0f64);
+        for kk in kk_range.clone() {
+            let product = input[row * case.k + kk] as f64 * weights[kk + col * case.k] as f64;
+            acc += product;
+            magnitude += product.abs();
+        }
+        if case.fused {
+            let r = residual[row * case.n + col] as f64;
+            acc += r;
+            magnitude += r.abs();
+        }
+        // Recursive summation of `len` terms is off by at most ~len · eps · Σ|terms|.
+        let terms = kk_range.len() + 2;
+        let tol = terms as f64 * f32::EPSILON as f64 * magnitude + f32::MIN_POSITIVE as f64;
+        (acc as f32, tol as f32)
+    }
+
+    /// `false` for NaN as well as for a real difference.
+    fn within(got: f32, want: f32, tol: f32) -> bool {
+        (got - want).abs() <= tol
+    }
+
+    struct Mismatch {
+        row: usize,
+        col: usize,
+        got: f32,
+        want: f32,
+        tol: f32,
+    }
+
+    fn check(case: &Case, kernel: Kernel) -> Result<(), Mismatch> {
+        let (input, weight, residual) = case.payload();
+        let got = case.run(kernel, &input, &weight, &residual);
+        let weights = weight.dequantize_to_f32().unwrap();
+        for row in 0..case.m {
+            for col in 0..case.n {
+                let (want, tol) =
+                    reference_cell(case, &input, &weights, &residual, (row, col), 0..case.k);
+                let got = got[row * case.n + col];
+                if !within(got, want, tol) {
+                    return Err(Mismatch {
+                        row,
+                        col,
+                        got,
+                        want,
+                        tol,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first block of column `col` whose contribution alone (input zeroed outside it)
+    /// disagrees with the reference: `(block index, its kk range)`.
+    fn contributing_block(
+        case: &Case,
+        kernel: Kernel,
+        (row, col): (usize, usize),
+    ) -> Option<(usize, Range<usize>)> {
+        let (elements, _) = block_layout(case.dtype).unwrap_or((BLOCK_ELEMENTS, 0));
+        let (input, weight, residual) = case.payload();
+        let weights = weight.dequantize_to_f32().unwrap();
+        let unfused = Case {
+            fused: false,
+            ..*case
+        };
+        let (first, last) = (
+            col * case.k / elements,
+            (col * case.k + case.k - 1) / elements,
+        );
+        (first..=last).find_map(|block| {
+            let start = (block * elements).max(col * case.k) - col * case.k;
+            let end = ((block + 1) * elements).min((col + 1) * case.k) - col * case.k;
+            let mut masked = vec![0.0f32; input.len()];
+            let row_input = row * case.k;
+            masked[row_input + start..row_input + end]
+                .copy_from_slice(&input[row_input + start..row_input + end]);
+            let got = unfused.run(kernel, &masked, &weight, &residual)[row * case.n + col];
+            let (want, tol) = reference_cell(
+                &unfused,
+                &masked,
+                &weights,
+                &residual,
+                (row, col),
+                start..end,
+            );
+            (!within(got, want, tol)).then_some((block, start..end))
+        })
+    }
+
+    /// Shrink a failing case to one none of whose [`Case::shrinks`] still fails.
+    fn minimize(mut case: Case, kernel: Kernel) -> Case {
+        while let Some(smaller) = case
+            .shrinks()
+            .into_iter()
+            .find(|c| check(c, kernel).is_err())
+        {
+            case = smaller;
+        }
+        case
+    }
+
+    fn report(case: Case, kernel: Kernel) -> String {
+        let case = minimize(case, kernel);
+        let Err(m) = check(&case, kernel) else {
+            unreachable!("minimize keeps a failing case");
+        };
+        let block = match contributing_block(&case, kernel, (m.row, m.col)) {
+            Some((b, kk)) => format!("block {b} (kk {kk:?} of column {})", m.col),
+            None => "no single block; the error only shows in the full sum".to_string(),
+        };
+        format!(
+            "kernel disagrees with the reference\n  minimized: {case:?}\n  first mismatch: \
+             output [{}, {}] = {} vs reference {} (|diff| {} > tol {})\n  contributing: {block}",
+            m.row,
+            m.col,
+            m.got,
+            m.want,
+            (m.got - m.want).abs(),
+            m.tol
+        )
+    }
+
+    fn env_u64(key: &str) -> Option<u64> {
+        let value = std::env::var(key).ok()?;
+        let value = value.trim();
+        match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    }
+
+    #[test]
+    fn every_kernel_variant_matches_the_dequantized_reference() {
+        let cases = env_u64("MATMUL_PROP_CASES").unwrap_or(DEFAULT_CASES);
+        let base = env_u64("MATMUL_PROP_SEED").unwrap_or(0x6d61_746d_756c);
+        for dtype in WEIGHT_TYPES {
+            for path in KernelPath::ALL {
+                for i in 0..cases {
+                    let case = Case::generate(dtype, path, base.wrapping_add(i));
+                    if check(&case, run_kernel).is_err() {
+                        panic!("{}", report(case, run_kernel));
+                    }
+                }
+            }
+        }
+    }
+
+    /// [`run_kernel`] with Q8_0 block 3's scale zeroed: stands in for an indexing bug.
+    fn drops_q8_0_block_3(
+        a: &Tensor,
+        b: &Tensor,
+        residual: Option<&[f32]>,
+        output: &mut Tensor,
+        path: KernelPath,
+    ) -> Result<()> {
+        let mut bytes = b.buffer().to_vec();
+        if let Some(scale) = bytes.get_mut(3 * Q8_0_BLOCK_SIZE..3 * Q8_0_BLOCK_SIZE + 2) {
+            scale.copy_from_slice(&[0, 0]);
+        }
+        let b = Tensor::new(b.dtype(), Arc::new(bytes), b.dimensions().to_vec());
+        run_kernel(a, &b, residual, output, path)
+    }
+
+    /// The harness itself: a broken kernel is caught, shrunk, and blamed on the right block.
+    #[test]
+    fn a_broken_block_is_minimized_and_located() {
+        let case = Case {
+            dtype: TensorType::Q8_0,
+            path: KernelPath::Scalar,
+            m: 3,
+            k: 100,
+            n: 5,
+            fused: true,
+            seed: 7,
+        };
+        assert!(check(&case, run_kernel).is_ok());
+        assert!(check(&case, drops_q8_0_block_3).is_err());
+
+        let minimized = minimize(case, drops_q8_0_block_3);
+        assert_eq!((minimized.m, minimized.fused), (1, false), "{minimized:?}");
+        assert!(
+            minimized.k * minimized.n > 3 * Q8_0_BLOCK_ELEMENTS,
+            "{minimized:?}"
+        );
+        let message = report(case, drops_q8_0_block_3);
+        assert!(message.contains("contributing: block 3 "), "{message}");
+        assert!(message.contains("seed: 7"), "{message}");
     }
 }
@@ -1,3 +1,6 @@
+// Pure kernel math, `core` + `alloc` only (see `no-std-core`); the modules below wrap it
+pub mod math;
+
 // Core compute kernels (performance-critical, may need SIMD)
 pub mod matmul;
 
@@ -11,7 +14,11 @@ pub mod rmsnorm;
 
 // Utility functions
 pub mod cpu_features;
+pub mod kernel_stats;
 pub mod residual_add;
+pub mod self_test;
+pub mod similarity;
+pub use math::specialized;
 
 // Model specific functions
 pub mod rope;
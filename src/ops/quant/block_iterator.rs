@@ -1,78 +1,282 @@
-// Block iterator for quantized tensors stored in blocks
-// Functional for Q4_K and Q6_K quantization types
+//! One view of every quantized block format: a block is a run of sub-blocks, each with an
+//! effective `scale` and `min`, over signed integer codes `q`, and every weight is
+//! `w = scale · q − min`. Formats differ only in how those are stored:
+//!
+//! | type | sub-blocks | `q` | `scale` | `min` |
+//! |------|------------|-----|---------|-------|
+//! | Q4_K | 8 × 32 | 4-bit, `0..=15` | `d · sc` (6-bit `sc`) | `dmin · m` (6-bit `m`) |
+//! | Q6_K | 16 × 16 | 6-bit code − 32, `-32..=31` | `d · sc` (int8 `sc`) | 0 |
+//! | Q8_0 | 1 × 32 | int8 | `d` | 0 |
+//!
+//! Only Q4_K stores mins ([`TensorType::has_block_mins`]); the others are symmetric around zero,
+//! Q6_K by subtracting a fixed offset from its unsigned code, Q8_0 by storing two's complement,
+//! and get `min = 0` here. [`BlockRef::dequantize`] is bit-identical to the dedicated decoders in
+//! [`crate::ops::quant::quant_k_handler`], which stay the matmul hot path.
 
-use crate::core::tensor::TensorType;
-use crate::ops::quant::quant_k_handler::{Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, extract_scale_min_k4};
+use crate::EngineError;
+use crate::core::tensor::{Tensor, TensorType};
+use crate::ops::quant::quant_k_handler::{
+    Q4K_BLOCK_SIZE, Q6K_BLOCK_SIZE, Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, extract_scale_min_k4,
+};
 use crate::ops::quant::utils::f16_to_f32;
 
-impl<'a> Iterator for BlockIter<'a> {
-    type Item = BlockRef<'a>;
+const K_BLOCK_ELEMENTS: usize = 256;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.block_index >= self.total_blocks {
-            return None;
-        }
+/// Effective scale and min of one sub-block, in f64 as the decoders compute them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubBlock {
+    pub scale: f64,
+    /// Always 0 for formats without mins.
+    pub min: f64,
+}
 
-        let (block_size, qs_len, qbits) = match self.dtype {
-            TensorType::Q4K => (Q4K_BLOCK_SIZE, 128usize, 4u8),
-            TensorType::Q6K => (Q6K_BLOCK_SIZE, 192usize, 6u8),
-            _ => return None,
-        };
+/// One decoded block header plus its integer codes.
+#[derive(Debug, Clone)]
+pub struct BlockRef {
+    pub block_index: usize,
+    /// Weights per sub-block.
+    pub sub_len: usize,
+    pub sub_blocks: Vec<SubBlock>,
+    /// One code per weight, in storage order.
+    pub q: Vec<i8>,
+}
 
-        let block_start = self.block_index * block_size;
-        let block_end = block_start + block_size;
-        if block_end > self.buffer.len() {
-            return None;
+impl BlockRef {
+    /// `scale · q − min` for every weight into `out` (at least `q.len()` long). A zero code
+    /// gives exactly `−min` even when the scale is not finite, as in the decoders.
+    pub fn dequantize(&self, out: &mut [f32]) {
+        for (i, (o, &q)) in out.iter_mut().zip(&self.q).enumerate() {
+            let sub = self.sub_blocks[i / self.sub_len];
+            let scaled = if q == 0 {
+                0.0
+            } else {
+                sub.scale * f64::from(q)
+            };
+            *o = (scaled - sub.min) as f32;
         }
+    }
+}
 
-        let block = &self.buffer[block_start..block_end];
-
-        let d = f16_to_f32(u16::from_le_bytes([block[0], block[1]]));
-        let dmin = f16_to_f32(u16::from_le_bytes([block[2], block[3]]));
+/// Blocks of a quantized tensor, in storage order.
+#[derive(Debug, Clone)]
+pub struct BlockIter<'a> {
+    buffer: &'a [u8],
+    dtype: TensorType,
+    block_index: usize,
+    total_blocks: usize,
+}
 
-        let scales_bytes = &block[4..16];
-        let mut scales = [0u8; 8];
-        let mut mins = [0u8; 8];
-        for sub in 0..8 {
-            let (s, m) = extract_scale_min_k4(sub, scales_bytes);
-            scales[sub] = s;
-            mins[sub] = m;
+impl<'a> BlockIter<'a> {
+    /// Fails for F32 and for a buffer too short for the tensor's element count.
+    pub fn new(tensor: &'a Tensor) -> Result<Self, EngineError> {
+        let dtype = tensor.dtype();
+        let (elements, bytes) = block_shape(dtype).ok_or_else(|| {
+            EngineError::Tensor(format!("{dtype:?} is not a block-quantized type"))
+        })?;
+        let total_blocks = tensor.element_count().div_ceil(elements);
+        if tensor.buffer().len() < total_blocks * bytes {
+            return Err(EngineError::Tensor(format!(
+                "{dtype:?} buffer has {} bytes, need {} for {total_blocks} blocks",
+                tensor.buffer().len(),
+                total_blocks * bytes
+            )));
         }
+        Ok(Self {
+            buffer: tensor.buffer(),
+            dtype,
+            block_index: 0,
+            total_blocks,
+        })
+    }
+}
 
-        let qs_start = 16;
-        let qs_end = qs_start + qs_len;
-        let qs = &block[qs_start..qs_end];
+/// `(weights, bytes)` per block, or `None` for F32.
+fn block_shape(dtype: TensorType) -> Option<(usize, usize)> {
+    match dtype {
+        TensorType::F32 => None,
+        TensorType::Q4K => Some((K_BLOCK_ELEMENTS, Q4K_BLOCK_SIZE)),
+        TensorType::Q6K => Some((K_BLOCK_ELEMENTS, Q6K_BLOCK_SIZE)),
+        TensorType::Q8_0 => Some((Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE)),
+    }
+}
+
+fn half(block: &[u8], at: usize) -> f64 {
+    f64::from(f16_to_f32(u16::from_le_bytes([block[at], block[at + 1]])))
+}
 
+impl Iterator for BlockIter<'_> {
+    type Item = BlockRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block_index >= self.total_blocks {
+            return None;
+        }
+        let (_, size) = block_shape(self.dtype)?;
+        let block = &self.buffer[self.block_index * size..(self.block_index + 1) * size];
+        let (sub_len, sub_blocks, q) = match self.dtype {
+            TensorType::Q4K => {
+                let (d, dmin) = (half(block, 0), half(block, 2));
+                let sub_blocks = (0..8)
+                    .map(|j| {
+                        let (sc, m) = extract_scale_min_k4(j, &block[4..16]);
+                        SubBlock {
+                            scale: d * f64::from(sc),
+                            min: dmin * f64::from(m),
+                        }
+                    })
+                    .collect();
+                let qs = &block[16..144];
+                // Each 32-byte chunk holds two sub-blocks: low nibbles, then high nibbles.
+                let q = (0..K_BLOCK_ELEMENTS)
+                    .map(|p| {
+                        let byte = qs[p / 64 * 32 + p % 32];
+                        (if p % 64 < 32 { byte & 0xF } else { byte >> 4 }) as i8
+                    })
+                    .collect();
+                (32, sub_blocks, q)
+            }
+            TensorType::Q6K => {
+                let (ql, qh, scales) = (&block[0..128], &block[128..192], &block[192..208]);
+                let d = half(block, 208);
+                let sub_blocks = scales
+                    .iter()
+                    .map(|&sc| SubBlock {
+                        scale: d * f64::from(sc as i8),
+                        min: 0.0,
+                    })
+                    .collect();
+                // Per 128-weight half, four 32-weight rows share `ql` (low/high nibble of two
+                // 32-byte runs) and `qh` (2-bit fields 0..3).
+                let q = (0..K_BLOCK_ELEMENTS)
+                    .map(|p| {
+                        let (h, row, l) = (p / 128, p % 128 / 32, p % 32);
+                        let low = ql[h * 64 + (row % 2) * 32 + l];
+                        let low = if row < 2 { low & 0xF } else { low >> 4 };
+                        let high = (qh[h * 32 + l] >> (2 * row)) & 3;
+                        ((low | high << 4) as i8) - 32
+                    })
+                    .collect();
+                (16, sub_blocks, q)
+            }
+            TensorType::Q8_0 => {
+                let sub_blocks = vec![SubBlock {
+                    scale: half(block, 0),
+                    min: 0.0,
+                }];
+                let q = block[2..Q8_0_BLOCK_SIZE].iter().map(|&b| b as i8).collect();
+                (Q8_0_BLOCK_ELEMENTS, sub_blocks, q)
+            }
+            TensorType::F32 => return None,
+        };
         let out = BlockRef {
             block_index: self.block_index,
-            d,
-            dmin,
-            scales,
-            mins,
-            qs,
-            qbits,
+            sub_len,
+            sub_blocks,
+            q,
         };
-
         self.block_index += 1;
         Some(out)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct BlockIter<'a> {
-    buffer: &'a [u8],
-    dtype: TensorType,
-    block_index: usize,
-    total_blocks: usize,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::quant::quant_k_handler::{
+        dequantize_q4k_block, dequantize_q6k_block, dequantize_q8_0_block,
+    };
+    use crate::ops::quant::utils::f32_to_f16;
+    use std::sync::Arc;
 
-#[derive(Debug, Clone)]
-pub struct BlockRef<'a> {
-    pub block_index: usize,
-    pub d: f32,
-    pub dmin: f32,
-    pub scales: [u8; 8],
-    pub mins: [u8; 8],
-    pub qs: &'a [u8],
-    pub qbits: u8,
+    fn pseudo_random(len: usize, seed: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| ((i * 73 + seed * 29 + 11) % 251) as u8)
+            .collect()
+    }
+
+    fn blocks(dtype: TensorType, bytes: Vec<u8>, elements: usize) -> Vec<BlockRef> {
+        let tensor = Tensor::new(dtype, Arc::new(bytes), vec![elements]);
+        BlockIter::new(&tensor).unwrap().collect()
+    }
+
+    #[test]
+    fn q8_0_dequantizes_without_mins() {
+        // d = 0.5, q = -16..16: w = 0.5 · q exactly.
+        let mut bytes = f32_to_f16(0.5).to_le_bytes().to_vec();
+        bytes.extend((-16i8..16).map(|q| q as u8));
+        let [block] = blocks(TensorType::Q8_0, bytes.clone(), 32)
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            block.sub_blocks,
+            [SubBlock {
+                scale: 0.5,
+                min: 0.0
+            }]
+        );
+        let mut uniform = [0.0f32; 32];
+        block.dequantize(&mut uniform);
+        let expected: Vec<f32> = (-16..16).map(|q| 0.5 * q as f32).collect();
+        assert_eq!(uniform.as_slice(), expected.as_slice());
+
+        let mut decoded = [0.0f32; 32];
+        dequantize_q8_0_block(&bytes, &mut decoded).unwrap();
+        assert_eq!(uniform, decoded);
+    }
+
+    #[test]
+    fn q6k_dequantizes_without_mins() {
+        // Every 6-bit code 32 (low nibble 0, high bits 2) is q = 0; scale 3 makes w = 0 throughout.
+        let mut bytes = vec![0u8; Q6K_BLOCK_SIZE];
+        bytes[128..192].fill(0b1010_1010);
+        bytes[192..208].fill(3);
+        bytes[208..210].copy_from_slice(&f32_to_f16(1.0).to_le_bytes());
+        let [block] = blocks(TensorType::Q6K, bytes, 256).try_into().unwrap();
+        assert!(block.q.iter().all(|&q| q == 0));
+        assert!(
+            block
+                .sub_blocks
+                .iter()
+                .all(|s| s.min == 0.0 && s.scale == 3.0)
+        );
+
+        // Arbitrary payloads: signed codes in -32..=31, no mins, and the Q6_K decoder's values.
+        let mut bytes = pseudo_random(Q6K_BLOCK_SIZE, 1);
+        bytes[208..210].copy_from_slice(&f32_to_f16(0.01).to_le_bytes());
+        let [block] = blocks(TensorType::Q6K, bytes.clone(), 256)
+            .try_into()
+            .unwrap();
+        assert!(block.q.iter().all(|q| (-32..=31).contains(q)));
+        assert!(block.sub_blocks.iter().all(|s| s.min == 0.0));
+        let (mut uniform, mut decoded) = ([0.0f32; 256], [0.0f32; 256]);
+        block.dequantize(&mut uniform);
+        dequantize_q6k_block(&bytes, &mut decoded).unwrap();
+        assert_eq!(uniform, decoded);
+    }
+
+    #[test]
+    fn q4k_is_the_only_format_with_mins() {
+        let mut bytes = pseudo_random(2 * Q4K_BLOCK_SIZE, 2);
+        for b in 0..2 {
+            let at = b * Q4K_BLOCK_SIZE;
+            bytes[at..at + 2].copy_from_slice(&f32_to_f16(0.02).to_le_bytes());
+            bytes[at + 2..at + 4].copy_from_slice(&f32_to_f16(0.005).to_le_bytes());
+        }
+        let decoded_blocks = blocks(TensorType::Q4K, bytes.clone(), 300);
+        assert_eq!(decoded_blocks.len(), 2);
+        for (block, raw) in decoded_blocks.iter().zip(bytes.chunks(Q4K_BLOCK_SIZE)) {
+            assert!(block.sub_blocks.iter().any(|s| s.min != 0.0));
+            let (mut uniform, mut decoded) = ([0.0f32; 256], [0.0f32; 256]);
+            block.dequantize(&mut uniform);
+            dequantize_q4k_block(raw, &mut decoded).unwrap();
+            assert_eq!(uniform, decoded);
+        }
+
+        for dtype in [TensorType::F32, TensorType::Q6K, TensorType::Q8_0] {
+            assert!(!dtype.has_block_mins(), "{dtype:?}");
+        }
+        assert!(TensorType::Q4K.has_block_mins());
+        let f32_tensor = Tensor::new(TensorType::F32, Arc::new(vec![0; 4]), vec![1]);
+        assert!(BlockIter::new(&f32_tensor).is_err());
+    }
 }
@@ -1,10 +1,6 @@
-use crate::EngineError;
+use crate::Result;
 
-pub fn residual_add(
-    input: &[f32],
-    residual: &[f32],
-    output: &mut [f32],
-) -> Result<(), EngineError> {
+pub fn residual_add(input: &[f32], residual: &[f32], output: &mut [f32]) -> Result<()> {
     for i in 0..input.len() {
         output[i] = input[i] + residual[i];
     }
@@ -1,65 +1,36 @@
-// This is the implementation of RMSNorm, over inputs
-// The input should already be dequantized, and the learned weights of the RMSNorm shouldnt be quantized, because their precision matters
+use crate::Result;
+use crate::ops::math;
 
-use crate::EngineError;
+pub use crate::ops::math::rmsnorm::rmsnorm_inplace_no_scale;
 
-/// In-place RMS re-scaling only (no learned scale): `x /= sqrt(mean(x^2)+eps)`.
-/// Matches HF `Gemma4RMSNorm` with `with_scale=false` used on attention **values** in Gemma 4.
-pub fn rmsnorm_inplace_no_scale(x: &mut [f32], epsilon: f32) {
-    let dim = x.len();
-    if dim == 0 {
-        return;
-    }
-    let mut sum_squared: f32 = 0.0;
-    for &v in x.iter() {
-        sum_squared += v * v;
-    }
-    let rms = (sum_squared / dim as f32 + epsilon).sqrt();
-    if rms > 0.0 {
-        for z in x.iter_mut() {
-            *z /= rms;
-        }
-    }
+pub fn rmsnorm(input: &[f32], weights: &[f32], epsilon: f32, output: &mut [f32]) -> Result<()> {
+    rmsnorm_with_offset(input, weights, 0.0, epsilon, output)
 }
 
-pub fn rmsnorm(
+/// [`rmsnorm`] scaling by `weights[i] + weight_offset`, for checkpoints that store the learned
+/// scale as a delta from 1 (offset `1.0`); see [`crate::model_config::ModelConfig::norm_weight_offset`].
+pub fn rmsnorm_with_offset(
     input: &[f32],
     weights: &[f32],
+    weight_offset: f32,
     epsilon: f32,
     output: &mut [f32],
-) -> Result<(), EngineError> {
-    #[cfg(debug_assertions)]
-    debug_assert_eq!(
-        input.len(),
-        weights.len(),
-        "Dimension missmatch for RMSNorm"
-    );
-
-    let mut sum_squared: f32 = 0.0;
-    let dim: usize = input.len();
-
-    for &x in input.iter() {
-        sum_squared += x.powi(2);
-    }
-    let mean_squared: f32 = sum_squared / (dim as f32);
-    let rms = (mean_squared + epsilon).sqrt();
-    for ((out_slot, &x), &w) in output.iter_mut().zip(input.iter()).zip(weights.iter()) {
-        *out_slot = x * w / rms;
-    }
+) -> Result<()> {
+    math::rmsnorm::rmsnorm_with_offset(input, weights, weight_offset, epsilon, output);
     Ok(())
 }
 
 #[cfg(test)]
 mod test {
-    use super::rmsnorm;
+    use super::{rmsnorm, rmsnorm_with_offset};
+    use crate::test_utils::assert_slices_close;
 
     #[test]
     fn rmsnorm_no_scale_unit_vector_unchanged_direction() {
         let mut v = vec![3.0f32, 4.0];
         super::rmsnorm_inplace_no_scale(&mut v, 1e-6);
         let rms = ((9.0f32 + 16.0) / 2.0 + 1e-6).sqrt();
-        assert!((v[0] - 3.0 / rms).abs() < 1e-5);
-        assert!((v[1] - 4.0 / rms).abs() < 1e-5);
+        assert_slices_close(&v, &[3.0 / rms, 4.0 / rms], 1e-5);
     }
 
     #[test]
@@ -72,9 +43,28 @@ mod test {
         rmsnorm(&input, &weights, epsilon, &mut output).unwrap();
 
         let expected = [0.092_582, 0.277_746, 0.555_492];
+        assert_slices_close(&output, &expected, 1e-3);
+    }
+
+    #[test]
+    fn weight_offset_of_one_treats_weights_as_deltas() {
+        let input = [0.5f32, -1.0, 1.5, 2.0];
+        let deltas = [0.0f32, -0.5, 0.25, 1.0];
+        let eps = 1e-6;
+
+        let mut shifted = [0.0f32; 4];
+        rmsnorm_with_offset(&input, &deltas, 1.0, eps, &mut shifted).unwrap();
+        let full: Vec<f32> = deltas.iter().map(|d| 1.0 + d).collect();
+        let mut expected = [0.0f32; 4];
+        rmsnorm(&input, &full, eps, &mut expected).unwrap();
+        assert_eq!(shifted, expected);
 
-        for i in 0..input.len() {
-            assert!((output[i] - expected[i]).abs() < 1e-3);
-        }
+        // A zero delta leaves the plain normalized value (weight 1), where the raw weight zeroes it.
+        let mut raw = [0.0f32; 4];
+        rmsnorm(&input, &deltas, eps, &mut raw).unwrap();
+        assert_eq!(raw[0], 0.0);
+        let rms = ((0.25f32 + 1.0 + 2.25 + 4.0) / 4.0 + eps).sqrt();
+        assert_slices_close(&shifted[..1], &[0.5 / rms], 1e-6);
+        assert_slices_close(&shifted[3..], &[2.0 * 2.0 / rms], 1e-5);
     }
 }
@@ -0,0 +1,381 @@
+//! Kernel self-test: every registered kernel variant is run against its scalar reference on
+//! randomized inputs, on the CPU the process is actually running on.
+//!
+//! The built-in variants are the fixed-length instances in [`crate::ops::specialized`], checked
+//! against the generic loops they are documented to match bit-for-bit. Lengths cover zero, short
+//! vectors, lane remainders and every specialized size; inputs include denormal-heavy and
+//! mixed-magnitude vectors besides plain uniform noise.
+//!
+//! A failing variant can be switched off through its [`KernelVariant::enabled`] flag, after which
+//! its dispatcher uses the generic loop. [`self_test`] runs the full battery;
+//! [`startup_self_test`] runs a quick subset once per process (see
+//! [`crate::engine::config::EngineConfig::startup_self_test`]).
+
+use std::fmt;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ops::specialized::{
+    self, AXPY_SPECIALIZED, DOT_SPECIALIZED, HEAD_DIMS, HIDDEN_DIMS, SUM_SQUARES_SPECIALIZED,
+};
+
+/// Lengths every variant is checked on in [`SelfTestMode::Full`], on top of its own.
+const FULL_LENGTHS: [usize; 16] = [0, 1, 2, 7, 8, 9, 15, 17, 31, 33, 63, 65, 100, 127, 129, 257];
+/// Lengths every variant is checked on in [`SelfTestMode::Quick`], on top of its own.
+const QUICK_LENGTHS: [usize; 3] = [0, 1, 7];
+/// Random seeds per (length, input kind) in [`SelfTestMode::Full`].
+const FULL_SEEDS: u64 = 3;
+
+/// A kernel and the scalar reference it must agree with. Both sides have the same signature.
+#[derive(Clone, Copy)]
+pub enum KernelPair {
+    Dot {
+        candidate: fn(&[f32], &[f32]) -> f32,
+        reference: fn(&[f32], &[f32]) -> f32,
+    },
+    Axpy {
+        candidate: fn(f32, &[f32], &mut [f32]),
+        reference: fn(f32, &[f32], &mut [f32]),
+    },
+    SumSquares {
+        candidate: fn(&[f32]) -> f32,
+        reference: fn(&[f32]) -> f32,
+    },
+}
+
+/// One registered kernel variant.
+pub struct KernelVariant {
+    pub name: &'static str,
+    pub pair: KernelPair,
+    /// Lengths this variant has dedicated code for; always checked, also in quick mode.
+    pub lengths: &'static [usize],
+    /// Largest accepted error, relative to `max(1, |reference|)`. `0.0` demands identical bits.
+    pub tolerance: f32,
+    /// Cleared by [`SelfTestReport::disable_failing`]; the kernel's dispatcher must then fall back
+    /// to the reference.
+    pub enabled: &'static AtomicBool,
+}
+
+/// How much of the battery to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestMode {
+    /// Every length, input kind and seed (milliseconds).
+    Full,
+    /// Each variant's own lengths plus a few remainders, uniform inputs only (microseconds).
+    Quick,
+}
+
+/// Outcome for one variant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KernelCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    /// Worst error seen, on the same scale as [`KernelVariant::tolerance`].
+    pub max_error: f32,
+    pub tolerance: f32,
+    /// Length of the input that produced `max_error`.
+    pub worst_len: usize,
+    pub cases: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelfTestReport {
+    pub checks: Vec<KernelCheck>,
+}
+
+impl SelfTestReport {
+    pub fn all_passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &KernelCheck> {
+        self.checks.iter().filter(|c| !c.passed)
+    }
+
+    /// Switch off every variant of `registry` that failed here, with a warning per variant.
+    /// Returns how many were disabled.
+    pub fn disable_failing(&self, registry: &KernelRegistry) -> usize {
+        let mut disabled = 0;
+        for check in self.failures() {
+            for v in registry.variants.iter().filter(|v| v.name == check.name) {
+                log::warn!(
+                    "kernel {} failed self-test (max error {:e} > {:e} at len {}); \
+                     falling back to the scalar reference",
+                    check.name,
+                    check.max_error,
+                    check.tolerance,
+                    check.worst_len
+                );
+                v.enabled.store(false, Ordering::Relaxed);
+                disabled += 1;
+            }
+        }
+        disabled
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.checks {
+            writeln!(
+                f,
+                "{} {:<24} max error {:.3e} (tolerance {:.1e}, worst len {}, {} cases)",
+                if c.passed { "PASS" } else { "FAIL" },
+                c.name,
+                c.max_error,
+                c.tolerance,
+                c.worst_len,
+                c.cases
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The set of variants a self-test covers.
+#[derive(Default)]
+pub struct KernelRegistry {
+    variants: Vec<KernelVariant>,
+}
+
+impl KernelRegistry {
+    /// The kernels this crate dispatches to at runtime.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register(KernelVariant {
+            name: "dot/specialized",
+            pair: KernelPair::Dot {
+                candidate: specialized::dot,
+                reference: specialized::dot_generic,
+            },
+            lengths: &HEAD_DIMS,
+            tolerance: 0.0,
+            enabled: &DOT_SPECIALIZED,
+        });
+        registry.register(KernelVariant {
+            name: "axpy/specialized",
+            pair: KernelPair::Axpy {
+                candidate: specialized::axpy,
+                reference: specialized::axpy_generic,
+            },
+            lengths: &HEAD_DIMS,
+            tolerance: 0.0,
+            enabled: &AXPY_SPECIALIZED,
+        });
+        registry.register(KernelVariant {
+            name: "sum_squares/specialized",
+            pair: KernelPair::SumSquares {
+                candidate: specialized::sum_squares,
+                reference: specialized::sum_squares_generic,
+            },
+            lengths: &HIDDEN_DIMS,
+            tolerance: 0.0,
+            enabled: &SUM_SQUARES_SPECIALIZED,
+        });
+        registry
+    }
+
+    pub fn register(&mut self, variant: KernelVariant) {
+        self.variants.push(variant);
+    }
+
+    pub fn variants(&self) -> &[KernelVariant] {
+        &self.variants
+    }
+
+    /// Check every variant. Variants that are already disabled are still run (through their
+    /// dispatcher, so they normally pass).
+    pub fn run(&self, mode: SelfTestMode) -> SelfTestReport {
+        SelfTestReport {
+            checks: self
+                .variants
+                .iter()
+                .map(|v| check_variant(v, mode))
+                .collect(),
+        }
+    }
+}
+
+/// Full battery over [`KernelRegistry::builtin`]; failing kernels are disabled for the rest of
+/// the process.
+pub fn self_test() -> SelfTestReport {
+    let registry = KernelRegistry::builtin();
+    let report = registry.run(SelfTestMode::Full);
+    report.disable_failing(&registry);
+    report
+}
+
+/// Quick subset over [`KernelRegistry::builtin`], run at most once per process; later calls
+/// return the first report.
+pub fn startup_self_test() -> &'static SelfTestReport {
+    static REPORT: OnceLock<SelfTestReport> = OnceLock::new();
+    REPORT.get_or_init(|| {
+        let registry = KernelRegistry::builtin();
+        let report = registry.run(SelfTestMode::Quick);
+        report.disable_failing(&registry);
+        report
+    })
+}
+
+#[derive(Clone, Copy)]
+enum InputKind {
+    Uniform,
+    /// Every other element subnormal.
+    Denormal,
+    /// Magnitudes spread over 1e-3..1e3.
+    MixedScale,
+}
+
+fn check_variant(v: &KernelVariant, mode: SelfTestMode) -> KernelCheck {
+    let (base, kinds, seeds): (&[usize], &[InputKind], u64) = match mode {
+        SelfTestMode::Full => (
+            &FULL_LENGTHS,
+            &[
+                InputKind::Uniform,
+                InputKind::Denormal,
+                InputKind::MixedScale,
+            ],
+            FULL_SEEDS,
+        ),
+        SelfTestMode::Quick => (&QUICK_LENGTHS, &[InputKind::Uniform], 1),
+    };
+    let mut out = KernelCheck {
+        name: v.name,
+        passed: true,
+        max_error: 0.0,
+        tolerance: v.tolerance,
+        worst_len: 0,
+        cases: 0,
+    };
+    for &len in base.iter().chain(v.lengths) {
+        for &kind in kinds {
+            for seed in 0..seeds {
+                let seed = seed.wrapping_mul(0x9e37_79b9) ^ len as u64;
+                let err = run_case(&v.pair, len, kind, seed);
+                out.cases += 1;
+                if err > out.max_error || err.is_nan() {
+                    out.max_error = err;
+                    out.worst_len = len;
+                }
+            }
+        }
+    }
+    out.passed = out.max_error <= v.tolerance;
+    out
+}
+
+fn run_case(pair: &KernelPair, len: usize, kind: InputKind, seed: u64) -> f32 {
+    let a = input(len, kind, seed);
+    let b = input(len, kind, seed ^ 0x5555);
+    match *pair {
+        KernelPair::Dot {
+            candidate,
+            reference,
+        } => scalar_error(candidate(&a, &b), reference(&a, &b)),
+        KernelPair::Axpy {
+            candidate,
+            reference,
+        } => {
+            let alpha = 0.37;
+            let mut got = b.clone();
+            let mut want = b;
+            candidate(alpha, &a, &mut got);
+            reference(alpha, &a, &mut want);
+            got.iter()
+                .zip(&want)
+                .map(|(&g, &w)| scalar_error(g, w))
+                .fold(0.0, f32::max)
+        }
+        KernelPair::SumSquares {
+            candidate,
+            reference,
+        } => scalar_error(candidate(&a), reference(&a)),
+    }
+}
+
+/// `|got - want| / max(1, |want|)`; zero for identical bits, infinite if only one is finite.
+fn scalar_error(got: f32, want: f32) -> f32 {
+    if got.to_bits() == want.to_bits() {
+        return 0.0;
+    }
+    if !(got.is_finite() && want.is_finite()) {
+        return f32::INFINITY;
+    }
+    (got - want).abs() / want.abs().max(1.0)
+}
+
+fn input(len: usize, kind: InputKind, seed: u64) -> Vec<f32> {
+    let mut s = seed;
+    let mut next = || {
+        s = s
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((s >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+    };
+    (0..len)
+        .map(|i| {
+            let u = next();
+            match kind {
+                InputKind::Uniform => u,
+                InputKind::Denormal if i % 2 == 0 => u * f32::MIN_POSITIVE * 0.5,
+                InputKind::Denormal => u,
+                InputKind::MixedScale => u * 10f32.powi((i % 7) as i32 - 3),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static BROKEN_ENABLED: AtomicBool = AtomicBool::new(true);
+
+    /// Drops the last element once the length passes one lane group.
+    fn broken_dot(a: &[f32], b: &[f32]) -> f32 {
+        let n = if a.len() > 8 { a.len() - 1 } else { a.len() };
+        specialized::dot_generic(&a[..n], &b[..n])
+    }
+
+    #[test]
+    fn builtin_kernels_pass_full_battery() {
+        let report = KernelRegistry::builtin().run(SelfTestMode::Full);
+        assert_eq!(report.checks.len(), 3);
+        assert!(report.all_passed(), "{report}");
+        assert!(report.checks.iter().all(|c| c.cases > 0));
+    }
+
+    #[test]
+    fn broken_kernel_is_reported_and_disabled() {
+        let mut registry = KernelRegistry::default();
+        registry.register(KernelVariant {
+            name: "dot/broken",
+            pair: KernelPair::Dot {
+                candidate: broken_dot,
+                reference: specialized::dot_generic,
+            },
+            lengths: &HEAD_DIMS,
+            tolerance: 1e-6,
+            enabled: &BROKEN_ENABLED,
+        });
+
+        let report = registry.run(SelfTestMode::Quick);
+        let failed: Vec<_> = report.failures().map(|c| c.name).collect();
+        assert_eq!(failed, ["dot/broken"]);
+        assert!(report.checks[0].max_error > 1e-6);
+        assert!(HEAD_DIMS.contains(&report.checks[0].worst_len));
+        assert!(report.to_string().starts_with("FAIL dot/broken"));
+
+        assert!(BROKEN_ENABLED.load(Ordering::Relaxed));
+        assert_eq!(report.disable_failing(&registry), 1);
+        assert!(!BROKEN_ENABLED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn nan_mismatch_counts_as_failure() {
+        assert_eq!(scalar_error(1.0, 1.0), 0.0);
+        assert_eq!(scalar_error(f32::NAN, 1.0), f32::INFINITY);
+        assert!((scalar_error(1.5, 1.0) - 0.5).abs() < 1e-7);
+        assert!((scalar_error(201.0, 200.0) - 0.005).abs() < 1e-7);
+    }
+}
@@ -0,0 +1,78 @@
+//! Embedding comparisons for semantic search over token/sequence embeddings.
+
+use crate::{EngineError, Result};
+
+/// Cosine of the angle between `a` and `b`, in `[-1, 1]`. Returns `0.0` when either vector has
+/// zero norm (no direction to compare). Accumulates in f64 so long vectors stay accurate.
+/// Vectors of different lengths are an [`EngineError::Op`].
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Result<f32> {
+    if a.len() != b.len() {
+        return Err(EngineError::Op(format!(
+            "cosine_similarity: length mismatch ({} vs {})",
+            a.len(),
+            b.len()
+        )));
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (&x, &y) in a.iter().zip(b) {
+        let (x, y) = (x as f64, y as f64);
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok((dot / (norm_a.sqrt() * norm_b.sqrt())).clamp(-1.0, 1.0) as f32)
+}
+
+/// Mean over rows of a contiguous `[seq, hidden_dim]` buffer (e.g. [`crate::engine::state::ForwardState::hidden`]).
+pub fn mean_pool(rows: &[f32], hidden_dim: usize) -> Result<Vec<f32>> {
+    if hidden_dim == 0 || rows.is_empty() || rows.len() % hidden_dim != 0 {
+        return Err(EngineError::Op(format!(
+            "mean_pool: {} values is not a non-empty multiple of hidden_dim {hidden_dim}",
+            rows.len()
+        )));
+    }
+    let seq_len = rows.len() / hidden_dim;
+    let mut sum = vec![0.0f64; hidden_dim];
+    for row in rows.chunks_exact(hidden_dim) {
+        for (s, &x) in sum.iter_mut().zip(row) {
+            *s += x as f64;
+        }
+    }
+    Ok(sum
+        .into_iter()
+        .map(|s| (s / seq_len as f64) as f32)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_identical_orthogonal_opposite() {
+        let a = [1.0, 2.0, 3.0];
+        assert!((cosine_similarity(&a, &a).unwrap() - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 5.0]).unwrap().abs() < 1e-6);
+        assert!((cosine_similarity(&a, &[-1.0, -2.0, -3.0]).unwrap() + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&a, &[0.0; 3]).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn cosine_rejects_length_mismatch() {
+        assert!(matches!(
+            cosine_similarity(&[1.0, 2.0], &[1.0, 2.0, 3.0]),
+            Err(EngineError::Op(_))
+        ));
+    }
+
+    #[test]
+    fn mean_pool_averages_rows() {
+        let rows = [1.0, 2.0, 3.0, 6.0];
+        assert_eq!(mean_pool(&rows, 2).unwrap(), vec![2.0, 4.0]);
+        assert!(mean_pool(&rows, 3).is_err());
+        assert!(mean_pool(&[], 2).is_err());
+    }
+}
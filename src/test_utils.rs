@@ -0,0 +1,156 @@
+//! Helpers for the crate's unit tests, which is the only build that includes this module: F32
+//! tensor construction, scratch directories, and assertions for tests that compare float
+//! buffers, with failure messages that say where the buffers differ instead of only that they do.
+
+use std::fmt::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::core::tensor::{Tensor, TensorType};
+
+/// An F32 tensor holding `values` in storage order.
+pub fn f32_tensor(values: &[f32], dims: Vec<usize>) -> Tensor {
+    let bytes = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    Tensor::new(TensorType::F32, Arc::new(bytes), dims)
+}
+
+/// A fresh directory under the system temp dir, removed with its contents on drop, so a failing
+/// test leaves nothing behind.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `inference_engine_rust_{name}_{pid}`, emptied first if an earlier run left it behind.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "inference_engine_rust_{name}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Diverging indices listed in a failure message; the rest are only counted.
+pub const MAX_REPORTED: usize = 8;
+
+/// Panic unless `a` and `b` have the same length and every pair is within `tol` (equal
+/// infinities count as close, NaN never does). The message lists the first [`MAX_REPORTED`]
+/// diverging indices with both values, and the largest absolute difference.
+#[track_caller]
+pub fn assert_slices_close(a: &[f32], b: &[f32], tol: f32) {
+    if let Err(msg) = compare_slices(a, b, tol) {
+        panic!("{msg}");
+    }
+}
+
+/// [`assert_slices_close`] without the panic: the failure message, if any.
+pub fn compare_slices(a: &[f32], b: &[f32], tol: f32) -> Result<(), String> {
+    if a.len() != b.len() {
+        return Err(format!(
+            "slices differ in length: left has {}, right has {}",
+            a.len(),
+            b.len()
+        ));
+    }
+    let diverging: Vec<usize> = (0..a.len())
+        .filter(|&i| !(a[i] == b[i] || (a[i] - b[i]).abs() <= tol))
+        .collect();
+    if diverging.is_empty() {
+        return Ok(());
+    }
+    let diff = |i: usize| {
+        let d = (a[i] - b[i]).abs();
+        if d.is_nan() { f32::INFINITY } else { d }
+    };
+    let worst = diverging
+        .iter()
+        .copied()
+        .max_by(|&i, &j| diff(i).total_cmp(&diff(j)))
+        .expect("non-empty");
+    let mut msg = format!(
+        "{} of {} elements differ by more than {tol}; max abs diff {} at [{worst}]",
+        diverging.len(),
+        a.len(),
+        (a[worst] - b[worst]).abs()
+    );
+    for &i in diverging.iter().take(MAX_REPORTED) {
+        let _ = write!(
+            msg,
+            "\n  [{i}] left {} right {} diff {}",
+            a[i],
+            b[i],
+            (a[i] - b[i]).abs()
+        );
+    }
+    if diverging.len() > MAX_REPORTED {
+        let _ = write!(msg, "\n  ... and {} more", diverging.len() - MAX_REPORTED);
+    }
+    Err(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_slices_pass() {
+        assert_slices_close(&[], &[], 0.0);
+        assert_slices_close(
+            &[1.0, -2.5, f32::INFINITY],
+            &[1.0, -2.5, f32::INFINITY],
+            0.0,
+        );
+        assert_slices_close(&[1.0, 2.0], &[1.0 + 1e-6, 2.0 - 1e-6], 1e-5);
+    }
+
+    #[test]
+    fn failure_names_the_bad_index_and_both_values() {
+        let left = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let right = [0.0, 1.0, 2.0, 3.5, 4.0];
+        let panic = std::panic::catch_unwind(|| assert_slices_close(&left, &right, 1e-3))
+            .expect_err("slices differ");
+        let msg = panic.downcast_ref::<String>().expect("formatted message");
+        assert!(msg.contains("1 of 5 elements"), "{msg}");
+        assert!(msg.contains("max abs diff 0.5 at [3]"), "{msg}");
+        assert!(msg.contains("[3] left 3 right 3.5"), "{msg}");
+        assert!(!msg.contains("[2]"), "{msg}");
+    }
+
+    #[test]
+    fn long_failures_are_truncated_and_nan_never_matches() {
+        let left = vec![0.0; 20];
+        let mut right = vec![1.0; 20];
+        right[7] = f32::NAN;
+        let msg = compare_slices(&left, &right, 0.1).unwrap_err();
+        assert!(msg.contains("max abs diff NaN at [7]"), "{msg}");
+        assert_eq!(msg.lines().count(), 1 + MAX_REPORTED + 1, "{msg}");
+        assert!(msg.ends_with("... and 12 more"), "{msg}");
+        assert!(
+            compare_slices(&[1.0], &[1.0, 2.0], 1.0)
+                .unwrap_err()
+                .contains("length")
+        );
+    }
+}
@@ -1,21 +1,35 @@
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use sentencepiece::SentencePieceProcessor;
 use std::path::Path;
 
 use tokenizers::Tokenizer as HfTokenizer;
 
+use super::normalize::{NormalizationForm, TextNormalization, normalize_prompt};
+use super::unknown::{ByteTokens, EncodeResult, UnknownTokenPolicy, apply_policy};
 use crate::EngineError;
 use crate::model_config::TokenizerPromptConfig;
 
+#[allow(clippy::large_enum_variant)]
 enum TokenizerBackend {
+    /// Not in browser (`wasm32-unknown-unknown`) builds: the library is C++.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
     SentencePiece(SentencePieceProcessor),
     HuggingFace(HfTokenizer),
 }
 
-/// Text tokenizer: **SentencePiece** (`.model`) or Hugging Face **`tokenizer.json`**.
+/// Text tokenizer: **SentencePiece** (`.model`) or Hugging Face **`tokenizer.json`**. Browser
+/// (`wasm32-unknown-unknown`) builds only have the latter.
 pub struct Tokenizer {
     backend: TokenizerBackend,
     /// SPM-only cache for [`Self::decode`] when pieces were produced by [`Self::encode`].
+    #[cfg_attr(all(target_arch = "wasm32", target_os = "unknown"), allow(dead_code))]
     id_to_piece: std::collections::HashMap<u32, String>,
+    /// Applied to text in [`Self::encode`] before the backend sees it.
+    normalization: TextNormalization,
+    unk_id: Option<u32>,
+    /// `<0x00>`..`<0xFF>`, when the vocabulary has all of them.
+    byte_tokens: Option<ByteTokens>,
+    unknown_policy: UnknownTokenPolicy,
 }
 
 impl Tokenizer {
@@ -29,27 +43,114 @@ impl Tokenizer {
             .is_some_and(|e| e.eq_ignore_ascii_case("json"));
 
         if is_hf_json {
-            let inner = HfTokenizer::from_file(path).map_err(|e| {
-                EngineError::Tokenizer(format!("failed to load Hugging Face tokenizer.json: {e}"))
+            return HfTokenizer::from_file(path)
+                .map(Self::from_hf)
+                .map_err(|e| {
+                    EngineError::Tokenizer(format!(
+                        "failed to load Hugging Face tokenizer.json: {e}"
+                    ))
+                });
+        }
+
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        return Err(EngineError::Tokenizer(
+            "SentencePiece tokenizers are not available in wasm32 builds; use tokenizer.json"
+                .into(),
+        ));
+
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        {
+            let inner = SentencePieceProcessor::open(path).map_err(|e| {
+                EngineError::Tokenizer(format!("failed to load SentencePiece tokenizer: {e}"))
             })?;
-            return Ok(Self {
-                backend: TokenizerBackend::HuggingFace(inner),
-                id_to_piece: std::collections::HashMap::new(),
-            });
+
+            // The `.model` carries its own normalizer rules; only fold newlines here.
+            Ok(Self::with_backend(
+                TokenizerBackend::SentencePiece(inner),
+                TextNormalization::default(),
+            ))
         }
+    }
 
-        let inner = SentencePieceProcessor::open(path).map_err(|e| {
-            EngineError::Tokenizer(format!("failed to load SentencePiece tokenizer: {e}"))
-        })?;
+    /// Hugging Face `tokenizer.json` contents already in memory (no file system, e.g. in a
+    /// browser).
+    pub fn from_json_bytes(bytes: &[u8]) -> Result<Self, EngineError> {
+        HfTokenizer::from_bytes(bytes)
+            .map(Self::from_hf)
+            .map_err(|e| {
+                EngineError::Tokenizer(format!("failed to parse Hugging Face tokenizer.json: {e}"))
+            })
+    }
+
+    fn from_hf(inner: HfTokenizer) -> Self {
+        let normalization = TextNormalization::with_form(hf_normalizer_form(&inner));
+        Self::with_backend(TokenizerBackend::HuggingFace(inner), normalization)
+    }
 
-        Ok(Self {
-            backend: TokenizerBackend::SentencePiece(inner),
+    fn with_backend(backend: TokenizerBackend, normalization: TextNormalization) -> Self {
+        let unk_id = match &backend {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            TokenizerBackend::SentencePiece(sp) => Some(sp.unk_id()),
+            TokenizerBackend::HuggingFace(hf) => serde_json::to_value(hf.get_model())
+                .ok()
+                .and_then(|m| m.get("unk_token")?.as_str().map(str::to_string))
+                .and_then(|tok| hf.token_to_id(&tok)),
+        };
+        let byte_tokens = ByteTokens::find(|piece| match &backend {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            TokenizerBackend::SentencePiece(sp) => sp.piece_to_id(piece).ok().flatten(),
+            TokenizerBackend::HuggingFace(hf) => hf.token_to_id(piece),
+        });
+        let unknown_policy = if byte_tokens.is_some() {
+            UnknownTokenPolicy::ByteFallback
+        } else {
+            UnknownTokenPolicy::EmitUnk
+        };
+        Self {
+            backend,
             id_to_piece: std::collections::HashMap::new(),
-        })
+            normalization,
+            unk_id,
+            byte_tokens,
+            unknown_policy,
+        }
+    }
+
+    /// Prompt preprocessing used by [`Self::encode`]. Defaults to the form named by the
+    /// tokenizer's own normalizer (HF `tokenizer.json`), otherwise newline folding only.
+    pub fn set_normalization(&mut self, normalization: TextNormalization) {
+        self.normalization = normalization;
+    }
+
+    pub fn normalization(&self) -> TextNormalization {
+        self.normalization
+    }
+
+    /// How [`Self::encode`] treats characters with no vocabulary piece. Defaults to
+    /// [`UnknownTokenPolicy::ByteFallback`] when the vocabulary has byte tokens, otherwise
+    /// [`UnknownTokenPolicy::EmitUnk`]; choosing byte fallback without byte tokens is an error.
+    pub fn set_unknown_policy(&mut self, policy: UnknownTokenPolicy) -> Result<(), EngineError> {
+        if policy == UnknownTokenPolicy::ByteFallback && self.byte_tokens.is_none() {
+            return Err(EngineError::Tokenizer(
+                "byte fallback needs <0x00>..<0xFF> tokens, which this vocabulary lacks".into(),
+            ));
+        }
+        self.unknown_policy = policy;
+        Ok(())
+    }
+
+    pub fn unknown_policy(&self) -> UnknownTokenPolicy {
+        self.unknown_policy
+    }
+
+    /// Whether the vocabulary has all 256 `<0xNN>` byte tokens.
+    pub fn has_byte_tokens(&self) -> bool {
+        self.byte_tokens.is_some()
     }
 
     pub fn decode_piece_ids(&self, ids: &[u32]) -> Result<String, EngineError> {
         match &self.backend {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
             TokenizerBackend::SentencePiece(sp) => sp
                 .decode_piece_ids(ids)
                 .map_err(|e| EngineError::Tokenizer(format!("decode_piece_ids: {e}"))),
@@ -59,8 +160,70 @@ impl Tokenizer {
         }
     }
 
+    /// Decode `tokens` as a continuation of existing text, without the leading space: the
+    /// word-boundary `▁` on the first piece is not turned into a space (`▁world` decodes to
+    /// `world`), whatever the decoder would do at that position. For the exact text the ids add
+    /// to a stream, spaces between words included, use [`Self::decode_appended`].
+    pub fn decode_continuation(&self, tokens: &[u32]) -> Result<String, EngineError> {
+        let text = self.decode_piece_ids(tokens)?;
+        Ok(match text.strip_prefix(' ') {
+            Some(rest) => rest.to_string(),
+            None => text,
+        })
+    }
+
+    /// Text `tokens` add when appended to already-decoded text (streaming, or constraining what
+    /// a token adds to the output). [`Self::decode_piece_ids`] treats its ids as the start of a
+    /// text, so SentencePiece-style decoders drop the leading `▁` of the first piece and appended
+    /// pieces run words together. Here the ids are decoded behind an ordinary anchor piece whose
+    /// text is then removed: a word-initial piece keeps its one space and a word-internal piece
+    /// (`ing`) gets none.
+    pub fn decode_appended(&self, tokens: &[u32]) -> Result<String, EngineError> {
+        if tokens.is_empty() {
+            return Ok(String::new());
+        }
+        let Some(anchor) = self.continuation_anchor() else {
+            return self.decode_piece_ids(tokens);
+        };
+        let ids: Vec<u32> = std::iter::once(anchor)
+            .chain(tokens.iter().copied())
+            .collect();
+        let anchored = self.decode_piece_ids(&ids)?;
+        let anchor_text = self.decode_piece_ids(&[anchor])?;
+        match anchored.strip_prefix(anchor_text.as_str()) {
+            Some(rest) => Ok(rest.to_string()),
+            // A decoder that merged the anchor into the next piece; fall back to a plain decode.
+            None => self.decode_piece_ids(tokens),
+        }
+    }
+
+    /// A plain piece to decode in front of [`Self::decode_appended`]'s ids: a lone `a` in one of
+    /// the common spellings, else UNK.
+    fn continuation_anchor(&self) -> Option<u32> {
+        const CANDIDATES: [&str; 3] = ["a", "\u{2581}a", "\u{120}a"];
+        let found = match &self.backend {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            TokenizerBackend::SentencePiece(sp) => CANDIDATES
+                .iter()
+                .find_map(|p| sp.piece_to_id(p).ok().flatten()),
+            TokenizerBackend::HuggingFace(hf) => CANDIDATES.iter().find_map(|t| hf.token_to_id(t)),
+        };
+        found.or_else(|| self.unk_id())
+    }
+
+    /// Token ids for `text`, with uncovered characters handled per [`Self::unknown_policy`].
     pub fn encode(&mut self, text: &str) -> Result<Vec<u32>, EngineError> {
-        match &mut self.backend {
+        Ok(self.encode_checked(text)?.ids)
+    }
+
+    /// [`Self::encode`], also reporting which characters fell back to byte tokens or became
+    /// UNK. Under [`UnknownTokenPolicy::Error`], uncovered text fails with
+    /// [`EngineError::UnknownText`].
+    pub fn encode_checked(&mut self, text: &str) -> Result<EncodeResult, EngineError> {
+        let normalized = normalize_prompt(text, &self.normalization)?;
+        let text = normalized.as_str();
+        let pieces: Vec<(u32, std::ops::Range<usize>)> = match &mut self.backend {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
             TokenizerBackend::SentencePiece(sp) => {
                 let pieces = sp
                     .encode(text)
@@ -68,37 +231,148 @@ impl Tokenizer {
                 for piece in &pieces {
                     self.id_to_piece.insert(piece.id, piece.piece.clone());
                 }
-                Ok(pieces.iter().map(|piece| piece.id).collect())
+                pieces
+                    .iter()
+                    .map(|p| (p.id, p.span.0 as usize..p.span.1 as usize))
+                    .collect()
             }
             TokenizerBackend::HuggingFace(hf) => {
                 let enc = hf
                     .encode(text, false)
                     .map_err(|e| EngineError::Tokenizer(format!("encode: {e}")))?;
-                Ok(enc.get_ids().to_vec())
+                enc.get_ids()
+                    .iter()
+                    .zip(enc.get_offsets())
+                    .map(|(&id, &(start, end))| (id, start..end))
+                    .collect()
+            }
+        };
+        let result = apply_policy(
+            text,
+            &pieces,
+            self.unk_id,
+            self.byte_tokens.as_ref(),
+            self.unknown_policy,
+        )?;
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        if let (TokenizerBackend::SentencePiece(_), Some(bytes)) =
+            (&self.backend, &self.byte_tokens)
+        {
+            for byte in 0..=u8::MAX {
+                self.id_to_piece
+                    .entry(bytes.id(byte))
+                    .or_insert_with(|| super::unknown::byte_piece(byte));
             }
         }
+        Ok(result)
     }
 
+    /// [`Self::encode`] plus the special tokens `cfg` asks for. With `cfg.dedupe_bos`, a prompt
+    /// that already starts with BOS keeps exactly one.
+    ///
+    /// Text that encodes to nothing (empty, whitespace the pre-tokenizer drops, or characters
+    /// removed by normalization) yields just the special tokens, e.g. `[BOS]`; with none to add
+    /// it fails with [`EngineError::EmptyPrompt`].
     pub fn encode_with_prompt_config(
         &mut self,
         text: &str,
         cfg: &TokenizerPromptConfig,
     ) -> Result<Vec<u32>, EngineError> {
-        let mut ids = self.encode(text)?;
-        if cfg.add_bos_token {
-            ids.insert(0, cfg.bos_token_id);
+        let bos = cfg.bos_token_id;
+        if cfg.add_bos_token && cfg.dedupe_bos {
+            // SentencePiece (and vocabularies without BOS as an added token) split a literal BOS
+            // piece into ordinary pieces, so look for it in the text as well as in the ids.
+            let rest = self
+                .bos_piece(bos)
+                .and_then(|piece| text.strip_prefix(piece.as_str()));
+            if let Some(rest) = rest {
+                let mut ids = vec![bos];
+                ids.extend(self.encode_prompt(rest)?);
+                return Ok(with_eos(ids, cfg));
+            }
         }
-        if cfg.add_eos_token {
-            ids.push(cfg.eos_token_id);
+        let mut ids = self.encode_prompt(text)?;
+        let has_bos = cfg.dedupe_bos && ids.first() == Some(&bos);
+        if cfg.add_bos_token && !has_bos {
+            ids.insert(0, bos);
+        }
+        let ids = with_eos(ids, cfg);
+        if ids.is_empty() {
+            return Err(EngineError::EmptyPrompt(format!(
+                "{text:?} encodes to no tokens and the model adds no BOS"
+            )));
         }
         Ok(ids)
     }
 
+    /// [`Self::encode`] for prompt text, warning when some of it could only be encoded as UNK
+    /// (the model never sees those characters).
+    fn encode_prompt(&mut self, text: &str) -> Result<Vec<u32>, EngineError> {
+        let result = self.encode_checked(text)?;
+        if !result.is_lossless() {
+            log::warn!(
+                "prompt has {} character(s) with no token in the vocabulary, encoded as UNK (first at byte {})",
+                result.unknown.len(),
+                result.unknown[0]
+            );
+        }
+        Ok(result.ids)
+    }
+
+    /// Text of the BOS piece: the HF vocabulary entry, or `<s>` if the SentencePiece model maps
+    /// it to `bos` (the Rust bindings only look pieces up by text).
+    fn bos_piece(&self, bos: u32) -> Option<String> {
+        match &self.backend {
+            TokenizerBackend::HuggingFace(hf) => hf.id_to_token(bos),
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            TokenizerBackend::SentencePiece(sp) => {
+                (sp.piece_to_id("<s>").ok().flatten() == Some(bos)).then(|| "<s>".to_string())
+            }
+        }
+    }
+
+    /// Decode ids to text. Errors, naming the first offending id, if any id is `>= vocab_size`.
     pub fn decode(&self, tokens: &[u32]) -> Result<String, EngineError> {
+        if let Some(&bad) = tokens.iter().find(|&&id| !self.is_known_id(id)) {
+            return Err(EngineError::Tokenizer(format!(
+                "cannot decode token id {bad}: outside vocabulary of {}",
+                self.vocab_size()
+            )));
+        }
+        self.decode_known(tokens)
+    }
+
+    /// Like [`Self::decode`], but out-of-vocab ids are replaced by the UNK piece instead of
+    /// failing. Errors only if the tokenizer defines no UNK token.
+    pub fn decode_lossy(&self, tokens: &[u32]) -> Result<String, EngineError> {
+        if tokens.iter().all(|&id| self.is_known_id(id)) {
+            return self.decode_known(tokens);
+        }
+        let unk = self.unk_id().ok_or_else(|| {
+            EngineError::Tokenizer("lossy decode: tokenizer has no UNK token".into())
+        })?;
+        let ids: Vec<u32> = tokens
+            .iter()
+            .map(|&id| if self.is_known_id(id) { id } else { unk })
+            .collect();
+        self.decode_known(&ids)
+    }
+
+    fn is_known_id(&self, id: u32) -> bool {
+        (id as usize) < self.vocab_size()
+    }
+
+    /// UNK id: SentencePiece's `unk_id`, or the HF model's `unk_token` if it has one.
+    fn unk_id(&self) -> Option<u32> {
+        self.unk_id
+    }
+
+    fn decode_known(&self, tokens: &[u32]) -> Result<String, EngineError> {
         match &self.backend {
             TokenizerBackend::HuggingFace(hf) => hf
                 .decode(tokens, false)
                 .map_err(|e| EngineError::Tokenizer(format!("decode: {e}"))),
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
             TokenizerBackend::SentencePiece(_) => {
                 let piece_strings: Vec<String> = tokens
                     .iter()
@@ -118,12 +392,62 @@ impl Tokenizer {
         }
     }
 
+    /// For each of `texts`, whether decoding its encoding gives the text back as
+    /// [`Self::encode`] saw it (after [`Self::normalization`]). Decodes with
+    /// [`Self::decode_piece_ids`], so SentencePiece word markers become spaces. A text that fails
+    /// to encode or decode counts as not round-tripping.
+    pub fn roundtrip_report(&mut self, texts: &[&str]) -> Vec<(String, bool)> {
+        texts
+            .iter()
+            .map(|&text| {
+                let ok = normalize_prompt(text, &self.normalization)
+                    .and_then(|expected| {
+                        let ids = self.encode(text)?;
+                        Ok(self.decode_piece_ids(&ids)? == expected)
+                    })
+                    .unwrap_or(false);
+                (text.to_string(), ok)
+            })
+            .collect()
+    }
+
     pub fn vocab_size(&self) -> usize {
         match &self.backend {
             TokenizerBackend::HuggingFace(hf) => hf.get_vocab_size(true),
-            TokenizerBackend::SentencePiece(_) => 0,
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            TokenizerBackend::SentencePiece(sp) => sp.len(),
+        }
+    }
+}
+
+fn with_eos(mut ids: Vec<u32>, cfg: &TokenizerPromptConfig) -> Vec<u32> {
+    if cfg.add_eos_token {
+        ids.push(cfg.eos_token_id);
+    }
+    ids
+}
+
+/// NFC/NFKC if the `tokenizer.json` normalizer (or any step of a `Sequence`) is one of them.
+fn hf_normalizer_form(hf: &HfTokenizer) -> NormalizationForm {
+    fn form_of(v: &serde_json::Value) -> NormalizationForm {
+        match v.get("type").and_then(|t| t.as_str()) {
+            Some("NFKC") => NormalizationForm::Nfkc,
+            Some("NFC") => NormalizationForm::Nfc,
+            Some("Sequence") => v
+                .get("normalizers")
+                .and_then(|n| n.as_array())
+                .into_iter()
+                .flatten()
+                .map(form_of)
+                .find(|f| *f != NormalizationForm::None)
+                .unwrap_or_default(),
+            _ => NormalizationForm::None,
         }
     }
+    hf.get_normalizer()
+        .and_then(|n| serde_json::to_value(n).ok())
+        .map(|v| form_of(&v))
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
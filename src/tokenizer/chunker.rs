@@ -0,0 +1,255 @@
+//! Regroup streamed token text into words or sentences for display.
+//!
+//! Boundaries are found in the decoded text (what [`super::IncrementalDecoder`] returns), not in
+//! token ids, so they work the same for SentencePiece and byte-level BPE vocabularies.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// How much text a stream hands out at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    /// Every token's text as soon as it decodes.
+    #[default]
+    Token,
+    /// Whole words: text is cut just before whitespace, so punctuation and quotes stay with the
+    /// word they touch (`don't`, `3.14`, `"Hi!"` are never split). Text containing CJK characters,
+    /// which has no spaces to cut at, is passed through per token.
+    Word,
+    /// Whole sentences: cut before the whitespace that follows `.`, `!` or `?` (plus any closing
+    /// quotes / brackets) when the next sentence starts with something other than a lowercase
+    /// letter, after a newline, and after `。`, `！`, `？`.
+    Sentence,
+}
+
+impl Granularity {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "token" => Some(Self::Token),
+            "word" => Some(Self::Word),
+            "sentence" => Some(Self::Sentence),
+            _ => None,
+        }
+    }
+}
+
+/// A piece of streamed text and the tokens that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub text: String,
+    /// Indices (into the generated tokens) of every token whose text overlaps `text`. When a
+    /// boundary falls inside a token's text, that token ends one chunk's span and starts the
+    /// next one's.
+    pub tokens: Range<usize>,
+}
+
+/// Buffers token text and releases it at [`Granularity`] boundaries.
+#[derive(Debug, Clone, Default)]
+pub struct TextChunker {
+    granularity: Granularity,
+    buffer: String,
+    /// `(token index, byte length in buffer)` for every buffered token, in order.
+    pieces: VecDeque<(usize, usize)>,
+}
+
+impl TextChunker {
+    pub fn new(granularity: Granularity) -> Self {
+        Self {
+            granularity,
+            ..Self::default()
+        }
+    }
+
+    pub fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// Add the text of token `index` (possibly empty) and return the chunk it completes, if any.
+    pub fn push(&mut self, index: usize, text: &str) -> Option<TextChunk> {
+        self.buffer.push_str(text);
+        self.pieces.push_back((index, text.len()));
+        let cut = match self.granularity {
+            Granularity::Token => self.buffer.len(),
+            Granularity::Word if self.buffer.chars().any(is_cjk) => self.buffer.len(),
+            Granularity::Word => last_word_cut(&self.buffer),
+            Granularity::Sentence => last_sentence_cut(&self.buffer),
+        };
+        self.take(cut)
+    }
+
+    /// Everything still buffered (call at EOS, stop, error or cancellation).
+    pub fn flush(&mut self) -> Option<TextChunk> {
+        let chunk = self.take(self.buffer.len());
+        self.pieces.clear();
+        chunk
+    }
+
+    /// Split off `buffer[..cut]` with the span of the tokens overlapping it.
+    fn take(&mut self, cut: usize) -> Option<TextChunk> {
+        if cut == 0 {
+            return None;
+        }
+        let first = self.pieces.front()?.0;
+        let mut last = first;
+        let mut offset = 0;
+        while let Some(&(index, len)) = self.pieces.front() {
+            if offset >= cut {
+                break;
+            }
+            last = index;
+            if offset + len > cut {
+                // Straddles the cut: keep the remainder for the next chunk.
+                self.pieces[0].1 = offset + len - cut;
+                break;
+            }
+            offset += len;
+            self.pieces.pop_front();
+        }
+        let rest = self.buffer.split_off(cut);
+        Some(TextChunk {
+            text: std::mem::replace(&mut self.buffer, rest),
+            tokens: first..last + 1,
+        })
+    }
+}
+
+/// Byte offset of the last whitespace that follows a non-whitespace character, or 0.
+fn last_word_cut(text: &str) -> usize {
+    let mut cut = 0;
+    let mut prev: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() && prev.is_some_and(|p| !p.is_whitespace()) {
+            cut = i;
+        }
+        prev = Some(c);
+    }
+    cut
+}
+
+/// Byte offset just past the last complete sentence, or 0. Needs the first character of the next
+/// sentence (after `.!?`) to decide, so a sentence is released one word late.
+fn last_sentence_cut(text: &str) -> usize {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut cut = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        i += 1;
+        if c == '\n' || matches!(c, '。' | '！' | '？') {
+            cut = at + c.len_utf8();
+            continue;
+        }
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        // Terminator run plus closing quotes / brackets.
+        while i < chars.len()
+            && matches!(
+                chars[i].1,
+                '.' | '!' | '?' | '"' | '\'' | ')' | ']' | '”' | '’'
+            )
+        {
+            i += 1;
+        }
+        let end = chars.get(i).map_or(text.len(), |&(at, _)| at);
+        let mut j = i;
+        while j < chars.len() && chars[j].1 == ' ' {
+            j += 1;
+        }
+        if j > i && chars.get(j).is_some_and(|&(_, next)| !next.is_lowercase()) {
+            cut = end;
+        }
+    }
+    cut
+}
+
+/// Han, kana, Hangul and CJK punctuation: scripts written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x30FF      // CJK punctuation, hiragana, katakana
+        | 0x3400..=0x4DBF    // CJK extension A
+        | 0x4E00..=0x9FFF    // CJK unified ideographs
+        | 0xAC00..=0xD7AF    // Hangul syllables
+        | 0xF900..=0xFAFF    // CJK compatibility ideographs
+        | 0xFF00..=0xFFEF    // full-width forms
+        | 0x20000..=0x2FFFF) // extensions B+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(granularity: Granularity, pieces: &[&str]) -> Vec<TextChunk> {
+        let mut chunker = TextChunker::new(granularity);
+        let mut out: Vec<TextChunk> = pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| chunker.push(i, p))
+            .collect();
+        out.extend(chunker.flush());
+        out
+    }
+
+    fn texts(chunks: &[TextChunk]) -> Vec<&str> {
+        chunks.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn word_mode_cuts_before_whitespace() {
+        let out = chunks(
+            Granularity::Word,
+            &[
+                "He", "llo", ",", " wor", "ld", "!", " \"", "Don", "'t", "\"",
+            ],
+        );
+        assert_eq!(texts(&out), ["Hello,", " world!", " \"Don't\""]);
+        assert_eq!(out[0].tokens, 0..3);
+        assert_eq!(out[1].tokens, 3..6);
+        assert_eq!(out[2].tokens, 6..10);
+    }
+
+    #[test]
+    fn straddling_token_is_shared_by_both_spans() {
+        let out = chunks(Granularity::Word, &["one two", "three", " four"]);
+        assert_eq!(texts(&out), ["one", " twothree", " four"]);
+        assert_eq!(out[0].tokens, 0..1);
+        assert_eq!(out[1].tokens, 0..2);
+        assert_eq!(out[2].tokens, 2..3);
+    }
+
+    #[test]
+    fn empty_pieces_attach_to_following_text() {
+        let out = chunks(Granularity::Word, &["a", " ", "", "é", " b"]);
+        assert_eq!(texts(&out), ["a", " é", " b"]);
+        assert_eq!(out[1].tokens, 1..4);
+    }
+
+    #[test]
+    fn cjk_falls_back_to_tokens() {
+        let out = chunks(Granularity::Word, &["你好", "世界", "。"]);
+        assert_eq!(texts(&out), ["你好", "世界", "。"]);
+    }
+
+    #[test]
+    fn sentence_mode_needs_a_new_sentence_start() {
+        let out = chunks(
+            Granularity::Sentence,
+            &[
+                "It", " is", " 3.14", ", e.g.", " pi", ".", " Yes", "!\"", " Then", "\n", "ok",
+            ],
+        );
+        assert_eq!(
+            texts(&out),
+            ["It is 3.14, e.g. pi.", " Yes!\"", " Then\n", "ok"]
+        );
+        assert_eq!(out[0].tokens, 0..6);
+    }
+
+    #[test]
+    fn token_mode_passes_through() {
+        let out = chunks(Granularity::Token, &["a", "", "b"]);
+        assert_eq!(texts(&out), ["a", "b"]);
+        assert_eq!(out[1].tokens, 1..3);
+        assert_eq!(Granularity::parse("Word"), Some(Granularity::Word));
+    }
+}
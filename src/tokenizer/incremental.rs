@@ -0,0 +1,47 @@
+//! Streaming detokenization: one token id in, the text it adds out.
+
+use super::Tokenizer;
+use crate::EngineError;
+
+/// Accumulates generated ids and returns, per pushed id, the text it appends.
+///
+/// Decoding ids one at a time loses context (SentencePiece leading spaces, byte-fallback
+/// sequences split across tokens), so every push re-decodes the whole sequence and returns the
+/// new suffix. A decode ending in U+FFFD is an incomplete UTF-8 sequence and is held back until a
+/// later id completes it. Cost is quadratic in length, which is fine for chat-sized replies.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalDecoder {
+    ids: Vec<u32>,
+    emitted: String,
+}
+
+impl IncrementalDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `id` and return the newly completed text (possibly empty).
+    pub fn push(&mut self, tokenizer: &Tokenizer, id: u32) -> Result<String, EngineError> {
+        self.ids.push(id);
+        let full = tokenizer.decode_piece_ids(&self.ids)?;
+        if full.ends_with(char::REPLACEMENT_CHARACTER) {
+            return Ok(String::new());
+        }
+        let chunk = match full.strip_prefix(self.emitted.as_str()) {
+            Some(suffix) => suffix.to_string(),
+            // Rare: the new id re-rendered earlier text; show the new token on its own.
+            None => tokenizer.decode_appended(std::slice::from_ref(&id))?,
+        };
+        self.emitted = full;
+        Ok(chunk)
+    }
+
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    /// Decode of every id up to the last one that completed a character.
+    pub fn text(&self) -> &str {
+        &self.emitted
+    }
+}
@@ -1,4 +1,12 @@
 //! Tokenizer: **SentencePiece** (`.model`) or Hugging Face **`tokenizer.json`** (e.g. Gemma 4).
 pub mod backend;
+pub mod chunker;
+pub mod incremental;
+pub mod normalize;
+pub mod unknown;
 
 pub use backend::Tokenizer;
+pub use chunker::{Granularity, TextChunk, TextChunker};
+pub use incremental::IncrementalDecoder;
+pub use normalize::{ControlCharPolicy, NormalizationForm, TextNormalization};
+pub use unknown::{EncodeResult, UnknownTextError, UnknownTokenPolicy};
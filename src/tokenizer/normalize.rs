@@ -0,0 +1,133 @@
+//! Prompt text preprocessing applied by [`super::Tokenizer::encode`] before tokenization.
+//!
+//! SentencePiece models are usually trained on NFKC text; prompts pasted from editors or the web
+//! carry decomposed accents, full-width Latin, curly quotes and `\r\n`, all of which shift token
+//! ids. NFC/NFKC use the optional `unicode-normalization` dependency (on by default).
+
+use crate::EngineError;
+
+/// Unicode normalization form applied to prompt text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalizationForm {
+    /// Pass text through unchanged.
+    #[default]
+    None,
+    /// Canonical composition (`e` + U+0301 → `é`).
+    Nfc,
+    /// Compatibility composition: NFC plus full-width/ligature folding (`Ａ` → `A`, `ﬁ` → `fi`).
+    Nfkc,
+}
+
+/// What to do with control characters other than `\n` and `\t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlCharPolicy {
+    #[default]
+    Keep,
+    /// Drop C0/C1 controls and other `char::is_control` code points.
+    Strip,
+}
+
+/// Full preprocessing policy; see [`normalize_prompt`] for the order of steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextNormalization {
+    pub form: NormalizationForm,
+    pub control_chars: ControlCharPolicy,
+    /// Rewrite `\r\n` and lone `\r` as `\n`.
+    pub normalize_newlines: bool,
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        Self {
+            form: NormalizationForm::None,
+            control_chars: ControlCharPolicy::Keep,
+            normalize_newlines: true,
+        }
+    }
+}
+
+impl TextNormalization {
+    /// No changes at all (not even newline folding).
+    pub fn passthrough() -> Self {
+        Self {
+            form: NormalizationForm::None,
+            control_chars: ControlCharPolicy::Keep,
+            normalize_newlines: false,
+        }
+    }
+
+    pub fn with_form(form: NormalizationForm) -> Self {
+        Self {
+            form,
+            ..Self::default()
+        }
+    }
+}
+
+/// Newlines first (so `\r` is never seen as a control char), then control stripping, then the
+/// Unicode normalization form.
+pub fn normalize_prompt(text: &str, cfg: &TextNormalization) -> Result<String, EngineError> {
+    let mut out = if cfg.normalize_newlines {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text.to_string()
+    };
+    if cfg.control_chars == ControlCharPolicy::Strip {
+        out.retain(|c| !c.is_control() || c == '\n' || c == '\t');
+    }
+    apply_form(out, cfg.form)
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn apply_form(text: String, form: NormalizationForm) -> Result<String, EngineError> {
+    use unicode_normalization::UnicodeNormalization;
+    Ok(match form {
+        NormalizationForm::None => text,
+        NormalizationForm::Nfc => text.nfc().collect(),
+        NormalizationForm::Nfkc => text.nfkc().collect(),
+    })
+}
+
+#[cfg(not(feature = "unicode-normalization"))]
+fn apply_form(text: String, form: NormalizationForm) -> Result<String, EngineError> {
+    match form {
+        NormalizationForm::None => Ok(text),
+        other => Err(EngineError::Tokenizer(format!(
+            "{other:?} normalization requires the `unicode-normalization` feature"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newlines_and_control_chars() {
+        let cfg = TextNormalization {
+            control_chars: ControlCharPolicy::Strip,
+            ..TextNormalization::default()
+        };
+        let out = normalize_prompt("a\r\nb\rc\u{0007}\td\u{200b}", &cfg).unwrap();
+        // U+200B is a format char, not a control char: kept.
+        assert_eq!(out, "a\nb\nc\td\u{200b}");
+    }
+
+    #[test]
+    fn passthrough_keeps_everything() {
+        let text = "cafe\u{0301}\r\n\u{ff21}\u{0007}";
+        let out = normalize_prompt(text, &TextNormalization::passthrough()).unwrap();
+        assert_eq!(out, text);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn nfc_composes_and_nfkc_folds_width() {
+        let nfc = TextNormalization::with_form(NormalizationForm::Nfc);
+        assert_eq!(normalize_prompt("cafe\u{0301}", &nfc).unwrap(), "caf\u{e9}");
+        // NFC leaves full-width letters alone; NFKC folds them.
+        assert_eq!(normalize_prompt("\u{ff21}", &nfc).unwrap(), "\u{ff21}");
+        let nfkc = TextNormalization::with_form(NormalizationForm::Nfkc);
+        assert_eq!(normalize_prompt("\u{ff21}\u{ff42}", &nfkc).unwrap(), "Ab");
+    }
+}
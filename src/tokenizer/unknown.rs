@@ -0,0 +1,241 @@
+//! What [`super::Tokenizer::encode_checked`] does with text the vocabulary has no piece for.
+//!
+//! Most vocabularies cover every input through `<0x00>`..`<0xFF>` byte tokens, but some older
+//! ones have no byte tokens, and the backends then emit the UNK id, which decodes to nothing (or
+//! to `<unk>`), so the text silently fails to round-trip. [`UnknownTokenPolicy`] makes the choice
+//! explicit, and [`EncodeResult`] reports which characters were affected so callers can warn.
+
+use std::fmt;
+use std::ops::Range;
+
+use thiserror::Error;
+
+/// Characters listed by name in an [`UnknownTextError`] message.
+const ERROR_PREVIEW_CHARS: usize = 8;
+
+/// Handling of characters no vocabulary piece covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownTokenPolicy {
+    /// Encode each such character as its UTF-8 bytes (`<0xNN>` tokens). Only available when the
+    /// vocabulary has all 256 byte tokens; the default then.
+    ByteFallback,
+    /// Keep the backend's UNK id; the default for vocabularies without byte tokens.
+    EmitUnk,
+    /// Fail with an [`UnknownTextError`] naming every uncovered character.
+    Error,
+}
+
+/// Ids from [`super::Tokenizer::encode_checked`] plus the characters that needed special handling.
+/// Offsets are byte offsets into the text after the tokenizer's
+/// [`TextNormalization`](super::TextNormalization), which by default only folds `\r\n` to `\n`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EncodeResult {
+    pub ids: Vec<u32>,
+    /// Offset of each character encoded as byte tokens, by the backend or by
+    /// [`UnknownTokenPolicy::ByteFallback`].
+    pub byte_fallback: Vec<usize>,
+    /// Offset of each character encoded as the UNK id.
+    pub unknown: Vec<usize>,
+}
+
+impl EncodeResult {
+    /// Nothing was lost: no character became UNK (byte-fallback characters round-trip).
+    pub fn is_lossless(&self) -> bool {
+        self.unknown.is_empty()
+    }
+}
+
+/// Text that [`UnknownTokenPolicy::Error`] rejected.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{} character(s) have no token in the vocabulary: {}", .chars.len(), Preview(.chars))]
+pub struct UnknownTextError {
+    /// Each uncovered character with its byte offset, in text order.
+    pub chars: Vec<(usize, char)>,
+}
+
+impl UnknownTextError {
+    pub fn offsets(&self) -> Vec<usize> {
+        self.chars.iter().map(|&(offset, _)| offset).collect()
+    }
+}
+
+struct Preview<'a>(&'a [(usize, char)]);
+
+impl fmt::Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (offset, c)) in self.0.iter().take(ERROR_PREVIEW_CHARS).enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{sep}{c:?} at byte {offset}")?;
+        }
+        if self.0.len() > ERROR_PREVIEW_CHARS {
+            write!(f, ", ... ({} more)", self.0.len() - ERROR_PREVIEW_CHARS)?;
+        }
+        Ok(())
+    }
+}
+
+/// Ids of the `<0x00>`..`<0xFF>` tokens, indexed by byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ByteTokens(Box<[u32; 256]>);
+
+impl ByteTokens {
+    /// The byte tokens, if `id_of` finds every one of them.
+    pub(crate) fn find(id_of: impl Fn(&str) -> Option<u32>) -> Option<Self> {
+        let mut ids = Box::new([0u32; 256]);
+        for (byte, id) in ids.iter_mut().enumerate() {
+            *id = id_of(&byte_piece(byte as u8))?;
+        }
+        Some(Self(ids))
+    }
+
+    pub(crate) fn id(&self, byte: u8) -> u32 {
+        self.0[byte as usize]
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        self.0.contains(&id)
+    }
+}
+
+/// The `<0xNN>` spelling byte tokens use in SentencePiece and Hugging Face vocabularies.
+pub(crate) fn byte_piece(byte: u8) -> String {
+    format!("<0x{byte:02X}>")
+}
+
+/// Apply `policy` to a backend encoding of `text`: `pieces` are ids with the byte span of `text`
+/// each came from, in order.
+pub(crate) fn apply_policy(
+    text: &str,
+    pieces: &[(u32, Range<usize>)],
+    unk: Option<u32>,
+    bytes: Option<&ByteTokens>,
+    policy: UnknownTokenPolicy,
+) -> Result<EncodeResult, UnknownTextError> {
+    let mut out = EncodeResult {
+        ids: Vec::with_capacity(pieces.len()),
+        ..EncodeResult::default()
+    };
+    let mut rejected = Vec::new();
+    for (id, span) in pieces {
+        let span = span.start.min(text.len())..span.end.min(text.len());
+        if Some(*id) == unk {
+            let chars = covered_chars(text, span.clone());
+            match (policy, bytes) {
+                (UnknownTokenPolicy::ByteFallback, Some(bytes)) if !span.is_empty() => {
+                    out.ids
+                        .extend(text.as_bytes()[span].iter().map(|&b| bytes.id(b)));
+                    push_offsets(&mut out.byte_fallback, &chars);
+                }
+                (UnknownTokenPolicy::Error, _) => rejected.extend(chars),
+                _ => {
+                    out.ids.push(*id);
+                    push_offsets(&mut out.unknown, &chars);
+                }
+            }
+        } else {
+            if bytes.is_some_and(|b| b.contains(*id)) {
+                push_offsets(&mut out.byte_fallback, &covered_chars(text, span));
+            }
+            out.ids.push(*id);
+        }
+    }
+    if rejected.is_empty() {
+        Ok(out)
+    } else {
+        Err(UnknownTextError { chars: rejected })
+    }
+}
+
+/// Non-whitespace characters of `text` overlapping `span` (SentencePiece spans may include the
+/// word-boundary space, which is always covered).
+fn covered_chars(text: &str, span: Range<usize>) -> Vec<(usize, char)> {
+    text.char_indices()
+        .filter(|&(i, c)| i + c.len_utf8() > span.start && i < span.end && !c.is_whitespace())
+        .collect()
+}
+
+/// Append the offsets of `chars`, skipping one already recorded (the bytes of one character can
+/// arrive as several pieces with the same span).
+fn push_offsets(offsets: &mut Vec<usize>, chars: &[(usize, char)]) {
+    for &(offset, _) in chars {
+        if offsets.last().is_none_or(|&last| last < offset) {
+            offsets.push(offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNK: u32 = 0;
+
+    /// Byte `b` is id `1000 + b`.
+    fn bytes() -> ByteTokens {
+        ByteTokens::find(|piece| {
+            let hex = piece.strip_prefix("<0x")?.strip_suffix('>')?;
+            Some(1000 + u32::from_str_radix(hex, 16).ok()?)
+        })
+        .unwrap()
+    }
+
+    /// `a`, UNK for `é` (bytes 1..3), `b`.
+    fn pieces() -> Vec<(u32, Range<usize>)> {
+        vec![(10, 0..1), (UNK, 1..3), (11, 3..4)]
+    }
+
+    #[test]
+    fn each_policy_handles_an_uncovered_character() {
+        let text = "aéb";
+        let fallback = apply_policy(
+            text,
+            &pieces(),
+            Some(UNK),
+            Some(&bytes()),
+            UnknownTokenPolicy::ByteFallback,
+        )
+        .unwrap();
+        assert_eq!(fallback.ids, [10, 1000 + 0xC3, 1000 + 0xA9, 11]);
+        assert_eq!(
+            (fallback.byte_fallback, fallback.unknown),
+            (vec![1], vec![])
+        );
+
+        // Without byte tokens there is nothing to fall back to.
+        let emitted = apply_policy(
+            text,
+            &pieces(),
+            Some(UNK),
+            None,
+            UnknownTokenPolicy::ByteFallback,
+        )
+        .unwrap();
+        assert_eq!(emitted.ids, [10, UNK, 11]);
+        assert_eq!(emitted.unknown, [1]);
+        assert!(!emitted.is_lossless());
+
+        let err =
+            apply_policy(text, &pieces(), Some(UNK), None, UnknownTokenPolicy::Error).unwrap_err();
+        assert_eq!(err.chars, [(1, 'é')]);
+        assert_eq!(
+            err.to_string(),
+            "1 character(s) have no token in the vocabulary: 'é' at byte 1"
+        );
+    }
+
+    #[test]
+    fn backend_byte_pieces_count_once_per_character() {
+        // "é" as two byte pieces sharing the character's span.
+        let pieces = [(1000 + 0xC3, 0..2), (1000 + 0xA9, 0..2), (10, 2..3)];
+        let result = apply_policy(
+            "éa",
+            &pieces,
+            Some(UNK),
+            Some(&bytes()),
+            UnknownTokenPolicy::Error,
+        )
+        .unwrap();
+        assert_eq!(result.byte_fallback, [0]);
+        assert!(result.is_lossless());
+    }
+}
@@ -0,0 +1,150 @@
+//! Browser API (`wasm` feature, `wasm32-unknown-unknown` only): load a GGUF model and its
+//! `tokenizer.json` from bytes and generate text, through [wasm-bindgen] bindings.
+//!
+//! ```js
+//! import init, { WasmModel, GenerateOptions } from "./pkg/inference_engine_rust.js";
+//! await init();
+//! const model = WasmModel.load(
+//!   new Uint8Array(await (await fetch("model.gguf")).arrayBuffer()),
+//!   new Uint8Array(await (await fetch("tokenizer.json")).arrayBuffer()),
+//! );
+//! const options = new GenerateOptions();
+//! options.max_new_tokens = 32;
+//! model.generate_streaming("Once upon a time", options, (text) => { out.textContent += text; });
+//! ```
+//!
+//! Everything runs on the calling thread, so call it from a Web Worker to keep the page
+//! responsive. Every call starts from an empty context.
+//!
+//! [wasm-bindgen]: https://rustwasm.github.io/docs/wasm-bindgen/
+
+use std::ops::ControlFlow;
+
+use wasm_bindgen::prelude::*;
+
+use crate::EngineError;
+use crate::engine::generation::{GenerationConfig, generate};
+use crate::engine::session::InferenceSession;
+use crate::loaded_model::LoadedModel;
+use crate::tokenizer::{Granularity, Tokenizer};
+
+fn js_error(e: EngineError) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// Sampling settings for [`WasmModel::generate`]; `new GenerateOptions()` starts from
+/// [`GenerationConfig::default`].
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct GenerateOptions {
+    pub max_new_tokens: usize,
+    /// `0` picks the most likely token every step.
+    pub temperature: f32,
+    pub min_p: f32,
+    /// A JS number, so 32 bits of the engine's 64-bit seed.
+    pub seed: u32,
+}
+
+#[wasm_bindgen]
+impl GenerateOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        let defaults = GenerationConfig::default();
+        Self {
+            max_new_tokens: defaults.max_new_tokens,
+            temperature: defaults.temperature,
+            min_p: defaults.min_p,
+            seed: defaults.seed as u32,
+        }
+    }
+}
+
+impl GenerateOptions {
+    fn config(&self) -> GenerationConfig {
+        GenerationConfig {
+            max_new_tokens: self.max_new_tokens,
+            temperature: self.temperature,
+            min_p: self.min_p,
+            seed: u64::from(self.seed),
+            ..GenerationConfig::default()
+        }
+    }
+}
+
+/// A model and its tokenizer, loaded from bytes.
+#[wasm_bindgen]
+pub struct WasmModel {
+    model: LoadedModel,
+    tokenizer: Tokenizer,
+}
+
+#[wasm_bindgen]
+impl WasmModel {
+    /// A GGUF file and the Hugging Face `tokenizer.json` that goes with it, e.g. from
+    /// `fetch(...).arrayBuffer()` or a file input. SentencePiece `tokenizer.model` files are
+    /// not supported in the browser.
+    pub fn load(model: &[u8], tokenizer_json: &[u8]) -> Result<WasmModel, JsError> {
+        Ok(Self {
+            model: LoadedModel::from_bytes(model).map_err(js_error)?,
+            tokenizer: Tokenizer::from_json_bytes(tokenizer_json).map_err(js_error)?,
+        })
+    }
+
+    /// The text generated after `prompt`.
+    pub fn generate(&mut self, prompt: &str, options: &GenerateOptions) -> Result<String, JsError> {
+        let mut session = InferenceSession::new(&self.model).map_err(js_error)?;
+        generate(&mut session, &mut self.tokenizer, prompt, &options.config())
+            .map(|out| out.text)
+            .map_err(js_error)
+    }
+
+    /// [`Self::generate`], also passing each token's text to `on_text` as soon as it decodes.
+    /// `on_text` returning `false` stops generation; an exception it throws stops generation and
+    /// is rethrown. Returns the text generated up to then.
+    pub fn generate_streaming(
+        &mut self,
+        prompt: &str,
+        options: &GenerateOptions,
+        on_text: &js_sys::Function,
+    ) -> Result<String, JsValue> {
+        let prompt_ids = self
+            .tokenizer
+            .encode_with_prompt_config(prompt, self.model.tokenizer_prompt())
+            .map_err(js_error)?;
+        let mut session = InferenceSession::new(&self.model).map_err(js_error)?;
+        let mut text = String::new();
+        let mut thrown = None;
+        session
+            .stream_text(
+                &self.tokenizer,
+                &prompt_ids,
+                &options.config(),
+                Granularity::Token,
+                |chunk| {
+                    text.push_str(&chunk.text);
+                    if thrown.is_some() {
+                        return ControlFlow::Break(());
+                    }
+                    match on_text.call1(&JsValue::NULL, &JsValue::from_str(&chunk.text)) {
+                        Ok(ret) if ret.as_bool() == Some(false) => ControlFlow::Break(()),
+                        Ok(_) => ControlFlow::Continue(()),
+                        Err(e) => {
+                            thrown = Some(e);
+                            ControlFlow::Break(())
+                        }
+                    }
+                },
+            )
+            .map_err(js_error)?;
+        match thrown {
+            Some(e) => Err(e),
+            None => Ok(text),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Additive attention masks threaded through the attention layers and the session.
+
+mod common;
+
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::state::ForwardState;
+use inference_engine_rust::layers::attention::{
+    causal_mask, decode_attention_layer, kv_caches_for_config, prefill_attention_layer,
+};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TINY_HIDDEN, tiny_llama};
+
+/// `[rows, rows]` prefix-LM mask: the first `prefix` rows see each other, later rows are causal.
+fn prefix_lm_mask(rows: usize, prefix: usize) -> Vec<f32> {
+    (0..rows)
+        .flat_map(|i| {
+            let visible = if i < prefix { prefix } else { i + 1 };
+            (0..rows).map(move |j| if j < visible { 0.0 } else { f32::NEG_INFINITY })
+        })
+        .collect()
+}
+
+#[test]
+fn prefix_lm_mask_lets_the_prefix_attend_forward_through_the_layer() {
+    let path = tiny_llama().write("attention_mask_layer");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let config = model.config();
+    let weights = model.weights().unwrap();
+    let layer = &weights.layers[0];
+    let (dims, attn) = (&config.layer_dims[0], &config.layer_attention[0]);
+
+    let seq_len = 4;
+    let hidden: Vec<f32> = (0..seq_len * TINY_HIDDEN)
+        .map(|i| (i as f32 * 0.37).sin())
+        .collect();
+    let input = ForwardState::from_flat(hidden, seq_len, TINY_HIDDEN).unwrap();
+    let run = |mask: Option<&[f32]>| {
+        let mut caches = kv_caches_for_config(config);
+        let out = prefill_attention_layer(
+            &input,
+            config,
+            dims,
+            attn,
+            layer,
+            &mut caches,
+            0,
+            None,
+            mask,
+        )
+        .expect("prefill attention");
+        (out, caches)
+    };
+
+    let (causal, causal_caches) = run(None);
+    let explicit: Vec<f32> = (0..seq_len)
+        .flat_map(|i| causal_mask(i, seq_len, None))
+        .collect();
+    assert_eq!(
+        run(Some(&explicit)).0,
+        causal,
+        "causal is the causal_mask special case"
+    );
+
+    // Prefix of 2: row 0 now also sees key 1; row 1 already saw 0..2, and rows 2.. are causal.
+    let (prefix, prefix_caches) = run(Some(&prefix_lm_mask(seq_len, 2)));
+    let row = |v: &[f32], i: usize| v[i * TINY_HIDDEN..(i + 1) * TINY_HIDDEN].to_vec();
+    assert_ne!(
+        row(&prefix, 0),
+        row(&causal, 0),
+        "row 0 must attend to key 1"
+    );
+    for i in 1..seq_len {
+        assert_eq!(row(&prefix, i), row(&causal, i), "row {i}");
+    }
+    // The mask only changes what is read; the appended K/V rows are the same.
+    assert_eq!(prefix_caches[0].snapshot(), causal_caches[0].snapshot());
+
+    // Decode: one row over the 5 keys including the new token.
+    let step = ForwardState::from_flat(vec![0.5; TINY_HIDDEN], 1, TINY_HIDDEN).unwrap();
+    let decode = |mask: Option<&[f32]>| {
+        let mut caches = run(None).1;
+        decode_attention_layer(&step, config, dims, attn, layer, &mut caches, 0, None, mask)
+            .expect("decode attention")
+    };
+    assert_eq!(decode(Some(&[0.0; 5])), decode(None));
+    let blocked = [0.0, f32::NEG_INFINITY, 0.0, 0.0, 0.0];
+    assert_ne!(decode(Some(&blocked)), decode(None));
+
+    let mut caches = kv_caches_for_config(config);
+    let err = prefill_attention_layer(
+        &input,
+        config,
+        dims,
+        attn,
+        layer,
+        &mut caches,
+        0,
+        None,
+        Some(&[0.0; 3]),
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("expected 4 rows x 4 keys"), "{err}");
+}
+
+#[test]
+fn session_prefill_masked_takes_a_prefix_lm_mask() {
+    let path = tiny_llama().write("attention_mask_session");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let prompt = [1u32, 5, 9, 13];
+
+    let mut plain = InferenceSession::new(&model).expect("session");
+    let state = plain.prefill(&prompt).expect("prefill");
+    let plain_logits = plain.logits_last_token(&state).unwrap();
+
+    let explicit: Vec<f32> = (0..prompt.len())
+        .flat_map(|i| causal_mask(i, prompt.len(), None))
+        .collect();
+    let mut causal = InferenceSession::new(&model).expect("session");
+    let state = causal.prefill_masked(&prompt, &explicit).expect("prefill");
+    assert_eq!(causal.logits_last_token(&state).unwrap(), plain_logits);
+
+    // The bidirectional prefix changes what later layers see, and so the last token's logits.
+    let mut prefix_lm = InferenceSession::new(&model).expect("session");
+    let state = prefix_lm
+        .prefill_masked(&prompt, &prefix_lm_mask(prompt.len(), 3))
+        .expect("prefill");
+    assert_ne!(prefix_lm.logits_last_token(&state).unwrap(), plain_logits);
+    assert_eq!(prefix_lm.position(), prompt.len());
+    assert_eq!(prefix_lm.budget().used(), prompt.len());
+
+    // Decoding after a masked prefill takes one mask row over the cache plus the new token.
+    prefix_lm
+        .decode_token_masked(7, &[0.0; 5])
+        .expect("masked decode");
+    assert!(prefix_lm.decode_token_masked(7, &[0.0; 5]).is_err());
+    assert_eq!(prefix_lm.position(), prompt.len() + 1);
+}
@@ -0,0 +1,41 @@
+//! `Tokenizer::encode_with_prompt_config` never produces a double BOS when the prompt already
+//! starts with one, using the tiny word-level `tokenizer.json` (`<s>` is id 1).
+
+mod common;
+
+use inference_engine_rust::model_config::TokenizerPromptConfig;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::write_tiny_tokenizer;
+
+fn cfg() -> TokenizerPromptConfig {
+    TokenizerPromptConfig {
+        add_bos_token: true,
+        ..TokenizerPromptConfig::default()
+    }
+}
+
+#[test]
+fn leading_bos_is_not_duplicated() {
+    let mut tok = Tokenizer::load_from_file(write_tiny_tokenizer("bos_dedupe")).unwrap();
+    let plain = tok.encode_with_prompt_config("w3 w4", &cfg()).unwrap();
+    assert_eq!(plain, [1, 3, 4]);
+
+    // BOS encoded as its own token, and BOS glued to the first word (split as plain text).
+    for prompt in ["<s> w3 w4", "<s>w3 w4"] {
+        let ids = tok.encode_with_prompt_config(prompt, &cfg()).unwrap();
+        assert_eq!(ids, plain, "{prompt:?}");
+        assert_eq!(ids.iter().filter(|&&id| id == 1).count(), 1);
+    }
+}
+
+#[test]
+fn dedupe_can_be_turned_off() {
+    let mut tok = Tokenizer::load_from_file(write_tiny_tokenizer("bos_dedupe_off")).unwrap();
+    let cfg = TokenizerPromptConfig {
+        dedupe_bos: false,
+        ..cfg()
+    };
+    let ids = tok.encode_with_prompt_config("<s> w3", &cfg).unwrap();
+    assert_eq!(ids, [1, 1, 3]);
+}
@@ -0,0 +1,439 @@
+//! Tiny GGUF v3 writer for tests that must not depend on multi-GB model files.
+//!
+//! Builds a file with arbitrary metadata and raw tensor blobs (already in ggml block layout),
+//! aligned to the default 32-byte `general.alignment`.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::ops::quant::quant_k_handler::{
+    Q8_0_BLOCK_ELEMENTS, Q8_0_BLOCK_SIZE, quantize_q8_0_block,
+};
+
+pub const GGML_TYPE_F32: u32 = 0;
+pub const GGML_TYPE_Q8_0: u32 = 8;
+pub const GGML_TYPE_Q4_K: u32 = 12;
+pub const GGML_TYPE_Q6_K: u32 = 14;
+pub const GGML_TYPE_BF16: u32 = 30;
+
+const ALIGNMENT: usize = 32;
+
+#[derive(Clone)]
+pub struct FixtureTensor {
+    pub name: String,
+    pub dims: Vec<u64>,
+    pub type_id: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+pub struct GgufFixture {
+    kv: Vec<(String, Data)>,
+    tensors: Vec<FixtureTensor>,
+}
+
+impl GgufFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn kv(mut self, key: &str, value: Data) -> Self {
+        self.kv.push((key.to_string(), value));
+        self
+    }
+
+    /// Drop every entry for `key` (e.g. to test metadata fallbacks on [`tiny_llama`]).
+    pub fn without_kv(mut self, key: &str) -> Self {
+        self.kv.retain(|(k, _)| k != key);
+        self
+    }
+
+    pub fn tensor(mut self, name: &str, dims: &[u64], type_id: u32, data: Vec<u8>) -> Self {
+        self.tensors.push(FixtureTensor {
+            name: name.to_string(),
+            dims: dims.to_vec(),
+            type_id,
+            data,
+        });
+        self
+    }
+
+    /// Drop the tensor `name` (e.g. to replace one of [`tiny_llama`]'s weights).
+    pub fn without_tensor(mut self, name: &str) -> Self {
+        self.tensors.retain(|t| t.name != name);
+        self
+    }
+
+    pub fn f32_tensor(self, name: &str, dims: &[u64], values: &[f32]) -> Self {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.tensor(name, dims, GGML_TYPE_F32, data)
+    }
+
+    /// Requantize every 2-D F32 tensor (the matrices; norms are 1-D) to Q8_0.
+    pub fn q8_0_matrices(mut self) -> Self {
+        for t in self.tensors.iter_mut() {
+            if t.type_id != GGML_TYPE_F32 || t.dims.len() != 2 {
+                continue;
+            }
+            let values: Vec<f32> = t
+                .data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            let mut data = vec![0u8; values.len() / Q8_0_BLOCK_ELEMENTS * Q8_0_BLOCK_SIZE];
+            for (src, dst) in values
+                .chunks_exact(Q8_0_BLOCK_ELEMENTS)
+                .zip(data.chunks_exact_mut(Q8_0_BLOCK_SIZE))
+            {
+                quantize_q8_0_block(src, dst).unwrap();
+            }
+            t.type_id = GGML_TYPE_Q8_0;
+            t.data = data;
+        }
+        self
+    }
+
+    /// Split into `count` shards as llama.cpp's `gguf-split` does: the first keeps the metadata,
+    /// each shard gets the `split.*` keys and a contiguous share of the tensors.
+    pub fn split(&self, count: usize) -> Vec<GgufFixture> {
+        let per_shard = self.tensors.len().div_ceil(count);
+        (0..count)
+            .map(|no| {
+                let kv = if no == 0 { self.kv.clone() } else { Vec::new() };
+                GgufFixture {
+                    kv,
+                    tensors: self
+                        .tensors
+                        .iter()
+                        .skip(no * per_shard)
+                        .take(per_shard)
+                        .cloned()
+                        .collect(),
+                }
+                .kv("split.no", Data::Uint16(no as u16))
+                .kv("split.count", Data::Uint16(count as u16))
+                .kv(
+                    "split.tensors.count",
+                    Data::Int32(self.tensors.len() as i32),
+                )
+            })
+            .collect()
+    }
+
+    /// Serialize to GGUF bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"GGUF");
+        out.extend_from_slice(&3u32.to_le_bytes());
+        out.extend_from_slice(&(self.tensors.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(self.kv.len() as u64).to_le_bytes());
+        for (key, value) in &self.kv {
+            write_string(&mut out, key);
+            out.extend_from_slice(&value_type_code(value).to_le_bytes());
+            write_value(&mut out, value);
+        }
+
+        let mut offsets = Vec::with_capacity(self.tensors.len());
+        let mut next = 0usize;
+        for t in &self.tensors {
+            offsets.push(next);
+            next = (next + t.data.len()).next_multiple_of(ALIGNMENT);
+        }
+        for (t, &offset) in self.tensors.iter().zip(&offsets) {
+            write_string(&mut out, &t.name);
+            out.extend_from_slice(&(t.dims.len() as u32).to_le_bytes());
+            for &d in &t.dims {
+                out.extend_from_slice(&d.to_le_bytes());
+            }
+            out.extend_from_slice(&t.type_id.to_le_bytes());
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+
+        out.resize(out.len().next_multiple_of(ALIGNMENT), 0);
+        let base = out.len();
+        for (t, &offset) in self.tensors.iter().zip(&offsets) {
+            out.resize(base + offset, 0);
+            out.extend_from_slice(&t.data);
+        }
+        out
+    }
+
+    /// Write to a unique file under the system temp dir; the file is removed when the returned
+    /// path is dropped.
+    pub fn write(&self, stem: &str) -> TempPath {
+        let path = TempPath::new(stem, "gguf");
+        std::fs::write(&path, self.to_bytes()).expect("write GGUF fixture");
+        path
+    }
+}
+
+/// A file path that removes the file when dropped, so a test that panics part-way still cleans
+/// up. Derefs to [`Path`]; [`TempPath::from`] adopts a path something else created (a sidecar,
+/// a transcript).
+#[derive(Debug)]
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// `inference_engine_rust_{stem}_{pid}.{ext}` under the system temp dir. Nothing is created.
+    pub fn new(stem: &str, ext: &str) -> Self {
+        Self(std::env::temp_dir().join(format!(
+            "inference_engine_rust_{stem}_{}.{ext}",
+            std::process::id()
+        )))
+    }
+}
+
+impl From<PathBuf> for TempPath {
+    fn from(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Shape of the synthetic Llama-style model produced by [`tiny_llama`].
+pub const TINY_VOCAB: usize = 32;
+pub const TINY_HIDDEN: usize = 16;
+pub const TINY_LAYERS: usize = 2;
+pub const TINY_HEADS: usize = 4;
+pub const TINY_KV_HEADS: usize = 2;
+pub const TINY_FFN: usize = 32;
+pub const TINY_CONTEXT: usize = 64;
+
+/// Word-level vocabulary of the tiny model (ids 0..3 are `<unk>`, `<s>`, `</s>`).
+pub fn tiny_vocab() -> Vec<String> {
+    vocab_words(TINY_VOCAB)
+}
+
+/// `n` tokens: `<unk>`, `<s>`, `</s>`, then `w3`, `w4`, ...
+fn vocab_words(n: usize) -> Vec<String> {
+    let mut v: Vec<String> = ["<unk>", "<s>", "</s>"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    for i in v.len()..n {
+        v.push(format!("w{i}"));
+    }
+    v
+}
+
+/// A complete, runnable F32 Llama-architecture GGUF (2 layers, GQA 4/2, untied LM head).
+///
+/// Weights come from a fixed LCG so every test sees the same logits.
+pub fn tiny_llama() -> GgufFixture {
+    tiny_llama_perturbed(0.0)
+}
+
+/// [`tiny_llama`] with independent uniform noise of amplitude `noise` added to every matrix
+/// weight (norms untouched), standing in for a coarser quantization of the same model.
+pub fn tiny_llama_perturbed(noise: f32) -> GgufFixture {
+    build_tiny_llama(noise, None, TINY_VOCAB, TINY_VOCAB)
+}
+
+/// [`tiny_llama`] with every matrix stored as Q8_0 (norms stay F32).
+pub fn tiny_llama_q8() -> GgufFixture {
+    tiny_llama().q8_0_matrices()
+}
+
+/// [`tiny_llama`] whose tokenizer defines `tokens` ids while the embedding and LM head have
+/// `rows >= tokens` rows, like checkpoints that pad the vocab to a round size.
+pub fn tiny_llama_padded_vocab(tokens: usize, rows: usize) -> GgufFixture {
+    build_tiny_llama(0.0, None, tokens, rows)
+}
+
+/// [`tiny_llama`] with `llama.attention.key_length = head_dim`, so `n_heads * head_dim` need not
+/// equal [`TINY_HIDDEN`]; Q/K/V/O are shaped from `head_dim`.
+pub fn tiny_llama_with_head_dim(head_dim: usize) -> GgufFixture {
+    build_tiny_llama(0.0, Some(head_dim), TINY_VOCAB, TINY_VOCAB)
+        .kv("llama.attention.key_length", Data::Uint32(head_dim as u32))
+        .kv(
+            "llama.attention.value_length",
+            Data::Uint32(head_dim as u32),
+        )
+}
+
+fn build_tiny_llama(
+    noise: f32,
+    head_dim: Option<usize>,
+    n_tokens: usize,
+    embedding_rows: usize,
+) -> GgufFixture {
+    let head_dim = head_dim.unwrap_or(TINY_HIDDEN / TINY_HEADS);
+    let q_dim = TINY_HEADS * head_dim;
+    let kv_dim = TINY_KV_HEADS * head_dim;
+    let mut rng = Lcg(0x5eed);
+    let mut noise_rng = Lcg(0xd1ff);
+    let tokens = vocab_words(n_tokens)
+        .into_iter()
+        .map(Data::String)
+        .collect();
+
+    let mut f = GgufFixture::new()
+        .kv("general.architecture", Data::String("llama".into()))
+        .kv("general.name", Data::String("tiny-llama-fixture".into()))
+        .kv("llama.context_length", Data::Uint32(TINY_CONTEXT as u32))
+        .kv("llama.embedding_length", Data::Uint32(TINY_HIDDEN as u32))
+        .kv("llama.block_count", Data::Uint32(TINY_LAYERS as u32))
+        .kv("llama.feed_forward_length", Data::Uint32(TINY_FFN as u32))
+        .kv(
+            "llama.attention.head_count",
+            Data::Uint32(TINY_HEADS as u32),
+        )
+        .kv(
+            "llama.attention.head_count_kv",
+            Data::Uint32(TINY_KV_HEADS as u32),
+        )
+        .kv(
+            "llama.attention.layer_norm_rms_epsilon",
+            Data::Float32(1e-5),
+        )
+        .kv("llama.rope.theta", Data::Float32(10000.0))
+        .kv("tokenizer.ggml.model", Data::String("llama".into()))
+        .kv("tokenizer.ggml.tokens", Data::Array(tokens))
+        .kv("tokenizer.ggml.bos_token_id", Data::Uint32(1))
+        .kv("tokenizer.ggml.eos_token_id", Data::Uint32(2));
+
+    let mut matrix = |f: GgufFixture, name: &str, k: usize, n: usize| {
+        let values: Vec<f32> = (0..k * n)
+            .map(|_| rng.next_f32() * 0.5 + noise_rng.next_f32() * noise)
+            .collect();
+        f.f32_tensor(name, &[k as u64, n as u64], &values)
+    };
+    f = matrix(f, "token_embd.weight", TINY_HIDDEN, embedding_rows);
+    for l in 0..TINY_LAYERS {
+        let p = format!("blk.{l}.");
+        f = matrix(f, &format!("{p}attn_q.weight"), TINY_HIDDEN, q_dim);
+        f = matrix(f, &format!("{p}attn_k.weight"), TINY_HIDDEN, kv_dim);
+        f = matrix(f, &format!("{p}attn_v.weight"), TINY_HIDDEN, kv_dim);
+        f = matrix(f, &format!("{p}attn_output.weight"), q_dim, TINY_HIDDEN);
+        f = matrix(f, &format!("{p}ffn_gate.weight"), TINY_HIDDEN, TINY_FFN);
+        f = matrix(f, &format!("{p}ffn_up.weight"), TINY_HIDDEN, TINY_FFN);
+        f = matrix(f, &format!("{p}ffn_down.weight"), TINY_FFN, TINY_HIDDEN);
+    }
+    f = matrix(f, "output.weight", TINY_HIDDEN, embedding_rows);
+
+    let ones = vec![1.0f32; TINY_HIDDEN];
+    for l in 0..TINY_LAYERS {
+        f = f
+            .f32_tensor(
+                &format!("blk.{l}.attn_norm.weight"),
+                &[TINY_HIDDEN as u64],
+                &ones,
+            )
+            .f32_tensor(
+                &format!("blk.{l}.ffn_norm.weight"),
+                &[TINY_HIDDEN as u64],
+                &ones,
+            );
+    }
+    f.f32_tensor("output_norm.weight", &[TINY_HIDDEN as u64], &ones)
+}
+
+/// Word-level `tokenizer.json` over [`tiny_vocab`] (whitespace split, `<unk>` fallback), so
+/// `"w5 w6"` encodes to `[5, 6]` and decodes back to the same string.
+pub fn write_tiny_tokenizer(stem: &str) -> TempPath {
+    let vocab: serde_json::Map<String, serde_json::Value> = tiny_vocab()
+        .into_iter()
+        .enumerate()
+        .map(|(id, tok)| (tok, id.into()))
+        .collect();
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "WhitespaceSplit" },
+        "post_processor": null,
+        "decoder": null,
+        "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "<unk>" }
+    });
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json.to_string()).expect("write tokenizer.json fixture");
+    path
+}
+
+/// Bytes of tensor data in [`tiny_llama`] (all F32).
+pub fn tiny_llama_tensor_bytes() -> usize {
+    tiny_llama().tensors.iter().map(|t| t.data.len()).sum()
+}
+
+struct Lcg(u64);
+
+impl Lcg {
+    /// Uniform in `[-1, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn value_type_code(value: &Data) -> u32 {
+    match value {
+        Data::Uint8(_) => 0,
+        Data::Int8(_) => 1,
+        Data::Uint16(_) => 2,
+        Data::Int16(_) => 3,
+        Data::Uint32(_) => 4,
+        Data::Int32(_) => 5,
+        Data::Float32(_) => 6,
+        Data::Bool(_) => 7,
+        Data::String(_) => 8,
+        Data::Array(_) => 9,
+        Data::Uint64(_) => 10,
+        Data::Int64(_) => 11,
+        Data::Float64(_) => 12,
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Data) {
+    match value {
+        Data::Uint8(v) => out.push(*v),
+        Data::Int8(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Uint16(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Int16(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Uint32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Int32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Float32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Bool(v) => out.push(u8::from(*v)),
+        Data::String(s) => write_string(out, s),
+        Data::Array(items) => {
+            let elem_type = items.first().map(value_type_code).unwrap_or(4);
+            out.extend_from_slice(&elem_type.to_le_bytes());
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        Data::Uint64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Int64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Data::Float64(v) => out.extend_from_slice(&v.to_le_bytes()),
+    }
+}
@@ -9,10 +9,13 @@
 //! `token_id * hidden_dim + h` buffer layout.
 #![allow(dead_code)]
 
+pub mod gguf_fixture;
 pub mod llama_logits_helpers;
 
 use std::path::{Path, PathBuf};
 
+use inference_engine_rust::core::tensor::{Tensor, TensorType};
+
 /// Relative to workspace root (same as `CARGO_MANIFEST_DIR` for integration tests).
 pub const REFERENCE_MODEL_REL_PATH: &str = "model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf";
 
@@ -73,3 +76,9 @@ pub fn assert_close(a: f32, b: f32, eps: f32) {
     let d = (a - b).abs();
     assert!(d <= eps, "expected |{a} - {b}| <= {eps}, got diff {d}");
 }
+
+/// An F32 tensor of `values` with ggml `dims`.
+pub fn f32_tensor(values: &[f32], dims: Vec<usize>) -> Tensor {
+    let bytes = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    Tensor::from_bytes(TensorType::F32, bytes, dims).unwrap()
+}
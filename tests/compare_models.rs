@@ -0,0 +1,50 @@
+//! Dual-model comparison on the synthetic model and perturbed copies of it.
+
+mod common;
+
+use inference_engine_rust::compare::{compare_model_files, compare_models};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{tiny_llama, tiny_llama_perturbed};
+
+fn prompts() -> Vec<Vec<u32>> {
+    vec![vec![1, 5, 9, 14], vec![1, 20, 3], vec![1, 30, 12, 7, 7]]
+}
+
+#[test]
+fn identical_models_agree_exactly() {
+    let path = tiny_llama().write("compare_identical");
+    let a = LoadedModel::load(&path).unwrap();
+    let b = LoadedModel::load(&path).unwrap();
+    let report = compare_models(&a, &b, &prompts(), 6).unwrap();
+    assert_eq!(report.token_agreement, 1.0);
+    assert!(report.mean_first_token_kl < 1e-9, "{report}");
+    assert!(report.perplexity_delta.unwrap().abs() < 1e-9);
+}
+
+#[test]
+fn agreement_metrics_track_perturbation_size() {
+    let base = tiny_llama().write("compare_base");
+    let small = tiny_llama_perturbed(0.01).write("compare_small");
+    let large = tiny_llama_perturbed(0.5).write("compare_large");
+
+    let near = compare_model_files(&base, &small, &prompts(), 6).unwrap();
+    let far = compare_model_files(&base, &large, &prompts(), 6).unwrap();
+
+    assert!(near.mean_first_token_kl > 0.0);
+    assert!(
+        far.mean_first_token_kl > 10.0 * near.mean_first_token_kl,
+        "near:\n{near}\nfar:\n{far}"
+    );
+    assert!(near.token_agreement >= far.token_agreement);
+    assert!(far.token_agreement < 1.0, "far:\n{far}");
+    assert!(
+        far.perplexity_delta.unwrap().abs() > near.perplexity_delta.unwrap().abs(),
+        "near:\n{near}\nfar:\n{far}"
+    );
+    assert!(far.a.load_ms > 0.0 && far.b.decode_tokens_per_sec > 0.0);
+
+    let json: serde_json::Value = serde_json::from_str(&far.to_json().unwrap()).unwrap();
+    assert_eq!(json["prompts"].as_array().unwrap().len(), prompts().len());
+    assert!(json["token_agreement"].is_number());
+}
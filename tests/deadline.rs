@@ -0,0 +1,156 @@
+//! `GenerationConfig::deadline` on the synthetic model, driven by a `ManualClock` that advances
+//! 10 ms on every read: one read when generation starts, then one per deadline check (before
+//! each prefill chunk and before each decode step).
+
+mod common;
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use inference_engine_rust::engine::deadline::{DEADLINE_PREFILL_CHUNK, ManualClock};
+use inference_engine_rust::engine::generation::{
+    FinishReason, GenerationConfig, GenerationOutput, generate_from_ids,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::{Granularity, TextChunk, Tokenizer};
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+const PROMPT: [u32; 4] = [1, 7, 8, 9];
+const TICK: Duration = Duration::from_millis(10);
+
+fn config(deadline_ms: Option<u64>, min_tokens: usize) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 12,
+        temperature: 0.8,
+        seed: 11,
+        deadline: deadline_ms.map(Duration::from_millis),
+        min_tokens,
+        ..GenerationConfig::default()
+    }
+}
+
+fn run(
+    session: &mut InferenceSession<'_>,
+    prompt: &[u32],
+    config: &GenerationConfig,
+) -> GenerationOutput {
+    session.set_clock(Arc::new(ManualClock::ticking(TICK)));
+    generate_from_ids(session, prompt, &[], config).unwrap()
+}
+
+#[test]
+fn deadline_mid_prefill_returns_no_tokens() {
+    let path = tiny_llama().write("deadline_prefill");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let prompt: Vec<u32> = (0..DEADLINE_PREFILL_CHUNK as u32 + 8)
+        .map(|i| 3 + i % 20)
+        .collect();
+
+    // Chunk 1 is checked at 10 ms, chunk 2 at 20 ms.
+    let out = run(&mut session, &prompt, &config(Some(15), 0));
+    assert_eq!(out.finish_reason, FinishReason::DeadlineExceeded);
+    assert!(out.generated_token_ids.is_empty());
+    assert_eq!(session.position(), DEADLINE_PREFILL_CHUNK);
+
+    // A chunked prefill that completes generates what a single-pass one does.
+    let chunked = run(&mut session, &prompt, &config(Some(60_000), 0));
+    let whole = run(&mut session, &prompt, &config(None, 0));
+    assert_eq!(chunked.generated_token_ids, whole.generated_token_ids);
+    for (a, b) in chunked
+        .generated_logprobs
+        .iter()
+        .zip(&whole.generated_logprobs)
+    {
+        assert!((a - b).abs() < 1e-4, "{a} vs {b}");
+    }
+}
+
+#[test]
+fn deadline_mid_decode_keeps_the_tokens_so_far() {
+    let path = tiny_llama().write("deadline_decode");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let full = run(&mut session, &PROMPT, &config(None, 0));
+    assert!(full.generated_token_ids.len() >= 4, "{full:?}");
+
+    // Prefill checked at 10 ms, decodes at 20, 30 and 40 ms.
+    let out = run(&mut session, &PROMPT, &config(Some(35), 0));
+    assert_eq!(out.finish_reason, FinishReason::DeadlineExceeded);
+    assert_eq!(out.generated_token_ids, full.generated_token_ids[..3]);
+    assert_eq!(out.generated_logprobs.len(), 3);
+    assert_eq!(session.position(), PROMPT.len() + 2);
+}
+
+#[test]
+fn deadline_exactly_at_a_token_boundary_stops_there() {
+    let path = tiny_llama().write("deadline_boundary");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let full = run(&mut session, &PROMPT, &config(None, 0));
+
+    let out = run(&mut session, &PROMPT, &config(Some(30), 0));
+    assert_eq!(out.finish_reason, FinishReason::DeadlineExceeded);
+    assert_eq!(out.generated_token_ids, full.generated_token_ids[..2]);
+}
+
+#[test]
+fn min_tokens_wins_only_until_the_hard_limit() {
+    let path = tiny_llama().write("deadline_min_tokens");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let full = run(&mut session, &PROMPT, &config(None, 0));
+
+    // Past the 15 ms deadline at 20 ms with one token; the hard limit is 30 ms.
+    let out = run(&mut session, &PROMPT, &config(Some(15), 5));
+    assert_eq!(out.finish_reason, FinishReason::DeadlineExceeded);
+    assert_eq!(out.generated_token_ids, full.generated_token_ids[..2]);
+
+    // Two tokens are enough once the deadline has passed.
+    let out = run(&mut session, &PROMPT, &config(Some(25), 2));
+    assert_eq!(out.generated_token_ids, full.generated_token_ids[..2]);
+
+    let untimed = run(&mut session, &PROMPT, &config(None, 5));
+    assert_eq!(untimed, full);
+}
+
+#[test]
+fn streaming_flushes_the_buffered_text_at_the_deadline() {
+    let path = tiny_llama().write("deadline_stream");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("deadline_stream");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let full = run(&mut session, &PROMPT, &config(None, 0));
+
+    // Starts at the first token: prefill checked at 10 ms, each token before it is fed at 20,
+    // 30 and 40 ms.
+    session.set_clock(Arc::new(ManualClock::ticking(TICK)));
+    let mut chunks: Vec<TextChunk> = Vec::new();
+    let end = session
+        .stream_text(
+            &tokenizer,
+            &PROMPT,
+            &config(Some(35), 0),
+            Granularity::Word,
+            |chunk| {
+                chunks.push(chunk.clone());
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+    assert_eq!(end.finish_reason, Some(FinishReason::DeadlineExceeded));
+    assert_eq!(end.token_count, 2);
+    assert!(!end.cancelled);
+
+    let text: String = chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(
+        text,
+        tokenizer.decode(&full.generated_token_ids[..2]).unwrap()
+    );
+    assert_eq!(chunks.last().unwrap().tokens.end, 2);
+    assert_eq!(session.position(), PROMPT.len() + 2);
+}
@@ -0,0 +1,119 @@
+//! Per-token decode traces on the synthetic model: the JSON lines parse back, and their ids,
+//! logprobs, alternatives and argmax flags agree with an untraced run of the same seed.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use inference_engine_rust::engine::deadline::ManualClock;
+use inference_engine_rust::engine::decode_trace::{DecodeStep, TRACE_ALTERNATIVES};
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+const PROMPT: [u32; 4] = [1, 7, 8, 9];
+const TICK: Duration = Duration::from_millis(5);
+
+fn config(temperature: f32) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 16,
+        temperature,
+        seed: 3,
+        ..GenerationConfig::default()
+    }
+}
+
+/// JSON lines of a traced run, plus the untraced output of the same config.
+fn trace_lines(stem: &str, config: &GenerationConfig) -> (Vec<String>, Vec<u32>, Vec<f32>) {
+    let model = LoadedModel::load(tiny_llama().write(stem)).unwrap();
+    let tokenizer = Tokenizer::load_from_file(write_tiny_tokenizer(stem)).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let expected = generate_from_ids(&mut session, &PROMPT, &[], config).unwrap();
+
+    session.set_clock(Arc::new(ManualClock::ticking(TICK)));
+    let mut iter = session.tokens(&tokenizer, &PROMPT, config).traced();
+    let mut lines = Vec::new();
+    while let Some(token) = iter.next() {
+        let token = token.unwrap();
+        let step = iter.last_step().expect("traced");
+        assert_eq!((step.id, &step.text), (token.id, &token.text));
+        lines.push(step.to_json_line());
+    }
+    (
+        lines,
+        expected.generated_token_ids,
+        expected.generated_logprobs,
+    )
+}
+
+#[test]
+fn json_lines_parse_and_agree_with_the_generation() {
+    let (lines, ids, logprobs) = trace_lines("decode_trace_sampled", &config(1.5));
+    assert_eq!(lines.len(), ids.len());
+
+    let steps: Vec<DecodeStep> = lines
+        .iter()
+        .map(|line| {
+            assert!(!line.contains('\n'), "{line}");
+            let value: serde_json::Value = serde_json::from_str(line).expect("valid JSON");
+            for field in [
+                "index",
+                "id",
+                "text",
+                "logprob",
+                "alternatives",
+                "elapsed_ms",
+            ] {
+                assert!(value.get(field).is_some(), "{field} missing: {line}");
+            }
+            serde_json::from_value(value).expect("decode step")
+        })
+        .collect();
+
+    let mut last_ms = 0.0;
+    for (i, step) in steps.iter().enumerate() {
+        assert_eq!(step.index, i);
+        assert_eq!(step.id, ids[i]);
+        assert!((step.logprob - logprobs[i]).abs() < 1e-5);
+        assert!(step.elapsed_ms > last_ms, "{lines:?}");
+        last_ms = step.elapsed_ms;
+
+        assert_eq!(step.alternatives.len(), TRACE_ALTERNATIVES);
+        assert!(step.alternatives.iter().all(|a| a.id != step.id));
+        assert!(
+            step.alternatives
+                .windows(2)
+                .all(|w| w[0].logprob >= w[1].logprob)
+        );
+        // Off the argmax exactly when some alternative was more likely.
+        let beaten = step.alternatives[0].logprob > step.logprob;
+        assert_eq!(
+            step.changed_by.as_deref(),
+            beaten.then_some("temperature"),
+            "{}",
+            lines[i]
+        );
+    }
+    assert!(
+        steps.iter().any(|s| s.changed_by.is_some()),
+        "a hot seeded run should leave the argmax at least once: {lines:?}"
+    );
+}
+
+#[test]
+fn greedy_traces_never_change_the_choice_and_render_one_row_each() {
+    let (lines, ids, _) = trace_lines("decode_trace_greedy", &config(0.0));
+    assert_eq!(lines.len(), ids.len());
+    for line in &lines {
+        let step: DecodeStep = serde_json::from_str(line).unwrap();
+        assert_eq!(step.changed_by, None);
+        assert!(step.alternatives.iter().all(|a| a.logprob <= step.logprob));
+        let row = step.to_string();
+        assert_eq!(row.lines().count(), 1, "{row}");
+        assert!(row.contains(&format!(" {} ", step.id)), "{row}");
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-role dtype overrides on the synthetic model: only the requested roles change dtype, the
+//! load report lists exactly those tensors, and generation agrees with the unconverted runs.
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use inference_engine_rust::compare::{compare_weight_precision, compare_weight_precision_models};
+use inference_engine_rust::core::tensor::TensorType;
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::dtype_overrides::{InMemoryDtype, WeightRole};
+use inference_engine_rust::model_loader::gguf_types::LoadOptions;
+
+use common::gguf_fixture::{TINY_LAYERS, tiny_llama, tiny_llama_q8};
+
+const PROMPT: [u32; 4] = [1, 5, 9, 13];
+
+const MATRIX_ROLES: [WeightRole; 9] = [
+    WeightRole::TokenEmbedding,
+    WeightRole::Output,
+    WeightRole::AttnQ,
+    WeightRole::AttnK,
+    WeightRole::AttnV,
+    WeightRole::AttnOutput,
+    WeightRole::FfnGate,
+    WeightRole::FfnUp,
+    WeightRole::FfnDown,
+];
+
+fn with_overrides(roles: &[WeightRole], dtype: InMemoryDtype) -> LoadOptions {
+    LoadOptions {
+        role_dtype_overrides: roles
+            .iter()
+            .map(|&r| (r, dtype))
+            .collect::<BTreeMap<_, _>>(),
+        ..LoadOptions::default()
+    }
+}
+
+/// Greedy continuation of [`PROMPT`] and the logits after the prompt.
+fn run(model: &LoadedModel) -> (Vec<u32>, Vec<f32>) {
+    let mut session = InferenceSession::new(model).unwrap();
+    let state = session.prefill(&PROMPT).unwrap();
+    let logits = session.logits_last_token(&state).unwrap();
+    let config = GenerationConfig {
+        max_new_tokens: 8,
+        ..GenerationConfig::default()
+    };
+    let out = generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+    (out.generated_token_ids, logits)
+}
+
+fn assert_logits_close(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len());
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        assert!((x - y).abs() < 1e-4, "logit {i}: {x} vs {y}");
+    }
+}
+
+#[test]
+fn promoting_only_ffn_down_leaves_the_rest_quantized() {
+    let path = tiny_llama_q8().write("dtype_overrides_ffn_down");
+    let none = LoadedModel::load(&path).unwrap();
+    let partial = LoadedModel::load_with(
+        &path,
+        &with_overrides(&[WeightRole::FfnDown], InMemoryDtype::F32),
+    )
+    .unwrap();
+    let all =
+        LoadedModel::load_with(&path, &with_overrides(&MATRIX_ROLES, InMemoryDtype::F32)).unwrap();
+
+    for (name, tensor) in partial.gguf().loaded_tensors() {
+        let expected = match (WeightRole::of(name), tensor.dimensions().len()) {
+            (WeightRole::FfnDown, _) | (_, 1) => TensorType::F32,
+            _ => TensorType::Q8_0,
+        };
+        assert_eq!(tensor.dtype(), expected, "{name}");
+    }
+    assert!(
+        all.gguf()
+            .loaded_tensors()
+            .all(|(_, t)| t.dtype() == TensorType::F32)
+    );
+
+    let report = &partial.load_stats().dtype_overrides;
+    let names: Vec<&str> = report.iter().map(|o| o.name.as_str()).collect();
+    let expected: Vec<String> = (0..TINY_LAYERS)
+        .map(|l| format!("blk.{l}.ffn_down.weight"))
+        .collect();
+    assert_eq!(names, expected);
+    for o in report {
+        assert_eq!(o.role, WeightRole::FfnDown);
+        assert_eq!((o.from, o.to), (TensorType::Q8_0, TensorType::F32));
+    }
+    assert!(none.load_stats().dtype_overrides.is_empty());
+    assert_eq!(all.load_stats().dtype_overrides.len(), 7 * TINY_LAYERS + 2);
+
+    // Dequantizing is exact, so every run sees the same weights through different kernels.
+    let (none_ids, none_logits) = run(&none);
+    let (partial_ids, partial_logits) = run(&partial);
+    let (all_ids, all_logits) = run(&all);
+    assert!(none_logits.iter().all(|x| x.is_finite()));
+    assert_logits_close(&partial_logits, &none_logits);
+    assert_logits_close(&partial_logits, &all_logits);
+    assert_eq!(partial_ids, none_ids);
+    assert_eq!(partial_ids, all_ids);
+}
+
+#[test]
+fn forcing_f32_weights_dequantizes_everything_and_stays_within_quantization_tolerance() {
+    let path = tiny_llama_q8().write("dtype_overrides_force_f32");
+    let quantized = LoadedModel::load(&path).unwrap();
+    // The flag wins over any per-role request.
+    let forced = LoadedModel::load_with(
+        &path,
+        &LoadOptions {
+            force_f32_weights: true,
+            ..with_overrides(&[WeightRole::FfnDown], InMemoryDtype::F16Scales)
+        },
+    )
+    .unwrap();
+    assert!(
+        forced
+            .gguf()
+            .loaded_tensors()
+            .all(|(_, t)| t.dtype() == TensorType::F32)
+    );
+    assert_eq!(
+        forced.load_stats().dtype_overrides.len(),
+        7 * TINY_LAYERS + 2
+    );
+
+    let report = compare_weight_precision_models(&quantized, &forced, &PROMPT).unwrap();
+    assert_eq!(report.positions, PROMPT.len());
+    assert_eq!(report.dequantized_tensors, 7 * TINY_LAYERS + 2);
+    assert!(report.max_abs_diff < 1e-3, "{report}");
+    assert!(report.mean_abs_diff <= report.max_abs_diff as f64);
+    assert!(
+        report.mean_kl <= report.max_kl && report.max_kl < 1e-6,
+        "{report}"
+    );
+    assert_eq!(report.argmax_agreement, PROMPT.len(), "{report}");
+    assert!(report.to_string().ends_with("argmax_agreement:    4/4"));
+
+    // An F32 file has nothing to dequantize: the two loads run identical kernels.
+    let f32_path = tiny_llama().write("dtype_overrides_force_f32_plain");
+    let plain = compare_weight_precision(&f32_path, &PROMPT).unwrap();
+    assert_eq!(plain.dequantized_tensors, 0);
+    assert_eq!(plain.max_abs_diff, 0.0);
+    assert!(compare_weight_precision(&f32_path, &[]).is_err());
+}
+
+#[test]
+fn f16_scales_compacts_an_f32_model_like_a_q8_0_file() {
+    let f32_path = tiny_llama().write("dtype_overrides_compact_f32");
+    let q8_path = tiny_llama_q8().write("dtype_overrides_compact_q8");
+    let compacted = LoadedModel::load_with(
+        &f32_path,
+        &with_overrides(&MATRIX_ROLES, InMemoryDtype::F16Scales),
+    )
+    .unwrap();
+    let stored = LoadedModel::load(&q8_path).unwrap();
+
+    for ((name, a), (_, b)) in compacted
+        .gguf()
+        .loaded_tensors()
+        .zip(stored.gguf().loaded_tensors())
+    {
+        assert_eq!(a.dtype(), b.dtype(), "{name}");
+        assert_eq!(a.buffer(), b.buffer(), "{name}");
+    }
+    assert_eq!(run(&compacted), run(&stored));
+}
+
+#[test]
+fn f16_scales_norms_are_refused() {
+    // Refused by role, before any tensor is read (the 16-element norms would also fail to
+    // requantize, but with a different error naming the tensor).
+    let path = tiny_llama().write("dtype_overrides_norm");
+    let Err(err) = LoadedModel::load_with(
+        &path,
+        &with_overrides(&[WeightRole::Norm], InMemoryDtype::F16Scales),
+    ) else {
+        panic!("F16Scales norms should not load");
+    };
+    let err = err.to_string();
+    assert!(err.contains("not supported for Norm"), "{err}");
+    assert!(!err.contains("norm.weight"), "{err}");
+}
@@ -0,0 +1,322 @@
+//! `EffectiveConfig` resolution: precedence between conflicting sources, the provenance recorded
+//! for the winners, validation errors that name their sources, per-field `ConfigError`s from
+//! `GenerationConfig::validate`, and the config attached to a run.
+
+mod common;
+
+use std::time::Duration;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::engine::effective_config::{
+    EffectiveConfig, EffectiveConfigError, GenerationSettings, Provenance, generate_effective,
+};
+use inference_engine_rust::engine::generation::{ConfigError, GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::guidance::Guidance;
+use inference_engine_rust::engine::json_schema::JsonSchema;
+use inference_engine_rust::engine::quality::QualityThresholds;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+fn settings(max_new_tokens: Option<usize>, temperature: Option<f32>) -> GenerationSettings {
+    GenerationSettings {
+        max_new_tokens,
+        temperature,
+        ..GenerationSettings::default()
+    }
+}
+
+#[test]
+fn higher_provenance_wins_whatever_the_layer_order() {
+    let effective = EffectiveConfig::resolve(&[
+        (Provenance::Override, settings(None, Some(0.0))),
+        (Provenance::CliFlag, settings(Some(9), Some(1.5))),
+        (Provenance::Metadata, settings(None, Some(0.7))),
+        (Provenance::UserConfig, settings(Some(64), Some(0.9))),
+    ])
+    .unwrap();
+    assert_eq!(effective.config.max_new_tokens, 9);
+    assert_eq!(
+        effective.provenance("max_new_tokens"),
+        Some(Provenance::CliFlag)
+    );
+    assert_eq!(effective.config.temperature, 0.0);
+    assert_eq!(
+        effective.provenance("temperature"),
+        Some(Provenance::Override)
+    );
+    assert_eq!(effective.config.seed, GenerationConfig::default().seed);
+    assert_eq!(effective.provenance("seed"), Some(Provenance::Default));
+    assert_eq!(effective.provenance("no_such_field"), None);
+
+    // Same provenance: the later layer wins.
+    let effective = EffectiveConfig::resolve(&[
+        (Provenance::UserConfig, settings(Some(5), None)),
+        (Provenance::UserConfig, settings(Some(6), None)),
+    ])
+    .unwrap();
+    assert_eq!(effective.config.max_new_tokens, 6);
+    assert_eq!(
+        effective.provenance("max_new_tokens"),
+        Some(Provenance::UserConfig)
+    );
+}
+
+#[test]
+fn json_schema_and_quality_layer_like_the_other_fields() {
+    let schema = |s: &str| JsonSchema::parse(s).unwrap();
+    let effective = EffectiveConfig::resolve(&[
+        (
+            Provenance::UserConfig,
+            GenerationSettings {
+                json_schema: Some(schema(r#"{"type":"string"}"#)),
+                quality: Some(QualityThresholds::default()),
+                ..GenerationSettings::default()
+            },
+        ),
+        (
+            Provenance::CliFlag,
+            GenerationSettings {
+                json_schema: Some(schema(r#"{"type":"integer"}"#)),
+                ..GenerationSettings::default()
+            },
+        ),
+    ])
+    .unwrap();
+    assert_eq!(
+        effective.config.json_schema,
+        Some(schema(r#"{"type":"integer"}"#))
+    );
+    assert_eq!(
+        effective.provenance("json_schema"),
+        Some(Provenance::CliFlag)
+    );
+    assert_eq!(effective.config.quality, Some(QualityThresholds::default()));
+    assert_eq!(
+        effective.provenance("quality"),
+        Some(Provenance::UserConfig)
+    );
+
+    let listing = effective.to_string();
+    assert!(
+        listing.contains(r#"json_schema     = {"type":"integer"}"#),
+        "{listing}"
+    );
+    assert!(
+        listing.contains("quality         = window 16, surprise 5, entropy none"),
+        "{listing}"
+    );
+}
+
+#[test]
+fn metadata_sampling_keys_sit_below_user_settings() {
+    let path = tiny_llama()
+        .kv("general.sampling.temp", Data::Float32(0.6))
+        .kv("general.sampling.min_p", Data::Float32(0.05))
+        .write("effective_config_metadata");
+    let model = LoadedModel::load(&path).unwrap();
+    let metadata = GenerationSettings::from_metadata(model.gguf());
+    assert_eq!(metadata.temperature, Some(0.6));
+    assert_eq!(metadata.min_p, Some(0.05));
+    assert_eq!(metadata.max_new_tokens, None);
+
+    let effective = EffectiveConfig::resolve(&[
+        (Provenance::Metadata, metadata),
+        (Provenance::UserConfig, settings(None, Some(1.0))),
+    ])
+    .unwrap();
+    assert_eq!(effective.config.temperature, 1.0);
+    assert_eq!(
+        effective.provenance("temperature"),
+        Some(Provenance::UserConfig)
+    );
+    assert_eq!(effective.config.min_p, 0.05);
+    assert_eq!(effective.provenance("min_p"), Some(Provenance::Metadata));
+}
+
+#[test]
+fn conflicts_name_the_source_of_each_value() {
+    let err = EffectiveConfig::resolve(&[
+        (Provenance::UserConfig, settings(Some(4), None)),
+        (
+            Provenance::CliFlag,
+            GenerationSettings {
+                min_tokens: Some(8),
+                deadline: Some(Duration::from_secs(1)),
+                ..GenerationSettings::default()
+            },
+        ),
+    ])
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            EffectiveConfigError::MinTokensAboveMax {
+                min_tokens: 8,
+                min_tokens_from: Provenance::CliFlag,
+                max_new_tokens: 4,
+                max_new_tokens_from: Provenance::UserConfig,
+            }
+        ),
+        "{err}"
+    );
+    let err = err.to_string();
+    assert!(
+        err.contains("min_tokens 8 (from cli flag) exceeds max_new_tokens 4 (from user config)"),
+        "{err}"
+    );
+
+    let err = EffectiveConfig::resolve(&[(Provenance::Metadata, settings(None, Some(-1.0)))])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("temperature -1 (from metadata)"), "{err}");
+
+    let err = EffectiveConfig::resolve(&[(
+        Provenance::Override,
+        GenerationSettings {
+            min_p: Some(1.5),
+            ..GenerationSettings::default()
+        },
+    )])
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("min_p 1.5 (from override)"), "{err}");
+}
+
+#[test]
+fn each_out_of_range_field_is_its_own_config_error() {
+    let guided = |scale| GenerationConfig {
+        guidance: Some(Guidance {
+            negative_prompt: String::new(),
+            scale,
+        }),
+        ..GenerationConfig::default()
+    };
+    let cases = [
+        (
+            GenerationConfig {
+                temperature: -1.0,
+                ..GenerationConfig::default()
+            },
+            ConfigError::Temperature(-1.0),
+            "temperature -1 must be finite and >= 0 (0 = greedy)",
+        ),
+        (
+            GenerationConfig {
+                temperature: f32::INFINITY,
+                ..GenerationConfig::default()
+            },
+            ConfigError::Temperature(f32::INFINITY),
+            "temperature inf must be",
+        ),
+        (
+            GenerationConfig {
+                min_p: 1.7,
+                ..GenerationConfig::default()
+            },
+            ConfigError::MinP(1.7),
+            "min_p 1.7 must be in [0, 1] (0 = disabled)",
+        ),
+        (
+            GenerationConfig {
+                min_p: -0.1,
+                ..GenerationConfig::default()
+            },
+            ConfigError::MinP(-0.1),
+            "min_p -0.1 must be",
+        ),
+        (
+            guided(-2.0),
+            ConfigError::GuidanceScale(-2.0),
+            "guidance.scale -2 must be finite and >= 0 (0 = disabled)",
+        ),
+    ];
+    for (config, expected, message) in cases {
+        let err = config.validate().unwrap_err();
+        assert_eq!(err, expected);
+        assert!(err.to_string().starts_with(message), "{err}");
+    }
+    // NaN compares unequal to itself, so check the variant and field only.
+    let nan = GenerationConfig {
+        min_p: f32::NAN,
+        ..GenerationConfig::default()
+    };
+    assert!(matches!(nan.validate(), Err(ConfigError::MinP(v)) if v.is_nan()));
+    assert!(matches!(
+        guided(f32::NAN).validate(),
+        Err(ConfigError::GuidanceScale(_))
+    ));
+
+    // Boundary values are valid: greedy, min-p disabled or keeping only the top tokens, and
+    // guidance off or past the clamp.
+    for config in [
+        GenerationConfig::default(),
+        GenerationConfig {
+            temperature: 0.7,
+            min_p: 1.0,
+            ..GenerationConfig::default()
+        },
+        guided(0.0),
+        guided(1e6),
+    ] {
+        assert_eq!(config.validate(), Ok(()), "{config:?}");
+    }
+
+    // Generation checks before touching the model.
+    let model = LoadedModel::load(tiny_llama().write("effective_config_invalid")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let config = GenerationConfig {
+        temperature: -0.5,
+        ..GenerationConfig::default()
+    };
+    let err = generate_from_ids(&mut session, &[1, 5], &[], &config).unwrap_err();
+    assert!(
+        matches!(err, EngineError::Config(ConfigError::Temperature(t)) if t == -0.5),
+        "{err}"
+    );
+    assert_eq!(session.position(), 0);
+
+    let err = EffectiveConfig::resolve(&[(
+        Provenance::UserConfig,
+        GenerationSettings {
+            guidance: Some(Guidance {
+                negative_prompt: String::new(),
+                scale: -1.0,
+            }),
+            ..GenerationSettings::default()
+        },
+    )])
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            EffectiveConfigError::OutOfRange {
+                error: ConfigError::GuidanceScale(_),
+                from: Provenance::UserConfig,
+            }
+        ),
+        "{err}"
+    );
+    let err = err.to_string();
+    assert!(
+        err.contains("guidance.scale -1 (from user config)"),
+        "{err}"
+    );
+}
+
+#[test]
+fn generation_output_carries_the_effective_config() {
+    let model = LoadedModel::load(tiny_llama().write("effective_config_generate")).unwrap();
+    let mut tokenizer =
+        Tokenizer::load_from_file(write_tiny_tokenizer("effective_config_generate")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let effective =
+        EffectiveConfig::resolve(&[(Provenance::CliFlag, settings(Some(3), None))]).unwrap();
+
+    let out = generate_effective(&mut session, &mut tokenizer, "a b", &effective).unwrap();
+    assert_eq!(out.effective_config.as_ref(), Some(&effective));
+    assert!(out.generated_token_ids.len() <= 3);
+}
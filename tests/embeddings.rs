@@ -0,0 +1,89 @@
+//! Embedding lookups on synthetic GGUFs: `lookup_embeddings_matrix` returns the same rows as
+//! `lookup_embeddings` laid out back to back, and out-of-vocab ids follow `OnOutOfVocab`.
+
+mod common;
+
+use inference_engine_rust::layers::embeddings::{
+    OnOutOfVocab, lookup_embeddings, lookup_embeddings_matrix, lookup_embeddings_with,
+};
+use inference_engine_rust::model_loader::file_loader::read_file;
+
+use common::gguf_fixture::{GGML_TYPE_Q8_0, GgufFixture, TINY_HIDDEN, TINY_VOCAB, tiny_llama};
+
+fn assert_flat_matches_rows(path: &str, token_ids: &[u32], expected_hidden: usize) {
+    // Separate handles so each call loads the table lazily on its own.
+    let mut rows_gguf = read_file(path).unwrap();
+    let rows = lookup_embeddings(&mut rows_gguf, path, token_ids).unwrap();
+    let mut flat_gguf = read_file(path).unwrap();
+    let (flat, hidden) = lookup_embeddings_matrix(&mut flat_gguf, path, token_ids).unwrap();
+
+    assert_eq!(hidden, expected_hidden);
+    assert_eq!(flat.len(), token_ids.len() * hidden);
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(
+            &flat[i * hidden..(i + 1) * hidden],
+            row.as_slice(),
+            "row {i}"
+        );
+    }
+}
+
+#[test]
+fn f32_matrix_matches_row_lookup() {
+    let path = tiny_llama().write("embeddings_matrix_f32");
+    let path = path.to_str().unwrap();
+    assert_flat_matches_rows(path, &[1, 7, 7, 0, 31], TINY_HIDDEN);
+}
+
+#[test]
+fn q8_0_matrix_matches_row_lookup() {
+    const HIDDEN: usize = 64;
+    const VOCAB: usize = 80;
+    // Two Q8_0 blocks per row: f16 scale 0.5, then quants that differ per block.
+    let data: Vec<u8> = (0..VOCAB * HIDDEN / 32)
+        .flat_map(|b| {
+            let mut block = vec![0x00, 0x38];
+            block.extend((0..32).map(|i| ((b * 7 + i * 3) % 255) as u8));
+            block
+        })
+        .collect();
+    let path = GgufFixture::new()
+        .tensor(
+            "token_embd.weight",
+            &[HIDDEN as u64, VOCAB as u64],
+            GGML_TYPE_Q8_0,
+            data,
+        )
+        .write("embeddings_matrix_q8_0");
+    let path = path.to_str().unwrap();
+    assert_flat_matches_rows(path, &[79, 0, 3, 40], HIDDEN);
+
+    let mut gguf = read_file(path).unwrap();
+    assert!(lookup_embeddings_matrix(&mut gguf, path, &[VOCAB as u32]).is_err());
+}
+
+#[test]
+fn out_of_vocab_ids_follow_the_chosen_mode() {
+    let path = tiny_llama().write("embeddings_oov");
+    let path = path.to_str().unwrap();
+    let mut gguf = read_file(path).unwrap();
+    let rows = |gguf: &mut _, ids: &[u32], mode| lookup_embeddings_with(gguf, path, ids, mode);
+    let row = |gguf: &mut _, id: u32| lookup_embeddings(gguf, path, &[id]).unwrap().remove(0);
+    let oov = TINY_VOCAB as u32 + 5;
+
+    let err = rows(&mut gguf, &[3, oov], OnOutOfVocab::Error).unwrap_err();
+    assert!(err.to_string().contains(&oov.to_string()), "{err}");
+    assert!(lookup_embeddings(&mut gguf, path, &[oov]).is_err());
+
+    let unk = rows(&mut gguf, &[3, oov, 4], OnOutOfVocab::Unk(0)).unwrap();
+    assert_eq!(
+        unk,
+        [row(&mut gguf, 3), row(&mut gguf, 0), row(&mut gguf, 4)]
+    );
+    // The substitute must itself be a valid id.
+    assert!(rows(&mut gguf, &[oov], OnOutOfVocab::Unk(oov)).is_err());
+
+    let clamped = rows(&mut gguf, &[oov, u32::MAX], OnOutOfVocab::Clamp).unwrap();
+    let last = row(&mut gguf, TINY_VOCAB as u32 - 1);
+    assert_eq!(clamped, [last.clone(), last]);
+}
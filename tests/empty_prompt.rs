@@ -0,0 +1,132 @@
+//! Prompts that encode to zero tokens: with BOS they run as `[BOS]`, without it they fail with
+//! `EngineError::EmptyPrompt` before the model is touched; chat rejects empty user messages.
+
+mod common;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::chat_prompt::ChatPromptStyle;
+use inference_engine_rust::engine::chat_session::ChatSession;
+use inference_engine_rust::engine::generation::{GenerationConfig, generate, generate_from_ids};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_config::TokenizerPromptConfig;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::tokenizer::{ControlCharPolicy, TextNormalization, Tokenizer};
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+/// Text the tiny word-level tokenizer turns into no pieces: nothing, whitespace its pre-tokenizer
+/// drops, and control characters stripped by normalization.
+const EMPTY_PROMPTS: [&str; 3] = ["", " \t\n  ", "\u{7}\u{1b}"];
+
+fn tokenizer(stem: &str) -> Tokenizer {
+    let mut tok = Tokenizer::load_from_file(write_tiny_tokenizer(stem)).unwrap();
+    tok.set_normalization(TextNormalization {
+        control_chars: ControlCharPolicy::Strip,
+        ..TextNormalization::default()
+    });
+    tok
+}
+
+fn config() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 2,
+        ..GenerationConfig::default()
+    }
+}
+
+#[test]
+fn prompts_that_encode_to_nothing_become_bos_or_an_error() {
+    let mut tok = tokenizer("empty_prompt_encode");
+    let with_bos = TokenizerPromptConfig {
+        add_bos_token: true,
+        ..TokenizerPromptConfig::default()
+    };
+    let without_bos = TokenizerPromptConfig::default();
+    for prompt in EMPTY_PROMPTS {
+        assert!(tok.encode(prompt).unwrap().is_empty(), "{prompt:?}");
+        assert_eq!(
+            tok.encode_with_prompt_config(prompt, &with_bos).unwrap(),
+            [with_bos.bos_token_id],
+            "{prompt:?}"
+        );
+        let err = tok
+            .encode_with_prompt_config(prompt, &without_bos)
+            .unwrap_err();
+        assert!(
+            matches!(err, EngineError::EmptyPrompt(_)),
+            "{prompt:?}: {err}"
+        );
+    }
+    // EOS alone is still something to run on.
+    let eos_only = TokenizerPromptConfig {
+        add_eos_token: true,
+        ..TokenizerPromptConfig::default()
+    };
+    assert_eq!(
+        tok.encode_with_prompt_config("", &eos_only).unwrap(),
+        [eos_only.eos_token_id]
+    );
+}
+
+#[test]
+fn empty_prompt_generates_from_bos_when_the_model_adds_one() {
+    let model = LoadedModel::load(tiny_llama().write("empty_prompt_bos")).unwrap();
+    assert!(model.tokenizer_prompt().add_bos_token);
+    let mut tok = tokenizer("empty_prompt_bos");
+    let mut session = InferenceSession::new(&model).unwrap();
+    for prompt in EMPTY_PROMPTS {
+        let out = generate(&mut session, &mut tok, prompt, &config()).unwrap();
+        assert_eq!(out.generated_token_ids.len(), 2, "{prompt:?}");
+        // BOS plus the first generated token (the last one is never fed back).
+        assert_eq!(session.position(), 2, "{prompt:?}");
+    }
+}
+
+#[test]
+fn empty_prompt_fails_before_touching_the_model_without_bos() {
+    let path = tiny_llama()
+        .kv("tokenizer.ggml.add_bos_token", Data::Bool(false))
+        .write("empty_prompt_no_bos");
+    let model = LoadedModel::load(path).unwrap();
+    let mut tok = tokenizer("empty_prompt_no_bos");
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.prefill(&[3, 4]).unwrap();
+
+    for prompt in EMPTY_PROMPTS {
+        let err = generate(&mut session, &mut tok, prompt, &config()).unwrap_err();
+        assert!(
+            matches!(err, EngineError::EmptyPrompt(_)),
+            "{prompt:?}: {err}"
+        );
+        // Not even reset: the earlier prefill is still cached.
+        assert_eq!(session.position(), 2);
+    }
+    let err = generate_from_ids(&mut session, &[], &[], &config()).unwrap_err();
+    assert!(matches!(err, EngineError::EmptyPrompt(_)), "{err}");
+    let err = session.prefill(&[]).unwrap_err();
+    assert!(matches!(err, EngineError::EmptyPrompt(_)), "{err}");
+    assert_eq!(session.position(), 2);
+    assert_eq!(session.budget().used(), 2);
+}
+
+#[test]
+fn chat_rejects_empty_user_messages_and_keeps_the_history() {
+    let model = LoadedModel::load(tiny_llama().write("empty_prompt_chat")).unwrap();
+    let mut chat = ChatSession::new(
+        &model,
+        tokenizer("empty_prompt_chat"),
+        ChatPromptStyle::MistralInstruct,
+    )
+    .unwrap();
+    for message in ["", "   ", "\n\t"] {
+        let err = chat.send(message, &config()).unwrap_err();
+        assert!(
+            matches!(err, EngineError::EmptyPrompt(_)),
+            "{message:?}: {err}"
+        );
+        assert!(chat.history().is_empty());
+    }
+    chat.send("w5 w6", &config()).unwrap();
+    assert_eq!(chat.history().len(), 2);
+}
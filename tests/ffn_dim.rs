@@ -0,0 +1,26 @@
+//! FFN inner size: `llama.feed_forward_length` when present, else the `ffn_gate.weight` shape.
+
+mod common;
+
+use inference_engine_rust::model_config::{ModelConfig, ffn_dim_from_weights};
+use inference_engine_rust::model_loader::file_loader::read_file;
+
+use common::gguf_fixture::{TINY_FFN, TINY_HIDDEN, tiny_llama};
+
+#[test]
+fn ffn_dim_falls_back_to_gate_weight_shape() {
+    let with_meta = tiny_llama().write("ffn_dim_meta");
+    let without_meta = tiny_llama()
+        .without_kv("llama.feed_forward_length")
+        .write("ffn_dim_no_meta");
+    for path in [&with_meta, &without_meta] {
+        let gguf = read_file(path.to_str().unwrap()).expect("read fixture");
+        let config = ModelConfig::from_gguf(&gguf).expect("config");
+        assert_eq!(config.ffn_dim, TINY_FFN);
+        assert!(config.layer_dims.iter().all(|d| d.ffn_dim == TINY_FFN));
+        assert_eq!(ffn_dim_from_weights(&gguf, TINY_HIDDEN).unwrap(), TINY_FFN);
+    }
+    let gguf = read_file(without_meta.to_str().unwrap()).unwrap();
+    let err = ffn_dim_from_weights(&gguf, TINY_HIDDEN + 1).unwrap_err();
+    assert!(err.to_string().contains("blk.0.ffn_gate.weight"), "{err}");
+}
@@ -0,0 +1,81 @@
+//! The final RMSNorm on the two-layer synthetic model: the LM head sees the last hidden state
+//! normalized by `output_norm.weight`, and a model without that tensor is rejected at load.
+
+mod common;
+
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::ops::matmul::matmul;
+
+use common::f32_tensor;
+use common::gguf_fixture::{TINY_HIDDEN, tiny_llama};
+
+const PROMPT: [u32; 4] = [1, 5, 6, 7];
+const EPS: f32 = 1e-5;
+
+#[test]
+fn lm_head_input_is_the_normalized_last_hidden_state() {
+    // Non-unit gains, so the weights' part in the norm is visible too.
+    let gains: Vec<f32> = (0..TINY_HIDDEN).map(|i| 0.5 + i as f32 * 0.1).collect();
+    let fixture = tiny_llama()
+        .without_tensor("output_norm.weight")
+        .f32_tensor("output_norm.weight", &[TINY_HIDDEN as u64], &gains);
+    let path = fixture.write("final_norm_gains");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let state = session.prefill(&PROMPT).unwrap();
+
+    let hidden = &state.hidden()[(PROMPT.len() - 1) * TINY_HIDDEN..];
+    let normed = session.final_hidden_last_token(&state).unwrap();
+    assert_eq!(normed.len(), TINY_HIDDEN);
+
+    let mean_sq = hidden.iter().map(|x| x * x).sum::<f32>() / TINY_HIDDEN as f32;
+    let inv_rms = 1.0 / (mean_sq + EPS).sqrt();
+    for (i, (&n, &x)) in normed.iter().zip(hidden).enumerate() {
+        let expected = x * inv_rms * gains[i];
+        assert!(
+            (n - expected).abs() <= 1e-5 * expected.abs().max(1.0),
+            "{i}: {n} vs {expected}"
+        );
+    }
+    // Without the gains, unit RMS; the residual stream itself is not.
+    let unscaled_rms = (normed
+        .iter()
+        .zip(&gains)
+        .map(|(n, g)| (n / g).powi(2))
+        .sum::<f32>()
+        / TINY_HIDDEN as f32)
+        .sqrt();
+    assert!((unscaled_rms - 1.0).abs() < 1e-3, "{unscaled_rms}");
+    assert!((mean_sq.sqrt() - 1.0).abs() > 1e-2, "{}", mean_sq.sqrt());
+
+    // The logits are the LM head applied to exactly that vector.
+    let weights = model.weights().unwrap();
+    let vocab = model.config().vocab_size;
+    let mut expected = f32_tensor(&vec![0.0; vocab], vec![1, vocab]);
+    matmul(
+        &f32_tensor(&normed, vec![1, TINY_HIDDEN]),
+        weights.lm_head,
+        &mut expected,
+    )
+    .unwrap();
+    assert_eq!(
+        session.logits_last_token(&state).unwrap(),
+        expected.as_f32_slice().unwrap()
+    );
+}
+
+#[test]
+fn model_without_a_final_norm_fails_to_load() {
+    let path = tiny_llama()
+        .without_tensor("output_norm.weight")
+        .write("final_norm_missing");
+    let Err(err) = LoadedModel::load(&path) else {
+        panic!("loaded a model without output_norm.weight");
+    };
+    let err = err.to_string();
+    assert!(
+        err.contains("final norm") && err.contains("output_norm.weight"),
+        "{err}"
+    );
+}
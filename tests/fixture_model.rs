@@ -0,0 +1,424 @@
+//! End-to-end forward passes on the synthetic model from `tests/common/gguf_fixture.rs`.
+
+mod common;
+
+use std::path::Path;
+
+use inference_engine_rust::engine::config::{EngineConfig, LayerSchedule};
+use inference_engine_rust::engine::generation::greedy_next_token;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::layers::attention::{CacheDtype, KVCache};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use inference_engine_rust::model_loader::gguf_types::{Data, LoadOptions};
+
+use common::gguf_fixture::{TINY_HIDDEN, TINY_KV_HEADS, TINY_LAYERS, TINY_VOCAB, tiny_llama};
+
+#[test]
+fn tiny_llama_loads_and_generates() {
+    let path = tiny_llama().write("fixture_model_generate");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    assert_eq!(model.config().n_layers, TINY_LAYERS);
+    assert_eq!(model.config().vocab_size, TINY_VOCAB);
+
+    let mut session = InferenceSession::new(&model).expect("session");
+    let mut state = session.prefill(&[1, 5, 9]).expect("prefill");
+    let logits = session.logits_last_token(&state).expect("logits");
+    assert_eq!(logits.len(), TINY_VOCAB);
+    assert!(logits.iter().all(|x| x.is_finite()));
+
+    for _ in 0..4 {
+        let next = greedy_next_token(&session, &state).expect("next token");
+        assert!((next as usize) < TINY_VOCAB);
+        state = session.decode_token(next).expect("decode");
+    }
+}
+
+fn assert_logits_close(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len());
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        assert!((x - y).abs() < 1e-5, "logit {i}: {x} vs {y}");
+    }
+}
+
+#[test]
+fn chunked_prefill_matches_one_prefill() {
+    let path = tiny_llama().write("fixture_model_chunked_prefill");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    // 7 tokens in chunks of 3: the last chunk is partial.
+    let prompt = [1, 4, 8, 15, 16, 23, 30];
+
+    let mut whole = InferenceSession::new(&model).expect("session");
+    let whole_state = whole.prefill(&prompt).expect("prefill");
+    let whole_logits = whole.logits_last_token(&whole_state).expect("logits");
+
+    let mut chunked = InferenceSession::new(&model).expect("session");
+    let mut chunk_state = None;
+    for chunk in prompt.chunks(3) {
+        chunk_state = Some(chunked.prefill(chunk).expect("chunk prefill"));
+    }
+    let chunk_state = chunk_state.unwrap();
+    assert_eq!(chunked.position(), prompt.len());
+    assert_logits_close(
+        &whole_logits,
+        &chunked.logits_last_token(&chunk_state).unwrap(),
+    );
+
+    // The caches must agree too: the next decode step sees identical history.
+    let next = greedy_next_token(&whole, &whole_state).unwrap();
+    let a = whole.decode_token(next).unwrap();
+    let b = chunked.decode_token(next).unwrap();
+    assert_logits_close(
+        &whole.logits_last_token(&a).unwrap(),
+        &chunked.logits_last_token(&b).unwrap(),
+    );
+}
+
+#[test]
+fn two_thread_pool_matches_default_pool() {
+    let path = tiny_llama().write("fixture_model_threads");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let prompt = [1, 3, 7, 12, 20];
+
+    let mut default = InferenceSession::new(&model).expect("session");
+    let mut capped =
+        InferenceSession::with_config(&model, &EngineConfig::with_threads(2)).expect("session");
+    let mut a = default.prefill(&prompt).unwrap();
+    let mut b = capped.prefill(&prompt).unwrap();
+    for _ in 0..3 {
+        let la = default.logits_last_token(&a).unwrap();
+        let lb = capped.logits_last_token(&b).unwrap();
+        // Each row/head is computed by one thread, so results are bit-identical.
+        assert_eq!(la, lb);
+        let next = greedy_next_token(&default, &a).unwrap();
+        a = default.decode_token(next).unwrap();
+        b = capped.decode_token(next).unwrap();
+    }
+}
+
+#[test]
+fn mixture_of_experts_gguf_is_rejected() {
+    let h = TINY_HIDDEN as u64;
+    let path = tiny_llama()
+        .kv("llama.expert_count", Data::Uint32(2))
+        .kv("llama.expert_used_count", Data::Uint32(1))
+        .f32_tensor(
+            "blk.0.ffn_gate_inp.weight",
+            &[h, 2],
+            &[0.0; 2 * TINY_HIDDEN],
+        )
+        .f32_tensor(
+            "blk.0.ffn_gate_exps.weight",
+            &[h, 4, 2],
+            &[0.0; 8 * TINY_HIDDEN],
+        )
+        .write("fixture_model_moe");
+    let Err(err) = LoadedModel::load(&path) else {
+        panic!("MoE fixture should not load");
+    };
+    let err = err.to_string();
+    assert!(err.contains("MoE not supported"), "{err}");
+    assert!(err.contains("expert_count = 2"), "{err}");
+    assert!(err.contains("blk.0.ffn_gate_inp.weight"), "{err}");
+    assert!(err.contains("blk.0.ffn_gate_exps.weight"), "{err}");
+}
+
+#[test]
+fn restored_system_prompt_snapshot_matches_full_prefill() {
+    let path = tiny_llama().write("fixture_model_kv_snapshot");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let system = [1u32, 10, 11, 12];
+    let user = [20u32, 21, 22];
+
+    let mut session = InferenceSession::new(&model).expect("session");
+    session.prefill(&system).expect("system prefill");
+    let snapshot = session.snapshot();
+    assert_eq!(snapshot[0].len(), system.len());
+
+    // Serve another request on top of the prefix, then rewind to it.
+    session.prefill(&[30, 31]).expect("first request");
+    session.restore(&snapshot).expect("restore");
+    assert_eq!(session.position(), system.len());
+    let state = session.prefill(&user).expect("user prefill");
+    let cached = session.logits_last_token(&state).expect("logits");
+
+    let mut fresh = InferenceSession::new(&model).expect("session");
+    let full: Vec<u32> = system.iter().chain(&user).copied().collect();
+    let state = fresh.prefill(&full).expect("full prefill");
+    assert_logits_close(&cached, &fresh.logits_last_token(&state).expect("logits"));
+}
+
+#[test]
+fn failed_restore_leaves_every_layer_untouched() {
+    let path = tiny_llama().write("fixture_model_kv_restore_atomic");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+
+    let mut other = InferenceSession::new(&model).expect("session");
+    other.prefill(&[1, 10, 11, 12, 13]).expect("prefill");
+    // Layers 0.. fit; only the last one has the wrong head width.
+    let mut bad = other.snapshot();
+    *bad.last_mut().unwrap() = KVCache::new(8, TINY_KV_HEADS, 3).snapshot();
+
+    let mut session = InferenceSession::new(&model).expect("session");
+    session.prefill(&[1, 20, 21]).expect("prefill");
+    let before = session.snapshot();
+    let err = session.restore(&bad).unwrap_err().to_string();
+    assert!(err.contains(&format!("layer {}", TINY_LAYERS - 1)), "{err}");
+    assert_eq!(session.snapshot(), before);
+    assert_eq!(session.position(), 3);
+    assert_eq!(session.budget().used(), 3);
+
+    assert!(session.restore(&before[1..]).is_err());
+    assert_eq!(session.snapshot(), before);
+}
+
+#[test]
+fn decode_after_prefill_attends_to_every_cached_position() {
+    let path = tiny_llama().write("fixture_model_decode_after_prefill");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+
+    // Prefill 0..3, then decode at position 3: its query must see all 4 rows, exactly like the
+    // last row of a 4-token prefill (whose causal mask also lets it see 0..=3).
+    let mut split = InferenceSession::new(&model).expect("session");
+    split.prefill(&[1, 4, 9]).expect("prefill");
+    let state = split.decode_token(16).expect("decode");
+    assert_eq!(split.position(), 4);
+    let decoded = split.logits_last_token(&state).expect("logits");
+
+    let mut whole = InferenceSession::new(&model).expect("session");
+    let state = whole.prefill(&[1, 4, 9, 16]).expect("prefill");
+    assert_logits_close(&decoded, &whole.logits_last_token(&state).expect("logits"));
+
+    // Masking the decode query down to its own row would not match.
+    let mut alone = InferenceSession::new(&model).expect("session");
+    let state = alone.prefill(&[16]).expect("prefill");
+    let own_only = alone.logits_last_token(&state).expect("logits");
+    assert!(
+        decoded
+            .iter()
+            .zip(&own_only)
+            .any(|(a, b)| (a - b).abs() > 1e-3)
+    );
+}
+
+#[test]
+fn f16_kv_cache_generation_tracks_f32() {
+    let path = tiny_llama().write("fixture_model_kv_f16");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let prompt = [1u32, 4, 9, 16, 25];
+
+    let mut exact = InferenceSession::new(&model).expect("session");
+    let engine = EngineConfig {
+        kv_cache_dtype: CacheDtype::F16,
+        ..EngineConfig::default()
+    };
+    let mut half = InferenceSession::with_config(&model, &engine).expect("session");
+    let mut a = exact.prefill(&prompt).unwrap();
+    let mut b = half.prefill(&prompt).unwrap();
+    for _ in 0..6 {
+        let la = exact.logits_last_token(&a).unwrap();
+        let lb = half.logits_last_token(&b).unwrap();
+        for (i, (x, y)) in la.iter().zip(&lb).enumerate() {
+            assert!((x - y).abs() < 1e-2, "logit {i}: {x} vs {y}");
+        }
+        let next = greedy_next_token(&exact, &a).unwrap();
+        assert_eq!(next, greedy_next_token(&half, &b).unwrap());
+        a = exact.decode_token(next).unwrap();
+        b = half.decode_token(next).unwrap();
+    }
+
+    // `reset` keeps the configured storage.
+    half.reset();
+    assert_eq!(half.snapshot()[0].dtype(), CacheDtype::F16);
+}
+
+#[test]
+fn layer_timings_cover_every_layer() {
+    let path = tiny_llama().write("fixture_model_layer_timings");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let engine = EngineConfig {
+        layer_timings: true,
+        ..EngineConfig::default()
+    };
+    let mut session = InferenceSession::with_config(&model, &engine).expect("session");
+    assert!(session.layer_timings().is_empty());
+
+    let state = session.prefill(&[1, 5, 9]).expect("prefill");
+    let prefill = session.layer_timings().to_vec();
+    assert_eq!(prefill.len(), TINY_LAYERS);
+    assert!(prefill.iter().all(|t| !t.is_zero()), "{prefill:?}");
+
+    let next = greedy_next_token(&session, &state).expect("next token");
+    session.decode_token(next).expect("decode");
+    let decode = session.layer_timings();
+    assert_eq!(decode.len(), TINY_LAYERS);
+    assert!(decode.iter().all(|t| !t.is_zero()), "{decode:?}");
+
+    session.set_layer_timing(false);
+    session.decode_token(next).expect("decode");
+    assert!(session.layer_timings().is_empty());
+}
+
+fn logits_with_schedule(model: &LoadedModel, schedule: LayerSchedule, prompt: &[u32]) -> Vec<f32> {
+    let engine = EngineConfig {
+        layer_schedule: schedule,
+        ..EngineConfig::default()
+    };
+    let mut session = InferenceSession::with_config(model, &engine).expect("session");
+    let state = session.prefill(prompt).expect("prefill");
+    session.logits_last_token(&state).expect("logits")
+}
+
+#[test]
+fn layer_schedule_first_n_all_matches_baseline_and_fewer_layers_differ() {
+    let path = tiny_llama().write("fixture_model_layer_schedule");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let prompt = [1u32, 5, 9, 13];
+
+    let baseline = logits_with_schedule(&model, LayerSchedule::All, &prompt);
+    let all = logits_with_schedule(&model, LayerSchedule::FirstN(TINY_LAYERS), &prompt);
+    assert_eq!(all, baseline);
+
+    // The fixture has two layers, so running the first one is the shortest real schedule.
+    for schedule in [
+        LayerSchedule::FirstN(TINY_LAYERS - 1),
+        LayerSchedule::SkipEvery {
+            start: 0,
+            stride: 2,
+        },
+    ] {
+        let engine = EngineConfig {
+            layer_schedule: schedule,
+            layer_timings: true,
+            ..EngineConfig::default()
+        };
+        let mut session = InferenceSession::with_config(&model, &engine).expect("session");
+        let state = session.prefill(&prompt).expect("prefill");
+        assert_eq!(session.position(), prompt.len(), "{schedule:?}");
+        let timed: Vec<bool> = session
+            .layer_timings()
+            .iter()
+            .map(|t| !t.is_zero())
+            .collect();
+        let expected: Vec<bool> = (0..TINY_LAYERS).map(|i| schedule.runs(i)).collect();
+        assert_eq!(timed, expected, "{schedule:?}");
+
+        let logits = session.logits_last_token(&state).expect("logits");
+        assert_eq!(logits.len(), TINY_VOCAB);
+        assert_ne!(logits, baseline, "{schedule:?}");
+
+        // The schedule is constant, so decoding through the cache agrees with a prefill.
+        let next = greedy_next_token(&session, &state).expect("next token");
+        let stepped = session.decode_token(next).expect("decode");
+        let stepped = session.logits_last_token(&stepped).expect("logits");
+        let history: Vec<u32> = prompt.iter().copied().chain([next]).collect();
+        let fresh = logits_with_schedule(&model, schedule, &history);
+        for (a, b) in stepped.iter().zip(&fresh) {
+            assert!((a - b).abs() < 1e-4, "{schedule:?}: {a} vs {b}");
+        }
+    }
+}
+
+#[test]
+fn layer_schedules_that_run_nothing_are_rejected() {
+    let path = tiny_llama().write("fixture_model_layer_schedule_invalid");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let mut session = InferenceSession::new(&model).expect("session");
+    for schedule in [
+        LayerSchedule::FirstN(0),
+        LayerSchedule::FirstN(TINY_LAYERS + 1),
+        LayerSchedule::SkipEvery {
+            start: 0,
+            stride: 1,
+        },
+        LayerSchedule::SkipEvery {
+            start: 0,
+            stride: 0,
+        },
+    ] {
+        assert!(
+            session.set_layer_schedule(schedule).is_err(),
+            "{schedule:?}"
+        );
+    }
+    assert_eq!(session.layer_schedule(), LayerSchedule::All);
+
+    session.prefill(&[1, 5]).expect("prefill");
+    session
+        .set_layer_schedule(LayerSchedule::FirstN(1))
+        .expect("valid schedule");
+    assert_eq!(
+        session.position(),
+        0,
+        "a new schedule starts from an empty cache"
+    );
+}
+
+#[test]
+fn attention_softcap_is_read_from_metadata_and_applied() {
+    let plain_path = tiny_llama().write("fixture_model_softcap_none");
+    let loose_path = tiny_llama()
+        .kv("llama.attn_logit_softcapping", Data::Float32(1e6))
+        .write("fixture_model_softcap_loose");
+    let tight_path = tiny_llama()
+        .kv("llama.attn_logit_softcapping", Data::Float32(1e-3))
+        .write("fixture_model_softcap_tight");
+    let plain = LoadedModel::load(&plain_path).unwrap();
+    let loose = LoadedModel::load(&loose_path).unwrap();
+    let tight = LoadedModel::load(&tight_path).unwrap();
+    assert_eq!(plain.config().attn_logit_softcapping, None);
+    assert_eq!(loose.config().attn_logit_softcapping, Some(1e6));
+
+    let logits = |model: &LoadedModel| {
+        let mut session = InferenceSession::new(model).unwrap();
+        let state = session.prefill(&[1, 5, 9, 2]).unwrap();
+        session.logits_last_token(&state).unwrap()
+    };
+    let (base, loose, tight) = (logits(&plain), logits(&loose), logits(&tight));
+    // A cap far above every score changes nothing; a tiny one flattens attention.
+    assert_logits_close(&base, &loose);
+    assert!(base.iter().zip(&tight).any(|(a, b)| (a - b).abs() > 1e-4));
+}
+
+#[test]
+fn split_gguf_set_loads_like_the_single_file() {
+    let whole_path = tiny_llama().write("fixture_model_split_whole");
+    let shard_paths: Vec<_> = tiny_llama()
+        .split(3)
+        .iter()
+        .enumerate()
+        .map(|(no, shard)| shard.write(&format!("fixture_model_split_{no}")))
+        .collect();
+    let whole = LoadedModel::load(&whole_path).expect("load single file");
+    let split = LoadedModel::load_split_with(&shard_paths, &LoadOptions::default())
+        .expect("load split set");
+    assert_eq!(split.model_path(), shard_paths[0].display().to_string());
+    assert_eq!(
+        split.gguf().num_tensors(),
+        whole.gguf().num_tensors(),
+        "every shard's tensors are merged"
+    );
+    assert_eq!(
+        split.load_stats().tensors_loaded,
+        whole.gguf().num_tensors()
+    );
+
+    let logits = |model: &LoadedModel| {
+        let mut session = InferenceSession::new(model).expect("session");
+        let state = session.prefill(&[1, 5, 9]).expect("prefill");
+        session.logits_last_token(&state).expect("logits")
+    };
+    assert_eq!(logits(&split), logits(&whole));
+
+    let reversed: Vec<&Path> = shard_paths.iter().rev().map(|p| &**p).collect();
+    let err = LoadedModel::load_split_with(&reversed, &LoadOptions::default())
+        .err()
+        .expect("shards out of order")
+        .to_string();
+    assert!(err.contains("out of order"), "{err}");
+    let err = LoadedModel::load_split_with(&shard_paths[..2], &LoadOptions::default())
+        .err()
+        .expect("missing shard")
+        .to_string();
+    assert!(err.contains("split.count 3"), "{err}");
+}
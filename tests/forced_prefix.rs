@@ -0,0 +1,74 @@
+//! Assistant-prefix forcing on the synthetic model with a word-level tokenizer.
+
+mod common;
+
+use inference_engine_rust::chat_prompt::{ChatPromptStyle, ChatRole};
+use inference_engine_rust::engine::chat_session::ChatSession;
+use inference_engine_rust::engine::generation::{
+    GenerationConfig, generate_with_forced_prefix, score_completions,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+fn config() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 5,
+        ..GenerationConfig::default()
+    }
+}
+
+#[test]
+fn forced_prefix_leads_output_and_logprobs_match_scoring() {
+    let model_path = tiny_llama().write("forced_prefix_raw");
+    let tok_path = write_tiny_tokenizer("forced_prefix_raw");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let mut tokenizer = Tokenizer::load_from_file(&tok_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+
+    let out = generate_with_forced_prefix(
+        &mut session,
+        &mut tokenizer,
+        "w7 w8 w9",
+        "w20 w21",
+        &config(),
+    )
+    .unwrap();
+    assert_eq!(out.forced_token_ids, vec![20, 21]);
+    assert!(out.text.starts_with("w20 w21"), "{:?}", out.text);
+    assert_eq!(out.forced_text(), "w20 w21");
+    assert_eq!(out.generated_logprobs.len(), out.generated_token_ids.len());
+    assert!(out.forced_logprobs.iter().all(|&lp| lp < 0.0));
+
+    let prompt_ids = tokenizer
+        .encode_with_prompt_config("w7 w8 w9", model.tokenizer_prompt())
+        .unwrap();
+    assert_eq!(prompt_ids.len(), out.prompt_tokens);
+    let scored = score_completions(&mut session, &prompt_ids, &[vec![20, 21]]).unwrap();
+    assert_eq!(scored[0].len(), 2);
+    for (a, b) in out.forced_logprobs.iter().zip(&scored[0]) {
+        assert!((a - b).abs() < 1e-5, "{a} vs {b}");
+    }
+}
+
+#[test]
+fn chat_session_reply_starts_with_forced_prefix() {
+    let model_path = tiny_llama().write("forced_prefix_chat");
+    let tok_path = write_tiny_tokenizer("forced_prefix_chat");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let tokenizer = Tokenizer::load_from_file(&tok_path).unwrap();
+    let mut chat = ChatSession::new(&model, tokenizer, ChatPromptStyle::MistralInstruct).unwrap();
+
+    let out = chat
+        .send_with_forced_prefix("w10 w11", "w30", &config())
+        .unwrap();
+    assert!(out.text.starts_with("w30"));
+    assert_eq!(out.forced_token_ids, vec![30]);
+
+    let history = chat.history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].role, ChatRole::Assistant);
+    assert!(history[1].content.starts_with("w30"));
+}
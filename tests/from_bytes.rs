@@ -0,0 +1,76 @@
+//! Loading a model and its tokenizer from bytes already in memory (no file system, as in a
+//! browser) gives the same model as loading the files.
+
+mod common;
+
+use inference_engine_rust::engine::generation::{GenerationConfig, generate};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::file_loader::read_from_bytes;
+use inference_engine_rust::model_loader::gguf_types::LoadOptions;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+fn reply(model: &LoadedModel, tokenizer: &mut Tokenizer) -> String {
+    let config = GenerationConfig {
+        max_new_tokens: 8,
+        temperature: 0.8,
+        seed: 5,
+        ..GenerationConfig::default()
+    };
+    let mut session = InferenceSession::new(model).unwrap();
+    generate(&mut session, tokenizer, "w5 w6 w7", &config)
+        .unwrap()
+        .text
+}
+
+#[test]
+fn model_and_tokenizer_from_bytes_generate_like_the_files() {
+    let model_path = tiny_llama().write("from_bytes_model");
+    let tokenizer_path = write_tiny_tokenizer("from_bytes_tokenizer");
+    let from_file = LoadedModel::load(&model_path).unwrap();
+    let from_bytes = LoadedModel::from_bytes(&std::fs::read(&model_path).unwrap()).unwrap();
+    assert_eq!(from_bytes.model_path(), "<memory>");
+    assert_eq!(
+        from_bytes.load_stats().tensors_loaded,
+        from_file.load_stats().tensors_loaded
+    );
+
+    let logits = |model: &LoadedModel| {
+        let mut session = InferenceSession::new(model).unwrap();
+        let state = session.prefill(&[1, 7, 8, 9]).unwrap();
+        session.logits_last_token(&state).unwrap()
+    };
+    assert_eq!(logits(&from_bytes), logits(&from_file));
+
+    let mut file_tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut bytes_tokenizer =
+        Tokenizer::from_json_bytes(&std::fs::read(&tokenizer_path).unwrap()).unwrap();
+    let expected = reply(&from_file, &mut file_tokenizer);
+    assert!(!expected.is_empty());
+    assert_eq!(reply(&from_bytes, &mut bytes_tokenizer), expected);
+}
+
+#[test]
+fn header_and_tensors_read_from_one_buffer() {
+    let bytes = std::fs::read(tiny_llama().write("from_bytes_gguf")).unwrap();
+    let mut gguf = read_from_bytes(&bytes).unwrap();
+    assert_eq!(gguf.num_tensors(), 0);
+    let stats = gguf
+        .load_tensors_from_bytes(&bytes, &LoadOptions::default())
+        .unwrap();
+    assert_eq!(gguf.num_tensors() as u64, gguf.total_tensors());
+    assert_eq!(stats.tensors_loaded, gguf.num_tensors());
+    assert!(stats.bytes_read > 0);
+    // Hints are for files.
+    assert_eq!(stats.hints_issued, 0);
+}
+
+#[test]
+fn truncated_buffer_is_an_error() {
+    let bytes = std::fs::read(tiny_llama().write("from_bytes_truncated")).unwrap();
+    assert!(LoadedModel::from_bytes(&bytes[..bytes.len() - 64]).is_err());
+    assert!(read_from_bytes(&bytes[..20]).is_err());
+    assert!(Tokenizer::from_json_bytes(b"{ not json").is_err());
+}
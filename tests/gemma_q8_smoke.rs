@@ -57,7 +57,7 @@ fn gemma4_print_tensor_shapes_debug() {
         "blk.0.inp_gate.weight",
         "blk.0.proj.weight",
     ] {
-        let t = gguf.tensors_metadata().iter().find(|x| x.name == name);
+        let t = gguf.tensor_info(name);
         eprintln!("{name}: {:?}", t.map(|x| x.dimensions.clone()));
     }
 }
@@ -95,12 +95,12 @@ fn gemma4_attn_k_dims_sample_layers() {
             "attn_k_norm.weight",
         ] {
             let name = format!("blk.{idx}.{stem}");
-            let t = gguf.tensors_metadata().iter().find(|x| x.name == name);
+            let t = gguf.tensor_info(&name);
             eprintln!("{name}: {:?}", t.map(|x| x.dimensions.clone()));
         }
         for stem in ["ffn_gate.weight", "ffn_up.weight", "ffn_down.weight"] {
             let name = format!("blk.{idx}.{stem}");
-            let t = gguf.tensors_metadata().iter().find(|x| x.name == name);
+            let t = gguf.tensor_info(&name);
             eprintln!("{name}: {:?}", t.map(|x| x.dimensions.clone()));
         }
     }
@@ -0,0 +1,116 @@
+//! Header parsing limits: the minimized fuzz crashers in `tests/data/gguf_crashers/` (see
+//! `fuzz/fuzz_targets/gguf_header.rs`) must each fail with the expected typed error, and nesting
+//! far past the limit must not recurse.
+
+mod common;
+
+use std::path::Path;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::model_loader::file_loader::read_file;
+use inference_engine_rust::model_loader::gguf_types::{Data, MetadataLimitError};
+
+use common::gguf_fixture::{TempPath, tiny_llama};
+
+fn crasher(name: &str) -> Result<(), EngineError> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/gguf_crashers")
+        .join(name);
+    read_file(path.to_str().unwrap()).map(|_| ())
+}
+
+#[test]
+fn every_committed_crasher_is_a_typed_error() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/gguf_crashers");
+    let mut seen = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        match crasher(&name) {
+            Err(EngineError::MetadataLimit(_)) => {}
+            other => panic!("{name}: expected a metadata limit error, got {other:?}"),
+        }
+        seen += 1;
+    }
+    assert!(seen >= 6, "crasher corpus missing ({seen} files)");
+}
+
+#[test]
+fn crashers_hit_the_limit_they_were_minimized_for() {
+    let limit = |name| match crasher(name) {
+        Err(EngineError::MetadataLimit(e)) => e,
+        other => panic!("{name}: {other:?}"),
+    };
+    assert!(matches!(
+        limit("array_len_u64_max.gguf"),
+        MetadataLimitError::ArrayTooLong { len: u64::MAX, .. }
+    ));
+    assert!(matches!(
+        limit("array_len_past_end.gguf"),
+        MetadataLimitError::ArrayPastEnd {
+            len: 1048576,
+            min_bytes: 4194304,
+            remaining: 16,
+            ..
+        }
+    ));
+    for name in [
+        "string_len_u64_max.gguf",
+        "key_len_u64_max.gguf",
+        "tensor_name_len_huge.gguf",
+    ] {
+        assert!(
+            matches!(limit(name), MetadataLimitError::StringTooLong { .. }),
+            "{name}"
+        );
+    }
+    assert!(matches!(
+        limit("nested_arrays_64_deep.gguf"),
+        MetadataLimitError::ArrayTooDeep { depth: 9, .. }
+    ));
+}
+
+#[test]
+fn nesting_a_million_deep_is_rejected_without_recursing() {
+    // 12 bytes per level: type code 9 (array) and length 1.
+    let mut value = Vec::with_capacity(12 * 1_000_000 + 12);
+    for _ in 0..1_000_000 {
+        value.extend_from_slice(&9u32.to_le_bytes());
+        value.extend_from_slice(&1u64.to_le_bytes());
+    }
+    value.extend_from_slice(&4u32.to_le_bytes());
+    value.extend_from_slice(&0u64.to_le_bytes());
+    let mut bytes = b"GGUF".to_vec();
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    bytes.extend_from_slice(b"a");
+    bytes.extend_from_slice(&9u32.to_le_bytes());
+    bytes.extend(value);
+    let path = TempPath::new("gguf_limits_deep_nesting", "gguf");
+    std::fs::write(&path, bytes).unwrap();
+    let err = read_file(path.to_str().unwrap()).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            EngineError::MetadataLimit(MetadataLimitError::ArrayTooDeep { .. })
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn real_sized_arrays_and_strings_still_load() {
+    let tokens: Vec<Data> = (0..100_000)
+        .map(|i| Data::String(format!("t{i}")))
+        .collect();
+    let path = tiny_llama()
+        .kv("test.big_array", Data::Array(tokens))
+        .kv("tokenizer.chat_template", Data::String("x".repeat(1 << 20)))
+        .write("gguf_limits_real_sized");
+    let gguf = read_file(path.to_str().unwrap()).unwrap();
+    assert!(matches!(
+        gguf.get_metadata("test.big_array"),
+        Some(Data::Array(a)) if a.len() == 100_000
+    ));
+}
@@ -0,0 +1,173 @@
+//! Classifier-free guidance on the synthetic model: `scale = 0` is plain generation, and a
+//! positive scale samples from `l_g + scale * (l_g - l_n)` over both contexts' logits.
+
+mod common;
+
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::guidance::{
+    Guidance, GuidedSession, MAX_GUIDANCE_SCALE, combine_guided_logits,
+};
+use inference_engine_rust::engine::sampling::{sample_greedy, token_logprob};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+const PROMPT: [u32; 4] = [1, 5, 9, 12];
+const NEGATIVE: [u32; 2] = [1, 20];
+
+fn guided_config(scale: f32, max_new_tokens: usize) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens,
+        guidance: Some(Guidance {
+            negative_prompt: String::new(),
+            scale,
+        }),
+        ..GenerationConfig::default()
+    }
+}
+
+fn pair(model: &LoadedModel) -> GuidedSession<'_> {
+    GuidedSession::new(
+        InferenceSession::new(model).unwrap(),
+        InferenceSession::new(model).unwrap(),
+    )
+}
+
+#[test]
+fn zero_scale_reproduces_plain_generation() {
+    let path = tiny_llama().write("guidance_zero");
+    let model = LoadedModel::load(&path).unwrap();
+
+    let mut plain = InferenceSession::new(&model).unwrap();
+    let baseline_config = GenerationConfig {
+        max_new_tokens: 8,
+        ..GenerationConfig::default()
+    };
+    let baseline = generate_from_ids(&mut plain, &PROMPT, &[], &baseline_config).unwrap();
+
+    let mut guided = pair(&model);
+    let out = guided
+        .generate_from_ids(&PROMPT, &NEGATIVE, &guided_config(0.0, 8))
+        .unwrap();
+    assert_eq!(out.generated_token_ids, baseline.generated_token_ids);
+    assert_eq!(out.generated_logprobs, baseline.generated_logprobs);
+    assert_eq!(out.finish_reason, baseline.finish_reason);
+
+    // Lockstep: every fed token went to both contexts; both caches count toward memory.
+    let fed = out.generated_token_ids.len().saturating_sub(1);
+    assert_eq!(guided.main().position(), PROMPT.len() + fed);
+    assert_eq!(guided.negative().position(), NEGATIVE.len() + fed);
+    assert_eq!(guided.kv_cache_bytes(), 2 * plain.kv_cache_bytes());
+
+    // The single-session path refuses a guidance config instead of ignoring it.
+    assert!(generate_from_ids(&mut plain, &PROMPT, &[], &guided_config(1.0, 8)).is_err());
+}
+
+#[test]
+fn positive_scale_samples_from_the_combined_logits() {
+    let path = tiny_llama().write("guidance_scale");
+    let model = LoadedModel::load(&path).unwrap();
+    let scale = 1.5;
+
+    // Capture each context's logits by hand.
+    let mut main = InferenceSession::new(&model).unwrap();
+    let mut negative = InferenceSession::new(&model).unwrap();
+    let main_state = main.prefill(&PROMPT).unwrap();
+    let negative_state = negative.prefill(&NEGATIVE).unwrap();
+    let l_g = main.next_token_logits(&main_state).unwrap();
+    let l_n = negative.next_token_logits(&negative_state).unwrap();
+    let expected: Vec<f32> = l_g
+        .iter()
+        .zip(&l_n)
+        .map(|(g, n)| g + scale * (g - n))
+        .collect();
+    assert_eq!(combine_guided_logits(&l_g, &l_n, scale).unwrap(), expected);
+    let first = sample_greedy(&expected).unwrap();
+
+    // Second step: feed `first` to both hand-driven contexts.
+    let main_state = main.decode_token(first).unwrap();
+    let negative_state = negative.decode_token(first).unwrap();
+    let step2 = combine_guided_logits(
+        &main.next_token_logits(&main_state).unwrap(),
+        &negative.next_token_logits(&negative_state).unwrap(),
+        scale,
+    )
+    .unwrap();
+    let second = sample_greedy(&step2).unwrap();
+
+    let mut guided = pair(&model);
+    let out = guided
+        .generate_from_ids(&PROMPT, &NEGATIVE, &guided_config(scale, 2))
+        .unwrap();
+    assert_eq!(out.generated_token_ids, [first, second]);
+    assert_eq!(
+        out.generated_logprobs,
+        [
+            token_logprob(&expected, first).unwrap(),
+            token_logprob(&step2, second).unwrap()
+        ]
+    );
+    // The negative context actually contributed.
+    assert_ne!(expected, l_g);
+}
+
+#[test]
+fn scales_past_the_maximum_are_clamped() {
+    let path = tiny_llama().write("guidance_clamp");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut guided = pair(&model);
+    let at_max = guided
+        .generate_from_ids(&PROMPT, &NEGATIVE, &guided_config(MAX_GUIDANCE_SCALE, 3))
+        .unwrap();
+    let huge = guided
+        .generate_from_ids(&PROMPT, &NEGATIVE, &guided_config(1e9, 3))
+        .unwrap();
+    assert_eq!(huge.generated_token_ids, at_max.generated_token_ids);
+    assert_eq!(huge.generated_logprobs, at_max.generated_logprobs);
+    assert!(
+        huge.generated_logprobs.iter().all(|lp| lp.is_finite()),
+        "{:?}",
+        huge.generated_logprobs
+    );
+
+    let err = guided
+        .generate_from_ids(&PROMPT, &NEGATIVE, &guided_config(-1.0, 3))
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("guidance.scale -1 must be"),
+        "{err}"
+    );
+}
+
+#[test]
+fn text_prompts_are_encoded_for_both_contexts() {
+    let path = tiny_llama().write("guidance_text");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut tokenizer = Tokenizer::load_from_file(write_tiny_tokenizer("guidance_text")).unwrap();
+    let mut config = guided_config(2.0, 4);
+    config.guidance.as_mut().unwrap().negative_prompt = "w20".into();
+
+    let mut guided = pair(&model);
+    let from_text = guided
+        .generate(&mut tokenizer, "w5 w9 w12", &config)
+        .unwrap();
+    let prompt_cfg = model.tokenizer_prompt();
+    let prompt_ids = tokenizer
+        .encode_with_prompt_config("w5 w9 w12", prompt_cfg)
+        .unwrap();
+    let negative_ids = tokenizer
+        .encode_with_prompt_config("w20", prompt_cfg)
+        .unwrap();
+    let from_ids = guided
+        .generate_from_ids(&prompt_ids, &negative_ids, &config)
+        .unwrap();
+    assert_eq!(from_text.generated_token_ids, from_ids.generated_token_ids);
+    assert_eq!(
+        from_text.text,
+        tokenizer
+            .decode_piece_ids(&from_ids.generated_token_ids)
+            .unwrap()
+    );
+}
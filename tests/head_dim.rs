@@ -0,0 +1,221 @@
+//! Models whose `head_dim` is not `hidden_dim / n_heads` (read from `llama.attention.key_length`).
+
+mod common;
+
+use inference_engine_rust::engine::generation::greedy_next_token;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::state::ForwardState;
+use inference_engine_rust::layers::attention::{
+    decode_attention_layer, kv_caches_for_config, prefill_attention_layer,
+};
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::model_weights::LayerWeights;
+
+use common::gguf_fixture::{
+    GGML_TYPE_Q8_0, GgufFixture, TINY_HEADS, TINY_HIDDEN, TINY_KV_HEADS, TINY_VOCAB, tiny_llama,
+    tiny_llama_with_head_dim, tiny_vocab,
+};
+
+#[test]
+fn explicit_head_dim_sizes_projections_and_cache() {
+    // 4 heads x 6 = 24 != hidden 16.
+    let path = tiny_llama_with_head_dim(6).write("head_dim_tiny");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let config = model.config();
+    assert_eq!(config.head_dim, 6);
+    assert_eq!(config.layer_dims[0].q_dim, TINY_HEADS * 6);
+    assert_eq!(config.layer_dims[0].kv_dim, TINY_KV_HEADS * 6);
+    assert_eq!(kv_caches_for_config(config)[0].head_dim(), 6);
+
+    let prompt = [1u32, 5, 9, 13];
+    let mut session = InferenceSession::new(&model).expect("session");
+    let state = session.prefill(&prompt).expect("prefill");
+    assert_eq!(state.hidden_dim(), TINY_HIDDEN);
+    let next = greedy_next_token(&session, &state).unwrap();
+    let state = session.decode_token(next).expect("decode");
+    let stepped = session.logits_last_token(&state).unwrap();
+    assert_eq!(stepped.len(), TINY_VOCAB);
+
+    // Decode through the cache must agree with prefilling the same history at once.
+    let mut fresh = InferenceSession::new(&model).expect("session");
+    let history: Vec<u32> = prompt.iter().copied().chain([next]).collect();
+    let state = fresh.prefill(&history).expect("prefill");
+    for (a, b) in stepped.iter().zip(fresh.logits_last_token(&state).unwrap()) {
+        assert!((a - b).abs() < 1e-4, "{a} vs {b}");
+    }
+}
+
+#[test]
+fn head_dim_disagreeing_with_projections_names_the_tensor() {
+    // Projections are built for head_dim 4, metadata claims 6.
+    let path = tiny_llama()
+        .kv("llama.attention.key_length", Data::Uint32(6))
+        .write("head_dim_mismatch");
+    let Err(e) = LoadedModel::load(&path) else {
+        panic!("mismatched head_dim must not load");
+    };
+    let err = e.to_string();
+    assert!(
+        err.contains("'blk.0.attn_q.weight' has dims [16, 16], expected [16, 24]"),
+        "{err}"
+    );
+    assert!(err.contains("head_dim 6"), "{err}");
+}
+
+#[test]
+fn attention_output_projection_returns_hidden_dim_rows() {
+    // Heads concatenate to 4 x 6 = 24 values per row; `attn_output` maps them back to 16.
+    let path = tiny_llama_with_head_dim(6).write("head_dim_projection");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let config = model.config();
+    let weights = model.weights().unwrap();
+    let layer = &weights.layers[0];
+    let (dims, attn) = (&config.layer_dims[0], &config.layer_attention[0]);
+    assert_ne!(dims.q_dim, TINY_HIDDEN);
+
+    let seq_len = 3;
+    let hidden: Vec<f32> = (0..seq_len * TINY_HIDDEN)
+        .map(|i| (i as f32 * 0.37).sin())
+        .collect();
+    let input = ForwardState::from_flat(hidden, seq_len, TINY_HIDDEN).unwrap();
+    let mut caches = kv_caches_for_config(config);
+    let out = prefill_attention_layer(
+        &input,
+        config,
+        dims,
+        attn,
+        layer,
+        &mut caches,
+        0,
+        None,
+        None,
+    )
+    .expect("prefill attention");
+    assert_eq!(out.len(), seq_len * TINY_HIDDEN);
+
+    let step = ForwardState::from_flat(vec![0.5; TINY_HIDDEN], 1, TINY_HIDDEN).unwrap();
+    let residual = vec![1.0; TINY_HIDDEN];
+    let out = decode_attention_layer(
+        &step,
+        config,
+        dims,
+        attn,
+        layer,
+        &mut caches,
+        0,
+        Some(&residual),
+        None,
+    )
+    .expect("decode attention");
+    assert_eq!(out.len(), TINY_HIDDEN);
+
+    // An output projection that does not map q_dim back to hidden_dim is named, not multiplied.
+    let transposed = LayerWeights {
+        wo: layer.wq,
+        lora: Default::default(),
+        ..*layer
+    };
+    let err = decode_attention_layer(
+        &step,
+        config,
+        dims,
+        attn,
+        &transposed,
+        &mut caches,
+        0,
+        None,
+        None,
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("output projection has dims [16, 24], expected [q_dim 24, hidden_dim 16]"),
+        "{err}"
+    );
+}
+
+/// Q8_0 `[k, n]` matrix of pseudo-random quants with scale 2^-9.
+fn q8_matrix(f: GgufFixture, name: &str, k: usize, n: usize, seed: u64) -> GgufFixture {
+    let mut state = seed;
+    let mut data = Vec::with_capacity(k * n / 32 * 34);
+    for _ in 0..k * n / 32 {
+        data.extend_from_slice(&0x1800u16.to_le_bytes());
+        for _ in 0..32 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            data.push((state >> 56) as u8);
+        }
+    }
+    f.tensor(name, &[k as u64, n as u64], GGML_TYPE_Q8_0, data)
+}
+
+#[test]
+fn head_dim_96_with_hidden_4096_and_32_heads_runs_end_to_end() {
+    let (hidden, heads, kv_heads, head_dim, ffn) = (4096usize, 32usize, 8usize, 96usize, 32usize);
+    let (q_dim, kv_dim) = (heads * head_dim, kv_heads * head_dim);
+    // Embedding lookup infers orientation from the smaller dim, so vocab must exceed hidden.
+    let vocab = hidden + 64;
+    let mut words = tiny_vocab();
+    words.extend((words.len()..vocab).map(|i| format!("w{i}")));
+    let tokens = words.into_iter().map(Data::String).collect();
+    let ones = vec![1.0f32; hidden];
+
+    let mut f = GgufFixture::new()
+        .kv("general.architecture", Data::String("llama".into()))
+        .kv("general.name", Data::String("head-dim-96-fixture".into()))
+        .kv("llama.context_length", Data::Uint32(8))
+        .kv("llama.embedding_length", Data::Uint32(hidden as u32))
+        .kv("llama.block_count", Data::Uint32(1))
+        .kv("llama.feed_forward_length", Data::Uint32(ffn as u32))
+        .kv("llama.attention.head_count", Data::Uint32(heads as u32))
+        .kv(
+            "llama.attention.head_count_kv",
+            Data::Uint32(kv_heads as u32),
+        )
+        .kv("llama.attention.key_length", Data::Uint32(head_dim as u32))
+        .kv(
+            "llama.attention.value_length",
+            Data::Uint32(head_dim as u32),
+        )
+        .kv(
+            "llama.attention.layer_norm_rms_epsilon",
+            Data::Float32(1e-5),
+        )
+        .kv("tokenizer.ggml.model", Data::String("llama".into()))
+        .kv("tokenizer.ggml.tokens", Data::Array(tokens))
+        .kv("tokenizer.ggml.bos_token_id", Data::Uint32(1))
+        .kv("tokenizer.ggml.eos_token_id", Data::Uint32(2))
+        .f32_tensor("blk.0.attn_norm.weight", &[hidden as u64], &ones)
+        .f32_tensor("blk.0.ffn_norm.weight", &[hidden as u64], &ones)
+        .f32_tensor("output_norm.weight", &[hidden as u64], &ones);
+    // No `output.weight`: the LM head is tied to the embedding.
+    f = q8_matrix(f, "token_embd.weight", hidden, vocab, 0);
+    f = q8_matrix(f, "blk.0.attn_q.weight", hidden, q_dim, 1);
+    f = q8_matrix(f, "blk.0.attn_k.weight", hidden, kv_dim, 2);
+    f = q8_matrix(f, "blk.0.attn_v.weight", hidden, kv_dim, 3);
+    f = q8_matrix(f, "blk.0.attn_output.weight", q_dim, hidden, 4);
+    f = q8_matrix(f, "blk.0.ffn_gate.weight", hidden, ffn, 5);
+    f = q8_matrix(f, "blk.0.ffn_up.weight", hidden, ffn, 6);
+    f = q8_matrix(f, "blk.0.ffn_down.weight", ffn, hidden, 7);
+    let path = f.write("head_dim_96");
+
+    let model = LoadedModel::load(&path).expect("load head_dim 96 fixture");
+    let config = model.config();
+    assert_eq!(config.head_dim, head_dim);
+    assert_ne!(config.head_dim, hidden / heads);
+    assert_eq!(config.layer_dims[0].q_dim, q_dim);
+    assert_eq!(config.layer_dims[0].kv_dim, kv_dim);
+    assert_eq!(kv_caches_for_config(config)[0].head_dim(), head_dim);
+
+    let mut session = InferenceSession::new(&model).expect("session");
+    let state = session.prefill(&[1, 7, 9]).expect("prefill");
+    assert_eq!(state.hidden_dim(), hidden);
+    let next = greedy_next_token(&session, &state).unwrap();
+    let state = session.decode_token(next).expect("decode");
+    let logits = session.logits_last_token(&state).unwrap();
+    assert_eq!(logits.len(), vocab);
+    assert!(logits.iter().all(|x| x.is_finite()));
+    assert_eq!(session.position(), 4);
+}
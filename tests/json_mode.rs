@@ -0,0 +1,185 @@
+//! JSON mode on the synthetic model: a word-level tokenizer whose pieces are JSON fragments, so
+//! the grammar has real choices to make at every step.
+
+mod common;
+
+use inference_engine_rust::chat_prompt::{ChatPromptStyle, ChatRole};
+use inference_engine_rust::engine::chat_session::ChatSession;
+use inference_engine_rust::engine::generation::{
+    FinishReason, GenerationConfig, generate, generate_from_ids,
+};
+use inference_engine_rust::engine::json_schema::JsonSchema;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{TINY_VOCAB, TempPath, tiny_llama};
+
+/// Every string, array and enum is bounded, so a complete value is always within reach.
+const SCHEMA: &str = r#"{
+    "type": "object",
+    "properties": {
+        "name": {"type": "string", "maxLength": 3},
+        "color": {"enum": ["red", "blue"]},
+        "tags": {"type": "array", "items": {"type": "string", "maxLength": 2}, "maxItems": 2},
+        "meta": {
+            "type": "object",
+            "properties": {"ok": {"type": "boolean"}, "x": {"enum": [0, 1, 7]}},
+            "required": ["ok"]
+        }
+    },
+    "required": ["color", "meta", "name"]
+}"#;
+
+/// `tokenizer.json` with JSON-fragment pieces, joined without separators when decoded. No piece
+/// is a proper prefix of a key or enum value (like `b` of `blue`): with no `lue` piece to finish
+/// it, the constraint would reach a dead end.
+fn write_json_tokenizer(stem: &str) -> TempPath {
+    let pieces = [
+        "<unk>", "<s>", "</s>", "{", "}", "\"", ":", ",", "[", "]", " ", "name", "color", "red",
+        "blue", "tags", "meta", "ok", "true", "false", "a", "d", "x", "0", "1", "7", "-", "null",
+        "\":", "\",", "{\"", "ab",
+    ];
+    assert_eq!(pieces.len(), TINY_VOCAB);
+    let vocab: serde_json::Map<String, serde_json::Value> = pieces
+        .iter()
+        .enumerate()
+        .map(|(id, piece)| (piece.to_string(), id.into()))
+        .collect();
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "WhitespaceSplit" },
+        "post_processor": null,
+        "decoder": { "type": "Fuse" },
+        "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "<unk>" }
+    });
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json.to_string()).expect("write tokenizer.json fixture");
+    path
+}
+
+fn config(seed: u64, temperature: f32) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 256,
+        temperature,
+        seed,
+        ..GenerationConfig::default()
+    }
+    .json_schema(SCHEMA)
+    .unwrap()
+}
+
+#[test]
+fn seeded_generation_produces_schema_valid_json() {
+    let model_path = tiny_llama().write("json_mode_generate");
+    let tok_path = write_json_tokenizer("json_mode_generate");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let mut tokenizer = Tokenizer::load_from_file(&tok_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let schema = JsonSchema::parse(SCHEMA).unwrap();
+
+    let mut outputs = Vec::new();
+    for (seed, temperature) in [(0, 0.0), (1, 1.0), (2, 1.5), (3, 1.5)] {
+        let config = config(seed, temperature);
+        let out = generate(&mut session, &mut tokenizer, "name color meta", &config).unwrap();
+        assert_eq!(
+            out.finish_reason,
+            FinishReason::Eos { token_id: 2 },
+            "{:?}",
+            out.text
+        );
+        let value: serde_json::Value = serde_json::from_str(&out.text).unwrap();
+        assert!(
+            value["color"] == "red" || value["color"] == "blue",
+            "{value}"
+        );
+        assert!(value["meta"]["ok"].is_boolean(), "{value}");
+        schema.validate(&out.text).unwrap();
+        assert_eq!(out.generated_logprobs.len(), out.generated_token_ids.len());
+
+        // Same seed, same output.
+        let again = generate(&mut session, &mut tokenizer, "name color meta", &config).unwrap();
+        assert_eq!(again.generated_token_ids, out.generated_token_ids);
+        outputs.push(out.text);
+    }
+    outputs.dedup();
+    assert!(
+        outputs.len() > 1,
+        "sampling should vary the output: {outputs:?}"
+    );
+}
+
+#[test]
+fn chat_replies_follow_the_schema_without_changing_the_user_turn() {
+    let model_path = tiny_llama().write("json_mode_chat");
+    let tok_path = write_json_tokenizer("json_mode_chat");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let tokenizer = Tokenizer::load_from_file(&tok_path).unwrap();
+    let plain = ChatSession::new(
+        &model,
+        Tokenizer::load_from_file(&tok_path).unwrap(),
+        ChatPromptStyle::MistralInstruct,
+    )
+    .unwrap()
+    .send(
+        "name a color",
+        &GenerationConfig {
+            max_new_tokens: 1,
+            ..GenerationConfig::default()
+        },
+    )
+    .unwrap();
+    let mut chat = ChatSession::new(&model, tokenizer, ChatPromptStyle::MistralInstruct).unwrap();
+
+    // The 64-token context leaves no room for the full schema's hint and reply.
+    let schema = r#"{"type":"object","properties":{"color":{"enum":["red","blue"]}}}"#;
+    let config = GenerationConfig {
+        temperature: 1.0,
+        seed: 5,
+        ..GenerationConfig::default()
+    }
+    .json_schema(schema)
+    .unwrap();
+    let out = chat.send("name a color", &config).unwrap();
+    let hint = config.json_schema.as_ref().unwrap().hint();
+    assert_eq!(
+        out.prompt_tokens,
+        plain.prompt_tokens + hint.split_whitespace().count()
+    );
+    JsonSchema::parse(schema)
+        .unwrap()
+        .validate(&out.text)
+        .unwrap();
+    let history = chat.history();
+    assert_eq!(history[0].content, "name a color");
+    assert_eq!(history[1].role, ChatRole::Assistant);
+    assert_eq!(history[1].content, out.text.trim_end());
+}
+
+#[test]
+fn token_level_entry_points_reject_a_schema_and_configs_round_trip() {
+    let model_path = tiny_llama().write("json_mode_ids");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let config = config(0, 0.0);
+    let err = generate_from_ids(&mut session, &[1, 11], &[], &config)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("json_schema needs the tokenizer"), "{err}");
+
+    let json = serde_json::to_string(&config).unwrap();
+    let back: GenerationConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, config);
+    let err = GenerationConfig::default()
+        .json_schema(r#"{"type":"string","format":"email"}"#)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("unsupported feature at #: keyword 'format'"),
+        "{err}"
+    );
+}
@@ -0,0 +1,165 @@
+//! Automatic KV cache dtype selection: the budget thresholds at which each dtype is chosen, the
+//! failure message, pinning, and agreement between the estimate and the allocated caches.
+
+mod common;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::engine::config::EngineConfig;
+use inference_engine_rust::engine::effective_config::{
+    EffectiveConfig, KV_CACHE_FIELD, Provenance,
+};
+use inference_engine_rust::engine::kv_policy::{KvCachePolicy, KvCachePolicyError, MemoryBudget};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::layers::attention::{CacheDtype, kv_cache_bytes};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TINY_CONTEXT, tiny_llama_with_head_dim};
+
+/// Heads wide enough that a q8 row (plus its scale) is smaller than an f16 one.
+fn load(stem: &str) -> LoadedModel {
+    let path = tiny_llama_with_head_dim(16).write(stem);
+    LoadedModel::load(&path).expect("load fixture model")
+}
+
+fn auto(budget: usize) -> KvCachePolicy {
+    KvCachePolicy::Auto(MemoryBudget::Bytes(budget))
+}
+
+#[test]
+fn each_budget_threshold_lands_on_the_next_dtype() {
+    let model = load("kv_policy_thresholds");
+    let config = model.config();
+    let size = |dtype| kv_cache_bytes(config, TINY_CONTEXT, dtype);
+    let (f32_bytes, f16_bytes, q8_bytes) = (
+        size(CacheDtype::F32),
+        size(CacheDtype::F16),
+        size(CacheDtype::Q8),
+    );
+    assert!(f32_bytes > f16_bytes && f16_bytes > q8_bytes);
+
+    for (budget, want) in [
+        (usize::MAX, CacheDtype::F32),
+        (f32_bytes, CacheDtype::F32),
+        (f32_bytes - 1, CacheDtype::F16),
+        (f16_bytes, CacheDtype::F16),
+        (f16_bytes - 1, CacheDtype::Q8),
+        (q8_bytes, CacheDtype::Q8),
+    ] {
+        let choice = auto(budget).resolve(config, TINY_CONTEXT).unwrap();
+        assert_eq!(choice.dtype, want, "budget {budget}");
+        assert_eq!(choice.bytes, size(want));
+        assert_eq!(choice.budget, Some(budget));
+        assert_eq!(choice.provenance, Provenance::Auto);
+    }
+
+    // A shorter context fits a budget the full one does not.
+    let half = auto(q8_bytes / 2)
+        .resolve(config, TINY_CONTEXT / 2)
+        .unwrap();
+    assert_eq!(half.dtype, CacheDtype::Q8);
+}
+
+#[test]
+fn failure_names_every_size_and_the_context_that_would_fit() {
+    let model = load("kv_policy_failure");
+    let config = model.config();
+    let q8_bytes = kv_cache_bytes(config, TINY_CONTEXT, CacheDtype::Q8);
+    let per_token = kv_cache_bytes(config, 1, CacheDtype::Q8);
+
+    let err = auto(q8_bytes - 1)
+        .resolve(config, TINY_CONTEXT)
+        .unwrap_err();
+    let KvCachePolicyError::DoesNotFit {
+        context_len,
+        budget,
+        needed,
+        max_context,
+    } = err.clone();
+    assert_eq!((context_len, budget), (TINY_CONTEXT, q8_bytes - 1));
+    assert_eq!(needed[2], q8_bytes);
+    assert_eq!(max_context, TINY_CONTEXT - 1);
+    assert_eq!(max_context, (q8_bytes - 1) / per_token);
+
+    let message = EngineError::from(err).to_string();
+    assert!(
+        message.contains(&format!("KV cache for {TINY_CONTEXT} tokens does not fit")),
+        "{message}"
+    );
+    for dtype in ["as f32", "as f16", "as q8"] {
+        assert!(message.contains(dtype), "{message}");
+    }
+    assert!(
+        message.contains(&format!("at most {} tokens", TINY_CONTEXT - 1)),
+        "{message}"
+    );
+    assert!(message.contains("allow more memory"), "{message}");
+
+    let nothing = auto(0).resolve(config, TINY_CONTEXT).unwrap_err();
+    assert!(
+        nothing.to_string().contains("at most 0 tokens"),
+        "{nothing}"
+    );
+}
+
+#[test]
+fn pinned_and_undetectable_budgets_skip_the_search() {
+    let model = load("kv_policy_pinned");
+    let config = model.config();
+
+    let pinned = KvCachePolicy::Pinned {
+        dtype: CacheDtype::Q8,
+        source: Provenance::CliFlag,
+    }
+    .resolve_with(config, TINY_CONTEXT, || {
+        panic!("pinned dtype detected memory")
+    })
+    .unwrap();
+    assert_eq!(pinned.dtype, CacheDtype::Q8);
+    assert_eq!(pinned.budget, None);
+    assert_eq!(pinned.provenance, Provenance::CliFlag);
+
+    let unknown = KvCachePolicy::default()
+        .resolve_with(config, TINY_CONTEXT, || None)
+        .unwrap();
+    assert_eq!(
+        (unknown.dtype, unknown.provenance),
+        (CacheDtype::F32, Provenance::Auto)
+    );
+
+    let detected = KvCachePolicy::default()
+        .resolve_with(config, TINY_CONTEXT, || {
+            Some(kv_cache_bytes(config, TINY_CONTEXT, CacheDtype::F16))
+        })
+        .unwrap();
+    assert_eq!(detected.dtype, CacheDtype::F16);
+
+    let mut effective = EffectiveConfig::resolve(&[]).unwrap();
+    assert_eq!(effective.provenance(KV_CACHE_FIELD), None);
+    effective.record_kv_cache(&detected);
+    assert_eq!(effective.kv_cache_dtype, Some(CacheDtype::F16));
+    assert_eq!(effective.provenance(KV_CACHE_FIELD), Some(Provenance::Auto));
+    let listing = effective.to_string();
+    let last = listing.lines().last().unwrap();
+    assert!(last.starts_with("kv_cache_dtype  = f16"), "{listing}");
+    assert!(last.ends_with("(auto)"), "{listing}");
+}
+
+#[test]
+fn estimate_matches_what_sessions_allocate() {
+    let model = load("kv_policy_estimate");
+    for dtype in [CacheDtype::F32, CacheDtype::F16, CacheDtype::Q8] {
+        let engine = EngineConfig {
+            kv_cache_dtype: dtype,
+            ..EngineConfig::default()
+        };
+        let mut session = InferenceSession::with_config(&model, &engine).expect("session");
+        assert_eq!(
+            session.kv_cache_bytes(),
+            kv_cache_bytes(model.config(), model.config().context_length, dtype),
+            "{dtype:?}"
+        );
+        let state = session.prefill(&[1, 4, 9]).expect("prefill");
+        let logits = session.logits_last_token(&state).expect("logits");
+        assert!(logits.iter().all(|x| x.is_finite()), "{dtype:?}");
+    }
+}
@@ -0,0 +1,82 @@
+//! Log levels of a model load, captured with a test logger: one `info` milestone at each end,
+//! per-tensor progress at `debug` only, and an `error` when loading fails.
+
+mod common;
+
+use std::sync::Mutex;
+
+use inference_engine_rust::model_loader::file_loader::read_file;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use common::gguf_fixture::{TINY_LAYERS, tiny_llama};
+
+static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+/// Tests share the one global logger; run them one at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+struct Capture;
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with("inference_engine_rust") {
+            let line = record.args().to_string();
+            RECORDS.lock().unwrap().push((record.level(), line));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<(Level, String)>) {
+    let _ = log::set_logger(&Capture);
+    log::set_max_level(LevelFilter::Trace);
+    RECORDS.lock().unwrap().clear();
+    let out = f();
+    (out, std::mem::take(&mut *RECORDS.lock().unwrap()))
+}
+
+#[test]
+fn per_tensor_progress_is_debug_and_milestones_are_info() {
+    let _serial = SERIAL.lock().unwrap();
+    let path = tiny_llama().write("logging_levels");
+    let path = path.to_str().unwrap();
+    let mut gguf = read_file(path).unwrap();
+    let total = gguf.total_tensors() as usize;
+    assert!(total > TINY_LAYERS);
+
+    let (result, records) = capture(|| gguf.load_tensors(path));
+    result.unwrap();
+    let per_tensor: Vec<_> = records
+        .iter()
+        .filter(|(_, line)| line.starts_with("Loading tensor"))
+        .collect();
+    assert_eq!(per_tensor.len(), total, "{records:?}");
+    assert!(per_tensor.iter().all(|(level, _)| *level == Level::Debug));
+
+    let info: Vec<_> = records
+        .iter()
+        .filter(|(level, _)| *level <= Level::Info)
+        .map(|(level, line)| (*level, line.as_str()))
+        .collect();
+    assert_eq!(info.len(), 2, "{info:?}");
+    assert!(info.iter().all(|(level, _)| *level == Level::Info));
+    assert!(info[0].1.ends_with(&format!("loading {total} tensors")));
+    assert!(info[1].1.contains(&format!("loaded {total} tensors")));
+}
+
+#[test]
+fn failures_are_logged_at_error() {
+    let _serial = SERIAL.lock().unwrap();
+    let (result, records) = capture(|| read_file("/nonexistent/logging_missing.gguf"));
+    assert!(result.is_err());
+    let errors: Vec<_> = records
+        .iter()
+        .filter(|(level, _)| *level == Level::Error)
+        .collect();
+    assert_eq!(errors.len(), 1, "{records:?}");
+    assert!(errors[0].1.contains("logging_missing.gguf"), "{records:?}");
+}
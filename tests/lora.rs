@@ -0,0 +1,230 @@
+//! LoRA adapters on the two-layer synthetic model: the runtime low-rank path matches a model with
+//! the deltas merged into its weights by hand, adapters compose, removing them restores the base
+//! outputs exactly, and adapters that do not fit the base are rejected.
+
+mod common;
+
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::layers::lora::{LoraAdapter, LoraTarget};
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+
+use common::gguf_fixture::{GgufFixture, TINY_FFN, TINY_HIDDEN, tiny_llama};
+
+const PROMPT: [u32; 4] = [1, 5, 6, 7];
+const DECODE: [u32; 2] = [8, 9];
+const RANK: usize = 2;
+
+/// One adapted weight: layer, target and its `[in, out]` dims in the base.
+struct Delta {
+    layer: usize,
+    target: LoraTarget,
+    k: usize,
+    n: usize,
+    a: Vec<f32>,
+    b: Vec<f32>,
+}
+
+impl Delta {
+    fn new(layer: usize, target: LoraTarget, k: usize, n: usize, seed: u32) -> Self {
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        };
+        let a = (0..k * RANK).map(|_| next()).collect();
+        let b = (0..RANK * n).map(|_| next()).collect();
+        Self {
+            layer,
+            target,
+            k,
+            n,
+            a,
+            b,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("blk.{}.{}.weight", self.layer, self.target)
+    }
+
+    /// `W(kk, col) += scale · Σ_r A(kk, r) · B(r, col)`, in ggml layout (`ne0` contiguous).
+    fn merge_into(&self, w: &mut [f32], scale: f32) {
+        for col in 0..self.n {
+            for kk in 0..self.k {
+                let delta: f32 = (0..RANK)
+                    .map(|r| self.a[r * self.k + kk] * self.b[col * RANK + r])
+                    .sum();
+                w[col * self.k + kk] += scale * delta;
+            }
+        }
+    }
+}
+
+fn deltas() -> Vec<Delta> {
+    vec![
+        Delta::new(0, LoraTarget::Q, TINY_HIDDEN, TINY_HIDDEN, 1),
+        Delta::new(0, LoraTarget::V, TINY_HIDDEN, TINY_HIDDEN / 2, 2),
+        Delta::new(1, LoraTarget::Output, TINY_HIDDEN, TINY_HIDDEN, 3),
+        Delta::new(1, LoraTarget::Down, TINY_FFN, TINY_HIDDEN, 4),
+    ]
+}
+
+fn adapter_fixture(deltas: &[Delta], alpha: Option<f32>) -> GgufFixture {
+    let mut f = GgufFixture::new()
+        .kv("general.architecture", Data::String("llama".into()))
+        .kv("general.type", Data::String("adapter".into()))
+        .kv("adapter.type", Data::String("lora".into()));
+    if let Some(alpha) = alpha {
+        f = f.kv("adapter.lora.alpha", Data::Float32(alpha));
+    }
+    for d in deltas {
+        f = f
+            .f32_tensor(
+                &format!("{}.lora_a", d.name()),
+                &[d.k as u64, RANK as u64],
+                &d.a,
+            )
+            .f32_tensor(
+                &format!("{}.lora_b", d.name()),
+                &[RANK as u64, d.n as u64],
+                &d.b,
+            );
+    }
+    f
+}
+
+/// The base fixture with `scale`-weighted deltas of every `(deltas, scale)` merged in.
+fn merged_model(base: &LoadedModel, adapters: &[(&[Delta], f32)], stem: &str) -> LoadedModel {
+    let mut fixture = tiny_llama();
+    let mut merged: Vec<(String, Vec<usize>, Vec<f32>)> = Vec::new();
+    for (deltas, scale) in adapters {
+        for d in deltas.iter() {
+            let name = d.name();
+            let index = match merged.iter().position(|(n, _, _)| *n == name) {
+                Some(i) => i,
+                None => {
+                    let t = base.gguf().get_tensor(&name).unwrap();
+                    let values = t.as_f32_slice().unwrap().to_vec();
+                    merged.push((name, t.dimensions().to_vec(), values));
+                    merged.len() - 1
+                }
+            };
+            d.merge_into(&mut merged[index].2, *scale);
+        }
+    }
+    for (name, dims, values) in &merged {
+        let dims: Vec<u64> = dims.iter().map(|&d| d as u64).collect();
+        fixture = fixture.without_tensor(name).f32_tensor(name, &dims, values);
+    }
+    LoadedModel::load(fixture.write(stem)).unwrap()
+}
+
+/// Prefill then decode logits.
+fn run(session: &mut InferenceSession<'_>) -> Vec<Vec<f32>> {
+    session.reset();
+    let state = session.prefill(&PROMPT).unwrap();
+    let mut logits = vec![session.logits_last_token(&state).unwrap()];
+    for token in DECODE {
+        let state = session.decode_token(token).unwrap();
+        logits.push(session.logits_last_token(&state).unwrap());
+    }
+    logits
+}
+
+fn assert_close(a: &[Vec<f32>], b: &[Vec<f32>]) {
+    for (x, y) in a.iter().flatten().zip(b.iter().flatten()) {
+        assert!((x - y).abs() <= 1e-4 * y.abs().max(1.0), "{x} vs {y}");
+    }
+}
+
+#[test]
+fn runtime_adapter_matches_the_hand_merged_model() {
+    let base = LoadedModel::load(tiny_llama().write("lora_base")).unwrap();
+    let deltas = deltas();
+    // alpha 1 at rank 2 halves the caller's scale.
+    let path = adapter_fixture(&deltas, Some(1.0)).write("lora_adapter");
+    let adapter = LoraAdapter::load(&path).unwrap();
+    assert_eq!(adapter.alpha(), Some(1.0));
+    assert_eq!(adapter.pairs().count(), deltas.len());
+
+    let mut session = InferenceSession::new(&base).unwrap();
+    let baseline = run(&mut session);
+    session.apply_lora(&path, 0.8).unwrap();
+    let adapted = run(&mut session);
+    assert_ne!(adapted, baseline);
+
+    let merged = merged_model(&base, &[(&deltas, 0.4)], "lora_merged");
+    let reference = run(&mut InferenceSession::new(&merged).unwrap());
+    assert_close(&adapted, &reference);
+
+    session.remove_lora();
+    assert_eq!(run(&mut session), baseline, "removal is exact");
+}
+
+#[test]
+fn adapters_compose_additively() {
+    let base = LoadedModel::load(tiny_llama().write("lora_compose_base")).unwrap();
+    let first = deltas();
+    let second = vec![
+        Delta::new(0, LoraTarget::Q, TINY_HIDDEN, TINY_HIDDEN, 11),
+        Delta::new(1, LoraTarget::Gate, TINY_HIDDEN, TINY_FFN, 12),
+    ];
+    let first_path = adapter_fixture(&first, None).write("lora_compose_a");
+    let second_path = adapter_fixture(&second, None).write("lora_compose_b");
+
+    let mut session = InferenceSession::new(&base).unwrap();
+    session.apply_lora(&first_path, 0.5).unwrap();
+    session.apply_lora(&second_path, -0.3).unwrap();
+    let adapted = run(&mut session);
+
+    let merged = merged_model(
+        &base,
+        &[(&first, 0.5), (&second, -0.3)],
+        "lora_compose_merged",
+    );
+    assert_close(&adapted, &run(&mut InferenceSession::new(&merged).unwrap()));
+}
+
+#[test]
+fn adapters_that_do_not_fit_are_rejected() {
+    let base = LoadedModel::load(tiny_llama().write("lora_reject_base")).unwrap();
+    let mut session = InferenceSession::new(&base).unwrap();
+    let baseline = run(&mut session);
+
+    // A Q delta shaped for a wider model.
+    let wide = [Delta::new(
+        0,
+        LoraTarget::Q,
+        TINY_HIDDEN * 2,
+        TINY_HIDDEN,
+        5,
+    )];
+    let err = session
+        .apply_lora(adapter_fixture(&wide, None).write("lora_wide"), 1.0)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("does not fit the base weight"),
+        "{err}"
+    );
+
+    let past_the_end = [Delta::new(9, LoraTarget::Up, TINY_HIDDEN, TINY_FFN, 6)];
+    let err = session
+        .apply_lora(
+            adapter_fixture(&past_the_end, None).write("lora_layer"),
+            1.0,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("targets layer 9"), "{err}");
+
+    let half = adapter_fixture(&[], None).f32_tensor(
+        "blk.0.attn_k.weight.lora_a",
+        &[TINY_HIDDEN as u64, RANK as u64],
+        &[0.0; TINY_HIDDEN * RANK],
+    );
+    let err = LoraAdapter::load(half.write("lora_half")).unwrap_err();
+    assert!(err.to_string().contains("only one of"), "{err}");
+
+    // Failed applications leave the session on the base model.
+    assert_eq!(run(&mut session), baseline);
+}
@@ -0,0 +1,34 @@
+//! Allocator accounting (`cargo test --features mem-profile --test mem_profile`).
+#![cfg(feature = "mem-profile")]
+
+mod common;
+
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::mem_profile::memory_stats;
+
+use common::gguf_fixture::{tiny_llama, tiny_llama_tensor_bytes};
+
+#[test]
+fn loading_fixture_model_registers_its_weights() {
+    let path = tiny_llama().write("mem_profile_load");
+    let tensor_bytes = tiny_llama_tensor_bytes();
+
+    let before = memory_stats().expect("mem-profile enabled");
+    let model = LoadedModel::load(&path).expect("load fixture model");
+    let after = memory_stats().expect("mem-profile enabled");
+
+    assert!(before.peak_bytes >= before.current_bytes);
+    assert!(after.peak_bytes >= after.current_bytes);
+    assert!(after.alloc_count > before.alloc_count);
+
+    // Resident weights plus metadata: at least the tensor blob, and the same order of magnitude.
+    let retained = after.current_bytes.saturating_sub(before.current_bytes);
+    assert!(
+        retained >= tensor_bytes && retained < tensor_bytes * 10,
+        "retained {retained} bytes for {tensor_bytes} bytes of tensors"
+    );
+    // The 1 MiB read buffer is transient, so the peak must have covered it.
+    assert!(after.peak_bytes >= before.current_bytes + (1 << 20));
+
+    drop(model);
+}
@@ -0,0 +1,182 @@
+//! The model alias registry on fixture GGUF files: add / list / remove and persistence, alias
+//! collisions, a moved file, and refreshing an entry whose file changed in place.
+
+mod common;
+
+use std::path::PathBuf;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::model_loader::registry::{
+    ModelRegistry, RegistryError, content_hash, resolve_model,
+};
+
+use common::gguf_fixture::{GgufFixture, tiny_llama};
+
+/// A fresh directory under the system temp dir, for one test's models and registry.
+fn scratch(stem: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "inference_engine_rust_registry_{stem}_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(fixture: &GgufFixture, path: &PathBuf) {
+    std::fs::write(path, fixture.to_bytes()).unwrap();
+}
+
+fn registry_error(e: EngineError) -> RegistryError {
+    match e {
+        EngineError::Registry(e) => e,
+        other => panic!("expected a registry error, got {other}"),
+    }
+}
+
+#[test]
+fn add_list_remove_round_trip_through_the_file() {
+    let dir = scratch("round_trip");
+    let model = dir.join("tiny.gguf");
+    write(
+        &tiny_llama().kv("general.file_type", Data::Uint32(7)),
+        &model,
+    );
+    let registry_path = dir.join("config").join("models.json");
+
+    let mut registry = ModelRegistry::open(&registry_path).unwrap();
+    assert_eq!(registry.entries().count(), 0);
+    let entry = registry.add("tiny", &model).unwrap().clone();
+    assert!(entry.path.is_absolute());
+    assert_eq!(entry.size, std::fs::metadata(&model).unwrap().len());
+    assert_eq!(entry.hash, content_hash(&model).unwrap());
+    assert_eq!(entry.architecture.as_deref(), Some("llama"));
+    assert_eq!(entry.file_type, Some(7));
+    assert!(entry.parameter_count > 0);
+    // The directory form resolves to the same file.
+    registry.add("tiny-dir", &dir).unwrap();
+    registry.save().unwrap();
+
+    let mut reopened = ModelRegistry::open(&registry_path).unwrap();
+    let aliases: Vec<&str> = reopened.entries().map(|(a, _)| a).collect();
+    assert_eq!(aliases, ["tiny", "tiny-dir"]);
+    assert_eq!(reopened.get("tiny"), Some(&entry));
+    assert_eq!(reopened.get("tiny-dir").unwrap().path, entry.path);
+
+    let resolved = resolve_model("tiny".as_ref(), &registry_path).unwrap();
+    assert_eq!(resolved.primary(), entry.path);
+
+    assert_eq!(reopened.remove("tiny").unwrap(), entry);
+    assert_eq!(
+        reopened.remove("tiny"),
+        Err(RegistryError::UnknownAlias("tiny".into()))
+    );
+    reopened.save().unwrap();
+    assert!(
+        ModelRegistry::open(&registry_path)
+            .unwrap()
+            .get("tiny")
+            .is_none()
+    );
+    assert!(model.is_file(), "removing an alias keeps the file");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn taken_and_malformed_aliases_are_rejected() {
+    let dir = scratch("collisions");
+    let (a, b) = (dir.join("a.gguf"), dir.join("b.gguf"));
+    write(&tiny_llama(), &a);
+    write(&tiny_llama(), &b);
+    let mut registry = ModelRegistry::open(dir.join("models.json")).unwrap();
+    registry.add("m", &a).unwrap();
+
+    let err = registry_error(registry.add("m", &b).unwrap_err());
+    let RegistryError::AliasTaken { alias, path } = &err else {
+        panic!("{err}");
+    };
+    assert_eq!(
+        (alias.as_str(), path),
+        ("m", &std::path::absolute(&a).unwrap())
+    );
+    assert!(err.to_string().contains("remove it first"), "{err}");
+    assert_eq!(
+        registry.get("m").unwrap().path,
+        std::path::absolute(&a).unwrap()
+    );
+
+    for bad in ["", "dir/m", "*.gguf"] {
+        assert_eq!(
+            registry_error(registry.add(bad, &b).unwrap_err()),
+            RegistryError::InvalidAlias(bad.into())
+        );
+    }
+    assert!(matches!(
+        registry.add("missing", &dir.join("nope.gguf")),
+        Err(EngineError::Model(_))
+    ));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn moved_file_is_an_error_naming_the_alias() {
+    let dir = scratch("moved");
+    let model = dir.join("tiny.gguf");
+    write(&tiny_llama(), &model);
+    let registry_path = dir.join("models.json");
+    let mut registry = ModelRegistry::open(&registry_path).unwrap();
+    registry.add("tiny", &model).unwrap();
+    registry.save().unwrap();
+    std::fs::rename(&model, dir.join("elsewhere.gguf")).unwrap();
+
+    let err = registry_error(registry.lookup("tiny").unwrap_err());
+    assert_eq!(
+        err,
+        RegistryError::Missing {
+            alias: "tiny".into(),
+            path: std::path::absolute(&model).unwrap(),
+        }
+    );
+    assert!(err.to_string().contains("add it again"), "{err}");
+    let via_cli = resolve_model("tiny".as_ref(), &registry_path).unwrap_err();
+    assert!(matches!(
+        via_cli,
+        EngineError::Registry(RegistryError::Missing { .. })
+    ));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn changed_file_refreshes_its_entry() {
+    let dir = scratch("stale");
+    let model = dir.join("tiny.gguf");
+    write(&tiny_llama(), &model);
+    let registry_path = dir.join("models.json");
+    let mut registry = ModelRegistry::open(&registry_path).unwrap();
+    let before = registry.add("tiny", &model).unwrap().clone();
+    registry.save().unwrap();
+
+    let unchanged = registry.lookup("tiny").unwrap();
+    assert!(!unchanged.refreshed);
+    assert_eq!(unchanged.entry, before);
+
+    // Same size, different header: only the hash can tell.
+    write(
+        &tiny_llama()
+            .without_kv("general.architecture")
+            .kv("general.architecture", Data::String("llamb".into())),
+        &model,
+    );
+    assert_eq!(std::fs::metadata(&model).unwrap().len(), before.size);
+    resolve_model("tiny".as_ref(), &registry_path).unwrap();
+    let saved = ModelRegistry::open(&registry_path).unwrap();
+    let after = saved.get("tiny").unwrap();
+    assert_ne!(after.hash, before.hash);
+    assert_eq!(after.architecture.as_deref(), Some("llamb"));
+
+    let mut again = saved.clone();
+    let lookup = again.lookup("tiny").unwrap();
+    assert!(!lookup.refreshed, "the refreshed entry was saved");
+    let _ = std::fs::remove_dir_all(dir);
+}
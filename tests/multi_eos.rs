@@ -0,0 +1,126 @@
+//! Several terminator ids (EOS plus `tokenizer.ggml.eot_token_id` / `eom_token_id`, and a chat
+//! style's turn end) on the synthetic model: whichever is sampled first stops generation and is
+//! reported in `FinishReason::Eos`.
+
+mod common;
+
+use inference_engine_rust::chat_prompt::ChatPromptStyle;
+use inference_engine_rust::engine::chat_session::ChatSession;
+use inference_engine_rust::engine::generation::{
+    FinishReason, GenerationConfig, GenerationOutput, generate_from_ids,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::token_iter::TokenIter;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::tokenizer::Tokenizer;
+
+use common::gguf_fixture::{GgufFixture, tiny_llama, tiny_vocab, write_tiny_tokenizer};
+
+const PROMPT: [u32; 3] = [1, 5, 9];
+
+fn config() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 12,
+        ..GenerationConfig::default()
+    }
+}
+
+fn generate(fixture: GgufFixture, stem: &str) -> (GenerationOutput, Vec<u32>) {
+    let path = fixture.write(stem);
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let out = generate_from_ids(&mut session, &PROMPT, &[], &config()).unwrap();
+    let eot = model.tokenizer_prompt().eot_token_ids.clone();
+    (out, eot)
+}
+
+/// Indices of tokens that do not occur earlier in `ids` (a terminator set to one of them stops
+/// generation exactly there).
+fn first_occurrences(ids: &[u32]) -> Vec<usize> {
+    (1..ids.len())
+        .filter(|&i| !ids[..i].contains(&ids[i]))
+        .collect()
+}
+
+#[test]
+fn either_terminator_stops_generation_and_is_reported() {
+    let (base, eot) = generate(tiny_llama(), "multi_eos_base");
+    assert!(eot.is_empty());
+    assert_eq!(base.finish_reason, FinishReason::Length);
+    let seq = base.generated_token_ids;
+    let firsts = first_occurrences(&seq);
+    assert!(firsts.len() >= 2, "fixture output too repetitive: {seq:?}");
+    let (a, b) = (firsts[0], firsts[1]);
+
+    // EOT fires first.
+    let fixture = tiny_llama()
+        .kv("tokenizer.ggml.eot_token_id", Data::Uint32(seq[a]))
+        .kv("tokenizer.ggml.eom_token_id", Data::Uint32(seq[b]));
+    let (out, eot) = generate(fixture, "multi_eos_eot");
+    assert_eq!(eot, [seq[a], seq[b]]);
+    assert_eq!(out.generated_token_ids, seq[..a]);
+    assert_eq!(out.finish_reason, FinishReason::Eos { token_id: seq[a] });
+
+    // EOM alone.
+    let fixture = tiny_llama().kv("tokenizer.ggml.eom_token_id", Data::Uint32(seq[b]));
+    let (out, _) = generate(fixture, "multi_eos_eom");
+    assert_eq!(out.generated_token_ids, seq[..b]);
+    assert_eq!(out.finish_reason, FinishReason::Eos { token_id: seq[b] });
+
+    // A caller stop id joins the model's terminators.
+    let path = tiny_llama()
+        .kv("tokenizer.ggml.eom_token_id", Data::Uint32(seq[b]))
+        .write("multi_eos_iter");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let tokenizer = Tokenizer::load_from_file(write_tiny_tokenizer("multi_eos_iter")).unwrap();
+    let extra = GenerationConfig {
+        stop_token_ids: vec![seq[a]],
+        ..config()
+    };
+    let stops = extra.stop_tokens(model.tokenizer_prompt());
+    assert_eq!(stops.ids().len(), 3);
+    assert!(stops.contains(2) && stops.contains(seq[a]) && stops.contains(seq[b]));
+
+    let mut iter = TokenIter::new(&mut session, &tokenizer, &PROMPT, &extra);
+    assert_eq!(iter.finish_reason(), None);
+    let ids: Vec<u32> = iter.by_ref().map(|t| t.unwrap().id).collect();
+    assert_eq!(ids, seq[..a]);
+    assert_eq!(
+        iter.finish_reason(),
+        Some(FinishReason::Eos { token_id: seq[a] })
+    );
+}
+
+#[test]
+fn chat_session_stops_on_the_styles_turn_end() {
+    let config = GenerationConfig {
+        max_new_tokens: 8,
+        ..GenerationConfig::default()
+    };
+    let reply = |fixture: GgufFixture, stem: &str| {
+        let path = fixture.write(stem);
+        let model = LoadedModel::load(&path).unwrap();
+        let tokenizer = Tokenizer::load_from_file(write_tiny_tokenizer(stem)).unwrap();
+        let mut chat = ChatSession::new(&model, tokenizer, ChatPromptStyle::Gemma4E2b).unwrap();
+        chat.send("w3 w4", &config).unwrap()
+    };
+
+    let base = reply(tiny_llama(), "multi_eos_chat_base");
+    let seq = base.generated_token_ids;
+    let k = *first_occurrences(&seq)
+        .first()
+        .unwrap_or_else(|| panic!("fixture output too repetitive: {seq:?}"));
+
+    // Same weights, but token `seq[k]` is Gemma's `<turn|>`.
+    let mut vocab = tiny_vocab();
+    vocab[seq[k] as usize] = "<turn|>".into();
+    let tokens = Data::Array(vocab.into_iter().map(Data::String).collect());
+    let out = reply(
+        tiny_llama().kv("tokenizer.ggml.tokens", tokens),
+        "multi_eos_chat_turn",
+    );
+    assert_eq!(out.generated_token_ids, seq[..k]);
+    assert_eq!(out.finish_reason, FinishReason::Eos { token_id: seq[k] });
+}
@@ -0,0 +1,200 @@
+//! `EngineObserver` events on the synthetic model: the full sequence for a generation that has
+//! to shift its context, load events, cancellation from `stream_text`, and errors.
+
+mod common;
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+use std::time::Duration;
+
+use inference_engine_rust::engine::budget::ContextShift;
+use inference_engine_rust::engine::deadline::ManualClock;
+use inference_engine_rust::engine::generation::{
+    FinishReason, GenerationConfig, generate_from_ids,
+};
+use inference_engine_rust::engine::observer::{
+    Cancelled, ContextShifted, EngineEvent, GenerationFinished, GenerationStarted,
+    ObserverToChannel, Operation, PrefillProgress, ShiftReason,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::LoadOptions;
+use inference_engine_rust::tokenizer::{Granularity, Tokenizer};
+
+use common::gguf_fixture::{TINY_CONTEXT, tiny_llama, write_tiny_tokenizer};
+
+/// Recording observer: the session sends, the test drains.
+fn recorder() -> (Arc<ObserverToChannel>, Receiver<EngineEvent>) {
+    let (tx, rx) = channel();
+    (Arc::new(ObserverToChannel::new(tx)), rx)
+}
+
+/// The tiny model without an EOS id, so greedy decoding always runs to `max_new_tokens`.
+fn model(stem: &str) -> LoadedModel {
+    LoadedModel::load(
+        tiny_llama()
+            .without_kv("tokenizer.ggml.eos_token_id")
+            .write(stem),
+    )
+    .unwrap()
+}
+
+#[test]
+fn generation_with_a_forced_context_shift_reports_every_event_in_order() {
+    let model = model("observer_shift");
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.set_clock(Arc::new(ManualClock::ticking(Duration::from_millis(5))));
+    let (observer, rx) = recorder();
+    session.set_observer(observer);
+    session.set_context_shift(Some(ContextShift {
+        keep: 4,
+        discard: 16,
+    }));
+
+    // Two tokens short of the context: the third decode needs a shift.
+    let prompt: Vec<u32> = (0..TINY_CONTEXT as u32 - 2).map(|i| 3 + i % 25).collect();
+    let config = GenerationConfig {
+        max_new_tokens: 5,
+        ..GenerationConfig::default()
+    };
+    let out = generate_from_ids(&mut session, &prompt, &[], &config).unwrap();
+    assert_eq!(out.generated_token_ids.len(), 5);
+    // Prompt and four fed tokens, less the 16 dropped.
+    assert_eq!(session.position(), TINY_CONTEXT + 2 - 16);
+
+    let events: Vec<EngineEvent> = rx.try_iter().collect();
+    let tokens = |range: std::ops::Range<usize>| -> Vec<u32> {
+        range.map(|i| out.generated_token_ids[i]).collect()
+    };
+    let ids = |events: &[EngineEvent]| -> Vec<u32> {
+        events
+            .iter()
+            .map(|e| match e {
+                EngineEvent::Token(t) => t.id,
+                other => panic!("expected a token, got {other:?}"),
+            })
+            .collect()
+    };
+    assert_eq!(events.len(), 9, "{events:#?}");
+    assert_eq!(
+        events[0],
+        EngineEvent::GenerationStarted(GenerationStarted {
+            prompt_tokens: prompt.len(),
+            forced_tokens: 0,
+            max_new_tokens: 5,
+        })
+    );
+    assert_eq!(
+        events[1],
+        EngineEvent::PrefillProgress(PrefillProgress {
+            done: prompt.len(),
+            total: prompt.len(),
+        })
+    );
+    // Tokens 0 and 1 fill the cache; token 2 is sampled, then the cache shifts to feed it.
+    assert_eq!(ids(&events[2..5]), tokens(0..3));
+    assert_eq!(
+        events[5],
+        EngineEvent::ContextShift(ContextShifted {
+            discarded: 16,
+            kept: TINY_CONTEXT - 16,
+            reason: ShiftReason::ContextFull,
+        })
+    );
+    assert_eq!(ids(&events[6..8]), tokens(3..5));
+    let EngineEvent::GenerationFinished(GenerationFinished {
+        generated, reason, ..
+    }) = &events[8]
+    else {
+        panic!("{:?}", events[8]);
+    };
+    assert_eq!((*generated, *reason), (5, FinishReason::Length));
+    let logprobs: Vec<f32> = events
+        .iter()
+        .filter_map(|e| match e {
+            EngineEvent::Token(t) => Some(t.logprob),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(logprobs, out.generated_logprobs);
+
+    // Without a shift the same run fails at the full context; the error is the last event.
+    session.set_context_shift(None);
+    let err = generate_from_ids(&mut session, &prompt, &[], &config).unwrap_err();
+    assert!(err.to_string().contains("token budget exceeded"), "{err}");
+    let events: Vec<EngineEvent> = rx.try_iter().collect();
+    assert_eq!(events.len(), 6, "{events:#?}");
+    let Some(EngineEvent::Error(failed)) = events.last() else {
+        panic!("{events:#?}");
+    };
+    assert_eq!(failed.operation, Operation::Generation);
+    assert_eq!(failed.message, err.to_string());
+}
+
+#[test]
+fn load_reports_start_and_finish_or_error() {
+    let path = tiny_llama().write("observer_load");
+    let (observer, events) = recorder();
+    let model =
+        LoadedModel::load_observed(&path, &LoadOptions::default(), observer.as_ref()).unwrap();
+    let events: Vec<EngineEvent> = events.try_iter().collect();
+    assert_eq!(events.len(), 2, "{events:#?}");
+    assert!(
+        matches!(&events[0], EngineEvent::LoadStarted(e) if e.path == path.display().to_string())
+    );
+    let EngineEvent::LoadFinished(finished) = &events[1] else {
+        panic!("{events:#?}");
+    };
+    assert_eq!(finished.tensors, model.load_stats().tensors_loaded);
+    assert_eq!(finished.bytes_read, model.load_stats().bytes_read);
+
+    let (observer, events) = recorder();
+    let missing = path.with_file_name("observer_missing.gguf");
+    assert!(
+        LoadedModel::load_observed(&missing, &LoadOptions::default(), observer.as_ref()).is_err()
+    );
+    let events: Vec<EngineEvent> = events.try_iter().collect();
+    assert!(matches!(
+        events.as_slice(),
+        [EngineEvent::LoadStarted(_), EngineEvent::Error(e)] if e.operation == Operation::Load
+    ));
+}
+
+#[test]
+fn cancelling_a_stream_is_reported_after_the_last_token() {
+    let model = model("observer_cancel");
+    let tokenizer = Tokenizer::load_from_file(write_tiny_tokenizer("observer_cancel")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let (observer, events) = recorder();
+    session.set_observer(observer);
+    let config = GenerationConfig {
+        max_new_tokens: 8,
+        ..GenerationConfig::default()
+    };
+    let end = session
+        .stream_text(&tokenizer, &[1, 7, 8], &config, Granularity::Token, |_| {
+            ControlFlow::Break(())
+        })
+        .unwrap();
+    assert!(end.cancelled);
+
+    let events: Vec<EngineEvent> = events.try_iter().collect();
+    assert!(matches!(events[0], EngineEvent::GenerationStarted(_)));
+    assert!(matches!(events[1], EngineEvent::PrefillProgress(_)));
+    assert!(matches!(events[2], EngineEvent::Token(_)));
+    assert_eq!(
+        events[3..],
+        [EngineEvent::Cancelled(Cancelled {
+            generated: end.token_count
+        })]
+    );
+
+    // No observer: nothing to receive, same tokens.
+    session.clear_observer();
+    let quiet: Vec<u32> = session
+        .tokens(&tokenizer, &[1, 7, 8], &config)
+        .map(|t| t.unwrap().id)
+        .collect();
+    assert_eq!(quiet.len(), 8);
+}
@@ -0,0 +1,119 @@
+//! Vocab padding: embedding / LM head rows beyond the tokenizer's vocabulary are never sampled.
+
+mod common;
+
+use inference_engine_rust::engine::generation::{
+    GenerationConfig, generate_from_ids, greedy_next_token,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+
+use common::gguf_fixture::{
+    GgufFixture, TINY_HIDDEN, TINY_VOCAB, tiny_llama, tiny_llama_padded_vocab,
+};
+
+const TOKENS: usize = 32000;
+const ROWS: usize = 32128;
+
+#[test]
+fn padded_rows_are_reported_and_masked() {
+    let path = tiny_llama_padded_vocab(TOKENS, ROWS).write("padded_vocab");
+    let model = LoadedModel::load(&path).unwrap();
+    let config = model.config();
+    assert_eq!(config.vocab_size, ROWS);
+    assert_eq!(config.tokenizer_vocab_size, TOKENS);
+    assert_eq!(config.padding_token_count(), 128);
+
+    let mut session = InferenceSession::new(&model).unwrap();
+    let state = session.prefill(&[1, 7, 8, 9]).unwrap();
+    let logits = session.logits_last_token(&state).unwrap();
+    assert_eq!(logits.len(), ROWS);
+    assert!(logits[TOKENS..].iter().all(|&l| l == f32::NEG_INFINITY));
+    assert!(logits[..TOKENS].iter().all(|l| l.is_finite()));
+    assert_eq!(session.next_token_logits(&state).unwrap().len(), TOKENS);
+    assert!((greedy_next_token(&session, &state).unwrap() as usize) < TOKENS);
+
+    let sampled = GenerationConfig {
+        max_new_tokens: 16,
+        temperature: 5.0,
+        seed: 3,
+        ..GenerationConfig::default()
+    };
+    let out = generate_from_ids(&mut session, &[1, 7, 8, 9], &[], &sampled).unwrap();
+    assert!(!out.generated_token_ids.is_empty());
+    assert!(
+        out.generated_token_ids
+            .iter()
+            .all(|&id| (id as usize) < TOKENS)
+    );
+}
+
+#[test]
+fn unpadded_model_has_no_padding() {
+    let path = tiny_llama().write("padded_vocab_none");
+    let model = LoadedModel::load(&path).unwrap();
+    assert_eq!(model.config().tokenizer_vocab_size, TINY_VOCAB);
+    assert_eq!(model.config().padding_token_count(), 0);
+}
+
+#[test]
+fn trailing_unused_token_types_count_as_padding() {
+    const TOKEN_TYPE_NORMAL: i32 = 1;
+    const TOKEN_TYPE_UNUSED: i32 = 5;
+    let types = (0..40)
+        .map(|i| {
+            Data::Int32(if i < 36 {
+                TOKEN_TYPE_NORMAL
+            } else {
+                TOKEN_TYPE_UNUSED
+            })
+        })
+        .collect();
+    let path = tiny_llama_padded_vocab(40, 40)
+        .kv("tokenizer.ggml.token_type", Data::Array(types))
+        .write("padded_vocab_unused");
+    let model = LoadedModel::load(&path).unwrap();
+    assert_eq!(model.config().vocab_size, 40);
+    assert_eq!(model.config().tokenizer_vocab_size, 36);
+    assert_eq!(model.config().padding_token_count(), 4);
+}
+
+/// [`tiny_llama`] with an `output.weight` of `rows` rows, the embedding left at [`TINY_VOCAB`].
+fn with_head_rows(rows: usize) -> GgufFixture {
+    let values: Vec<f32> = (0..TINY_HIDDEN * rows)
+        .map(|i| ((i * 7 % 13) as f32 - 6.0) * 0.05)
+        .collect();
+    tiny_llama().without_tensor("output.weight").f32_tensor(
+        "output.weight",
+        &[TINY_HIDDEN as u64, rows as u64],
+        &values,
+    )
+}
+
+#[test]
+fn head_wider_than_the_embedding_is_cut_to_the_shared_rows() {
+    let path = with_head_rows(TINY_VOCAB + 8).write("padded_vocab_head");
+    let model = LoadedModel::load(&path).unwrap();
+    assert_eq!(model.config().vocab_size, TINY_VOCAB);
+    assert_eq!(model.config().padding_token_count(), 0);
+
+    let mut session = InferenceSession::new(&model).unwrap();
+    let state = session.prefill(&[1, 7, 8, 9]).unwrap();
+    assert_eq!(session.logits_last_token(&state).unwrap().len(), TINY_VOCAB);
+    assert!((greedy_next_token(&session, &state).unwrap() as usize) < TINY_VOCAB);
+}
+
+#[test]
+fn head_smaller_than_the_tokenizer_vocab_is_rejected() {
+    let path = with_head_rows(TINY_VOCAB - 4).write("padded_vocab_short_head");
+    let err = LoadedModel::load(&path)
+        .err()
+        .expect("a head missing tokenizer rows must not load");
+    let msg = err.to_string();
+    assert!(
+        msg.contains(&format!("tokenizer defines {TINY_VOCAB} tokens")),
+        "{msg}"
+    );
+    assert!(msg.contains(&format!("{} rows", TINY_VOCAB - 4)), "{msg}");
+}
@@ -0,0 +1,211 @@
+//! Quality telemetry on the synthetic model: `QualityStats` against values recomputed from each
+//! step's logits, `quality_alert` events when a threshold is set to trip, and the same tracking
+//! through `TokenIter` and `stream_text`.
+
+mod common;
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::observer::{EngineEvent, ObserverToChannel};
+use inference_engine_rust::engine::quality::{NEAR_ARGMAX_NATS, QualitySignal, QualityThresholds};
+use inference_engine_rust::engine::sampling::token_logprob;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::{Granularity, Tokenizer};
+
+use common::gguf_fixture::{tiny_llama, write_tiny_tokenizer};
+
+const PROMPT: [u32; 4] = [1, 5, 9, 12];
+
+/// The tiny model without an EOS id, so generation always runs to `max_new_tokens`.
+fn model(stem: &str) -> LoadedModel {
+    LoadedModel::load(
+        tiny_llama()
+            .without_kv("tokenizer.ggml.eos_token_id")
+            .write(stem),
+    )
+    .unwrap()
+}
+
+fn sampled(thresholds: QualityThresholds) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 6,
+        temperature: 1.5,
+        seed: 11,
+        quality: Some(thresholds),
+        ..GenerationConfig::default()
+    }
+}
+
+fn entropy(logits: &[f32]) -> f64 {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max) as f64;
+    let z: f64 = logits.iter().map(|&l| (l as f64 - max).exp()).sum();
+    -logits
+        .iter()
+        .map(|&l| {
+            let lp = l as f64 - max - z.ln();
+            lp.exp() * lp
+        })
+        .sum::<f64>()
+}
+
+#[test]
+fn stats_match_values_recomputed_from_each_step() {
+    let model = model("quality_stats");
+    let mut session = InferenceSession::new(&model).unwrap();
+    let window = 4;
+    let out = generate_from_ids(
+        &mut session,
+        &PROMPT,
+        &[],
+        &sampled(QualityThresholds {
+            window,
+            mean_surprise: None,
+            mean_entropy: None,
+        }),
+    )
+    .unwrap();
+    let q = out.stats.quality.expect("quality requested");
+    let ids = &out.generated_token_ids;
+    assert_eq!(ids.len(), 6);
+
+    // Replay the generation by hand to get each step's logits.
+    let mut replay = InferenceSession::new(&model).unwrap();
+    let mut state = replay.prefill(&PROMPT).unwrap();
+    let (mut surprises, mut entropies, mut near) = (Vec::new(), Vec::new(), 0);
+    for &id in ids {
+        let logits = replay.next_token_logits(&state).unwrap();
+        surprises.push(-token_logprob(&logits, id).unwrap() as f64);
+        entropies.push(entropy(&logits));
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        near += usize::from(logits[id as usize] >= max - NEAR_ARGMAX_NATS);
+        state = replay.decode_token(id).unwrap();
+    }
+    let mean = |v: &[f64]| (v.iter().sum::<f64>() / v.len() as f64) as f32;
+    let max = |v: &[f64]| v.iter().copied().fold(0.0, f64::max) as f32;
+    let close = |a: f32, b: f32| assert!((a - b).abs() < 1e-4, "{a} vs {b}");
+
+    assert_eq!(q.tokens, 6);
+    close(q.mean_surprise, mean(&surprises));
+    close(q.max_surprise, max(&surprises));
+    close(q.rolling_mean_surprise, mean(&surprises[6 - window..]));
+    close(q.mean_entropy, mean(&entropies));
+    close(q.max_entropy, max(&entropies));
+    close(q.rolling_mean_entropy, mean(&entropies[6 - window..]));
+    assert_eq!((q.near_argmax, q.tail), (near, 6 - near));
+    assert_eq!(q.alerts, 0);
+    // Surprise is under the unscaled logits, the same as the reported logprobs.
+    let from_logprobs: Vec<f64> = out.generated_logprobs.iter().map(|&l| -l as f64).collect();
+    close(q.mean_surprise, mean(&from_logprobs));
+
+    let plain = generate_from_ids(
+        &mut session,
+        &PROMPT,
+        &[],
+        &GenerationConfig {
+            quality: None,
+            ..sampled(QualityThresholds::default())
+        },
+    )
+    .unwrap();
+    assert_eq!(plain.stats.quality, None);
+    assert_eq!(plain.generated_token_ids, out.generated_token_ids);
+}
+
+#[test]
+fn crossing_a_threshold_reports_one_alert_per_crossing() {
+    let model = model("quality_alert");
+    let mut session = InferenceSession::new(&model).unwrap();
+    let (tx, rx) = channel();
+    session.set_observer(Arc::new(ObserverToChannel::new(tx)));
+
+    // Every sample has some surprise, so a zero threshold trips as soon as the window fills and
+    // stays tripped; an unreachable entropy limit never fires.
+    let out = generate_from_ids(
+        &mut session,
+        &PROMPT,
+        &[],
+        &sampled(QualityThresholds {
+            window: 2,
+            mean_surprise: Some(0.0),
+            mean_entropy: Some(1e6),
+        }),
+    )
+    .unwrap();
+    let alerts: Vec<_> = rx
+        .try_iter()
+        .filter_map(|e| match e {
+            EngineEvent::QualityAlert(a) => Some(a),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(alerts.len(), 1, "{alerts:?}");
+    let alert = &alerts[0];
+    assert_eq!(alert.index, 1);
+    assert_eq!(alert.signal, QualitySignal::MeanSurprise);
+    assert_eq!((alert.threshold, alert.window), (0.0, 2));
+    let lps = &out.generated_logprobs;
+    assert!((alert.value - -(lps[0] + lps[1]) / 2.0).abs() < 1e-5);
+    assert_eq!(out.stats.quality.unwrap().alerts, 1);
+
+    // Nothing fires below the thresholds.
+    generate_from_ids(
+        &mut session,
+        &PROMPT,
+        &[],
+        &sampled(QualityThresholds {
+            window: 2,
+            mean_surprise: Some(1e6),
+            mean_entropy: None,
+        }),
+    )
+    .unwrap();
+    assert!(
+        !rx.try_iter()
+            .any(|e| matches!(e, EngineEvent::QualityAlert(_)))
+    );
+}
+
+#[test]
+fn token_iter_and_stream_text_track_quality_like_generate() {
+    let path = tiny_llama()
+        .without_kv("tokenizer.ggml.eos_token_id")
+        .write("quality_stream");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("quality_stream");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let (tx, rx) = channel();
+    session.set_observer(Arc::new(ObserverToChannel::new(tx)));
+    let config = sampled(QualityThresholds {
+        window: 2,
+        mean_surprise: Some(0.0),
+        mean_entropy: None,
+    });
+    let alerts = || {
+        rx.try_iter()
+            .filter(|e| matches!(e, EngineEvent::QualityAlert(_)))
+            .count()
+    };
+
+    let expected = generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+    assert_eq!(alerts(), 1);
+    assert_eq!(expected.stats.generated_tokens, 6);
+
+    let mut tokens = session.tokens(&tokenizer, &PROMPT, &config);
+    tokens.by_ref().for_each(|t| drop(t.unwrap()));
+    assert_eq!(tokens.stats(), expected.stats);
+    drop(tokens);
+    assert_eq!(alerts(), 1);
+
+    let end = session
+        .stream_text(&tokenizer, &PROMPT, &config, Granularity::Token, |_| {
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(end.stats, expected.stats);
+    assert_eq!(alerts(), 1);
+}
@@ -15,7 +15,9 @@
 mod common;
 
 use inference_engine_rust::layers::embeddings::lookup_embeddings;
-use inference_engine_rust::model_config::TokenizerPromptConfig;
+use inference_engine_rust::model_config::{
+    ModelConfig, TokenizerPromptConfig, ffn_dim_from_weights,
+};
 use inference_engine_rust::model_loader::file_loader::read_file;
 
 use common::{
@@ -47,6 +49,41 @@ fn mistral_tokenizer_prompt_config_from_gguf() {
     assert_eq!(cfg.eos_token_id, 2);
 }
 
+/// `llama.feed_forward_length` and the `ffn_gate.weight` shape agree on Mistral's 14336.
+#[test]
+#[ignore = "requires model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf"]
+fn mistral_ffn_dim_from_metadata_and_weights() {
+    let path = reference_model_path();
+    if !path.is_file() {
+        eprintln!("skip: missing {}", path.display());
+        return;
+    }
+    let gguf = read_file(REFERENCE_MODEL_REL_PATH).expect("read gguf");
+    let config = ModelConfig::from_gguf(&gguf).expect("model config");
+    assert_eq!(config.ffn_dim, 14336);
+    assert!(config.layer_dims.iter().all(|d| d.ffn_dim == 14336));
+    assert_eq!(
+        ffn_dim_from_weights(&gguf, config.hidden_dim).expect("ffn_gate shape"),
+        14336
+    );
+}
+
+/// Mistral 7B v0.1 has 7,241,732,096 weights, counted from the tensor table alone.
+#[test]
+#[ignore = "requires model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf"]
+fn mistral_parameter_count_from_metadata() {
+    let path = reference_model_path();
+    if !path.is_file() {
+        eprintln!("skip: missing {}", path.display());
+        return;
+    }
+    let gguf = read_file(REFERENCE_MODEL_REL_PATH).expect("read gguf");
+    assert_eq!(gguf.num_tensors(), 0);
+    let count = gguf.parameter_count();
+    assert_eq!(count, 7_241_732_096);
+    assert!((7.1e9..7.3e9).contains(&(count as f64)));
+}
+
 /// Compare Rust `lookup_embeddings` to hardcoded gguf-py reference for [`REFERENCE_TOKEN_ID`].
 #[test]
 #[ignore = "requires model/mistral-7b-v0.1/mistral-7b-v0.1.Q4_K_M.gguf (see tests/common/mod.rs)"]
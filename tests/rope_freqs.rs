@@ -0,0 +1,59 @@
+//! Model-wide `rope_freqs.weight` (Llama 3.1 long-context frequency factors) on the synthetic
+//! model: resolved for every layer, applied in RoPE, and rejected when its length is not
+//! `rotary_dim / 2`.
+
+mod common;
+
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TINY_HEADS, TINY_HIDDEN, tiny_llama};
+
+const PAIRS: usize = TINY_HIDDEN / TINY_HEADS / 2;
+const PROMPT: [u32; 5] = [1, 4, 8, 15, 16];
+
+fn last_logits(model: &LoadedModel) -> Vec<f32> {
+    let mut session = InferenceSession::new(model).unwrap();
+    let state = session.prefill(&PROMPT).unwrap();
+    session.logits_last_token(&state).unwrap()
+}
+
+fn with_factors(stem: &str, factors: &[f32]) -> LoadedModel {
+    let path = tiny_llama()
+        .f32_tensor("rope_freqs.weight", &[factors.len() as u64], factors)
+        .write(stem);
+    LoadedModel::load(path).unwrap()
+}
+
+#[test]
+fn frequency_factors_change_logits_and_unit_factors_do_not() {
+    let baseline = last_logits(&LoadedModel::load(tiny_llama().write("rope_freqs_base")).unwrap());
+
+    let ones = with_factors("rope_freqs_ones", &[1.0; PAIRS]);
+    assert_eq!(last_logits(&ones), baseline);
+
+    let mut factors = [1.0; PAIRS];
+    factors[0] = 8.0;
+    let scaled = last_logits(&with_factors("rope_freqs_scaled", &factors));
+    let max_diff = scaled
+        .iter()
+        .zip(&baseline)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0f32, f32::max);
+    assert!(max_diff > 1e-4, "factors had no effect ({max_diff})");
+}
+
+#[test]
+fn wrong_length_is_rejected_at_load() {
+    let path = tiny_llama()
+        .f32_tensor("rope_freqs.weight", &[PAIRS as u64 + 1], &[1.0; PAIRS + 1])
+        .write("rope_freqs_bad_len");
+    let Err(e) = LoadedModel::load(path) else {
+        panic!("a rope_freqs table longer than rotary_dim/2 must not load");
+    };
+    let msg = e.to_string();
+    assert!(
+        msg.contains("rope_freqs.weight") && msg.contains("rotary_dim/2"),
+        "{msg}"
+    );
+}
@@ -0,0 +1,78 @@
+//! `llama.rope.scaling.*` metadata: parsed into per-layer frequency scales that change the
+//! forward pass, and unsupported scaling types refused at load.
+
+mod common;
+
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::gguf_types::Data;
+use inference_engine_rust::ops::rope::RopeScaling;
+
+use common::gguf_fixture::{GgufFixture, TINY_HEADS, TINY_HIDDEN, tiny_llama};
+
+fn scaled(kind: &str) -> GgufFixture {
+    tiny_llama()
+        .kv("llama.rope.scaling.type", Data::String(kind.into()))
+        .kv("llama.rope.scaling.factor", Data::Float32(4.0))
+        .kv(
+            "llama.rope.scaling.original_context_length",
+            Data::Uint32(256),
+        )
+}
+
+fn last_logits(model: &LoadedModel) -> Vec<f32> {
+    let mut session = InferenceSession::new(model).unwrap();
+    let state = session.prefill(&[1, 7, 8, 9, 10, 11]).unwrap();
+    session.logits_last_token(&state).unwrap()
+}
+
+#[test]
+fn yarn_metadata_sets_per_layer_scales_and_changes_the_logits() {
+    let plain = LoadedModel::load(tiny_llama().write("rope_scaling_plain")).unwrap();
+    let yarn = LoadedModel::load(scaled("yarn").write("rope_scaling_yarn")).unwrap();
+    let config = yarn.config();
+    assert_eq!(
+        config.rope_scaling,
+        Some(RopeScaling::Yarn {
+            factor: 4.0,
+            original_context_length: 256,
+            beta_fast: RopeScaling::YARN_BETA_FAST,
+            beta_slow: RopeScaling::YARN_BETA_SLOW,
+        })
+    );
+    let pairs = TINY_HIDDEN / TINY_HEADS / 2;
+    for spec in &config.layer_attention {
+        let scales = spec.rope_freq_scales.as_deref().expect("scaled layer");
+        assert_eq!(scales.len(), pairs);
+        assert_eq!(scales[0], 1.0, "the fastest pair is never interpolated");
+        assert!(scales[pairs - 1] > 1.0);
+    }
+    assert_eq!(plain.config().rope_scaling, None);
+    assert!(plain.config().layer_attention[0].rope_freq_scales.is_none());
+
+    let (a, b) = (last_logits(&plain), last_logits(&yarn));
+    assert!(a.iter().zip(&b).any(|(x, y)| (x - y).abs() > 1e-6));
+}
+
+#[test]
+fn unit_factor_is_a_no_op_and_unknown_types_are_rejected() {
+    let unit = scaled("linear").kv("llama.rope.scaling.factor", Data::Float32(1.0));
+    let model = LoadedModel::load(unit.write("rope_scaling_unit")).unwrap();
+    assert_eq!(model.config().rope_scaling, None);
+
+    let err = LoadedModel::load(scaled("longrope").write("rope_scaling_longrope"))
+        .err()
+        .expect("longrope is not implemented")
+        .to_string();
+    assert!(
+        err.contains("unsupported rope scaling type 'longrope'"),
+        "{err}"
+    );
+
+    let no_original = scaled("yarn").without_kv("llama.rope.scaling.original_context_length");
+    let err = LoadedModel::load(no_original.write("rope_scaling_no_original"))
+        .err()
+        .expect("YaRN needs the original context")
+        .to_string();
+    assert!(err.contains("original_context_length"), "{err}");
+}
@@ -0,0 +1,47 @@
+//! Per-request seeds on the synthetic model: requests from one base seed sample different text,
+//! and each replays exactly from its index.
+
+mod common;
+
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::seed::SeedSequence;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::tiny_llama;
+
+const PROMPT: [u32; 3] = [1, 5, 6];
+
+fn template() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 12,
+        temperature: 1.5,
+        ..GenerationConfig::default()
+    }
+}
+
+#[test]
+fn requests_get_independent_reproducible_seeds() {
+    let model = LoadedModel::load(tiny_llama().write("seed_sequence")).unwrap();
+    let run = |config: &GenerationConfig| {
+        let mut session = InferenceSession::new(&model).unwrap();
+        generate_from_ids(&mut session, &PROMPT, &[], config)
+            .unwrap()
+            .generated_token_ids
+    };
+
+    let sequence = SeedSequence::new(1234);
+    let (i0, seed0) = sequence.next_seed();
+    let (i1, seed1) = sequence.next_seed();
+    assert_eq!((i0, i1), (0, 1));
+    assert_ne!(seed0, seed1);
+
+    let first = run(&sequence.config_for(&template(), 0));
+    let second = run(&sequence.config_for(&template(), 1));
+    assert_ne!(first, second, "requests must not share an RNG stream");
+
+    // A fresh sequence from the same base replays each request on its own.
+    let replay = SeedSequence::new(1234);
+    assert_eq!(run(&replay.config_for(&template(), 1)), second);
+    assert_eq!(run(&replay.config_for(&template(), 0)), first);
+}
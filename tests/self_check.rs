@@ -0,0 +1,47 @@
+//! `InferenceSession::self_check`: one BOS forward whose logits must look like a working model.
+
+mod common;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TINY_HIDDEN, TINY_VOCAB, tiny_llama};
+
+#[test]
+fn healthy_model_passes_and_keeps_the_cache() {
+    let path = tiny_llama().write("self_check_ok");
+    let model = LoadedModel::load(&path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.set_layer_timing(true);
+    session.prefill(&[1, 5, 6]).unwrap();
+    let timings = session.layer_timings().to_vec();
+    let cache_bytes = session.kv_cache_bytes();
+    session.self_check().unwrap();
+    assert_eq!(session.position(), 3);
+    assert_eq!(session.budget().used(), 3);
+    assert_eq!(session.kv_cache_bytes(), cache_bytes);
+    assert_eq!(
+        session.layer_timings(),
+        timings,
+        "the check's forward is not timed"
+    );
+}
+
+#[test]
+fn zeroed_output_weight_is_flagged() {
+    let zeros = vec![0.0; TINY_HIDDEN * TINY_VOCAB];
+    let fixture = tiny_llama().without_tensor("output.weight").f32_tensor(
+        "output.weight",
+        &[TINY_HIDDEN as u64, TINY_VOCAB as u64],
+        &zeros,
+    );
+    let model = LoadedModel::load(fixture.write("self_check_zeroed_head")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let err = session.self_check().unwrap_err();
+    assert!(
+        matches!(&err, EngineError::Model(msg) if msg.contains("zero")),
+        "{err}"
+    );
+    assert_eq!(session.position(), 0);
+}
@@ -0,0 +1,155 @@
+//! `LoadOptions::shared_cache`: weights mapped from the GGUF, converted tensors from a sidecar.
+
+mod common;
+
+use std::path::Path;
+
+use inference_engine_rust::model_loader::file_loader::read_file;
+use inference_engine_rust::model_loader::gguf_types::{GGUFData, LoadOptions, LoadStats};
+use inference_engine_rust::model_loader::registry::{HASH_PREFIX_BYTES, content_hash};
+use inference_engine_rust::model_loader::sidecar::{options_hash, sidecar_path};
+
+use common::gguf_fixture::{GGML_TYPE_BF16, GgufFixture, TempPath, tiny_llama_q8};
+
+/// Q8_0 matrices and F32 norms, plus a BF16 vector, so a load widens one tensor even without
+/// overrides.
+fn fixture(bf16: [u16; 4]) -> GgufFixture {
+    let bytes = bf16.iter().flat_map(|v| v.to_le_bytes()).collect();
+    tiny_llama_q8().tensor("rope_freqs.weight", &[4], GGML_TYPE_BF16, bytes)
+}
+
+fn shared(force_f32_weights: bool) -> LoadOptions {
+    LoadOptions {
+        shared_cache: true,
+        force_f32_weights,
+        ..LoadOptions::default()
+    }
+}
+
+/// The sidecar a load of `model` with `options` writes, removed when dropped.
+fn sidecar_for(model: &Path, options: &LoadOptions) -> TempPath {
+    sidecar_path(model, options_hash(&options.effective_overrides())).into()
+}
+
+/// Replace the model file the way a re-export does: write `fixture` alongside and rename it over
+/// `model`, so mappings of the old file (including this process's) keep their data.
+fn replace(model: &Path, fixture: GgufFixture, stem: &str) {
+    std::fs::rename(fixture.write(stem), model).expect("rename over the model");
+}
+
+fn load(path: &str, options: &LoadOptions) -> (GGUFData, LoadStats) {
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    let stats = gguf.load_tensors_with(path, options).expect("load tensors");
+    (gguf, stats)
+}
+
+fn assert_same_tensors(a: &GGUFData, b: &GGUFData) {
+    assert_eq!(a.num_tensors(), b.num_tensors());
+    for (name, t) in a.loaded_tensors() {
+        let other = b.get_tensor(name).expect(name);
+        assert_eq!(t.dtype(), other.dtype(), "{name}");
+        assert_eq!(t.dimensions(), other.dimensions(), "{name}");
+        assert_eq!(t.buffer(), other.buffer(), "{name}");
+    }
+}
+
+#[test]
+fn second_load_maps_converted_tensors_from_the_sidecar() {
+    let model = fixture([0x3f80, 0x4000, 0x3f00, 0x3f80]).write("shared_cache_reuse");
+    let path = model.to_str().expect("utf8 path");
+    let options = shared(true);
+    let sidecar = sidecar_for(&model, &options);
+
+    let (first, built) = load(path, &options);
+    assert!(built.sidecar_written && sidecar.is_file());
+    assert_eq!(built.sidecar_hits, 0);
+    assert!(built.tensors_mapped > 0, "F32 norms are viewed in place");
+    assert!(built.timing.elements_converted > 0);
+
+    // A second process: nothing is read or converted, every tensor is a shared mapping.
+    let (second, reused) = load(path, &options);
+    assert_eq!(
+        reused.sidecar_hits,
+        built.tensors_loaded - built.tensors_mapped
+    );
+    assert_eq!(reused.tensors_mapped, built.tensors_mapped);
+    assert_eq!(reused.timing.elements_converted, 0);
+    assert_eq!(reused.bytes_read, 0);
+    assert!(!reused.sidecar_written);
+    assert_eq!(reused.dtype_overrides, built.dtype_overrides);
+    assert!(second.loaded_tensors().all(|(_, t)| t.is_mapped()));
+
+    let plain = LoadOptions {
+        shared_cache: false,
+        ..options
+    };
+    let (reference, _) = load(path, &plain);
+    assert_same_tensors(&reference, &first);
+    assert_same_tensors(&reference, &second);
+}
+
+#[test]
+fn stale_sidecar_is_rebuilt_and_disabled_cache_writes_nothing() {
+    let model = fixture([0x3f80; 4]).write("shared_cache_stale");
+    let path = model.to_str().expect("utf8 path");
+    let options = shared(false);
+    let sidecar = sidecar_for(&model, &options);
+
+    let (_, off) = load(
+        path,
+        &LoadOptions {
+            shared_cache: false,
+            ..LoadOptions::default()
+        },
+    );
+    assert!(!off.sidecar_written && !sidecar.exists());
+    assert_eq!((off.tensors_mapped, off.sidecar_hits), (0, 0));
+
+    let (_, built) = load(path, &options);
+    assert!(built.sidecar_written, "the BF16 vector is widened");
+
+    // Same path, different content: the sidecar's model hash no longer matches.
+    replace(&model, fixture([0x4000; 4]), "shared_cache_stale_next");
+    let (rebuilt, stats) = load(path, &options);
+    assert_eq!(stats.sidecar_hits, 0);
+    assert!(stats.sidecar_written);
+    let freqs = rebuilt.get_tensor("rope_freqs.weight").unwrap();
+    assert_eq!(freqs.as_f32_slice().unwrap(), &[2.0; 4]);
+
+    let (_, reused) = load(path, &options);
+    assert_eq!(reused.sidecar_hits, 1);
+}
+
+#[test]
+fn sidecar_is_rebuilt_when_converted_data_past_the_hashed_prefix_changes() {
+    // An F32 filler pushes the BF16 tensor past the prefix `content_hash` reads.
+    let filler = vec![0.25f32; HASH_PREFIX_BYTES as usize / 4 + 1024];
+    let with = |bf16: [u16; 4]| {
+        let bytes = bf16.iter().flat_map(|v| v.to_le_bytes()).collect();
+        tiny_llama_q8()
+            .f32_tensor("filler.weight", &[filler.len() as u64], &filler)
+            .tensor("rope_freqs.weight", &[4], GGML_TYPE_BF16, bytes)
+    };
+    let model = with([0x3f80; 4]).write("shared_cache_deep_change");
+    let path = model.to_str().expect("utf8 path");
+    let options = shared(false);
+    let _sidecar = sidecar_for(&model, &options);
+
+    let (_, built) = load(path, &options);
+    assert!(built.sidecar_written);
+    let hash = content_hash(&model).unwrap();
+
+    // Same size and same first MiB: only the converted tensor's bytes differ.
+    replace(&model, with([0x4000; 4]), "shared_cache_deep_change_next");
+    assert_eq!(content_hash(&model).unwrap(), hash);
+    let (rebuilt, stats) = load(path, &options);
+    assert_eq!(stats.sidecar_hits, 0);
+    assert!(stats.sidecar_written);
+    let freqs = rebuilt.get_tensor("rope_freqs.weight").unwrap();
+    assert_eq!(freqs.as_f32_slice().unwrap(), &[2.0; 4]);
+
+    let (reused, stats) = load(path, &options);
+    assert_eq!(stats.sidecar_hits, 1);
+    let freqs = reused.get_tensor("rope_freqs.weight").unwrap();
+    assert_eq!(freqs.as_f32_slice().unwrap(), &[2.0; 4]);
+}
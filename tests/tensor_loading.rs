@@ -0,0 +1,514 @@
+//! Loader checks against small synthetic GGUF files (no model download needed).
+
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Mutex;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::core::tensor::TensorType;
+use inference_engine_rust::model_loader::file_loader::read_file;
+use inference_engine_rust::model_loader::gguf_types::{LoadOptions, LoadStats};
+use inference_engine_rust::model_loader::reader::Reader;
+use inference_engine_rust::model_loader::storage::{Advice, Advisor};
+use inference_engine_rust::model_loader::tensor_loader::load_tensor_into;
+use inference_engine_rust::ops::rmsnorm::rmsnorm;
+
+use common::gguf_fixture::{
+    GGML_TYPE_BF16, GGML_TYPE_Q4_K, GGML_TYPE_Q6_K, GGML_TYPE_Q8_0, GgufFixture, TINY_VOCAB,
+    tiny_llama, tiny_llama_q8,
+};
+
+#[test]
+fn one_dimensional_tensors_load_for_every_supported_type() {
+    let norm = [1.0f32, 2.0, 0.5, 1.0];
+    // BF16 1.0 / 2.0 / 0.5 / 1.0 (upper halves of the f32 bit patterns).
+    let bf16: Vec<u8> = [0x3f80u16, 0x4000, 0x3f00, 0x3f80]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    let path = GgufFixture::new()
+        .f32_tensor("f32_norm.weight", &[4], &norm)
+        .tensor("bf16_norm.weight", &[4], GGML_TYPE_BF16, bf16)
+        .tensor("q8_vec.weight", &[32], GGML_TYPE_Q8_0, vec![0u8; 34])
+        .tensor("q4k_vec.weight", &[256], GGML_TYPE_Q4_K, vec![0u8; 144])
+        .tensor("q6k_vec.weight", &[256], GGML_TYPE_Q6_K, vec![0u8; 210])
+        .f32_tensor("scale.scalar", &[], &[0.25])
+        .write("one_dim_tensors");
+    let path = path.to_str().expect("utf8 path");
+
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    gguf.load_tensors(path).expect("load fixture tensors");
+
+    let expect = [
+        ("f32_norm.weight", TensorType::F32, vec![4]),
+        ("bf16_norm.weight", TensorType::F32, vec![4]),
+        ("q8_vec.weight", TensorType::Q8_0, vec![32]),
+        ("q4k_vec.weight", TensorType::Q4K, vec![256]),
+        ("q6k_vec.weight", TensorType::Q6K, vec![256]),
+        ("scale.scalar", TensorType::F32, vec![]),
+    ];
+    for (name, dtype, dims) in expect {
+        let t = gguf
+            .get_tensor(name)
+            .unwrap_or_else(|| panic!("{name} loaded"));
+        assert_eq!(t.dtype(), dtype, "{name}");
+        assert_eq!(t.dimensions(), dims.as_slice(), "{name}");
+    }
+
+    assert_eq!(
+        gguf.get_tensor("f32_norm.weight")
+            .unwrap()
+            .as_vector()
+            .unwrap(),
+        norm
+    );
+    assert_eq!(
+        gguf.get_tensor("bf16_norm.weight")
+            .unwrap()
+            .as_vector()
+            .unwrap(),
+        norm
+    );
+    assert_eq!(
+        gguf.get_tensor("scale.scalar")
+            .unwrap()
+            .as_vector()
+            .unwrap(),
+        [0.25]
+    );
+    assert!(
+        gguf.get_tensor("q8_vec.weight")
+            .unwrap()
+            .as_vector()
+            .is_err()
+    );
+
+    // Use the loaded vector as RMSNorm weights end to end.
+    let weights = gguf
+        .get_tensor("f32_norm.weight")
+        .unwrap()
+        .as_vector()
+        .unwrap();
+    let input = [1.0f32, -1.0, 2.0, -2.0];
+    let mut out = [0.0f32; 4];
+    rmsnorm(&input, weights, 0.0, &mut out).expect("rmsnorm");
+    let rms = (10.0f32 / 4.0).sqrt();
+    for i in 0..4 {
+        assert!((out[i] - input[i] * norm[i] / rms).abs() < 1e-6, "idx {i}");
+    }
+}
+
+#[test]
+fn as_vector_rejects_matrices() {
+    let path = GgufFixture::new()
+        .f32_tensor("mat.weight", &[2, 2], &[1.0, 2.0, 3.0, 4.0])
+        .write("as_vector_matrix");
+    let path = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    gguf.load_tensors(path).expect("load fixture tensors");
+    let t = gguf.get_tensor("mat.weight").unwrap();
+    assert!(t.as_vector().is_err());
+    assert_eq!(t.as_f32_slice().unwrap().len(), 4);
+}
+
+#[test]
+fn zero_element_dimension_is_rejected() {
+    let path = GgufFixture::new()
+        .f32_tensor("empty.weight", &[4, 0], &[])
+        .write("zero_dim");
+    let err = read_file(path.to_str().expect("utf8 path")).expect_err("zero dim must fail");
+    assert!(err.to_string().contains("empty.weight"), "{err}");
+}
+
+#[test]
+fn overflowing_dimensions_are_rejected_before_loading() {
+    let path = GgufFixture::new()
+        .tensor("evil.weight", &[u64::MAX, 2], 0, Vec::new())
+        .write("overflow_dims");
+    let err = read_file(path.to_str().expect("utf8 path")).expect_err("overflow must fail");
+    assert!(matches!(err, EngineError::Overflow(_)), "{err}");
+    assert!(err.to_string().contains("evil.weight"), "{err}");
+}
+
+#[test]
+fn load_where_loads_only_matching_tensors() {
+    let path = GgufFixture::new()
+        .f32_tensor("blk.0.attn_norm.weight", &[4], &[1.0; 4])
+        .tensor(
+            "blk.0.attn_q.weight",
+            &[256],
+            GGML_TYPE_Q4_K,
+            vec![0u8; 144],
+        )
+        .f32_tensor("blk.1.ffn_norm.weight", &[4], &[2.0; 4])
+        .tensor("output.weight", &[256], GGML_TYPE_Q6_K, vec![0u8; 210])
+        .write("load_where");
+    let path = path.to_str().expect("utf8 path");
+
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    let matched = gguf
+        .load_where(path, |name, _| name.contains("norm"))
+        .expect("load norms");
+    assert_eq!(matched, 2);
+    assert_eq!(gguf.num_tensors(), 2);
+    assert!(gguf.get_tensor("blk.0.attn_norm.weight").is_some());
+    assert!(gguf.get_tensor("blk.1.ffn_norm.weight").is_some());
+    assert!(gguf.get_tensor("blk.0.attn_q.weight").is_none());
+    assert!(gguf.get_tensor("output.weight").is_none());
+
+    // Later calls add to what is loaded; matches that are already present are not re-read.
+    gguf.load_where(path, |name, _| name.starts_with("blk.0."))
+        .expect("load layer 0");
+    assert_eq!(gguf.num_tensors(), 3);
+    assert!(gguf.get_tensor("output.weight").is_none());
+}
+
+#[test]
+fn loaded_tensors_yields_only_what_was_loaded_in_table_order() {
+    let path = GgufFixture::new()
+        .f32_tensor("output_norm.weight", &[4], &[1.0; 4])
+        .f32_tensor("blk.0.attn_norm.weight", &[4], &[2.0; 4])
+        .f32_tensor("token_embd.weight", &[4, 2], &[0.5; 8])
+        .write("loaded_tensors");
+    let path = path.to_str().expect("utf8 path");
+
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    assert_eq!(gguf.loaded_tensors().count(), 0);
+    gguf.load_named_tensors(
+        path,
+        &[
+            "token_embd.weight".to_string(),
+            "output_norm.weight".to_string(),
+        ],
+    )
+    .expect("load two tensors");
+    let loaded: Vec<(&str, usize)> = gguf
+        .loaded_tensors()
+        .map(|(name, t)| (name, t.element_count()))
+        .collect();
+    assert_eq!(
+        loaded,
+        [("output_norm.weight", 4), ("token_embd.weight", 8)]
+    );
+}
+
+#[test]
+fn parameter_count_sums_table_dims_without_loading() {
+    let path = GgufFixture::new()
+        .f32_tensor("output_norm.weight", &[4], &[1.0; 4])
+        .f32_tensor("token_embd.weight", &[4, 3], &[0.5; 12])
+        .tensor(
+            "blk.0.ffn_down.weight",
+            &[32, 2],
+            GGML_TYPE_Q8_0,
+            vec![0; 68],
+        )
+        .write("parameter_count");
+    let gguf = read_file(path.to_str().expect("utf8 path")).expect("read fixture metadata");
+    assert_eq!(gguf.parameter_count(), 4 + 12 + 64);
+    assert_eq!(gguf.num_tensors(), 0);
+}
+
+#[test]
+fn array_len_counts_elements_of_array_keys_only() {
+    let path = tiny_llama().write("array_len");
+    let gguf = read_file(path.to_str().expect("utf8 path")).expect("read fixture metadata");
+    assert_eq!(gguf.array_len("tokenizer.ggml.tokens"), Some(TINY_VOCAB));
+    assert_eq!(gguf.array_len("tokenizer.ggml.bos_token_id"), None);
+    assert_eq!(gguf.array_len("tokenizer.ggml.scores"), None);
+}
+
+#[test]
+fn duplicate_keys_are_kept_as_warnings_and_duplicate_tensors_rejected() {
+    use inference_engine_rust::model_loader::gguf_types::Data;
+
+    let path = GgufFixture::new()
+        .kv("general.name", Data::String("first".into()))
+        .kv("general.name", Data::String("second".into()))
+        .f32_tensor("a.weight", &[4], &[1.0; 4])
+        .write("duplicate_kv");
+    let path = path.to_str().expect("utf8 path");
+    let gguf = read_file(path).expect("duplicate keys are not fatal");
+    assert!(matches!(gguf.get_metadata("general.name"), Some(Data::String(s)) if s == "second"));
+    let dups = gguf.duplicate_keys();
+    assert_eq!(dups.len(), 1);
+    assert_eq!(dups[0].key, "general.name");
+    assert_eq!(dups[0].offsets.len(), 2);
+
+    let path = GgufFixture::new()
+        .f32_tensor("a.weight", &[4], &[1.0; 4])
+        .f32_tensor("a.weight", &[4], &[2.0; 4])
+        .write("duplicate_tensor");
+    let path = path.to_str().expect("utf8 path");
+    let err = read_file(path).unwrap_err().to_string();
+    assert!(err.contains("duplicate tensor name 'a.weight'"), "{err}");
+}
+
+#[test]
+fn model_id_is_stable_and_tracks_metadata() {
+    use inference_engine_rust::model_loader::gguf_types::Data;
+
+    let id_of = |stem: &str, arch: &str, second: &str, dims: &[u64]| {
+        let path = GgufFixture::new()
+            .kv("general.architecture", Data::String(arch.into()))
+            .f32_tensor("a.weight", &[4], &[1.0, 2.0, 3.0, 4.0])
+            .f32_tensor(second, dims, &[0.5; 4])
+            .write(stem);
+        read_file(path.to_str().expect("utf8 path"))
+            .expect("read")
+            .model_id()
+    };
+    let first = id_of("model_id_same", "llama", "b.weight", &[2, 2]);
+    assert_eq!(first.len(), 16);
+    assert_eq!(id_of("model_id_same", "llama", "b.weight", &[2, 2]), first);
+
+    assert_ne!(id_of("model_id_name", "llama", "c.weight", &[2, 2]), first);
+    assert_ne!(id_of("model_id_dims", "llama", "b.weight", &[4, 1]), first);
+    assert_ne!(id_of("model_id_arch", "gemma4", "b.weight", &[2, 2]), first);
+}
+
+#[test]
+fn tensor_names_are_interned_in_table_order() {
+    let names = [
+        "token_embd.weight",
+        "blk.0.attn_norm.weight",
+        "output.weight",
+    ];
+    let mut fixture = GgufFixture::new();
+    for (i, name) in names.iter().enumerate() {
+        fixture = fixture.f32_tensor(name, &[4], &[i as f32; 4]);
+    }
+    let path = fixture.write("interned_names");
+    let path = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path).expect("read fixture metadata");
+
+    for (i, info) in gguf.tensors_metadata().iter().enumerate() {
+        assert_eq!(info.name.index(), i);
+        assert_eq!(gguf.tensor_name(info), names[i]);
+        assert_eq!(gguf.tensor_symbol(names[i]), Some(info.name));
+        assert_eq!(
+            gguf.tensor_info(names[i]).map(|t| t.offset),
+            Some(info.offset)
+        );
+    }
+    assert_eq!(gguf.tensor_symbol("missing.weight"), None);
+
+    let output = gguf.tensor_symbol("output.weight").unwrap();
+    gguf.load_tensors(path).expect("load fixture tensors");
+    // Symbols survive loading and agree with lookups by name.
+    assert_eq!(gguf.tensor_symbol("output.weight"), Some(output));
+    let by_symbol = gguf.get_tensor_by_symbol(output).unwrap();
+    let by_name = gguf.get_tensor("output.weight").unwrap();
+    assert!(std::ptr::eq(by_symbol, by_name));
+    assert_eq!(by_symbol.as_f32_slice().unwrap(), [2.0; 4]);
+}
+
+/// Records every hint instead of sending it to the OS.
+#[derive(Default)]
+struct RecordingAdvisor(Mutex<Vec<(Advice, u64, u64)>>);
+
+impl Advisor for RecordingAdvisor {
+    fn advise(&self, _file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<bool> {
+        self.0.lock().unwrap().push((advice, offset, len));
+        Ok(true)
+    }
+}
+
+#[test]
+fn readahead_hints_cover_the_tensors_in_file_order() {
+    let path = GgufFixture::new()
+        .f32_tensor("a.weight", &[4], &[1.0; 4])
+        .f32_tensor("b.weight", &[8], &[2.0; 8])
+        .tensor("c.weight", &[32], GGML_TYPE_Q8_0, vec![0u8; 34])
+        .f32_tensor("d.weight", &[2], &[3.0; 2])
+        .write("readahead_hints");
+    let path = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    let ranges: Vec<(u64, u64)> = gguf
+        .tensors_metadata()
+        .iter()
+        .map(|t| {
+            let len = t.byte_size().unwrap() as u64;
+            (gguf.tensor_data_offset() + t.offset as u64, len)
+        })
+        .collect();
+
+    // Requested out of order; reads (and hints) still follow the file.
+    let names: Vec<String> = ["d.weight", "b.weight", "a.weight", "c.weight"]
+        .map(String::from)
+        .to_vec();
+    let advisor = RecordingAdvisor::default();
+    let stats = gguf
+        .load_named_tensors_advised(path, &names, Some(&advisor))
+        .expect("load fixture tensors");
+
+    let calls = advisor.0.into_inner().unwrap();
+    let (last_start, last_len) = ranges[3];
+    assert_eq!(
+        calls[0],
+        (
+            Advice::Sequential,
+            ranges[0].0,
+            last_start + last_len - ranges[0].0
+        )
+    );
+    let will_need: Vec<(u64, u64)> = calls[1..]
+        .iter()
+        .map(|&(advice, offset, len)| {
+            assert_eq!(advice, Advice::WillNeed);
+            (offset, len)
+        })
+        .collect();
+    assert_eq!(will_need, ranges[1..]);
+
+    assert_eq!(stats.tensors_loaded, 4);
+    assert_eq!(stats.bytes_read, ranges.iter().map(|r| r.1).sum::<u64>());
+    assert!(stats.hints_applied);
+    assert_eq!((stats.hints_issued, stats.hints_failed), (4, 0));
+    assert_eq!(
+        gguf.get_tensor("b.weight").unwrap().as_f32_slice().unwrap(),
+        [2.0; 8]
+    );
+
+    // Already loaded: nothing read, nothing hinted.
+    let again = gguf
+        .load_tensors_with(path, &LoadOptions::default())
+        .unwrap();
+    assert_eq!(again, LoadStats::default());
+}
+
+#[test]
+fn prefetch_off_sends_no_hints() {
+    let path = GgufFixture::new()
+        .f32_tensor("a.weight", &[4], &[1.0; 4])
+        .f32_tensor("b.weight", &[4], &[2.0; 4])
+        .write("readahead_off");
+    let path = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    let stats = gguf
+        .load_tensors_with(
+            path,
+            &LoadOptions {
+                prefetch: false,
+                ..LoadOptions::default()
+            },
+        )
+        .expect("load fixture tensors");
+    assert_eq!(stats.tensors_loaded, 2);
+    assert!(!stats.hints_applied);
+    assert_eq!(stats.hints_issued, 0);
+}
+
+#[test]
+fn load_timing_phases_add_up_to_the_load_and_split_by_type() {
+    use inference_engine_rust::model_loader::tensor::GgmlType;
+    use std::time::Duration;
+
+    let path = tiny_llama_q8().write("load_timing");
+    let path = path.to_str().expect("utf8 path");
+    let load = |options: &LoadOptions| {
+        let mut gguf = read_file(path).expect("read fixture metadata");
+        gguf.load_tensors_with(path, options)
+            .expect("load fixture tensors")
+    };
+
+    // Dequantizing at load puts every phase to work for the Q8_0 matrices.
+    let stats = load(&LoadOptions {
+        force_f32_weights: true,
+        ..LoadOptions::default()
+    });
+    let q8 = stats.timing_by_type[&GgmlType::Q8_0];
+    assert!(q8.tensors > 0);
+    assert!(!q8.read.is_zero(), "{q8:?}");
+    assert!(!q8.convert.is_zero(), "{q8:?}");
+    assert!(!q8.alloc.is_zero(), "{q8:?}");
+    assert!(q8.elements_converted > 0);
+    assert!(q8.read_mb_per_s().is_some() && q8.convert_melem_per_s().is_some());
+
+    let total = stats.timing.total();
+    assert!(total <= stats.elapsed, "{total:?} > {:?}", stats.elapsed);
+    let untimed = stats.elapsed - total;
+    assert!(
+        untimed <= stats.elapsed / 10 + Duration::from_millis(5),
+        "{untimed:?} of {:?} not charged to a phase",
+        stats.elapsed
+    );
+
+    let mut by_type = stats.timing_by_type.values();
+    let first = *by_type.next().unwrap();
+    let summed = by_type.fold(first, |mut acc, t| {
+        acc.tensors += t.tensors;
+        acc.read += t.read;
+        acc.convert += t.convert;
+        acc.alloc += t.alloc;
+        acc.bytes_read += t.bytes_read;
+        acc.elements_converted += t.elements_converted;
+        acc
+    });
+    assert_eq!(summed, stats.timing);
+    assert_eq!(stats.timing.tensors, stats.tensors_loaded);
+    assert_eq!(stats.timing.bytes_read, stats.bytes_read);
+    let report = stats.timing_report();
+    assert!(
+        report.contains("Q8_0") && report.contains("MB/s"),
+        "{report}"
+    );
+
+    // Kept as stored, quantized blocks are not unpacked at load.
+    let stats = load(&LoadOptions::default());
+    let q8 = stats.timing_by_type[&GgmlType::Q8_0];
+    assert_eq!(q8.elements_converted, 0);
+}
+
+#[test]
+fn load_tensor_into_fills_a_caller_buffer_like_a_normal_load() {
+    let values: Vec<f32> = (0..300).map(|i| (i as f32 - 150.0) / 7.0).collect();
+    let bf16: Vec<u8> = [0x3f80u16, 0xc040, 0x3e00]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    // 40 Q8_0 weights: one full block and a partial one (d = 0.5, q = i - 20).
+    let q8: Vec<u8> = (0..2)
+        .flat_map(|b| {
+            let mut block = vec![0x00, 0x38];
+            block.extend((0..32).map(|i| (b * 32 + i - 20) as i8 as u8));
+            block
+        })
+        .collect();
+    let path = tiny_llama_q8()
+        .f32_tensor("long.weight", &[300], &values)
+        .tensor("bf16.weight", &[3], GGML_TYPE_BF16, bf16)
+        .tensor("partial_q8.weight", &[40], GGML_TYPE_Q8_0, q8)
+        .write("load_tensor_into");
+    let path = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path).expect("read fixture metadata");
+    gguf.load_tensors(path).expect("load fixture tensors");
+
+    let mut reader = Reader::new(BufReader::new(File::open(path).unwrap()), 0);
+    let mut arena = vec![f32::NAN; 4096];
+    let names: Vec<String> = gguf.loaded_tensors().map(|(n, _)| n.to_string()).collect();
+    for name in &names {
+        let info = gguf.tensor_info(name).unwrap();
+        let n = info.num_elements().unwrap();
+        let out = &mut arena[..n];
+        load_tensor_into(&mut reader, info, gguf.tensor_data_offset(), out).expect(name);
+        let expected = gguf.get_tensor(name).unwrap().dequantize_to_f32().unwrap();
+        assert_eq!(out, &expected[..], "{name}");
+    }
+    let mut widened = [0.0f32; 3];
+    let info = gguf.tensor_info("bf16.weight").unwrap();
+    load_tensor_into(&mut reader, info, gguf.tensor_data_offset(), &mut widened).unwrap();
+    assert_eq!(widened, [1.0, -3.0, 0.125]);
+
+    let info = gguf.tensor_info("long.weight").unwrap();
+    let err = load_tensor_into(
+        &mut reader,
+        info,
+        gguf.tensor_data_offset(),
+        &mut arena[..299],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("buffer holds 299 values"), "{err}");
+}
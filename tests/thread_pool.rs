@@ -0,0 +1,74 @@
+//! Shared worker pools: sessions with the same thread settings reuse one pool, and the result of
+//! a forward pass or a large matmul does not depend on how many workers split it.
+
+mod common;
+
+use std::sync::Arc;
+
+use inference_engine_rust::engine::config::EngineConfig;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::thread_pool::{ThreadAffinity, shared_pool};
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::ops::matmul::matmul;
+
+use common::f32_tensor;
+use common::gguf_fixture::tiny_llama;
+
+const PROMPT: [u32; 5] = [1, 4, 9, 16, 25];
+
+#[test]
+fn large_matmul_matches_column_by_column_on_any_pool() {
+    const K: usize = 512;
+    const N: usize = 256;
+    let input: Vec<f32> = (0..K).map(|i| ((i * 7 % 13) as f32 - 6.0) / 6.0).collect();
+    let weight: Vec<f32> = (0..K * N)
+        .map(|i| ((i * 37 % 101) as f32 - 50.0) / 50.0)
+        .collect();
+    let a = f32_tensor(&input, vec![1, K]);
+    let b = f32_tensor(&weight, vec![K, N]);
+
+    // Each output column alone is far below the parallel threshold, so it runs inline.
+    let expected: Vec<f32> = (0..N)
+        .map(|col| {
+            let column = f32_tensor(&weight[col * K..(col + 1) * K], vec![K, 1]);
+            let mut out = f32_tensor(&[0.0], vec![1, 1]);
+            matmul(&a, &column, &mut out).unwrap();
+            out.as_f32_slice().unwrap()[0]
+        })
+        .collect();
+
+    for threads in [1, 4] {
+        let pool = shared_pool(Some(threads), ThreadAffinity::None).unwrap();
+        let mut out = f32_tensor(&[0.0; N], vec![1, N]);
+        pool.install(|| matmul(&a, &b, &mut out)).unwrap();
+        assert_eq!(out.as_f32_slice().unwrap(), expected, "{threads} threads");
+    }
+}
+
+#[test]
+fn sessions_share_pools_and_logits_do_not_depend_on_thread_count() {
+    let model = LoadedModel::load(tiny_llama().write("thread_pool_sessions")).unwrap();
+    let logits = |threads: usize| {
+        let mut session =
+            InferenceSession::with_config(&model, &EngineConfig::with_threads(threads)).unwrap();
+        assert_eq!(session.num_threads(), threads);
+        let state = session.prefill(&PROMPT).unwrap();
+        session.logits_last_token(&state).unwrap()
+    };
+    let single = logits(1);
+    assert_eq!(single, logits(4));
+    assert_eq!(single, logits(4), "a second session on the same pool");
+
+    let config = EngineConfig::with_threads(4);
+    let (a, b) = (
+        config.build_thread_pool().unwrap(),
+        config.build_thread_pool().unwrap(),
+    );
+    assert!(Arc::ptr_eq(&a.unwrap(), &b.unwrap()));
+    assert!(
+        EngineConfig::default()
+            .build_thread_pool()
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,139 @@
+//! `InferenceSession::budget` against the KV cache on the synthetic model: randomized sequences
+//! of prefill / forced prefill / decode / snapshot / reset, checking the accountant and the cache
+//! never disagree and that overflow surfaces as a budget error, not a cache error.
+
+mod common;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::engine::budget::{BudgetError, TokenUse};
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TINY_CONTEXT, TINY_VOCAB, tiny_llama};
+
+fn ids(rng: &mut StdRng, n: usize) -> Vec<u32> {
+    (0..n)
+        .map(|_| rng.gen_range(1..TINY_VOCAB as u32))
+        .collect()
+}
+
+fn assert_in_sync(session: &InferenceSession<'_>, step: usize) {
+    let b = session.budget();
+    assert_eq!(b.used(), session.position(), "step {step}: {b:?}");
+    assert!(b.used() <= b.context_length(), "step {step}: {b:?}");
+    let by_kind: usize = [
+        TokenUse::Prompt,
+        TokenUse::Forced,
+        TokenUse::Generated,
+        TokenUse::Restored,
+    ]
+    .into_iter()
+    .map(|k| b.count(k))
+    .sum();
+    assert_eq!(by_kind, b.used(), "step {step}");
+}
+
+/// `Ok` if the op fit; otherwise it must be a budget error that left the cache untouched.
+fn expect_fit_or_budget_error<T>(
+    result: Result<T, EngineError>,
+    needed: usize,
+    before: usize,
+    session: &InferenceSession<'_>,
+) {
+    let fits = before + needed <= TINY_CONTEXT;
+    match result {
+        Ok(_) => assert!(fits, "{needed} tokens at {before} should not fit"),
+        Err(EngineError::Budget(BudgetError::Exceeded { requested, .. })) => {
+            assert!(!fits);
+            assert_eq!(requested, needed);
+            assert_eq!(session.position(), before);
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+    }
+}
+
+#[test]
+fn randomized_operations_keep_budget_and_cache_in_sync() {
+    let model = LoadedModel::load(tiny_llama().write("token_budget_random")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    assert_eq!(session.budget().context_length(), TINY_CONTEXT);
+
+    for seed in 0..4u64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut saved = None;
+        session.reset();
+        for step in 0..40 {
+            let before = session.position();
+            match rng.gen_range(0..7) {
+                0 | 1 => {
+                    let n = rng.gen_range(1..24);
+                    let prompt = ids(&mut rng, n);
+                    let r = session.prefill(&prompt);
+                    expect_fit_or_budget_error(r, n, before, &session);
+                }
+                2 => {
+                    let n = rng.gen_range(1..12);
+                    let prompt = ids(&mut rng, n);
+                    let r = session.prefill(&prompt);
+                    expect_fit_or_budget_error(r, n, before, &session);
+                }
+                3 => {
+                    let id = rng.gen_range(1..TINY_VOCAB as u32);
+                    let r = session.decode_token(id);
+                    expect_fit_or_budget_error(r, 1, before, &session);
+                }
+                4 => {
+                    // Resets, then charges prompt + forced + generated.
+                    let (p, f) = (rng.gen_range(1..30), rng.gen_range(0..30));
+                    let max_new = rng.gen_range(1..6);
+                    let config = GenerationConfig {
+                        max_new_tokens: max_new,
+                        ..GenerationConfig::default()
+                    };
+                    let (prompt, forced) = (ids(&mut rng, p), ids(&mut rng, f));
+                    match generate_from_ids(&mut session, &prompt, &forced, &config) {
+                        Ok(out) => {
+                            assert_eq!(session.budget().count(TokenUse::Forced), f);
+                            assert_eq!(session.budget().count(TokenUse::Prompt), p);
+                            // The last sampled token is only fed back if more may follow.
+                            let n = out.generated_token_ids.len();
+                            let fed = if n == max_new { n - 1 } else { n };
+                            assert_eq!(session.budget().count(TokenUse::Generated), fed);
+                        }
+                        Err(EngineError::Budget(_)) => assert!(p + f + max_new > TINY_CONTEXT),
+                        Err(e) => panic!("unexpected error: {e}"),
+                    }
+                }
+                5 => saved = Some(session.snapshot()),
+                _ => match (&saved, rng.gen_bool(0.5)) {
+                    (Some(snap), true) => {
+                        session.restore(snap).unwrap();
+                        assert_eq!(session.budget().count(TokenUse::Restored), snap[0].len());
+                    }
+                    _ => session.reset(),
+                },
+            }
+            assert_in_sync(&session, step);
+        }
+    }
+}
+
+#[test]
+fn full_cache_rejects_decode_before_any_layer_runs() {
+    let model = LoadedModel::load(tiny_llama().write("token_budget_full")).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.prefill(&vec![3; TINY_CONTEXT]).unwrap();
+    assert_eq!(session.budget().remaining(), 0);
+
+    let Err(EngineError::Budget(BudgetError::Exceeded { kind, used, .. })) =
+        session.decode_token(4)
+    else {
+        panic!("expected a budget error");
+    };
+    assert_eq!((kind, used), (TokenUse::Generated, TINY_CONTEXT));
+    assert_eq!(session.position(), TINY_CONTEXT);
+}
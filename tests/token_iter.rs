@@ -0,0 +1,320 @@
+//! `InferenceSession::tokens` (and the `async` stream adapter) on the synthetic model.
+
+mod common;
+
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+
+use inference_engine_rust::engine::generation::{GenerationConfig, generate_from_ids};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::token_iter::GeneratedToken;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::tokenizer::{Granularity, IncrementalDecoder, TextChunk, Tokenizer};
+
+use common::gguf_fixture::{TempPath, tiny_llama, write_tiny_tokenizer};
+
+const PROMPT: [u32; 4] = [1, 7, 8, 9];
+
+fn config() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 6,
+        temperature: 0.8,
+        seed: 11,
+        ..GenerationConfig::default()
+    }
+}
+
+fn assert_logits_close(a: &[f32], b: &[f32]) {
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-4, "{x} vs {y}");
+    }
+}
+
+#[test]
+fn collected_tokens_match_generate() {
+    let path = tiny_llama().write("token_iter_collect");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("token_iter_collect");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+
+    let expected = generate_from_ids(&mut session, &PROMPT, &[], &config()).unwrap();
+    let tokens: Vec<GeneratedToken> = session
+        .tokens(&tokenizer, &PROMPT, &config())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(!tokens.is_empty());
+
+    let ids: Vec<u32> = tokens.iter().map(|t| t.id).collect();
+    assert_eq!(ids, expected.generated_token_ids);
+    for (t, lp) in tokens.iter().zip(&expected.generated_logprobs) {
+        assert!((t.logprob - lp).abs() < 1e-5, "{} vs {lp}", t.logprob);
+    }
+    let text: String = tokens.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(text, tokenizer.decode(&ids).unwrap());
+    assert_eq!(session.position(), PROMPT.len() + ids.len());
+}
+
+#[test]
+fn dropping_early_leaves_cache_at_yielded_tokens() {
+    let path = tiny_llama().write("token_iter_drop");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("token_iter_drop");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+
+    let taken: Vec<u32> = session
+        .tokens(&tokenizer, &PROMPT, &config())
+        .take(2)
+        .map(|t| t.unwrap().id)
+        .collect();
+    assert_eq!(taken.len(), 2);
+    assert_eq!(session.position(), PROMPT.len() + taken.len());
+
+    // Keep stepping by hand; the result must match prefilling the whole history at once.
+    let state = session.decode_token(5).unwrap();
+    let resumed = session.logits_last_token(&state).unwrap();
+
+    let mut fresh = InferenceSession::new(&model).unwrap();
+    let history: Vec<u32> = PROMPT.iter().chain(&taken).chain(&[5]).copied().collect();
+    let state = fresh.prefill(&history).unwrap();
+    assert_logits_close(&resumed, &fresh.logits_last_token(&state).unwrap());
+}
+
+fn stream_chunks(
+    session: &mut InferenceSession<'_>,
+    tokenizer: &Tokenizer,
+    config: &GenerationConfig,
+    granularity: Granularity,
+    cancel_after: usize,
+) -> (Vec<TextChunk>, usize, bool) {
+    let mut chunks = Vec::new();
+    let end = session
+        .stream_text(tokenizer, &PROMPT, config, granularity, |chunk| {
+            chunks.push(chunk.clone());
+            if chunks.len() == cancel_after {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+    (chunks, end.token_count, end.cancelled)
+}
+
+#[test]
+fn word_chunks_rebuild_the_text_without_splitting_words() {
+    let path = tiny_llama().write("token_iter_words");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("token_iter_words");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let config = GenerationConfig {
+        max_new_tokens: 12,
+        ..config()
+    };
+
+    let expected = generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+    let text = tokenizer.decode(&expected.generated_token_ids).unwrap();
+    assert!(text.contains(' '), "need several words: {text:?}");
+
+    let (tokens, n, cancelled) =
+        stream_chunks(&mut session, &tokenizer, &config, Granularity::Token, 0);
+    let (words, n_words, _) =
+        stream_chunks(&mut session, &tokenizer, &config, Granularity::Word, 0);
+    assert_eq!((n, n_words), (expected.generated_token_ids.len(), n));
+    assert!(!cancelled);
+
+    let joined = |chunks: &[TextChunk]| chunks.iter().map(|c| c.text.clone()).collect::<String>();
+    assert_eq!(joined(&tokens), text);
+    assert_eq!(joined(&words), text);
+    for pair in words.windows(2) {
+        let (left, right) = (&pair[0], &pair[1]);
+        assert!(!left.text.ends_with(char::is_whitespace), "{:?}", left.text);
+        assert!(
+            right.text.starts_with(char::is_whitespace),
+            "{:?}",
+            right.text
+        );
+        assert!(left.tokens.end <= right.tokens.start + 1);
+    }
+    assert_eq!(words.first().unwrap().tokens.start, 0);
+    assert_eq!(words.last().unwrap().tokens.end, n);
+}
+
+#[test]
+fn cancelled_stream_still_flushes_buffered_text() {
+    let path = tiny_llama().write("token_iter_cancel");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("token_iter_cancel");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+
+    let (chunks, n, cancelled) =
+        stream_chunks(&mut session, &tokenizer, &config(), Granularity::Word, 1);
+    assert!(cancelled);
+    assert_eq!(
+        chunks.len(),
+        2,
+        "the cancelling chunk plus the flushed remainder"
+    );
+    assert_eq!(session.position(), PROMPT.len() + n);
+
+    let mut fresh = InferenceSession::new(&model).unwrap();
+    let ids: Vec<u32> = fresh
+        .tokens(&tokenizer, &PROMPT, &config())
+        .take(n)
+        .map(|t| t.unwrap().id)
+        .collect();
+    let joined: String = chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(joined, tokenizer.decode(&ids).unwrap());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn stream_adapter_yields_iterator_sequence() {
+    use std::future::{Future, poll_fn, ready};
+    use std::pin::{Pin, pin};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use futures_core::Stream;
+    use inference_engine_rust::engine::token_stream::BlockingStream;
+
+    struct NoopWake;
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Busy-polling executor; enough for futures that never actually park.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWake));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    let path = tiny_llama().write("token_iter_stream");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_tiny_tokenizer("token_iter_stream");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+
+    let expected: Vec<u32> = session
+        .tokens(&tokenizer, &PROMPT, &config())
+        .map(|t| t.unwrap().id)
+        .collect();
+
+    let iter = session.tokens(&tokenizer, &PROMPT, &config());
+    let mut stream = BlockingStream::new(iter, |step| ready(step()));
+    let streamed = block_on(async {
+        let mut ids = Vec::new();
+        while let Some(t) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            ids.push(t.unwrap().id);
+        }
+        ids
+    });
+    assert_eq!(streamed, expected);
+}
+
+/// Letters plus the UTF-8 bytes of `é` (C3 A9) and `∑` (E2 88 91) as `<0xNN>` pieces, decoded
+/// with byte fallback, so sampled ids split characters across tokens.
+fn write_byte_tokenizer(stem: &str) -> TempPath {
+    let mut pieces: Vec<String> = ["<unk>", "<s>", "</s>"].map(String::from).to_vec();
+    pieces.extend([0xC3u8, 0xA9, 0xE2, 0x88, 0x91].map(|b| format!("<0x{b:02X}>")));
+    pieces.extend(('a'..='x').map(String::from));
+    let vocab: serde_json::Map<String, serde_json::Value> = pieces
+        .into_iter()
+        .enumerate()
+        .map(|(id, piece)| (piece, id.into()))
+        .collect();
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "WhitespaceSplit" },
+        "post_processor": null,
+        "decoder": {
+            "type": "Sequence",
+            "decoders": [{ "type": "ByteFallback" }, { "type": "Fuse" }]
+        },
+        "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "<unk>" }
+    });
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json.to_string()).unwrap();
+    path
+}
+
+/// Bytes written and how often they were flushed.
+#[derive(Default)]
+struct Recorder {
+    bytes: Vec<u8>,
+    flushes: usize,
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_gets_valid_utf8_flushed_per_token() {
+    let path = tiny_llama().write("token_iter_writer");
+    let model = LoadedModel::load(&path).unwrap();
+    let tokenizer_path = write_byte_tokenizer("token_iter_writer");
+    let tokenizer = Tokenizer::load_from_file(&tokenizer_path).unwrap();
+    let mut session = InferenceSession::new(&model).unwrap();
+    let config = GenerationConfig {
+        max_new_tokens: 48,
+        temperature: 1.0,
+        seed: 6,
+        ..GenerationConfig::default()
+    };
+
+    let mut out = Recorder::default();
+    let end = session
+        .generate_to_writer(&tokenizer, &PROMPT, &config, &mut out)
+        .unwrap();
+    assert_eq!(out.flushes, end.token_count);
+    let written = String::from_utf8(out.bytes).expect("valid UTF-8");
+
+    // Exactly the incremental decode of the same tokens, which holds back partial characters.
+    let expected = generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+    assert_eq!(expected.generated_token_ids.len(), end.token_count);
+    let mut decoder = IncrementalDecoder::new();
+    let mut text = String::new();
+    for &id in &expected.generated_token_ids {
+        text += &decoder.push(&tokenizer, id).unwrap();
+    }
+    assert_eq!(written, text);
+    // The seed samples at least one character whose bytes came from separate tokens.
+    assert!(written.contains(['é', '∑']), "{written:?}");
+
+    // A failing writer stops generation with an I/O error.
+    struct Broken;
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("pipe closed"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let err = session
+        .generate_to_writer(&tokenizer, &PROMPT, &config, &mut Broken)
+        .unwrap_err();
+    assert!(err.to_string().contains("pipe closed"), "{err}");
+}
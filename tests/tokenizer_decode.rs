@@ -0,0 +1,118 @@
+//! Out-of-vocab ids in `Tokenizer::decode` / `decode_lossy`, `decode_continuation` spacing, and
+//! `roundtrip_report` over a small corpus.
+
+mod common;
+
+use common::gguf_fixture::{TINY_VOCAB, TempPath, write_tiny_tokenizer};
+use inference_engine_rust::tokenizer::Tokenizer;
+use inference_engine_rust::tokenizer::normalize::{NormalizationForm, TextNormalization};
+
+#[test]
+fn decode_rejects_id_equal_to_vocab_size() {
+    let tok = Tokenizer::load_from_file(write_tiny_tokenizer("decode_oov")).unwrap();
+    assert_eq!(tok.vocab_size(), TINY_VOCAB);
+    let oov = TINY_VOCAB as u32;
+
+    let err = tok.decode(&[5, oov, 6]).unwrap_err().to_string();
+    assert!(err.contains(&format!("token id {oov}")), "{err}");
+
+    assert_eq!(tok.decode(&[5, 6]).unwrap(), "w5 w6");
+    assert_eq!(tok.decode_lossy(&[5, oov, 6]).unwrap(), "w5 <unk> w6");
+}
+
+/// SentencePiece-style `tokenizer.json`: Metaspace pre-tokenizer and decoder, so word starts are
+/// marked with `▁` and the first piece of a decode loses its leading space.
+fn write_metaspace_tokenizer(stem: &str) -> TempPath {
+    write_metaspace_tokenizer_with(
+        stem,
+        serde_json::json!({ "<unk>": 0, "▁Hello": 1, "▁world": 2, "ing": 3, "a": 4 }),
+    )
+}
+
+fn write_metaspace_tokenizer_with(stem: &str, vocab: serde_json::Value) -> TempPath {
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always", "split": true },
+        "post_processor": null,
+        "decoder": { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always", "split": true },
+        "model": {
+            "type": "WordLevel",
+            "vocab": vocab,
+            "unk_token": "<unk>"
+        }
+    });
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json.to_string()).unwrap();
+    path
+}
+
+#[test]
+fn continuation_decode_does_not_prepend_a_space() {
+    let path = write_metaspace_tokenizer("decode_continuation");
+    let tok = Tokenizer::load_from_file(&path).unwrap();
+    let (hello, world, ing) = (1, 2, 3);
+
+    // The word-start marker of the first piece is not turned into a space.
+    assert_eq!(tok.decode_piece_ids(&[world]).unwrap(), "world");
+    assert_eq!(tok.decode_continuation(&[world]).unwrap(), "world");
+    assert_eq!(tok.decode_continuation(&[world, ing]).unwrap(), "worlding");
+    assert_eq!(tok.decode_continuation(&[ing]).unwrap(), "ing");
+    assert_eq!(tok.decode_continuation(&[]).unwrap(), "");
+
+    // Appended to existing text, the marker is the space between words.
+    assert_eq!(tok.decode_appended(&[world]).unwrap(), " world");
+    assert_eq!(tok.decode_appended(&[ing]).unwrap(), "ing");
+    let whole = tok.decode_piece_ids(&[hello, world, ing]).unwrap();
+    let streamed = tok.decode_piece_ids(&[hello]).unwrap()
+        + &tok.decode_appended(&[world]).unwrap()
+        + &tok.decode_appended(&[ing]).unwrap();
+    assert_eq!(whole, "Hello worlding");
+    assert_eq!(streamed, whole);
+}
+
+#[test]
+fn continuation_decode_on_a_word_level_vocab() {
+    let path = write_tiny_tokenizer("decode_continuation_words");
+    let tok = Tokenizer::load_from_file(&path).unwrap();
+    assert_eq!(tok.decode_continuation(&[6]).unwrap(), "w6");
+    assert_eq!(
+        tok.decode_piece_ids(&[5]).unwrap() + &tok.decode_appended(&[6]).unwrap(),
+        tok.decode_piece_ids(&[5, 6]).unwrap()
+    );
+}
+
+#[test]
+fn roundtrip_report_flags_texts_that_do_not_survive_encode_decode() {
+    let path = write_metaspace_tokenizer_with(
+        "decode_roundtrip",
+        serde_json::json!({ "<unk>": 0, "▁Hello": 1, "▁world": 2, "▁café": 3, "ing": 4 }),
+    );
+    let mut tok = Tokenizer::load_from_file(&path).unwrap();
+    tok.set_normalization(TextNormalization::with_form(NormalizationForm::Nfc));
+    let corpus = [
+        "Hello world",
+        "Hello café",
+        // Decomposed accent: NFC makes it the vocabulary's `é` before encoding.
+        "cafe\u{301}",
+        "",
+        "Hello  world",
+        "Hello wörld",
+        "日本語",
+    ];
+    let report = tok.roundtrip_report(&corpus);
+    let texts: Vec<&str> = report.iter().map(|(t, _)| t.as_str()).collect();
+    assert_eq!(texts, corpus);
+    let ok: Vec<bool> = report.iter().map(|&(_, ok)| ok).collect();
+    assert_eq!(ok, [true, true, true, true, false, false, false]);
+
+    // Word-level vocab: the decoder joins words with one space.
+    let mut words =
+        Tokenizer::load_from_file(write_tiny_tokenizer("decode_roundtrip_words")).unwrap();
+    let report = words.roundtrip_report(&["w5 w6", "w5\tw6", " w5", "w5 w99"]);
+    let ok: Vec<bool> = report.iter().map(|&(_, ok)| ok).collect();
+    assert_eq!(ok, [true, false, false, false]);
+}
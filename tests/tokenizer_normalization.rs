@@ -0,0 +1,64 @@
+//! Prompt normalization in `Tokenizer::encode`, using a tiny word-level `tokenizer.json`.
+
+mod common;
+
+use inference_engine_rust::tokenizer::{NormalizationForm, TextNormalization, Tokenizer};
+
+use common::gguf_fixture::TempPath;
+
+/// Word-level tokenizer split on whitespace; `normalizer` is the raw JSON for that field.
+fn word_level_tokenizer(stem: &str, normalizer: &str) -> Tokenizer {
+    let json = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": NORMALIZER,
+        "pre_tokenizer": { "type": "WhitespaceSplit" },
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "WordLevel",
+            "vocab": { "[UNK]": 0, "café": 1, "Abc": 2, "line": 3 },
+            "unk_token": "[UNK]"
+        }
+    }"#
+    .replace("NORMALIZER", normalizer);
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json).expect("write tokenizer.json");
+    Tokenizer::load_from_file(&path).expect("load tokenizer.json")
+}
+
+#[cfg(feature = "unicode-normalization")]
+#[test]
+fn nfkc_maps_decomposed_and_full_width_to_same_ids() {
+    let mut tok = word_level_tokenizer("norm_nfkc", "null");
+    assert_eq!(tok.normalization().form, NormalizationForm::None);
+    tok.set_normalization(TextNormalization::with_form(NormalizationForm::Nfkc));
+
+    let composed = tok.encode("caf\u{e9} Abc").unwrap();
+    let decomposed = tok.encode("cafe\u{301} Abc").unwrap();
+    let full_width = tok.encode("caf\u{e9} \u{ff21}\u{ff42}\u{ff43}").unwrap();
+    assert_eq!(composed, vec![1, 2]);
+    assert_eq!(decomposed, composed);
+    assert_eq!(full_width, composed);
+}
+
+#[test]
+fn passthrough_leaves_text_untouched() {
+    let mut tok = word_level_tokenizer("norm_passthrough", "null");
+    tok.set_normalization(TextNormalization::passthrough());
+    assert_eq!(tok.encode("caf\u{e9}").unwrap(), vec![1]);
+    assert_eq!(tok.encode("cafe\u{301}").unwrap(), vec![0]);
+    assert_eq!(tok.encode("\u{ff21}\u{ff42}\u{ff43}").unwrap(), vec![0]);
+}
+
+#[test]
+fn default_form_follows_tokenizer_json_normalizer() {
+    let tok = word_level_tokenizer(
+        "norm_default",
+        r#"{ "type": "Sequence", "normalizers": [{ "type": "NFKC" }] }"#,
+    );
+    assert_eq!(tok.normalization().form, NormalizationForm::Nfkc);
+    assert!(tok.normalization().normalize_newlines);
+}
@@ -0,0 +1,94 @@
+//! Transcript record / replay on the synthetic model: a recording replays exactly on the same
+//! weights, and perturbed weights are reported at the first token that changes.
+
+mod common;
+
+use inference_engine_rust::engine::generation::{
+    FinishReason, GenerationConfig, generate_from_ids,
+};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::transcript::{Transcript, replay};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::{TempPath, tiny_llama, tiny_llama_perturbed};
+
+const PROMPT: [u32; 4] = [1, 5, 9, 13];
+
+fn sampling_config() -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens: 16,
+        temperature: 0.9,
+        seed: 4242,
+        ..GenerationConfig::default()
+    }
+}
+
+#[test]
+fn recording_replays_exactly_on_the_same_model() {
+    let model_path = tiny_llama().write("transcript_same");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let transcript_path = TempPath::new("transcript_same", "json");
+    let config = sampling_config();
+
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.set_transcript(Some(transcript_path.to_path_buf()));
+    let out = generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+
+    let transcript = Transcript::load(&transcript_path).unwrap();
+    assert_eq!(transcript.prompt_ids, PROMPT);
+    assert_eq!(transcript.config, config);
+    assert_eq!(transcript.model.content_hash, model.content_hash());
+    assert_eq!(transcript.finish_reason, out.finish_reason);
+    assert_eq!(
+        transcript.sampled_ids[..out.generated_token_ids.len()],
+        out.generated_token_ids
+    );
+
+    // Replaying must not overwrite the recording it reads.
+    let mut fresh = InferenceSession::new(&model).unwrap();
+    fresh.set_transcript(Some(transcript_path.to_path_buf()));
+    let report = replay(&mut fresh, &transcript_path).unwrap();
+    assert!(report.is_exact(), "{:?}", report.divergence);
+    assert_eq!(report.steps_matched, transcript.sampled_ids.len());
+    assert_eq!(Transcript::load(&transcript_path).unwrap(), transcript);
+}
+
+#[test]
+fn perturbed_weights_diverge_at_the_first_changed_token() {
+    let model_path = tiny_llama().write("transcript_base");
+    let perturbed_path = tiny_llama_perturbed(0.05).write("transcript_perturbed");
+    let model = LoadedModel::load(&model_path).unwrap();
+    let perturbed = LoadedModel::load(&perturbed_path).unwrap();
+    assert_ne!(model.content_hash(), perturbed.content_hash());
+    let transcript_path = TempPath::new("transcript_perturbed", "json");
+    let config = sampling_config();
+
+    let mut session = InferenceSession::new(&model).unwrap();
+    session.set_transcript(Some(transcript_path.to_path_buf()));
+    generate_from_ids(&mut session, &PROMPT, &[], &config).unwrap();
+    let recorded = Transcript::load(&transcript_path).unwrap().sampled_ids;
+
+    let mut other = InferenceSession::new(&perturbed).unwrap();
+    let out = generate_from_ids(&mut other, &PROMPT, &[], &config).unwrap();
+    let mut replayed = out.generated_token_ids;
+    if let FinishReason::Eos { token_id } = out.finish_reason {
+        replayed.push(token_id);
+    }
+    let first_diff = (0..recorded.len().max(replayed.len()))
+        .find(|&i| recorded.get(i) != replayed.get(i))
+        .expect("noise should change the sampled tokens");
+
+    let report = replay(&mut other, &transcript_path).unwrap();
+    assert!(!report.model_matches);
+    let divergence = report.divergence.expect("divergence");
+    assert_eq!(divergence.step, first_diff);
+    assert_eq!(report.steps_matched, first_diff);
+    assert_eq!(divergence.expected, recorded.get(first_diff).copied());
+    assert_eq!(divergence.actual, replayed.get(first_diff).copied());
+    assert!(!divergence.top.is_empty());
+    assert!(
+        divergence
+            .to_string()
+            .starts_with(&format!("step {first_diff}:"))
+    );
+}
@@ -0,0 +1,149 @@
+//! Unknown-token handling on restricted `tokenizer.json` vocabularies: lowercase ASCII letters
+//! only, with and without the `<0x00>`..`<0xFF>` byte tokens, so `é` and `∑` have no piece.
+
+mod common;
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::tokenizer::{Tokenizer, UnknownTokenPolicy};
+
+use common::gguf_fixture::TempPath;
+
+const UNK: u32 = 0;
+/// Ids of `a`..`z` start here; byte tokens follow the letters.
+const FIRST_LETTER: u32 = 3;
+const FIRST_BYTE: u32 = FIRST_LETTER + 26;
+
+/// Character-level BPE (no merges, no backend byte fallback) with an `<unk>` token.
+fn restricted_tokenizer(stem: &str, byte_tokens: bool) -> Tokenizer {
+    let mut vocab = serde_json::Map::new();
+    for (id, tok) in ["<unk>", "<s>", "</s>"].into_iter().enumerate() {
+        vocab.insert(tok.into(), id.into());
+    }
+    for (i, c) in ('a'..='z').enumerate() {
+        vocab.insert(c.to_string(), (FIRST_LETTER as usize + i).into());
+    }
+    if byte_tokens {
+        for b in 0..=255u8 {
+            vocab.insert(
+                format!("<0x{b:02X}>"),
+                (FIRST_BYTE as usize + b as usize).into(),
+            );
+        }
+    }
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "WhitespaceSplit" },
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "BPE",
+            "dropout": null,
+            "unk_token": "<unk>",
+            "continuing_subword_prefix": null,
+            "end_of_word_suffix": null,
+            "fuse_unk": false,
+            "byte_fallback": false,
+            "vocab": vocab,
+            "merges": []
+        }
+    });
+    let path = TempPath::new(stem, "json");
+    std::fs::write(&path, json.to_string()).unwrap();
+    Tokenizer::load_from_file(&path).unwrap()
+}
+
+fn letter(c: char) -> u32 {
+    FIRST_LETTER + (c as u32 - 'a' as u32)
+}
+
+/// `é` is at byte 2, `∑` (3 bytes) at byte 7.
+const TEXT: &str = "caé bc∑";
+
+#[test]
+fn default_policy_follows_the_byte_tokens() {
+    let with = restricted_tokenizer("unknown_default_bytes", true);
+    assert!(with.has_byte_tokens());
+    assert_eq!(with.unknown_policy(), UnknownTokenPolicy::ByteFallback);
+
+    let mut without = restricted_tokenizer("unknown_default_plain", false);
+    assert!(!without.has_byte_tokens());
+    assert_eq!(without.unknown_policy(), UnknownTokenPolicy::EmitUnk);
+    assert!(matches!(
+        without.set_unknown_policy(UnknownTokenPolicy::ByteFallback),
+        Err(EngineError::Tokenizer(_))
+    ));
+    assert_eq!(without.unknown_policy(), UnknownTokenPolicy::EmitUnk);
+}
+
+#[test]
+fn byte_fallback_encodes_uncovered_characters_as_their_bytes() {
+    let mut tokenizer = restricted_tokenizer("unknown_bytes", true);
+    let result = tokenizer.encode_checked(TEXT).unwrap();
+    let byte = |b: u8| FIRST_BYTE + b as u32;
+    assert_eq!(
+        result.ids,
+        [
+            letter('c'),
+            letter('a'),
+            byte(0xC3),
+            byte(0xA9),
+            letter('b'),
+            letter('c'),
+            byte(0xE2),
+            byte(0x88),
+            byte(0x91),
+        ]
+    );
+    assert_eq!(result.byte_fallback, [2, 7]);
+    assert!(result.unknown.is_empty() && result.is_lossless());
+    assert_eq!(tokenizer.encode(TEXT).unwrap(), result.ids);
+}
+
+#[test]
+fn emit_unk_keeps_unk_ids_and_reports_their_offsets() {
+    let mut tokenizer = restricted_tokenizer("unknown_emit", true);
+    tokenizer
+        .set_unknown_policy(UnknownTokenPolicy::EmitUnk)
+        .unwrap();
+    let result = tokenizer.encode_checked(TEXT).unwrap();
+    assert_eq!(
+        result.ids,
+        [letter('c'), letter('a'), UNK, letter('b'), letter('c'), UNK]
+    );
+    assert_eq!(result.unknown, [2, 7]);
+    assert!(result.byte_fallback.is_empty());
+    assert!(!result.is_lossless());
+
+    // Covered text is untouched by any policy.
+    let covered = tokenizer.encode_checked("abc").unwrap();
+    assert!(covered.unknown.is_empty() && covered.byte_fallback.is_empty());
+}
+
+#[test]
+fn error_policy_names_every_uncovered_character() {
+    let mut tokenizer = restricted_tokenizer("unknown_error", false);
+    tokenizer
+        .set_unknown_policy(UnknownTokenPolicy::Error)
+        .unwrap();
+    let err = tokenizer.encode_checked(TEXT).unwrap_err();
+    let EngineError::UnknownText(err) = err else {
+        panic!("expected an unknown-text error, got {err}");
+    };
+    assert_eq!(err.chars, [(2, 'é'), (7, '∑')]);
+    assert_eq!(err.offsets(), [2, 7]);
+    let message = err.to_string();
+    assert!(
+        message.contains("'é' at byte 2") && message.contains("'∑' at byte 7"),
+        "{message}"
+    );
+    // The plain encode path enforces the policy too.
+    assert!(tokenizer.encode(TEXT).is_err());
+    assert_eq!(
+        tokenizer.encode("cab").unwrap(),
+        [letter('c'), letter('a'), letter('b')]
+    );
+}
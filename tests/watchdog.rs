@@ -0,0 +1,192 @@
+//! The decode watchdog: a mock forward pass with a slow layer is reported by stage, a hung one is
+//! cancelled at the threshold, a real session step stalled through a stage hook is cancelled and
+//! rolled back, and a real session under a generous watchdog decodes unchanged.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+use std::time::{Duration, Instant};
+
+use inference_engine_rust::EngineError;
+use inference_engine_rust::engine::config::EngineConfig;
+use inference_engine_rust::engine::observer::{EngineEvent, ObserverToChannel};
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::engine::watchdog::{
+    ForwardOp, Stage, Watchdog, WatchdogConfig, checkpoint, enter_stage,
+};
+use inference_engine_rust::loaded_model::LoadedModel;
+
+use common::gguf_fixture::tiny_llama;
+
+const LAYERS: usize = 4;
+
+/// Stands in for the forward pass: marks each layer's stages like the real blocks do and runs
+/// `work(layer)` in its FFN.
+fn mock_forward(mut work: impl FnMut(usize) -> Result<(), EngineError>) -> Result<(), EngineError> {
+    for layer in 0..LAYERS {
+        checkpoint()?;
+        enter_stage(Stage::layer(layer, ForwardOp::Attention));
+        enter_stage(Stage::layer(layer, ForwardOp::Ffn));
+        work(layer)?;
+    }
+    Ok(())
+}
+
+fn watched(config: WatchdogConfig) -> (Watchdog, Receiver<EngineEvent>) {
+    let (tx, rx) = channel();
+    let watchdog = Watchdog::start(config, Some(Arc::new(ObserverToChannel::new(tx)))).unwrap();
+    (watchdog, rx)
+}
+
+#[test]
+fn slow_layer_is_reported_without_cancelling() {
+    let (watchdog, events) = watched(WatchdogConfig {
+        stall_after: Duration::from_millis(50),
+        cancel_after: None,
+    });
+    let armed = watchdog.arm(7);
+    let result = watchdog.scope(|| {
+        mock_forward(|layer| {
+            if layer == 2 {
+                std::thread::sleep(Duration::from_millis(300));
+            }
+            Ok(())
+        })
+    });
+    drop(armed);
+    assert!(result.is_ok());
+    drop(watchdog);
+
+    let events: Vec<EngineEvent> = events.iter().collect();
+    let [EngineEvent::StallDetected(report)] = events.as_slice() else {
+        panic!("expected one stall event, got {events:?}");
+    };
+    assert_eq!(report.stage, Stage::layer(2, ForwardOp::Ffn));
+    assert_eq!(report.position, 7);
+    assert!(report.elapsed >= Duration::from_millis(50), "{report}");
+}
+
+#[test]
+fn hung_layer_is_cancelled_at_the_threshold() {
+    let cancel_after = Duration::from_millis(150);
+    let (watchdog, events) = watched(WatchdogConfig {
+        stall_after: Duration::from_millis(30),
+        cancel_after: Some(cancel_after),
+    });
+    let started = Instant::now();
+    let result = {
+        let _armed = watchdog.arm(0);
+        watchdog.scope(|| {
+            mock_forward(|layer| {
+                // Layer 1 never finishes on its own; it only polls for cancellation.
+                if layer == 1 {
+                    loop {
+                        checkpoint()?;
+                        std::thread::sleep(Duration::from_millis(2));
+                    }
+                }
+                Ok(())
+            })
+        })
+    };
+    let took = started.elapsed();
+
+    let Err(EngineError::Stalled(cancelled)) = result else {
+        panic!("expected a watchdog cancellation, got {result:?}");
+    };
+    assert_eq!(cancelled.0.stage, Stage::layer(1, ForwardOp::Ffn));
+    assert!(cancelled.0.elapsed >= cancel_after, "{}", cancelled.0);
+    assert!(took >= cancel_after && took < cancel_after * 10, "{took:?}");
+    assert!(cancelled.to_string().contains("layer 1 ffn"), "{cancelled}");
+
+    // The stall was reported first, from the same stage.
+    let first = events.try_recv().unwrap();
+    assert!(
+        matches!(&first, EngineEvent::StallDetected(r) if r.stage.layer == Some(1)),
+        "{first:?}"
+    );
+
+    // Re-arming clears the cancellation.
+    let _armed = watchdog.arm(1);
+    assert!(watchdog.scope(|| mock_forward(|_| Ok(()))).is_ok());
+}
+
+#[test]
+fn generous_watchdog_leaves_decoding_unchanged() {
+    let path = tiny_llama().write("watchdog_session");
+    let model = LoadedModel::load(&path).unwrap();
+    let decode = |engine: &EngineConfig| {
+        let mut session = InferenceSession::with_config(&model, engine).unwrap();
+        let (tx, rx) = channel();
+        session.set_observer(Arc::new(ObserverToChannel::new(tx)));
+        session.prefill(&[1, 5, 6]).unwrap();
+        let mut logits = Vec::new();
+        for token in [7, 8, 9] {
+            let state = session.decode_token(token).unwrap();
+            logits.push(session.logits_last_token(&state).unwrap());
+        }
+        assert_eq!(session.position(), 6);
+        drop(session);
+        (logits, rx.iter().count())
+    };
+
+    let (plain, _) = decode(&EngineConfig::default());
+    let config = WatchdogConfig {
+        stall_after: Duration::from_secs(60),
+        cancel_after: Some(Duration::from_secs(120)),
+    };
+    let (watched, events) = decode(&EngineConfig {
+        watchdog: Some(config),
+        ..EngineConfig::default()
+    });
+    assert_eq!(watched, plain);
+    assert_eq!(events, 0);
+}
+
+#[test]
+fn stalled_session_step_is_cancelled_and_rolled_back() {
+    let path = tiny_llama().write("watchdog_session_cancel");
+    let model = LoadedModel::load(&path).unwrap();
+    let engine = EngineConfig {
+        watchdog: Some(WatchdogConfig {
+            stall_after: Duration::from_millis(20),
+            cancel_after: Some(Duration::from_millis(100)),
+        }),
+        ..EngineConfig::default()
+    };
+    let mut session = InferenceSession::with_config(&model, &engine).unwrap();
+    session.prefill(&[1, 5, 6]).unwrap();
+    let budget_before = session.budget().clone();
+
+    // Layer 0's FFN outlasts `cancel_after`; the checkpoint before layer 1 then cancels the step.
+    let stalled = Stage::layer(0, ForwardOp::Ffn);
+    session
+        .watchdog()
+        .unwrap()
+        .set_stage_hook(Some(Arc::new(move |stage| {
+            if stage == stalled {
+                std::thread::sleep(Duration::from_millis(300));
+            }
+        })));
+    let result = session.decode_token(7);
+    let Err(EngineError::Stalled(cancelled)) = result else {
+        panic!("expected a watchdog cancellation, got {result:?}");
+    };
+    assert_eq!(cancelled.0.stage, stalled);
+    assert_eq!(cancelled.0.position, 3);
+    assert_eq!(session.position(), 3, "layer 0's K/V row was dropped");
+    assert_eq!(session.budget(), &budget_before);
+
+    // Without the hook the same step goes through, as if the cancelled one never ran.
+    session.watchdog().unwrap().set_stage_hook(None);
+    let state = session.decode_token(7).unwrap();
+    assert_eq!(session.position(), 4);
+    let mut plain = InferenceSession::new(&model).unwrap();
+    plain.prefill(&[1, 5, 6]).unwrap();
+    let expected = plain.decode_token(7).unwrap();
+    assert_eq!(
+        session.logits_last_token(&state).unwrap(),
+        plain.logits_last_token(&expected).unwrap()
+    );
+}
@@ -0,0 +1,76 @@
+//! `LoadOptions::optimize_layout` on the synthetic model: the selected roles are column-tiled
+//! where their shape allows it, and the model computes exactly what the ggml-order load does.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use inference_engine_rust::core::tensor::WeightLayout;
+use inference_engine_rust::engine::session::InferenceSession;
+use inference_engine_rust::loaded_model::LoadedModel;
+use inference_engine_rust::model_loader::dtype_overrides::WeightRole;
+use inference_engine_rust::model_loader::gguf_types::LoadOptions;
+use inference_engine_rust::model_loader::weight_layout::LAYOUT_ROLES;
+
+use common::gguf_fixture::{TINY_LAYERS, tiny_llama_q8};
+
+const PROMPT: [u32; 4] = [1, 5, 9, 13];
+
+fn logits(model: &LoadedModel) -> Vec<f32> {
+    let mut session = InferenceSession::new(model).unwrap();
+    let state = session.prefill(&PROMPT).unwrap();
+    session.logits_last_token(&state).unwrap()
+}
+
+#[test]
+fn tiled_projections_give_bit_identical_logits() {
+    let path = tiny_llama_q8().write("weight_layout_logits");
+    let ggml = LoadedModel::load(&path).unwrap();
+    let tiled = LoadedModel::load_with(
+        &path,
+        &LoadOptions {
+            optimize_layout: LAYOUT_ROLES.into_iter().collect(),
+            ..LoadOptions::default()
+        },
+    )
+    .unwrap();
+
+    // Only `ffn_down` ([32, 16] Q8_0) has whole-block columns in the tiny model.
+    let tiled_names: Vec<&str> = tiled
+        .gguf()
+        .loaded_tensors()
+        .filter(|(_, t)| t.layout() == WeightLayout::ColumnTiles)
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(tiled_names.len(), TINY_LAYERS, "{tiled_names:?}");
+    assert!(
+        tiled_names
+            .iter()
+            .all(|name| WeightRole::of(name) == WeightRole::FfnDown)
+    );
+    assert!(
+        ggml.gguf()
+            .loaded_tensors()
+            .all(|(_, t)| t.layout() == WeightLayout::Ggml)
+    );
+
+    let (a, b) = (logits(&ggml), logits(&tiled));
+    assert!(a.iter().all(|x| x.is_finite()));
+    let bits = |v: &[f32]| v.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+    assert_eq!(bits(&a), bits(&b));
+}
+
+#[test]
+fn embeddings_cannot_be_selected() {
+    let path = tiny_llama_q8().write("weight_layout_embeddings");
+    let Err(err) = LoadedModel::load_with(
+        &path,
+        &LoadOptions {
+            optimize_layout: BTreeSet::from([WeightRole::TokenEmbedding]),
+            ..LoadOptions::default()
+        },
+    ) else {
+        panic!("token_embd was accepted for optimize_layout");
+    };
+    assert!(err.to_string().contains("TokenEmbedding"), "{err}");
+}
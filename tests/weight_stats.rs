@@ -0,0 +1,115 @@
+//! Per-tensor weight statistics over a small GGUF file: the streamed values against hand-computed
+//! ones, the JSON round trip, and the diff against a perturbed reference report.
+
+mod common;
+
+use inference_engine_rust::core::stats::{HISTOGRAM_BINS, StatsReport};
+use inference_engine_rust::model_loader::file_loader::read_file;
+
+use common::gguf_fixture::{GGML_TYPE_Q8_0, GgufFixture};
+
+/// One Q8_0 block with scale `d_bits` (f16) and every quant `q`.
+fn q8_0_block(d_bits: u16, q: i8) -> Vec<u8> {
+    let mut block = d_bits.to_le_bytes().to_vec();
+    block.extend([q as u8; 32]);
+    block
+}
+
+fn fixture_report(stem: &str) -> StatsReport {
+    // 0x3800 = 0.5, 0x3400 = 0.25: values 2 x 0.5 = 1.0, then -4 x 0.25 = -1.0.
+    let mut q8 = q8_0_block(0x3800, 2);
+    q8.extend(q8_0_block(0x3400, -4));
+    let path = GgufFixture::new()
+        .tensor("blk.0.attn_q.weight", &[32, 2], GGML_TYPE_Q8_0, q8)
+        .f32_tensor("blk.0.attn_norm.weight", &[4], &[1.0, 2.0, 3.0, 4.0])
+        .f32_tensor("output_norm.weight", &[2], &[0.5, 0.5])
+        .write(stem);
+    let path_str = path.to_str().expect("utf8 path");
+    let mut gguf = read_file(path_str).expect("read fixture metadata");
+    gguf.load_tensors(path_str).expect("load fixture tensors");
+    gguf.stats_report(|name| name.starts_with("blk.0."))
+        .expect("stats")
+}
+
+#[test]
+fn report_matches_hand_computed_stats() {
+    let report = fixture_report("weight_stats_hand");
+    assert_eq!(
+        report.tensors.keys().collect::<Vec<_>>(),
+        ["blk.0.attn_norm.weight", "blk.0.attn_q.weight"]
+    );
+
+    let q = &report.tensors["blk.0.attn_q.weight"];
+    assert_eq!(q.dtype, "Q8_0");
+    assert_eq!(
+        (q.values.count, q.values.min, q.values.max),
+        (64, -1.0, 1.0)
+    );
+    assert!(q.values.mean.abs() < 1e-12);
+    assert!((q.values.stddev - 1.0).abs() < 1e-12);
+    assert_eq!(q.histogram.counts[0], 32);
+    assert_eq!(q.histogram.counts[HISTOGRAM_BINS - 1], 32);
+    let scales = q.scales.expect("Q8_0 scales");
+    assert_eq!((scales.min, scales.max, scales.mean), (0.25, 0.5, 0.375));
+
+    // 1, 2, 3, 4: mean 2.5, population variance 1.25.
+    let norm = &report.tensors["blk.0.attn_norm.weight"];
+    assert_eq!(norm.values.mean, 2.5);
+    assert!((norm.values.stddev - 1.25f64.sqrt()).abs() < 1e-12);
+    assert!(norm.scales.is_none());
+
+    let table = report.to_string();
+    assert_eq!(table.lines().count(), 3, "{table}");
+    assert!(
+        table
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("blk.0.attn_q.weight")
+    );
+}
+
+#[test]
+fn json_round_trips_and_diff_reports_the_perturbed_tensor() {
+    let report = fixture_report("weight_stats_diff");
+    let json = report.to_json();
+    let back = StatsReport::from_json(&json).unwrap();
+    assert_eq!(back, report);
+    assert_eq!(report.diff(&back).max_deviation(), 0.0);
+
+    let mut reference = back;
+    reference
+        .tensors
+        .get_mut("blk.0.attn_q.weight")
+        .unwrap()
+        .values
+        .mean += 0.125;
+    let norm = reference.tensors.remove("blk.0.attn_norm.weight").unwrap();
+    reference.tensors.insert("blk.1.ffn_up.weight".into(), norm);
+
+    let diff = report.diff(&reference);
+    assert_eq!(diff.tensors.len(), 1);
+    assert_eq!(diff.tensors[0].name, "blk.0.attn_q.weight");
+    assert_eq!(diff.tensors[0].field, "mean");
+    assert_eq!(diff.max_deviation(), 0.125);
+    assert_eq!(diff.missing_in_reference, ["blk.0.attn_norm.weight"]);
+    assert_eq!(diff.missing_here, ["blk.1.ffn_up.weight"]);
+    let listing = diff.to_string();
+    assert!(listing.contains("not in reference"), "{listing}");
+    assert!(listing.contains("only in reference"), "{listing}");
+}
+
+#[test]
+fn external_reports_may_omit_histograms_and_scales() {
+    let report = fixture_report("weight_stats_external");
+    let external = r#"{"tensors": {"blk.0.attn_q.weight": {
+        "dtype": "Q8_0",
+        "values": {"count": 64, "min": -1.0, "max": 1.0, "mean": 0.0, "stddev": 0.999}
+    }}}"#;
+    let reference = StatsReport::from_json(external).unwrap();
+    let diff = report.diff(&reference);
+    assert_eq!(diff.tensors[0].field, "stddev");
+    assert!((diff.max_deviation() - 0.001).abs() < 1e-12);
+
+    assert!(StatsReport::from_json("{\"tensors\": 3}").is_err());
+}